@@ -22,9 +22,87 @@ def execute_piranha(piranha_argument: PiranhaArguments) -> list[PiranhaOutputSum
     Returns
     ------------
     List of `PiranhaOutPutSummary`
+    Raises
+    ------------
+    RuntimeError
+        If the run failed (e.g. the condition of a `MultiFile` seed rule is not satisfied), with the reason
+    """
+    ...
+
+def register_language(
+    name: str,
+    library_path: str,
+    extensions: Optional[list[str]] = None,
+    comment_nodes: Optional[list[str]] = None,
+    scope_config: Optional[str] = None,
+    rules: Optional[str] = None,
+    edges: Optional[str] = None,
+) -> None:
+    """
+    Registers the language `name` whose tree-sitter grammar is compiled into a shared library, so that it can be used like the built-in languages
+    Parameters
+    ------------
+        name: str
+            The name of the language
+        library_path: str
+            The path to the shared library exporting the function `tree_sitter_<name>`
+        extensions: list[str]
+            The extensions of the files of the language (by default, the name)
+        comment_nodes: list[str]
+            The node kinds to be considered when searching for comments
+        scope_config: str
+            The scope configurations of the language, in the format of `scope_config.toml`
+        rules: str
+            The built-in (cleanup) rules of the language, in the format of `rules.toml`
+        edges: str
+            The built-in edges of the language, in the format of `edges.toml`
+    Raises
+    ------------
+    ValueError
+        If the grammar cannot be loaded (or its ABI version is not supported), if a language with the same name exists,
+        if one of the extensions is already the extension of another language, or if the configurations are invalid
+    Note that the shared library is loaded into the process and its `tree_sitter_<name>` function is called,
+    hence only the grammars built from trusted sources should be registered.
+    """
+    ...
+
+def render_edit(edit: Edit, original_code: str, options: Optional[RenderOptions] = None) -> str:
+    """
+    Renders the edit as a block in the style of the compiler diagnostics for human review, i.e. the matched lines
+    (numbered in a gutter) with the matched code underlined by carets, and the replacement beneath
+    Parameters
+    ------------
+        edit: Edit
+            The edit to render
+        original_code: str
+            The code the edit was applied to (its range refers to this code)
+        options: RenderOptions
+            How the edit is rendered (by default, 2 context lines and a maximum width of 100)
+    Returns
+    ------------
+    The rendered edit
     """
     ...
 
+class RenderOptions:
+    """ How an edit is rendered (see `render_edit`) """
+    context_lines: int
+    "The number of lines shown before and after the matched lines"
+    max_width: int
+    "The maximum width of the rendered lines (excluding the gutter), the longer ones are truncated"
+    def __init__(self, context_lines: Optional[int] = None, max_width: Optional[int] = None):
+        """
+        Constructs `RenderOptions`
+
+        Parameters
+        ------------
+            context_lines: int
+                The number of lines shown before and after the matched lines (2 by default)
+            max_width: int
+                The maximum width of the rendered lines (100 by default)
+        """
+        ...
+
 class PiranhaArguments:
     """
     A class to capture Piranha's configurations
@@ -45,10 +123,39 @@ class PiranhaArguments:
         cleanup_comments_buffer: Optional[int] = None,
         number_of_ancestors_in_parent_scope: Optional[int] = None,
         delete_consecutive_new_lines: Optional[bool] = None,
-        global_tag_prefix: Optional[str] = 'GLOBAL_TAG',
+        global_tag_prefix: Optional[str] = 'GLOBAL_TAG.',
         delete_file_if_empty: Optional[bool] = None,
         path_to_output: Optional[str] = None,
-        allow_dirty_ast: Optional[bool] = None
+        allow_dirty_ast: Optional[bool] = None,
+        whitespace_sensitivity: Optional[str] = None,
+        rematch_slack: Optional[int] = None,
+        dump_graph: Optional[str] = None,
+        pbxproj_references_to_remove: Optional[list[str]] = None,
+        explain: Optional[bool] = None,
+        max_line_length: Optional[int] = None,
+        process_long_lines: Optional[bool] = None,
+        cleanup_only: Optional[bool] = None,
+        on_parse_error: Optional[str] = None,
+        matches_output: Optional[str] = None,
+        omit_matches_from_summary: Optional[bool] = None,
+        cache_dir: Optional[str] = None,
+        export_rules: Optional[str] = None,
+        per_file_timeout_seconds: Optional[int] = None,
+        normalize_line_endings: Optional[str] = None,
+        report_capture_ranges: Optional[bool] = None,
+        max_file_size_bytes: Optional[int] = None,
+        output_patch: Optional[str] = None,
+        rules_from_comments: Optional[bool] = None,
+        metrics_output: Optional[str] = None,
+        scope_query_cache_size: Optional[int] = None,
+        comment_deletion_patterns: Optional[List[str]] = None,
+        comment_deletion_in_all_files: Optional[bool] = None,
+        strict_substitutions: Optional[bool] = None,
+        input_substitutions_file: Optional[str] = None,
+        output_archive: Optional[str] = None,
+        max_propagation_depth: Optional[int] = None,
+        trace_propagation: Optional[bool] = None,
+        strict_builtins: Optional[bool] = None
     ):
         """
         Constructs `PiranhaArguments`
@@ -60,7 +167,7 @@ class PiranhaArguments:
             path_to_codebase: str
                 Path to source code folder or file
             keyword arguments: _
-                 substitutions (dict): Substitutions to instantiate the initial set of rules
+                 substitutions (dict): Substitutions to instantiate the initial set of rules (a list of values instantiates a rule once per value)
                  path_to_configurations (str): Directory containing the configuration files - `piranha_arguments.toml`, `rules.toml`, and  `edges.toml`
                  rule_graph (RuleGraph): The rule graph constructed via RuleGraph DSL
                  code_snippet (str): The input code snippet to transform
@@ -69,10 +176,41 @@ class PiranhaArguments:
                  cleanup_comments_buffer (int): The number of lines to consider for cleaning up the comments
                  number_of_ancestors_in_parent_scope (int): The number of ancestors considered when PARENT rules
                  delete_consecutive_new_lines (bool): Replaces consecutive \ns  with a \n
-                 global_tag_prefix (str): the prefix for global tags
+                 global_tag_prefix (str): the prefix of the tags whose values are shared across the files (e.g. `GLOBAL_TAG.flag`), defaults to `GLOBAL_TAG.`
                  delete_file_if_empty (bool): User option that determines whether an empty file will be deleted
                  path_to_output (str): Path to the output json file
                  allow_dirty_ast (bool): Allows syntax errors in the input source code 
+                 whitespace_sensitivity (str): How strictly code snippets are compared - `exact` (default), `line-endings` or `insensitive`
+                 rematch_slack (int): The number of ancestors of the edited node considered when re-matching a rule after an edit
+                 dump_graph (str): Path to the file where the rule graph should be dumped (DOT format, or Mermaid if the extension is `.mmd`)
+                 pbxproj_references_to_remove (list[str]): Names of the frameworks, files or Swift packages whose references should be removed from the Xcode project files (`project.pbxproj`)
+                 explain (bool): Reports each candidate match that was intentionally skipped (and why) in the output summary
+                 max_line_length (int): Files with a line longer than this (like minified files) are skipped, unless `process_long_lines` is enabled
+                 process_long_lines (bool): Processes the files with lines longer than `max_line_length` (without deleting their consecutive new lines)
+                 cleanup_only (bool): Only applies the built-in cleanup rules (e.g. simplifying `if (true)`) as seed rules, to clean up manually edited code
+                 on_parse_error (str): How the input files with syntax errors are handled (unless `allow_dirty_ast` is enabled) - `skip` (default), `proceed` or `fail`
+                 matches_output (str): Path to the JSON Lines file where the matches of the match-only rules should be written, as soon as each file is processed
+                 omit_matches_from_summary (bool): Does not report the matches of the match-only rules in the output summary (e.g. when they are written to `matches_output`)
+                 cache_dir (str): Directory of the negative cache, which records the files that had no match (for the same rules) in a previous run, so that they are not parsed again
+                 export_rules (str): Path to the file where the (user defined) rules and edges should be exported as TOML, e.g. to reuse the rules built via the API
+                 per_file_timeout_seconds (int): The time budget (in seconds) for applying the rules to a file, after which the remaining rules are not applied to it (the edits already applied are kept)
+                 normalize_line_endings (str): The line endings of the rewritten files - `preserve` (default) keeps them and re-emits the new lines of the replacements with the dominant line ending of the file, `lf` and `crlf` convert all of them
+                 report_capture_ranges (bool): Reports the range of each node captured by the matches (`Match.capture_ranges`) in the output summary
+                 max_file_size_bytes (int): Files larger than this (in bytes), like generated files, are skipped and reported in the `skipped_files` of the output summary (without their content)
+                 output_patch (str): Path to the file where the changes should be written as a patch (unified diff in the git format, with paths relative to the code base), e.g. to apply them later with `git apply`. The files are also rewritten, unless `dry_run` is enabled
+                 rules_from_comments (bool): Scans the code base for the rules annotated in comments (e.g. `// @piranha-rule: query="..." replacement="..."`) and applies them along with the other rules
+                 metrics_output (str): Path to the JSON file where the metrics of the run (e.g. the number of files edited and the edits by rule) should be written, even if the run fails partway
+                 scope_query_cache_size (int): The maximum number of compiled tree-sitter queries kept in the cache, the least recently used ones being evicted (512 by default)
+                 comment_deletion_patterns (List[str]): Regexes (where the tags are replaced with the substitutions) of the comments to delete once the rules were applied, e.g. `TODO.*@stale_flag_name`
+                 comment_deletion_in_all_files (bool): Deletes the comments matching the `comment_deletion_patterns` in all the files, rather than only in the rewritten ones
+                 strict_substitutions (bool): Rejects the substitutions whose values contain characters significant to the tree-sitter query syntax (`@`, `#`, unbalanced quotes or parentheses), instead of warning about them
+                 input_substitutions_file (str): Path to a flat JSON object or TOML table of substitutions, overridden by the `substitutions`
+                 output_archive (str): Path to the archive where the files should be written (with the changes) when `path_to_codebase` is an archive (`.zip`, `.tar.gz` or `.tgz`). The untouched entries are copied as is
+                 max_propagation_depth (int): The maximum number of "Parent" cleanups chained after an edit. Once exceeded, the chain is stopped and noted in the output summary. Defaults to `100`
+                 trace_propagation (bool): Reports the chain of "Parent" cleanups (rule and range) applied after each edit in the output summary (`propagation_traces`)
+                 strict_builtins (bool): Fails if a built-in (cleanup) rule references a node kind or a field unknown to the grammar (e.g. renamed by a newer version of the grammar), instead of warning about it and leaving the rule out
+        Raises:
+                 ValueError: If the arguments or the rules are invalid (e.g. the rules of different languages are connected by an edge other than `Global`)
         """
         ...
 
@@ -86,6 +224,17 @@ class PiranhaOutputSummary:
     content: content of the file after all the rewrites
     matches: All the occurrences of "match-only" rules
     rewrites: All the applied edits
+    suggested_rewrites: The edits of the `dry` rules, which were not applied
+    rewrites_by_variant: The indices of the `rewrites` produced by each variant of the rules with holes, e.g. `replace_is_on(constant=Experiment.ALPHA)`
+    skip_counts: The number of candidate matches intentionally skipped, for each rule and reason
+    skipped_matches: All the candidate matches intentionally skipped (only reported when `explain` is enabled)
+    skipped_files: The files skipped without being read into the summary (e.g. larger than `max_file_size_bytes`), in which case `content` is empty
+    notes: Notes about how the file was handled (e.g. why it was skipped, or that a rule deleted one of its top-level types)
+    parse_errors: Ranges of the syntax errors in the file, when it was skipped because of them
+    propagation_traces: The chains of "Parent" cleanups (rule and range) applied after the edits (only reported when `trace_propagation` is enabled)
+    rule_statistics: The statistics of each rule applied to the file (including the rules that never matched)
+    unapplied_seed_rules: The seed rules of the pass that produced neither an edit nor a match in any file, along with why
+    stage: The stage of the migration that produced this summary, if the rules declare stages
     """
 
     path: str
@@ -103,6 +252,103 @@ class PiranhaOutputSummary:
     rewrites: list[Edit]
     "All the applied edits"
 
+    suggested_rewrites: list[Edit]
+    "The edits of the `dry` rules, which were not applied"
+
+    rewrites_by_variant: dict[str, list[int]]
+    "The indices of the `rewrites` produced by each variant of the rules with holes (the rule name followed by its substitutions, e.g. `replace_is_on(constant=Experiment.ALPHA)`)"
+
+    skip_counts: dict[str, dict[str, int]]
+    "The number of candidate matches intentionally skipped, for each rule and reason"
+
+    skipped_matches: list[SkippedMatch]
+    "All the candidate matches intentionally skipped (only reported when `explain` is enabled)"
+
+    skipped_files: list[SkippedFile]
+    "The files skipped without being read into the summary (e.g. larger than `max_file_size_bytes`), in which case `content` is empty"
+
+    notes: list[str]
+    "Notes about how the file was handled (e.g. why it was skipped)"
+
+    parse_errors: list[Range]
+    "Ranges of the syntax errors in the file, when it was skipped because of them"
+
+    propagation_traces: list[list[tuple[str, Range]]]
+    'The chains of "Parent" cleanups (rule and range) applied after the edits, each starting with the edit that triggered them (only reported when `trace_propagation` is enabled)'
+
+    rule_statistics: dict[str, RuleStat]
+    "The statistics of each rule applied to the file (including the rules that never matched)"
+
+    unapplied_seed_rules: dict[str, str]
+    "The seed rules of the pass (or stage) that produced neither an edit nor a match in any file, along with why (best effort)"
+
+    stage: Optional[str]
+    "The stage of the migration that produced this summary, if the rules declare stages"
+
+class RuleStat:
+    """
+     A class to represent the statistics of a rule applied to a file
+
+    Attributes
+    ----------
+    matches: The number of matches of the rule, including the candidate matches that were skipped (e.g. rejected by the filters)
+    applications: The number of edits applied for the rule
+    time_ms: The time spent matching the rule (in milliseconds)
+    suppressed_duplicates: The number of times the rule was not scheduled again for the same scope within a propagation
+    """
+
+    matches: int
+    "The number of matches of the rule, including the candidate matches that were skipped (e.g. rejected by the filters)"
+
+    applications: int
+    "The number of edits applied for the rule"
+
+    time_ms: int
+    "The time spent matching the rule (in milliseconds)"
+
+    suppressed_duplicates: int
+    "The number of times the rule was not scheduled again for the same scope within a propagation, since it was already pending (or applied without any edit since)"
+
+class SkippedFile:
+    """
+     A class to represent a file that was not processed by Piranha (e.g. a generated file larger than `max_file_size_bytes`), reported without its content
+
+    Attributes
+    ----------
+    path: Path to the file
+    size_bytes: The size of the file (in bytes)
+    reason: Why the file was skipped
+    """
+
+    path: str
+    "Path to the file"
+
+    size_bytes: int
+    "The size of the file (in bytes)"
+
+    reason: str
+    "Why the file was skipped"
+
+class SkippedMatch:
+    """
+     A class to represent a candidate match that was intentionally skipped by Piranha
+
+    Attributes
+    ----------
+    rule_name: Name of the rule
+    reason: Why the candidate match was skipped - `filter_rejected`, `duplicate_match`, `no_op_edit`, `vetoed_by_plugin`, `unbound_tag`, `vetoed_by_interceptor`, `suppressed_by_comment`, `outside_line_range` or `exclusive_group`
+    range: Range of the candidate match
+    """
+
+    rule_name: str
+    "Name of the rule"
+
+    reason: str
+    "Why the candidate match was skipped - `filter_rejected`, `duplicate_match`, `no_op_edit`, `vetoed_by_plugin`, `unbound_tag`, `vetoed_by_interceptor`, `suppressed_by_comment`, `outside_line_range` or `exclusive_group`"
+
+    range: Range
+    "Range of the candidate match"
+
 class Edit:
     """
      A class to represent an edit performed by Piranha
@@ -112,6 +358,8 @@ class Edit:
     p_match: The match representing the target site of the edit
     replacement_string: The string to replace the substring encompassed by the match
     matched_rule: The rule used for creating this match-replace
+    triggered_by: The previously applied rule that triggered this edit through an edge (`None` for seed rules)
+    rule_substitutions: The substitutions for the holes of the matched rule (i.e. the values it was instantiated with)
     """
 
     p_match: Match
@@ -123,6 +371,32 @@ class Edit:
     replacement_string: str
     "The string to replace the substring encompassed by the match"
 
+    triggered_by: Optional[TriggeredBy]
+    "The previously applied rule that triggered this edit through an edge (`None` for seed rules)"
+
+    rule_substitutions: dict[str, str]
+    "The substitutions for the holes of the matched rule (i.e. the values it was instantiated with)"
+
+class TriggeredBy:
+    """
+     A class to represent why a rule was applied
+
+    Attributes
+    ----------
+    rule: Name of the rule whose application triggered the rule
+    scope: Scope of the edge (e.g. `Parent`, `Global`, `Method`)
+    range: Range of the triggering match (or edit)
+    """
+
+    rule: str
+    "Name of the rule whose application triggered the rule"
+
+    scope: str
+    "Scope of the edge (e.g. `Parent`, `Global`, `Method`)"
+
+    range: Range
+    "Range of the triggering match (or edit)"
+
 class Match:
     """
      A class to represent a match
@@ -132,6 +406,7 @@ class Match:
     matched_sting: Code snippet that matched
     range: Range of the entire AST node captured by the match
     matches: The mapping between tags and string representation of the AST captured
+    capture_ranges: The mapping between tags and the ranges of the AST nodes captured (only when `report_capture_ranges` is enabled)
     """
 
     matched_string: str
@@ -142,6 +417,9 @@ class Match:
 
     matches: dict
     "The mapping between tags and string representation of the AST captured"
+
+    capture_ranges: dict[str, list[Range]]
+    "The mapping between tags and the ranges of the AST nodes captured (a quantified tag has a range for each node it captured)"
     ""
 
 class Range:
@@ -176,6 +454,12 @@ class Filter:
     "Number of named children under the primary matched node"
     sibling_count: int
     "Number of named siblings of the primary matched node"
+    parent_kind: str
+    "Kind of the direct parent of the primary matched node (if not empty)"
+    max_enclosing_node_distance: int
+    "Maximum number of named ancestors of the primary matched node up to the node matching `enclosing_node` (0 if it is the matched node itself)"
+    negated: bool
+    "Inverts the filter, i.e. the filter is satisfied iff its conditions are NOT satisfied"
     def __init__(
         self,
         enclosing_node: Optional[str] = None,
@@ -185,7 +469,10 @@ class Filter:
         at_least: int = 1,
         at_most: int = 4294967295, # u32::MAX
         child_count: int = 4294967295, # u32::MAX
-        sibling_count: int = 4294967295 # u32::MAX
+        sibling_count: int = 4294967295, # u32::MAX
+        parent_kind: Optional[str] = None,
+        max_enclosing_node_distance: int = 4294967295, # u32::MAX
+        negated: bool = False
     ):
         """
         Constructs `Filter`
@@ -196,6 +483,42 @@ class Filter:
                 AST patterns that some ancestor node of the primary match should comply
             not_contains: list[str]
                  AST patterns that should not match any subtree of node matching `enclosing_node` pattern
+            parent_kind: str
+                 Kind of the direct parent of the primary matched node, e.g. `expression_statement`
+            max_enclosing_node_distance: int
+                 Maximum number of named ancestors of the primary matched node up to the node matching `enclosing_node`
+            negated: bool
+                 Inverts the filter, i.e. the filter is satisfied iff its conditions are NOT satisfied
+        """
+        ...
+
+class PackageFilter:
+    """ Restricts a Piranha Rule to the files whose declared package (or module, or namespace) matches.
+    The rule is not applied to the files without a package declaration.
+    """
+    exact: str
+    "The declared package should be equal to this (if not empty)"
+    prefix: str
+    "The declared package should start with this (if not empty)"
+    regex: str
+    "The declared package should match this regex (if not empty)"
+    def __init__(
+        self,
+        exact: Optional[str] = None,
+        prefix: Optional[str] = None,
+        regex: Optional[str] = None
+    ):
+        """
+        Constructs `PackageFilter`
+
+        Parameters
+        ------------
+            exact: str
+                The declared package should be equal to this
+            prefix: str
+                The declared package should start with this
+            regex: str
+                The declared package should match this regex
         """
         ...
 
@@ -207,7 +530,7 @@ class Rule:
     query: TSQuery
     "Tree-sitter query as string"
     replace_node: str
-    "The tag corresponding to the node to be replaced"
+    "The tag corresponding to the node to be replaced. When prefixed with `@` (e.g. `@annotation`), only the node(s) captured by the tag within the match are replaced"
     replace_node_idx: str
     "The i'th child of node corresponding to the replace_node tag will be replaced"
     replace: str
@@ -220,6 +543,36 @@ class Rule:
     "Filters to test before applying a rule"
     is_seed_rule: bool
     "Marks a rule as a seed rule"
+    scope: str
+    "Scope of a seed rule. The `MultiFile` seed rules are matched against all the files before any rewrite begins"
+    exactly_one_match: bool
+    "Rewrites proceed only if the (`MultiFile`) rule matches exactly once across all the files"
+    delete_empty_enclosing: bool
+    "After a delete edit, also deletes the enclosing blocks/statements that become empty (recursively)"
+    package_filter: Optional[PackageFilter]
+    "Applies the rule only to the files whose declared package (or module) satisfies this filter"
+    requires_features: list[str]
+    "The rule is applied only if these features were detected (i.e. the `MultiFile` match-only seed rules with these names matched)"
+    language: str
+    "The language of the files the rule is applied to (e.g. `py`), if it differs from the language of the run"
+    replace_with_capture: str
+    "Replaces the node with the code captured by this tag, copied verbatim (i.e. instead of instantiating the `replace` pattern)"
+    directory_scope: str
+    "Applies the rule only to the files whose path (relative to the code base) matches this glob, e.g. `services/@service_name/**`"
+    query_file: str
+    "Path to the file of the tree-sitter query (instead of `query`), relative to the configuration directory (or the current directory)"
+    capture_all_groups: bool
+    "Captures each named node of the query that has no capture yet with its kind, prefixed with `__` (e.g. `@__identifier`, `@__identifier_2`)"
+    query_cst: str
+    "The concrete syntax of the code to match (e.g. `exp.isTreated(:[flag])`), instead of `query`, where the holes (`:[name]`) match any single named node"
+    exclusive_group: str
+    "The mutual-exclusion group of the rule. The other rules of the group are not applied to the code rewritten by one of them"
+    dry: bool
+    "Records the edits of the rule among the suggested rewrites of the file (`suggested_rewrites`), instead of applying them"
+    template_params: list[str]
+    "The parameters of the rule template (instantiated by the `[[template_instances]]` of the TOML configurations)"
+    prefilter: bool
+    "Skips (without parsing them) the files containing none of the values of the holes of the rule"
 
     def __init__(
         self,
@@ -231,6 +584,20 @@ class Rule:
         holes: set[str] = set(),
         filters: set[Filter] = set(),
         is_seed_rule: bool = True,
+        scope: str = "",
+        exactly_one_match: bool = False,
+        delete_empty_enclosing: bool = False,
+        package_filter: Optional[PackageFilter] = None,
+        requires_features: list[str] = [],
+        language: str = "",
+        replace_with_capture: str = "",
+        directory_scope: str = "",
+        query_file: str = "",
+        capture_all_groups: bool = False,
+        query_cst: str = "",
+        exclusive_group: str = "",
+        dry: bool = False,
+        prefilter: bool = True,
     ):
         """
         Constructs `Rule`
@@ -253,6 +620,34 @@ class Rule:
                 Filters to test before applying a rule
             is_seed_rule: bool
                 Marks a rule as a seed rule
+            scope: str
+                Scope of a seed rule. The `MultiFile` seed rules are matched against all the files before any rewrite begins
+            exactly_one_match: bool
+                Rewrites proceed only if the (`MultiFile`) rule matches exactly once across all the files
+            delete_empty_enclosing: bool
+                After a delete edit, also deletes the enclosing blocks/statements that become empty (recursively)
+            package_filter: PackageFilter
+                Applies the rule only to the files whose declared package (or module) satisfies this filter
+            requires_features: list[str]
+                The rule is applied only if these features were detected (i.e. the `MultiFile` match-only seed rules with these names matched)
+            language: str
+                The language of the files the rule is applied to (e.g. `py`), if it differs from the language of the run
+            replace_with_capture: str
+                Replaces the node with the code captured by this tag, copied verbatim (i.e. instead of instantiating the `replace` pattern)
+            directory_scope: str
+                Applies the rule only to the files whose path (relative to the code base) matches this glob, e.g. `services/@service_name/**`
+            query_file: str
+                Path to the file of the tree-sitter query (e.g. `queries/delete_flag.scm`), instead of `query`. Relative to the current directory
+            capture_all_groups: bool
+                Captures each named node of the query that has no capture yet with its kind, prefixed with `__` (e.g. `@__identifier`, `@__identifier_2`)
+            query_cst: str
+                The concrete syntax of the code to match (e.g. `exp.isTreated(:[flag])`), instead of `query`. The holes (`:[name]`) match any single named node, captured as `@name`, and the whole match is captured as `@cst`
+            exclusive_group: str
+                The mutual-exclusion group of the rule (e.g. `treatment_handling`). Once a rule of the group rewrote some code of a file, the other rules of the group are not applied to the rewritten code (the suppressed attempts are reported in the notes)
+            dry: bool
+                Records the edits of the rule among the suggested rewrites of the file (`suggested_rewrites`), including their replacement, instead of applying them. Hence they trigger no propagation
+            prefilter: bool
+                Uses the values of the holes of the rule (e.g. the flag name) as literal anchors, i.e. the files containing none of them are not parsed. Disable it if the holes do not appear literally in the matched code
         """
         ...
 
@@ -261,14 +656,14 @@ class OutgoingEdges:
     "The source rule or group of rules"
     to: list[str]
     "The target edges or groups of edges"
-    scope: str
-    "The scope label for the edge"
+    scope: str | list[str]
+    "The scope label (or the list of scope labels) for the edge (the next rules are applied in each of these scopes)"
 
     def __init__(
         self,
         frm: str,
         to: list[str],
-        scope: str,
+        scope: str | list[str],
     ):
         """
         Constructs `OutgoingEdge`
@@ -279,8 +674,8 @@ class OutgoingEdges:
                 The source rule or group of rules
             to: list[str]
                 The target edges or groups of edges
-            scope: str
-                The scope label for the edge
+            scope: str | list[str]
+                The scope label (or the list of scope labels) for the edge
         """
         ...
 
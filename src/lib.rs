@@ -12,9 +12,28 @@
 */
 #![allow(deprecated)] // This prevents cargo clippy throwing warning for deprecated use.
 use models::{
-  edit::Edit, filter::Filter, matches::Match, outgoing_edges::OutgoingEdges,
-  piranha_arguments::PiranhaArguments, piranha_output::PiranhaOutputSummary, rule::Rule,
-  rule_graph::RuleGraph, source_code_unit::SourceCodeUnit,
+  edit::{Edit, TriggeredBy},
+  filter::Filter,
+  language::py_register_language,
+  matches::Match,
+  metrics::{MetricsRecorder, UnappliedSeedRule},
+  negative_cache::NegativeCache,
+  outgoing_edges::OutgoingEdges,
+  package_filter::PackageFilter,
+  piranha_arguments::PiranhaArguments,
+  piranha_output::{MatchRecord, PiranhaOutputSummary, RuleStat, SkippedFile},
+  piranha_plugin::PiranhaPlugin,
+  progress::ProgressEvent,
+  render::{py_render_edit, RenderOptions},
+  rule::Rule,
+  rule_graph::RuleGraph,
+  skipped_match::{SkipReason, SkippedMatch},
+  source_code_unit::SourceCodeUnit,
+  stage::StagesOutcome,
+};
+pub use models::{
+  rule::{ParseError, Rule},
+  source_code_view::{Analysis, SourceCodeView},
 };
 
 pub mod models;
@@ -22,28 +41,51 @@ pub mod models;
 mod tests;
 pub mod utilities;
 
-use std::{collections::HashMap, fs::File, io::Write, path::PathBuf};
+use std::{
+  collections::{BTreeMap, HashMap, HashSet},
+  fs::{self, File},
+  io::{BufWriter, Write},
+  path::{Path, PathBuf},
+  sync::Arc,
+};
 
 use itertools::Itertools;
-use log::{debug, info};
+use log::{debug, error, info, warn};
+use utilities::{
+  codebase::Codebase,
+  patch::Patch,
+  pbxproj::{self, PBXPROJ_FILE_NAME},
+};
 
 use crate::models::rule_store::RuleStore;
 
-use pyo3::prelude::{pyfunction, pymodule, wrap_pyfunction, PyModule, PyResult, Python};
+use pyo3::{
+  exceptions::PyRuntimeError,
+  prelude::{pyfunction, pymodule, wrap_pyfunction, PyModule, PyResult, Python},
+};
 use tempdir::TempDir;
+use tree_sitter::Parser;
 
 #[pymodule]
 fn polyglot_piranha(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
   pyo3_log::init();
-  m.add_function(wrap_pyfunction!(execute_piranha, m)?)?;
+  m.add_function(wrap_pyfunction!(py_execute_piranha, m)?)?;
+  m.add_function(wrap_pyfunction!(py_register_language, m)?)?;
+  m.add_function(wrap_pyfunction!(py_render_edit, m)?)?;
   m.add_class::<PiranhaArguments>()?;
   m.add_class::<PiranhaOutputSummary>()?;
+  m.add_class::<RuleStat>()?;
+  m.add_class::<SkippedFile>()?;
   m.add_class::<Edit>()?;
+  m.add_class::<TriggeredBy>()?;
   m.add_class::<Match>()?;
+  m.add_class::<SkippedMatch>()?;
   m.add_class::<RuleGraph>()?;
   m.add_class::<Rule>()?;
   m.add_class::<OutgoingEdges>()?;
   m.add_class::<Filter>()?;
+  m.add_class::<PackageFilter>()?;
+  m.add_class::<RenderOptions>()?;
   Ok(())
 }
 
@@ -54,20 +96,397 @@ fn polyglot_piranha(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
 ///
 /// Returns Piranha Output Summary for each file touched or analyzed by Piranha.
 /// For each file, it reports its content after the rewrite, the list of matches and the list of rewrites.
-#[pyfunction]
+///
+/// # Panics
+/// If the run failed, e.g. the condition of a `MultiFile` seed rule is not satisfied, or a stage failed and the next
+/// stages were not executed. Call `execute_piranha_with_plugins` (with no plugin) to get these failures as an error.
 pub fn execute_piranha(piranha_arguments: &PiranhaArguments) -> Vec<PiranhaOutputSummary> {
+  execute_piranha_with_plugins(piranha_arguments, vec![]).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// Executes piranha for the given `piranha_arguments` (see `execute_piranha`).
+/// Raises a `RuntimeError` with the reason if the run failed (e.g. the condition of a `MultiFile` seed rule
+/// is not satisfied), rather than panicking.
+#[pyfunction(name = "execute_piranha")]
+pub fn py_execute_piranha(
+  piranha_arguments: &PiranhaArguments,
+) -> PyResult<Vec<PiranhaOutputSummary>> {
+  execute_piranha_with_plugins(piranha_arguments, vec![]).map_err(PyRuntimeError::new_err)
+}
+
+/// Executes piranha for the given `piranha_arguments`, invoking the given `plugins` in between the rule application steps.
+///
+/// # Arguments:
+/// * piranha_arguments: Piranha Arguments
+/// * plugins: Custom hooks invoked (in order) before and after the rules are applied to each file
+///
+/// Returns Piranha Output Summary for each file touched or analyzed by Piranha.
+/// Returns an error (without rewriting any file) if the condition of a `MultiFile` seed rule is not satisfied.
+///
+/// If the rule graph declares stages (see `Stage`), they are executed in order, each one starting from the result
+/// (and the global substitutions) of the previous ones. The summaries are attributed to their stage.
+/// Returns an error if a stage failed (or had warnings, see `ContinueOn`) and the next stages were not executed.
+/// See `execute_stages_with_plugins` to get the summaries of the completed stages in that case.
+pub fn execute_piranha_with_plugins(
+  piranha_arguments: &PiranhaArguments, plugins: Vec<Box<dyn PiranhaPlugin>>,
+) -> Result<Vec<PiranhaOutputSummary>, String> {
+  execute_stages_with_plugins(piranha_arguments, plugins).into_result()
+}
+
+/// Executes piranha like `execute_piranha_with_plugins`, but returns the summaries of the stages that completed
+/// along with why the run stopped early (if it did), e.g. when a stage failed and the next stages were not executed.
+/// The outputs written to files (i.e. `output_patch`, `matches_output`, `export_rules` and `output_archive`) cover
+/// all the completed stages.
+pub fn execute_stages_with_plugins(
+  piranha_arguments: &PiranhaArguments, mut plugins: Vec<Box<dyn PiranhaPlugin>>,
+) -> StagesOutcome {
   info!("Executing Polyglot Piranha !!!");
 
-  let mut piranha = Piranha::new(piranha_arguments);
-  piranha.perform_cleanup();
+  // The graph executed from the seed rules of the run
+  if let Some(path) = piranha_arguments.dump_graph() {
+    let rule_store = RuleStore::new(piranha_arguments);
+    if let Err(e) = rule_store.dump_graph(piranha_arguments.rule_graph(), path) {
+      return StagesOutcome::new(vec![], Some(e));
+    }
+  }
 
-  let summaries = piranha
-    .get_updated_files()
+  // The metrics are written (to `metrics_output`) when dropped, i.e. even if the run fails partway
+  let mut metrics = MetricsRecorder::new(piranha_arguments);
+  let mut outputs = match RunOutputs::new(piranha_arguments) {
+    Ok(outputs) => outputs,
+    Err(e) => return StagesOutcome::new(vec![], Some(e)),
+  };
+  let (summaries, mut stop_reason) =
+    execute_stages(piranha_arguments, &mut plugins, &mut outputs, &mut metrics);
+  if let Err(e) = outputs.write(piranha_arguments) {
+    stop_reason.get_or_insert(e);
+  }
+  if stop_reason.is_none() {
+    metrics.record_completion();
+  }
+  StagesOutcome::new(summaries, stop_reason)
+}
+
+/// Applies the rules to the code base (like `execute_piranha`) without writing anything, i.e. in the `dry_run` mode,
+/// and returns the `Analysis` of the files with matches or rewrites, handing out a read-only `SourceCodeView` of each
+/// file (sorted by path), e.g. for the tools inspecting their syntax trees and tag bindings themselves.
+/// The stages of the rule graph (if any) are not executed in turn. Returns an error if the condition of a `MultiFile`
+/// seed rule is not satisfied.
+pub fn analyze(piranha_arguments: &PiranhaArguments) -> Result<Analysis, String> {
+  let mut piranha = Piranha::new(&piranha_arguments.with_dry_run(), vec![]);
+  piranha.perform_cleanup()?;
+  Ok(Analysis::new(
+    piranha
+      .get_updated_files()
+      .into_iter()
+      .sorted_by(|a, b| a.path().cmp(b.path()))
+      .collect(),
+  ))
+}
+
+/// Replaces the code between the byte offsets `start_byte` and `end_byte` of the `code_snippet` of the
+/// `piranha_arguments` with the `replacement`, e.g. for the edits computed by an external analysis tool rather than
+/// by a rule. Returns the summary of the updated code, reporting the edit among its rewrites, or an error if the offsets
+/// do not denote a valid range of the code, if the `edit_interceptor` skipped the edit, or if the edit produced
+/// syntactically incorrect code.
+pub fn apply_edit_at_offset(
+  piranha_arguments: &PiranhaArguments, start_byte: usize, end_byte: usize, replacement: &str,
+) -> Result<PiranhaOutputSummary, String> {
+  let (mut source_code_unit, mut parser) = code_snippet_unit(piranha_arguments)?;
+  source_code_unit.apply_edit_at_offset(start_byte, end_byte, replacement, &mut parser)?;
+  Ok(PiranhaOutputSummary::new(&source_code_unit))
+}
+
+/// Renames the symbol `old_name` to `new_name` in the `code_snippet` of the `piranha_arguments`, i.e. replaces each
+/// identifier whose text is `old_name` (regardless of its declaration or scope), without writing a rule for it.
+/// Returns the summary of the updated code, with one rewrite per renamed identifier, or an error if there is no code
+/// snippet.
+pub fn refactor_rename_symbol(
+  piranha_arguments: &PiranhaArguments, old_name: &str, new_name: &str,
+) -> Result<PiranhaOutputSummary, String> {
+  let (mut source_code_unit, mut parser) = code_snippet_unit(piranha_arguments)?;
+  let mut rule_store = RuleStore::new(piranha_arguments);
+  source_code_unit.refactor_rename_symbol(old_name, new_name, &mut parser, &mut rule_store);
+  Ok(PiranhaOutputSummary::new(&source_code_unit))
+}
+
+/// Applies the (global) rules of the `piranha_arguments` to their `code_snippet`, but only to the matches starting
+/// within the lines `start_line` to `end_line` (inclusive, 0-based), e.g. the lines changed in a pull request.
+/// The matches of the rules triggered by an edge are restricted too, unless `propagate_outside_range` is set.
+/// Returns the summary of the updated code, or an error if there is no code snippet.
+pub fn apply_rules_on_lines(
+  piranha_arguments: &PiranhaArguments, start_line: usize, end_line: usize,
+  propagate_outside_range: bool,
+) -> Result<PiranhaOutputSummary, String> {
+  let (mut source_code_unit, mut parser) = code_snippet_unit(piranha_arguments)?;
+  let mut rule_store = RuleStore::new(piranha_arguments);
+  let language = piranha_arguments.get_language();
+  // The code snippet is only offered the rules of its language
+  let rules = rule_store
+    .global_rules()
     .iter()
-    .map(PiranhaOutputSummary::new)
+    .filter(|r| r.rule().language_or(&language) == language)
+    .cloned()
     .collect_vec();
-  log_piranha_output_summaries(&summaries);
-  summaries
+  source_code_unit.apply_rules_on_lines(
+    &mut rule_store,
+    &rules,
+    &mut parser,
+    start_line,
+    end_line,
+    propagate_outside_range,
+  );
+  Ok(PiranhaOutputSummary::new(&source_code_unit))
+}
+
+/// Replaces all the occurrences of the literal `old` in the `code_snippet` of the `piranha_arguments` with `new`
+/// (e.g. to rename a constant), without querying the syntax tree.
+/// Returns the summary of the updated code, with one rewrite per replaced occurrence, or an error if there is no code
+/// snippet or if the replacements introduce syntax errors (unless `allow_dirty_ast` is set).
+pub fn replace_all_literals(
+  piranha_arguments: &PiranhaArguments, old: &str, new: &str,
+) -> Result<PiranhaOutputSummary, String> {
+  let (mut source_code_unit, mut parser) = code_snippet_unit(piranha_arguments)?;
+  source_code_unit
+    .replace_all_literals(old, new, &mut parser)
+    .map_err(|e| e.to_string())?;
+  Ok(PiranhaOutputSummary::new(&source_code_unit))
+}
+
+/// The source code unit of the `code_snippet` of the `piranha_arguments` (with their input substitutions), along with
+/// the parser of its language, for the functions rewriting a piece of code (rather than a code base).
+/// Returns an error if there is no code snippet.
+fn code_snippet_unit(
+  piranha_arguments: &PiranhaArguments,
+) -> Result<(SourceCodeUnit, Parser), String> {
+  if piranha_arguments.code_snippet().is_empty() {
+    return Err("The `code_snippet` to rewrite is missing in the Piranha arguments.".to_string());
+  }
+  let mut parser = piranha_arguments.language().parser();
+  let source_code_unit = SourceCodeUnit::new(
+    &mut parser,
+    piranha_arguments.code_snippet().to_string(),
+    &piranha_arguments.input_substitutions(),
+    Path::new("code_snippet"),
+    piranha_arguments,
+  );
+  Ok((source_code_unit, parser))
+}
+
+/// Executes the stages of the migration in order (see `Stage`), or a single pass if the rule graph declares none.
+/// Returns the summaries of the completed stages, along with why the run stopped early (if it did).
+fn execute_stages(
+  piranha_arguments: &PiranhaArguments, plugins: &mut Vec<Box<dyn PiranhaPlugin>>,
+  outputs: &mut RunOutputs, metrics: &mut MetricsRecorder,
+) -> (Vec<PiranhaOutputSummary>, Option<String>) {
+  let stages = piranha_arguments.rule_graph().stages();
+  if stages.is_empty() || piranha_arguments.stage().is_some() {
+    return match execute_pass(piranha_arguments, plugins, outputs, metrics) {
+      Ok((summaries, _)) => (summaries, None),
+      Err(e) => (vec![], Some(e)),
+    };
+  }
+
+  let mut summaries = vec![];
+  let mut substitutions = piranha_arguments.input_substitutions();
+  let mut code_snippet = piranha_arguments.code_snippet().to_string();
+  for (i, stage) in stages.iter().enumerate() {
+    info!("Executing the stage `{}`", stage.name());
+    let next_stages = stages[i + 1..]
+      .iter()
+      .map(|s| format!("`{}`", s.name()))
+      .join(", ");
+    let stage_arguments = piranha_arguments.for_stage(stage.name(), &substitutions, &code_snippet);
+    match execute_pass(&stage_arguments, plugins, outputs, metrics) {
+      Ok((stage_summaries, global_substitutions)) => {
+        let has_warnings = stage_summaries.iter().any(|s| s.has_warnings());
+        substitutions = global_substitutions;
+        // The next stage starts from the rewritten code snippet
+        if let Some(summary) = stage_summaries
+          .first()
+          .filter(|s| !code_snippet.is_empty() && !s.is_skipped_file())
+        {
+          code_snippet = summary.content().to_string();
+        }
+        summaries.extend(
+          stage_summaries
+            .into_iter()
+            .map(|s| s.with_stage(stage.name())),
+        );
+        if !next_stages.is_empty() && !stage.should_continue(false, has_warnings) {
+          let stop_reason = format!(
+            "The stage `{}` completed with warnings, hence the next stages ({next_stages}) were not executed.",
+            stage.name()
+          );
+          return (summaries, Some(stop_reason));
+        }
+      }
+      Err(e) if next_stages.is_empty() => {
+        return (
+          summaries,
+          Some(format!("The stage `{}` failed - {e}", stage.name())),
+        )
+      }
+      Err(e) if !stage.should_continue(true, false) => {
+        let stop_reason = format!(
+          "The stage `{}` failed, hence the next stages ({next_stages}) were not executed - {e}",
+          stage.name()
+        );
+        return (summaries, Some(stop_reason));
+      }
+      Err(e) => error!("The stage `{}` failed - {e}", stage.name()),
+    }
+  }
+  (summaries, None)
+}
+
+/// Applies the rules to the code base until a fixed point is reached (i.e. a run, or a stage of a run).
+/// Returns the summaries, along with the global substitutions at the end of the pass.
+fn execute_pass(
+  piranha_arguments: &PiranhaArguments, plugins: &mut Vec<Box<dyn PiranhaPlugin>>,
+  outputs: &mut RunOutputs, metrics: &mut MetricsRecorder,
+) -> Result<(Vec<PiranhaOutputSummary>, HashMap<String, String>), String> {
+  let mut piranha = Piranha::new(piranha_arguments, std::mem::take(plugins));
+  piranha.outputs = std::mem::take(outputs);
+  // The metrics are written after each file, and when the pass panics (as `piranha` is dropped)
+  piranha.metrics = std::mem::take(metrics);
+  let outcome = piranha.execute();
+  // Hand the plugins, the outputs and the metrics back for the next stages (even if the pass failed)
+  *plugins = std::mem::take(&mut piranha.plugins);
+  *outputs = std::mem::take(&mut piranha.outputs);
+  *metrics = std::mem::take(&mut piranha.metrics);
+  if let Ok((summaries, _)) = &outcome {
+    outputs.record_pass(summaries);
+  }
+  metrics.record_pass(
+    piranha
+      .relevant_files
+      .values()
+      .map(|scu| (scu, piranha.rule_store.get_file_skip_counts(scu.path()))),
+    piranha.skipped_files.keys(),
+  );
+  let unapplied_seed_rules = piranha.get_unapplied_seed_rules();
+  log_unapplied_seed_rules(&unapplied_seed_rules);
+  let outcome = outcome.map(|(summaries, global_substitutions)| {
+    let summaries = summaries
+      .into_iter()
+      .map(|s| s.with_unapplied_seed_rules(&unapplied_seed_rules))
+      .collect();
+    (summaries, global_substitutions)
+  });
+  metrics.record_unapplied_seed_rules(unapplied_seed_rules);
+  if outcome.is_err() {
+    metrics.record_failure();
+  }
+  outcome
+}
+
+/// The outputs of a run that are shared by its passes (i.e. its stages), hence written once for the whole run.
+#[derive(Default)]
+struct RunOutputs {
+  // The changes to the files (if `output_patch` is set), from their original content to their content after the last pass
+  patch: Option<Patch>,
+  // The path and writer of the `matches_output` file, to which the matches are streamed (if set)
+  matches_writer: Option<(String, BufWriter<File>)>,
+  // The files of an archive code base updated by the passes (by path, `None` when deleted), written to the `output_archive`
+  archive_files: HashMap<PathBuf, Option<String>>,
+  // The content of the files after the previous passes, since it is not written in place in the `dry_run` mode
+  // (nor for an archive), while the next passes start from it
+  latest_contents: HashMap<PathBuf, String>,
+}
+
+impl RunOutputs {
+  /// Creates the outputs of the run, and exports its (user defined) rules to `export_rules` (if set).
+  fn new(piranha_arguments: &PiranhaArguments) -> Result<Self, String> {
+    if let Some(path) = piranha_arguments.export_rules() {
+      let rules = RuleStore::new(piranha_arguments)
+        .export_toml()
+        .map_err(|e| format!("Could not export the rules - {e}"))?;
+      fs::write(path, rules).map_err(|e| format!("Could not write the rules to {path} - {e}"))?;
+    }
+    let matches_writer = match piranha_arguments.matches_output() {
+      Some(path) => {
+        let file = File::create(path)
+          .map_err(|e| format!("Could not create the matches output file - {path} \n {e}"))?;
+        Some((path.to_string(), BufWriter::new(file)))
+      }
+      None => None,
+    };
+    Ok(Self {
+      patch: piranha_arguments
+        .output_patch()
+        .as_ref()
+        .map(|_| Patch::new(piranha_arguments.codebase())),
+      matches_writer,
+      ..Default::default()
+    })
+  }
+
+  /// Records the content of the files reported in the `summaries` of a pass, for the next passes.
+  /// The skipped files are not reported with their content, hence they are read again by the next passes.
+  fn record_pass(&mut self, summaries: &[PiranhaOutputSummary]) {
+    for summary in summaries.iter().filter(|s| !s.is_skipped_file()) {
+      self
+        .latest_contents
+        .insert(PathBuf::from(summary.path()), summary.content().to_string());
+    }
+  }
+
+  /// Writes the patch (to `output_patch`) and the updated archive (to `output_archive`) of the run.
+  fn write(self, piranha_arguments: &PiranhaArguments) -> Result<(), String> {
+    if let (Some(path), Some(patch)) = (piranha_arguments.output_patch(), &self.patch) {
+      fs::write(path, patch.to_string())
+        .map_err(|e| format!("Could not write the patch to {path} - {e}"))?;
+    }
+    let codebase = piranha_arguments.codebase();
+    if !codebase.is_archive()
+      || !piranha_arguments.code_snippet().is_empty()
+      || *piranha_arguments.dry_run()
+    {
+      return Ok(());
+    }
+    match piranha_arguments.output_archive() {
+      Some(output_archive) => {
+        codebase.write_archive(Path::new(output_archive), &self.archive_files)?
+      }
+      None => warn!(
+        "The code base is an archive, but no `output_archive` was provided. The changes are only reported in the summaries."
+      ),
+    }
+    Ok(())
+  }
+}
+
+/// Removes the `pbxproj_references_to_remove` from the Xcode project files (`project.pbxproj`) in the code base.
+/// There is no grammar for this format, hence these files are not handled by the rules.
+/// The files of an archive are not written in place (see `RunOutputs::write`).
+/// Returns the summaries for the updated files, or an error if a file could not be read or written.
+fn remove_pbxproj_references(
+  piranha_arguments: &PiranhaArguments,
+) -> Result<Vec<PiranhaOutputSummary>, String> {
+  let names = piranha_arguments.pbxproj_references_to_remove();
+  if names.is_empty() {
+    return Ok(vec![]);
+  }
+  let codebase = piranha_arguments.codebase();
+  let mut summaries = vec![];
+  for (path, content) in
+    codebase.read_files(|path| path.file_name().is_some_and(|n| n == PBXPROJ_FILE_NAME))?
+  {
+    let updated_content = pbxproj::remove_references(&content, names);
+    if updated_content == content {
+      continue;
+    }
+    if !*piranha_arguments.dry_run() && !codebase.is_archive() {
+      fs::write(&path, &updated_content).map_err(|e| format!("Could not write {path:?} - {e}"))?;
+    }
+    summaries.push(PiranhaOutputSummary::for_special_file(
+      &path,
+      content,
+      updated_content,
+    ));
+  }
+  Ok(summaries)
 }
 
 fn log_piranha_output_summaries(summaries: &Vec<PiranhaOutputSummary>) {
@@ -87,107 +506,679 @@ fn log_piranha_output_summaries(summaries: &Vec<PiranhaOutputSummary>) {
   info!("Total number of rewrites {}", total_number_of_rewrites);
 }
 
+fn log_skip_counts(skip_counts: &BTreeMap<(String, SkipReason), usize>) {
+  for ((rule_name, reason), count) in skip_counts {
+    info!(
+      "Rule {} : # Skipped matches ({}) : {}",
+      rule_name, reason, count
+    );
+  }
+}
+
+fn log_unapplied_seed_rules(unapplied_seed_rules: &BTreeMap<String, UnappliedSeedRule>) {
+  for (rule_name, reason) in unapplied_seed_rules {
+    info!(
+      "Seed rule {} : No edit nor match, since {}",
+      rule_name, reason
+    );
+  }
+}
+
 // Maintains the state of Piranha and the updated content of files in the source code.
 struct Piranha {
   // Maintains Piranha's state
   rule_store: RuleStore,
   // Files updated by Piranha.
   relevant_files: HashMap<PathBuf, SourceCodeUnit>,
+  // The path (key of `relevant_files`) of the `SourceCodeUnit` of each file, by canonical path
+  canonical_paths: HashMap<PathBuf, PathBuf>,
   // Piranha Arguments
   piranha_arguments: PiranhaArguments,
+  // User defined hooks invoked before and after applying the rules to a file
+  plugins: Vec<Box<dyn PiranhaPlugin>>,
+  // Files whose updated content should not be written to the disk (vetoed by a plugin)
+  vetoed_files: HashSet<PathBuf>,
+  // Files (along with their size and why) skipped because they are larger than `max_file_size_bytes`
+  // or contain lines longer than `max_line_length`
+  skipped_files: HashMap<PathBuf, SkippedFile>,
+  // Records the files that had no match in the previous runs (if `cache_dir` is set)
+  negative_cache: Option<NegativeCache>,
+  // The arguments for the files of the languages of the rules other than the language of the run (by extension)
+  language_arguments: HashMap<String, PiranhaArguments>,
+  // The substitutions for the global tags found during the run (along with the input substitutions)
+  global_substitutions: HashMap<String, String>,
+  // The outputs shared with the other passes of the run (e.g. the patch)
+  outputs: RunOutputs,
+  // The metrics of the run, recorded as the files are processed
+  metrics: MetricsRecorder,
 }
 
 impl Piranha {
+  /// Applies the rules, records the outputs (e.g. the patch) and returns the summaries along with
+  /// the global substitutions at the end of the run.
+  fn execute(&mut self) -> Result<(Vec<PiranhaOutputSummary>, HashMap<String, String>), String> {
+    let piranha_arguments = self.piranha_arguments.clone();
+    self.perform_cleanup()?;
+    if *piranha_arguments.omit_matches_from_summary() {
+      for scu in self.relevant_files.values_mut() {
+        scu.clear_matches();
+      }
+    }
+
+    let mut summaries = self
+      .get_updated_files()
+      .iter()
+      .map(|scu| {
+        PiranhaOutputSummary::new(scu).with_skipped_matches(
+          self.rule_store.get_skipped_matches(scu.path()),
+          &self.rule_store.get_file_skip_counts(scu.path()),
+        )
+      })
+      .collect_vec();
+    summaries.extend(self.get_skipped_file_summaries());
+    let pbxproj_summaries = remove_pbxproj_references(&piranha_arguments)?;
+    for summary in &pbxproj_summaries {
+      if let Some(patch) = self.outputs.patch.as_mut() {
+        patch.add(
+          Path::new(summary.path()),
+          summary.original_content(),
+          Some(summary.content()),
+        );
+      }
+      if piranha_arguments.codebase().is_archive() {
+        self.outputs.archive_files.insert(
+          PathBuf::from(summary.path()),
+          Some(summary.content().to_string()),
+        );
+      }
+    }
+    summaries.extend(pbxproj_summaries);
+    log_piranha_output_summaries(&summaries);
+    log_skip_counts(&self.rule_store.get_skip_counts());
+    let query_cache = self.rule_store.rule_query_cache();
+    info!(
+      "Query cache : {} hit(s), {} miss(es) (hit rate {:.2})",
+      query_cache.hits(),
+      query_cache.misses(),
+      query_cache.hit_rate()
+    );
+    Ok((summaries, std::mem::take(&mut self.global_substitutions)))
+  }
+
+  /// The seed rules that produced neither an edit nor a match in any file, along with why (see `UnappliedSeedRule`)
+  fn get_unapplied_seed_rules(&self) -> BTreeMap<String, UnappliedSeedRule> {
+    let applied_rules = self
+      .relevant_files
+      .values()
+      .flat_map(|scu| scu.rule_statistics())
+      .filter(|(_, s)| *s.matches() > 0 || *s.applications() > 0)
+      .map(|(rule, _)| rule.to_string())
+      .collect();
+    self.rule_store.get_unapplied_seed_rules(&applied_rules)
+  }
+
+  /// The files with matches, rewrites or suggested rewrites, along with the files that timed out (to report the timeout)
   fn get_updated_files(&self) -> Vec<SourceCodeUnit> {
     self
       .relevant_files
       .values()
-      .filter(|r| !r.matches().is_empty() || !r.rewrites().is_empty())
+      .filter(|r| {
+        !r.matches().is_empty()
+          || !r.rewrites().is_empty()
+          || !r.suggested_rewrites().is_empty()
+          || r.timed_out_rule().is_some()
+          || !r.notes().is_empty()
+      })
       .cloned()
       .collect_vec()
   }
 
-  /// Performs cleanup related to stale flags
-  fn perform_cleanup(&mut self) {
-    // Setup the parser for the specific language
+  /// Writes the `matches` of the file at `file_path` to the `matches_output` file (one `MatchRecord` per line),
+  /// and flushes them, so that the matches are streamed as the files are processed.
+  fn write_matches(
+    matches_writer: &mut (String, BufWriter<File>), file_path: &Path, matches: &[(String, Match)],
+  ) -> Result<(), String> {
+    let (path, writer) = matches_writer;
+    let write_err = |e: String| format!("Could not write the matches to the file - {path} \n {e}");
+    for (rule, p_match) in matches {
+      let record = MatchRecord::new(file_path, rule.to_string(), p_match.clone());
+      serde_json::to_writer(&mut *writer, &record).map_err(|e| write_err(e.to_string()))?;
+      writeln!(writer).map_err(|e| write_err(e.to_string()))?;
+    }
+    writer.flush().map_err(|e| write_err(e.to_string()))
+  }
+
+  /// Summaries for the files skipped because they are too large, contain lines longer than `max_line_length`, syntax errors
+  /// or are disabled by a `piranha:disable` comment.
+  fn get_skipped_file_summaries(&self) -> Vec<PiranhaOutputSummary> {
+    self
+      .skipped_files
+      .values()
+      .map(PiranhaOutputSummary::for_skipped_file)
+      .chain(
+        self
+          .relevant_files
+          .values()
+          .filter(|scu| scu.is_skipped())
+          .map(PiranhaOutputSummary::for_file_with_parse_errors),
+      )
+      .chain(
+        self
+          .relevant_files
+          .values()
+          .filter(|scu| scu.is_disabled())
+          .map(PiranhaOutputSummary::for_disabled_file),
+      )
+      .collect_vec()
+  }
+
+  /// Checks if the file should be skipped because it is larger than `max_file_size_bytes` (like generated files)
+  /// or contains lines longer than `max_line_length` (like minified files).
+  /// Such files are recorded in `skipped_files`.
+  fn should_skip_file(
+    piranha_args: &PiranhaArguments, skipped_files: &mut HashMap<PathBuf, SkippedFile>,
+    path: &Path, content: &str,
+  ) -> bool {
+    if skipped_files.contains_key(path) {
+      return true;
+    }
+    let Some(note) = piranha_args.get_skip_reason(content) else {
+      return false;
+    };
+    warn!("{} - {:?} ({} bytes)", note, path, content.len());
+    skipped_files.insert(
+      path.to_path_buf(),
+      SkippedFile::new(path, content.len(), note),
+    );
+    true
+  }
+
+  /// Returns the arguments for the file at `path`, i.e. with the language of its extension if it is the language
+  /// of a rule (see `Rule::language`), else with the language of the run.
+  fn arguments_for_file<'a>(
+    piranha_args: &'a PiranhaArguments, language_arguments: &'a HashMap<String, PiranhaArguments>,
+    path: &Path,
+  ) -> &'a PiranhaArguments {
+    path
+      .extension()
+      .and_then(|e| e.to_str())
+      .filter(|e| !piranha_args.language().has_extension(e))
+      .and_then(|e| {
+        language_arguments
+          .values()
+          .find(|args| args.language().has_extension(e))
+      })
+      .unwrap_or(piranha_args)
+  }
+
+  /// Returns the path of the `SourceCodeUnit` for the file at `path`, i.e. the first path it was reached through
+  /// (recorded in `canonical_paths`). Hence, all the edits to a file reached through several paths (e.g. symlinks)
+  /// are applied to a single copy of it, instead of the last written copy overwriting the edits of the others.
+  fn source_code_unit_path(
+    canonical_paths: &mut HashMap<PathBuf, PathBuf>, path: &Path,
+  ) -> PathBuf {
+    let canonical_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let unit_path = canonical_paths
+      .entry(canonical_path)
+      .or_insert_with(|| path.to_path_buf());
+    if unit_path != path {
+      debug!(
+        "{:?} is the same file as {:?}, its edits are applied to the latter",
+        path, unit_path
+      );
+    }
+    unit_path.clone()
+  }
+
+  /// Checks that the `SourceCodeUnit` created for the file at `path` is its only one, i.e. that `path` is the one
+  /// `source_code_unit_path` returns for the file (two copies of a file would overwrite each other's edits).
+  fn is_single_source_code_unit(canonical_paths: &HashMap<PathBuf, PathBuf>, path: &Path) -> bool {
+    let canonical_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    canonical_paths.get(&canonical_path).map(PathBuf::as_path) == Some(path)
+  }
+
+  /// Deletes the comments matching the `comment_deletion_patterns` (instantiated with the global substitutions)
+  /// from the edited files, or from all the `files` of the code base (read by the last global pass of `perform_cleanup`)
+  /// if `comment_deletion_in_all_files` is set.
+  fn delete_matching_comments(
+    &mut self, files: HashMap<PathBuf, String>, parsers: &mut HashMap<String, Parser>,
+  ) {
     let piranha_args = &self.piranha_arguments;
+    if piranha_args.comment_deletion_patterns().is_empty() {
+      return;
+    }
+    let patterns = piranha_args.comment_deletion_regexes(&self.global_substitutions);
+    if *piranha_args.comment_deletion_in_all_files() {
+      for (path, content) in files {
+        if !patterns.iter().any(|p| p.is_match(&content)) {
+          continue;
+        }
+        let path = Self::source_code_unit_path(&mut self.canonical_paths, &path);
+        if self.relevant_files.contains_key(&path)
+          || Self::should_skip_file(piranha_args, &mut self.skipped_files, &path, &content)
+        {
+          continue;
+        }
+        let file_args = Self::arguments_for_file(piranha_args, &self.language_arguments, &path);
+        let parser = parsers
+          .entry(file_args.get_language())
+          .or_insert_with(|| file_args.language().parser());
+        debug_assert!(
+          Self::is_single_source_code_unit(&self.canonical_paths, &path),
+          "A second SourceCodeUnit was created for {path:?}"
+        );
+        let source_code_unit = SourceCodeUnit::new(
+          parser,
+          content,
+          &self.global_substitutions,
+          path.as_path(),
+          file_args,
+        );
+        self.relevant_files.insert(path, source_code_unit);
+      }
+    }
+    let delete_in_all_files = *piranha_args.comment_deletion_in_all_files();
+    for source_code_unit in self.relevant_files.values_mut() {
+      if source_code_unit.is_skipped()
+        || source_code_unit.is_disabled()
+        || (!delete_in_all_files && source_code_unit.rewrites().is_empty())
+      {
+        continue;
+      }
+      let parser = parsers
+        .entry(source_code_unit.piranha_arguments().get_language())
+        .or_insert_with(|| source_code_unit.piranha_arguments().language().parser());
+      source_code_unit.perform_comment_deletion(&patterns, parser);
+    }
+  }
 
-    let mut parser = piranha_args.language().parser();
+  /// Performs cleanup related to stale flags
+  fn perform_cleanup(&mut self) -> Result<(), String> {
+    // Setup the parser for each language (by extension), lazily
+    let mut parsers: HashMap<String, Parser> = HashMap::new();
 
-    let mut path_to_codebase = self.piranha_arguments.path_to_codebase().to_string();
+    let mut codebase = self.piranha_arguments.codebase().clone();
 
     let temp_dir = if !self.piranha_arguments.code_snippet().is_empty() {
       let td = self.write_code_snippet_to_temp();
-      path_to_codebase = td.path().to_str().unwrap_or_default().to_string();
+      codebase = Arc::new(Codebase::new(
+        td.path(),
+        self.piranha_arguments.include(),
+        self.piranha_arguments.exclude(),
+      ));
       Some(td)
     } else {
       None
     };
 
+    self.check_multi_file_rules(&codebase, &mut parsers)?;
+    self.rule_store.remove_rules_without_required_features();
+
+    let piranha_args = &self.piranha_arguments;
     let mut current_global_substitutions = piranha_args.input_substitutions();
-    // Keep looping until new `global` rules are added.
+    // All the files of the code base (read by the last global pass), when the comments are deleted from all of them
+    let delete_comments_in_all_files = !piranha_args.comment_deletion_patterns().is_empty()
+      && *piranha_args.comment_deletion_in_all_files();
+    let mut all_files = HashMap::new();
+    // The fingerprint (see `SourceCodeUnit::hash`) of each file after the current `global` rules were applied to it
+    let mut fixed_points: HashMap<PathBuf, u64> = HashMap::new();
+    // Keep looping until the files reach a fixed point w.r.t. the `global` rules.
     loop {
       let current_rules = self.rule_store.global_rules().clone();
+      // Each file is only offered the rules of its language
+      let current_rules_by_language = current_rules.iter().cloned().into_group_map_by(|r| {
+        r.rule()
+          .language_or(&piranha_args.get_language())
+          .to_string()
+      });
+      // Some rules only apply to the files in their `directory_scope`
+      let has_directory_scopes = current_rules
+        .iter()
+        .any(|r| !r.rule().directory_scope().is_empty());
+      let rule_set_hash = self
+        .negative_cache
+        .as_ref()
+        .map(|cache| cache.rule_set_hash(&current_rules));
 
       debug!("\n # Global rules {}", current_rules.len());
       // Iterate over each file containing the usage of the feature flag API
 
-      for (path, content) in self.rule_store.get_relevant_files(
-        &path_to_codebase,
-        piranha_args.include(),
-        piranha_args.exclude(),
-      ) {
+      let mut relevant_files = if delete_comments_in_all_files {
+        all_files = self.rule_store.get_files(&codebase);
+        self
+          .rule_store
+          .filter_relevant_files(&codebase, all_files.clone())
+      } else {
+        self.rule_store.get_relevant_files(&codebase)
+      };
+      // The files rewritten by the previous passes (e.g. stages) start from their content after these passes,
+      // since it may not be written in place (e.g. in the `dry_run` mode)
+      if temp_dir.is_none() {
+        relevant_files.extend(self.outputs.latest_contents.clone());
+      }
+      piranha_args.report_progress(ProgressEvent::GlobalPhaseStarted {
+        number_of_files: relevant_files.len(),
+      });
+      for (path, content) in relevant_files {
+        let path = Self::source_code_unit_path(&mut self.canonical_paths, &path);
+        piranha_args.report_progress(ProgressEvent::FileStarted { path: path.clone() });
+        // Every started file is reported as done, including the skipped ones (without any edit)
+        let report_file_done = |edits| {
+          piranha_args.report_progress(ProgressEvent::FileDone {
+            path: path.clone(),
+            edits,
+          })
+        };
+        if Self::should_skip_file(piranha_args, &mut self.skipped_files, &path, &content) {
+          self.metrics.record_skipped_file(&path);
+          report_file_done(0);
+          continue;
+        }
+        let file_args = Self::arguments_for_file(piranha_args, &self.language_arguments, &path);
+        let parser = parsers
+          .entry(file_args.get_language())
+          .or_insert_with(|| file_args.language().parser());
+        let mut file_rules = current_rules_by_language
+          .get(&file_args.get_language())
+          .map(Vec::as_slice)
+          .unwrap_or_default();
+        let scoped_file_rules;
+        if has_directory_scopes {
+          let relative_path = path.strip_prefix(codebase.path()).unwrap_or(&path);
+          scoped_file_rules = file_rules
+            .iter()
+            .filter(|r| r.applies_to(relative_path))
+            .cloned()
+            .collect_vec();
+          // The file is outside the directory scopes of all the current rules
+          if scoped_file_rules.is_empty() {
+            report_file_done(0);
+            continue;
+          }
+          file_rules = scoped_file_rules.as_slice();
+        }
+        // Skip the files that had no match for the current `global` rules in a previous run
+        if let (Some(cache), Some(rule_set_hash)) =
+          (self.negative_cache.as_mut(), rule_set_hash.as_deref())
+        {
+          if cache.lookup(&path, &content, rule_set_hash)
+            && !self.relevant_files.contains_key(&path)
+          {
+            cache.record_hit(&path);
+            report_file_done(0);
+            continue;
+          }
+        }
         // Get the `SourceCodeUnit` for the file `path` from the cache `relevant_files`.
         // In case of miss, lazily insert a new `SourceCodeUnit`.
+        let is_new = !self.relevant_files.contains_key(&path);
         let source_code_unit = self
           .relevant_files
           .entry(path.to_path_buf())
           .or_insert_with(|| {
+            debug_assert!(
+              Self::is_single_source_code_unit(&self.canonical_paths, &path),
+              "A second SourceCodeUnit was created for {path:?}"
+            );
             SourceCodeUnit::new(
-              &mut parser,
+              parser,
               content,
               &current_global_substitutions,
               path.as_path(),
-              piranha_args,
+              file_args,
             )
           });
 
-        // Apply the rules in this `SourceCodeUnit`
-        source_code_unit.apply_rules(&mut self.rule_store, &current_rules, &mut parser, None);
+        // Do not apply any rule to the files with syntax errors (when `on_parse_error` is `skip`)
+        if source_code_unit.is_skipped() {
+          report_file_done(0);
+          continue;
+        }
+
+        // The current `global` rules were already applied to this file (and it has not changed since)
+        if fixed_points.get(&path) == Some(&source_code_unit.hash()) {
+          report_file_done(0);
+          continue;
+        }
+
+        // Let the plugins pre-process the source code (once, rather than in each pass)
+        if is_new {
+          for plugin in &self.plugins {
+            if let Some(code) = plugin.before_apply(&source_code_unit.view()) {
+              source_code_unit._replace_file_contents_and_re_parse(&code, parser, false);
+            }
+          }
+        }
+
+        // Apply the rules in this `SourceCodeUnit` (all or nothing).
+        // The source code unit is left as it was when an edit fails, and the failure is reported in its summary.
+        let number_of_rewrites = source_code_unit.rewrites().len();
+        let number_of_matches = source_code_unit.matches().len();
+        if let Err(e) =
+          source_code_unit.apply_rules_transactional(&mut self.rule_store, file_rules, parser)
+        {
+          error!("{e}");
+          source_code_unit.record_failure(&e);
+        }
+        if let Some(matches_writer) = self.outputs.matches_writer.as_mut() {
+          let new_matches = source_code_unit
+            .matches()
+            .get(number_of_matches..)
+            .unwrap_or_default();
+          Self::write_matches(matches_writer, &path, new_matches)?;
+        }
+        report_file_done(source_code_unit.rewrites().len() - number_of_rewrites);
+        self.metrics.record_file(
+          source_code_unit,
+          &self.rule_store.get_file_skip_counts(&path),
+        );
+
+        // Let the plugins veto writing the updated source code
+        let number_of_vetoes = self
+          .plugins
+          .iter()
+          .filter(|plugin| !plugin.after_apply(&source_code_unit.view()))
+          .count();
+        if number_of_vetoes > 0 {
+          self.vetoed_files.insert(path.to_path_buf());
+        }
 
         // Add the substitutions for the global tags to the `current_global_substitutions`
         current_global_substitutions.extend(source_code_unit.global_substitutions());
+        fixed_points.insert(path.to_path_buf(), source_code_unit.hash());
 
         // Break when a new `global` rule is added
         if self.rule_store.global_rules().len() > current_rules.len() {
           debug!("Found a new global rule. Will start scanning all the files again.");
+          // The new rule has to be applied to all the files
+          fixed_points.clear();
           break;
         }
       }
-      // If no new `global_rules` were added, break.
-      if self.rule_store.global_rules().len() == current_rules.len() {
+      // The current `global` rules were applied to all the relevant files: if no new `global_rules` were added and
+      // no cached file changed since, break. The cached units are checked, rather than walking the code base again.
+      if self.rule_store.global_rules().len() == current_rules.len()
+        && fixed_points.iter().all(|(path, hash)| {
+          self
+            .relevant_files
+            .get(path)
+            .is_some_and(|scu| scu.hash() == *hash)
+        })
+      {
         break;
       }
     }
+    self.global_substitutions = current_global_substitutions;
+    self.delete_matching_comments(all_files, &mut parsers);
+    if let Some(cache) = &self.negative_cache {
+      info!("Negative cache hits : {}", cache.number_of_hits());
+      cache.persist(&self.relevant_files);
+    }
     // Delete the temp dir inside which the input code snippet was copied
     if let Some(t) = temp_dir {
       _ = t.close();
     } else {
       let source_code_units = self.get_updated_files();
+      // The files of an archive are written to the `output_archive` (see `RunOutputs::write`), not in place
 
       for scu in source_code_units.iter() {
-        scu.persist();
+        if self.vetoed_files.contains(scu.path()) {
+          info!("Writing {:?} was vetoed by a plugin", scu.path());
+          for edit in scu.rewrites() {
+            self.rule_store.report_skipped_match(
+              scu.path(),
+              SkippedMatch::new(
+                edit.matched_rule(),
+                SkipReason::VetoedByPlugin,
+                edit.p_match().range(),
+              ),
+            );
+          }
+          continue;
+        }
+        if let Err(conflicts) = scu.validate_edits() {
+          for conflict in conflicts {
+            error!("{:?}: {}", scu.path(), conflict);
+          }
+          error!(
+            "Could not persist {:?}: its edits are inconsistent",
+            scu.path()
+          );
+          continue;
+        }
+        if let Some(patch) = self.outputs.patch.as_mut() {
+          let updated_content = (!scu.is_deleted_on_persist()).then(|| scu.code().as_str());
+          patch.add(scu.path(), scu.original_content(), updated_content);
+        }
+        if !codebase.is_archive() {
+          scu.persist();
+        } else if scu.is_deleted_on_persist() || scu.code() != scu.original_content() {
+          let updated_content = (!scu.is_deleted_on_persist()).then(|| scu.code().to_string());
+          self
+            .outputs
+            .archive_files
+            .insert(scu.path().clone(), updated_content);
+        }
+        if !*self.piranha_arguments.dry_run() {
+          self
+            .piranha_arguments
+            .report_progress(ProgressEvent::FileWritten {
+              path: scu.path().clone(),
+            });
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Matches the `MultiFile` seed rules against all the relevant files, before any rewrite begins.
+  /// The match-only ones detect the features of the code base (see `Rule::requires_features`).
+  /// Returns an error if the matches (aggregated across all the files) do not satisfy the condition of a rule.
+  fn check_multi_file_rules(
+    &mut self, codebase: &Codebase, parsers: &mut HashMap<String, Parser>,
+  ) -> Result<(), String> {
+    let multi_file_rules = self
+      .rule_store
+      .global_rules()
+      .iter()
+      .filter(|r| r.rule().is_multi_file_rule())
+      .cloned()
+      .collect_vec();
+    if multi_file_rules.is_empty() {
+      return Ok(());
+    }
+
+    let piranha_args = &self.piranha_arguments;
+    let substitutions = piranha_args.input_substitutions();
+    for (path, content) in self.rule_store.get_relevant_files(codebase) {
+      let path = Self::source_code_unit_path(&mut self.canonical_paths, &path);
+      if Self::should_skip_file(piranha_args, &mut self.skipped_files, &path, &content) {
+        continue;
+      }
+      let file_args = Self::arguments_for_file(piranha_args, &self.language_arguments, &path);
+      let parser = parsers
+        .entry(file_args.get_language())
+        .or_insert_with(|| file_args.language().parser());
+      self
+        .relevant_files
+        .entry(path.to_path_buf())
+        .or_insert_with(|| {
+          debug_assert!(
+            Self::is_single_source_code_unit(&self.canonical_paths, &path),
+            "A second SourceCodeUnit was created for {path:?}"
+          );
+          SourceCodeUnit::new(parser, content, &substitutions, path.as_path(), file_args)
+        });
+    }
+
+    for rule in &multi_file_rules {
+      let rule_language = self.rule_store.language_of(rule.rule()).extension().clone();
+      let number_of_matches: usize = self
+        .relevant_files
+        .values()
+        .filter(|scu| {
+          !scu.is_skipped()
+            && !scu.is_disabled()
+            && scu.piranha_arguments().get_language() == rule_language
+        })
+        .map(|scu| {
+          scu
+            .get_matches(rule, &mut self.rule_store, scu.root_node(), true)
+            .len()
+        })
+        .sum();
+      info!(
+        "Found {} match(es) for the MultiFile rule {}",
+        number_of_matches,
+        rule.name()
+      );
+      if number_of_matches > 0 && rule.rule().is_feature_detection_rule() {
+        self.rule_store.add_detected_feature(rule.name());
+      }
+      if let Some(condition) = rule.rule().unsatisfied_condition(number_of_matches) {
+        return Err(format!(
+          "The MultiFile rule `{}` is expected to {condition} across all the files, but found {} match(es). No file was rewritten.",
+          rule.name(),
+          number_of_matches
+        ));
       }
     }
+    Ok(())
   }
 
   /// Instantiate Flag-cleaner
-  fn new(piranha_arguments: &PiranhaArguments) -> Self {
+  fn new(piranha_arguments: &PiranhaArguments, plugins: Vec<Box<dyn PiranhaPlugin>>) -> Self {
     let graph_rule_store = RuleStore::new(piranha_arguments);
+    let language_arguments = graph_rule_store
+      .rule_languages()
+      .iter()
+      .map(|l| {
+        (
+          l.extension().to_string(),
+          piranha_arguments.with_language(l),
+        )
+      })
+      .collect();
+    // The plugins may pre-process the files (and the edit interceptor may skip all the edits of a file),
+    // hence the files cannot be skipped
+    let negative_cache = if plugins.is_empty() && piranha_arguments.edit_interceptor().is_none() {
+      NegativeCache::new(piranha_arguments)
+    } else {
+      None
+    };
     Self {
       rule_store: graph_rule_store,
       relevant_files: HashMap::new(),
+      canonical_paths: HashMap::new(),
       piranha_arguments: piranha_arguments.clone(),
+      plugins,
+      vetoed_files: HashSet::new(),
+      skipped_files: HashMap::new(),
+      negative_cache,
+      language_arguments,
+      global_substitutions: HashMap::new(),
+      outputs: RunOutputs::default(),
+      metrics: MetricsRecorder::default(),
     }
   }
 
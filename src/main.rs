@@ -12,40 +12,184 @@
 */
 
 //! Defines the entry-point for Piranha.
-use std::{fs, time::Instant};
+use std::{fs, path::PathBuf, time::Instant};
 
-use log::{debug, info};
+use clap::{Args, CommandFactory, FromArgMatches, Subcommand};
+use itertools::Itertools;
+use log::{debug, error, info};
 use polyglot_piranha::{
-  execute_piranha, models::piranha_arguments::PiranhaArguments,
-  models::piranha_output::PiranhaOutputSummary,
+  execute_stages_with_plugins,
+  models::pack_comparison::{compare_packs, PackComparison},
+  models::piranha_arguments::PiranhaArguments,
+  models::piranha_output::{OutputFormat, PiranhaOutputSummary},
+  models::render::RenderOptions,
+  models::schema::emit_schemas,
 };
 
+/// The commands other than running Piranha (which the arguments of a run cannot be mixed with)
+#[derive(Subcommand)]
+enum Command {
+  /// Runs two versions of a rule pack over a corpus (without rewriting it) and classifies the differences of their
+  /// edits - `unchanged`, `additive` (new edits only) or `modified` (modified or removed edits). Exits with `0`,
+  /// `2` or `3` respectively, so that CI can require a version bump for the behavioral changes.
+  ComparePacks(ComparePacks),
+  /// Writes the JSON schemas of the configuration files and of the arguments (instead of running Piranha)
+  EmitSchema(EmitSchema),
+}
+
+#[derive(Args)]
+struct ComparePacks {
+  /// Directory of the configurations (`rules.toml` and `edges.toml`) of the old version of the rule pack
+  #[clap(long)]
+  pack_a: PathBuf,
+  /// Directory of the configurations of the new version of the rule pack
+  #[clap(long)]
+  pack_b: PathBuf,
+  /// Directory of the representative code the rule packs are run over
+  #[clap(long)]
+  corpus: PathBuf,
+  /// Target language (`java`, `go`, `kt`, ...)
+  #[clap(short = 'l', long)]
+  language: String,
+  /// Path to a JSON or TOML file of the substitutions of the rules
+  #[clap(long)]
+  input_substitutions_file: Option<PathBuf>,
+  /// Path of the JSON report of the comparison
+  #[clap(long)]
+  report: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct EmitSchema {
+  /// Directory where the JSON schemas of `rules.toml`, `edges.toml`, `scope_config.toml`, the
+  /// `input_substitutions_file` and the arguments are written
+  dir: PathBuf,
+}
+
 fn main() {
   let now = Instant::now();
   env_logger::init();
 
+  // The arguments of a run, or one of the other commands
+  let matches = Command::augment_subcommands(PiranhaArguments::command())
+    .subcommand_negates_reqs(true)
+    .args_conflicts_with_subcommands(true)
+    .get_matches();
+  if matches.subcommand().is_some() {
+    let command = Command::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    std::process::exit(run_command(command));
+  }
+  let args = PiranhaArguments::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
   info!("Executing Polyglot Piranha");
 
-  let args = PiranhaArguments::from_cli();
+  let args = PiranhaArguments::from_cli_arguments(&args);
 
   debug!("Piranha Arguments are \n{:#?}", args);
-  let piranha_output_summaries = execute_piranha(&args);
+  // The summaries of the completed stages are written even if the run stopped early
+  let (piranha_output_summaries, stop_reason) =
+    execute_stages_with_plugins(&args, vec![]).into_parts();
+
+  if *args.progress() {
+    // End the line of the progress bar
+    eprintln!();
+  }
+
+  if *args.verbose() {
+    print_highlighted_matches(&piranha_output_summaries);
+  }
+
+  if *args.explain() {
+    print_rendered_rewrites(&piranha_output_summaries);
+  }
 
   if let Some(path) = args.path_to_output_summary() {
-    write_output_summary(piranha_output_summaries, path);
+    write_output_summary(piranha_output_summaries, path, *args.output_format());
   }
 
   info!("Time elapsed - {:?}", now.elapsed().as_secs());
+  if let Some(stop_reason) = stop_reason {
+    error!("{stop_reason}");
+    std::process::exit(1);
+  }
 }
 
-/// Writes the output summaries to a Json file named `path_to_output_summaries` .
+/// Runs the `command`, and returns the exit code.
+fn run_command(command: Command) -> i32 {
+  match command {
+    Command::ComparePacks(args) => match run_compare_packs(args) {
+      Ok(comparison) => comparison.change().exit_code(),
+      Err(err) => {
+        error!("{err}");
+        1
+      }
+    },
+    Command::EmitSchema(EmitSchema { dir }) => match emit_schemas(&dir) {
+      Ok(paths) => {
+        info!("Wrote the JSON schemas {:?}", paths);
+        0
+      }
+      Err(err) => {
+        error!("{err}");
+        1
+      }
+    },
+  }
+}
+
+/// Compares the rule packs (see `compare_packs`), and writes the report of the comparison (if requested).
+fn run_compare_packs(args: ComparePacks) -> Result<PackComparison, String> {
+  let comparison = compare_packs(
+    &args.pack_a,
+    &args.pack_b,
+    &args.corpus,
+    &args.language,
+    args.input_substitutions_file,
+  )?;
+  info!(
+    "The edits of the rule packs are {:?} in {} file(s), suggested version bump - {}",
+    comparison.change(),
+    comparison.files().len(),
+    comparison.suggested_version_bump()
+  );
+  if let Some(path) = args.report {
+    let contents = serde_json::to_string_pretty(&comparison).map_err(|e| e.to_string())?;
+    fs::write(&path, contents).map_err(|e| format!("Could not write the report {path:?} - {e}"))?;
+  }
+  Ok(comparison)
+}
+
+/// Prints the content of each file with the code matched by each rule highlighted.
+fn print_highlighted_matches(piranha_output_summaries: &[PiranhaOutputSummary]) {
+  for summary in piranha_output_summaries {
+    for rule_name in summary.matches().iter().map(|(name, _)| name).unique() {
+      println!("{} ({rule_name})", summary.path());
+      println!("{}", summary.highlight_matches(rule_name));
+    }
+  }
+}
+
+/// Prints each rewrite of each file as a diagnostic-style block (see `render_edit`), e.g. for reviewing the edits.
+fn print_rendered_rewrites(piranha_output_summaries: &[PiranhaOutputSummary]) {
+  for summary in piranha_output_summaries {
+    for rendered in summary.render_rewrites(RenderOptions::default()) {
+      println!("{}\n{rendered}\n", summary.path());
+    }
+  }
+}
+
+/// Writes the output summaries to the file `path_to_output_summary`, in the `output_format` (Json or CSV).
 fn write_output_summary(
-  piranha_output_summaries: Vec<PiranhaOutputSummary>, path_to_json: &String,
+  piranha_output_summaries: Vec<PiranhaOutputSummary>, path_to_output_summary: &String,
+  output_format: OutputFormat,
 ) {
-  if let Ok(contents) = serde_json::to_string_pretty(&piranha_output_summaries) {
-    if fs::write(path_to_json, contents).is_ok() {
-      return;
-    }
+  let written = match output_format {
+    OutputFormat::Json => serde_json::to_string_pretty(&piranha_output_summaries)
+      .is_ok_and(|contents| fs::write(path_to_output_summary, contents).is_ok()),
+    OutputFormat::Csv => fs::File::create(path_to_output_summary)
+      .is_ok_and(|file| PiranhaOutputSummary::write_csv(&piranha_output_summaries, file).is_ok()),
+  };
+  if !written {
+    panic!("Could not write the output summary to the file - {path_to_output_summary}");
   }
-  panic!("Could not write the output summary to the file - {path_to_json}");
 }
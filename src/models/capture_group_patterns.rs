@@ -14,16 +14,18 @@ Copyright (c) 2023 Uber Technologies, Inc.
 use crate::{
   models::Validator,
   utilities::{
+    instantiate_query,
     tree_sitter_utilities::{get_ts_query_parser, number_of_errors},
     Instantiate,
   },
 };
 use pyo3::prelude::pyclass;
-use serde_derive::Deserialize;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 #[pyclass]
-#[derive(Deserialize, Debug, Clone, Default, PartialEq, Hash, Eq)]
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq, Hash, Eq)]
 pub struct CGPattern(pub String);
 
 impl CGPattern {
@@ -49,10 +51,6 @@ impl Validator for CGPattern {
 
 impl Instantiate for CGPattern {
   fn instantiate(&self, substitutions: &HashMap<String, String>) -> Self {
-    let substitutions = substitutions
-      .iter()
-      .map(|(k, v)| (k.to_string(), v.replace('\n', "\\n")))
-      .collect();
-    CGPattern::new(self.pattern().instantiate(&substitutions))
+    CGPattern::new(instantiate_query(&self.pattern(), substitutions))
   }
 }
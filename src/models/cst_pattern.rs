@@ -0,0 +1,287 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+//! Compiles the concrete syntax patterns (e.g. `exp.isTreated(:[flag])`) to the equivalent tree-sitter queries, for
+//! the rule authors unfamiliar with the S-expression queries. The snippet is parsed with the grammar of the language,
+//! and each of its nodes becomes a node of the query, where:
+//! * the leaves (e.g. the identifiers and the literals) must match exactly (i.e. have the same code),
+//! * the anonymous tokens (e.g. the operators and the punctuation) must match exactly,
+//! * the holes (`:[name]`) match any single named node, which is captured as `@name` (a hole used more than once
+//!   must match the same code each time). A hole standing for a whole statement (e.g. `{ :[body]; }`) matches any
+//!   statement, which is captured along with its terminator (e.g. the `;`).
+//!
+//! The children of a node are matched in order, without any other child in between (the whitespace and the comments
+//! are ignored). A snippet that is not a complete fragment (e.g. an expression without its `;` in Java) is also
+//! parsed within the usual contexts of the language (a statement of a method, or a member of a class).
+
+use std::{collections::HashMap, fmt};
+
+use itertools::Itertools;
+use regex::{Captures, Regex};
+use tree_sitter::Node;
+
+use crate::utilities::tree_sitter_utilities::number_of_errors;
+
+use super::{
+  default_configs::{C_SHARP, GO, JAVA, KOTLIN, SWIFT, TSX, TYPESCRIPT},
+  language::PiranhaLanguage,
+  xpath::escape,
+};
+
+/// The tag of the node matched by the whole snippet, in the compiled query
+pub const CST_TAG: &str = "cst";
+
+// The holes are parsed as these identifiers (followed by their index), which parse as a single node wherever a hole can be
+const HOLE_PLACEHOLDER: &str = "__piranha_hole_";
+
+// Where the snippet is placed in a context
+const SNIPPET: &str = "$SNIPPET";
+
+/// Why a concrete syntax pattern could not be compiled to a tree-sitter query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CstPatternError {
+  /// The snippet does not parse as a fragment of the language (standalone, or within the usual contexts)
+  Unparsable { snippet: String, language: String },
+  /// The snippet parses as several nodes (e.g. two statements), rather than a single one
+  NotASingleNode { snippet: String, kinds: Vec<String> },
+}
+
+impl fmt::Display for CstPatternError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      CstPatternError::Unparsable { snippet, language } => write!(
+        f,
+        "The concrete syntax `{snippet}` does not parse as a {language} fragment (standalone, as a statement or as a member of a class). \
+        Complete it (e.g. with its missing parentheses), or wrap it in its enclosing construct with holes for the code around it \
+        (e.g. `:[receiver].bar()` rather than `.bar()`)"
+      ),
+      CstPatternError::NotASingleNode { snippet, kinds } => write!(
+        f,
+        "The concrete syntax `{snippet}` parses as several nodes ({}), rather than a single one. \
+        Wrap it in its enclosing construct (e.g. the block or the call) with holes for the code around it, or use a rule for each node",
+        kinds.iter().map(|k| format!("`{k}`")).join(", ")
+      ),
+    }
+  }
+}
+
+impl std::error::Error for CstPatternError {}
+
+/// The names of the holes of the concrete syntax `snippet` (in order, without duplicates).
+pub(crate) fn get_cst_holes(snippet: &str) -> Vec<String> {
+  hole_regex()
+    .captures_iter(snippet)
+    .map(|c| c[1].to_string())
+    .unique()
+    .collect()
+}
+
+/// Compiles the concrete syntax `snippet` (see above) to the equivalent tree-sitter query for the `language`.
+/// The node matched by the whole snippet is tagged `@cst` (see `CST_TAG`), and each hole is captured by its name.
+pub fn cst_to_query(snippet: &str, language: &PiranhaLanguage) -> Result<String, CstPatternError> {
+  let snippet = snippet.trim();
+  let mut holes = vec![];
+  let code = hole_regex()
+    .replace_all(snippet, |c: &Captures| {
+      holes.push(c[1].to_string());
+      format!("{HOLE_PLACEHOLDER}{}", holes.len() - 1)
+    })
+    .to_string();
+
+  let mut parser = language.parser();
+  let mut error = CstPatternError::Unparsable {
+    snippet: snippet.to_string(),
+    language: language.extension().to_string(),
+  };
+  for context in contexts(language) {
+    let offset = context.find(SNIPPET).unwrap();
+    let source = context.replacen(SNIPPET, &code, 1);
+    let Some(tree) = parser.parse(&source, None) else {
+      continue;
+    };
+    if number_of_errors(&tree.root_node()) > 0 {
+      continue;
+    }
+    let (start, end) = (offset, offset + code.len());
+    let Some(node) = tree.root_node().named_descendant_for_byte_range(start, end) else {
+      continue;
+    };
+    if node.start_byte() != start || node.end_byte() != end || node.parent().is_none() {
+      let mut cursor = node.walk();
+      let kinds = node
+        .named_children(&mut cursor)
+        .filter(|n| n.start_byte() >= start && n.end_byte() <= end && !n.is_extra())
+        .map(|n| n.kind().to_string())
+        .collect_vec();
+      error = CstPatternError::NotASingleNode {
+        snippet: snippet.to_string(),
+        kinds,
+      };
+      continue;
+    }
+
+    // The (byte range of the) placeholder of each hole in the source
+    let placeholders = holes
+      .iter()
+      .enumerate()
+      .map(|(i, hole)| {
+        let placeholder = format!("{HOLE_PLACEHOLDER}{i}");
+        let position = offset + code.find(&placeholder).unwrap();
+        ((position, position + placeholder.len()), hole.to_string())
+      })
+      .collect();
+    let mut builder = QueryBuilder {
+      source: &source,
+      placeholders,
+      occurrences: HashMap::new(),
+      predicates: vec![],
+      literals: 0,
+    };
+    let pattern = builder.pattern(node);
+    return Ok(format!(
+      "(\n{}\n)",
+      std::iter::once(format!("{pattern} @{CST_TAG}"))
+        .chain(builder.predicates)
+        .join("\n")
+    ));
+  }
+  Err(error)
+}
+
+fn hole_regex() -> Regex {
+  Regex::new(r":\[([A-Za-z_]\w*)\]").unwrap()
+}
+
+// The contexts the snippet is parsed within (in order), i.e. as is, then as a statement and as a member of a class
+fn contexts(language: &PiranhaLanguage) -> Vec<&'static str> {
+  let contexts: &[&str] = match language.extension().as_str() {
+    JAVA => &[
+      "class C { void m() { $SNIPPET; } }",
+      "class C { void m() { $SNIPPET } }",
+      "class C { $SNIPPET }",
+    ],
+    C_SHARP => &[
+      "class C { void M() { $SNIPPET; } }",
+      "class C { void M() { $SNIPPET } }",
+      "class C { $SNIPPET }",
+    ],
+    KOTLIN => &["fun f() { $SNIPPET }", "class C { $SNIPPET }"],
+    SWIFT => &["func f() { $SNIPPET }", "class C { $SNIPPET }"],
+    GO => &["package p\nfunc f() { $SNIPPET }", "package p\n$SNIPPET"],
+    TYPESCRIPT | TSX => &["function f() { $SNIPPET }", "class C { $SNIPPET }"],
+    _ => &[],
+  };
+  std::iter::once(SNIPPET)
+    .chain(contexts.iter().copied())
+    .collect()
+}
+
+// Builds the query of the nodes of the parsed snippet
+struct QueryBuilder<'a> {
+  source: &'a str,
+  // The byte range of the placeholder of each hole, along with the name of the hole
+  placeholders: HashMap<(usize, usize), String>,
+  // The number of occurrences of each hole so far
+  occurrences: HashMap<String, usize>,
+  predicates: Vec<String>,
+  // The number of leaves captured so far (to compare their code)
+  literals: usize,
+}
+
+impl QueryBuilder<'_> {
+  fn pattern(&mut self, node: Node) -> String {
+    // The outermost node of the placeholder of a hole matches any named node, and so does a statement made of a
+    // hole only (e.g. `:[body];` is an expression statement, but stands for any statement)
+    if let Some(hole) = self
+      .placeholders
+      .get(&(node.start_byte(), node.end_byte()))
+      .or_else(|| self.statement_hole(node))
+    {
+      let hole = hole.to_string();
+      let occurrence = self.occurrences.entry(hole.to_string()).or_insert(0);
+      *occurrence += 1;
+      if *occurrence == 1 {
+        return format!("(_) @{hole}");
+      }
+      // The later occurrences must match the same code as the first one
+      let tag = format!("{hole}_{occurrence}");
+      self.predicates.push(format!("(#eq? @{hole} @{tag})"));
+      return format!("(_) @{tag}");
+    }
+    if !node.is_named() {
+      return format!("\"{}\"", escape(node.kind()));
+    }
+    let mut cursor = node.walk();
+    let mut children = vec![];
+    if cursor.goto_first_child() {
+      loop {
+        let child = cursor.node();
+        let is_token = !child.is_named() && !child.kind().trim().is_empty();
+        if !child.is_extra() && (child.is_named() || is_token) {
+          children.push((child, cursor.field_name()));
+        }
+        if !cursor.goto_next_sibling() {
+          break;
+        }
+      }
+    }
+    // The leaves must have the same code
+    if children.iter().all(|(child, _)| !child.is_named()) {
+      let tag = format!("{CST_TAG}_{}", self.literals);
+      self.literals += 1;
+      let code = node.utf8_text(self.source.as_bytes()).unwrap();
+      self
+        .predicates
+        .push(format!("(#eq? @{tag} \"{}\")", escape(code)));
+      return format!("({}) @{tag}", node.kind());
+    }
+    // The children are anchored to each other (i.e. they are consecutive), and to the parent if they are named
+    let mut parts = vec![];
+    if children.first().is_some_and(|(child, _)| child.is_named()) {
+      parts.push(".".to_string());
+    }
+    for (i, (child, field)) in children.iter().enumerate() {
+      if i > 0 {
+        parts.push(".".to_string());
+      }
+      let pattern = self.pattern(*child);
+      parts.push(match field {
+        Some(field) if child.is_named() => format!("{field}: {pattern}"),
+        _ => pattern,
+      });
+    }
+    if children.last().is_some_and(|(child, _)| child.is_named()) {
+      parts.push(".".to_string());
+    }
+    format!("({} {})", node.kind(), parts.join(" "))
+  }
+
+  // The hole of the `node` if it is a statement whose only named child is the placeholder of a hole
+  fn statement_hole(&self, node: Node) -> Option<&String> {
+    if !node.kind().ends_with("statement") {
+      return None;
+    }
+    let mut cursor = node.walk();
+    let (child,) = node
+      .named_children(&mut cursor)
+      .filter(|child| !child.is_extra())
+      .collect_tuple()?;
+    self
+      .placeholders
+      .get(&(child.start_byte(), child.end_byte()))
+  }
+}
+
+#[cfg(test)]
+#[path = "unit_tests/cst_pattern_test.rs"]
+mod cst_pattern_test;
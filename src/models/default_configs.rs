@@ -11,13 +11,28 @@
  limitations under the License.
 */
 
-use std::collections::{HashMap, HashSet};
+use std::{
+  collections::{HashMap, HashSet},
+  path::PathBuf,
+  sync::Arc,
+};
 
 use glob::Pattern;
 
+use crate::utilities::tree_sitter_utilities::WhitespaceSensitivity;
+
 use super::{
-  capture_group_patterns::CGPattern, filter::Filter, language::PiranhaLanguage,
-  outgoing_edges::OutgoingEdges, rule::Rule, rule_graph::RuleGraph,
+  capture_group_patterns::CGPattern,
+  edit_interceptor::EditInterceptor,
+  filter::Filter,
+  language::PiranhaLanguage,
+  outgoing_edges::OutgoingEdges,
+  package_filter::PackageFilter,
+  piranha_output::OutputFormat,
+  progress::ProgressSink,
+  rule::Rule,
+  rule_graph::RuleGraph,
+  source_code_unit::{LineEndings, OnParseError},
 };
 
 pub const JAVA: &str = "java";
@@ -25,9 +40,11 @@ pub const KOTLIN: &str = "kt";
 pub const GO: &str = "go";
 pub const PYTHON: &str = "py";
 pub const SWIFT: &str = "swift";
+pub const C_SHARP: &str = "cs";
 pub const TYPESCRIPT: &str = "ts";
 pub const TSX: &str = "tsx";
 pub const THRIFT: &str = "thrift";
+pub const PROTO: &str = "proto";
 pub const STRINGS: &str = "strings";
 pub const TS_SCHEME: &str = "scm"; // We support scheme files that contain tree-sitter query
 
@@ -39,6 +56,10 @@ pub fn default_number_of_ancestors_in_parent_scope() -> u8 {
   4
 }
 
+pub fn default_rematch_slack() -> u8 {
+  2
+}
+
 pub fn default_language() -> String {
   JAVA.to_string()
 }
@@ -91,6 +112,114 @@ pub fn default_path_to_output_summaries() -> Option<String> {
   None
 }
 
+pub fn default_output_format() -> OutputFormat {
+  OutputFormat::default()
+}
+
+pub fn default_dump_graph() -> Option<String> {
+  None
+}
+
+pub fn default_matches_output() -> Option<String> {
+  None
+}
+
+pub fn default_omit_matches_from_summary() -> bool {
+  false
+}
+
+pub fn default_metrics_output() -> Option<String> {
+  None
+}
+
+pub fn default_scope_query_cache_size() -> usize {
+  512
+}
+
+pub fn default_comment_deletion_patterns() -> Vec<String> {
+  Vec::new()
+}
+
+pub fn default_comment_deletion_in_all_files() -> bool {
+  false
+}
+
+pub fn default_strict_substitutions() -> bool {
+  false
+}
+
+pub fn default_input_substitutions_file() -> Option<PathBuf> {
+  None
+}
+
+pub fn default_cache_dir() -> Option<String> {
+  None
+}
+
+pub fn default_export_rules() -> Option<String> {
+  None
+}
+
+pub fn default_per_file_timeout_seconds() -> Option<u64> {
+  None
+}
+
+pub fn default_report_capture_ranges() -> bool {
+  false
+}
+
+pub fn default_max_file_size_bytes() -> Option<usize> {
+  None
+}
+
+pub fn default_output_patch() -> Option<String> {
+  None
+}
+
+pub fn default_output_archive() -> Option<String> {
+  None
+}
+
+pub fn default_max_propagation_depth() -> usize {
+  100
+}
+
+pub fn default_trace_propagation() -> bool {
+  false
+}
+
+pub fn default_strict_builtins() -> bool {
+  false
+}
+
+pub fn default_rules_from_comments() -> bool {
+  false
+}
+
+pub fn default_pbxproj_references_to_remove() -> Vec<String> {
+  Vec::new()
+}
+
+pub fn default_explain() -> bool {
+  false
+}
+
+pub fn default_verbose() -> bool {
+  false
+}
+
+pub fn default_max_line_length() -> usize {
+  10_000
+}
+
+pub fn default_process_long_lines() -> bool {
+  false
+}
+
+pub fn default_cleanup_only() -> bool {
+  false
+}
+
 pub fn default_piranha_language() -> PiranhaLanguage {
   PiranhaLanguage::default()
 }
@@ -115,6 +244,42 @@ pub fn default_replace() -> String {
   String::new()
 }
 
+pub fn default_replace_with_capture() -> String {
+  String::new()
+}
+
+pub fn default_directory_scope() -> String {
+  String::new()
+}
+
+pub fn default_query_file() -> String {
+  String::new()
+}
+
+pub fn default_capture_all_groups() -> bool {
+  false
+}
+
+pub fn default_query_cst() -> String {
+  String::new()
+}
+
+pub fn default_exclusive_group() -> String {
+  String::new()
+}
+
+pub fn default_dry() -> bool {
+  false
+}
+
+pub fn default_template_params() -> Vec<String> {
+  Vec::new()
+}
+
+pub fn default_prefilter() -> bool {
+  true
+}
+
 pub fn default_rule_graph_map() -> HashMap<String, Vec<(String, String)>> {
   HashMap::new()
 }
@@ -163,6 +328,18 @@ pub(crate) fn default_sibling_count() -> u32 {
   u32::MAX
 }
 
+pub(crate) fn default_parent_kind() -> String {
+  String::new()
+}
+
+pub(crate) fn default_max_enclosing_node_distance() -> u32 {
+  u32::MAX
+}
+
+pub(crate) fn default_negated() -> bool {
+  false
+}
+
 pub(crate) fn default_enclosing_node() -> CGPattern {
   CGPattern::new(String::new())
 }
@@ -183,6 +360,58 @@ pub(crate) fn default_is_seed_rule() -> bool {
   true
 }
 
+pub(crate) fn default_rule_scope() -> String {
+  String::new()
+}
+
+pub(crate) fn default_exactly_one_match() -> bool {
+  false
+}
+
+pub(crate) fn default_delete_empty_enclosing() -> bool {
+  false
+}
+
+pub(crate) fn default_package_filter() -> Option<PackageFilter> {
+  None
+}
+
+pub(crate) fn default_requires_features() -> Vec<String> {
+  Vec::new()
+}
+
+pub(crate) fn default_rule_language() -> String {
+  String::new()
+}
+
 pub(crate) fn default_allow_dirty_ast() -> bool {
   false
 }
+
+pub(crate) fn default_whitespace_sensitivity() -> WhitespaceSensitivity {
+  WhitespaceSensitivity::default()
+}
+
+pub fn default_on_parse_error() -> OnParseError {
+  OnParseError::default()
+}
+
+pub fn default_normalize_line_endings() -> LineEndings {
+  LineEndings::default()
+}
+
+pub fn default_edit_interceptor() -> Option<Arc<dyn EditInterceptor>> {
+  None
+}
+
+pub fn default_progress_sink() -> Option<Arc<dyn ProgressSink>> {
+  None
+}
+
+pub fn default_stage() -> Option<String> {
+  None
+}
+
+pub fn default_progress() -> bool {
+  false
+}
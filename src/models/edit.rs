@@ -11,44 +11,94 @@ Copyright (c) 2023 Uber Technologies, Inc.
  limitations under the License.
 */
 
-use std::fmt;
+use std::{collections::HashMap, fmt};
 
 use colored::Colorize;
 use getset::{Getters, MutGetters};
-use log::{debug, trace};
+use itertools::Itertools;
+use log::{debug, trace, warn};
 use serde_derive::{Deserialize, Serialize};
-use tree_sitter::{Node, Range};
+use serde_json::json;
+use tree_sitter::{Node, Parser, Range};
 
 use super::{
-  matches::Match, rule::InstantiatedRule, rule_store::RuleStore, source_code_unit::SourceCodeUnit,
+  matches::{self, Match},
+  rule::InstantiatedRule,
+  rule_graph::PARENT,
+  rule_store::RuleStore,
+  skipped_match::{SkipReason, SkippedMatch},
+  source_code_unit::SourceCodeUnit,
 };
 use crate::utilities::{
-  gen_py_str_methods,
-  tree_sitter_utilities::{get_context, get_node_for_range},
-  Instantiate,
+  gen_py_str_methods, instantiate_replacement,
+  tree_sitter_utilities::{
+    get_context, get_match_for_query, get_node_for_range, get_range_for_offsets,
+    get_tree_sitter_edit, number_of_errors,
+  },
+  truncate_snippet, Instantiate, MAX_DISPLAYED_SNIPPET_LENGTH,
 };
 use pyo3::{prelude::pyclass, pymethods};
 
 #[derive(Serialize, Debug, Clone, Getters, MutGetters, Deserialize)]
 #[pyclass]
-pub(crate) struct Edit {
+pub struct Edit {
   // The match representing the target site of the edit
   #[pyo3(get)]
   #[get = "pub"]
-  #[get_mut]
+  #[get_mut = "pub(crate)"]
   p_match: Match,
   // The string to replace the substring encompassed by the match
   #[pyo3(get)]
   #[get = "pub"]
+  #[get_mut = "pub(crate)"]
   replacement_string: String,
   // The rule used for creating this match-replace
   #[pyo3(get)]
   #[get = "pub"]
   matched_rule: String,
+  // The (previously applied) rule that triggered this edit through an edge (`None` for seed rules)
+  #[pyo3(get)]
+  #[get = "pub"]
+  #[serde(default)]
+  triggered_by: Option<TriggeredBy>,
+  // The substitutions for the holes of the matched rule (i.e. the values it was instantiated with)
+  #[pyo3(get)]
+  #[get = "pub"]
+  #[serde(default)]
+  rule_substitutions: HashMap<String, String>,
 }
 
 gen_py_str_methods!(Edit);
 
+/// Captures why a rule was applied, i.e. the previously applied rule and the scope of the edge that lead to it.
+#[derive(Serialize, Debug, Clone, Getters, Deserialize)]
+#[pyclass]
+pub struct TriggeredBy {
+  // Name of the rule whose application triggered the rule
+  #[pyo3(get)]
+  #[get = "pub"]
+  rule: String,
+  // Scope of the edge (e.g. `Parent`, `Global`, `Method`)
+  #[pyo3(get)]
+  #[get = "pub"]
+  scope: String,
+  // Range of the triggering match (or edit)
+  #[pyo3(get)]
+  range: matches::Range,
+}
+
+gen_py_str_methods!(TriggeredBy);
+
+impl TriggeredBy {
+  pub(crate) fn new(rule: &str, scope: &str, range: Range) -> Self {
+    Self {
+      rule: rule.to_string(),
+      scope: scope.to_string(),
+      range: matches::Range::from(range),
+    }
+  }
+}
+
 impl Edit {
   pub(crate) fn new(
     p_match: Match, replacement_string: String, matched_rule: String, code: &str,
@@ -57,15 +107,60 @@ impl Edit {
       p_match,
       replacement_string,
       matched_rule,
+      triggered_by: None,
+      rule_substitutions: HashMap::new(),
     };
     if edit.is_delete() {
       edit.p_match_mut().expand_to_associated_matches(code);
     }
     edit
   }
+
+  /// Edit replacing the code between the byte offsets `start_byte` and `end_byte` with the `replacement`
+  /// (i.e. an edit that is not driven by a rule).
+  pub(crate) fn replace_offsets(
+    code: &str, start_byte: usize, end_byte: usize, replacement: &str,
+  ) -> Self {
+    Self {
+      p_match: Match::new(
+        code[start_byte..end_byte].to_string(),
+        get_range_for_offsets(code, start_byte, end_byte),
+        HashMap::new(),
+      ),
+      replacement_string: replacement.to_string(),
+      matched_rule: "Edit At Offset".to_string(),
+      triggered_by: None,
+      rule_substitutions: HashMap::new(),
+    }
+  }
+
+  /// Edit replacing the code between the byte offsets `start_byte` and `end_byte` with the `replacement` for the
+  /// pseudo-rule `rule` (e.g. the occurrences of a literal, see `SourceCodeUnit::replace_all_literals`).
+  pub(crate) fn replace_offsets_for_rule(
+    code: &str, start_byte: usize, end_byte: usize, replacement: &str, rule: &str,
+  ) -> Self {
+    Self {
+      matched_rule: rule.to_string(),
+      ..Self::replace_offsets(code, start_byte, end_byte, replacement)
+    }
+  }
+
+  /// Edit deleting the code between the byte offsets `start_byte` and `end_byte` for the pseudo-rule `rule`
+  /// (e.g. a comment matching the `comment_deletion_patterns`).
+  pub(crate) fn delete_offsets(code: &str, start_byte: usize, end_byte: usize, rule: &str) -> Self {
+    Self::replace_offsets_for_rule(code, start_byte, end_byte, "", rule)
+  }
+
+  /// The same edit with the given `replacement` (e.g. for an `EditInterceptor` to modify the edits).
+  pub fn with_replacement(&self, replacement: &str) -> Self {
+    Self {
+      replacement_string: replacement.to_string(),
+      ..self.clone()
+    }
+  }
+
   #[cfg(test)]
   pub(crate) fn delete_range(code: &str, replacement_range: Range) -> Self {
-    use std::collections::HashMap;
     Self {
       p_match: Match::new(
         code[replacement_range.start_byte..replacement_range.end_byte].to_string(),
@@ -74,19 +169,56 @@ impl Edit {
       ),
       replacement_string: String::new(),
       matched_rule: "Delete Range".to_string(),
+      triggered_by: None,
+      rule_substitutions: HashMap::new(),
     }
   }
 
   pub(crate) fn is_delete(&self) -> bool {
     self.replacement_string.trim().is_empty()
   }
+
+  /// The LSP (3.17) `WorkspaceEdit` applying this edit to the file `file_uri`, i.e.
+  /// `{"changes": {file_uri: [TextEdit]}}` (e.g. for a language server built on top of Piranha).
+  /// The range is the one in the `code` the edit is applied to, with 0-based lines and the characters counted in
+  /// UTF-16 code units, i.e. the default `utf-16` position encoding of LSP.
+  pub fn to_lsp_workspace_edit(&self, code: &str, file_uri: &str) -> serde_json::Value {
+    let range = self.p_match().range();
+    json!({
+      "changes": {
+        file_uri: [{
+          "range": {
+            "start": {
+              "line": range.start_point.row,
+              "character": utf16_column(code, range.start_byte, range.start_point.column),
+            },
+            "end": {
+              "line": range.end_point.row,
+              "character": utf16_column(code, range.end_byte, range.end_point.column),
+            },
+          },
+          "newText": self.replacement_string(),
+        }]
+      }
+    })
+  }
+}
+
+/// The column (in UTF-16 code units) of the byte offset `byte` of the `code`, at the (byte) `column` of its line.
+fn utf16_column(code: &str, byte: usize, column: usize) -> usize {
+  code
+    .get(byte - column..byte)
+    .map_or(column, |prefix| prefix.encode_utf16().count())
 }
 
 impl fmt::Display for Edit {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     let replace_range: Range = self.p_match().range();
-    let replacement = self.replacement_string();
-    let replaced_code_snippet = self.p_match().matched_string();
+    let replacement = truncate_snippet(self.replacement_string(), MAX_DISPLAYED_SNIPPET_LENGTH);
+    let replaced_code_snippet = truncate_snippet(
+      self.p_match().matched_string(),
+      MAX_DISPLAYED_SNIPPET_LENGTH,
+    );
     let mut edit_kind = "Delete code".red();
     let mut replacement_snippet_fmt = format!("{} ", replaced_code_snippet.italic());
     if !replacement.is_empty() {
@@ -101,13 +233,66 @@ impl fmt::Display for Edit {
   }
 }
 
+/// An inconsistency in the (byte) ranges of the rewrites of a source code unit (see `SourceCodeUnit::validate_edits`).
+/// The `index` is the position of the offending edit in the rewrites.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditConflict {
+  /// The range of the edit is not within the code it is applied to (of `code_length` bytes)
+  OutOfBounds {
+    index: usize,
+    start_byte: usize,
+    end_byte: usize,
+    code_length: usize,
+  },
+  /// The range of the edit partially overlaps the code produced by a previous edit (at `previous_index`),
+  /// i.e. it neither encloses nor is enclosed by it
+  Overlap { index: usize, previous_index: usize },
+  /// The range of the edit does not start or end at a character boundary, i.e. the edit would produce invalid UTF-8
+  InvalidUtf8 {
+    index: usize,
+    start_byte: usize,
+    end_byte: usize,
+  },
+}
+
+impl fmt::Display for EditConflict {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      EditConflict::OutOfBounds {
+        index,
+        start_byte,
+        end_byte,
+        code_length,
+      } => write!(
+        f,
+        "Edit #{index}: the range {start_byte}..{end_byte} is out of the bounds of the code ({code_length} bytes)"
+      ),
+      EditConflict::Overlap {
+        index,
+        previous_index,
+      } => write!(
+        f,
+        "Edit #{index}: the range partially overlaps the code produced by the edit #{previous_index}"
+      ),
+      EditConflict::InvalidUtf8 {
+        index,
+        start_byte,
+        end_byte,
+      } => write!(
+        f,
+        "Edit #{index}: the range {start_byte}..{end_byte} does not start or end at a character boundary"
+      ),
+    }
+  }
+}
+
 // Implements instance methods related to getting edits for rule(s)
 impl SourceCodeUnit {
   // Apply all the `rules` to the node, parent, grand parent and great grand parent.
   // Short-circuit on the first match.
   pub(crate) fn get_edit_for_context(
     &self, previous_edit_start: usize, previous_edit_end: usize, rules_store: &mut RuleStore,
-    rules: &Vec<InstantiatedRule>,
+    rules: &[InstantiatedRule],
   ) -> Option<Edit> {
     let number_of_ancestors_in_parent_scope = *self
       .piranha_arguments()
@@ -140,20 +325,164 @@ impl SourceCodeUnit {
     &self, rule: &InstantiatedRule, rule_store: &mut RuleStore, node: Node, recursive: bool,
   ) -> Option<Edit> {
     // Get all matches for the query in the given scope `node`.
-
-    return self
-      .get_matches(rule, rule_store, node, recursive)
-      .first()
-      .map(|p_match| {
-        let replacement_string = rule.replace().instantiate(p_match.matches());
-        let edit = Edit::new(
-          p_match.clone(),
-          replacement_string,
+    for mut p_match in self.get_matches(rule, rule_store, node, recursive) {
+      // Skip the matches for which the replacement references unbound tags, instead of emitting them as is
+      let unbound_tags = rule_store.get_unbound_tags(rule, p_match.matches());
+      if !unbound_tags.is_empty() {
+        warn!(
+          "Skipped a match of the rule {} in {:?}, since its replacement references the unbound tag(s) {}",
           rule.name(),
-          self.code(),
+          self.path(),
+          unbound_tags.iter().map(|t| format!("`@{t}`")).join(", ")
+        );
+        rule_store.report_skipped_match(
+          self.path(),
+          SkippedMatch::new(&rule.name(), SkipReason::UnboundTag, p_match.range()),
+        );
+        continue;
+      }
+      let replacement_string = if rule.rule().replace_with_capture().is_empty() {
+        // The regexes of the `%{tag:regex}` placeholders are checked when the rule is validated
+        instantiate_replacement(&rule.replace(), p_match.matches())
+          .unwrap_or_else(|_| rule.replace().instantiate(p_match.matches()))
+      } else {
+        // The captured code is copied verbatim, rather than substituted in a template (which may alter it)
+        let Some(captured_code) = p_match
+          .capture_byte_range(rule.rule().replace_with_capture())
+          .and_then(|range| self.code().get(range))
+          .map(str::to_string)
+        else {
+          warn!(
+            "Skipped a match of the rule {} in {:?}, since the tag `@{}` (`replace_with_capture`) did not capture any node",
+            rule.name(),
+            self.path(),
+            rule.rule().replace_with_capture()
+          );
+          rule_store.report_skipped_match(
+            self.path(),
+            SkippedMatch::new(&rule.name(), SkipReason::UnboundTag, p_match.range()),
+          );
+          continue;
+        };
+        if !*self.piranha_arguments().report_capture_ranges() {
+          p_match.capture_ranges_mut().clear();
+        }
+        captured_code
+      };
+      // Skip the matches whose replacement is equivalent to the matched code (i.e. no-op edits)
+      if self.are_equivalent_snippets(p_match.matched_string(), &replacement_string) {
+        rule_store.report_skipped_match(
+          self.path(),
+          SkippedMatch::new(&rule.name(), SkipReason::NoOpEdit, p_match.range()),
         );
-        trace!("Rewrite found : {:#?}", edit);
-        edit
-      });
+        continue;
+      }
+      let mut edit = Edit::new(p_match, replacement_string, rule.name(), self.code());
+      if self.is_vetoed_edit(&edit) {
+        continue;
+      }
+      edit.triggered_by = rule.triggered_by().clone();
+      edit.rule_substitutions = rule.substitutions().clone();
+      trace!("Rewrite found : {}", edit);
+      return Some(edit);
+    }
+    None
+  }
+
+  /// Gets the edit deleting the node enclosing the `replace_range` of the previous edit, that became empty
+  /// (i.e. has no named children) after the `rule` deleted some code there (see `Rule::delete_empty_enclosing`).
+  ///
+  /// If the empty node cannot be deleted on its own (e.g. it is the body of an `if` statement), its parent is deleted instead.
+  /// This is only done when the empty node is the last named child of the parent,
+  /// and no other child of the parent is of the same kind (e.g. the `then` and `else` blocks).
+  /// Returns `None` if the node to be deleted delimits a scope (like a method or a class), or is the body of such a node.
+  pub(crate) fn get_edit_for_empty_enclosing_node(
+    &self, replace_range: Range, rule: &InstantiatedRule, rules_store: &mut RuleStore,
+    parser: &mut Parser,
+  ) -> Option<Edit> {
+    // The smallest node strictly enclosing the (deleted) `replace_range`
+    let position = replace_range.start_byte;
+    let mut node = self
+      .root_node()
+      .descendant_for_byte_range(position, position)?;
+    while !(node.start_byte() < position && position < node.end_byte()) {
+      node = node.parent()?;
+    }
+    if node.named_child_count() > 0 {
+      return None;
+    }
+    loop {
+      let parent = node.parent()?;
+      if self.is_scope_node(node, rules_store) || self.is_scope_node(parent, rules_store) {
+        return None;
+      }
+      let p_match = Match::new(
+        self.code()[node.start_byte()..node.end_byte()].to_string(),
+        node.range(),
+        HashMap::new(),
+      );
+      let mut edit = Edit::new(p_match, String::new(), rule.name(), self.code());
+      if self.can_be_deleted(&edit, parent, parser) {
+        edit.triggered_by = Some(TriggeredBy::new(&rule.name(), PARENT, replace_range));
+        return Some(edit);
+      }
+      let mut named_children = parent
+        .named_children(&mut parent.walk())
+        .collect::<Vec<_>>();
+      let is_last_named_child = named_children.pop() == Some(node);
+      if !is_last_named_child || named_children.iter().any(|c| c.kind() == node.kind()) {
+        return None;
+      }
+      node = parent;
+    }
+  }
+
+  /// Checks if the `node` matches the enclosing node of any scope (like a method or a class) of the language
+  fn is_scope_node(&self, node: Node, rules_store: &mut RuleStore) -> bool {
+    self
+      .piranha_arguments()
+      .language()
+      .scopes()
+      .iter()
+      .flat_map(|scope| scope.rules())
+      .any(|scope_rule| {
+        get_match_for_query(
+          &node,
+          self.code(),
+          rules_store.query(
+            scope_rule.enclosing_node(),
+            self.piranha_arguments().language(),
+          ),
+          false,
+        )
+        .is_some()
+      })
+  }
+
+  /// Checks if the code deleted by the `edit` can be deleted on its own, i.e. the resulting code is syntactically correct
+  /// and the `parent` retains the rest of its named children (e.g. the `if` statement does not capture the next statement as its body).
+  fn can_be_deleted(&self, edit: &Edit, parent: Node, parser: &mut Parser) -> bool {
+    let (new_code, ts_edit) = get_tree_sitter_edit(self.code().to_string(), edit);
+    // Reparse incrementally, i.e. only the part of the tree touched by the edit
+    let mut old_tree = self.ast().clone();
+    old_tree.edit(&ts_edit);
+    let Some(tree) = parser.parse(&new_code, Some(&old_tree)) else {
+      return false;
+    };
+    if number_of_errors(&tree.root_node()) > number_of_errors(&self.root_node()) {
+      return false;
+    }
+    let deleted_bytes = ts_edit.old_end_byte - ts_edit.new_end_byte;
+    let updated_parent = get_node_for_range(
+      tree.root_node(),
+      parent.start_byte(),
+      parent.end_byte() - deleted_bytes,
+    );
+    updated_parent.kind() == parent.kind()
+      && updated_parent.named_child_count() + 1 == parent.named_child_count()
   }
 }
+
+#[cfg(test)]
+#[path = "unit_tests/edit_test.rs"]
+mod edit_test;
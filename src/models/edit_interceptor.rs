@@ -0,0 +1,38 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use std::{fmt::Debug, path::Path};
+
+use super::edit::Edit;
+
+/// What to do with an edit found by Piranha (see `EditInterceptor`).
+#[derive(Debug, Clone)]
+pub enum EditDecision {
+  /// The edit is applied as is
+  Apply,
+  /// The edit is not applied. The same rule is not applied again at the same range of the file
+  Skip,
+  /// The given edit is applied instead
+  Replace(Box<Edit>),
+}
+
+/// A hook for library consumers to enforce their own policies on the edits, e.g. never editing the generated files,
+/// or adding a tracking comment to the replacements.
+///
+/// The interceptor is passed via `PiranhaArguments::edit_interceptor` (it is not available from the command line or Python),
+/// and is consulted for each edit before it is applied to the file, including the deletions of the empty enclosing nodes
+/// and the edits applied through `apply_edit_at_offset`.
+pub trait EditInterceptor: Send + Sync + Debug {
+  /// Decides whether the `edit` of the file `unit_path` is applied, skipped or replaced by another edit.
+  fn on_edit(&self, unit_path: &Path, edit: &Edit) -> EditDecision;
+}
@@ -19,7 +19,8 @@ use getset::Getters;
 use itertools::Itertools;
 use pyo3::prelude::{pyclass, pymethods};
 
-use serde_derive::Deserialize;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
 use tree_sitter::Node;
 
 use crate::utilities::{
@@ -28,9 +29,18 @@ use crate::utilities::{
 };
 
 use super::{
-  capture_group_patterns::CGPattern, default_configs::default_child_count,
-  default_configs::default_sibling_count, rule::InstantiatedRule, rule_store::RuleStore,
-  source_code_unit::SourceCodeUnit, Validator,
+  capture_group_patterns::CGPattern,
+  default_configs::default_child_count,
+  default_configs::default_max_enclosing_node_distance,
+  default_configs::default_negated,
+  default_configs::default_parent_kind,
+  default_configs::default_sibling_count,
+  language::PiranhaLanguage,
+  rule::InstantiatedRule,
+  rule_store::RuleStore,
+  source_code_unit::SourceCodeUnit,
+  xpath::{xpath_to_queries, XPathError},
+  Validator,
 };
 
 use crate::utilities::Instantiate;
@@ -40,7 +50,10 @@ use super::default_configs::{
   default_enclosing_node, default_not_contains_queries, default_not_enclosing_node,
 };
 
-#[derive(Deserialize, Debug, Clone, Hash, PartialEq, Eq, Getters, Builder)]
+#[derive(
+  Serialize, Deserialize, JsonSchema, Debug, Clone, Hash, PartialEq, Eq, Getters, Builder,
+)]
+#[serde(deny_unknown_fields)]
 #[pyclass]
 #[builder(build_fn(name = "create"))]
 pub struct Filter {
@@ -104,6 +117,69 @@ pub struct Filter {
   #[serde(default = "default_sibling_count")]
   #[pyo3(get)]
   sibling_count: u32,
+
+  // kind of the direct parent of the primary matched node (if not empty), e.g. `expression_statement`
+  #[builder(default = "default_parent_kind()")]
+  #[get = "pub"]
+  #[serde(default = "default_parent_kind")]
+  #[pyo3(get)]
+  parent_kind: String,
+
+  // maximum number of named ancestors of the primary matched node up to the node matching `enclosing_node`
+  // (or `outermost_enclosing_node`), e.g. 1 if it is the parent of the primary matched node
+  #[builder(default = "default_max_enclosing_node_distance()")]
+  #[get = "pub"]
+  #[serde(default = "default_max_enclosing_node_distance")]
+  #[pyo3(get)]
+  max_enclosing_node_distance: u32,
+
+  // inverts the filter, i.e. the filter is satisfied iff the above conditions are NOT satisfied
+  #[builder(default = "default_negated()")]
+  #[get = "pub"]
+  #[serde(default = "default_negated")]
+  #[pyo3(get)]
+  negated: bool,
+}
+
+impl Filter {
+  /// The filter requiring the matched node (or rather the `enclosing_node`, once set) to contain a node selected by
+  /// the XPath-like `xpath` (see `xpath_to_query`), e.g. `//method_declaration[@name='setUp']` for a class declaring
+  /// a `setUp` method. The translated query can also be used as the `enclosing_node` of a filter, to require the
+  /// matched node to be within such a node.
+  ///
+  /// A descendant axis (`//`) between two steps nests the filter: the steps before it are the `enclosing_node`,
+  /// which should contain a node selected by the steps after it. E.g. with
+  /// `//class_declaration//method_declaration[@name='setUp']`, the matched node should be within a class declaring a
+  /// `setUp` method (anywhere in the class, i.e. a descendant of the first step of the `enclosing_node`).
+  pub fn from_xpath(xpath: &str, language: &PiranhaLanguage) -> Result<Filter, XPathError> {
+    let queries = xpath_to_queries(xpath, language)?;
+    let mut filter_builder = FilterBuilder::default();
+    match queries.as_slice() {
+      [query] => filter_builder.contains(CGPattern::new(query.to_string())),
+      [ancestor_query, query] => filter_builder
+        .enclosing_node(CGPattern::new(ancestor_query.to_string()))
+        .contains(CGPattern::new(query.to_string())),
+      _ => {
+        return Err(XPathError::Unsupported(
+          "a filter supports at most one descendant axis (`//`) between two steps".to_string(),
+        ))
+      }
+    };
+    Ok(filter_builder.build())
+  }
+
+  /// Returns all the patterns of the filter (i.e. `enclosing_node`, `outermost_enclosing_node`, `not_enclosing_node`, `contains` and `not_contains`).
+  pub(crate) fn patterns(&self) -> Vec<&CGPattern> {
+    [
+      &self.enclosing_node,
+      &self.outermost_enclosing_node,
+      &self.not_enclosing_node,
+      &self.contains,
+    ]
+    .into_iter()
+    .chain(self.not_contains.iter())
+    .collect()
+  }
 }
 
 #[pymethods]
@@ -113,9 +189,11 @@ impl Filter {
     enclosing_node: Option<String>, outermost_enclosing_node: Option<String>,
     not_enclosing_node: Option<String>, not_contains: Option<Vec<String>>,
     contains: Option<String>, at_least: Option<u32>, at_most: Option<u32>,
-    child_count: Option<u32>, sibling_count: Option<u32>,
+    child_count: Option<u32>, sibling_count: Option<u32>, parent_kind: Option<String>,
+    max_enclosing_node_distance: Option<u32>, negated: Option<bool>,
   ) -> Self {
-    FilterBuilder::default()
+    let mut filter_builder = FilterBuilder::default();
+    filter_builder
       .enclosing_node(CGPattern::new(enclosing_node.unwrap_or_default()))
       .outermost_enclosing_node(CGPattern::new(outermost_enclosing_node.unwrap_or_default()))
       .not_enclosing_node(CGPattern::new(not_enclosing_node.unwrap_or_default()))
@@ -131,7 +209,14 @@ impl Filter {
       .at_most(at_most.unwrap_or(default_contains_at_most()))
       .child_count(child_count.unwrap_or(default_child_count()))
       .sibling_count(sibling_count.unwrap_or(default_sibling_count()))
-      .build()
+      .parent_kind(parent_kind.unwrap_or_default())
+      .max_enclosing_node_distance(
+        max_enclosing_node_distance.unwrap_or(default_max_enclosing_node_distance()),
+      );
+    if negated.unwrap_or(default_negated()) {
+      filter_builder.negate();
+    }
+    filter_builder.build()
   }
   gen_py_str_methods!();
 }
@@ -196,11 +281,26 @@ impl Validator for Filter {
       return Err("The child/sibling count operator is not compatible with (not) enclosing node and (not) contains operator".to_string());
     }
 
+    if *self.max_enclosing_node_distance() != default_max_enclosing_node_distance()
+      && *self.enclosing_node() == default_enclosing_node()
+      && *self.outermost_enclosing_node() == default_enclosing_node()
+    {
+      return Err(
+        "Invalid Filter Argument. `max_enclosing_node_distance` is set, but `enclosing_node` (or `outermost_enclosing_node`) is empty !!!"
+          .to_string(),
+      );
+    }
+
     Ok(())
   }
 }
 
 impl FilterBuilder {
+  /// Inverts the filter being built (i.e. sets `negated`)
+  pub fn negate(&mut self) -> &mut Self {
+    self.negated(true)
+  }
+
   /// Builds Filter from FilterBuilder
   /// * create Filter from the builder
   /// * validates new argument combinations
@@ -231,6 +331,13 @@ impl FilterBuilder {
 /// 'at_least' and 'at_most' specify the inclusive range for the count of matches 'contains' queries should find within
 /// the 'enclosing_node'. These parameters provide control over the desired quantity of matches.
 ///
+/// 'parent_kind' is an optional parameter that specifies the kind of the direct parent of the matched node.
+///
+/// 'max_enclosing_node_distance' is an optional parameter that specifies the maximum number of named ancestors of the
+/// matched node up to the 'enclosing_node' (0 if the 'enclosing_node' is the matched node itself).
+///
+/// 'negated' is an optional parameter that inverts the filter (i.e. the filter is satisfied iff the above conditions are not).
+///
 /// Usage:
 ///
 /// ```
@@ -258,7 +365,7 @@ impl FilterBuilder {
 /// ```
 ///
 macro_rules! filter {
-  ($(enclosing_node = $enclosing_node:expr)? $(, outermost_enclosing_node=$outermost_enclosing_node:expr)? $(, not_enclosing_node=$not_enclosing_node:expr)? $(, not_contains= [$($q:expr,)*])? $(, contains= $p:expr)? $(, at_least=$min:expr)? $(, at_most=$max:expr)? $(, child_count=$nChildren:expr)? $(, sibling_count=$nSibling:expr)?) => {
+  ($(enclosing_node = $enclosing_node:expr)? $(, outermost_enclosing_node=$outermost_enclosing_node:expr)? $(, not_enclosing_node=$not_enclosing_node:expr)? $(, not_contains= [$($q:expr,)*])? $(, contains= $p:expr)? $(, at_least=$min:expr)? $(, at_most=$max:expr)? $(, child_count=$nChildren:expr)? $(, sibling_count=$nSibling:expr)? $(, parent_kind=$parent_kind:expr)? $(, max_enclosing_node_distance=$distance:expr)? $(, negated=$negated:expr)?) => {
     $crate::models::filter::FilterBuilder::default()
       $(.enclosing_node($crate::models::capture_group_patterns::CGPattern::new($enclosing_node.to_string())))?
       $(.outermost_enclosing_node($crate::models::capture_group_patterns::CGPattern::new($outermost_enclosing_node.to_string())))?
@@ -269,6 +376,9 @@ macro_rules! filter {
       $(.at_most($max))?
       $(.child_count($nChildren))?
       $(.sibling_count($nSibling))?
+      $(.parent_kind($parent_kind.to_string()))?
+      $(.max_enclosing_node_distance($distance))?
+      $(.negated($negated))?
       .build()
   };
 }
@@ -296,6 +406,9 @@ impl Instantiate for Filter {
       at_most: self.at_most,
       child_count: self.child_count,
       sibling_count: self.sibling_count,
+      parent_kind: self.parent_kind.clone(),
+      max_enclosing_node_distance: self.max_enclosing_node_distance,
+      negated: self.negated,
     }
   }
 }
@@ -307,6 +420,7 @@ impl SourceCodeUnit {
     rule_store: &mut RuleStore,
   ) -> bool {
     let mut updated_substitutions = self.piranha_arguments().input_substitutions();
+    updated_substitutions.extend(rule.substitutions().clone());
     updated_substitutions.extend(substitutions.clone());
     rule
       .filters()
@@ -320,20 +434,38 @@ impl SourceCodeUnit {
   /// (i) `enclosing_node`, the node to inspect, optional. If not provided we check whether the contains or non_contains are satisfied in the current node.
   /// (ii) `not_enclosing_node`, optional query that no ancestor of the primary match should match,
   /// (iii) `not_contains` and `contains`, optional queries that should not and should match within the `enclosing_node`,
-  /// (iv) `at_least` and `at_most`, optional parameters indicating the acceptable range of matches for `contains` within the `enclosing_node`.
+  /// (iv) `at_least` and `at_most`, optional parameters indicating the acceptable range of matches for `contains` within the `enclosing_node`,
+  /// (v) `parent_kind`, the optional kind of the direct parent of the `node`,
+  /// (vi) `max_enclosing_node_distance`, the optional maximum number of named ancestors of the `node` up to the `enclosing_node`.
   ///
   /// The function identifies the `enclosing_node` by traversing the ancestors of the `node`. Within this node:
   /// (i) if `not_contains` is provided, it ensures no sub-tree matches any of these queries,
   /// (ii) if `contains` is provided, it ensures the number sub-trees matching `contains` fall within the specified range.
   ///
   /// If these conditions hold, the function returns true, indicating the `node` meets the `filter`'s criteria.
+  /// When the `filter` is `negated`, the result is inverted.
   fn _check(
     &self, filter: Filter, node: Node, rule_store: &mut RuleStore,
     substitutions: &HashMap<String, String>,
+  ) -> bool {
+    let negated = *filter.negated();
+    self._is_satisfied(filter, node, rule_store, substitutions) != negated
+  }
+
+  /// Determines if the given `node` meets the conditions specified by the `filter` (ignoring `negated`).
+  fn _is_satisfied(
+    &self, filter: Filter, node: Node, rule_store: &mut RuleStore,
+    substitutions: &HashMap<String, String>,
   ) -> bool {
     let mut node_to_check = node;
     let instantiated_filter = filter.instantiate(substitutions);
 
+    if !filter.parent_kind().is_empty()
+      && node.parent().map(|p| p.kind()) != Some(filter.parent_kind().as_str())
+    {
+      return false;
+    }
+
     if *filter.child_count() != default_child_count() {
       return node.named_child_count() == (*filter.child_count() as usize);
     }
@@ -366,10 +498,35 @@ impl SourceCodeUnit {
       }
     }
 
+    if *filter.max_enclosing_node_distance() != default_max_enclosing_node_distance()
+      && !Self::_is_within_distance(node, node_to_check, *filter.max_enclosing_node_distance())
+    {
+      return false;
+    }
+
     self._check_filter_not_contains(&instantiated_filter, rule_store, &node_to_check)
       && self._check_filter_contains(&instantiated_filter, rule_store, &node_to_check)
   }
 
+  /// Checks if the `ancestor` (e.g. the node matching `enclosing_node`) is at most `max_distance` named ancestors above the `node`.
+  /// The distance is 0 if the `ancestor` is the `node` itself (`enclosing_node` also considers the matched node).
+  fn _is_within_distance(node: Node, ancestor: Node, max_distance: u32) -> bool {
+    let mut current_node = node;
+    let mut distance = 0;
+    while current_node.byte_range() != ancestor.byte_range()
+      || current_node.kind() != ancestor.kind()
+    {
+      let Some(parent) = current_node.parent() else {
+        return false;
+      };
+      if parent.is_named() {
+        distance += 1;
+      }
+      current_node = parent;
+    }
+    distance <= max_distance
+  }
+
   /// Check if the `node` does not have any ancestor that matches the `not_enclosing_node` query
   fn _check_not_enclosing_node(
     &self, rule_store: &mut RuleStore, node_to_check: Node, instantiated_filter: &Filter,
@@ -415,9 +572,12 @@ impl SourceCodeUnit {
     }
 
     while let Some(parent) = current_node.parent() {
-      if let Some(p_match) =
-        get_match_for_query(&parent, self.code(), rule_store.query(ts_query), false)
-      {
+      if let Some(p_match) = get_match_for_query(
+        &parent,
+        self.code(),
+        rule_store.query(ts_query, self.piranha_arguments().language()),
+        false,
+      ) {
         let matched_ancestor = get_node_for_range(
           self.root_node(),
           p_match.range().start_byte,
@@ -441,7 +601,7 @@ impl SourceCodeUnit {
     }
 
     // Retrieve all matches within the ancestor node
-    let contains_query = &rule_store.query(filter.contains());
+    let contains_query = &rule_store.query(filter.contains(), self.piranha_arguments().language());
     let matches = get_all_matches_for_query(
       ancestor,
       self.code().to_string(),
@@ -463,7 +623,7 @@ impl SourceCodeUnit {
     for ts_query in filter.not_contains() {
       // Check if there's a match within the scope node
       // If one of the filters is not satisfied, return false
-      let query = &rule_store.query(ts_query);
+      let query = &rule_store.query(ts_query, self.piranha_arguments().language());
       if get_match_for_query(ancestor, self.code(), query, true).is_some() {
         return false;
       }
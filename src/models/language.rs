@@ -11,20 +11,27 @@
  limitations under the License.
 */
 
-use std::str::FromStr;
+use std::{path::Path, str::FromStr, sync::Mutex};
 
 use getset::Getters;
+use pyo3::{exceptions::PyValueError, prelude::pyfunction, PyResult};
 use serde_derive::Deserialize;
 use tree_sitter::{Parser, Query};
 
-use crate::utilities::parse_toml;
+use itertools::Itertools;
+use tree_sitter_traversal::{traverse, Order};
+
+use crate::utilities::{parse_toml, tree_sitter_utilities::get_ts_query_parser};
 
 use super::{
+  capture_group_patterns::CGPattern,
   default_configs::{
-    default_language, GO, JAVA, KOTLIN, PYTHON, STRINGS, SWIFT, THRIFT, TSX, TS_SCHEME, TYPESCRIPT,
+    default_language, C_SHARP, GO, JAVA, KOTLIN, PROTO, PYTHON, STRINGS, SWIFT, THRIFT, TSX,
+    TS_SCHEME, TYPESCRIPT,
   },
-  outgoing_edges::Edges,
-  rule::Rules,
+  outgoing_edges::{Edges, OutgoingEdges},
+  rule::{Rule, Rules},
+  rule_graph::CLEANUP_ONLY_SEED,
   scopes::{ScopeConfig, ScopeGenerator},
 };
 
@@ -33,6 +40,9 @@ pub struct PiranhaLanguage {
   /// The extension of the language FIXME: - https://github.com/uber/piranha/issues/365
   #[get = "pub"]
   extension: String,
+  /// The other extensions of the files of the language (for the languages registered at runtime)
+  #[get = "pub"]
+  other_extensions: Vec<String>,
   /// the language (enum)
   #[get = "pub"]
   supported_language: SupportedLanguage,
@@ -51,6 +61,17 @@ pub struct PiranhaLanguage {
   /// The node kinds to be considered when searching for comments
   #[get = "pub"]
   comment_nodes: Vec<String>,
+  /// The node kinds of the comma separated lists (e.g. `argument_list`), i.e. whose commas are deleted along with
+  /// their elements. If empty, the commas of any node (but a string or a comment) are deleted
+  #[get = "pub"]
+  list_nodes: Vec<String>,
+  /// The query capturing the declared package (or module, or namespace) as `@package`, if the language has such a concept
+  #[get = "pub(crate)"]
+  package_query: Option<CGPattern>,
+  /// The node kinds of the type declarations (e.g. `class_declaration`), to report the edits deleting one of the
+  /// several top-level types of a file (whose imports may no longer be used)
+  #[get = "pub(crate)"]
+  type_declaration_nodes: Vec<String>,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
@@ -60,15 +81,303 @@ pub enum SupportedLanguage {
   Kotlin,
   Go,
   Swift,
+  CSharp,
   Ts,
   Tsx,
   Python,
   Thrift,
+  Proto,
   Strings,
   TsScheme,
+  /// A language registered at runtime (see `register_language`)
+  Custom,
+}
+
+// The languages registered at runtime (see `register_language`)
+static CUSTOM_LANGUAGES: Mutex<Vec<PiranhaLanguage>> = Mutex::new(Vec::new());
+
+/// The definition of a language that is not built into Piranha (e.g. an internal DSL with its own tree-sitter grammar).
+/// Once registered (see `register_language`), the language is used like the built-in ones, i.e. as the `language`
+/// argument or the `language` of a rule.
+pub trait LanguageDefinition {
+  /// The name of the language (e.g. `dsl`)
+  fn name(&self) -> String;
+
+  /// The tree-sitter grammar of the language
+  fn tree_sitter_language(&self) -> tree_sitter::Language;
+
+  /// The extensions of the files of the language (without the leading `.`)
+  fn extensions(&self) -> Vec<String> {
+    vec![self.name()]
+  }
+
+  /// The node kinds to be considered when searching for comments
+  fn comment_nodes(&self) -> Vec<String> {
+    vec![]
+  }
+
+  /// The node kinds of the comma separated lists (e.g. `argument_list`), whose commas are deleted along with their
+  /// elements (by default, the commas of any node but a string or a comment)
+  fn list_nodes(&self) -> Vec<String> {
+    vec![]
+  }
+
+  /// The scope configurations of the language, in the format of `scope_config.toml`
+  fn scope_config(&self) -> Option<String> {
+    None
+  }
+
+  /// The built-in (cleanup) rules of the language, in the format of `rules.toml`
+  fn rules(&self) -> Option<String> {
+    None
+  }
+
+  /// The built-in edges of the language, in the format of `edges.toml`
+  fn edges(&self) -> Option<String> {
+    None
+  }
+}
+
+/// Registers the language `definition`, so that it can be used like the built-in languages.
+/// Returns an error if a language with the same name already exists, if one of its extensions is already the
+/// extension of another language (i.e. the files would be routed to either of them), or if its configurations
+/// are invalid.
+pub fn register_language(definition: &dyn LanguageDefinition) -> Result<(), String> {
+  let name = definition.name();
+  if PiranhaLanguage::from_str(&name).is_ok() {
+    return Err(format!("The language `{name}` already exists."));
+  }
+  let extensions = definition.extensions();
+  if let Some(extension) = extensions
+    .iter()
+    .find(|e| PiranhaLanguage::from_str(e).is_ok())
+  {
+    return Err(format!(
+      "The extension `{extension}` of the language `{name}` is already the extension of another language."
+    ));
+  }
+  let scopes = parse_language_config::<ScopeConfig>(&name, definition.scope_config())?
+    .map(|scope_config| scope_config.scopes().to_vec())
+    .unwrap_or_default();
+  let language = PiranhaLanguage {
+    extension: name.to_string(),
+    other_extensions: extensions.into_iter().filter(|e| *e != name).collect(),
+    supported_language: SupportedLanguage::Custom,
+    language: definition.tree_sitter_language(),
+    rules: parse_language_config(&name, definition.rules())?,
+    edges: parse_language_config(&name, definition.edges())?,
+    scopes,
+    comment_nodes: definition.comment_nodes(),
+    list_nodes: definition.list_nodes(),
+    type_declaration_nodes: vec![],
+    package_query: None,
+  };
+  let mut custom_languages = CUSTOM_LANGUAGES.lock().unwrap();
+  // The languages registered since the checks above (e.g. by another thread)
+  if let Some(extension) = [&language.extension]
+    .into_iter()
+    .chain(&language.other_extensions)
+    .find(|e| custom_languages.iter().any(|l| l.has_extension(e)))
+  {
+    return Err(format!(
+      "The extension `{extension}` of the language `{name}` is already the extension of another language."
+    ));
+  }
+  custom_languages.push(language);
+  Ok(())
+}
+
+/// Parses the (TOML) `content` of a configuration of the language `name`, if any.
+fn parse_language_config<T: serde::de::DeserializeOwned>(
+  name: &str, content: Option<String>,
+) -> Result<Option<T>, String> {
+  content
+    .map(|c| {
+      toml::from_str(&c)
+        .map_err(|e| format!("Invalid configuration for the language `{name}` - {e}"))
+    })
+    .transpose()
+}
+
+/// Registers the language `name` whose tree-sitter grammar is compiled into the shared library at `library_path`
+/// (i.e. the library exports the function `tree_sitter_<name>`).
+///
+/// # Arguments:
+/// * name: The name of the language
+/// * library_path: The path to the shared library
+/// * extensions: The extensions of the files of the language (by default, the name)
+/// * comment_nodes: The node kinds to be considered when searching for comments
+/// * scope_config: The scope configurations of the language, in the format of `scope_config.toml`
+/// * rules: The built-in (cleanup) rules of the language, in the format of `rules.toml`
+/// * edges: The built-in edges of the language, in the format of `edges.toml`
+///
+/// Returns an error if the grammar cannot be loaded, or if the language cannot be registered (see `register_language`).
+///
+/// # Safety
+/// The shared library is loaded into the process (running its initializers) and never unloaded. Its
+/// `tree_sitter_<name>` function is trusted to be the function generated by tree-sitter for the grammar: a library
+/// exporting a function of another signature under this name is undefined behavior. Hence, only the grammars built
+/// from trusted sources should be registered. The ABI version of the grammar is checked against the one supported
+/// by Piranha's tree-sitter.
+pub fn register_language_from_library(
+  name: String, library_path: String, extensions: Option<Vec<String>>,
+  comment_nodes: Option<Vec<String>>, scope_config: Option<String>, rules: Option<String>,
+  edges: Option<String>,
+) -> Result<(), String> {
+  let language = load_tree_sitter_language(&library_path, &name)?;
+  let definition = SharedLibraryLanguage {
+    extensions: extensions.unwrap_or_else(|| vec![name.to_string()]),
+    name,
+    language,
+    comment_nodes: comment_nodes.unwrap_or_default(),
+    scope_config,
+    rules,
+    edges,
+  };
+  register_language(&definition)
+}
+
+/// Registers the language `name` whose tree-sitter grammar is compiled into the shared library at `library_path`
+/// (see `register_language_from_library`). Raises a `ValueError` if the language cannot be registered.
+#[pyfunction(name = "register_language")]
+pub fn py_register_language(
+  name: String, library_path: String, extensions: Option<Vec<String>>,
+  comment_nodes: Option<Vec<String>>, scope_config: Option<String>, rules: Option<String>,
+  edges: Option<String>,
+) -> PyResult<()> {
+  register_language_from_library(
+    name,
+    library_path,
+    extensions,
+    comment_nodes,
+    scope_config,
+    rules,
+    edges,
+  )
+  .map_err(PyValueError::new_err)
+}
+
+/// A language whose grammar is loaded from a shared library (see `register_language_from_library`)
+struct SharedLibraryLanguage {
+  name: String,
+  language: tree_sitter::Language,
+  extensions: Vec<String>,
+  comment_nodes: Vec<String>,
+  scope_config: Option<String>,
+  rules: Option<String>,
+  edges: Option<String>,
+}
+
+impl LanguageDefinition for SharedLibraryLanguage {
+  fn name(&self) -> String {
+    self.name.to_string()
+  }
+
+  fn tree_sitter_language(&self) -> tree_sitter::Language {
+    self.language
+  }
+
+  fn extensions(&self) -> Vec<String> {
+    self.extensions.clone()
+  }
+
+  fn comment_nodes(&self) -> Vec<String> {
+    self.comment_nodes.clone()
+  }
+
+  fn scope_config(&self) -> Option<String> {
+    self.scope_config.clone()
+  }
+
+  fn rules(&self) -> Option<String> {
+    self.rules.clone()
+  }
+
+  fn edges(&self) -> Option<String> {
+    self.edges.clone()
+  }
+}
+
+/// Loads the tree-sitter grammar `name` from the shared library at `library_path`, by calling its `tree_sitter_<name>` function.
+/// The library is never unloaded, since the grammar is used until the end of the process.
+#[cfg(unix)]
+fn load_tree_sitter_language(
+  library_path: &str, name: &str,
+) -> Result<tree_sitter::Language, String> {
+  use std::ffi::CString;
+  let path = CString::new(library_path).map_err(|e| e.to_string())?;
+  let symbol =
+    CString::new(format!("tree_sitter_{}", name.replace('-', "_"))).map_err(|e| e.to_string())?;
+  // SAFETY: The symbol is expected to be the language function generated by tree-sitter, which takes no argument
+  // and returns a pointer to the (static) language.
+  unsafe {
+    let handle = libc::dlopen(path.as_ptr(), libc::RTLD_NOW);
+    if handle.is_null() {
+      return Err(format!("Could not load the shared library {library_path}"));
+    }
+    let function = libc::dlsym(handle, symbol.as_ptr());
+    if function.is_null() {
+      return Err(format!(
+        "The shared library {library_path} does not export {}",
+        symbol.to_string_lossy()
+      ));
+    }
+    let language_fn: unsafe extern "C" fn() -> tree_sitter::Language =
+      std::mem::transmute(function);
+    let language = language_fn();
+    let supported_versions =
+      tree_sitter::MIN_COMPATIBLE_LANGUAGE_VERSION..=tree_sitter::LANGUAGE_VERSION;
+    if !supported_versions.contains(&language.version()) {
+      return Err(format!(
+        "The grammar in {library_path} has the ABI version {}, while the supported versions are {} to {}",
+        language.version(),
+        supported_versions.start(),
+        supported_versions.end()
+      ));
+    }
+    Ok(language)
+  }
+}
+
+#[cfg(not(unix))]
+fn load_tree_sitter_language(
+  library_path: &str, _name: &str,
+) -> Result<tree_sitter::Language, String> {
+  Err(format!(
+    "Could not load the shared library {library_path}: loading grammars is only supported on Unix"
+  ))
+}
+
+/// The name of a built-in rule (or group) of the `language`, when it is the language of a rule but not of the run
+/// (see `PiranhaLanguage::prefixed_built_in_rules`), e.g. `kt/boolean_literal_cleanup`.
+/// The `cleanup_only_seed` group is not renamed, since it seeds the cleanup rules of every language.
+pub(crate) fn built_in_name(language: &str, name: &str) -> String {
+  if name == CLEANUP_ONLY_SEED {
+    name.to_string()
+  } else {
+    format!("{language}/{name}")
+  }
 }
 
 impl PiranhaLanguage {
+  /// The built-in rules and edges of the language, when it is the language of a rule but not of the run (see
+  /// `Rule::language`). They are prefixed by the language (see `built_in_name`), hence they do not clash with the
+  /// built-in rules of the run, and the rules of the language can trigger them (e.g. `to = ["kt/boolean_literal_cleanup"]`).
+  pub(crate) fn prefixed_built_in_rules(&self) -> (Vec<Rule>, Vec<OutgoingEdges>) {
+    let rules = self.rules().clone().unwrap_or_default().rules;
+    let edges = self.edges().clone().unwrap_or_default().edges;
+    (
+      rules
+        .iter()
+        .map(|r| r.for_language(self.extension()))
+        .collect(),
+      edges
+        .iter()
+        .map(|e| e.for_language(self.extension()))
+        .collect(),
+    )
+  }
+
   pub fn create_query(&self, query_str: String) -> Query {
     let query = Query::new(self.language, query_str.as_str());
     if let Ok(q) = query {
@@ -81,6 +390,47 @@ impl PiranhaLanguage {
     );
   }
 
+  /// The node kinds of the (named) nodes of the `query` that the grammar does not define, e.g. a node kind renamed
+  /// by a newer version of the grammar. The wildcards and the `ERROR` and `MISSING` nodes are not node kinds.
+  pub(crate) fn unknown_node_kinds(&self, query: &str) -> Vec<String> {
+    let Some(tree) = get_ts_query_parser().parse(query, None) else {
+      return vec![];
+    };
+    traverse(tree.walk(), Order::Pre)
+      .filter(|node| node.kind() == "named_node")
+      .filter_map(|node| {
+        node
+          .child_by_field_name("name")
+          .or_else(|| node.named_child(0))
+      })
+      .filter_map(|name| name.utf8_text(query.as_bytes()).ok())
+      .filter(|kind| !["_", "ERROR", "MISSING"].contains(kind))
+      .filter(|kind| self.language.id_for_node_kind(kind, true) == 0)
+      .unique()
+      .map(str::to_string)
+      .collect()
+  }
+
+  /// The field names of the `query` (e.g. `name` in `(method_declaration name: (_))`, or a negated field `!name`) that
+  /// the grammar does not define, e.g. a field renamed by a newer version of the grammar.
+  pub(crate) fn unknown_field_names(&self, query: &str) -> Vec<String> {
+    let Some(tree) = get_ts_query_parser().parse(query, None) else {
+      return vec![];
+    };
+    traverse(tree.walk(), Order::Pre)
+      .filter(|node| ["field_definition", "negated_field"].contains(&node.kind()))
+      .filter_map(|node| {
+        node
+          .child_by_field_name("name")
+          .or_else(|| node.named_child(0))
+      })
+      .filter_map(|name| name.utf8_text(query.as_bytes()).ok())
+      .filter(|field| self.language.field_id_for_name(field).is_none())
+      .unique()
+      .map(str::to_string)
+      .collect()
+  }
+
   pub fn parser(&self) -> Parser {
     let mut parser = Parser::new();
     parser
@@ -89,13 +439,28 @@ impl PiranhaLanguage {
     parser
   }
 
-  pub(crate) fn can_parse(&self, de: &jwalk::DirEntry<((), ())>) -> bool {
-    de.path()
+  /// Checks if the file at `path` is of this language (by its extension)
+  pub(crate) fn can_parse_path(&self, path: &Path) -> bool {
+    path
       .extension()
-      .and_then(|e| e.to_str().filter(|x| x.eq(&self.extension())))
+      .and_then(|e| e.to_str().filter(|x| self.has_extension(x)))
       .is_some()
   }
 
+  /// Checks if the files with the `extension` are of this language
+  pub(crate) fn has_extension(&self, extension: &str) -> bool {
+    self.extension() == extension || self.other_extensions().iter().any(|e| e == extension)
+  }
+
+  /// The node kinds of the identifiers (e.g. `identifier`, `type_identifier`) in the grammar of this language
+  pub(crate) fn identifier_nodes(&self) -> Vec<&'static str> {
+    IDENTIFIER_NODES
+      .iter()
+      .copied()
+      .filter(|kind| self.language.id_for_node_kind(kind, true) != 0)
+      .collect()
+  }
+
   #[cfg(test)]
   pub(crate) fn set_scopes(&mut self, scopes: Vec<ScopeGenerator>) {
     self.scopes = scopes;
@@ -114,6 +479,223 @@ impl From<&str> for PiranhaLanguage {
   }
 }
 
+// The node kinds of the identifiers across the grammars (see `PiranhaLanguage::identifier_nodes`)
+static IDENTIFIER_NODES: &[&str] = &[
+  "identifier",
+  "type_identifier",
+  "field_identifier",
+  "package_identifier",
+  "property_identifier",
+  "shorthand_property_identifier",
+  "simple_identifier",
+];
+
+// The comma separated lists of each language (see `PiranhaLanguage::list_nodes`)
+// (The commas of the `for` headers are not deleted, e.g. `for (i = 0, j = 0; ...)`)
+static JAVA_LIST_NODES: &[&str] = &[
+  "annotation_argument_list",
+  "argument_list",
+  "array_initializer",
+  "constant_declaration",
+  "element_value_array_initializer",
+  "enum_body",
+  "exports_module_directive",
+  "field_declaration",
+  "formal_parameters",
+  "inferred_parameters",
+  "local_variable_declaration",
+  "opens_module_directive",
+  "provides_module_directive",
+  "record_pattern_body",
+  "switch_label",
+  "throws",
+  "type_arguments",
+  "type_list",
+  "type_parameters",
+];
+
+static GO_LIST_NODES: &[&str] = &[
+  "argument_list",
+  "const_spec",
+  "expression_list",
+  "field_declaration",
+  "field_name_list",
+  "literal_value",
+  "parameter_declaration",
+  "parameter_list",
+  "special_argument_list",
+  "type_arguments",
+  "type_case",
+  "type_conversion_expression",
+  "type_parameter_list",
+  "var_spec",
+];
+
+static PYTHON_LIST_NODES: &[&str] = &[
+  "argument_list",
+  "assert_statement",
+  "case_clause",
+  "class_pattern",
+  "dict_pattern",
+  "dictionary",
+  "except_clause",
+  "exec_statement",
+  "expression_list",
+  "expression_statement",
+  "for_in_clause",
+  "future_import_statement",
+  "global_statement",
+  "import_from_statement",
+  "import_statement",
+  "lambda_parameters",
+  "list",
+  "list_pattern",
+  "match_statement",
+  "nonlocal_statement",
+  "parameters",
+  "pattern_list",
+  "print_statement",
+  "set",
+  "subscript",
+  "tuple",
+  "tuple_pattern",
+  "type_parameter",
+  "with_clause",
+];
+
+// (The commas of the sequence expressions are not deleted, e.g. `i++, j++`)
+static TYPESCRIPT_LIST_NODES: &[&str] = &[
+  "arguments",
+  "array",
+  "array_pattern",
+  "class_body",
+  "enum_body",
+  "export_clause",
+  "extends_clause",
+  "extends_type_clause",
+  "formal_parameters",
+  "implements_clause",
+  "import_clause",
+  "lexical_declaration",
+  "named_imports",
+  "object",
+  "object_pattern",
+  "object_type",
+  "tuple_type",
+  "type_arguments",
+  "type_parameters",
+  "variable_declaration",
+];
+
+static KOTLIN_LIST_NODES: &[&str] = &[
+  "class_declaration",
+  "class_parameters",
+  "collection_literal",
+  "delegation_specifiers",
+  "enum_class_body",
+  "function_type_parameters",
+  "function_value_parameters",
+  "indexing_suffix",
+  "lambda_parameters",
+  "multi_variable_declaration",
+  "object_declaration",
+  "type_arguments",
+  "type_constraints",
+  "type_parameters",
+  "value_arguments",
+  "when_entry",
+];
+
+static SWIFT_LIST_NODES: &[&str] = &[
+  "array_literal",
+  "capture_list",
+  "class_declaration",
+  "dictionary_literal",
+  "enum_entry",
+  "function_declaration",
+  "guard_statement",
+  "if_statement",
+  "init_declaration",
+  "lambda_function_type_parameters",
+  "property_declaration",
+  "protocol_declaration",
+  "protocol_function_declaration",
+  "subscript_declaration",
+  "switch_entry",
+  "tuple_expression",
+  "tuple_type",
+  "type_arguments",
+  "type_constraints",
+  "type_parameters",
+  "value_arguments",
+  "while_statement",
+];
+
+// (The commas of the interpolations are not deleted, e.g. `$"{value,10}"`)
+static CSHARP_LIST_NODES: &[&str] = &[
+  "anonymous_object_creation_expression",
+  "argument_list",
+  "array_rank_specifier",
+  "attribute_argument_list",
+  "attribute_list",
+  "base_list",
+  "bracketed_argument_list",
+  "bracketed_parameter_list",
+  "enum_member_declaration_list",
+  "function_pointer_type",
+  "initializer_expression",
+  "parameter_list",
+  "parenthesized_variable_designation",
+  "positional_pattern_clause",
+  "property_pattern_clause",
+  "switch_expression",
+  "tuple_expression",
+  "tuple_pattern",
+  "tuple_type",
+  "type_argument_list",
+  "type_parameter_constraints_clause",
+  "type_parameter_list",
+  "variable_declaration",
+];
+
+// (The separators of the fields and of the enum members are part of them, e.g. `1: string name,`)
+static THRIFT_LIST_NODES: &[&str] = &[
+  "annotation_definition",
+  "const_list",
+  "const_map",
+  "enum_definition",
+  "field",
+  "function_definition",
+  "parameters",
+  "throws",
+];
+
+// The top-level type declarations of each language (see `PiranhaLanguage::type_declaration_nodes`)
+static JAVA_TYPE_DECLARATION_NODES: &[&str] = &[
+  "annotation_type_declaration",
+  "class_declaration",
+  "enum_declaration",
+  "interface_declaration",
+  "record_declaration",
+];
+
+static KOTLIN_TYPE_DECLARATION_NODES: &[&str] = &["class_declaration", "object_declaration"];
+
+static SWIFT_TYPE_DECLARATION_NODES: &[&str] = &["class_declaration", "protocol_declaration"];
+
+static CSHARP_TYPE_DECLARATION_NODES: &[&str] = &[
+  "class_declaration",
+  "enum_declaration",
+  "interface_declaration",
+  "record_declaration",
+  "struct_declaration",
+];
+
+/// The node kinds (e.g. of the comma separated lists, see `PiranhaLanguage::list_nodes`) as strings
+fn node_kinds(kinds: &[&str]) -> Vec<String> {
+  kinds.iter().map(|k| k.to_string()).collect()
+}
+
 impl std::str::FromStr for PiranhaLanguage {
   type Err = &'static str;
   /// This method is leveraged by `clap` to parse the command line
@@ -125,6 +707,7 @@ impl std::str::FromStr for PiranhaLanguage {
         let edges: Edges = parse_toml(include_str!("../cleanup_rules/java/edges.toml"));
         Ok(Self {
           extension: language.to_string(),
+          other_extensions: vec![],
           supported_language: SupportedLanguage::Java,
           language: tree_sitter_java::language(),
           rules: Some(rules),
@@ -135,6 +718,11 @@ impl std::str::FromStr for PiranhaLanguage {
           .scopes()
           .to_vec(),
           comment_nodes: vec!["line_comment".to_string(), "block_comment".to_string()],
+          list_nodes: node_kinds(JAVA_LIST_NODES),
+          type_declaration_nodes: node_kinds(JAVA_TYPE_DECLARATION_NODES),
+          package_query: Some(CGPattern::new(
+            "(package_declaration [(identifier) (scoped_identifier)] @package)".to_string(),
+          )),
         })
       }
       GO => {
@@ -142,6 +730,7 @@ impl std::str::FromStr for PiranhaLanguage {
         let edges: Edges = parse_toml(include_str!("../cleanup_rules/go/edges.toml"));
         Ok(PiranhaLanguage {
           extension: language.to_string(),
+          other_extensions: vec![],
           supported_language: SupportedLanguage::Go,
           language: tree_sitter_go::language(),
           rules: Some(rules),
@@ -150,6 +739,11 @@ impl std::str::FromStr for PiranhaLanguage {
             .scopes()
             .to_vec(),
           comment_nodes: vec!["comment".to_string()],
+          list_nodes: node_kinds(GO_LIST_NODES),
+          type_declaration_nodes: vec![],
+          package_query: Some(CGPattern::new(
+            "(package_clause (package_identifier) @package)".to_string(),
+          )),
         })
       }
       KOTLIN => {
@@ -157,6 +751,7 @@ impl std::str::FromStr for PiranhaLanguage {
         let edges: Edges = parse_toml(include_str!("../cleanup_rules/kt/edges.toml"));
         Ok(PiranhaLanguage {
           extension: language.to_string(),
+          other_extensions: vec![],
           supported_language: SupportedLanguage::Kotlin,
           language: tree_sitter_kotlin::language(),
           rules: Some(rules),
@@ -165,22 +760,36 @@ impl std::str::FromStr for PiranhaLanguage {
             .scopes()
             .to_vec(),
           comment_nodes: vec!["comment".to_string(), "line_comment".to_string()],
+          list_nodes: node_kinds(KOTLIN_LIST_NODES),
+          type_declaration_nodes: node_kinds(KOTLIN_TYPE_DECLARATION_NODES),
+          package_query: Some(CGPattern::new(
+            "(package_header (identifier) @package)".to_string(),
+          )),
+        })
+      }
+      PYTHON => {
+        let rules: Rules = parse_toml(include_str!("../cleanup_rules/py/rules.toml"));
+        let edges: Edges = parse_toml(include_str!("../cleanup_rules/py/edges.toml"));
+        Ok(PiranhaLanguage {
+          extension: language.to_string(),
+          other_extensions: vec![],
+          supported_language: SupportedLanguage::Python,
+          language: tree_sitter_python::language(),
+          rules: Some(rules),
+          edges: Some(edges),
+          scopes: vec![],
+          comment_nodes: vec!["comment".to_string()],
+          list_nodes: node_kinds(PYTHON_LIST_NODES),
+          type_declaration_nodes: vec![],
+          package_query: None,
         })
       }
-      PYTHON => Ok(PiranhaLanguage {
-        extension: language.to_string(),
-        supported_language: SupportedLanguage::Python,
-        language: tree_sitter_python::language(),
-        rules: None,
-        edges: None,
-        scopes: vec![],
-        comment_nodes: vec![],
-      }),
       SWIFT => {
         let rules: Rules = parse_toml(include_str!("../cleanup_rules/swift/rules.toml"));
         let edges: Edges = parse_toml(include_str!("../cleanup_rules/swift/edges.toml"));
         Ok(PiranhaLanguage {
           extension: language.to_string(),
+          other_extensions: vec![],
           supported_language: SupportedLanguage::Swift,
           language: tree_sitter_swift::language(),
           scopes: parse_toml::<ScopeConfig>(include_str!(
@@ -189,56 +798,141 @@ impl std::str::FromStr for PiranhaLanguage {
           .scopes()
           .to_vec(),
           comment_nodes: vec!["comment".to_string(), "multiline_comment".to_string()],
+          list_nodes: node_kinds(SWIFT_LIST_NODES),
+          type_declaration_nodes: node_kinds(SWIFT_TYPE_DECLARATION_NODES),
+          package_query: None,
           rules: Some(rules),
           edges: Some(edges),
         })
       }
-      TYPESCRIPT => Ok(PiranhaLanguage {
-        extension: language.to_string(),
-        supported_language: SupportedLanguage::Ts,
-        language: tree_sitter_typescript::language_typescript(),
-        rules: None,
-        edges: None,
-        scopes: vec![],
-        comment_nodes: vec![],
-      }),
-      TSX => Ok(PiranhaLanguage {
-        extension: language.to_string(),
-        supported_language: SupportedLanguage::Tsx,
-        language: tree_sitter_typescript::language_tsx(),
-        rules: None,
-        edges: None,
-        scopes: vec![],
-        comment_nodes: vec![],
-      }),
-      THRIFT => Ok(PiranhaLanguage {
-        extension: language.to_string(),
-        supported_language: SupportedLanguage::Thrift,
-        language: tree_sitter_thrift::language(),
-        rules: None,
-        edges: None,
-        scopes: vec![],
-        comment_nodes: vec![],
-      }),
+      C_SHARP => {
+        let rules: Rules = parse_toml(include_str!("../cleanup_rules/cs/rules.toml"));
+        let edges: Edges = parse_toml(include_str!("../cleanup_rules/cs/edges.toml"));
+        Ok(PiranhaLanguage {
+          extension: language.to_string(),
+          other_extensions: vec![],
+          supported_language: SupportedLanguage::CSharp,
+          language: tree_sitter_c_sharp::language(),
+          scopes: parse_toml::<ScopeConfig>(include_str!("../cleanup_rules/cs/scope_config.toml"))
+            .scopes()
+            .to_vec(),
+          comment_nodes: vec!["comment".to_string()],
+          list_nodes: node_kinds(CSHARP_LIST_NODES),
+          type_declaration_nodes: node_kinds(CSHARP_TYPE_DECLARATION_NODES),
+          package_query: Some(CGPattern::new(
+            "(namespace_declaration name: (_) @package)".to_string(),
+          )),
+          rules: Some(rules),
+          edges: Some(edges),
+        })
+      }
+      TYPESCRIPT => {
+        let rules: Rules = parse_toml(include_str!("../cleanup_rules/ts/rules.toml"));
+        let edges: Edges = parse_toml(include_str!("../cleanup_rules/ts/edges.toml"));
+        Ok(PiranhaLanguage {
+          extension: language.to_string(),
+          other_extensions: vec![],
+          supported_language: SupportedLanguage::Ts,
+          language: tree_sitter_typescript::language_typescript(),
+          rules: Some(rules),
+          edges: Some(edges),
+          scopes: vec![],
+          comment_nodes: vec![],
+          list_nodes: node_kinds(TYPESCRIPT_LIST_NODES),
+          type_declaration_nodes: vec![],
+          package_query: None,
+        })
+      }
+      TSX => {
+        let rules: Rules = parse_toml(include_str!("../cleanup_rules/ts/rules.toml"));
+        let edges: Edges = parse_toml(include_str!("../cleanup_rules/ts/edges.toml"));
+        Ok(PiranhaLanguage {
+          extension: language.to_string(),
+          other_extensions: vec![],
+          supported_language: SupportedLanguage::Tsx,
+          language: tree_sitter_typescript::language_tsx(),
+          rules: Some(rules),
+          edges: Some(edges),
+          scopes: vec![],
+          comment_nodes: vec![],
+          list_nodes: node_kinds(TYPESCRIPT_LIST_NODES),
+          type_declaration_nodes: vec![],
+          package_query: None,
+        })
+      }
+      THRIFT => {
+        let rules: Rules = parse_toml(include_str!("../cleanup_rules/thrift/rules.toml"));
+        Ok(PiranhaLanguage {
+          extension: language.to_string(),
+          other_extensions: vec![],
+          supported_language: SupportedLanguage::Thrift,
+          language: tree_sitter_thrift::language(),
+          rules: Some(rules),
+          edges: None,
+          scopes: parse_toml::<ScopeConfig>(include_str!(
+            "../cleanup_rules/thrift/scope_config.toml"
+          ))
+          .scopes()
+          .to_vec(),
+          comment_nodes: vec!["comment".to_string()],
+          list_nodes: node_kinds(THRIFT_LIST_NODES),
+          type_declaration_nodes: vec![],
+          package_query: None,
+        })
+      }
+      PROTO => {
+        let rules: Rules = parse_toml(include_str!("../cleanup_rules/proto/rules.toml"));
+        Ok(PiranhaLanguage {
+          extension: language.to_string(),
+          other_extensions: vec![],
+          supported_language: SupportedLanguage::Proto,
+          language: tree_sitter_proto::language(),
+          rules: Some(rules),
+          edges: None,
+          scopes: parse_toml::<ScopeConfig>(include_str!(
+            "../cleanup_rules/proto/scope_config.toml"
+          ))
+          .scopes()
+          .to_vec(),
+          comment_nodes: vec!["comment".to_string()],
+          list_nodes: vec![],
+          type_declaration_nodes: vec![],
+          package_query: None,
+        })
+      }
       STRINGS => Ok(PiranhaLanguage {
         extension: language.to_string(),
+        other_extensions: vec![],
         supported_language: SupportedLanguage::Strings,
         language: tree_sitter_strings::language(),
         rules: None,
         edges: None,
         scopes: vec![],
         comment_nodes: vec![],
+        list_nodes: vec![],
+        type_declaration_nodes: vec![],
+        package_query: None,
       }),
       TS_SCHEME => Ok(PiranhaLanguage {
         extension: language.to_string(),
+        other_extensions: vec![],
         supported_language: SupportedLanguage::TsScheme,
         language: tree_sitter_query::language(),
         rules: None,
         edges: None,
         scopes: vec![],
         comment_nodes: vec![],
+        list_nodes: vec![],
+        type_declaration_nodes: vec![],
+        package_query: None,
       }),
-      _ => Err("Language not supported"),
+      _ => CUSTOM_LANGUAGES
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|l| l.extension() == language)
+        .cloned()
+        .ok_or("Language not supported"),
     }
   }
 }
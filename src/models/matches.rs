@@ -13,6 +13,7 @@ Copyright (c) 2023 Uber Technologies, Inc.
 
 use std::collections::HashMap;
 
+use colored::Colorize;
 use getset::{Getters, MutGetters};
 use itertools::Itertools;
 use log::trace;
@@ -21,29 +22,42 @@ use serde_derive::{Deserialize, Serialize};
 use tree_sitter::Node;
 
 use crate::utilities::{
-  gen_py_str_methods,
+  exclude_split_line_ending, gen_py_str_methods, is_protected_header_line,
   tree_sitter_utilities::{get_all_matches_for_query, get_node_for_range},
+  truncate_lines, truncate_snippet, MAX_DISPLAYED_SNIPPET_LENGTH,
 };
 
 use super::{
-  piranha_arguments::PiranhaArguments, rule::InstantiatedRule, rule_store::RuleStore,
+  package_filter::PACKAGE_TAG,
+  piranha_arguments::PiranhaArguments,
+  rule::InstantiatedRule,
+  rule_store::RuleStore,
+  skipped_match::{SkipReason, SkippedMatch},
   source_code_unit::SourceCodeUnit,
 };
 
 #[derive(Serialize, Debug, Clone, Getters, MutGetters, Deserialize)]
 #[pyclass]
-pub(crate) struct Match {
+pub struct Match {
   // Code snippet that matched
   #[get = "pub"]
   #[pyo3(get)]
   matched_string: String,
   // Range of the entire AST node captured by the match
   #[pyo3(get)]
+  #[get_mut = "pub(crate)"]
   range: Range,
   // The mapping between tags and string representation of the AST captured.
   #[pyo3(get)]
   #[get = "pub"]
   matches: HashMap<String, String>,
+  // The mapping between tags and the ranges of the AST nodes captured (only reported when `report_capture_ranges` is enabled).
+  // A quantified tag (e.g. `(_)* @args`) has a range for each node it captured.
+  #[pyo3(get)]
+  #[get = "pub(crate)"]
+  #[get_mut = "pub(crate)"]
+  #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+  capture_ranges: HashMap<String, Vec<Range>>,
   // Captures the range of the associated comma
   #[get]
   #[get_mut]
@@ -65,6 +79,7 @@ impl Match {
       matched_string,
       range: Range::from(range),
       matches,
+      capture_ranges: HashMap::new(),
       associated_comma: None,
       associated_comments: Vec::new(),
     }
@@ -90,6 +105,7 @@ impl Match {
 
   /// Merge the associated matches of the given match into the current match.
   /// It basically extends the range to include the first and last associated match of the given match.
+  /// The range never ends within a `\r\n` line ending (see `exclude_split_line_ending`).
   pub(crate) fn expand_to_associated_matches(&mut self, code: &str) {
     let (start_range, end_range) = self.get_first_and_last_associated_ranges();
     if start_range.start_byte < self.range.start_byte {
@@ -100,9 +116,22 @@ impl Match {
       self.range.end_byte = end_range.end_byte;
       self.range.end_point = end_range.end_point;
     }
+    let end_byte = exclude_split_line_ending(code, self.range.end_byte);
+    if end_byte < self.range.end_byte {
+      // The `\r` is the last column of its row
+      self.range.end_byte = end_byte;
+      self.range.end_point.column -= 1;
+    }
     self.matched_string = code[self.range.start_byte..self.range.end_byte].to_string()
   }
 
+  /// Returns the byte range spanning the nodes captured by the `tag` (i.e. all the nodes of a quantified tag),
+  /// or `None` if the tag did not capture any node.
+  pub(crate) fn capture_byte_range(&self, tag: &str) -> Option<std::ops::Range<usize>> {
+    let ranges = self.capture_ranges.get(tag)?;
+    Some(ranges.first()?.start_byte..ranges.last()?.end_byte)
+  }
+
   /// Get the edit's replacement range.
   pub(crate) fn range(&self) -> tree_sitter::Range {
     tree_sitter::Range {
@@ -129,6 +158,10 @@ impl Match {
 
   /// Get the associated elements for the match.
   /// We currently capture leading and trailing comments and commas.
+  /// The commas of an ancestor are associated only if the ancestor spans the same range as the `node`,
+  /// e.g. deleting an annotation of a parameter should not delete the comma after the parameter.
+  /// Similarly, the leading comma is associated only if no other element follows the node (before the next comma),
+  /// e.g. deleting the modifiers preceding a parameter should not delete the comma before them.
   fn get_associated_elements(
     &mut self, node: &Node, code: &String, piranha_arguments: &PiranhaArguments, trailing: bool,
   ) {
@@ -136,7 +169,11 @@ impl Match {
     let mut buf = *piranha_arguments.cleanup_comments_buffer();
     let mut found_comment = !self.associated_comments().is_empty();
     let mut found_comma = self.associated_comma().is_some();
+    // Whether the node whose siblings are looked up spans the same range as the `node`
+    let mut spans_node = true;
     loop {
+      let is_followed_by_element =
+        !trailing && self.is_followed_by_element(&current_node, piranha_arguments);
       // If we are looking for trailing elements, we start from the next sibling of the node
       // Else we start from the previous sibling of the node
       while let Some(sibling) = if trailing {
@@ -146,13 +183,19 @@ impl Match {
       } {
         let content = sibling.utf8_text(code.as_bytes()).unwrap();
         // Check if the sibling is a comment
-        if !found_comma && content.trim().eq(",") {
+        if !found_comma
+          && spans_node
+          && !is_followed_by_element
+          && content.trim().eq(",")
+          && self.is_list_comma(&sibling, &current_node, piranha_arguments)
+        {
           // Add the comma to the associated matches
           self.associated_comma = Some(Range::from(sibling.range()));
           current_node = sibling;
           found_comma = true;
           continue; // Continue the inner loop (i.e. evaluate next sibling)
-        } else if self._is_comment_safe_to_delete(&sibling, node, piranha_arguments, trailing) {
+        } else if self._is_comment_safe_to_delete(&sibling, node, code, piranha_arguments, trailing)
+        {
           // Add the comment to the associated matches
           self.associated_comments.push(Range::from(sibling.range()));
           current_node = sibling;
@@ -168,11 +211,49 @@ impl Match {
         break; // Break the outer loop
       }
       current_node = parent.unwrap();
+      spans_node = spans_node && current_node.byte_range() == node.byte_range();
       buf -= 1;
       continue; // Continue the outer loop (i.e. lookup parent's siblings for comma/comment)
     }
   }
 
+  /// Checks if the `node` is followed by a named node (other than a comment) before the next comma, i.e. it is only a part of an element.
+  fn is_followed_by_element(&self, node: &Node, piranha_arguments: &PiranhaArguments) -> bool {
+    let comment_nodes = piranha_arguments.language().comment_nodes();
+    let mut current_node = *node;
+    while let Some(sibling) = current_node.next_sibling() {
+      if sibling.kind() == "," {
+        return false;
+      }
+      if sibling.is_named() && !comment_nodes.contains(&sibling.kind().to_string()) {
+        return true;
+      }
+      current_node = sibling;
+    }
+    false
+  }
+
+  /// Checks if the `comma` separates the `element` from the other elements of a list (see `PiranhaLanguage::list_nodes`),
+  /// e.g. not the text of a string literal, the comma of a `for` header or the comma of another list.
+  fn is_list_comma(
+    &self, comma: &Node, element: &Node, piranha_arguments: &PiranhaArguments,
+  ) -> bool {
+    let language = piranha_arguments.language();
+    let Some(parent) = comma.parent() else {
+      return false;
+    };
+    if element.parent() != Some(parent) {
+      return false;
+    }
+    if language.list_nodes().is_empty() {
+      return !parent.kind().contains("string")
+        && !language
+          .comment_nodes()
+          .contains(&parent.kind().to_string());
+    }
+    language.list_nodes().contains(&parent.kind().to_string())
+  }
+
   /// Checks if the given node kind is a comment in the language (determined from piranha arguments)
   fn is_comment(&self, kind: String, piranha_arguments: &PiranhaArguments) -> bool {
     *piranha_arguments.cleanup_comments()
@@ -180,14 +261,24 @@ impl Match {
   }
 
   /// Checks if the given comment is safe to delete.
+  /// A shebang or an encoding declaration (see `is_protected_header_line`) is never deleted.
   fn _is_comment_safe_to_delete(
-    &mut self, comment: &Node, deleted_node: &Node, piranha_arguments: &PiranhaArguments,
-    trailing: bool,
+    &mut self, comment: &Node, deleted_node: &Node, code: &str,
+    piranha_arguments: &PiranhaArguments, trailing: bool,
   ) -> bool {
     // Check if the comment is a comment in the language
     if !self.is_comment(comment.kind().to_string(), piranha_arguments) {
       return false;
     }
+    let row = comment.start_position().row;
+    if row <= 1
+      && code
+        .lines()
+        .nth(row)
+        .is_some_and(|line| is_protected_header_line(line, row))
+    {
+      return false;
+    }
     // If trailing, check if the comment is on the same line as the deleted node
     // i.e. where the deleted node ends or starts
     let is_on_same_line = comment.range().start_point.row == deleted_node.range().end_point.row
@@ -227,6 +318,28 @@ impl Match {
         && node_2.end_position().row < node_1.end_position().row)
   }
 }
+
+/// Returns a copy of `code` where the code snippets at the byte `ranges` are wrapped in ANSI (yellow) color codes.
+/// A range overlapping with a previously highlighted range is not highlighted. The long lines are truncated
+/// (see `MAX_DISPLAYED_SNIPPET_LENGTH`), e.g. for the minified files.
+pub(crate) fn highlight(code: &str, ranges: impl Iterator<Item = (usize, usize)>) -> String {
+  let truncate = |text: &str| truncate_lines(text, MAX_DISPLAYED_SNIPPET_LENGTH);
+  let mut highlighted_code = String::new();
+  let mut highlighted_until = 0;
+  for (start_byte, end_byte) in ranges.sorted() {
+    if start_byte < highlighted_until {
+      continue;
+    }
+    if let Some(matched_code) = code.get(start_byte..end_byte) {
+      highlighted_code.push_str(&truncate(&code[highlighted_until..start_byte]));
+      highlighted_code.push_str(&truncate(matched_code).yellow().to_string());
+      highlighted_until = end_byte;
+    }
+  }
+  highlighted_code.push_str(&truncate(&code[highlighted_until..]));
+  highlighted_code
+}
+
 /// A range of positions in a multi-line text document, both in terms of bytes and of
 /// rows and columns.
 /// Note `LocalRange` derives serialize.
@@ -234,7 +347,7 @@ impl Match {
   serde_derive::Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize,
 )]
 #[pyclass]
-struct Range {
+pub(crate) struct Range {
   #[pyo3(get)]
   start_byte: usize,
   #[pyo3(get)]
@@ -284,36 +397,148 @@ impl SourceCodeUnit {
     &self, rule: &InstantiatedRule, rule_store: &mut RuleStore, node: Node, recursive: bool,
   ) -> Vec<Match> {
     let mut output: Vec<Match> = vec![];
+    // Skip the rule altogether if the declared package does not satisfy its package filter
+    if let Some(package_filter) = rule.rule().package_filter() {
+      if !package_filter.is_satisfied_by(self.declared_package(rule_store)) {
+        return output;
+      }
+    }
     // Get all matches for the query in the given scope `node`.
-    let (replace_node_tag, replace_node_idx) =
-      if rule.rule().is_match_only_rule() || rule.rule().is_dummy_rule() {
-        (None, None)
-      } else {
-        (rule.replace_node(), rule.replace_idx())
-      };
+    // The rules replacing a capture within the match are matched against the whole match (see `replaced_capture`)
+    let (replace_node_tag, replace_node_idx) = if rule.rule().is_match_only_rule()
+      || rule.rule().is_dummy_rule()
+      || rule.rule().replaced_capture().is_some()
+    {
+      (None, None)
+    } else {
+      (rule.replace_node(), rule.replace_idx())
+    };
     let mut all_query_matches = get_all_matches_for_query(
       &node,
       self.code().to_string(),
-      rule_store.query(&rule.query()),
+      rule_store.query(&rule.query(), self.piranha_arguments().language()),
       recursive,
       replace_node_tag,
       replace_node_idx,
     );
 
+    // The capture ranges are only reported when requested (to limit the size of the output summary).
+    // The rules replacing the node with a capture need them to build the edit (see `get_edit`),
+    // and the rules replacing a capture within the match to find the captured nodes.
+    if !*self.piranha_arguments().report_capture_ranges()
+      && rule.rule().replace_with_capture().is_empty()
+      && rule.rule().replaced_capture().is_none()
+    {
+      all_query_matches
+        .iter_mut()
+        .for_each(|m| m.capture_ranges.clear());
+    }
+
+    // The declared package is bound to `@__package__` for the rules referencing it (see `PACKAGE_TAG`)
+    let package_reference = format!("@{PACKAGE_TAG}");
+    let declared_package = if rule.rule().replace().contains(&package_reference)
+      || rule
+        .rule()
+        .filters()
+        .iter()
+        .flat_map(|f| f.patterns())
+        .any(|p| p.pattern().contains(&package_reference))
+    {
+      self.declared_package(rule_store).map(str::to_string)
+    } else {
+      None
+    };
+
     // Applies the filter and returns the first element
     for p_match in all_query_matches.iter_mut() {
+      if let Some(package) = &declared_package {
+        p_match
+          .matches
+          .entry(PACKAGE_TAG.to_string())
+          .or_insert_with(|| package.to_string());
+      }
+      if self.is_suppressed(&rule.name(), p_match.range()) {
+        rule_store.report_skipped_match(
+          self.path(),
+          SkippedMatch::new(
+            &rule.name(),
+            SkipReason::SuppressedByComment,
+            p_match.range(),
+          ),
+        );
+        continue;
+      }
+      if !self.is_within_line_range(rule, p_match.range()) {
+        rule_store.report_skipped_match(
+          self.path(),
+          SkippedMatch::new(&rule.name(), SkipReason::OutsideLineRange, p_match.range()),
+        );
+        continue;
+      }
       let matched_node = get_node_for_range(
         self.root_node(),
         p_match.range().start_byte,
         p_match.range().end_byte,
       );
       if self.is_satisfied(matched_node, rule, p_match.matches(), rule_store) {
-        p_match.populate_associated_elements(&matched_node, self.code(), self.piranha_arguments());
-        trace!("Found match {:#?}", p_match);
-        output.push(p_match.clone());
+        for mut p_match in self.matches_of_replaced_capture(rule, p_match) {
+          let matched_node = get_node_for_range(
+            self.root_node(),
+            p_match.range().start_byte,
+            p_match.range().end_byte,
+          );
+          p_match.populate_associated_elements(
+            &matched_node,
+            self.code(),
+            self.piranha_arguments(),
+          );
+          trace!(
+            "Found match at {:?} : {}",
+            p_match.range(),
+            truncate_snippet(p_match.matched_string(), MAX_DISPLAYED_SNIPPET_LENGTH)
+          );
+          output.push(p_match);
+        }
+      } else {
+        rule_store.report_skipped_match(
+          self.path(),
+          SkippedMatch::new(&rule.name(), SkipReason::FilterRejected, p_match.range()),
+        );
       }
     }
     trace!("Matches found {}", output.len());
     output
   }
+
+  /// Splits the (whole) match of a rule replacing a capture within the match (see `Rule::replaced_capture`) into a match
+  /// per node captured by the tag, from the last to the first (like the matches, from the bottom to the top).
+  /// These matches keep the tags of the whole match. Returns the `p_match` as is for the other rules.
+  fn matches_of_replaced_capture(&self, rule: &InstantiatedRule, p_match: &Match) -> Vec<Match> {
+    let Some(tag) = rule.rule().replaced_capture() else {
+      return vec![p_match.clone()];
+    };
+    let capture_ranges = if *self.piranha_arguments().report_capture_ranges()
+      || !rule.rule().replace_with_capture().is_empty()
+    {
+      p_match.capture_ranges().clone()
+    } else {
+      HashMap::new()
+    };
+    p_match
+      .capture_ranges()
+      .get(tag)
+      .into_iter()
+      .flatten()
+      .rev()
+      .map(|range| {
+        let mut capture_match = Match::new(
+          self.code()[range.start_byte..range.end_byte].to_string(),
+          get_node_for_range(self.root_node(), range.start_byte, range.end_byte).range(),
+          p_match.matches().clone(),
+        );
+        capture_match.capture_ranges = capture_ranges.clone();
+        capture_match
+      })
+      .collect()
+  }
 }
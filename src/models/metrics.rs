@@ -0,0 +1,418 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+//! The cumulative statistics of a run (see `PiranhaArguments::metrics_output`), written as a small and stable JSON
+//! document (e.g. to track the burn-down of the stale flags on a dashboard). The document excludes the contents
+//! of the files, hence it is cheap to archive.
+//! The metrics are assembled as the files and the passes (or stages) of the run complete, and written after each file,
+//! hence even if the run fails (or panics, or is killed) partway, from whatever completed.
+
+use std::{
+  collections::{BTreeMap, BTreeSet, HashMap},
+  fmt, fs,
+  path::{Path, PathBuf},
+  time::Instant,
+};
+
+use getset::{CopyGetters, Getters};
+use log::warn;
+use serde_derive::{Deserialize, Serialize};
+
+use super::{
+  piranha_arguments::PiranhaArguments,
+  piranha_output::{count_skipped_matches, RuleStat},
+  skipped_match::SkipReason,
+  source_code_unit::SourceCodeUnit,
+};
+
+/// The version of the schema of the metrics, incremented on each breaking change (e.g. a renamed field)
+pub const METRICS_SCHEMA_VERSION: u32 = 2;
+
+/// The metrics of a run
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Getters, CopyGetters)]
+pub struct RunMetrics {
+  /// The version of the schema of this document (see `METRICS_SCHEMA_VERSION`)
+  #[get_copy = "pub"]
+  schema_version: u32,
+  /// Whether the run completed, else the metrics only cover the passes (or stages) completed before it failed
+  #[get_copy = "pub"]
+  completed: bool,
+  /// The duration of the run (in milliseconds)
+  #[get_copy = "pub"]
+  duration_ms: u64,
+  /// The number of files the rules were applied to
+  #[get_copy = "pub"]
+  files_scanned: usize,
+  /// The number of files rewritten (excluding the deleted ones)
+  #[get_copy = "pub"]
+  files_edited: usize,
+  /// The number of files deleted, since they became empty (see `delete_file_if_empty`)
+  #[get_copy = "pub"]
+  files_deleted: usize,
+  /// The number of edits applied for each rule
+  #[get = "pub"]
+  edits_by_rule: BTreeMap<String, usize>,
+  /// The number of matches of each rule
+  #[get = "pub"]
+  matches_by_rule: BTreeMap<String, usize>,
+  /// The statistics of each rule (see `RuleStat`) across all the files the rules were applied to, including the
+  /// files that were not updated and the rules that never matched
+  #[get = "pub"]
+  #[serde(default)]
+  rule_statistics: BTreeMap<String, RuleStat>,
+  /// The files only partially processed (hence the edits may be incomplete)
+  #[get = "pub"]
+  truncation: Truncation,
+  /// The errors that occurred during the run
+  #[get = "pub"]
+  errors: ErrorCounts,
+  /// Hints to debug the rules (e.g. the seed rules that never fired)
+  #[get = "pub"]
+  #[serde(default)]
+  diagnostics: Diagnostics,
+  /// The metadata of the run (e.g. the language and the substitutions)
+  #[get = "pub"]
+  metadata: BTreeMap<String, String>,
+}
+
+/// The files that were not (completely) processed
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, CopyGetters)]
+pub struct Truncation {
+  /// The number of files skipped, because they are larger than `max_file_size_bytes` or contain lines longer than `max_line_length`
+  #[get_copy = "pub"]
+  files_skipped: usize,
+  /// The number of files whose time budget (see `per_file_timeout_seconds`) expired before all the rules were applied
+  #[get_copy = "pub"]
+  files_timed_out: usize,
+}
+
+/// The number of errors, by kind
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, CopyGetters)]
+pub struct ErrorCounts {
+  /// The number of files with syntax errors
+  #[get_copy = "pub"]
+  files_with_parse_errors: usize,
+  /// The number of failed passes (or stages) of the run
+  #[get_copy = "pub"]
+  failures: usize,
+}
+
+/// Hints to debug the rules of the run
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Getters)]
+pub struct Diagnostics {
+  /// The seed rules that produced neither an edit nor a match, along with why
+  #[get = "pub"]
+  unapplied_seed_rules: BTreeMap<String, UnappliedSeedRule>,
+}
+
+/// Why a seed rule produced neither an edit nor a match (best effort)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum UnappliedSeedRule {
+  /// The query of the rule never matched
+  NeverMatched,
+  /// The query matched, but none of the matches satisfied the filters of the rule
+  FailedConstraints { count: usize },
+  /// The query matched, but only outside the lines the rules are applied to, or in suppressed regions
+  OutOfScope { count: usize },
+  /// The query matched, but the matches were skipped for other reasons (e.g. the edits were no-ops)
+  Skipped { count: usize },
+  /// The rule was not seeded (in the `cleanup_only` mode), since these holes are not substituted
+  UnsatisfiedHoles { holes: Vec<String> },
+}
+
+impl fmt::Display for UnappliedSeedRule {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      UnappliedSeedRule::NeverMatched => write!(f, "the query never matched"),
+      UnappliedSeedRule::FailedConstraints { count } => {
+        write!(f, "all the matches ({count}) failed the filters")
+      }
+      UnappliedSeedRule::OutOfScope { count } => {
+        write!(f, "all the matches ({count}) were out of scope")
+      }
+      UnappliedSeedRule::Skipped { count } => write!(f, "all the matches ({count}) were skipped"),
+      UnappliedSeedRule::UnsatisfiedHoles { holes } => write!(
+        f,
+        "it was not seeded, since its holes ({}) are not substituted",
+        holes.join(", ")
+      ),
+    }
+  }
+}
+
+/// Assembles the metrics as the files (and the passes) of the run complete, and writes them to `metrics_output` (if set)
+/// after each file and when dropped, i.e. also when the run panics or is killed partway.
+#[derive(Debug)]
+pub(crate) struct MetricsRecorder {
+  metrics_output: Option<String>,
+  started: Instant,
+  // The metrics of the completed passes (or stages)
+  totals: MetricsTotals,
+  // The metrics of the files processed by the current pass, replaced each time a file is processed again
+  pass_files: BTreeMap<PathBuf, FileMetrics>,
+}
+
+/// The metrics accumulated over the passes of a run
+#[derive(Debug, Clone, Default)]
+struct MetricsTotals {
+  metrics: RunMetrics,
+  // The files are counted once, even if several passes (or stages) process them
+  files_scanned: BTreeSet<PathBuf>,
+  files_edited: BTreeSet<PathBuf>,
+  files_deleted: BTreeSet<PathBuf>,
+  files_skipped: BTreeSet<PathBuf>,
+  files_timed_out: BTreeSet<PathBuf>,
+  files_with_parse_errors: BTreeSet<PathBuf>,
+}
+
+/// The metrics of a file (in a pass)
+#[derive(Debug, Clone, Default)]
+struct FileMetrics {
+  edits_by_rule: BTreeMap<String, usize>,
+  matches_by_rule: BTreeMap<String, usize>,
+  rule_statistics: HashMap<String, RuleStat>,
+  is_deleted: bool,
+  is_edited: bool,
+  is_timed_out: bool,
+  has_parse_errors: bool,
+}
+
+impl FileMetrics {
+  fn new(scu: &SourceCodeUnit, skip_counts: &BTreeMap<(String, SkipReason), usize>) -> Self {
+    let rule_statistics = scu.rule_statistics();
+    let mut rule_statistics_with_skipped_matches = rule_statistics.clone();
+    count_skipped_matches(&mut rule_statistics_with_skipped_matches, skip_counts);
+    FileMetrics {
+      edits_by_rule: rule_statistics
+        .iter()
+        .filter(|(_, s)| *s.applications() > 0)
+        .map(|(rule, s)| (rule.clone(), *s.applications()))
+        .collect(),
+      matches_by_rule: rule_statistics
+        .iter()
+        .filter(|(_, s)| *s.matches() > 0)
+        .map(|(rule, s)| (rule.clone(), *s.matches()))
+        .collect(),
+      rule_statistics: rule_statistics_with_skipped_matches,
+      is_deleted: scu.is_deleted_on_persist(),
+      is_edited: scu.code() != scu.original_content(),
+      is_timed_out: scu.timed_out_rule().is_some(),
+      has_parse_errors: scu.is_skipped(),
+    }
+  }
+}
+
+impl MetricsTotals {
+  fn add_file(&mut self, path: &Path, file: &FileMetrics) {
+    self.files_scanned.insert(path.to_path_buf());
+    for (rule, matches) in &file.matches_by_rule {
+      *self
+        .metrics
+        .matches_by_rule
+        .entry(rule.clone())
+        .or_default() += matches;
+    }
+    for (rule, edits) in &file.edits_by_rule {
+      *self.metrics.edits_by_rule.entry(rule.clone()).or_default() += edits;
+    }
+    for (rule, rule_stat) in &file.rule_statistics {
+      self
+        .metrics
+        .rule_statistics
+        .entry(rule.clone())
+        .or_default()
+        .add(rule_stat);
+    }
+    if file.is_deleted {
+      self.files_deleted.insert(path.to_path_buf());
+    } else if file.is_edited {
+      self.files_edited.insert(path.to_path_buf());
+    }
+    if file.is_timed_out {
+      self.files_timed_out.insert(path.to_path_buf());
+    }
+    if file.has_parse_errors {
+      self.files_with_parse_errors.insert(path.to_path_buf());
+    }
+  }
+}
+
+impl Default for MetricsRecorder {
+  fn default() -> Self {
+    MetricsRecorder {
+      metrics_output: None,
+      started: Instant::now(),
+      totals: MetricsTotals::default(),
+      pass_files: BTreeMap::new(),
+    }
+  }
+}
+
+impl MetricsRecorder {
+  pub(crate) fn new(piranha_arguments: &PiranhaArguments) -> Self {
+    let mut metadata = BTreeMap::from([
+      (
+        "piranha_version".to_string(),
+        env!("CARGO_PKG_VERSION").to_string(),
+      ),
+      ("language".to_string(), piranha_arguments.get_language()),
+      (
+        "path_to_codebase".to_string(),
+        piranha_arguments.path_to_codebase().to_string(),
+      ),
+      (
+        "dry_run".to_string(),
+        piranha_arguments.dry_run().to_string(),
+      ),
+    ]);
+    for (name, value) in piranha_arguments.input_substitutions() {
+      metadata.insert(format!("substitutions.{name}"), value);
+    }
+    MetricsRecorder {
+      metrics_output: piranha_arguments.metrics_output().clone(),
+      started: Instant::now(),
+      totals: MetricsTotals {
+        metrics: RunMetrics {
+          schema_version: METRICS_SCHEMA_VERSION,
+          metadata,
+          ..Default::default()
+        },
+        ..Default::default()
+      },
+      pass_files: BTreeMap::new(),
+    }
+  }
+
+  /// Records the outcome of the rules applied to a file of the current pass (or stage), along with the number of
+  /// candidate matches skipped in this file so far (per rule and reason), and writes the metrics.
+  pub(crate) fn record_file(
+    &mut self, scu: &SourceCodeUnit, skip_counts: &BTreeMap<(String, SkipReason), usize>,
+  ) {
+    self
+      .pass_files
+      .insert(scu.path().clone(), FileMetrics::new(scu, skip_counts));
+    self.write();
+  }
+
+  /// Records a file of the current pass (or stage) that was skipped (see `Truncation::files_skipped`), and writes the metrics.
+  pub(crate) fn record_skipped_file(&mut self, path: &Path) {
+    self.totals.files_skipped.insert(path.to_path_buf());
+    self.write();
+  }
+
+  /// Records the outcome of a pass (or stage), i.e. the `source_code_units` it processed (in their final state, along
+  /// with the number of their skipped candidate matches) and the `skipped_files`.
+  pub(crate) fn record_pass<'a>(
+    &mut self,
+    source_code_units: impl Iterator<Item = (&'a SourceCodeUnit, BTreeMap<(String, SkipReason), usize>)>,
+    skipped_files: impl Iterator<Item = &'a PathBuf>,
+  ) {
+    for (scu, skip_counts) in source_code_units {
+      self
+        .pass_files
+        .insert(scu.path().clone(), FileMetrics::new(scu, &skip_counts));
+    }
+    for (path, file) in std::mem::take(&mut self.pass_files) {
+      self.totals.add_file(&path, &file);
+    }
+    self.totals.files_skipped.extend(skipped_files.cloned());
+  }
+
+  /// Records the seed rules of a pass (or stage) that produced neither an edit nor a match.
+  pub(crate) fn record_unapplied_seed_rules(
+    &mut self, unapplied_seed_rules: BTreeMap<String, UnappliedSeedRule>,
+  ) {
+    self
+      .totals
+      .metrics
+      .diagnostics
+      .unapplied_seed_rules
+      .extend(unapplied_seed_rules);
+  }
+
+  /// Records a failed pass (or stage).
+  pub(crate) fn record_failure(&mut self) {
+    self.totals.metrics.errors.failures += 1;
+  }
+
+  /// Records that the run completed.
+  pub(crate) fn record_completion(&mut self) {
+    self.totals.metrics.completed = true;
+  }
+
+  /// The metrics recorded so far, including the files of the current pass.
+  pub(crate) fn metrics(&self) -> RunMetrics {
+    let mut totals = self.totals.clone();
+    for (path, file) in &self.pass_files {
+      totals.add_file(path, file);
+    }
+    let files_edited = totals
+      .files_edited
+      .difference(&totals.files_deleted)
+      .count();
+    RunMetrics {
+      duration_ms: self.started.elapsed().as_millis() as u64,
+      files_scanned: totals.files_scanned.union(&totals.files_skipped).count(),
+      files_edited,
+      files_deleted: totals.files_deleted.len(),
+      truncation: Truncation {
+        files_skipped: totals.files_skipped.len(),
+        files_timed_out: totals.files_timed_out.len(),
+      },
+      errors: ErrorCounts {
+        files_with_parse_errors: totals.files_with_parse_errors.len(),
+        ..totals.metrics.errors
+      },
+      // The rules applied in another pass (or stage) are not reported
+      diagnostics: Diagnostics {
+        unapplied_seed_rules: totals
+          .metrics
+          .diagnostics
+          .unapplied_seed_rules
+          .iter()
+          .filter(|(rule, _)| {
+            !totals.metrics.edits_by_rule.contains_key(*rule)
+              && !totals.metrics.matches_by_rule.contains_key(*rule)
+          })
+          .map(|(rule, reason)| (rule.clone(), reason.clone()))
+          .collect(),
+      },
+      ..totals.metrics
+    }
+  }
+
+  /// Writes the metrics recorded so far to `metrics_output` (if set).
+  /// Best effort: failing to write the metrics does not fail the run.
+  fn write(&self) {
+    let Some(path) = &self.metrics_output else {
+      return;
+    };
+    let written = serde_json::to_string_pretty(&self.metrics())
+      .map_err(|e| e.to_string())
+      .and_then(|metrics| fs::write(path, metrics).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+      warn!("Could not write the metrics to {path} - {e}");
+    }
+  }
+}
+
+impl Drop for MetricsRecorder {
+  fn drop(&mut self) {
+    self.write();
+  }
+}
+
+#[cfg(test)]
+#[path = "unit_tests/metrics_test.rs"]
+mod metrics_test;
@@ -12,19 +12,34 @@ Copyright (c) 2023 Uber Technologies, Inc.
 */
 
 pub(crate) mod capture_group_patterns;
+pub mod cst_pattern;
 pub(crate) mod default_configs;
-pub(crate) mod edit;
+pub mod edit;
+pub mod edit_interceptor;
 pub(crate) mod filter;
-pub(crate) mod language;
-pub(crate) mod matches;
+pub mod language;
+pub mod matches;
+pub mod metrics;
+pub(crate) mod negative_cache;
 pub(crate) mod outgoing_edges;
+pub mod pack_comparison;
+pub(crate) mod package_filter;
 pub mod piranha_arguments;
 pub mod piranha_output;
+pub mod piranha_plugin;
+pub mod progress;
+pub mod render;
 pub(crate) mod rule;
 pub(crate) mod rule_graph;
-pub(crate) mod rule_store;
+pub mod rule_store;
+pub mod schema;
 pub(crate) mod scopes;
-pub(crate) mod source_code_unit;
+pub mod skipped_match;
+pub mod source_code_unit;
+pub mod source_code_view;
+pub mod stage;
+pub(crate) mod suppression;
+pub mod xpath;
 
 pub(crate) trait Validator {
   fn validate(&self) -> Result<(), String>;
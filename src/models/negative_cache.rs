@@ -0,0 +1,148 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+//! The negative cache records (on the disk) the files that had no match for a set of rules, so that
+//! subsequent runs with the same rules neither parse nor match them again.
+//!
+//! Each entry is an empty file `<cache_dir>/<rule set hash>/<content hash>`, where the rule set hash
+//! covers the version of Piranha, the arguments affecting the matching and the instantiated rules.
+//! Both are sha256 hashes, so that an entry is never shared by two files (or rule sets), and stays valid across
+//! the builds of Piranha.
+//! Hence, changing any of these (or the content of the file) automatically invalidates the entry.
+
+use std::{
+  collections::{HashMap, HashSet},
+  fs,
+  hash::Hash,
+  path::{Path, PathBuf},
+};
+
+use itertools::Itertools;
+use log::{debug, warn};
+
+use super::{
+  piranha_arguments::PiranhaArguments, rule::InstantiatedRule, source_code_unit::SourceCodeUnit,
+};
+use crate::utilities::Sha256Hasher;
+
+pub(crate) struct NegativeCache {
+  // The directory containing the cache entries
+  cache_dir: PathBuf,
+  // The hash of the version of Piranha and the arguments affecting the matching
+  arguments_hash: String,
+  // For each file looked up, the hash of its content and the hashes of the rule sets it was looked up with
+  lookups: HashMap<PathBuf, (String, HashSet<String>)>,
+  // The files that were not parsed (nor matched) because of a cache hit
+  hits: HashSet<PathBuf>,
+}
+
+impl NegativeCache {
+  /// Returns the negative cache if `cache_dir` is set.
+  pub(crate) fn new(piranha_arguments: &PiranhaArguments) -> Option<NegativeCache> {
+    let cache_dir = piranha_arguments.cache_dir().as_ref()?;
+    let mut hasher = Sha256Hasher::default();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    piranha_arguments.language().extension().hash(&mut hasher);
+    piranha_arguments
+      .input_substitutions()
+      .iter()
+      .sorted()
+      .for_each(|s| s.hash(&mut hasher));
+    piranha_arguments.allow_dirty_ast().hash(&mut hasher);
+    piranha_arguments.on_parse_error().hash(&mut hasher);
+    Some(NegativeCache {
+      cache_dir: PathBuf::from(cache_dir),
+      arguments_hash: hasher.digest(),
+      lookups: HashMap::new(),
+      hits: HashSet::new(),
+    })
+  }
+
+  /// The hash of the `rules` (independently of their order), along with the arguments affecting the matching.
+  pub(crate) fn rule_set_hash(&self, rules: &[InstantiatedRule]) -> String {
+    let mut hasher = Sha256Hasher::default();
+    self.arguments_hash.hash(&mut hasher);
+    rules
+      .iter()
+      .map(|r| {
+        let mut rule_hasher = Sha256Hasher::default();
+        r.rule().hash(&mut rule_hasher);
+        r.substitutions()
+          .iter()
+          .sorted()
+          .for_each(|s| s.hash(&mut rule_hasher));
+        rule_hasher.digest()
+      })
+      .sorted()
+      .for_each(|r| r.hash(&mut hasher));
+    hasher.digest()
+  }
+
+  /// Checks if the file `path` (with the `content`) had no match for the rules (with the `rule_set_hash`) in a previous run.
+  /// The lookup is remembered, so that the file can be recorded (see `persist`) if it has no match in this run either.
+  pub(crate) fn lookup(&mut self, path: &Path, content: &str, rule_set_hash: &str) -> bool {
+    let mut hasher = Sha256Hasher::default();
+    content.hash(&mut hasher);
+    let content_hash = hasher.digest();
+    let entry_path = self.entry_path(rule_set_hash, &content_hash);
+    let (_, rule_set_hashes) = self
+      .lookups
+      .entry(path.to_path_buf())
+      .or_insert_with(|| (content_hash, HashSet::new()));
+    rule_set_hashes.insert(rule_set_hash.to_string());
+    entry_path.exists()
+  }
+
+  /// Records that a file was not parsed (nor matched) because of a cache hit.
+  pub(crate) fn record_hit(&mut self, path: &Path) {
+    debug!("Negative cache hit for {:?}", path);
+    self.hits.insert(path.to_path_buf());
+  }
+
+  /// The number of files that were not parsed (nor matched) because of a cache hit.
+  pub(crate) fn number_of_hits(&self) -> usize {
+    self.hits.len()
+  }
+
+  /// Adds an entry for each rule set that the looked up files without any match or rewrite (in `source_code_units`) were matched against.
+  pub(crate) fn persist(&self, source_code_units: &HashMap<PathBuf, SourceCodeUnit>) {
+    for (path, (content_hash, rule_set_hashes)) in &self.lookups {
+      let Some(scu) = source_code_units.get(path) else {
+        continue;
+      };
+      if scu.is_skipped()
+        || scu.timed_out_rule().is_some()
+        || !scu.matches().is_empty()
+        || !scu.rewrites().is_empty()
+        || !scu.suggested_rewrites().is_empty()
+      {
+        continue;
+      }
+      for rule_set_hash in rule_set_hashes {
+        let entry_path = self.entry_path(rule_set_hash, content_hash);
+        let result =
+          fs::create_dir_all(entry_path.parent().unwrap()).and_then(|_| fs::write(&entry_path, ""));
+        if let Err(e) = result {
+          warn!(
+            "Could not write the negative cache entry {:?} - {}",
+            entry_path, e
+          );
+        }
+      }
+    }
+  }
+
+  fn entry_path(&self, rule_set_hash: &str, content_hash: &str) -> PathBuf {
+    self.cache_dir.join(rule_set_hash).join(content_hash)
+  }
+}
@@ -13,57 +13,148 @@ Copyright (c) 2023 Uber Technologies, Inc.
 
 use derive_builder::Builder;
 use getset::Getters;
-use pyo3::prelude::{pyclass, pymethods};
-use serde_derive::Deserialize;
+use pyo3::{
+  prelude::{pyclass, pymethods},
+  FromPyObject, IntoPy, PyObject, Python,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize as _, Deserializer, Serialize as _, Serializer};
+use serde_derive::{Deserialize, Serialize};
 
-use crate::utilities::gen_py_str_methods;
-#[derive(Deserialize, Debug, Clone, Hash, PartialEq, Eq, Default)]
-// Represents the `edges.toml` file
+use crate::{models::language::built_in_name, utilities::gen_py_str_methods};
+#[derive(Deserialize, JsonSchema, Debug, Clone, Hash, PartialEq, Eq, Default)]
+// Represents the `edges.toml` file (whose other tables are ignored, since the exported rules are both a valid
+// `rules.toml` and `edges.toml`)
 pub(crate) struct Edges {
   pub(crate) edges: Vec<OutgoingEdges>,
 }
 
 // Captures an entry from the `edges.toml` file.
-#[derive(Deserialize, Debug, Clone, Hash, PartialEq, Eq, Default, Getters, Builder)]
+#[derive(
+  Serialize, Deserialize, JsonSchema, Debug, Clone, Hash, PartialEq, Eq, Default, Getters, Builder,
+)]
+#[serde(deny_unknown_fields)]
 #[pyclass]
 pub struct OutgoingEdges {
   /// The source rule or group of rules
   #[get = "pub with_prefix"]
-  #[serde(alias = "from")]
+  #[serde(alias = "from", rename(serialize = "from"))]
+  #[schemars(rename = "from")]
   #[pyo3(get)]
   frm: String,
   /// The target edges or groups of edges
   #[get = "pub with_prefix"]
   #[pyo3(get)]
   to: Vec<String>,
-  /// The scope label for the edge
+  /// The scope labels for the edge (the next rules are applied in each of these scopes)
   #[get = "pub with_prefix"]
-  #[pyo3(get)]
-  scope: String,
+  #[serde(
+    deserialize_with = "deserialize_scope_labels",
+    serialize_with = "serialize_scope_labels"
+  )]
+  #[schemars(with = "ScopeLabels")]
+  scope: Vec<String>,
+}
+
+/// The scope of an edge - either a single scope label or a list of scope labels.
+#[derive(Serialize, Deserialize, JsonSchema, FromPyObject)]
+#[serde(untagged)]
+enum ScopeLabels {
+  One(String),
+  Many(Vec<String>),
+}
+
+impl From<&[String]> for ScopeLabels {
+  fn from(scopes: &[String]) -> Self {
+    match scopes {
+      [scope] => ScopeLabels::One(scope.to_string()),
+      _ => ScopeLabels::Many(scopes.to_vec()),
+    }
+  }
+}
+
+impl IntoPy<PyObject> for ScopeLabels {
+  fn into_py(self, py: Python<'_>) -> PyObject {
+    match self {
+      ScopeLabels::One(scope) => scope.into_py(py),
+      ScopeLabels::Many(scopes) => scopes.into_py(py),
+    }
+  }
+}
+
+impl From<ScopeLabels> for Vec<String> {
+  fn from(scope_labels: ScopeLabels) -> Self {
+    match scope_labels {
+      ScopeLabels::One(scope) => vec![scope],
+      ScopeLabels::Many(scopes) => scopes,
+    }
+  }
+}
+
+fn deserialize_scope_labels<'de, D: Deserializer<'de>>(
+  deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+  ScopeLabels::deserialize(deserializer).map(Vec::from)
+}
+
+// An edge with a single scope is written as `scope = "Parent"` (like in the `edges.toml` files)
+fn serialize_scope_labels<S: Serializer>(
+  scopes: &[String], serializer: S,
+) -> Result<S::Ok, S::Error> {
+  ScopeLabels::from(scopes).serialize(serializer)
 }
 
 #[macro_export]
 macro_rules! edges {
+  (from = $from: expr, to = [$($to: expr)*], scope = [$($scope: expr)*]) => {
+    $crate::models::outgoing_edges::OutgoingEdgesBuilder::default()
+    .frm($from.to_string())
+    .to(vec![$($to.to_string())*])
+    .scope(vec![$($scope.to_string()),*])
+    .build().unwrap()
+  };
   (from = $from: expr, to = [$($to: expr)*], scope = $scope: expr) => {
     $crate::models::outgoing_edges::OutgoingEdgesBuilder::default()
     .frm($from.to_string())
     .to(vec![$($to.to_string())*])
-    .scope($scope.to_string())
+    .scope(vec![$scope.to_string()])
     .build().unwrap()
   };
 }
 
+impl OutgoingEdges {
+  /// The built-in edge of the `language` (other than the language of the run), between the rules (or groups)
+  /// prefixed by the `language` (see `Rule::for_language`)
+  pub(crate) fn for_language(&self, language: &str) -> OutgoingEdges {
+    OutgoingEdges {
+      frm: built_in_name(language, self.get_frm()),
+      to: self
+        .get_to()
+        .iter()
+        .map(|to| built_in_name(language, to))
+        .collect(),
+      scope: self.get_scope().clone(),
+    }
+  }
+}
+
 #[pymethods]
 impl OutgoingEdges {
   #[new]
-  fn py_new(from: String, to: Vec<String>, scope: String) -> Self {
+  fn py_new(from: String, to: Vec<String>, scope: ScopeLabels) -> Self {
     OutgoingEdgesBuilder::default()
       .frm(from)
       .to(to)
-      .scope(scope)
+      .scope(scope.into())
       .build()
       .unwrap()
   }
 
+  /// The scope label for the edge, or the list of scope labels if there are several (i.e. as it was specified)
+  #[getter]
+  fn scope(&self) -> ScopeLabels {
+    ScopeLabels::from(self.scope.as_slice())
+  }
+
   gen_py_str_methods!();
 }
@@ -0,0 +1,241 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+//! Compares the behavior of two versions of a rule pack over a corpus of representative code (see `compare_packs`),
+//! to tell whether the new version requires a version bump.
+
+use std::{
+  collections::{BTreeMap, HashMap},
+  path::{Path, PathBuf},
+  str::FromStr,
+};
+
+use getset::{CopyGetters, Getters};
+use serde_derive::Serialize;
+
+use super::{
+  language::PiranhaLanguage,
+  piranha_arguments::{PiranhaArguments, PiranhaArgumentsBuilder},
+  piranha_output::PiranhaOutputSummary,
+};
+use crate::execute_piranha_with_plugins;
+
+/// How the edits of the new version of a rule pack differ from the edits of the old version (ordered by severity)
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum BehavioralChange {
+  /// The same edits are applied to the corpus
+  Unchanged,
+  /// All the edits of the old version are applied, along with new ones
+  Additive,
+  /// Some edits of the old version are modified or not applied anymore
+  Modified,
+}
+
+impl BehavioralChange {
+  /// The semantic version bump suggested for the rule pack
+  pub fn suggested_version_bump(&self) -> &'static str {
+    match self {
+      BehavioralChange::Unchanged => "patch",
+      BehavioralChange::Additive => "minor",
+      BehavioralChange::Modified => "major",
+    }
+  }
+
+  /// The exit code of `compare-packs`, so that CI can require a version bump for the behavioral changes
+  /// (`1` is reserved for the errors)
+  pub fn exit_code(&self) -> i32 {
+    match self {
+      BehavioralChange::Unchanged => 0,
+      BehavioralChange::Additive => 2,
+      BehavioralChange::Modified => 3,
+    }
+  }
+}
+
+/// An edit applied by a rule pack, as compared between the versions (i.e. regardless of the name of its rule)
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Getters, CopyGetters)]
+pub struct PackEdit {
+  /// The line of the start of the code replaced by the edit (1-based), when the edit is applied
+  #[get_copy = "pub"]
+  line: usize,
+  /// The column of the start of the code replaced by the edit (1-based), when the edit is applied
+  #[get_copy = "pub"]
+  column: usize,
+  /// The code replaced by the edit
+  #[get = "pub"]
+  matched_string: String,
+  /// The replacement of the code
+  #[get = "pub"]
+  replacement: String,
+}
+
+/// The edits of a file of the corpus that differ between the versions of the rule pack
+#[derive(Serialize, Debug, Clone, Getters, CopyGetters)]
+pub struct FileComparison {
+  /// The path of the file, relative to the corpus
+  #[get = "pub"]
+  path: String,
+  #[get_copy = "pub"]
+  change: BehavioralChange,
+  /// The edits only applied by the new version
+  #[get = "pub"]
+  added_edits: Vec<PackEdit>,
+  /// The edits only applied by the old version
+  #[get = "pub"]
+  removed_edits: Vec<PackEdit>,
+}
+
+impl FileComparison {
+  /// Compares the edits of the old version (`edits_a`) with the edits of the new one (`edits_b`), as multisets
+  /// (i.e. applying an edit one more time is an addition).
+  pub(crate) fn new(path: &str, edits_a: &[PackEdit], edits_b: &[PackEdit]) -> Self {
+    let mut counts: HashMap<&PackEdit, isize> = HashMap::new();
+    for edit in edits_b {
+      *counts.entry(edit).or_default() += 1;
+    }
+    for edit in edits_a {
+      *counts.entry(edit).or_default() -= 1;
+    }
+    let (mut added_edits, mut removed_edits) = (Vec::new(), Vec::new());
+    for (edit, count) in counts {
+      let edits = if count > 0 {
+        &mut added_edits
+      } else {
+        &mut removed_edits
+      };
+      for _ in 0..count.unsigned_abs() {
+        edits.push(edit.clone());
+      }
+    }
+    added_edits.sort();
+    removed_edits.sort();
+    let change = if !removed_edits.is_empty() {
+      BehavioralChange::Modified
+    } else if !added_edits.is_empty() {
+      BehavioralChange::Additive
+    } else {
+      BehavioralChange::Unchanged
+    };
+    FileComparison {
+      path: path.to_string(),
+      change,
+      added_edits,
+      removed_edits,
+    }
+  }
+}
+
+/// The result of comparing two versions of a rule pack over a corpus (see `compare_packs`)
+#[derive(Serialize, Debug, Clone, Getters, CopyGetters)]
+pub struct PackComparison {
+  /// The most severe change over the files of the corpus
+  #[get_copy = "pub"]
+  change: BehavioralChange,
+  #[get = "pub"]
+  suggested_version_bump: String,
+  /// The files whose edits differ between the versions (ordered by path)
+  #[get = "pub"]
+  files: Vec<FileComparison>,
+}
+
+impl PackComparison {
+  /// Compares the edits applied to each file of the corpus by the old version (`edits_a`) and the new one (`edits_b`).
+  pub(crate) fn new(
+    edits_a: &BTreeMap<String, Vec<PackEdit>>, edits_b: &BTreeMap<String, Vec<PackEdit>>,
+  ) -> Self {
+    let mut paths = edits_a.keys().chain(edits_b.keys()).collect::<Vec<_>>();
+    paths.sort();
+    paths.dedup();
+    let files = paths
+      .into_iter()
+      .map(|path| {
+        let edits =
+          |edits: &BTreeMap<String, Vec<PackEdit>>| edits.get(path).cloned().unwrap_or_default();
+        FileComparison::new(path, &edits(edits_a), &edits(edits_b))
+      })
+      .filter(|file| file.change != BehavioralChange::Unchanged)
+      .collect::<Vec<_>>();
+    let change = files
+      .iter()
+      .map(|file| file.change)
+      .max()
+      .unwrap_or(BehavioralChange::Unchanged);
+    PackComparison {
+      change,
+      suggested_version_bump: change.suggested_version_bump().to_string(),
+      files,
+    }
+  }
+}
+
+/// Runs the rule packs `pack_a` (the old version) and `pack_b` (the new version), i.e. directories with their
+/// `rules.toml` and `edges.toml`, over the `corpus` (without rewriting it) and compares the edits applied to each
+/// file. The substitutions of the rules are read from the `input_substitutions_file`, if any.
+/// Returns an error if the language is unknown, or if a rule pack is invalid.
+pub fn compare_packs(
+  pack_a: &Path, pack_b: &Path, corpus: &Path, language: &str,
+  input_substitutions_file: Option<PathBuf>,
+) -> Result<PackComparison, String> {
+  let edits = |pack: &Path| {
+    let piranha_arguments = PiranhaArgumentsBuilder::default()
+      .path_to_codebase(corpus.to_string_lossy().to_string())
+      .path_to_configurations(pack.to_string_lossy().to_string())
+      .language(PiranhaLanguage::from_str(language)?)
+      .input_substitutions_file(input_substitutions_file.clone())
+      .dry_run(true)
+      .try_build()
+      .map_err(|e| format!("Could not load the rule pack {pack:?} - {e}"))?;
+    run_pack(&piranha_arguments, corpus)
+  };
+  Ok(PackComparison::new(&edits(pack_a)?, &edits(pack_b)?))
+}
+
+/// The edits applied by the rule pack to each file of the `corpus`, by path relative to it.
+fn run_pack(
+  piranha_arguments: &PiranhaArguments, corpus: &Path,
+) -> Result<BTreeMap<String, Vec<PackEdit>>, String> {
+  let summaries = execute_piranha_with_plugins(piranha_arguments, vec![])?;
+  Ok(
+    summaries
+      .iter()
+      .map(|summary| (relative_path(summary, corpus), pack_edits(summary)))
+      .collect(),
+  )
+}
+
+fn relative_path(summary: &PiranhaOutputSummary, corpus: &Path) -> String {
+  let path = Path::new(summary.path());
+  path
+    .strip_prefix(corpus)
+    .unwrap_or(path)
+    .to_string_lossy()
+    .to_string()
+}
+
+fn pack_edits(summary: &PiranhaOutputSummary) -> Vec<PackEdit> {
+  summary
+    .rewrites()
+    .iter()
+    .map(|edit| PackEdit {
+      line: edit.p_match().range().start_point.row + 1,
+      column: edit.p_match().range().start_point.column + 1,
+      matched_string: edit.p_match().matched_string().to_string(),
+      replacement: edit.replacement_string().to_string(),
+    })
+    .collect()
+}
+
+#[cfg(test)]
+#[path = "unit_tests/pack_comparison_test.rs"]
+mod pack_comparison_test;
@@ -0,0 +1,115 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use std::collections::HashMap;
+
+use getset::Getters;
+use pyo3::prelude::{pyclass, pymethods};
+use regex::Regex;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::utilities::{cached_regex, gen_py_str_methods, Instantiate};
+
+use super::Validator;
+
+/// The reserved tag bound to the package declared in the file (if any), e.g. `@__package__` in a replacement or a filter
+pub(crate) const PACKAGE_TAG: &str = "__package__";
+
+/// Restricts a rule to the files whose declared package (or module, or namespace) matches.
+/// The rule is not applied to the files without a package declaration.
+#[derive(
+  Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq, Eq, Hash, Getters,
+)]
+#[serde(deny_unknown_fields)]
+#[pyclass]
+pub struct PackageFilter {
+  /// The declared package should be equal to this (if not empty)
+  #[get = "pub"]
+  #[serde(default)]
+  #[pyo3(get)]
+  exact: String,
+  /// The declared package should start with this (if not empty)
+  #[get = "pub"]
+  #[serde(default)]
+  #[pyo3(get)]
+  prefix: String,
+  /// The declared package should match this regex (if not empty)
+  #[get = "pub"]
+  #[serde(default)]
+  #[pyo3(get)]
+  regex: String,
+}
+
+impl PackageFilter {
+  /// Checks if the `package` declared in a file satisfies all the (non empty) conditions of this filter.
+  pub(crate) fn is_satisfied_by(&self, package: Option<&str>) -> bool {
+    let Some(package) = package else {
+      return false;
+    };
+    // The regex is validated when the rules are loaded (and its holes are substituted with escaped values), hence
+    // it is only compiled once (see `cached_regex`)
+    (self.exact.is_empty() || package == self.exact)
+      && (self.prefix.is_empty() || package.starts_with(&self.prefix))
+      && (self.regex.is_empty()
+        || cached_regex(&self.regex).is_ok_and(|regex| regex.is_match(package)))
+  }
+}
+
+#[pymethods]
+impl PackageFilter {
+  #[new]
+  fn py_new(exact: Option<String>, prefix: Option<String>, regex: Option<String>) -> Self {
+    PackageFilter {
+      exact: exact.unwrap_or_default(),
+      prefix: prefix.unwrap_or_default(),
+      regex: regex.unwrap_or_default(),
+    }
+  }
+  gen_py_str_methods!();
+}
+
+impl Validator for PackageFilter {
+  fn validate(&self) -> Result<(), String> {
+    if self.exact.is_empty() && self.prefix.is_empty() && self.regex.is_empty() {
+      return Err("A package filter should specify `exact`, `prefix` or `regex`".to_string());
+    }
+    if let Err(e) = Regex::new(&self.regex) {
+      return Err(format!(
+        "Invalid package filter regex - {} \n {e}",
+        self.regex
+      ));
+    }
+    Ok(())
+  }
+}
+
+impl Instantiate for PackageFilter {
+  /// Fills the holes in `exact`, `prefix` and `regex` with the `substitutions` (escaped in the `regex`, so that they
+  /// are matched literally).
+  fn instantiate(&self, substitutions: &HashMap<String, String>) -> PackageFilter {
+    let escaped_substitutions = substitutions
+      .iter()
+      .map(|(tag, value)| (tag.to_string(), regex::escape(value)))
+      .collect();
+    PackageFilter {
+      exact: self.exact.instantiate(substitutions),
+      prefix: self.prefix.instantiate(substitutions),
+      regex: self.regex.instantiate(&escaped_substitutions),
+    }
+  }
+}
+
+#[cfg(test)]
+#[path = "unit_tests/package_filter_test.rs"]
+mod package_filter_test;
@@ -12,37 +12,74 @@ Copyright (c) 2023 Uber Technologies, Inc.
 */
 
 use super::{
+  capture_group_patterns::CGPattern,
   default_configs::{
-    default_allow_dirty_ast, default_cleanup_comments, default_cleanup_comments_buffer,
-    default_code_snippet, default_delete_consecutive_new_lines, default_delete_file_if_empty,
-    default_dry_run, default_exclude, default_global_tag_prefix, default_include,
-    default_number_of_ancestors_in_parent_scope, default_path_to_codebase,
-    default_path_to_configurations, default_path_to_output_summaries, default_piranha_language,
-    default_rule_graph, default_substitutions, GO, JAVA, KOTLIN, PYTHON, SWIFT, TSX, TYPESCRIPT,
+    default_allow_dirty_ast, default_cache_dir, default_cleanup_comments,
+    default_cleanup_comments_buffer, default_cleanup_only, default_code_snippet,
+    default_comment_deletion_in_all_files, default_comment_deletion_patterns,
+    default_delete_consecutive_new_lines, default_delete_file_if_empty, default_dry_run,
+    default_dump_graph, default_edit_interceptor, default_exclude, default_explain,
+    default_export_rules, default_global_tag_prefix, default_include,
+    default_input_substitutions_file, default_matches_output, default_max_file_size_bytes,
+    default_max_line_length, default_max_propagation_depth, default_metrics_output,
+    default_normalize_line_endings, default_number_of_ancestors_in_parent_scope,
+    default_omit_matches_from_summary, default_on_parse_error, default_output_archive,
+    default_output_format, default_output_patch, default_path_to_codebase,
+    default_path_to_configurations, default_path_to_output_summaries,
+    default_pbxproj_references_to_remove, default_per_file_timeout_seconds,
+    default_piranha_language, default_process_long_lines, default_progress, default_progress_sink,
+    default_rematch_slack, default_report_capture_ranges, default_rule_graph,
+    default_rules_from_comments, default_scope_query_cache_size, default_stage,
+    default_strict_builtins, default_strict_substitutions, default_substitutions,
+    default_trace_propagation, default_verbose, default_whitespace_sensitivity, C_SHARP, GO, JAVA,
+    KOTLIN, PROTO, PYTHON, SWIFT, THRIFT, TSX, TYPESCRIPT,
   },
+  edit_interceptor::EditInterceptor,
   language::PiranhaLanguage,
-  rule_graph::{read_user_config_files, RuleGraph, RuleGraphBuilder},
-  source_code_unit::SourceCodeUnit,
+  piranha_output::OutputFormat,
+  progress::{ProgressBar, ProgressEvent, ProgressSink},
+  rule::Rule,
+  rule_graph::{
+    compile_cst_queries, load_query_files, read_comment_rules, read_user_config_files, RuleGraph,
+    RuleGraphBuilder,
+  },
+  source_code_unit::{with_line_ending, LineEndings, OnParseError, SourceCodeUnit},
+};
+use crate::utilities::{
+  archive::ArchiveFormat,
+  codebase::Codebase,
+  delete_consecutive_new_lines, get_query_significant_characters, has_long_lines,
+  parse_glob_pattern, parse_key_val, read_file,
+  tree_sitter_utilities::{differ_in_whitespace_only, normalize_snippet, WhitespaceSensitivity},
+  Instantiate,
 };
-use crate::utilities::{parse_glob_pattern, parse_key_val};
-use clap::builder::TypedValueParser;
+use clap::builder::{ArgPredicate, TypedValueParser};
 use clap::Parser;
+use clap::ValueEnum;
 use derive_builder::Builder;
 use getset::{CopyGetters, Getters};
 use glob::Pattern;
 use itertools::Itertools;
 use log::{info, warn};
 use pyo3::{
+  exceptions::PyValueError,
   prelude::{pyclass, pymethods},
   types::PyDict,
+  PyResult,
 };
 use regex::Regex;
+use schemars::JsonSchema;
 
-use std::collections::HashMap;
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
 
 /// A refactoring tool that eliminates dead code related to stale feature flags
-#[derive(Clone, Getters, CopyGetters, Debug, Parser, Builder)]
+#[derive(Clone, Getters, CopyGetters, Debug, Parser, Builder, JsonSchema)]
 #[clap(name = "Piranha")]
+#[schemars(default = "PiranhaArguments::unbuilt_default")]
 #[pyclass]
 #[builder(build_fn(name = "create"))]
 pub struct PiranhaArguments {
@@ -56,12 +93,14 @@ pub struct PiranhaArguments {
   #[get = "pub"]
   #[builder(default = "default_include()")]
   #[clap(long, value_parser = parse_glob_pattern, num_args = 0.., required=false)]
+  #[schemars(with = "Vec<String>")]
   include: Vec<Pattern>,
 
   /// Paths to exclude (as glob patterns)
   #[get = "pub"]
   #[builder(default = "default_exclude()")]
   #[clap(long, value_parser = parse_glob_pattern, num_args = 0.., required=false)]
+  #[schemars(with = "Vec<String>")]
   exclude: Vec<Pattern>,
 
   /// Code snippet to transform
@@ -71,15 +110,35 @@ pub struct PiranhaArguments {
   code_snippet: String,
 
   /// These substitutions instantiate the initial set of rules.
-  /// Usage : -s stale_flag_name=SOME_FLAG -s namespace=SOME_NS1
+  /// Usage : -s stale_flag_name=SOME_FLAG -s namespace=SOME_NS1 (or -s stale_flag_name=FLAG_A -s stale_flag_name=FLAG_B to instantiate the rules once per flag)
   #[builder(default = "default_substitutions()")]
   #[clap(short = 's', value_parser = parse_key_val)]
+  #[schemars(with = "HashMap<String, String>")]
   substitutions: Vec<(String, String)>,
 
+  /// Rejects the substitutions whose values contain characters significant to the tree-sitter query syntax
+  /// (`@`, `#`, unbalanced quotes or parentheses), instead of warning about them
+  #[get = "pub"]
+  #[builder(default = "default_strict_substitutions()")]
+  #[clap(long, default_value_t = default_strict_substitutions())]
+  strict_substitutions: bool,
+
+  /// Path to a JSON object or TOML table of substitutions (e.g. `{"stale_flag_name": "SOME_FLAG"}`), merged with
+  /// the substitutions passed with `-s` (which take precedence)
+  #[get = "pub"]
+  #[builder(default = "default_input_substitutions_file()")]
+  #[clap(long)]
+  input_substitutions_file: Option<PathBuf>,
+
   /// Directory containing the configuration files -  `rules.toml` and  `edges.toml` (optional)
   #[get = "pub"]
   #[builder(default = "default_path_to_configurations()")]
-  #[clap(short = 'f', long)]
+  #[clap(
+    short = 'f',
+    long,
+    required_unless_present = "cleanup_only",
+    default_value_if("cleanup_only", ArgPredicate::IsPresent, "")
+  )]
   path_to_configurations: String,
 
   /// Path to output summary json file
@@ -87,11 +146,114 @@ pub struct PiranhaArguments {
   #[builder(default = "default_path_to_output_summaries()")]
   #[clap(short = 'j', long)]
   path_to_output_summary: Option<String>,
+
+  /// The format of the output summary file - `json`, or `csv` with one row per rewrite (file, rule, lines, matched and replacement text)
+  #[get = "pub"]
+  #[builder(default = "default_output_format()")]
+  #[clap(long, value_enum, default_value_t = default_output_format())]
+  output_format: OutputFormat,
+
+  /// Path to the file where the rule graph should be dumped (DOT format, or Mermaid if the extension is `.mmd`)
+  #[get = "pub"]
+  #[builder(default = "default_dump_graph()")]
+  #[clap(long)]
+  dump_graph: Option<String>,
+
+  /// Path to the JSON Lines file where the matches of the match-only rules should be written, as soon as each file is processed
+  #[get = "pub"]
+  #[builder(default = "default_matches_output()")]
+  #[clap(long)]
+  matches_output: Option<String>,
+
+  /// Does not report the matches of the match-only rules in the output summary (e.g. when they are written to `matches_output`)
+  #[get = "pub"]
+  #[builder(default = "default_omit_matches_from_summary()")]
+  #[clap(long, default_value_t = default_omit_matches_from_summary())]
+  omit_matches_from_summary: bool,
+
+  /// Path to the JSON file where the metrics of the run (e.g. the number of files edited and the edits by rule) should be written,
+  /// even if the run fails partway
+  #[get = "pub"]
+  #[builder(default = "default_metrics_output()")]
+  #[clap(long)]
+  metrics_output: Option<String>,
+
+  /// Directory of the negative cache, which records the files that had no match (for the same rules) in a previous run
+  #[get = "pub"]
+  #[builder(default = "default_cache_dir()")]
+  #[clap(long)]
+  cache_dir: Option<String>,
+
+  /// Path to the file where the (user defined) rules and edges should be exported as TOML (e.g. to reuse the rules built via the API)
+  #[get = "pub"]
+  #[builder(default = "default_export_rules()")]
+  #[clap(long)]
+  export_rules: Option<String>,
+
+  /// The time budget (in seconds) for applying the rules to a file. Once exceeded, the remaining rules are not applied to the file (the edits already applied are kept)
+  #[get = "pub"]
+  #[builder(default = "default_per_file_timeout_seconds()")]
+  #[clap(long)]
+  per_file_timeout_seconds: Option<u64>,
+
+  /// Reports the range of each node captured by the matches (for each tag) in the output summary
+  #[get = "pub"]
+  #[builder(default = "default_report_capture_ranges()")]
+  #[clap(long, default_value_t = default_report_capture_ranges())]
+  report_capture_ranges: bool,
+
+  /// Files larger than this (in bytes), like generated files, are skipped
+  #[get = "pub"]
+  #[builder(default = "default_max_file_size_bytes()")]
+  #[clap(long)]
+  max_file_size_bytes: Option<usize>,
+
+  /// Path to the file where the changes should be written as a patch (unified diff in the git format), e.g. to apply them later with `git apply`.
+  /// The files are also rewritten, unless `dry_run` is enabled
+  #[get = "pub"]
+  #[builder(default = "default_output_patch()")]
+  #[clap(long)]
+  output_patch: Option<String>,
+
+  /// Path to the archive where the files of the code base should be written (with the changes), when `path_to_codebase`
+  /// is an archive (`.zip`, `.tar.gz` or `.tgz`). The archive has the same format, and the untouched entries are copied as is.
+  /// Nothing is written in the `dry_run` mode
+  #[get = "pub"]
+  #[builder(default = "default_output_archive()")]
+  #[clap(long)]
+  output_archive: Option<String>,
+
+  /// The maximum number of "Parent" cleanups chained after an edit (each one triggered by the previous one).
+  /// Once exceeded, the chain is stopped (e.g. for rules rewriting each other back and forth) and noted in the output summary
+  #[get = "pub"]
+  #[builder(default = "default_max_propagation_depth()")]
+  #[clap(long, default_value_t = default_max_propagation_depth())]
+  max_propagation_depth: usize,
+
+  /// Reports the chain of "Parent" cleanups (rule and range) applied after each edit in the output summary, e.g. to debug the cleanups
+  #[get = "pub"]
+  #[builder(default = "default_trace_propagation()")]
+  #[clap(long, default_value_t = default_trace_propagation())]
+  trace_propagation: bool,
+
+  /// Fails if a built-in (cleanup) rule references a node kind or a field unknown to the grammar (e.g. renamed by a
+  /// newer version of the grammar), instead of warning about it and leaving the rule out
+  #[get = "pub"]
+  #[builder(default = "default_strict_builtins()")]
+  #[clap(long, default_value_t = default_strict_builtins())]
+  strict_builtins: bool,
+
+  /// Scans the code base for the rules annotated in comments (`@piranha-rule: ...`) and applies them along with the other rules
+  #[get = "pub"]
+  #[builder(default = "default_rules_from_comments()")]
+  #[clap(long, default_value_t = default_rules_from_comments())]
+  rules_from_comments: bool,
   /// The target language
   #[get = "pub"]
   #[builder(default = "default_piranha_language()")]
-  #[clap(short = 'l', value_parser = clap::builder::PossibleValuesParser::new([JAVA, SWIFT, PYTHON, KOTLIN, GO, TSX, TYPESCRIPT])
+  #[clap(short = 'l', value_parser = clap::builder::PossibleValuesParser::new([JAVA, SWIFT, PYTHON, KOTLIN, GO, TSX, TYPESCRIPT, C_SHARP, THRIFT, PROTO])
   .map(|s| s.parse::<PiranhaLanguage>().unwrap()))]
+  #[schemars(with = "String")]
   language: PiranhaLanguage,
 
   /// User option that determines whether an empty file will be deleted
@@ -117,6 +279,13 @@ pub struct PiranhaArguments {
   #[builder(default = "default_number_of_ancestors_in_parent_scope()")]
   #[clap(long, default_value_t = default_number_of_ancestors_in_parent_scope())]
   number_of_ancestors_in_parent_scope: u8,
+
+  /// The number of (named) ancestors of the edited node considered when re-matching a rule after an edit
+  #[get = "pub"]
+  #[builder(default = "default_rematch_slack()")]
+  #[clap(long, default_value_t = default_rematch_slack())]
+  rematch_slack: u8,
+
   /// The number of lines to consider for cleaning up the comments
   #[get = "pub"]
   #[builder(default = "default_cleanup_comments_buffer()")]
@@ -129,6 +298,19 @@ pub struct PiranhaArguments {
   #[clap(long, default_value_t = default_cleanup_comments())]
   cleanup_comments: bool,
 
+  /// Regexes of the comments to delete once the rules were applied (e.g. `TODO.*@stale_flag_name`), where the tags are replaced with the substitutions.
+  /// Only the rewritten files are scanned, unless `comment_deletion_in_all_files` is enabled
+  #[get = "pub"]
+  #[builder(default = "default_comment_deletion_patterns()")]
+  #[clap(long, num_args = 0.., required = false)]
+  comment_deletion_patterns: Vec<String>,
+
+  /// Deletes the comments matching the `comment_deletion_patterns` in all the files, rather than only in the rewritten ones
+  #[get = "pub"]
+  #[builder(default = "default_comment_deletion_in_all_files()")]
+  #[clap(long, default_value_t = default_comment_deletion_in_all_files())]
+  comment_deletion_in_all_files: bool,
+
   /// Disables in-place rewriting of code
   #[get = "pub"]
   #[builder(default = "default_dry_run()")]
@@ -139,13 +321,109 @@ pub struct PiranhaArguments {
   #[get = "pub"]
   #[builder(default = "default_rule_graph()")]
   #[clap(skip)]
+  #[schemars(skip)]
   rule_graph: RuleGraph,
 
+  // Decides whether each edit is applied, skipped or replaced (only available via the Rust API)
+  #[get = "pub"]
+  #[builder(default = "default_edit_interceptor()")]
+  #[clap(skip)]
+  #[schemars(skip)]
+  edit_interceptor: Option<Arc<dyn EditInterceptor>>,
+
+  // The code base the files are read from (see `Codebase`), created from `path_to_codebase` once the arguments are built
+  #[get = "pub(crate)"]
+  #[builder(setter(skip), default)]
+  #[clap(skip)]
+  #[schemars(skip)]
+  codebase: Arc<Codebase>,
+
+  // Receives the progress of the run (only available via the Rust API, see `progress` for the command line)
+  #[get = "pub"]
+  #[builder(default = "default_progress_sink()")]
+  #[clap(skip)]
+  #[schemars(skip)]
+  progress_sink: Option<Arc<dyn ProgressSink>>,
+
+  // The stage of the rule graph to execute (see `Stage`). All the stages are executed in order if it is not set.
+  #[get = "pub"]
+  #[builder(default = "default_stage()")]
+  #[clap(skip)]
+  #[schemars(skip)]
+  stage: Option<String>,
+
   /// Allows syntax errors in the input source code
   #[get = "pub"]
   #[builder(default = "default_allow_dirty_ast()")]
   #[clap(long, default_value_t = default_allow_dirty_ast())]
   allow_dirty_ast: bool,
+
+  /// Determines how strictly code snippets are compared (when de-duplicating matches and detecting no-op edits)
+  #[get = "pub"]
+  #[builder(default = "default_whitespace_sensitivity()")]
+  #[clap(long, value_enum, default_value_t = default_whitespace_sensitivity())]
+  whitespace_sensitivity: WhitespaceSensitivity,
+
+  /// Determines how the input files with syntax errors are handled (unless `allow_dirty_ast` is enabled)
+  #[get = "pub"]
+  #[builder(default = "default_on_parse_error()")]
+  #[clap(long, value_enum, default_value_t = default_on_parse_error())]
+  on_parse_error: OnParseError,
+
+  /// Determines the line endings of the rewritten files - `preserve` keeps them (the new lines of the replacements use the dominant line ending of the file), `lf` and `crlf` convert all of them
+  #[get = "pub"]
+  #[builder(default = "default_normalize_line_endings()")]
+  #[clap(long, value_enum, default_value_t = default_normalize_line_endings())]
+  normalize_line_endings: LineEndings,
+
+  /// Names of the frameworks, files or Swift packages whose references should be removed from the Xcode project files (`project.pbxproj`)
+  #[get = "pub"]
+  #[builder(default = "default_pbxproj_references_to_remove()")]
+  #[clap(long, num_args = 0.., required = false)]
+  pbxproj_references_to_remove: Vec<String>,
+
+  /// Reports each candidate match that was intentionally skipped (and why) in the output summary
+  /// (and prints each rewrite as an annotated snippet when run from the command line)
+  #[get = "pub"]
+  #[builder(default = "default_explain()")]
+  #[clap(long, default_value_t = default_explain())]
+  explain: bool,
+
+  /// Files with a line longer than this (like minified files) are skipped, unless `process_long_lines` is enabled
+  #[get = "pub"]
+  #[builder(default = "default_max_line_length()")]
+  #[clap(long, default_value_t = default_max_line_length())]
+  max_line_length: usize,
+
+  /// Processes the files with lines longer than `max_line_length` (without deleting their consecutive new lines)
+  #[get = "pub"]
+  #[builder(default = "default_process_long_lines()")]
+  #[clap(long, default_value_t = default_process_long_lines())]
+  process_long_lines: bool,
+
+  /// The maximum number of compiled tree-sitter queries kept in the cache (the least recently used ones are evicted)
+  #[get = "pub"]
+  #[builder(default = "default_scope_query_cache_size()")]
+  #[clap(long, default_value_t = default_scope_query_cache_size())]
+  scope_query_cache_size: usize,
+
+  /// Only applies the built-in cleanup rules (e.g. simplifying `if (true)`) as seed rules, to clean up manually edited code
+  #[get = "pub"]
+  #[builder(default = "default_cleanup_only()")]
+  #[clap(long, default_value_t = default_cleanup_only())]
+  cleanup_only: bool,
+
+  /// Prints the code matched by each rule (highlighted) when run from the command line
+  #[get = "pub"]
+  #[builder(default = "default_verbose()")]
+  #[clap(long, default_value_t = default_verbose())]
+  verbose: bool,
+
+  /// Renders a progress bar (files processed, edits so far, estimated remaining time and current file) when run from the command line
+  #[get = "pub"]
+  #[builder(default = "default_progress()")]
+  #[clap(long, default_value_t = default_progress())]
+  progress: bool,
 }
 
 impl Default for PiranhaArguments {
@@ -174,6 +452,35 @@ impl PiranhaArguments {
   /// * delete_file_if_empty (bool): User option that determines whether an empty file will be deleted
   /// * path_to_output_summary : Path to the file where the Piranha output summary should be persisted
   /// * allow_dirty_ast : Allows syntax errors in the input source code
+  /// * dump_graph : Path to the file where the rule graph should be dumped (DOT format, or Mermaid if the extension is `.mmd`)
+  /// * rematch_slack (u8): The number of ancestors of the edited node considered when re-matching a rule after an edit
+  /// * whitespace_sensitivity (string) : How strictly code snippets are compared - `exact`, `line-endings` or `insensitive`
+  /// * pbxproj_references_to_remove (list of strings) : Names of the frameworks, files or Swift packages whose references should be removed from the Xcode project files
+  /// * explain (bool) : Reports each candidate match that was intentionally skipped (and why) in the output summary
+  /// * max_line_length (usize) : Files with a line longer than this (like minified files) are skipped, unless `process_long_lines` is enabled
+  /// * process_long_lines (bool) : Processes the files with lines longer than `max_line_length` (without deleting their consecutive new lines)
+  /// * cleanup_only (bool) : Only applies the built-in cleanup rules (e.g. simplifying `if (true)`) as seed rules
+  /// * on_parse_error (string) : How the input files with syntax errors are handled - `skip`, `proceed` or `fail`
+  /// * matches_output : Path to the JSON Lines file where the matches should be written, as soon as each file is processed
+  /// * omit_matches_from_summary (bool) : Does not report the matches in the output summary
+  /// * cache_dir : Directory of the negative cache, which records the files that had no match (for the same rules) in a previous run
+  /// * export_rules : Path to the file where the (user defined) rules and edges should be exported as TOML
+  /// * per_file_timeout_seconds (u64) : The time budget for applying the rules to a file, after which the remaining rules are not applied to it
+  /// * normalize_line_endings (string) : The line endings of the rewritten files - `preserve`, `lf` or `crlf`
+  /// * report_capture_ranges (bool) : Reports the range of each node captured by the matches in the output summary
+  /// * max_file_size_bytes (usize) : Files larger than this (in bytes), like generated files, are skipped
+  /// * output_patch : Path to the file where the changes should be written as a patch (unified diff in the git format)
+  /// * rules_from_comments (bool) : Scans the code base for the rules annotated in comments (`@piranha-rule: ...`) and applies them too
+  /// * metrics_output : Path to the JSON file where the metrics of the run should be written (even if the run fails partway)
+  /// * scope_query_cache_size (usize) : The maximum number of compiled tree-sitter queries kept in the cache
+  /// * comment_deletion_patterns (list of strings) : Regexes (with `@tag` substitution) of the comments to delete once the rules were applied
+  /// * comment_deletion_in_all_files (bool) : Deletes the comments matching the `comment_deletion_patterns` in all the files (not only the rewritten ones)
+  /// * strict_substitutions (bool) : Rejects the substitutions whose values contain characters significant to the tree-sitter query syntax
+  /// * input_substitutions_file : Path to a JSON or TOML file of substitutions (overridden by `substitutions`)
+  /// * output_archive : Path to the archive where the files should be written, when `path_to_codebase` is an archive (`.zip`, `.tar.gz` or `.tgz`)
+  /// * max_propagation_depth (usize) : The maximum number of "Parent" cleanups chained after an edit, beyond which the chain is stopped
+  /// * trace_propagation (bool) : Reports the chain of "Parent" cleanups applied after each edit in the output summary
+  /// * strict_builtins (bool) : Fails if a built-in rule references a node kind or a field unknown to the grammar, instead of leaving the rule out
   /// Returns PiranhaArgument.
   #[new]
   fn py_new(
@@ -184,11 +491,31 @@ impl PiranhaArguments {
     cleanup_comments_buffer: Option<i32>, number_of_ancestors_in_parent_scope: Option<u8>,
     delete_consecutive_new_lines: Option<bool>, global_tag_prefix: Option<String>,
     delete_file_if_empty: Option<bool>, path_to_output_summary: Option<String>,
-    allow_dirty_ast: Option<bool>,
-  ) -> Self {
+    allow_dirty_ast: Option<bool>, whitespace_sensitivity: Option<String>,
+    rematch_slack: Option<u8>, dump_graph: Option<String>,
+    pbxproj_references_to_remove: Option<Vec<String>>, explain: Option<bool>,
+    max_line_length: Option<usize>, process_long_lines: Option<bool>, cleanup_only: Option<bool>,
+    on_parse_error: Option<String>, matches_output: Option<String>,
+    omit_matches_from_summary: Option<bool>, cache_dir: Option<String>,
+    export_rules: Option<String>, per_file_timeout_seconds: Option<u64>,
+    normalize_line_endings: Option<String>, report_capture_ranges: Option<bool>,
+    max_file_size_bytes: Option<usize>, output_patch: Option<String>,
+    rules_from_comments: Option<bool>, metrics_output: Option<String>,
+    scope_query_cache_size: Option<usize>, comment_deletion_patterns: Option<Vec<String>>,
+    comment_deletion_in_all_files: Option<bool>, strict_substitutions: Option<bool>,
+    input_substitutions_file: Option<String>, output_archive: Option<String>,
+    max_propagation_depth: Option<usize>, trace_propagation: Option<bool>,
+    strict_builtins: Option<bool>,
+  ) -> PyResult<Self> {
     let subs = substitutions.map_or(vec![], |s| {
       s.iter()
-        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .flat_map(|(k, v)| {
+          // A list of values (e.g. `["FLAG_A", "FLAG_B"]`) is passed as a substitution per value
+          let values = v
+            .extract::<Vec<String>>()
+            .unwrap_or_else(|_| vec![v.to_string()]);
+          values.into_iter().map(move |value| (k.to_string(), value))
+        })
         .collect_vec()
     });
 
@@ -230,7 +557,53 @@ impl PiranhaArguments {
       .delete_file_if_empty(delete_file_if_empty.unwrap_or_else(default_delete_file_if_empty))
       .path_to_output_summary(path_to_output_summary)
       .allow_dirty_ast(allow_dirty_ast.unwrap_or_else(default_allow_dirty_ast))
-      .build()
+      .whitespace_sensitivity(
+        parse_value_enum("whitespace_sensitivity", whitespace_sensitivity)?
+          .unwrap_or_else(default_whitespace_sensitivity),
+      )
+      .rematch_slack(rematch_slack.unwrap_or_else(default_rematch_slack))
+      .dump_graph(dump_graph)
+      .pbxproj_references_to_remove(
+        pbxproj_references_to_remove.unwrap_or_else(default_pbxproj_references_to_remove),
+      )
+      .explain(explain.unwrap_or_else(default_explain))
+      .max_line_length(max_line_length.unwrap_or_else(default_max_line_length))
+      .process_long_lines(process_long_lines.unwrap_or_else(default_process_long_lines))
+      .cleanup_only(cleanup_only.unwrap_or_else(default_cleanup_only))
+      .on_parse_error(
+        parse_value_enum("on_parse_error", on_parse_error)?.unwrap_or_else(default_on_parse_error),
+      )
+      .matches_output(matches_output)
+      .omit_matches_from_summary(
+        omit_matches_from_summary.unwrap_or_else(default_omit_matches_from_summary),
+      )
+      .cache_dir(cache_dir)
+      .export_rules(export_rules)
+      .per_file_timeout_seconds(per_file_timeout_seconds)
+      .normalize_line_endings(
+        parse_value_enum("normalize_line_endings", normalize_line_endings)?
+          .unwrap_or_else(default_normalize_line_endings),
+      )
+      .report_capture_ranges(report_capture_ranges.unwrap_or_else(default_report_capture_ranges))
+      .max_file_size_bytes(max_file_size_bytes)
+      .output_patch(output_patch)
+      .rules_from_comments(rules_from_comments.unwrap_or_else(default_rules_from_comments))
+      .metrics_output(metrics_output)
+      .scope_query_cache_size(scope_query_cache_size.unwrap_or_else(default_scope_query_cache_size))
+      .comment_deletion_patterns(
+        comment_deletion_patterns.unwrap_or_else(default_comment_deletion_patterns),
+      )
+      .comment_deletion_in_all_files(
+        comment_deletion_in_all_files.unwrap_or_else(default_comment_deletion_in_all_files),
+      )
+      .strict_substitutions(strict_substitutions.unwrap_or_else(default_strict_substitutions))
+      .input_substitutions_file(input_substitutions_file.map(PathBuf::from))
+      .output_archive(output_archive)
+      .max_propagation_depth(max_propagation_depth.unwrap_or_else(default_max_propagation_depth))
+      .trace_propagation(trace_propagation.unwrap_or_else(default_trace_propagation))
+      .strict_builtins(strict_builtins.unwrap_or_else(default_strict_builtins))
+      .try_build()
+      .map_err(PyValueError::new_err)
   }
 }
 
@@ -239,14 +612,59 @@ impl PiranhaArguments {
     self.language.extension().to_string()
   }
 
+  /// Reports the `event` to the `progress_sink` (if any).
+  pub(crate) fn report_progress(&self, event: ProgressEvent) {
+    if let Some(progress_sink) = self.progress_sink() {
+      progress_sink.on_event(&event);
+    }
+  }
+
+  /// The arguments for the files of another `language`, i.e. the files matched by the rules of that language (see `Rule::language`)
+  pub(crate) fn with_language(&self, language: &PiranhaLanguage) -> PiranhaArguments {
+    PiranhaArguments {
+      language: language.clone(),
+      ..self.clone()
+    }
+  }
+
+  /// The arguments of a run that writes nothing (see `analyze`).
+  pub(crate) fn with_dry_run(&self) -> PiranhaArguments {
+    PiranhaArguments {
+      dry_run: true,
+      ..self.clone()
+    }
+  }
+
+  /// The arguments for the `stage`, starting from the `substitutions` (i.e. the global substitutions of the previous stages)
+  /// and, for a code snippet, the `code_snippet` rewritten by the previous stages.
+  pub(crate) fn for_stage(
+    &self, stage: &str, substitutions: &HashMap<String, String>, code_snippet: &str,
+  ) -> PiranhaArguments {
+    PiranhaArguments {
+      stage: Some(stage.to_string()),
+      substitutions: substitutions
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .sorted()
+        .collect(),
+      code_snippet: code_snippet.to_string(),
+      ..self.clone()
+    }
+  }
+
   pub fn from_cli() -> Self {
-    let p = PiranhaArguments::parse();
+    Self::from_cli_arguments(&PiranhaArguments::parse())
+  }
+
+  /// Builds the arguments parsed from the command line (e.g. along with a subcommand), i.e. loads their rule graph.
+  pub fn from_cli_arguments(p: &PiranhaArguments) -> Self {
     PiranhaArgumentsBuilder::default()
       .path_to_codebase(p.path_to_codebase().to_string())
       .substitutions(p.substitutions.clone())
       .language(p.language().clone())
       .path_to_configurations(p.path_to_configurations().to_string())
       .path_to_output_summary(p.path_to_output_summary().clone())
+      .output_format(*p.output_format())
       .delete_file_if_empty(*p.delete_file_if_empty())
       .delete_consecutive_new_lines(*p.delete_consecutive_new_lines())
       .global_tag_prefix(p.global_tag_prefix().to_string())
@@ -254,12 +672,148 @@ impl PiranhaArguments {
       .cleanup_comments_buffer(*p.cleanup_comments_buffer())
       .cleanup_comments(*p.cleanup_comments())
       .dry_run(*p.dry_run())
+      .whitespace_sensitivity(*p.whitespace_sensitivity())
+      .rematch_slack(*p.rematch_slack())
+      .dump_graph(p.dump_graph().clone())
+      .pbxproj_references_to_remove(p.pbxproj_references_to_remove().clone())
+      .explain(*p.explain())
+      .verbose(*p.verbose())
+      .progress(*p.progress())
+      .progress_sink(
+        p.progress()
+          .then(|| Arc::new(ProgressBar::default()) as Arc<dyn ProgressSink>),
+      )
+      .max_line_length(*p.max_line_length())
+      .process_long_lines(*p.process_long_lines())
+      .cleanup_only(*p.cleanup_only())
+      .on_parse_error(*p.on_parse_error())
+      .matches_output(p.matches_output().clone())
+      .omit_matches_from_summary(*p.omit_matches_from_summary())
+      .cache_dir(p.cache_dir().clone())
+      .export_rules(p.export_rules().clone())
+      .per_file_timeout_seconds(*p.per_file_timeout_seconds())
+      .normalize_line_endings(*p.normalize_line_endings())
+      .report_capture_ranges(*p.report_capture_ranges())
+      .max_file_size_bytes(*p.max_file_size_bytes())
+      .output_patch(p.output_patch().clone())
+      .rules_from_comments(*p.rules_from_comments())
+      .metrics_output(p.metrics_output().clone())
+      .scope_query_cache_size(*p.scope_query_cache_size())
+      .comment_deletion_patterns(p.comment_deletion_patterns().clone())
+      .comment_deletion_in_all_files(*p.comment_deletion_in_all_files())
+      .strict_substitutions(*p.strict_substitutions())
+      .input_substitutions_file(p.input_substitutions_file().clone())
+      .output_archive(p.output_archive().clone())
+      .max_propagation_depth(*p.max_propagation_depth())
+      .trace_propagation(*p.trace_propagation())
+      .strict_builtins(*p.strict_builtins())
       .build()
   }
 
+  /// The default arguments, without loading the rule graph (i.e. the defaults of the arguments schema)
+  fn unbuilt_default() -> Self {
+    PiranhaArgumentsBuilder::default().create().unwrap()
+  }
+
   pub(crate) fn input_substitutions(&self) -> HashMap<String, String> {
     self.substitutions.iter().cloned().collect()
   }
+
+  /// The values of each substitution, in order. A substitution passed several times (e.g. `-s stale_flag_name=FLAG_A
+  /// -s stale_flag_name=FLAG_B`, or a list of values) instantiates the seed rules once per value
+  /// (see `InstantiatedRule::new_for_each_value`).
+  pub(crate) fn input_substitution_values(&self) -> HashMap<String, Vec<String>> {
+    let mut values: HashMap<String, Vec<String>> = HashMap::new();
+    for (key, value) in &self.substitutions {
+      let key_values = values.entry(key.to_string()).or_default();
+      if !key_values.contains(value) {
+        key_values.push(value.to_string());
+      }
+    }
+    values
+  }
+
+  /// Reads the substitutions of the `input_substitutions_file` (if any), a flat JSON object or TOML table (by extension).
+  /// A list of values (e.g. `["FLAG_A", "FLAG_B"]`) is read as a substitution per value, and the numbers and booleans as is.
+  pub fn input_substitutions_from_file(&self) -> Result<Vec<(String, String)>, String> {
+    let Some(path) = self.input_substitutions_file() else {
+      return Ok(vec![]);
+    };
+    let content = read_file(path)
+      .map_err(|e| format!("Could not read the substitutions file {path:?} - {e}"))?;
+    let table: serde_json::Map<String, serde_json::Value> =
+      if path.extension().is_some_and(|e| e == "json") {
+        serde_json::from_str(&content).map_err(|e| e.to_string())
+      } else {
+        toml::from_str(&content).map_err(|e| e.to_string())
+      }
+      .map_err(|e| format!("Could not parse the substitutions file {path:?} - {e}"))?;
+
+    let to_string = |key: &str, value: &serde_json::Value| {
+      match value {
+      serde_json::Value::String(s) => Ok(s.to_string()),
+      serde_json::Value::Number(_) | serde_json::Value::Bool(_) => Ok(value.to_string()),
+      _ => Err(format!(
+        "The value of the substitution `{key}` in {path:?} should be a string, a number, a boolean or a list of them"
+      )),
+    }
+    };
+    let mut substitutions = vec![];
+    for (key, value) in &table {
+      match value {
+        serde_json::Value::Array(values) => {
+          for v in values {
+            substitutions.push((key.to_string(), to_string(key, v)?));
+          }
+        }
+        _ => substitutions.push((key.to_string(), to_string(key, value)?)),
+      }
+    }
+    Ok(substitutions)
+  }
+
+  /// The keys of the substitutions whose values contain characters significant to the tree-sitter query syntax,
+  /// along with these characters (see `get_query_significant_characters`).
+  pub(crate) fn suspicious_substitutions(&self) -> Vec<(String, Vec<char>)> {
+    self
+      .substitutions
+      .iter()
+      .map(|(key, value)| (key.to_string(), get_query_significant_characters(value)))
+      .filter(|(_, characters)| !characters.is_empty())
+      .collect()
+  }
+
+  /// Why a file with the `content` is not processed, if it is larger than `max_file_size_bytes` (like generated files)
+  /// or contains lines longer than `max_line_length` (like minified files).
+  pub(crate) fn get_skip_reason(&self, content: &str) -> Option<String> {
+    match self.max_file_size_bytes() {
+      Some(max_file_size_bytes) if content.len() > *max_file_size_bytes => {
+        Some(format!("Skipped: larger than {max_file_size_bytes} bytes"))
+      }
+      _ if !*self.process_long_lines() && has_long_lines(content, *self.max_line_length()) => {
+        Some(format!(
+          "Skipped: contains a line longer than {} characters",
+          self.max_line_length()
+        ))
+      }
+      _ => None,
+    }
+  }
+
+  /// The `comment_deletion_patterns`, where the tags are replaced with the (escaped) `substitutions`.
+  pub(crate) fn comment_deletion_regexes(
+    &self, substitutions: &HashMap<String, String>,
+  ) -> Vec<Regex> {
+    let escaped_substitutions = substitutions
+      .iter()
+      .map(|(tag, value)| (tag.to_string(), regex::escape(value)))
+      .collect();
+    self
+      .comment_deletion_patterns()
+      .iter()
+      .map(|p| Regex::new(&p.instantiate(&escaped_substitutions)).unwrap())
+      .collect()
+  }
 }
 
 impl PiranhaArgumentsBuilder {
@@ -267,18 +821,48 @@ impl PiranhaArgumentsBuilder {
   /// * create PiranhaArgument from the builder
   /// * parse `piranha_arguments.toml` (if it exists)
   /// * merge the two PiranhaArguments
+  /// Panics if the arguments or the rules are invalid (see `try_build`).
   pub fn build(&self) -> PiranhaArguments {
-    if let Err(e) = &self._validate() {
-      panic!("{}", e);
-    };
+    self.try_build().unwrap_or_else(|e| panic!("{}", e))
+  }
 
-    let mut _arg = self.create().unwrap();
+  /// Builds PiranhaArguments from PiranhaBuilder (see `build`), or returns an error if the arguments or the rules
+  /// (i.e. the configuration files, the query files or the annotated rules) are invalid.
+  pub fn try_build(&self) -> Result<PiranhaArguments, String> {
+    let mut builder = self.clone();
+    builder.merge_input_substitutions_file()?;
+    builder._validate()?;
 
-    let rule_graph = get_rule_graph(&_arg);
+    let mut _arg = builder.create().unwrap();
+    _arg.codebase = Arc::new(Codebase::new(
+      Path::new(_arg.path_to_codebase()),
+      _arg.include(),
+      _arg.exclude(),
+    ));
+
+    let rule_graph = get_rule_graph(&_arg)?;
     _arg = PiranhaArguments { rule_graph, .._arg };
     #[rustfmt::skip]
     info!( "Number of rules and edges loaded : {:?}", _arg.rule_graph().get_number_of_rules_and_edges());
-    _arg
+    Ok(_arg)
+  }
+
+  /// Adds the substitutions of the `input_substitutions_file` (if any) that are not overridden by the `substitutions`.
+  fn merge_input_substitutions_file(&mut self) -> Result<(), String> {
+    let _arg: PiranhaArguments = self.create().unwrap();
+    let file_substitutions = _arg
+      .input_substitutions_from_file()
+      .map_err(|e| format!("Invalid Piranha arguments. {e}"))?;
+    if !file_substitutions.is_empty() {
+      let substitutions = _arg.input_substitutions();
+      let merged = file_substitutions
+        .into_iter()
+        .filter(|(key, _)| !substitutions.contains_key(key))
+        .chain(_arg.substitutions.iter().cloned())
+        .collect_vec();
+      self.substitutions(merged);
+    }
+    Ok(())
   }
 
   fn _validate(&self) -> Result<bool, String> {
@@ -298,36 +882,197 @@ impl PiranhaArgumentsBuilder {
       );
     }
 
+    for (key, characters) in _arg.suspicious_substitutions() {
+      let characters = characters.iter().map(|c| format!("`{c}`")).join(", ");
+      if *_arg.strict_substitutions() {
+        return Err(format!(
+          "Invalid Piranha arguments. The substitution `{key}` contains characters significant to the tree-sitter query syntax - {characters}"
+        ));
+      }
+      warn!(
+        "The substitution `{key}` contains characters significant to the tree-sitter query syntax ({characters}), which may change what the rules match. Use `--strict-substitutions` to reject it."
+      );
+    }
+
+    for pattern in _arg.comment_deletion_patterns() {
+      if let Err(e) = Regex::new(pattern) {
+        return Err(format!(
+          "Invalid Piranha arguments. Invalid comment deletion pattern `{pattern}` - {e}"
+        ));
+      }
+    }
+
+    if let Some(output_archive) = _arg.output_archive() {
+      let format = ArchiveFormat::of(Path::new(_arg.path_to_codebase()));
+      if format.is_none() {
+        return Err(
+          "Invalid Piranha arguments. The `output_archive` requires the `path_to_codebase` to be an archive (`.zip`, `.tar.gz` or `.tgz`)."
+            .to_string(),
+        );
+      }
+      if ArchiveFormat::of(Path::new(output_archive)) != format {
+        return Err(format!(
+          "Invalid Piranha arguments. The `output_archive` ({output_archive}) should have the same format as the `path_to_codebase`."
+        ));
+      }
+    }
+
+    if *_arg.scope_query_cache_size() == 0 {
+      return Err(
+        "Invalid Piranha arguments. The `scope_query_cache_size` should be positive.".to_string(),
+      );
+    }
+
     Ok(true)
   }
 }
 
+/// Parses the `value` of the (Python) argument `name`, e.g. `whitespace_sensitivity="insensitive"`.
+/// Returns a `ValueError` listing the possible values if the value is invalid.
+fn parse_value_enum<T: ValueEnum>(name: &str, value: Option<String>) -> PyResult<Option<T>> {
+  value
+    .map(|v| {
+      T::from_str(&v, true).map_err(|_| {
+        let possible_values = T::value_variants()
+          .iter()
+          .filter_map(|variant| variant.to_possible_value())
+          .map(|p| format!("`{}`", p.get_name()))
+          .join(", ");
+        PyValueError::new_err(format!(
+          "Invalid `{name}` - `{v}` (the possible values are {possible_values})"
+        ))
+      })
+    })
+    .transpose()
+}
+
 /// Gets rule graph for PiranhaArguments
 ///   * Loads the language specific graphs
 ///   * Merges these with the user defined graphs
 /// Returns this merged graph
-fn get_rule_graph(_arg: &PiranhaArguments) -> RuleGraph {
+fn get_rule_graph(_arg: &PiranhaArguments) -> Result<RuleGraph, String> {
   // Get the built-in rule -graph for the language
   let piranha_language = _arg.language();
 
-  let built_in_rules = RuleGraphBuilder::default()
+  let checked_built_in_rules = check_built_in_rules(
+    built_in_rules(piranha_language),
+    piranha_language,
+    *_arg.strict_builtins(),
+  )?;
+  let mut built_in_rules = RuleGraphBuilder::default()
     .edges(piranha_language.edges().clone().unwrap_or_default().edges)
-    .rules(piranha_language.rules().clone().unwrap_or_default().rules)
-    .build();
+    .rules(checked_built_in_rules)
+    .try_build()?;
 
   // TODO: Move to `PiranhaArgumentBuilder`'s _validate - https://github.com/uber/piranha/issues/387
   // Get the user-defined rule graph (if any) via the Python/Rust API
   let mut user_defined_rules: RuleGraph = _arg.rule_graph().clone();
+  // The query files of the rules passed via the API are relative to the current directory
+  load_query_files(user_defined_rules.rules_mut(), &[PathBuf::from(".")])?;
+  compile_cst_queries(user_defined_rules.rules_mut(), piranha_language)?;
   // In the scenario when rules/edges are passed as toml files
   if !_arg.path_to_configurations().is_empty() {
-    user_defined_rules = read_user_config_files(_arg.path_to_configurations())
+    user_defined_rules = read_user_config_files(_arg.path_to_configurations(), piranha_language)?
+  }
+  // The rules annotated in the comments of the code base (if enabled)
+  if *_arg.rules_from_comments() {
+    user_defined_rules = user_defined_rules.merge(&read_comment_rules(_arg)?)?;
   }
 
-  if user_defined_rules.graph().is_empty() {
+  if user_defined_rules.graph().is_empty() && !_arg.cleanup_only() {
     warn!("NO RULES PROVIDED. Please provide rules via the RuleGraph API or as toml files");
   }
 
-  built_in_rules.merge(&user_defined_rules)
+  // The built-in rules of the other languages of the (user defined) rules, prefixed by their language
+  for language in user_defined_rules.rule_languages(piranha_language.extension()) {
+    let (rules, edges) = language.prefixed_built_in_rules();
+    let rules = check_built_in_rules(rules, &language, *_arg.strict_builtins())?;
+    built_in_rules = built_in_rules.merge(
+      &RuleGraphBuilder::default()
+        .edges(edges)
+        .rules(rules)
+        .try_build()?,
+    )?;
+  }
+
+  let rule_graph = built_in_rules.merge(&user_defined_rules)?;
+  rule_graph.validate_languages(piranha_language.extension())?;
+  Ok(rule_graph)
+}
+
+/// Checks the built-in `rules` against the grammar of the `language`, since a node kind or a field renamed by a newer
+/// version of the grammar would make a rule inert (or, in a filter, a guard of the rule). The query and the patterns
+/// of the filters of each rule are checked. The rules referencing unknown node kinds or fields are reported and left
+/// out (their edges are then ignored), unless `strict_builtins` is enabled, in which case an error is returned.
+pub(crate) fn check_built_in_rules(
+  rules: Vec<Rule>, language: &PiranhaLanguage, strict_builtins: bool,
+) -> Result<Vec<Rule>, String> {
+  let mut checked_rules = vec![];
+  for rule in rules {
+    let patterns = patterns_of(&rule);
+    let unknown = |unknown_names: &dyn Fn(&str) -> Vec<String>| {
+      patterns
+        .iter()
+        .flat_map(|p| unknown_names(&p.pattern()))
+        .unique()
+        .map(|name| format!("`{name}`"))
+        .join(", ")
+    };
+    let unknown_node_kinds = unknown(&|p| language.unknown_node_kinds(p));
+    let unknown_field_names = unknown(&|p| language.unknown_field_names(p));
+    if unknown_node_kinds.is_empty() && unknown_field_names.is_empty() {
+      checked_rules.push(rule);
+      continue;
+    }
+    let references = [
+      (unknown_node_kinds, "the node kind(s)"),
+      (unknown_field_names, "the field(s)"),
+    ]
+    .into_iter()
+    .filter(|(names, _)| !names.is_empty())
+    .map(|(names, kind)| format!("{kind} {names}"))
+    .join(" and ");
+    let message = format!(
+      "The built-in rule `{}` references {references} unknown to the {} grammar",
+      rule.name(),
+      language.extension()
+    );
+    if strict_builtins {
+      return Err(format!("Invalid built-in rules. {message}."));
+    }
+    warn!("{message}. It will not be applied (use `--strict-builtins` to fail instead).");
+  }
+  Ok(checked_rules)
+}
+
+/// The tree-sitter patterns of the `rule`, i.e. its query and the patterns of its filters
+pub(crate) fn patterns_of(rule: &Rule) -> Vec<CGPattern> {
+  let mut patterns = vec![rule.query().clone()];
+  for filter in rule.filters() {
+    patterns.extend([
+      filter.enclosing_node().clone(),
+      filter.outermost_enclosing_node().clone(),
+      filter.not_enclosing_node().clone(),
+      filter.contains().clone(),
+    ]);
+    patterns.extend(filter.not_contains().iter().cloned());
+  }
+  patterns
+}
+
+/// The built-in rules of the `language` (along with the rules added by the tests, see `TEST_BUILT_IN_RULES`)
+fn built_in_rules(language: &PiranhaLanguage) -> Vec<Rule> {
+  let rules = language.rules().clone().unwrap_or_default().rules;
+  #[cfg(test)]
+  let rules = [rules, TEST_BUILT_IN_RULES.with(|r| r.borrow().clone())].concat();
+  rules
+}
+
+#[cfg(test)]
+thread_local! {
+  /// The rules added to the built-in rules of the language by the tests (of the current thread), e.g. a rule
+  /// referencing a node kind renamed by a newer version of the grammar
+  pub(crate) static TEST_BUILT_IN_RULES: std::cell::RefCell<Vec<Rule>> = std::cell::RefCell::new(vec![]);
 }
 
 #[cfg(test)]
@@ -337,20 +1082,66 @@ mod piranha_arguments_test;
 // Implements instance methods related to applying the user options provided in  piranha arguments
 impl SourceCodeUnit {
   /// Replaces three consecutive newline characters with two
+  /// (skipped for files with lines longer than `max_line_length`)
   pub(crate) fn perform_delete_consecutive_new_lines(&mut self) {
-    if *self.piranha_arguments().delete_consecutive_new_lines() {
-      let regex = Regex::new(r"\n(\s*\n)+(\s*\n)").unwrap();
-      let x = &regex.replace_all(self.code(), "\n${2}").into_owned();
-      self.set_code(x.clone());
+    let args = self.piranha_arguments();
+    if *args.delete_consecutive_new_lines() && !has_long_lines(self.code(), *args.max_line_length())
+    {
+      let code = delete_consecutive_new_lines(self.code());
+      self.record_content_replacement(&code);
+      self.set_code(code);
     }
   }
 
+  /// Converts all the line endings of a rewritten file as per `normalize_line_endings` (unless it is `preserve`)
+  pub(crate) fn perform_normalize_line_endings(&mut self, parser: &mut tree_sitter::Parser) {
+    let line_ending = match self.piranha_arguments().normalize_line_endings() {
+      LineEndings::Preserve => return,
+      LineEndings::Lf => "\n",
+      LineEndings::Crlf => "\r\n",
+    };
+    if self.rewrites().is_empty() {
+      return;
+    }
+    let code = with_line_ending(self.code(), line_ending);
+    if &code != self.code() {
+      self._replace_file_contents_and_re_parse(&code, parser, false);
+    }
+  }
+
+  /// Checks whether the two code snippets are equivalent w.r.t. the `whitespace_sensitivity` option.
+  /// The snippets are only parsed (see `normalize_snippet`) if they differ in their whitespace alone.
+  pub(crate) fn are_equivalent_snippets(&self, snippet_1: &str, snippet_2: &str) -> bool {
+    if snippet_1 == snippet_2 {
+      return true;
+    }
+    let sensitivity = *self.piranha_arguments().whitespace_sensitivity();
+    if sensitivity == WhitespaceSensitivity::Exact
+      || !differ_in_whitespace_only(snippet_1, snippet_2)
+    {
+      return false;
+    }
+    let language = self.piranha_arguments().language();
+    let mut parser = language.parser();
+    let comment_nodes = language.comment_nodes();
+    normalize_snippet(snippet_1, &mut parser, comment_nodes, sensitivity)
+      == normalize_snippet(snippet_2, &mut parser, comment_nodes, sensitivity)
+  }
+
+  /// Checks if the file is deleted (rather than written) when persisted.
+  /// Only the files emptied by a rewrite are deleted (i.e. not the files that were already empty).
+  pub(crate) fn is_deleted_on_persist(&self) -> bool {
+    self.code().as_str().is_empty()
+      && !self.rewrites().is_empty()
+      && *self.piranha_arguments().delete_file_if_empty()
+  }
+
   /// Writes the current contents of `code` to the file system and deletes a file if empty.
   pub(crate) fn persist(&self) {
     if *self.piranha_arguments().dry_run() {
       return;
     }
-    if self.code().as_str().is_empty() && *self.piranha_arguments().delete_file_if_empty() {
+    if self.is_deleted_on_persist() {
       std::fs::remove_file(self.path()).expect("Unable to Delete file");
       return;
     }
@@ -11,22 +11,49 @@ Copyright (c) 2023 Uber Technologies, Inc.
  limitations under the License.
 */
 
+use std::{
+  collections::{BTreeMap, HashMap},
+  io::Write,
+  path::Path,
+  time::Duration,
+};
+
 use getset::Getters;
 use itertools::Itertools;
+use schemars::JsonSchema;
 use serde_derive::{Deserialize, Serialize};
 
 use crate::utilities::gen_py_str_methods;
 
-use super::{edit::Edit, matches::Match, source_code_unit::SourceCodeUnit};
+use super::{
+  edit::Edit,
+  matches::{highlight, Match, Range},
+  metrics::UnappliedSeedRule,
+  render::{render_edit, RenderOptions},
+  rule::variant_label,
+  skipped_match::{SkipReason, SkippedMatch},
+  source_code_unit::SourceCodeUnit,
+};
 use pyo3::{prelude::pyclass, pymethods};
 
+/// The format of the output summary file (see `PiranhaArguments::path_to_output_summary`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, clap::ValueEnum, JsonSchema)]
+#[schemars(rename_all = "kebab-case")]
+pub enum OutputFormat {
+  /// The summaries of the files, as a JSON array
+  #[default]
+  Json,
+  /// One row per rewrite (see `PiranhaOutputSummary::write_csv`), e.g. to track a migration in a spreadsheet
+  Csv,
+}
+
 /// A class to represent Piranha's output
 #[derive(Serialize, Debug, Clone, Default, Deserialize, Getters)]
 #[pyclass]
 pub struct PiranhaOutputSummary {
   /// Path to the file
   #[pyo3(get)]
-  #[get = "pub(crate)"]
+  #[get = "pub"]
   path: String,
   /// Original content of the file after all the rewrites
   #[pyo3(get)]
@@ -39,24 +66,454 @@ pub struct PiranhaOutputSummary {
   content: String,
   /// All the occurrences of "match-only" rules
   #[pyo3(get)]
-  #[get = "pub(crate)"]
+  #[get = "pub"]
   matches: Vec<(String, Match)>,
+  /// The byte range of each of the `matches` in the final content (None once its code was rewritten)
+  #[serde(skip)]
+  match_ranges: Vec<Option<(usize, usize)>>,
   /// All the applied edits
   #[pyo3(get)]
   #[get = "pub(crate)"]
   rewrites: Vec<Edit>,
+  /// The edits of the `dry` rules, which were not applied
+  #[pyo3(get)]
+  #[get = "pub"]
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  suggested_rewrites: Vec<Edit>,
+  /// The indices of the `rewrites` produced by each variant of the rules with holes (see `variant_label`), e.g. to tell
+  /// apart the edits of a global rule seeded with different substitutions in different files
+  #[pyo3(get)]
+  #[get = "pub"]
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  rewrites_by_variant: BTreeMap<String, Vec<usize>>,
+  /// The number of candidate matches intentionally skipped, for each rule and reason
+  #[pyo3(get)]
+  #[get = "pub(crate)"]
+  #[serde(default)]
+  skip_counts: HashMap<String, HashMap<String, usize>>,
+  /// All the candidate matches intentionally skipped (only reported when `explain` is enabled)
+  #[pyo3(get)]
+  #[get = "pub(crate)"]
+  #[serde(default)]
+  skipped_matches: Vec<SkippedMatch>,
+  /// The files that were skipped without being read into the summary (e.g. larger than `max_file_size_bytes`), in which
+  /// case the `content` of the summary is empty
+  #[pyo3(get)]
+  #[get = "pub"]
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  skipped_files: Vec<SkippedFile>,
+  /// Notes about how the file was handled (e.g. why it was skipped)
+  #[pyo3(get)]
+  #[get = "pub(crate)"]
+  #[serde(default)]
+  notes: Vec<String>,
+  /// Ranges of the syntax errors in the file, when it was skipped because of them
+  #[pyo3(get)]
+  #[get = "pub(crate)"]
+  #[serde(default)]
+  parse_errors: Vec<Range>,
+  /// The chains of "Parent" cleanups (rule and range) applied after the edits, each starting with the edit that
+  /// triggered them (only reported when `trace_propagation` is enabled)
+  #[pyo3(get)]
+  #[get = "pub(crate)"]
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  propagation_traces: Vec<Vec<(String, Range)>>,
+  /// The statistics of each rule applied to the file (including the rules that never matched)
+  #[pyo3(get)]
+  #[get = "pub"]
+  #[serde(default)]
+  rule_statistics: HashMap<String, RuleStat>,
+  /// The seed rules of the pass (or stage) that produced neither an edit nor a match in any file, along with why
+  /// (best effort, see `UnappliedSeedRule`), to tell why a rule did nothing
+  #[pyo3(get)]
+  #[get = "pub"]
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  unapplied_seed_rules: BTreeMap<String, String>,
+  /// The stage of the migration (see `Stage`) that produced this summary, if the rule graph declares stages
+  #[pyo3(get)]
+  #[get = "pub"]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  stage: Option<String>,
 }
 
 gen_py_str_methods!(PiranhaOutputSummary);
 
+/// The indices of the `rewrites` produced by each variant of the rules with holes (see `variant_label`).
+fn get_rewrites_by_variant(rewrites: &[Edit]) -> BTreeMap<String, Vec<usize>> {
+  let mut rewrites_by_variant: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+  for (i, edit) in rewrites.iter().enumerate() {
+    if !edit.rule_substitutions().is_empty() {
+      rewrites_by_variant
+        .entry(variant_label(
+          edit.matched_rule(),
+          edit.rule_substitutions(),
+        ))
+        .or_default()
+        .push(i);
+    }
+  }
+  rewrites_by_variant
+}
+
+/// A file that was not processed by Piranha (e.g. a generated file larger than `max_file_size_bytes`), reported
+/// without its content
+#[derive(Serialize, Debug, Clone, Default, Deserialize, PartialEq, Getters)]
+#[pyclass]
+pub struct SkippedFile {
+  /// Path to the file
+  #[pyo3(get)]
+  #[get = "pub"]
+  path: String,
+  /// The size of the file (in bytes)
+  #[pyo3(get)]
+  #[get = "pub"]
+  size_bytes: usize,
+  /// Why the file was skipped
+  #[pyo3(get)]
+  #[get = "pub"]
+  reason: String,
+}
+
+gen_py_str_methods!(SkippedFile);
+
+impl SkippedFile {
+  pub(crate) fn new(path: &Path, size_bytes: usize, reason: String) -> Self {
+    SkippedFile {
+      path: path.to_string_lossy().to_string(),
+      size_bytes,
+      reason,
+    }
+  }
+}
+
+/// The statistics of a rule applied to a file, e.g. to identify the rules that are bottlenecks or never fire
+#[derive(Serialize, Debug, Clone, Default, Deserialize, PartialEq, Getters)]
+#[pyclass]
+pub struct RuleStat {
+  /// The number of matches of the rule, including the candidate matches that were skipped (e.g. rejected by the
+  /// filters, or vetoed)
+  #[pyo3(get)]
+  #[get = "pub"]
+  matches: usize,
+  /// The number of edits applied for the rule
+  #[pyo3(get)]
+  #[get = "pub"]
+  applications: usize,
+  /// The time spent matching the rule (in milliseconds)
+  #[pyo3(get)]
+  #[get = "pub"]
+  time_ms: u64,
+  /// The number of times the rule was not scheduled again for the same scope within a propagation, since it was
+  /// already pending (or applied without any edit since), e.g. for a diamond-shaped rule graph
+  #[pyo3(get)]
+  #[get = "pub"]
+  #[serde(default)]
+  suppressed_duplicates: usize,
+  // The time spent matching the rule, accumulated without rounding each measurement to milliseconds
+  #[serde(skip)]
+  elapsed: Duration,
+}
+
+gen_py_str_methods!(RuleStat);
+
+impl RuleStat {
+  /// Records a single match of the rule, along with the edit applied for it (if `applied`).
+  pub(crate) fn record_match(&mut self, applied: bool) {
+    self.matches += 1;
+    if applied {
+      self.applications += 1;
+    }
+  }
+
+  pub(crate) fn record_suppressed_duplicate(&mut self) {
+    self.suppressed_duplicates += 1;
+  }
+
+  pub(crate) fn record_time(&mut self, elapsed: Duration) {
+    self.elapsed += elapsed;
+    self.time_ms = self.elapsed.as_millis() as u64;
+  }
+
+  /// Adds the statistics of the rule in another file (or pass).
+  pub(crate) fn add(&mut self, other: &RuleStat) {
+    self.matches += other.matches;
+    self.applications += other.applications;
+    self.suppressed_duplicates += other.suppressed_duplicates;
+    self.record_time(other.elapsed);
+  }
+}
+
+/// Adds the candidate matches that were skipped (see `SkippedMatch`), counted per rule and reason, to the matches
+/// of their rule in the `rule_statistics`, except for the duplicates of a recorded match and the edits vetoed by a
+/// plugin once applied, which were already counted.
+pub(crate) fn count_skipped_matches(
+  rule_statistics: &mut HashMap<String, RuleStat>,
+  skip_counts: &BTreeMap<(String, SkipReason), usize>,
+) {
+  for ((rule_name, reason), count) in skip_counts {
+    if !matches!(
+      reason,
+      SkipReason::DuplicateMatch | SkipReason::VetoedByPlugin
+    ) {
+      rule_statistics
+        .entry(rule_name.to_string())
+        .or_default()
+        .matches += count;
+    }
+  }
+}
+
 impl PiranhaOutputSummary {
   pub(crate) fn new(source_code_unit: &SourceCodeUnit) -> PiranhaOutputSummary {
+    let notes = source_code_unit
+      .timed_out_rule()
+      .iter()
+      .map(|rule| {
+        format!(
+          "Timed out after {} second(s) while applying the rule `{rule}`: the remaining rules were not applied",
+          source_code_unit
+            .piranha_arguments()
+            .per_file_timeout_seconds()
+            .unwrap_or_default()
+        )
+      })
+      .chain(source_code_unit.notes().iter().cloned())
+      .collect_vec();
     return PiranhaOutputSummary {
       path: String::from(source_code_unit.path().as_os_str().to_str().unwrap()),
       original_content: source_code_unit.original_content().to_string(),
       content: source_code_unit.code().to_string(),
       matches: source_code_unit.matches().iter().cloned().collect_vec(),
+      match_ranges: source_code_unit.match_ranges().clone(),
+      rewrites_by_variant: get_rewrites_by_variant(source_code_unit.rewrites()),
       rewrites: source_code_unit.rewrites().iter().cloned().collect_vec(),
+      suggested_rewrites: source_code_unit.suggested_rewrites().clone(),
+      rule_statistics: source_code_unit.rule_statistics().clone(),
+      notes,
+      propagation_traces: source_code_unit.propagation_traces().clone(),
+      ..Default::default()
     };
   }
+
+  /// Adds the `skip_counts` (per rule and reason) to the summary, and to the matches of the `rule_statistics`,
+  /// along with the `skipped_matches` themselves (only kept when `explain` is enabled).
+  pub(crate) fn with_skipped_matches(
+    mut self, skipped_matches: Vec<SkippedMatch>,
+    skip_counts: &BTreeMap<(String, SkipReason), usize>,
+  ) -> PiranhaOutputSummary {
+    count_skipped_matches(&mut self.rule_statistics, skip_counts);
+    for ((rule_name, reason), count) in skip_counts {
+      *self
+        .skip_counts
+        .entry(rule_name.to_string())
+        .or_default()
+        .entry(reason.to_string())
+        .or_insert(0) += count;
+    }
+    self.skipped_matches = skipped_matches;
+    self
+  }
+
+  /// Merges the summaries of independent runs (e.g. the shards of a distributed run) into a summary per file (path),
+  /// in the order the files first appear. When several summaries are for the same file, the later one replaces the
+  /// earlier ones (along with their rewrites, matches, skipped matches, notes and `rule_statistics`, which are hence
+  /// not counted twice), but keeps the `original_content` of the first one.
+  pub fn merge(summaries: Vec<PiranhaOutputSummary>) -> Vec<PiranhaOutputSummary> {
+    let mut merged: Vec<PiranhaOutputSummary> = vec![];
+    for summary in summaries {
+      match merged.iter_mut().find(|m| m.path == summary.path) {
+        Some(earlier) => {
+          let original_content = std::mem::take(&mut earlier.original_content);
+          *earlier = PiranhaOutputSummary {
+            original_content,
+            ..summary
+          };
+        }
+        None => merged.push(summary),
+      }
+    }
+    merged
+  }
+
+  /// Adds the seed rules of the pass that produced neither an edit nor a match, along with why.
+  pub(crate) fn with_unapplied_seed_rules(
+    mut self, unapplied_seed_rules: &BTreeMap<String, UnappliedSeedRule>,
+  ) -> PiranhaOutputSummary {
+    self.unapplied_seed_rules = unapplied_seed_rules
+      .iter()
+      .map(|(rule, reason)| (rule.to_string(), reason.to_string()))
+      .collect();
+    self
+  }
+
+  /// Attributes the summary to the `stage` of the migration.
+  pub(crate) fn with_stage(mut self, stage: &str) -> PiranhaOutputSummary {
+    self.stage = Some(stage.to_string());
+    self
+  }
+
+  /// Checks if the file was skipped or reported with a note (e.g. a timeout), i.e. the warnings gating the next stages.
+  pub(crate) fn has_warnings(&self) -> bool {
+    !self.notes.is_empty() || !self.parse_errors.is_empty()
+  }
+
+  /// Returns a copy of the final content where the matches of the rule `rule_name` are highlighted (in yellow).
+  /// The matches are located in the final content by their ranges shifted by the later rewrites, hence the matches
+  /// whose code was rewritten are not highlighted.
+  pub fn highlight_matches(&self, rule_name: &str) -> String {
+    highlight(
+      self.content(),
+      self
+        .matches
+        .iter()
+        .zip(self.match_ranges.iter())
+        .filter(|((name, _), _)| name == rule_name)
+        .filter_map(|((_, m), range)| {
+          range.filter(|(start, end)| self.content.get(*start..*end) == Some(m.matched_string()))
+        }),
+    )
+  }
+
+  /// Renders each rewrite (in order) against the code it was applied to (see `render_edit`), i.e. the original
+  /// content with the previous rewrites applied. The rendering stops at the first rewrite whose range does not
+  /// match the reconstructed code (e.g. after the consecutive new lines were deleted).
+  pub fn render_rewrites(&self, options: RenderOptions) -> Vec<String> {
+    let mut code = self.original_content.clone();
+    let mut rendered = vec![];
+    for edit in &self.rewrites {
+      let range = edit.p_match().range();
+      if code.get(range.start_byte..range.end_byte) != Some(edit.p_match().matched_string()) {
+        break;
+      }
+      rendered.push(render_edit(edit, &code, options));
+      code.replace_range(range.start_byte..range.end_byte, edit.replacement_string());
+    }
+    rendered
+  }
+
+  /// Writes the rewrites of the `summaries` as CSV, one row per edit (in order) with the columns `file_path`,
+  /// `rule_name`, `start_line`, `end_line` (1-based), `matched_text` and `replacement_text`.
+  pub fn write_csv(summaries: &[PiranhaOutputSummary], writer: impl Write) -> csv::Result<()> {
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    csv_writer.write_record([
+      "file_path",
+      "rule_name",
+      "start_line",
+      "end_line",
+      "matched_text",
+      "replacement_text",
+    ])?;
+    for summary in summaries {
+      for edit in summary.rewrites() {
+        let range = edit.p_match().range();
+        csv_writer.write_record([
+          summary.path(),
+          edit.matched_rule(),
+          &(range.start_point.row + 1).to_string(),
+          &(range.end_point.row + 1).to_string(),
+          edit.p_match().matched_string(),
+          edit.replacement_string(),
+        ])?;
+      }
+    }
+    csv_writer.flush()?;
+    Ok(())
+  }
+
+  /// Summary for a file that is not parsed by tree-sitter (like `project.pbxproj`), and hence has no matches or rewrites.
+  pub(crate) fn for_special_file(
+    path: &Path, original_content: String, content: String,
+  ) -> PiranhaOutputSummary {
+    PiranhaOutputSummary {
+      path: String::from(path.as_os_str().to_str().unwrap()),
+      original_content,
+      content,
+      ..Default::default()
+    }
+  }
+
+  /// Summary for a file that was not processed by Piranha, explaining why in the `note`.
+  fn for_unprocessed_file(path: &Path, content: String, note: String) -> PiranhaOutputSummary {
+    PiranhaOutputSummary {
+      path: String::from(path.as_os_str().to_str().unwrap()),
+      original_content: content.to_string(),
+      content,
+      notes: vec![note],
+      ..Default::default()
+    }
+  }
+
+  /// Summary for a file that was skipped without being read into a source code unit (see `SkippedFile`),
+  /// hence without its content.
+  pub(crate) fn for_skipped_file(skipped_file: &SkippedFile) -> PiranhaOutputSummary {
+    PiranhaOutputSummary {
+      skipped_files: vec![skipped_file.clone()],
+      ..Self::for_unprocessed_file(
+        Path::new(skipped_file.path()),
+        String::new(),
+        skipped_file.reason().to_string(),
+      )
+    }
+  }
+
+  /// Checks if the file was skipped without being read into the summary (hence the `content` is not its content).
+  pub(crate) fn is_skipped_file(&self) -> bool {
+    !self.skipped_files.is_empty()
+  }
+
+  /// Summary for a file that was skipped because it is disabled by a leading `piranha:disable` comment.
+  pub(crate) fn for_disabled_file(source_code_unit: &SourceCodeUnit) -> PiranhaOutputSummary {
+    Self::for_unprocessed_file(
+      source_code_unit.path(),
+      source_code_unit.original_content().to_string(),
+      "Skipped: disabled by a `piranha:disable` comment".to_string(),
+    )
+  }
+
+  /// Summary for a file that was skipped because it contains syntax errors (see `OnParseError::Skip`).
+  pub(crate) fn for_file_with_parse_errors(
+    source_code_unit: &SourceCodeUnit,
+  ) -> PiranhaOutputSummary {
+    let parse_errors = source_code_unit.parse_errors().clone();
+    PiranhaOutputSummary {
+      parse_errors,
+      ..Self::for_unprocessed_file(
+        source_code_unit.path(),
+        source_code_unit.original_content().to_string(),
+        format!(
+          "Skipped: contains {} syntax error(s)",
+          source_code_unit.parse_errors().len()
+        ),
+      )
+    }
+  }
 }
+
+/// A line of the JSON Lines file where the matches are written (see `PiranhaArguments::matches_output`)
+#[derive(Serialize, Debug, Clone, Deserialize, Getters)]
+pub struct MatchRecord {
+  /// Path to the file
+  #[get = "pub"]
+  path: String,
+  /// Name of the rule
+  #[get = "pub"]
+  rule: String,
+  /// The match
+  #[get = "pub"]
+  #[serde(rename = "match")]
+  p_match: Match,
+}
+
+impl MatchRecord {
+  pub(crate) fn new(path: &Path, rule: String, p_match: Match) -> MatchRecord {
+    MatchRecord {
+      path: String::from(path.as_os_str().to_str().unwrap()),
+      rule,
+      p_match,
+    }
+  }
+}
+
+#[cfg(test)]
+#[path = "unit_tests/piranha_output_test.rs"]
+mod piranha_output_test;
@@ -0,0 +1,29 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use super::source_code_view::SourceCodeView;
+
+/// A hook for injecting custom (Rust) code in between the rule application steps of Piranha.
+/// It can be used for custom validations, metrics collection, etc.
+///
+/// Plugins are passed to `execute_piranha_with_plugins` and are invoked (in order) for each file, with a read-only
+/// view of its source code unit.
+pub trait PiranhaPlugin: Send + Sync {
+  /// Invoked once per file, before the rules are first applied to it (i.e. not again in the next passes).
+  /// Returns the pre-processed source code (if any), which will replace the current content of the file.
+  fn before_apply(&self, scu: &SourceCodeView) -> Option<String>;
+
+  /// Invoked after the rules are applied to the source code unit.
+  /// Returns `false` to veto writing the updated content of the file to the disk.
+  fn after_apply(&self, scu: &SourceCodeView) -> bool;
+}
@@ -0,0 +1,144 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use std::{
+  fmt::Debug,
+  io::Write,
+  path::PathBuf,
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+/// The progress of a run of Piranha, reported to the `ProgressSink`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressEvent {
+  /// The `global` rules are (again) applied to the relevant files, i.e. `number_of_files` files.
+  /// Each time a `global` rule is added, the files are scanned again.
+  GlobalPhaseStarted { number_of_files: usize },
+  /// The rules are about to be applied to the file
+  FileStarted { path: PathBuf },
+  /// The rule rewrote the file (once per edit)
+  RuleApplied { rule: String, path: PathBuf },
+  /// The rules were applied to the file, which was rewritten by `edits` edits (sent for every started file,
+  /// i.e. with no edit if the file was skipped)
+  FileDone { path: PathBuf, edits: usize },
+  /// The updated file was written (or deleted) on the disk
+  FileWritten { path: PathBuf },
+}
+
+/// A hook for library consumers to report the progress of a run, e.g. on big code bases.
+///
+/// The sink is passed via `PiranhaArguments::progress_sink` (the command line renders a progress bar with `--progress`),
+/// and receives the events as they happen. It may receive them from several threads, hence it must be `Sync`.
+pub trait ProgressSink: Send + Sync + Debug {
+  fn on_event(&self, event: &ProgressEvent);
+}
+
+/// Renders the progress as a bar on the standard error (see `--progress`), along with the estimated remaining time
+/// of the current phase, e.g. `[=========>          ] 12/40 files | 7 edits | ETA 1m05s | src/Foo.java`.
+#[derive(Debug, Default)]
+pub(crate) struct ProgressBar {
+  state: Mutex<ProgressState>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ProgressState {
+  // The number of files of the current phase, and the number of them already started
+  total: usize,
+  processed: usize,
+  // The number of edits so far (across all the phases)
+  edits: usize,
+  current_file: Option<PathBuf>,
+  // When the current phase started (to estimate its remaining time)
+  phase_started: Option<Instant>,
+}
+
+// The number of characters of the bar itself
+const BAR_WIDTH: usize = 20;
+
+impl ProgressSink for ProgressBar {
+  fn on_event(&self, event: &ProgressEvent) {
+    let line = self.state.lock().unwrap().update(event, Instant::now());
+    // Overwrite the previous line of the bar
+    let mut stderr = std::io::stderr().lock();
+    _ = write!(stderr, "\r{line}\x1b[K");
+    _ = stderr.flush();
+  }
+}
+
+impl ProgressState {
+  /// Updates the state with the `event` (received at `now`), and returns the line of the bar to render.
+  fn update(&mut self, event: &ProgressEvent, now: Instant) -> String {
+    match event {
+      ProgressEvent::GlobalPhaseStarted { number_of_files } => {
+        self.total = *number_of_files;
+        self.processed = 0;
+        self.phase_started = Some(now);
+      }
+      ProgressEvent::FileStarted { path } => {
+        self.processed += 1;
+        self.current_file = Some(path.clone());
+      }
+      ProgressEvent::RuleApplied { .. } => self.edits += 1,
+      ProgressEvent::FileDone { .. } => {}
+      ProgressEvent::FileWritten { path } => self.current_file = Some(path.clone()),
+    }
+    self.render(now)
+  }
+
+  /// The estimated remaining time of the current phase at `now`, assuming that the remaining files take as long
+  /// (on average) as the files started so far
+  fn eta(&self, now: Instant) -> Option<Duration> {
+    let phase_started = self.phase_started?;
+    if self.processed == 0 || self.processed >= self.total {
+      return None;
+    }
+    let remaining = (self.total - self.processed) as u32;
+    Some(now.saturating_duration_since(phase_started) / self.processed as u32 * remaining)
+  }
+
+  fn render(&self, now: Instant) -> String {
+    let filled = (BAR_WIDTH * self.processed.min(self.total))
+      .checked_div(self.total)
+      .unwrap_or(BAR_WIDTH);
+    let bar = match filled {
+      0 => " ".repeat(BAR_WIDTH),
+      BAR_WIDTH => "=".repeat(BAR_WIDTH),
+      _ => format!(
+        "{}>{}",
+        "=".repeat(filled - 1),
+        " ".repeat(BAR_WIDTH - filled)
+      ),
+    };
+    let current_file = self
+      .current_file
+      .as_ref()
+      .map(|p| format!(" | {}", p.display()))
+      .unwrap_or_default();
+    let eta = self
+      .eta(now)
+      .map(|eta| match eta.as_secs() {
+        s if s < 60 => format!(" | ETA {s}s"),
+        s => format!(" | ETA {}m{:02}s", s / 60, s % 60),
+      })
+      .unwrap_or_default();
+    format!(
+      "[{bar}] {}/{} files | {} edits{eta}{current_file}",
+      self.processed, self.total, self.edits
+    )
+  }
+}
+
+#[cfg(test)]
+#[path = "unit_tests/progress_test.rs"]
+mod progress_test;
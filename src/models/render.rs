@@ -0,0 +1,223 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+//! Renders an edit as a diagnostic-style block for human review (e.g. by a review bot), i.e. the lines of the
+//! matched code (along with some context), numbered in a gutter, with the matched code underlined by carets,
+//! followed by a preview of the replacement:
+//!
+//! ```text
+//! replace_foo (2:14)
+//!   |
+//! 1 | class A {
+//! 2 |   void m() { foo(FLAG); }
+//!   |              ^^^^^^^^^
+//! 3 | }
+//!   = replace with: bar()
+//! ```
+
+use getset::CopyGetters;
+use itertools::Itertools;
+use pyo3::prelude::{pyclass, pyfunction, pymethods};
+
+use crate::utilities::gen_py_str_methods;
+
+use super::edit::Edit;
+
+const DEFAULT_CONTEXT_LINES: usize = 2;
+const DEFAULT_MAX_WIDTH: usize = 100;
+
+/// How an edit is rendered (see `render_edit`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, CopyGetters)]
+#[pyclass]
+pub struct RenderOptions {
+  /// The number of lines shown before and after the matched lines
+  #[get_copy = "pub"]
+  #[pyo3(get)]
+  context_lines: usize,
+  /// The maximum width of the rendered lines (excluding the gutter), the longer ones are truncated
+  #[get_copy = "pub"]
+  #[pyo3(get)]
+  max_width: usize,
+}
+
+impl RenderOptions {
+  pub fn new(context_lines: usize, max_width: usize) -> Self {
+    RenderOptions {
+      context_lines,
+      max_width,
+    }
+  }
+}
+
+impl Default for RenderOptions {
+  fn default() -> Self {
+    RenderOptions::new(DEFAULT_CONTEXT_LINES, DEFAULT_MAX_WIDTH)
+  }
+}
+
+#[pymethods]
+impl RenderOptions {
+  #[new]
+  fn py_new(context_lines: Option<usize>, max_width: Option<usize>) -> Self {
+    RenderOptions::new(
+      context_lines.unwrap_or(DEFAULT_CONTEXT_LINES),
+      max_width.unwrap_or(DEFAULT_MAX_WIDTH),
+    )
+  }
+  gen_py_str_methods!();
+}
+
+/// Renders the `edit` applied to the `original_code` (i.e. the code its range refers to) as a diagnostic-style
+/// block (see above). The header names the rule, along with the (1-based) line and column of the match.
+pub fn render_edit(edit: &Edit, original_code: &str, options: RenderOptions) -> String {
+  let range = edit.p_match().range();
+  // The range may not refer to the given code (e.g. if it was applied to another version of the file), it is thus
+  // clamped to the code and moved back to the closest character boundaries (every line is then sliced on those)
+  let start_byte = floor_char_boundary(original_code, range.start_byte);
+  let end_byte = floor_char_boundary(original_code, range.end_byte).max(start_byte);
+
+  let lines = original_code.split('\n').collect_vec();
+  let line_starts = lines
+    .iter()
+    .scan(0, |start, line| {
+      let line_start = *start;
+      *start += line.len() + 1;
+      Some(line_start)
+    })
+    .collect_vec();
+  let row_of = |byte: usize| line_starts.partition_point(|start| *start <= byte) - 1;
+  let start_row = row_of(start_byte);
+  let mut end_row = row_of(end_byte);
+  // A match ending with a new line ends on the previous line
+  if end_row > start_row && end_byte == line_starts[end_row] {
+    end_row -= 1;
+  }
+  // The empty "line" after the last new line is only shown when the match is there (e.g. an insertion at the end)
+  let last_line = if original_code.ends_with('\n') {
+    lines.len().saturating_sub(2)
+  } else {
+    lines.len() - 1
+  };
+  let first_row = start_row.saturating_sub(options.context_lines);
+  let last_row = end_row.max((end_row + options.context_lines).min(last_line));
+
+  let width = (last_row + 1).to_string().len();
+  let gutter = format!("{:width$} |", "");
+  let truncate = |text: &str| {
+    if text.chars().count() <= options.max_width {
+      text.to_string()
+    } else {
+      let kept = options.max_width.saturating_sub(3);
+      format!("{}...", text.chars().take(kept).collect::<String>())
+    }
+  };
+  let with_gutter = |gutter: &str, text: &str| {
+    if text.is_empty() {
+      gutter.to_string()
+    } else {
+      format!("{gutter} {text}")
+    }
+  };
+
+  let start_line = lines[start_row]
+    .strip_suffix('\r')
+    .unwrap_or(lines[start_row]);
+  let start_column = start_line[..(start_byte - line_starts[start_row]).min(start_line.len())]
+    .chars()
+    .count();
+  let mut rendered = vec![
+    format!(
+      "{} ({}:{})",
+      edit.matched_rule(),
+      start_row + 1,
+      start_column + 1
+    ),
+    gutter.clone(),
+  ];
+  for row in first_row..=last_row {
+    let line = lines[row].strip_suffix('\r').unwrap_or(lines[row]);
+    rendered.push(with_gutter(
+      &format!("{:>width$} |", row + 1),
+      &truncate(line),
+    ));
+    if row < start_row || row > end_row {
+      continue;
+    }
+    // The matched part of the line (the lines within the match are underlined from their indentation)
+    let from = if row == start_row {
+      start_byte - line_starts[row]
+    } else {
+      line.len() - line.trim_start().len()
+    }
+    .min(line.len());
+    let to = if row == end_row {
+      end_byte - line_starts[row]
+    } else {
+      line.len()
+    }
+    .clamp(from, line.len());
+    let carets = if from < to {
+      line[from..to].chars().count()
+    } else if start_byte == end_byte {
+      // An insertion is pointed at
+      1
+    } else {
+      continue;
+    };
+    let underline = format!(
+      "{}{}",
+      " ".repeat(line[..from].chars().count()),
+      "^".repeat(carets)
+    );
+    rendered.push(with_gutter(
+      &gutter,
+      &underline
+        .chars()
+        .take(options.max_width)
+        .collect::<String>(),
+    ));
+  }
+
+  let replacement = edit.replacement_string().trim_end_matches(['\n', '\r']);
+  let preview = format!("{:width$} =", "");
+  if edit.is_delete() {
+    rendered.push(format!("{preview} delete"));
+  } else if !replacement.contains('\n') {
+    rendered.push(format!("{preview} replace with: {}", truncate(replacement)));
+  } else {
+    rendered.push(format!("{preview} replace with:"));
+    for line in replacement.lines() {
+      rendered.push(with_gutter(&gutter, &truncate(line)));
+    }
+  }
+  rendered.join("\n")
+}
+
+/// The largest character boundary of `code` that is at most `byte` (i.e. `str::floor_char_boundary`)
+fn floor_char_boundary(code: &str, byte: usize) -> usize {
+  (0..=byte.min(code.len()))
+    .rev()
+    .find(|b| code.is_char_boundary(*b))
+    .unwrap_or_default()
+}
+
+/// Renders the `edit` applied to the `original_code` as a diagnostic-style block for human review
+/// (with the default `RenderOptions` if no `options` are given).
+#[pyfunction(name = "render_edit")]
+pub fn py_render_edit(edit: &Edit, original_code: &str, options: Option<RenderOptions>) -> String {
+  render_edit(edit, original_code, options.unwrap_or_default())
+}
+
+#[cfg(test)]
+#[path = "unit_tests/render_test.rs"]
+mod render_test;
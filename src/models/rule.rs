@@ -11,33 +11,126 @@ Copyright (c) 2023 Uber Technologies, Inc.
  limitations under the License.
 */
 
-use std::collections::{HashMap, HashSet};
+use std::{
+  collections::{hash_map::Entry, HashMap, HashSet},
+  fmt,
+  hash::{DefaultHasher, Hash, Hasher},
+  path::{Path, PathBuf},
+  str::FromStr,
+  sync::OnceLock,
+};
 
 use colored::Colorize;
 use derive_builder::Builder;
 use getset::Getters;
+use glob::Pattern;
+use itertools::Itertools;
 use pyo3::prelude::{pyclass, pymethods};
-use serde_derive::Deserialize;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Serializer;
+use serde_derive::{Deserialize, Serialize};
+
+use tree_sitter_traversal::{traverse, Order};
 
-use crate::utilities::{gen_py_str_methods, Instantiate};
+use crate::utilities::{
+  capture_all_nodes, gen_py_str_methods, get_tag_references, is_bound_by, is_likely_misspelling,
+  read_file, serialize_sorted, substitute_tags_regex,
+  tree_sitter_utilities::{get_tree_sitter_edit, get_ts_query_parser},
+  Instantiate, Sha256Hasher, RESERVED_TAG_PREFIX,
+};
 
 use super::{
   capture_group_patterns::CGPattern,
+  cst_pattern::{cst_to_query, get_cst_holes, CST_TAG},
   default_configs::{
-    default_filters, default_groups, default_holes, default_is_seed_rule, default_query,
-    default_replace, default_replace_idx, default_replace_node, default_rule_name,
+    default_capture_all_groups, default_delete_empty_enclosing, default_directory_scope,
+    default_dry, default_exactly_one_match, default_exclusive_group, default_filters,
+    default_groups, default_holes, default_is_seed_rule, default_package_filter, default_prefilter,
+    default_query, default_query_cst, default_query_file, default_replace, default_replace_idx,
+    default_replace_node, default_replace_with_capture, default_requires_features,
+    default_rule_language, default_rule_name, default_rule_scope, default_template_params,
   },
+  edit::TriggeredBy,
   filter::Filter,
+  language::{built_in_name, PiranhaLanguage},
+  package_filter::PackageFilter,
+  rule_graph::MULTI_FILE,
+  rule_store::RuleStore,
+  source_code_view::SourceCodeView,
+  stage::Stage,
   Validator,
 };
 
-#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
-// Represents the `rules.toml` file
+/// The contents of the query files (see `Rule::query_file`) read so far, by (canonical) path
+pub(crate) type QueryFiles = HashMap<PathBuf, String>;
+
+/// The annotation of the rules defined in code comments (see `Rule::from_comment`)
+pub(crate) static RULE_COMMENT_MARKER: &str = "@piranha-rule:";
+
+#[derive(Deserialize, JsonSchema, Debug, Clone, Default, PartialEq)]
+// Represents the `rules.toml` file (whose other tables are ignored, see `Edges`)
 pub(crate) struct Rules {
+  #[serde(default)]
   pub(crate) rules: Vec<Rule>,
+  // The stages of the migration (see `Stage`)
+  #[serde(default)]
+  pub(crate) stages: Vec<Stage>,
+  // The instantiations of the rule templates (see `TemplateInstance`)
+  #[serde(default)]
+  pub(crate) template_instances: Vec<TemplateInstance>,
 }
 
-#[derive(Deserialize, Debug, Clone, Default, PartialEq, Getters, Builder)]
+/// An instantiation of a rule template (see `Rule::template_params`), i.e. the rule obtained by substituting
+/// each parameter of the template with its value
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq, Getters)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateInstance {
+  /// The name of the template
+  #[get = "pub"]
+  template: String,
+  /// The name of the instantiated rule. Defaults to the name of the template followed by the values of
+  /// the parameters (in the order of the `template_params`), e.g. `delete_isTreated`
+  #[serde(default)]
+  #[get = "pub"]
+  name: String,
+  /// The value of each parameter of the template
+  #[get = "pub"]
+  params: HashMap<String, String>,
+}
+
+impl TemplateInstance {
+  #[cfg(test)]
+  pub(crate) fn new(template: &str, name: &str, params: &[(&str, &str)]) -> Self {
+    Self {
+      template: template.to_string(),
+      name: name.to_string(),
+      params: params
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect(),
+    }
+  }
+
+  /// The name of the rule instantiated from the `template` (see `name`)
+  pub(crate) fn rule_name(&self, template: &Rule) -> String {
+    if !self.name.is_empty() {
+      return self.name.clone();
+    }
+    template
+      .template_params()
+      .iter()
+      .filter_map(|p| self.params.get(p))
+      .fold(self.template.clone(), |name, value| {
+        format!("{name}_{value}")
+      })
+  }
+}
+
+#[derive(
+  Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq, Getters, Builder,
+)]
+#[serde(deny_unknown_fields)]
 #[pyclass]
 pub struct Rule {
   /// Name of the rule. (It is unique)
@@ -48,10 +141,21 @@ pub struct Rule {
   /// Tree-sitter query as string
   #[builder(default = "default_query()")]
   #[serde(default = "default_query")]
-  #[get = "pub"]
   #[pyo3(get)]
   query: CGPattern,
-  /// The tag corresponding to the node to be replaced
+  /// Path to the file of the tree-sitter query (e.g. `queries/delete_flag.scm`), relative to the configuration
+  /// directory, instead of the `query`
+  #[builder(default = "default_query_file()")]
+  #[serde(default = "default_query_file")]
+  #[get = "pub"]
+  #[pyo3(get)]
+  query_file: String,
+  /// The query read from the `query_file` (see `load_query_file`)
+  #[builder(default)]
+  #[serde(skip)]
+  query_file_contents: Option<CGPattern>,
+  /// The tag corresponding to the node to be replaced. When prefixed with `@` (e.g. `@annotation`), only the node(s)
+  /// captured by the tag are replaced, while the filters are checked against the whole match (see `replaced_capture`)
   #[builder(default = "default_replace_node()")]
   #[serde(default = "default_replace_node")]
   #[get = "pub"]
@@ -69,21 +173,27 @@ pub struct Rule {
   #[get = "pub"]
   #[pyo3(get)]
   replace: String,
+  /// Replaces the node with the code captured by this tag, copied verbatim (i.e. instead of instantiating the `replace` pattern)
+  #[builder(default = "default_replace_with_capture()")]
+  #[serde(default = "default_replace_with_capture")]
+  #[get = "pub"]
+  #[pyo3(get)]
+  replace_with_capture: String,
   /// Group(s) to which the rule belongs
   #[builder(default = "default_groups()")]
-  #[serde(default = "default_groups")]
+  #[serde(default = "default_groups", serialize_with = "serialize_sorted")]
   #[get = "pub"]
   #[pyo3(get)]
   groups: HashSet<String>,
   /// Holes that need to be filled, in order to instantiate a rule
   #[builder(default = "default_holes()")]
-  #[serde(default = "default_holes")]
+  #[serde(default = "default_holes", serialize_with = "serialize_sorted")]
   #[get = "pub"]
   #[pyo3(get)]
   holes: HashSet<String>,
   /// Filters to test before applying a rule
   #[builder(default = "default_filters()")]
-  #[serde(default = "default_filters")]
+  #[serde(default = "default_filters", serialize_with = "serialize_filters")]
   #[get = "pub"]
   #[pyo3(get)]
   filters: HashSet<Filter>,
@@ -94,20 +204,508 @@ pub struct Rule {
   #[get = "pub"]
   #[pyo3(get)]
   is_seed_rule: bool,
+
+  /// Scope of a seed rule. The `MultiFile` seed rules are matched against all the files before any rewrite begins
+  #[builder(default = "default_rule_scope()")]
+  #[serde(default = "default_rule_scope")]
+  #[get = "pub"]
+  #[pyo3(get)]
+  scope: String,
+
+  /// Rewrites proceed only if the (`MultiFile`) rule matches exactly once across all the files
+  #[builder(default = "default_exactly_one_match()")]
+  #[serde(default = "default_exactly_one_match")]
+  #[get = "pub"]
+  #[pyo3(get)]
+  exactly_one_match: bool,
+
+  /// After a delete edit, also deletes the enclosing blocks/statements that become empty (recursively)
+  #[builder(default = "default_delete_empty_enclosing()")]
+  #[serde(default = "default_delete_empty_enclosing")]
+  #[get = "pub"]
+  #[pyo3(get)]
+  delete_empty_enclosing: bool,
+
+  /// Applies the rule only to the files whose declared package (or module) satisfies this filter
+  #[builder(default = "default_package_filter()")]
+  #[serde(default = "default_package_filter")]
+  #[get = "pub"]
+  #[pyo3(get)]
+  package_filter: Option<PackageFilter>,
+
+  /// The rule is applied only if all these features were detected in the code base, i.e. the `MultiFile`
+  /// match-only seed rules with these names matched at least once
+  #[builder(default = "default_requires_features()")]
+  #[serde(default = "default_requires_features")]
+  #[get = "pub"]
+  #[pyo3(get)]
+  requires_features: Vec<String>,
+
+  /// The language of the files the rule is applied to (e.g. `java`), if it differs from the language of the run.
+  /// The rule is matched (and its queries compiled) with the grammar of this language
+  #[builder(default = "default_rule_language()")]
+  #[serde(default = "default_rule_language")]
+  #[get = "pub"]
+  #[pyo3(get)]
+  language: String,
+
+  /// Applies the rule (as a seed or `Global` rule) only to the files whose path, relative to the code base, matches this glob
+  /// (e.g. `services/@service_name/**`, where the tags are the holes of the rule)
+  #[builder(default = "default_directory_scope()")]
+  #[serde(default = "default_directory_scope")]
+  #[get = "pub"]
+  #[pyo3(get)]
+  directory_scope: String,
+
+  /// Captures each named node of the query that has no capture yet with its kind, prefixed with `__` (e.g. `@__identifier`, `@__identifier_2`),
+  /// so that the code of any node can be referenced in the `replace` pattern
+  #[builder(default = "default_capture_all_groups()")]
+  #[serde(default = "default_capture_all_groups")]
+  #[get = "pub"]
+  #[pyo3(get)]
+  capture_all_groups: bool,
+
+  /// The concrete syntax of the code to match (e.g. `exp.isTreated(:[flag])`), instead of the `query`. It is compiled to
+  /// the equivalent query, where the holes (`:[name]`) match any single named node (see `cst_pattern`)
+  #[builder(default = "default_query_cst()")]
+  #[serde(default = "default_query_cst")]
+  #[get = "pub"]
+  #[pyo3(get)]
+  query_cst: String,
+
+  /// The mutual-exclusion group of the rule (if any). Once a rule of the group rewrote some code of a file, the other
+  /// rules of the group are not applied to the rewritten code (e.g. the "treated" and "control" cleanups of a flag)
+  #[builder(default = "default_exclusive_group()")]
+  #[serde(default = "default_exclusive_group")]
+  #[get = "pub"]
+  #[pyo3(get)]
+  exclusive_group: String,
+
+  /// Records the edits of the (rewrite) rule among the suggested rewrites of the file, instead of applying them
+  /// (hence they trigger no propagation), e.g. to stage a risky rule alongside the safe ones
+  #[builder(default = "default_dry()")]
+  #[serde(default = "default_dry")]
+  #[get = "pub"]
+  #[pyo3(get)]
+  dry: bool,
+
+  /// The parameters of the rule template (e.g. `api_method`), referenced as `@api_method` like the holes. A template
+  /// is not applied, but instantiated at load time with concrete values (see `TemplateInstance`), and the edges
+  /// to (or from) the template apply to all of its instantiations
+  #[builder(default = "default_template_params()")]
+  #[serde(default = "default_template_params")]
+  #[get = "pub"]
+  #[pyo3(get)]
+  template_params: Vec<String>,
+
+  /// Uses the values of the holes of the (instantiated) rule, e.g. the flag name, as literal anchors: the files
+  /// containing none of the anchors of the current rules are not parsed. Disable it for the rules whose holes do
+  /// not appear literally in the matched code (e.g. a hole only used in the replacement)
+  #[builder(default = "default_prefilter()")]
+  #[serde(default = "default_prefilter")]
+  #[get = "pub"]
+  #[pyo3(get)]
+  prefilter: bool,
 }
 
 impl Rule {
   /// Dummy rules are helper rules that make it easier to define the rule graph
   pub(crate) fn is_dummy_rule(&self) -> bool {
-    *self.query() == default_query() && *self.replace_node() == default_replace_node()
+    !self.has_query() && *self.replace_node() == default_replace_node()
+  }
+
+  /// Checks if the rule has a query (or a concrete syntax to compile to a query)
+  fn has_query(&self) -> bool {
+    *self.query() != default_query() || !self.query_cst().is_empty()
+  }
+
+  /// The tags of the query, or of the concrete syntax if it was not compiled yet (i.e. its holes and `@cst`)
+  fn query_tags(&self) -> Vec<String> {
+    if *self.query() == default_query() && !self.query_cst().is_empty() {
+      return get_cst_holes(self.query_cst())
+        .into_iter()
+        .chain([CST_TAG.to_string()])
+        .collect();
+    }
+    get_tag_references(&self.query().pattern())
+  }
+
+  /// The tag of the capture replaced within the match, when the `replace_node` is prefixed with `@` (e.g. `@annotation`).
+  /// Each node captured by the tag in a match is replaced (e.g. by a quantified tag), while the filters and the
+  /// tags still operate on the whole match (i.e. the outermost node of the query).
+  pub(crate) fn replaced_capture(&self) -> Option<&str> {
+    self.replace_node().strip_prefix('@')
   }
 
   /// Checks if a rule is `match-only` i.e. it has a query but no replace_node
   pub(crate) fn is_match_only_rule(&self) -> bool {
-    *self.query() != default_query() && *self.replace_node() == default_replace_node()
+    self.has_query() && *self.replace_node() == default_replace_node()
+  }
+
+  /// Checks if a rule is a seed rule (dummy rules are never applied as seed rules)
+  pub(crate) fn is_seed(&self) -> bool {
+    *self.is_seed_rule() && !self.is_dummy_rule()
+  }
+
+  /// Checks if a rule detects a feature of the code base (see `requires_features`), i.e. it is a
+  /// `MultiFile` match-only seed rule
+  pub(crate) fn is_feature_detection_rule(&self) -> bool {
+    self.is_multi_file_rule() && self.is_match_only_rule() && self.is_seed()
+  }
+
+  /// Checks if a rule should be matched against all the files before any rewrite begins
+  pub(crate) fn is_multi_file_rule(&self) -> bool {
+    self.scope() == MULTI_FILE
+  }
+
+  /// The language of the files the rule is applied to, i.e. its `language` or else the language of the run
+  pub(crate) fn language_or<'a>(&'a self, run_language: &'a str) -> &'a str {
+    if self.language().is_empty() {
+      run_language
+    } else {
+      self.language()
+    }
+  }
+
+  /// The built-in rule of the `language` (other than the language of the run), whose name and groups are prefixed
+  /// by the `language` (see `built_in_name`), and which is applied to the files of the `language`
+  pub(crate) fn for_language(&self, language: &str) -> Rule {
+    Rule {
+      name: built_in_name(language, self.name()),
+      groups: self
+        .groups()
+        .iter()
+        .map(|g| built_in_name(language, g))
+        .collect(),
+      language: language.to_string(),
+      ..self.clone()
+    }
+  }
+
+  /// Checks if the rule is a template (see `template_params`)
+  pub(crate) fn is_template(&self) -> bool {
+    !self.template_params().is_empty()
+  }
+
+  /// Instantiates this template with the `params` of the `instance`, i.e. substitutes them in the queries, the
+  /// replacement and the filters. The instantiated rule is named after the `instance`, and belongs to the
+  /// group named after the template (so that the edges of the template apply to it).
+  pub(crate) fn instantiate_template(&self, instance: &TemplateInstance) -> Result<Rule, String> {
+    let rule_name = instance.rule_name(self);
+    let expected = self.template_params().iter().sorted().collect_vec();
+    if instance.params().keys().sorted().collect_vec() != expected {
+      return Err(format!(
+        "The instantiation `{rule_name}` of the template `{}` must provide exactly the parameters {}.",
+        self.name(),
+        expected.iter().map(|p| format!("`{p}`")).join(", ")
+      ));
+    }
+    let params = instance.params();
+    let mut groups = self.groups().clone();
+    groups.insert(self.name().to_string());
+    Ok(Rule {
+      name: rule_name,
+      query: self.query.instantiate(params),
+      query_file_contents: self
+        .query_file_contents
+        .as_ref()
+        .map(|q| q.instantiate(params)),
+      query_cst: self.query_cst().instantiate(params),
+      replace: self.replace().instantiate(params),
+      directory_scope: self.directory_scope().instantiate(params),
+      package_filter: self
+        .package_filter()
+        .as_ref()
+        .map(|p| p.instantiate(params)),
+      filters: self
+        .filters()
+        .iter()
+        .map(|f| f.instantiate(params))
+        .collect(),
+      groups,
+      template_params: vec![],
+      ..self.clone()
+    })
+  }
+
+  /// The number of (distinct) captures of the query, e.g. `1` for `((identifier) @id (#eq? @id "@flag"))`
+  /// (where `@flag` is part of a string, e.g. a hole). The named nodes count as captures if `capture_all_groups` is set.
+  pub fn capture_count(&self) -> usize {
+    let query = self.query_with_captures().pattern();
+    let Some(tree) = get_ts_query_parser().parse(&query, None) else {
+      return 0;
+    };
+    traverse(tree.walk(), Order::Pre)
+      .filter(|node| node.kind() == "capture")
+      .filter_map(|node| node.utf8_text(query.as_bytes()).ok())
+      .unique()
+      .count()
+  }
+
+  /// Returns the code that would result from applying the rule to its first match in the code of the `view`
+  /// (with the substitutions of the file for its holes), without applying the edit, e.g. to let the user accept or
+  /// reject it. Returns `None` if the rule is match-only, if one of its holes has no substitution, or if it does not
+  /// match.
+  pub fn preview(&self, view: &SourceCodeView) -> Option<String> {
+    let source_code_unit = view.source_code_unit();
+    let mut rule = self.clone();
+    rule
+      .compile_query_cst(source_code_unit.piranha_arguments().language())
+      .ok()?;
+    let substitutions = source_code_unit.substitutions();
+    if rule.is_match_only_rule() || rule.holes().iter().any(|h| !substitutions.contains_key(h)) {
+      return None;
+    }
+    let rule = InstantiatedRule::new(&rule, substitutions);
+    let mut rule_store = RuleStore::new(source_code_unit.piranha_arguments());
+    let edit =
+      source_code_unit.get_edit(&rule, &mut rule_store, source_code_unit.root_node(), true)?;
+    let (code, _) = get_tree_sitter_edit(source_code_unit.code().to_string(), &edit);
+    Some(code)
+  }
+
+  /// The query of the rule, where each named node is captured if `capture_all_groups` is set
+  pub(crate) fn query_with_captures(&self) -> CGPattern {
+    if *self.capture_all_groups() {
+      CGPattern::new(capture_all_nodes(&self.query().pattern()))
+    } else {
+      self.query().clone()
+    }
+  }
+
+  /// The condition of this `MultiFile` rule that the number of matches (aggregated across all the files) does not
+  /// satisfy, if any (e.g. `match exactly once`)
+  pub(crate) fn unsatisfied_condition(&self, number_of_matches: usize) -> Option<&'static str> {
+    (*self.exactly_one_match() && number_of_matches != 1).then_some("match exactly once")
+  }
+
+  /// Tree-sitter query of the rule, i.e. its `query`, or the query read from its `query_file` (see `load_query_file`).
+  pub fn query(&self) -> &CGPattern {
+    self.query_file_contents.as_ref().unwrap_or(&self.query)
+  }
+
+  /// Loads the query of the rule from its `query_file` (if any), looked up in the `directories` in order (unless it
+  /// is absolute). The `query_file` is kept as is (e.g. when the rule is exported), while the query read from it is
+  /// stored along with it (see `query`).
+  /// Each file is read once, its content being cached in `query_files`.
+  pub(crate) fn load_query_file(
+    &mut self, directories: &[PathBuf], query_files: &mut QueryFiles,
+  ) -> Result<(), String> {
+    if self.query_file().is_empty() {
+      return Ok(());
+    }
+    if self.query != default_query() {
+      return Err(format!(
+        "The rule `{}` cannot have both a `query` and a `query_file`.",
+        self.name()
+      ));
+    }
+    let path = directories
+      .iter()
+      .map(|d| d.join(self.query_file()))
+      .find(|p| p.is_file())
+      .and_then(|p| p.canonicalize().ok())
+      .ok_or_else(|| {
+        format!(
+          "Could not find the query file `{}` of the rule `{}` (in {directories:?})",
+          self.query_file(),
+          self.name()
+        )
+      })?;
+    let query = match query_files.entry(path.clone()) {
+      Entry::Occupied(e) => e.get().to_string(),
+      Entry::Vacant(e) => e
+        .insert(read_file(&path).map_err(|err| {
+          format!(
+            "Could not read the query file {path:?} of the rule `{}` - {err}",
+            self.name()
+          )
+        })?)
+        .to_string(),
+    };
+    self.query_file_contents = Some(CGPattern::new(query));
+    Ok(())
+  }
+
+  /// Compiles the `query_cst` of the rule (if any) to its query, with the grammar of its language
+  /// (the `run_language`, unless the rule targets another one, see `Rule::language`).
+  pub(crate) fn compile_query_cst(&mut self, run_language: &PiranhaLanguage) -> Result<(), String> {
+    if self.query_cst().is_empty() {
+      return Ok(());
+    }
+    let language = if self.language().is_empty() {
+      run_language.clone()
+    } else {
+      PiranhaLanguage::from(self.language().as_str())
+    };
+    let query = cst_to_query(self.query_cst(), &language)
+      .map_err(|e| format!("Invalid `query_cst` for the rule `{}` - {e}", self.name()))?;
+    // The query may have been compiled from the concrete syntax already (e.g. before the rule was exported)
+    if *self.query() != default_query() && self.query().pattern() != query {
+      return Err(format!(
+        "The rule `{}` cannot have both a `query` and a `query_cst`.",
+        self.name()
+      ));
+    }
+    self.query = CGPattern::new(query);
+    Ok(())
+  }
+
+  /// The DOT attributes for rendering this rule in the rule graph, filled if it `is_seed`
+  /// (labelled with the file of its query, if it was read from a `query_file`)
+  pub(crate) fn dot_style(&self, is_seed: bool) -> String {
+    let shape = if self.is_dummy_rule() {
+      "diamond"
+    } else if self.is_match_only_rule() {
+      "box"
+    } else {
+      "ellipse"
+    };
+    let fill = if is_seed {
+      ", style = filled, fillcolor = lightblue"
+    } else {
+      ""
+    };
+    let label = if self.query_file().is_empty() {
+      String::new()
+    } else {
+      let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+      format!(
+        ", label = \"{}\\n{}\"",
+        escape(self.name()),
+        escape(self.query_file())
+      )
+    };
+    format!("shape = {shape}{fill}{label}")
+  }
+
+  /// The (opening, closing) brackets for rendering this rule in a Mermaid flowchart
+  pub(crate) fn mermaid_shape(&self) -> (&str, &str) {
+    if self.is_dummy_rule() {
+      ("{", "}")
+    } else if self.is_match_only_rule() {
+      ("[", "]")
+    } else {
+      ("([", "])")
+    }
+  }
+
+  /// Parses the rule annotated in a code comment, e.g.
+  /// `// @piranha-rule: name="rename_foo" query="((identifier) @id (#eq? @id \"foo\"))" replacement="bar"`.
+  /// The (double-quoted, with `\"` and `\\` escapes) values of the `name`, `query`, `replace_node`,
+  /// `replacement` (or `replace`), `groups` (comma separated) and `language` keys follow the annotation.
+  /// When omitted, `replace_node` defaults to the tag of the query if it has a single one (when a replacement is
+  /// given), and `name` to one derived from the query and the replacement.
+  pub fn from_comment(comment_text: &str) -> Result<Rule, ParseError> {
+    static KEY_VALUE_REGEX: OnceLock<Regex> = OnceLock::new();
+    static ESCAPED_CHAR_REGEX: OnceLock<Regex> = OnceLock::new();
+    static TAG_REGEX: OnceLock<Regex> = OnceLock::new();
+    let Some((_, annotation)) = comment_text.split_once(RULE_COMMENT_MARKER) else {
+      return Err(ParseError::MissingAnnotation);
+    };
+    let key_value =
+      KEY_VALUE_REGEX.get_or_init(|| Regex::new(r#"(\w+)\s*=\s*"((?:[^"\\]|\\.)*)""#).unwrap());
+    let escaped_char = ESCAPED_CHAR_REGEX.get_or_init(|| Regex::new(r"\\(.)").unwrap());
+    let mut values = HashMap::new();
+    for captures in key_value.captures_iter(annotation) {
+      let value = escaped_char.replace_all(&captures[2], "$1");
+      if values
+        .insert(captures[1].to_string(), value.to_string())
+        .is_some()
+      {
+        return Err(ParseError::DuplicateKey(captures[1].to_string()));
+      }
+    }
+
+    let mut rule_builder = RuleBuilder::default();
+    let Some(query) = values.remove("query") else {
+      return Err(ParseError::MissingKey("query".to_string()));
+    };
+    let replace = values.remove("replacement").or(values.remove("replace"));
+    let replace_node = match (values.remove("replace_node"), &replace) {
+      (Some(replace_node), _) => Some(replace_node),
+      (None, Some(_)) => {
+        let tags = TAG_REGEX
+          .get_or_init(|| Regex::new(r"@(\w+)").unwrap())
+          .captures_iter(&query)
+          .map(|c| c[1].to_string())
+          .unique()
+          .collect_vec();
+        match tags.as_slice() {
+          [tag] => Some(tag.clone()),
+          _ => return Err(ParseError::AmbiguousReplaceNode(tags)),
+        }
+      }
+      (None, None) => None,
+    };
+    let name = values.remove("name").unwrap_or_else(|| {
+      let mut hasher = DefaultHasher::new();
+      (&query, &replace_node, &replace).hash(&mut hasher);
+      format!("comment_rule_{:x}", hasher.finish())
+    });
+    rule_builder.name(name).query(CGPattern::new(query));
+    if let Some(replace_node) = replace_node {
+      rule_builder.replace_node(replace_node);
+    }
+    if let Some(replace) = replace {
+      rule_builder.replace(replace);
+    }
+    if let Some(groups) = values.remove("groups") {
+      rule_builder.groups(groups.split(',').map(|g| g.trim().to_string()).collect());
+    }
+    if let Some(language) = values.remove("language") {
+      rule_builder.language(language);
+    }
+    if let Some(key) = values.keys().sorted().next() {
+      return Err(ParseError::UnknownKey(key.to_string()));
+    }
+
+    let rule = rule_builder
+      .build()
+      .map_err(|e| ParseError::InvalidRule(e.to_string()))?;
+    rule.validate().map_err(ParseError::InvalidRule)?;
+    Ok(rule)
+  }
+}
+
+/// Why the rule annotated in a code comment could not be parsed (see `Rule::from_comment`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+  /// The comment has no `@piranha-rule:` annotation
+  MissingAnnotation,
+  /// A required key (i.e. the `query`) is missing
+  MissingKey(String),
+  /// A key is given several times
+  DuplicateKey(String),
+  /// A key is not one of the keys of the annotation
+  UnknownKey(String),
+  /// The `replace_node` is omitted (along with a replacement), while the query does not have a single tag
+  AmbiguousReplaceNode(Vec<String>),
+  /// The annotated rule is invalid (e.g. its language is not supported)
+  InvalidRule(String),
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ParseError::MissingAnnotation => {
+        write!(f, "Missing the `{RULE_COMMENT_MARKER}` annotation")
+      }
+      ParseError::MissingKey(key) => write!(f, "Missing the `{key}` of the rule"),
+      ParseError::DuplicateKey(key) => write!(f, "Duplicate key `{key}`"),
+      ParseError::UnknownKey(key) => write!(f, "Unknown key `{key}`"),
+      ParseError::AmbiguousReplaceNode(tags) => write!(
+        f,
+        "Missing the `replace_node` of the rule (the query has {} tags)",
+        tags.len()
+      ),
+      ParseError::InvalidRule(message) => write!(f, "Invalid rule - {message}"),
+    }
   }
 }
 
+impl std::error::Error for ParseError {}
+
 #[macro_export]
 /// This macro can be used to construct a Rule (via the builder).'
 /// Allows to use builder pattern more "dynamically"
@@ -136,10 +734,23 @@ macro_rules! piranha_rule {
                 $(, replace_node = $replace_node:expr)?
                 $(, replace_idx = $replace_idx:expr)?
                 $(, replace = $replace:expr)?
+                $(, replace_with_capture = $replace_with_capture:expr)?
                 $(, holes = [$($hole: expr)*])?
                 $(, is_seed_rule = $is_seed_rule:expr)?
                 $(, groups = [$($group_name: expr)*])?
                 $(, filters = [$($filter:tt)*])?
+                $(, delete_empty_enclosing = $delete_empty_enclosing:expr)?
+                $(, package_filter = $package_filter:expr)?
+                $(, requires_features = [$($feature: expr)*])?
+                $(, language = $language:expr)?
+                $(, directory_scope = $directory_scope:expr)?
+                $(, query_file = $query_file:expr)?
+                $(, capture_all_groups = $capture_all_groups:expr)?
+                $(, query_cst = $query_cst:expr)?
+                $(, exclusive_group = $exclusive_group:expr)?
+                $(, dry = $dry:expr)?
+                $(, template_params = [$($template_param: expr)*])?
+                $(, prefilter = $prefilter:expr)?
               ) => {
     $crate::models::rule::RuleBuilder::default()
     .name($name.to_string())
@@ -147,9 +758,23 @@ macro_rules! piranha_rule {
     $(.replace_node($replace_node.to_string()))?
     $(.replace_idx($replace_idx.to_string()))?
     $(.replace($replace.to_string()))?
+    $(.replace_with_capture($replace_with_capture.to_string()))?
     $(.holes(std::collections::HashSet::from([$($hole.to_string(),)*])))?
+    $(.is_seed_rule($is_seed_rule))?
     $(.groups(std::collections::HashSet::from([$($group_name.to_string(),)*])))?
     $(.filters(std::collections::HashSet::from([$($filter)*])))?
+    $(.delete_empty_enclosing($delete_empty_enclosing))?
+    $(.package_filter(Some($package_filter)))?
+    $(.requires_features(vec![$($feature.to_string(),)*]))?
+    $(.language($language.to_string()))?
+    $(.directory_scope($directory_scope.to_string()))?
+    $(.query_file($query_file.to_string()))?
+    $(.capture_all_groups($capture_all_groups))?
+    $(.query_cst($query_cst.to_string()))?
+    $(.exclusive_group($exclusive_group.to_string()))?
+    $(.dry($dry))?
+    $(.template_params(vec![$($template_param.to_string(),)*]))?
+    $(.prefilter($prefilter))?
     .build().unwrap()
   };
 }
@@ -160,7 +785,13 @@ impl Rule {
   fn py_new(
     name: String, query: Option<String>, replace: Option<String>, replace_idx: Option<u8>,
     replace_node: Option<String>, holes: Option<HashSet<String>>, groups: Option<HashSet<String>>,
-    filters: Option<HashSet<Filter>>, is_seed_rule: Option<bool>,
+    filters: Option<HashSet<Filter>>, is_seed_rule: Option<bool>, scope: Option<String>,
+    exactly_one_match: Option<bool>, delete_empty_enclosing: Option<bool>,
+    package_filter: Option<PackageFilter>, requires_features: Option<Vec<String>>,
+    language: Option<String>, replace_with_capture: Option<String>,
+    directory_scope: Option<String>, query_file: Option<String>, capture_all_groups: Option<bool>,
+    query_cst: Option<String>, exclusive_group: Option<String>, dry: Option<bool>,
+    prefilter: Option<bool>,
   ) -> Self {
     let mut rule_builder = RuleBuilder::default();
 
@@ -197,6 +828,62 @@ impl Rule {
       rule_builder.is_seed_rule(is_seed_rule);
     }
 
+    if let Some(scope) = scope {
+      rule_builder.scope(scope);
+    }
+
+    if let Some(exactly_one_match) = exactly_one_match {
+      rule_builder.exactly_one_match(exactly_one_match);
+    }
+
+    if let Some(delete_empty_enclosing) = delete_empty_enclosing {
+      rule_builder.delete_empty_enclosing(delete_empty_enclosing);
+    }
+
+    if let Some(package_filter) = package_filter {
+      rule_builder.package_filter(Some(package_filter));
+    }
+
+    if let Some(requires_features) = requires_features {
+      rule_builder.requires_features(requires_features);
+    }
+
+    if let Some(language) = language {
+      rule_builder.language(language);
+    }
+
+    if let Some(replace_with_capture) = replace_with_capture {
+      rule_builder.replace_with_capture(replace_with_capture);
+    }
+
+    if let Some(directory_scope) = directory_scope {
+      rule_builder.directory_scope(directory_scope);
+    }
+
+    if let Some(query_file) = query_file {
+      rule_builder.query_file(query_file);
+    }
+
+    if let Some(capture_all_groups) = capture_all_groups {
+      rule_builder.capture_all_groups(capture_all_groups);
+    }
+
+    if let Some(query_cst) = query_cst {
+      rule_builder.query_cst(query_cst);
+    }
+
+    if let Some(exclusive_group) = exclusive_group {
+      rule_builder.exclusive_group(exclusive_group);
+    }
+
+    if let Some(dry) = dry {
+      rule_builder.dry(dry);
+    }
+
+    if let Some(prefilter) = prefilter {
+      rule_builder.prefilter(prefilter);
+    }
+
     rule_builder.build().unwrap()
   }
 
@@ -205,22 +892,193 @@ impl Rule {
 
 impl Validator for Rule {
   fn validate(&self) -> Result<(), String> {
+    if !self.scope().is_empty() && !self.is_multi_file_rule() {
+      return Err(format!(
+        "Invalid scope `{}` for the rule `{}`. Only `{MULTI_FILE}` is supported.",
+        self.scope(),
+        self.name()
+      ));
+    }
+    if *self.exactly_one_match() && !(self.is_multi_file_rule() && self.is_seed()) {
+      return Err(format!(
+        "`exactly_one_match` is only applicable to the `{MULTI_FILE}` seed rules (see rule `{}`).",
+        self.name()
+      ));
+    }
+    if !self.language().is_empty() && PiranhaLanguage::from_str(self.language()).is_err() {
+      return Err(format!(
+        "Unsupported language `{}` for the rule `{}`.",
+        self.language(),
+        self.name()
+      ));
+    }
+    if let Some(tag) = self
+      .query_tags()
+      .iter()
+      .chain(self.holes())
+      .find(|tag| tag.starts_with(RESERVED_TAG_PREFIX))
+    {
+      return Err(format!(
+        "The tag `{tag}` of the rule `{}` starts with `{RESERVED_TAG_PREFIX}`, which is reserved for the tags generated by Piranha.",
+        self.name()
+      ));
+    }
+    if let Err(e) = substitute_tags_regex(self.replace(), &HashMap::new()) {
+      return Err(format!(
+        "Invalid regex in the replacement of the rule `{}` - {}",
+        self.name(),
+        e
+      ));
+    }
+    if let Err(e) = Pattern::new(self.directory_scope()) {
+      return Err(format!(
+        "Invalid `directory_scope` glob `{}` for the rule `{}` - {e}",
+        self.directory_scope(),
+        self.name()
+      ));
+    }
+    // The references of the replacement that are neither captures nor holes are emitted as is (e.g. an annotation),
+    // which is a mistake when they are misspelled tags
+    let tags = self
+      .query_tags()
+      .into_iter()
+      .chain(self.holes().iter().cloned())
+      .collect_vec();
+    for reference in get_tag_references(self.replace()) {
+      if is_bound_by(&reference, |t| tags.iter().any(|tag| tag == t)) {
+        continue;
+      }
+      if let Some(tag) = tags
+        .iter()
+        .find(|tag| is_likely_misspelling(&reference, tag))
+      {
+        return Err(format!(
+          "The replacement of the rule `{}` references the unknown tag `@{reference}` (a misspelling of `@{tag}`?), which would be emitted as is.",
+          self.name()
+        ));
+      }
+    }
+    if let Some(tag) = self.replaced_capture() {
+      if !self.query_tags().contains(&tag.to_string()) {
+        return Err(format!(
+          "The tag `{}` (`replace_node`) is not captured by the query of the rule `{}`.",
+          tag,
+          self.name()
+        ));
+      }
+      if *self.replace_idx() != default_replace_idx() {
+        return Err(format!(
+          "The rule `{}` cannot have both a `replace_idx` and a `replace_node` capture (`@{}`).",
+          self.name(),
+          tag
+        ));
+      }
+    }
+    if !self.replace_with_capture().is_empty() {
+      if !self.replace().is_empty() {
+        return Err(format!(
+          "The rule `{}` cannot have both a `replace` pattern and a `replace_with_capture` tag.",
+          self.name()
+        ));
+      }
+      if self.is_match_only_rule() {
+        return Err(format!(
+          "The rule `{}` has a `replace_with_capture` tag, but no `replace_node` to replace.",
+          self.name()
+        ));
+      }
+      if !self.query_tags().contains(self.replace_with_capture()) {
+        return Err(format!(
+          "The tag `{}` (`replace_with_capture`) is not captured by the query of the rule `{}`.",
+          self.replace_with_capture(),
+          self.name()
+        ));
+      }
+    }
     let validation = self
       .query()
       .validate()
-      .and_then(|_: ()| self.filters().iter().try_for_each(|f| f.validate()));
+      .and_then(|_: ()| self.filters().iter().try_for_each(|f| f.validate()))
+      .and_then(|_: ()| self.package_filter().iter().try_for_each(|p| p.validate()));
     validation
   }
 }
 
+/// Hashes the fields of the rule, independently of the order of its sets (e.g. its `holes`).
+/// Serializes the `filters` in a deterministic order. Since `Filter` is not `Ord`, they are sorted by their debug
+/// representation (like `serialize_sorted`).
+fn serialize_filters<S: Serializer>(
+  filters: &HashSet<Filter>, serializer: S,
+) -> Result<S::Ok, S::Error> {
+  serializer.collect_seq(filters.iter().sorted_by_key(|f| format!("{f:?}")))
+}
+
+impl Hash for Rule {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.name.hash(state);
+    self.query().hash(state);
+    self.replace_node.hash(state);
+    self.replace_idx.hash(state);
+    self.replace.hash(state);
+    self.replace_with_capture.hash(state);
+    self.groups.iter().sorted().for_each(|g| g.hash(state));
+    self.holes.iter().sorted().for_each(|h| h.hash(state));
+    self
+      .filters
+      .iter()
+      .map(|f| {
+        let mut hasher = Sha256Hasher::default();
+        f.hash(&mut hasher);
+        hasher.digest()
+      })
+      .sorted()
+      .for_each(|f| f.hash(state));
+    self.is_seed_rule.hash(state);
+    self.scope.hash(state);
+    self.exactly_one_match.hash(state);
+    self.delete_empty_enclosing.hash(state);
+    self.package_filter.hash(state);
+    self.requires_features.hash(state);
+    self.language.hash(state);
+    self.directory_scope.hash(state);
+    self.query_file.hash(state);
+    self.capture_all_groups.hash(state);
+    self.query_cst.hash(state);
+    self.exclusive_group.hash(state);
+    self.dry.hash(state);
+    self.template_params.hash(state);
+    self.prefilter.hash(state);
+  }
+}
+
 pub use piranha_rule;
 
+/// The label of the variant of the rule `rule_name` instantiated with the `substitutions`, i.e. its name followed by
+/// the substitutions sorted by hole (e.g. `delete_flag(flag_name=STALE_FLAG, treated=true)`).
+pub(crate) fn variant_label(rule_name: &str, substitutions: &HashMap<String, String>) -> String {
+  format!(
+    "{rule_name}({})",
+    substitutions
+      .iter()
+      .sorted()
+      .map(|(hole, value)| format!("{hole}={value}"))
+      .join(", ")
+  )
+}
+
+/// A rule whose holes are filled with the substitutions
 #[derive(Debug, Getters, Clone)]
 pub(crate) struct InstantiatedRule {
   #[get = "pub"]
   rule: Rule,
   #[get = "pub"]
   substitutions: HashMap<String, String>,
+  #[get = "pub"]
+  triggered_by: Option<TriggeredBy>,
+  // The rule before its holes are filled (see `to_rule`)
+  template: Rule,
+  // The glob of the `directory_scope` of the rule, compiled once (see `applies_to`)
+  directory_scope: Option<Pattern>,
 }
 
 impl InstantiatedRule {
@@ -236,16 +1094,87 @@ impl InstantiatedRule {
       #[rustfmt::skip]
       panic!("{}", format!( "Could not instantiate the rule {rule:?} with substitutions {substitutions_for_holes:?}").red());
     }
+    let template = rule.clone();
+    let rule = rule.instantiate(&substitutions_for_holes);
+    let directory_scope = Some(rule.directory_scope())
+      .filter(|d| !d.is_empty())
+      .and_then(|d| Pattern::new(d).ok());
     InstantiatedRule {
-      rule: rule.instantiate(&substitutions_for_holes),
+      rule,
       substitutions: substitutions_for_holes,
+      triggered_by: None,
+      template,
+      directory_scope,
+    }
+  }
+
+  /// Instantiates the `rule` once per value of its holes with several values (e.g. `-s stale_flag_name=FLAG_A
+  /// -s stale_flag_name=FLAG_B`, see `PiranhaArguments::input_substitution_values`), or once per combination of
+  /// values (i.e. the cross product) when there are multiple such holes.
+  pub(crate) fn new_for_each_value(
+    rule: &Rule, substitutions: &HashMap<String, String>,
+    substitution_values: &HashMap<String, Vec<String>>,
+  ) -> Vec<Self> {
+    let list_holes = rule
+      .holes()
+      .iter()
+      .sorted()
+      .filter_map(|h| {
+        let values = substitution_values
+          .get(h)
+          .filter(|values| values.len() > 1)?;
+        Some(
+          values
+            .iter()
+            .map(|v| (h.to_string(), v.to_string()))
+            .collect_vec(),
+        )
+      })
+      .collect_vec();
+    if list_holes.is_empty() {
+      return vec![InstantiatedRule::new(rule, substitutions)];
     }
+    list_holes
+      .into_iter()
+      .multi_cartesian_product()
+      .map(|bindings| {
+        let mut substitutions = substitutions.clone();
+        substitutions.extend(bindings);
+        InstantiatedRule::new(rule, &substitutions)
+      })
+      .collect()
+  }
+
+  /// Returns the (uninstantiated) rule this rule was instantiated from, e.g. to show the rule template along
+  /// with its instantiation.
+  pub(crate) fn to_rule(&self) -> Rule {
+    self.template.clone()
+  }
+
+  /// Records the previously applied rule (and the scope of the edge) that lead to this rule
+  pub(crate) fn with_triggered_by(mut self, triggered_by: TriggeredBy) -> Self {
+    self.triggered_by = Some(triggered_by);
+    self
   }
 
   pub fn name(&self) -> String {
     self.rule().name().to_string()
   }
 
+  /// The name of the rule along with its substitutions (sorted by hole), which tell apart the variants of a rule,
+  /// e.g. a global rule seeded with different values in different files.
+  pub(crate) fn variant_key(&self) -> (String, Vec<(String, String)>) {
+    (
+      self.name(),
+      self
+        .substitutions()
+        .iter()
+        .map(|(hole, value)| (hole.to_string(), value.to_string()))
+        .sorted()
+        .collect(),
+    )
+  }
+
   pub fn replace(&self) -> String {
     self.rule().replace().to_string()
   }
@@ -256,7 +1185,13 @@ impl InstantiatedRule {
 
   pub fn replace_node(&self) -> Option<String> {
     if *self.rule().replace_node() != default_replace_node() {
-      return Some(self.rule().replace_node().to_string());
+      return Some(
+        self
+          .rule()
+          .replace_node()
+          .trim_start_matches('@')
+          .to_string(),
+      );
     }
     None
   }
@@ -272,6 +1207,16 @@ impl InstantiatedRule {
     self.rule().holes()
   }
 
+  /// Checks if the rule applies to the file at `relative_path` (relative to the code base), i.e. the path
+  /// matches its `directory_scope` (if any).
+  pub(crate) fn applies_to(&self, relative_path: &Path) -> bool {
+    self.rule().directory_scope().is_empty()
+      || self
+        .directory_scope
+        .as_ref()
+        .is_some_and(|p| p.matches_path(relative_path))
+  }
+
   pub fn filters(&self) -> &HashSet<Filter> {
     self.rule().filters()
   }
@@ -283,9 +1228,27 @@ impl Instantiate for Rule {
   /// i.e. It assumes that `substitutions_for_holes` is exhaustive and complete
   fn instantiate(&self, substitutions_for_holes: &HashMap<String, String>) -> Rule {
     let updated_rule = self.clone();
+    // The nodes are captured once the holes are substituted, so that a generated capture is never substituted
+    let query = updated_rule.query().instantiate(substitutions_for_holes);
     Rule {
-      query: updated_rule.query().instantiate(substitutions_for_holes),
+      query: if *updated_rule.capture_all_groups() {
+        CGPattern::new(capture_all_nodes(&query.pattern()))
+      } else {
+        query
+      },
+      query_file_contents: None,
       replace: updated_rule.replace().instantiate(substitutions_for_holes),
+      // The substituted values are matched literally by the glob
+      directory_scope: updated_rule.directory_scope().instantiate(
+        &substitutions_for_holes
+          .iter()
+          .map(|(tag, value)| (tag.to_string(), Pattern::escape(value)))
+          .collect(),
+      ),
+      package_filter: updated_rule
+        .package_filter()
+        .as_ref()
+        .map(|p| p.instantiate(substitutions_for_holes)),
       ..updated_rule
     }
   }
@@ -13,24 +13,37 @@ Copyright (c) 2023 Uber Technologies, Inc.
 
 use crate::{
   models::{outgoing_edges::OutgoingEdges, rule::Rule},
-  utilities::{gen_py_str_methods, read_toml, MapOfVec},
+  utilities::{gen_py_str_methods, try_read_toml, MapOfVec},
 };
 use colored::Colorize;
 use derive_builder::Builder;
 use getset::{Getters, MutGetters};
-use itertools::Itertools;
-use std::{collections::HashMap, path::Path};
+use itertools::{Either, Itertools};
+use log::info;
+use std::{
+  collections::{HashMap, HashSet},
+  fs,
+  path::{Path, PathBuf},
+};
+use tree_sitter_traversal::{traverse, Order};
 
 use super::{
   default_configs::{default_edges, default_rule_graph_map, default_rules},
+  language::PiranhaLanguage,
   outgoing_edges::Edges,
-  rule::{InstantiatedRule, Rules},
+  piranha_arguments::PiranhaArguments,
+  rule::{InstantiatedRule, QueryFiles, Rules, TemplateInstance, RULE_COMMENT_MARKER},
+  rule_store::{read_source_files, RuleStore},
+  stage::Stage,
   Validator,
 };
 use pyo3::prelude::{pyclass, pymethods};
 
 pub(crate) static GLOBAL: &str = "Global";
 pub(crate) static PARENT: &str = "Parent";
+pub(crate) static MULTI_FILE: &str = "MultiFile";
+// The group of the built-in cleanup rules used as seed rules in the `cleanup_only` mode
+pub(crate) static CLEANUP_ONLY_SEED: &str = "cleanup_only_seed";
 
 #[derive(Debug, Default, Getters, MutGetters, Builder, Clone, PartialEq)]
 #[builder(build_fn(name = "create"))]
@@ -53,14 +66,34 @@ pub struct RuleGraph {
   #[get = "pub(crate)"]
   #[pyo3(get)]
   graph: HashMap<String, Vec<(String, String)>>,
+
+  /// The stages of the migration (executed in order), if any
+  #[builder(default)]
+  #[get = "pub(crate)"]
+  stages: Vec<Stage>,
+
+  /// The instantiations of the rule templates (see `Rule::template_params`), expanded when the graph is built
+  #[builder(default)]
+  #[get = "pub(crate)"]
+  template_instances: Vec<TemplateInstance>,
 }
 
 impl Validator for RuleGraph {
   fn validate(&self) -> Result<(), String> {
     match self.rules().iter().try_for_each(|rule| rule.validate()) {
-      Ok(()) => Ok(()),
-      Err(e) => Err(format!("Incorrect Rule Graph - {}", e)),
+      Ok(()) => {}
+      Err(e) => return Err(format!("Incorrect Rule Graph - {}", e)),
+    }
+    if let Some(name) = self.stages().iter().map(|s| s.name()).duplicates().next() {
+      return Err(format!(
+        "Incorrect Rule Graph - The stage `{name}` is declared more than once."
+      ));
     }
+    self
+      .stages()
+      .iter()
+      .try_for_each(|stage| stage.validate(self))
+      .map_err(|e| format!("Incorrect Rule Graph - {}", e))
   }
 }
 
@@ -79,7 +112,14 @@ impl RuleGraph {
 impl RuleGraphBuilder {
   /// Build the rule graph.
   pub fn build(&self) -> RuleGraph {
-    let _rule_graph = self.create().unwrap();
+    self.try_build().unwrap_or_else(|e| panic!("{}", e.red()))
+  }
+
+  /// Build the rule graph, or returns an error if a template cannot be instantiated or the graph is invalid.
+  pub fn try_build(&self) -> Result<RuleGraph, String> {
+    let mut _rule_graph = self.create().unwrap();
+    // Expand the templates before adding the edges, so that the edges of a template apply to its instantiations
+    _rule_graph.rules = expand_templates(_rule_graph.rules(), _rule_graph.template_instances())?;
 
     let mut graph = HashMap::new();
 
@@ -87,16 +127,18 @@ impl RuleGraphBuilder {
       graph.insert(r.name().to_string(), vec![]);
     }
     // Add the edge(s) to the graph. Multiple edges will be added
-    // when either edge endpoint is a group name.
+    // when either edge endpoint is a group name, or when the edge has multiple scopes.
     for edge in _rule_graph.edges() {
       for from_rule in _rule_graph.get_rules_for_group(edge.get_frm()) {
         for outgoing_edge in edge.get_to() {
           for to_rule in _rule_graph.get_rules_for_group(outgoing_edge) {
-            // Add edge to the adjacency list
-            graph.collect(
-              from_rule.to_string(),
-              (edge.get_scope().to_string(), to_rule.to_string()),
-            );
+            // Add edge to the adjacency list (once per scope)
+            for scope in edge.get_scope() {
+              graph.collect(
+                from_rule.to_string(),
+                (scope.to_string(), to_rule.to_string()),
+              );
+            }
           }
         }
       }
@@ -106,14 +148,12 @@ impl RuleGraphBuilder {
       .edges(_rule_graph.edges().clone())
       .rules(_rule_graph.rules().clone())
       .graph(graph)
+      .stages(_rule_graph.stages().clone())
       .create()
       .unwrap();
 
-    if let Err(err) = graph.validate() {
-      panic!("{}", err.as_str().red());
-    }
-
-    graph
+    graph.validate()?;
+    Ok(graph)
   }
 }
 
@@ -137,6 +177,45 @@ impl RuleGraph {
     self.rules().iter().find(|x| x.name() == name)
   }
 
+  /// Returns the languages of the rules (see `Rule::language`) other than the language of the run.
+  pub(crate) fn rule_languages(&self, run_language: &str) -> Vec<PiranhaLanguage> {
+    self
+      .rules()
+      .iter()
+      .map(|r| r.language_or(run_language))
+      .filter(|l| *l != run_language)
+      .unique()
+      .map(PiranhaLanguage::from)
+      .collect_vec()
+  }
+
+  /// Checks that the rules of different languages (see `Rule::language`) are only connected by `Global` edges,
+  /// since the rules of the other scopes are applied to the same file.
+  pub(crate) fn validate_languages(&self, run_language: &str) -> Result<(), String> {
+    for (from, scope, to) in self.get_labelled_edges() {
+      let (Some(from_rule), Some(to_rule)) = (self.get_rule_named(from), self.get_rule_named(to))
+      else {
+        continue;
+      };
+      if scope == GLOBAL || from_rule.is_dummy_rule() || to_rule.is_dummy_rule() {
+        continue;
+      }
+      let from_language = from_rule.language_or(run_language);
+      let to_language = to_rule.language_or(run_language);
+      if from_language != to_language {
+        return Err(format!(
+          "Incorrect Rule Graph - The rules `{from}` ({from_language}) and `{to}` ({to_language}) have different languages, hence they can only be connected by a `{GLOBAL}` edge (found `{scope}`)."
+        ));
+      }
+    }
+    Ok(())
+  }
+
+  /// Returns the stage named `name` (if found)
+  pub(crate) fn get_stage_named(&self, name: &str) -> Option<&Stage> {
+    self.stages().iter().find(|s| s.name() == name)
+  }
+
   // Returns rule(s) with name or group as given `group`
   pub(crate) fn get_rules_for_group(&self, group: &String) -> Vec<&String> {
     if let Some(r) = self.get_rule_named(group) {
@@ -150,13 +229,15 @@ impl RuleGraph {
       .collect_vec()
   }
 
-  pub(crate) fn merge(&self, rule_graph: &RuleGraph) -> Self {
+  pub(crate) fn merge(&self, rule_graph: &RuleGraph) -> Result<Self, String> {
     let all_rules = [rule_graph.rules().clone(), self.rules().clone()].concat();
     let all_edges = [rule_graph.edges().clone(), self.edges().clone()].concat();
+    let all_stages = [self.stages().clone(), rule_graph.stages().clone()].concat();
     RuleGraphBuilder::default()
       .rules(all_rules)
       .edges(all_edges)
-      .build()
+      .stages(all_stages)
+      .try_build()
   }
 
   /// Get the next rules to be applied grouped by the scope in which they should be performed.
@@ -195,17 +276,328 @@ impl RuleGraph {
   }
 }
 
-pub(crate) fn read_user_config_files(path_to_configurations: &String) -> RuleGraph {
+// Implements the methods to export the rule graph in DOT and Mermaid formats
+impl RuleGraph {
+  /// Writes the part of the rule graph that is executed when the `seed_rules` are seeded (see `get_executed_rules`)
+  /// to the file `path`. The graph is rendered in the Mermaid format if the file has the extension `mmd`, else in
+  /// the DOT format. Returns an error if the file cannot be written.
+  pub(crate) fn dump(&self, path: &str, seed_rules: &HashSet<String>) -> Result<(), String> {
+    let content = if path.ends_with(".mmd") {
+      self.to_mermaid(seed_rules)
+    } else {
+      self.to_dot(seed_rules)
+    };
+    fs::write(path, content)
+      .map_err(|e| format!("Could not write the rule graph to the file - {path} \n {e}"))
+  }
+
+  /// The rules that may be applied when the `seed_rules` are seeded, i.e. the seed rules (other than the dummy rules,
+  /// which are never applied as seed rules) and the rules reachable from them (after expanding the groups).
+  fn get_executed_rules(&self, seed_rules: &HashSet<String>) -> HashSet<&String> {
+    let mut executed_rules: HashSet<&String> = self
+      .rules()
+      .iter()
+      .filter(|r| seed_rules.contains(r.name()) && !r.is_dummy_rule())
+      .map(|r| r.name())
+      .collect();
+    let mut stack = executed_rules.iter().copied().collect_vec();
+    while let Some(rule_name) = stack.pop() {
+      for (_, to_rule) in self.graph().get(rule_name).into_iter().flatten() {
+        if executed_rules.insert(to_rule) {
+          stack.push(to_rule);
+        }
+      }
+    }
+    executed_rules
+  }
+
+  /// Renders the rules executed when the `seed_rules` are seeded (see `get_executed_rules`) in the DOT format.
+  /// * Seed rules are filled, match-only rules are boxes, rewrite rules are ellipses and dummy rules are diamonds.
+  /// * Edges are labelled with their scope.
+  /// * Rules belonging to a group are rendered within the cluster of that group.
+  /// * Rules whose query was read from a `query_file` are labelled with the path of the file.
+  pub(crate) fn to_dot(&self, seed_rules: &HashSet<String>) -> String {
+    let quote = |s: &str| format!("\"{}\"", s.replace('"', "\\\""));
+    let is_seed = |rule: &Rule| seed_rules.contains(rule.name()) && !rule.is_dummy_rule();
+    let mut lines = vec!["digraph RuleGraph {".to_string()];
+    for (group, rules) in self.get_rules_by_group(seed_rules) {
+      match group {
+        Some(group) => {
+          lines.push(format!(
+            "  subgraph {} {{",
+            quote(&format!("cluster_{group}"))
+          ));
+          lines.push(format!("    label = {};", quote(&group)));
+          for rule in rules {
+            lines.push(format!(
+              "    {} [{}];",
+              quote(rule.name()),
+              rule.dot_style(is_seed(rule))
+            ));
+          }
+          lines.push("  }".to_string());
+        }
+        None => {
+          for rule in rules {
+            lines.push(format!(
+              "  {} [{}];",
+              quote(rule.name()),
+              rule.dot_style(is_seed(rule))
+            ));
+          }
+        }
+      }
+    }
+    for (from, scope, to) in self.get_labelled_edges(seed_rules) {
+      let (from, to, scope) = (quote(from), quote(to), quote(scope));
+      lines.push(format!("  {from} -> {to} [label = {scope}];"));
+    }
+    lines.push("}".to_string());
+    lines.join("\n")
+  }
+
+  /// Renders the rules executed when the `seed_rules` are seeded (see `get_executed_rules`) in the Mermaid
+  /// (flowchart) format.
+  /// * Seed rules have the class `seed`, match-only rules are rectangles, rewrite rules are stadiums and dummy rules are rhombuses.
+  /// * Edges are labelled with their scope.
+  /// * Rules belonging to a group are rendered within the subgraph of that group.
+  /// * Rules whose query was read from a `query_file` are labelled with the path of the file.
+  pub(crate) fn to_mermaid(&self, seed_rules: &HashSet<String>) -> String {
+    let quote = |s: &str| format!("\"{}\"", s.replace('"', "#quot;"));
+    // The rules are identified by their index in the rule graph
+    let ids: HashMap<&String, String> = self
+      .rules()
+      .iter()
+      .enumerate()
+      .map(|(i, r)| (r.name(), format!("r{i}")))
+      .collect();
+    let node = |id: &str, rule: &Rule| {
+      let (open, close) = rule.mermaid_shape();
+      let label = if rule.query_file().is_empty() {
+        rule.name().to_string()
+      } else {
+        format!("{}<br/>{}", rule.name(), rule.query_file())
+      };
+      format!("{id}{open}{}{close}", quote(&label))
+    };
+    let mut lines = vec![
+      "flowchart TD".to_string(),
+      "  classDef seed fill:#add8e6".to_string(),
+    ];
+    for (idx, (group, rules)) in self.get_rules_by_group(seed_rules).into_iter().enumerate() {
+      let indent = if group.is_some() { "    " } else { "  " };
+      let nodes = rules
+        .iter()
+        .filter_map(|r| Some(format!("{indent}{}", node(ids.get(r.name())?, r))));
+      match group {
+        Some(group) => {
+          lines.push(format!("  subgraph g{idx} [{}]", quote(&group)));
+          lines.extend(nodes);
+          lines.push("  end".to_string());
+        }
+        None => lines.extend(nodes),
+      }
+    }
+    for (from, scope, to) in self.get_labelled_edges(seed_rules) {
+      if let (Some(from), Some(to)) = (ids.get(from), ids.get(to)) {
+        lines.push(format!("  {from} -->|{}| {to}", quote(scope)));
+      }
+    }
+    for (i, _) in self
+      .rules()
+      .iter()
+      .enumerate()
+      .filter(|(_, r)| seed_rules.contains(r.name()) && !r.is_dummy_rule())
+    {
+      lines.push(format!("  class r{i} seed"));
+    }
+    lines.join("\n")
+  }
+
+  /// Groups the rules executed when the `seed_rules` are seeded by their (first) group. Rules that do not belong to
+  /// any group are keyed by `None`.
+  fn get_rules_by_group(&self, seed_rules: &HashSet<String>) -> Vec<(Option<String>, Vec<&Rule>)> {
+    let executed_rules = self.get_executed_rules(seed_rules);
+    self
+      .rules()
+      .iter()
+      .filter(|r| executed_rules.contains(r.name()))
+      .into_group_map_by(|r| r.groups().iter().min().cloned())
+      .into_iter()
+      .sorted_by(|a, b| a.0.cmp(&b.0))
+      .collect_vec()
+  }
+
+  /// Returns the edges (after expanding the groups) between the rules executed when the `seed_rules` are seeded,
+  /// as (from, scope, to) triples.
+  fn get_labelled_edges(&self, seed_rules: &HashSet<String>) -> Vec<(&String, &String, &String)> {
+    let executed_rules = self.get_executed_rules(seed_rules);
+    self
+      .rules()
+      .iter()
+      .filter(|r| executed_rules.contains(r.name()))
+      .flat_map(|r| {
+        self
+          .graph()
+          .get(r.name())
+          .into_iter()
+          .flatten()
+          .map(move |(scope, to)| (r.name(), scope, to))
+      })
+      .collect_vec()
+  }
+}
+
+/// Replaces the rule templates (see `Rule::template_params`) of the `rules` with their instantiations (see
+/// `TemplateInstance`). Returns an error if a template is unknown, if the parameters of an instantiation do not
+/// match those of its template, or if the name of an instantiated rule is already taken.
+fn expand_templates(
+  rules: &[Rule], template_instances: &[TemplateInstance],
+) -> Result<Vec<Rule>, String> {
+  let (templates, mut expanded_rules): (Vec<&Rule>, Vec<Rule>) = rules.iter().partition_map(|r| {
+    if r.is_template() {
+      Either::Left(r)
+    } else {
+      Either::Right(r.clone())
+    }
+  });
+  let mut instantiated = HashSet::new();
+  for instance in template_instances {
+    let Some(template) = templates.iter().find(|t| t.name() == instance.template()) else {
+      return Err(format!(
+        "Incorrect Rule Graph - The template `{}` is not declared (i.e. no rule with this name has `template_params`).",
+        instance.template()
+      ));
+    };
+    let rule = template
+      .instantiate_template(instance)
+      .map_err(|e| format!("Incorrect Rule Graph - {e}"))?;
+    if instantiated.contains(rule.name()) {
+      return Err(format!(
+        "Incorrect Rule Graph - The rule `{}` is instantiated more than once (see the template `{}`).",
+        rule.name(),
+        template.name()
+      ));
+    }
+    if expanded_rules.iter().any(|r| r.name() == rule.name()) {
+      return Err(format!(
+        "Incorrect Rule Graph - The instantiation `{}` of the template `{}` has the same name as another rule.",
+        rule.name(),
+        template.name()
+      ));
+    }
+    instantiated.insert(rule.name().to_string());
+    expanded_rules.push(rule);
+  }
+  Ok(expanded_rules)
+}
+
+/// Reads the rules and edges provided by the user in the configurations directory, i.e. `rules.toml`, `edges.toml`
+/// and the rule files of the `rules` directory (if any). The rule templates are instantiated before the graph is built.
+/// Returns an error if a configuration file is invalid.
+pub(crate) fn read_user_config_files(
+  path_to_configurations: &String, language: &PiranhaLanguage,
+) -> Result<RuleGraph, String> {
   let path_to_config = Path::new(path_to_configurations);
   // Read the rules and edges provided by the user
-  let input_rules: Rules = read_toml(&path_to_config.join("rules.toml"), true);
-  let input_edges: Edges = read_toml(&path_to_config.join("edges.toml"), true);
+  let mut input_rules: Rules = try_read_toml(&path_to_config.join("rules.toml"), true)?;
+  load_query_files(&mut input_rules.rules, &[path_to_config.to_path_buf()])?;
+  compile_cst_queries(&mut input_rules.rules, language)?;
+  let input_edges: Edges = try_read_toml(&path_to_config.join("edges.toml"), true)?;
+  // The rules can also be split across the rule files of the `rules` directory
+  let rules_directory = path_to_config.join("rules");
+  if rules_directory.is_dir() {
+    for rule in RuleStore::from_directory_iter(&rules_directory, language) {
+      input_rules.rules.push(rule?);
+    }
+    input_rules
+      .template_instances
+      .extend(RuleStore::template_instances_from_directory(
+        &rules_directory,
+      )?);
+  }
   RuleGraphBuilder::default()
     .rules(input_rules.rules)
     .edges(input_edges.edges)
-    .build()
+    .stages(input_rules.stages)
+    .template_instances(input_rules.template_instances)
+    .try_build()
+}
+
+/// Loads the queries of the `rules` with a `query_file` (see `Rule::load_query_file`), looked up in the `directories`.
+/// Returns an error if a query file cannot be loaded.
+pub(crate) fn load_query_files(rules: &mut [Rule], directories: &[PathBuf]) -> Result<(), String> {
+  let mut query_files = QueryFiles::new();
+  for rule in rules {
+    rule.load_query_file(directories, &mut query_files)?;
+  }
+  Ok(())
+}
+
+/// Compiles the concrete syntax of the `rules` with a `query_cst` (see `Rule::compile_query_cst`), with the grammar
+/// of their language (the `language`, unless the rule targets another one). Returns an error if a concrete syntax
+/// cannot be compiled.
+pub(crate) fn compile_cst_queries(
+  rules: &mut [Rule], language: &PiranhaLanguage,
+) -> Result<(), String> {
+  rules
+    .iter_mut()
+    .try_for_each(|rule| rule.compile_query_cst(language))
+}
+
+/// Reads the rules annotated in the comments (see `Rule::from_comment`) of the code base (or the code snippet).
+/// The files skipped by the run (e.g. larger than `max_file_size_bytes`, see `PiranhaArguments::get_skip_reason`)
+/// are ignored. Returns an error if an annotation is invalid, or if different rules have the same name.
+pub(crate) fn read_comment_rules(args: &PiranhaArguments) -> Result<RuleGraph, String> {
+  let language = args.language();
+  let sources = if !args.code_snippet().is_empty() {
+    HashMap::from([(
+      PathBuf::from("code_snippet"),
+      args.code_snippet().to_string(),
+    )])
+  } else {
+    read_source_files(args.codebase(), |path| language.can_parse_path(path))?
+  };
+
+  let mut parser = language.parser();
+  let mut rules: Vec<Rule> = vec![];
+  for (path, content) in sources
+    .iter()
+    .filter(|(_, content)| content.contains(RULE_COMMENT_MARKER))
+    .filter(|(_, content)| args.get_skip_reason(content).is_none())
+    .sorted_by(|(a, _), (b, _)| a.cmp(b))
+  {
+    let tree = parser.parse(content, None).unwrap();
+    for comment in traverse(tree.root_node().walk(), Order::Pre)
+      .filter(|n| language.comment_nodes().iter().any(|kind| kind == n.kind()))
+    {
+      let text = &content[comment.byte_range()];
+      if !text.contains(RULE_COMMENT_MARKER) {
+        continue;
+      }
+      let rule = Rule::from_comment(text)
+        .map_err(|e| format!("Invalid rule in {path:?} - {e} (in the comment `{text}`)"))?;
+      match rules.iter().find(|r| r.name() == rule.name()) {
+        // The same rule annotated in several places
+        Some(r) if *r == rule => {}
+        Some(_) => {
+          return Err(format!(
+            "The rule `{}` is annotated with different definitions (see {path:?})",
+            rule.name()
+          ))
+        }
+        None => rules.push(rule),
+      }
+    }
+  }
+  info!("Number of rules read from the comments : {}", rules.len());
+  RuleGraphBuilder::default().rules(rules).try_build()
 }
 
 #[cfg(test)]
 #[path = "unit_tests/rule_graph_validation_test.rs"]
 mod rule_graph_validation_test;
+
+#[cfg(test)]
+#[path = "unit_tests/rule_graph_test.rs"]
+mod rule_graph_test;
@@ -12,96 +12,646 @@ Copyright (c) 2023 Uber Technologies, Inc.
 */
 
 use std::{
-  collections::HashMap,
+  collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, HashMap, HashSet},
+  fmt, fs,
+  hash::{Hash, Hasher},
+  iter,
+  num::NonZeroUsize,
   path::{Path, PathBuf},
 };
 
 use colored::Colorize;
-use getset::Getters;
+use getset::{CopyGetters, Getters};
 use itertools::Itertools;
-use jwalk::WalkDir;
-use log::{debug, trace};
+use log::{debug, trace, warn};
+use lru::LruCache;
 use regex::Regex;
+use serde_derive::Serialize;
 use tree_sitter::Query;
 
 use crate::{
-  models::capture_group_patterns::CGPattern, models::piranha_arguments::PiranhaArguments,
-  models::scopes::ScopeQueryGenerator, utilities::read_file,
+  models::capture_group_patterns::CGPattern,
+  models::default_configs::default_scope_query_cache_size,
+  models::piranha_arguments::{patterns_of, PiranhaArguments},
+  models::rule_graph::CLEANUP_ONLY_SEED,
+  models::scopes::{ScopeGenerator, ScopeQueryGenerator, ScopeStrategy},
+  models::Validator,
+  utilities::{
+    codebase::Codebase, get_placeholder_tags, get_tag_references, is_bound_by, read_file,
+  },
 };
 
-use super::{language::PiranhaLanguage, rule::InstantiatedRule};
-use glob::Pattern;
+use super::{
+  language::PiranhaLanguage,
+  metrics::UnappliedSeedRule,
+  outgoing_edges::OutgoingEdges,
+  package_filter::PACKAGE_TAG,
+  rule::{InstantiatedRule, QueryFiles, Rule, Rules, TemplateInstance},
+  rule_graph::RuleGraph,
+  skipped_match::{SkipReason, SkippedMatch},
+};
 
 /// This maintains the state for Piranha.
 #[derive(Debug, Getters, Default)]
-pub(crate) struct RuleStore {
-  // Caches the compiled tree-sitter queries.
-  rule_query_cache: HashMap<String, Query>,
+pub struct RuleStore {
+  // Caches the compiled tree-sitter queries (by the extension of their language and their pattern, see `QueryCache`).
+  #[get = "pub(crate)"]
+  rule_query_cache: QueryCache,
   // Current global rules to be applied.
-  #[get = "pub"]
+  #[get = "pub(crate)"]
   global_rules: Vec<InstantiatedRule>,
 
   #[get = "pub"]
   language: PiranhaLanguage,
+  // The languages of the rules (see `Rule::language`) other than the language of the run.
+  #[get = "pub"]
+  rule_languages: Vec<PiranhaLanguage>,
+  // The candidate matches that were intentionally skipped, for each file.
+  skipped_matches: HashMap<PathBuf, SkippedMatches>,
+  // Whether the skipped matches themselves are kept (else only their counts), see `PiranhaArguments::explain`.
+  explain: bool,
+  // The features detected in the code base (i.e. the names of the matched feature detection rules).
+  #[get = "pub"]
+  detected_features: HashSet<String>,
+  // The tags known in the rule graph (i.e. the captures and holes of the rules, and the input substitutions).
+  known_tags: HashSet<String>,
+  // The user defined rules and edges.
+  rule_set: RuleSet,
+  // The names of the seed rules (see `get_unapplied_seed_rules`).
+  seed_rules: BTreeSet<String>,
+  // The seed rules that were not seeded (in the `cleanup_only` mode), along with their holes that are not substituted.
+  unseeded_rules: BTreeMap<String, Vec<String>>,
+}
+
+/// The candidate matches skipped in a file. A candidate match is usually found (and skipped) again each time its
+/// rule is re-matched, hence they are deduplicated: by value when they are kept (i.e. `explain` is enabled),
+/// else by their hash.
+#[derive(Debug, Default)]
+struct SkippedMatches {
+  matches: HashSet<SkippedMatch>,
+  hashes: HashSet<u64>,
+  // The number of (distinct) skipped matches for each rule and reason
+  counts: BTreeMap<(String, SkipReason), usize>,
+}
+
+/// The least recently used compiled queries of each language (up to `scope_query_cache_size` of them per language),
+/// along with the number of lookups that found the query (hits) or compiled it (misses).
+#[derive(Debug, CopyGetters)]
+pub(crate) struct QueryCache {
+  // The queries of each language (by extension), looked up by their pattern
+  queries: Vec<(String, LruCache<String, Query>)>,
+  capacity: NonZeroUsize,
+  #[get_copy = "pub"]
+  hits: usize,
+  #[get_copy = "pub"]
+  misses: usize,
+}
+
+impl QueryCache {
+  pub(crate) fn new(capacity: usize) -> Self {
+    QueryCache {
+      queries: vec![],
+      capacity: NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN),
+      hits: 0,
+      misses: 0,
+    }
+  }
+
+  /// Get the compiled `query` of the `language` from the cache, else compile it and add it to the cache (evicting the
+  /// least recently used query of the language if it is full). The query is looked up once, without allocating.
+  pub(crate) fn get_or_compile(&mut self, query: &CGPattern, language: &PiranhaLanguage) -> &Query {
+    let index = match self
+      .queries
+      .iter()
+      .position(|(extension, _)| extension == language.extension())
+    {
+      Some(index) => index,
+      None => {
+        let queries = LruCache::new(self.capacity);
+        self
+          .queries
+          .push((language.extension().to_string(), queries));
+        self.queries.len() - 1
+      }
+    };
+    let mut is_compiled = false;
+    let compiled_query = self.queries[index]
+      .1
+      .get_or_insert_ref(query.0.as_str(), || {
+        is_compiled = true;
+        language.create_query(query.pattern())
+      });
+    if is_compiled {
+      self.misses += 1;
+    } else {
+      self.hits += 1;
+    }
+    compiled_query
+  }
+
+  /// Grows the cache (of each language) to hold at least `capacity` queries.
+  pub(crate) fn reserve(&mut self, capacity: usize) {
+    let Some(capacity) = NonZeroUsize::new(capacity).filter(|c| *c > self.capacity) else {
+      return;
+    };
+    self.capacity = capacity;
+    for (_, queries) in &mut self.queries {
+      queries.resize(capacity);
+    }
+  }
+
+  /// The number of cached queries (across the languages)
+  pub(crate) fn number_of_queries(&self) -> usize {
+    self.queries.iter().map(|(_, queries)| queries.len()).sum()
+  }
+
+  /// The ratio of the lookups that found the query in the cache.
+  pub(crate) fn hit_rate(&self) -> f64 {
+    match self.hits + self.misses {
+      0 => 0.0,
+      lookups => self.hits as f64 / lookups as f64,
+    }
+  }
+}
+
+impl Default for QueryCache {
+  fn default() -> Self {
+    QueryCache::new(default_scope_query_cache_size())
+  }
+}
+
+/// A likely mistake in the (user defined) rules, which is reported as a warning rather than rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleGraphWarning {
+  /// The rule has a replacement, but no captures (hence the replacement cannot reference the matched code)
+  NoCaptures { rule_name: String },
+}
+
+impl fmt::Display for RuleGraphWarning {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      RuleGraphWarning::NoCaptures { rule_name } => write!(
+        f,
+        "The rule `{rule_name}` has a replacement, but its query has no captures. The replacement cannot reference the matched code."
+      ),
+    }
+  }
+}
+
+/// The rules and edges of a Piranha configuration (i.e. the content of `rules.toml` and `edges.toml`).
+#[derive(Debug, Default, Serialize)]
+struct RuleSet {
+  rules: Vec<Rule>,
+  edges: Vec<OutgoingEdges>,
 }
 
 impl RuleStore {
-  pub(crate) fn new(args: &PiranhaArguments) -> RuleStore {
+  pub fn new(args: &PiranhaArguments) -> RuleStore {
     let mut rule_store = RuleStore {
+      rule_query_cache: QueryCache::new(*args.scope_query_cache_size()),
+      explain: *args.explain(),
       language: args.language().clone(),
+      rule_languages: args
+        .rule_graph()
+        .rule_languages(args.language().extension()),
       ..Default::default()
     };
 
+    let feature_detection_rules: HashSet<&String> = args
+      .rule_graph()
+      .rules()
+      .iter()
+      .filter(|r| r.is_feature_detection_rule())
+      .map(|r| r.name())
+      .collect();
+    // The seed rules of the stage being executed (if any)
+    let stage_rules: Option<HashSet<&String>> = args.stage().as_ref().map(|name| {
+      let stage = args
+        .rule_graph()
+        .get_stage_named(name)
+        .unwrap_or_else(|| panic!("{}", format!("The stage `{name}` is not declared").red()));
+      stage
+        .rules()
+        .iter()
+        .flat_map(|r| args.rule_graph().get_rules_for_group(r))
+        .collect()
+    });
+    let input_substitutions = args.input_substitutions();
+    let input_substitution_values = args.input_substitution_values();
     for rule in args.rule_graph().rules().clone() {
-      if *rule.is_seed_rule() {
-        rule_store.add_to_global_rules(&InstantiatedRule::new(&rule, &args.input_substitutions()));
+      for feature in rule.requires_features() {
+        if !feature_detection_rules.contains(feature) {
+          warn!(
+            "The rule `{}` requires the feature `{}`, but there is no `MultiFile` match-only seed rule detecting it. It will not be applied.",
+            rule.name(),
+            feature
+          );
+        }
+      }
+      let rule_language = rule_store.language_of(&rule);
+      if rule.package_filter().is_some() && rule_language.package_query().is_none() {
+        warn!(
+          "The rule `{}` has a package filter, but {} files do not declare packages. It will not be applied.",
+          rule.name(),
+          rule_language.extension()
+        );
+      }
+      // In the `cleanup_only` mode, the (built-in) cleanup rules are the only seed rules, while those with holes
+      // (e.g. deleting a field by name) are only seeded when their holes are substituted.
+      // In a stage, only its own seed rules are.
+      let is_seed_rule = if *args.cleanup_only() {
+        rule.groups().contains(CLEANUP_ONLY_SEED)
+      } else if let Some(stage_rules) = &stage_rules {
+        *rule.is_seed_rule() && stage_rules.contains(rule.name())
+      } else {
+        *rule.is_seed_rule()
+      };
+      if !is_seed_rule {
+        continue;
+      }
+      rule_store.seed_rules.insert(rule.name().to_string());
+      // In the `cleanup_only` mode, the seed rules whose holes are not all substituted are not seeded
+      let unsatisfied_holes = rule
+        .holes()
+        .iter()
+        .filter(|h| !input_substitutions.contains_key(*h))
+        .sorted()
+        .cloned()
+        .collect_vec();
+      if *args.cleanup_only() && !unsatisfied_holes.is_empty() {
+        rule_store
+          .unseeded_rules
+          .insert(rule.name().to_string(), unsatisfied_holes);
+        continue;
+      }
+      for instantiated_rule in InstantiatedRule::new_for_each_value(
+        &rule,
+        &input_substitutions,
+        &input_substitution_values,
+      ) {
+        rule_store.add_to_global_rules(&instantiated_rule);
       }
     }
+    // The queries of the seed rules are looked up for each file, hence the cache keeps all of them (the instances of a
+    // rule with several values of a hole are as many queries), since looking up more queries than the cache holds in
+    // the same order each time would never hit
+    let number_of_seed_queries = rule_store
+      .global_rules
+      .iter()
+      .flat_map(|r| patterns_of(r.rule()))
+      .filter(|p| !p.pattern().is_empty())
+      .unique()
+      .count();
+    rule_store.rule_query_cache.reserve(number_of_seed_queries);
+    warn_about_tags(args);
+    rule_store.known_tags = get_known_tags(args);
+    rule_store.rule_set = get_user_defined_rule_set(args);
+    for warning in rule_store.validate() {
+      warn!("{warning}");
+    }
     trace!("Rule Store {}", format!("{rule_store:#?}"));
     rule_store
   }
 
-  /// Add a new global rule, along with grep heuristics (If it doesn't already exist)
+  /// Checks the user defined rules for likely mistakes (see `RuleGraphWarning`).
+  pub fn validate(&self) -> Vec<RuleGraphWarning> {
+    self
+      .rule_set
+      .rules
+      .iter()
+      .filter(|rule| !rule.replace().is_empty() && rule.capture_count() == 0)
+      .map(|rule| RuleGraphWarning::NoCaptures {
+        rule_name: rule.name().to_string(),
+      })
+      .collect()
+  }
+
+  /// Lazily reads the rules of the rule files (i.e. the `*.toml` files, in the `rules.toml` format) of the directory `path`,
+  /// in the order of their names. The directory is only listed once the first rule is requested, and each file is only
+  /// read once the rules of the previous files were consumed, hence the large rule repositories can be processed rule by rule.
+  /// Each rule is validated (and its concrete syntax, if any, is compiled to its query for its language, i.e. the `language`
+  /// unless the rule targets another one). As for the rules of `rules.toml`, the queries themselves are only compiled
+  /// when the rules are applied.
+  pub fn from_directory_iter(
+    path: &Path, language: &PiranhaLanguage,
+  ) -> impl Iterator<Item = Result<Rule, String>> {
+    let language = language.clone();
+    // The query files are looked up in the rules directory, then in the configuration directory
+    let directories = [Some(path), path.parent()]
+      .into_iter()
+      .flatten()
+      .map(Path::to_path_buf)
+      .collect_vec();
+    let mut query_files = QueryFiles::new();
+    iter::once(path.to_path_buf())
+      .flat_map(|path| list_rule_files(&path))
+      .flat_map(
+        |rule_file| match rule_file.and_then(|f| read_rule_file(&f)) {
+          Ok(rules) => rules.rules.into_iter().map(Ok).collect_vec(),
+          Err(e) => vec![Err(e)],
+        },
+      )
+      .map(move |rule| {
+        rule.and_then(|mut rule| {
+          rule.load_query_file(&directories, &mut query_files)?;
+          check_rule(rule, &language)
+        })
+      })
+  }
+
+  /// Reads the instantiations of the rule templates (see `TemplateInstance`) declared in the rule files of the directory `path`.
+  pub(crate) fn template_instances_from_directory(
+    path: &Path,
+  ) -> Result<Vec<TemplateInstance>, String> {
+    let mut template_instances = vec![];
+    for rule_file in list_rule_files(path) {
+      template_instances.extend(read_rule_file(&rule_file?)?.template_instances);
+    }
+    Ok(template_instances)
+  }
+
+  /// Serializes the rule set (i.e. the user defined rules, with their filters, and edges) to TOML.
+  /// The output is a valid Piranha configuration (both as `rules.toml` and `edges.toml`), which allows
+  /// persisting a rule set built via the API, to replay it in the subsequent runs.
+  pub fn export_toml(&self) -> Result<String, toml::ser::Error> {
+    toml::to_string(&self.rule_set)
+  }
+
+  /// Records a candidate match (in the file `path`) that was intentionally skipped.
+  /// All the mechanisms that skip candidate matches should report them through this method.
+  /// The skipped match is only kept if `explain` is enabled, else it is only counted.
+  pub(crate) fn report_skipped_match(&mut self, path: &Path, skipped_match: SkippedMatch) {
+    trace!("Skipped match in {:?} : {:?}", path, skipped_match);
+    let skipped_matches = self.skipped_matches.entry(path.to_path_buf()).or_default();
+    let key = (
+      skipped_match.rule_name().to_string(),
+      skipped_match.reason(),
+    );
+    let is_new = if self.explain {
+      skipped_matches.matches.insert(skipped_match)
+    } else {
+      let mut hasher = DefaultHasher::new();
+      skipped_match.hash(&mut hasher);
+      skipped_matches.hashes.insert(hasher.finish())
+    };
+    if is_new {
+      *skipped_matches.counts.entry(key).or_insert(0) += 1;
+    }
+  }
+
+  /// Returns the skipped candidate matches in the file `path` (sorted by rule name, reason and range).
+  /// They are only kept if `explain` is enabled (see `get_file_skip_counts` otherwise).
+  pub(crate) fn get_skipped_matches(&self, path: &Path) -> Vec<SkippedMatch> {
+    self
+      .skipped_matches
+      .get(path)
+      .map(|s| s.matches.iter().cloned().sorted().collect_vec())
+      .unwrap_or_default()
+  }
+
+  /// Returns the number of skipped candidate matches in the file `path` for each rule and reason.
+  pub(crate) fn get_file_skip_counts(&self, path: &Path) -> BTreeMap<(String, SkipReason), usize> {
+    self
+      .skipped_matches
+      .get(path)
+      .map(|s| s.counts.clone())
+      .unwrap_or_default()
+  }
+
+  /// Returns the number of skipped candidate matches (across all the files) for each rule and reason.
+  pub(crate) fn get_skip_counts(&self) -> BTreeMap<(String, SkipReason), usize> {
+    let mut skip_counts = BTreeMap::new();
+    for (key, count) in self.skipped_matches.values().flat_map(|s| &s.counts) {
+      *skip_counts.entry(key.clone()).or_insert(0) += count;
+    }
+    skip_counts
+  }
+
+  /// Returns the seed rules that produced neither an edit nor a match (i.e. that are not `applied_rules`),
+  /// along with the (best effort) reason why.
+  pub(crate) fn get_unapplied_seed_rules(
+    &self, applied_rules: &HashSet<String>,
+  ) -> BTreeMap<String, UnappliedSeedRule> {
+    let skip_counts = self.get_skip_counts();
+    let count = |rule_name: &String, reasons: &[SkipReason]| {
+      reasons
+        .iter()
+        .filter_map(|r| skip_counts.get(&(rule_name.to_string(), *r)))
+        .sum::<usize>()
+    };
+    let mut unapplied_seed_rules = BTreeMap::new();
+    for rule_name in self.seed_rules.difference(applied_rules) {
+      let reason = if let Some(holes) = self.unseeded_rules.get(rule_name) {
+        UnappliedSeedRule::UnsatisfiedHoles {
+          holes: holes.clone(),
+        }
+      } else {
+        let failed_constraints = count(rule_name, &[SkipReason::FilterRejected]);
+        let out_of_scope = count(
+          rule_name,
+          &[
+            SkipReason::OutsideLineRange,
+            SkipReason::SuppressedByComment,
+          ],
+        );
+        let skipped = skip_counts
+          .iter()
+          .filter(|((r, _), _)| r == rule_name)
+          .map(|(_, c)| c)
+          .sum::<usize>();
+        if failed_constraints > 0 {
+          UnappliedSeedRule::FailedConstraints {
+            count: failed_constraints,
+          }
+        } else if out_of_scope > 0 {
+          UnappliedSeedRule::OutOfScope {
+            count: out_of_scope,
+          }
+        } else if skipped > 0 {
+          UnappliedSeedRule::Skipped { count: skipped }
+        } else {
+          UnappliedSeedRule::NeverMatched
+        }
+      };
+      unapplied_seed_rules.insert(rule_name.to_string(), reason);
+    }
+    unapplied_seed_rules
+  }
+
+  /// Writes the part of the `rule_graph` executed from the rules seeded by this store (e.g. only the cleanup rules in
+  /// the `cleanup_only` mode) to the file `path` (see `RuleGraph::dump`).
+  pub(crate) fn dump_graph(&self, rule_graph: &RuleGraph, path: &str) -> Result<(), String> {
+    let seeded_rules = self
+      .seed_rules
+      .iter()
+      .filter(|r| !self.unseeded_rules.contains_key(*r))
+      .cloned()
+      .collect();
+    rule_graph.dump(path, &seeded_rules)
+  }
+
+  /// Records that the feature detection rule `rule_name` matched in the code base.
+  pub(crate) fn add_detected_feature(&mut self, rule_name: String) {
+    debug!("Detected feature {}", rule_name);
+    self.detected_features.insert(rule_name);
+  }
+
+  /// Checks if all the features required by the `rule` were detected in the code base.
+  pub(crate) fn has_required_features(&self, rule: &InstantiatedRule) -> bool {
+    rule
+      .rule()
+      .requires_features()
+      .iter()
+      .all(|f| self.detected_features.contains(f))
+  }
+
+  /// Removes the global rules whose required features were not detected in the code base.
+  pub(crate) fn remove_rules_without_required_features(&mut self) {
+    let (global_rules, deactivated_rules): (Vec<_>, Vec<_>) =
+      std::mem::take(&mut self.global_rules)
+        .into_iter()
+        .partition(|r| self.has_required_features(r));
+    for r in deactivated_rules {
+      debug!(
+        "Deactivated the rule {} (missing required features)",
+        r.name()
+      );
+    }
+    self.global_rules = global_rules;
+  }
+
+  /// Returns the tags referenced in the replacement of the `rule` that are not bound by the `matches`,
+  /// although they are known in the rule graph (i.e. they were meant to be substituted),
+  /// as well as the tags of its `%{tag}` placeholders that are not bound by the `matches`.
+  pub(crate) fn get_unbound_tags(
+    &self, rule: &InstantiatedRule, matches: &HashMap<String, String>,
+  ) -> Vec<String> {
+    let replace = rule.rule().replace();
+    let mut unbound_tags = vec![];
+    if replace.contains('@') {
+      unbound_tags.extend(get_tag_references(replace).into_iter().filter(|r| {
+        !is_bound_by(r, |t| matches.contains_key(t))
+          && is_bound_by(r, |t| self.known_tags.contains(t))
+      }));
+    }
+    if replace.contains("%{") {
+      unbound_tags.extend(
+        get_placeholder_tags(replace)
+          .into_iter()
+          .filter(|t| !matches.contains_key(t)),
+      );
+    }
+    unbound_tags.into_iter().unique().collect()
+  }
+
+  /// Add a new global rule, along with grep heuristics (If it doesn't already exist).
+  /// The variants of a rule (i.e. the rule instantiated with different substitutions, e.g. by different files) are
+  /// all kept, while an identical variant is only added once (so that the code base is not scanned twice for it).
+  /// The variants of a rule are ordered by their substitutions, rather than by the order the files are processed in.
   pub(crate) fn add_to_global_rules(&mut self, rule: &InstantiatedRule) {
     let r = rule.clone();
-    if !self.global_rules.iter().any(|r| {
-      r.name().eq(&rule.name()) && r.replace().eq(&rule.replace()) && r.query().eq(&rule.query())
-    }) {
-      #[rustfmt::skip]
-      debug!("{}", format!("Added Global Rule : {:?} - {}", r.name(), r.query().pattern()).bright_blue());
-      self.global_rules.push(r);
+    let key = r.variant_key();
+    if self.global_rules.iter().any(|g| g.variant_key() == key) {
+      return;
     }
+    debug!(
+      "{}",
+      format!(
+        "Added Global Rule : {:?} - {} (instantiated from {})",
+        r.name(),
+        r.query().pattern(),
+        r.to_rule().query().pattern()
+      )
+      .bright_blue()
+    );
+    let variants = self
+      .global_rules
+      .iter()
+      .positions(|g| g.name() == r.name())
+      .collect_vec();
+    let position = match variants
+      .iter()
+      .rfind(|i| self.global_rules[**i].variant_key() < key)
+    {
+      Some(i) => i + 1,
+      None => variants.first().copied().unwrap_or(self.global_rules.len()),
+    };
+    self.global_rules.insert(position, r);
   }
 
-  /// Get the compiled query for the `query_str` from the cache
-  /// else compile it, add it to the cache and return it.
-  pub(crate) fn query(&mut self, query_str: &CGPattern) -> &Query {
+  /// The language of the files the `rule` is applied to (see `Rule::language`)
+  pub(crate) fn language_of(&self, rule: &Rule) -> &PiranhaLanguage {
+    let extension = rule.language_or(self.language().extension());
     self
-      .rule_query_cache
-      .entry(query_str.pattern())
-      .or_insert_with(|| self.language.create_query(query_str.pattern()))
+      .rule_languages()
+      .iter()
+      .find(|l| l.extension() == extension)
+      .unwrap_or(self.language())
   }
 
-  // For the given scope level, get the ScopeQueryGenerator from the `scope_config.toml` file
-  pub(crate) fn get_scope_query_generators(&self, scope_level: &str) -> Vec<ScopeQueryGenerator> {
+  /// Get the compiled query for the `query_str` (in the `language`) from the cache
+  /// else compile it, add it to the cache (evicting the least recently used query if it is full) and return it.
+  pub(crate) fn query(&mut self, query_str: &CGPattern, language: &PiranhaLanguage) -> &Query {
+    self.rule_query_cache.get_or_compile(query_str, language)
+  }
+
+  /// The scope `scope_level` of the files of the `language`, i.e. the scope defined for the `language` (see
+  /// `ScopeGenerator::language`) by the scope configuration of the language of the run or of a rule, else the scope
+  /// defined by the scope configuration of the `language` itself.
+  fn get_scope_generator<'a>(
+    &'a self, scope_level: &str, language: &'a PiranhaLanguage,
+  ) -> Option<&'a ScopeGenerator> {
+    let extension = language.extension();
+    std::iter::once(self.language())
+      .chain(self.rule_languages())
+      .flat_map(|l| l.scopes())
+      .find(|scope| scope.name() == scope_level && scope.language() == extension)
+      .or_else(|| {
+        language.scopes().iter().find(|scope| {
+          scope.name() == scope_level
+            && (scope.language().is_empty() || scope.language() == extension)
+        })
+      })
+  }
+
+  // For the given scope level, get the ScopeQueryGenerator from the `scope_config.toml` file of the `language`
+  pub(crate) fn get_scope_query_generators(
+    &self, scope_level: &str, language: &PiranhaLanguage,
+  ) -> Vec<ScopeQueryGenerator> {
     self
-      .language()
-      .scopes()
-      .iter()
-      .find(|level| level.name().eq(scope_level))
+      .get_scope_generator(scope_level, language)
       .map(|scope| scope.rules().to_vec())
       .unwrap_or_else(Vec::new)
   }
 
+  /// The strategy selecting the scope node of the `scope_level` (see `ScopeStrategy`).
+  pub(crate) fn get_scope_strategy(
+    &self, scope_level: &str, language: &PiranhaLanguage,
+  ) -> ScopeStrategy {
+    self
+      .get_scope_generator(scope_level, language)
+      .map(|scope| *scope.strategy())
+      .unwrap_or_default()
+  }
+
   /// To create the current set of global rules, certain substitutions were applied.
-  /// This method creates a regex pattern matching these substituted values.
+  /// This method creates a regex pattern matching these substituted values (literally).
   ///
   /// At the directory level, we would always look to perform global rules. However this is expensive because
   /// it requires parsing each file. To overcome this, we apply this simple
   /// heuristic to find the (upper bound) files that would match one of our current global rules.
   /// This heuristic reduces the number of files to parse.
+  /// Returns `None` if a global rule with holes opted out of this heuristic (see `Rule::prefilter`), since it
+  /// may match files containing none of the substituted values.
   ///
-  pub(crate) fn get_grep_heuristics(&self) -> Regex {
+  pub(crate) fn get_grep_heuristics(&self) -> Option<Regex> {
+    if self
+      .global_rules()
+      .iter()
+      .any(|r| !r.holes().is_empty() && !*r.rule().prefilter())
+    {
+      return None;
+    }
     let reg_x = self
       .global_rules()
       .iter()
@@ -114,8 +664,9 @@ impl RuleStore {
       .filter(|x| {
         !x.is_empty() && !x.to_lowercase().eq("true") && !x.to_lowercase().as_str().eq("false")
       })
+      .map(|x| regex::escape(x))
       .join("|");
-    Regex::new(reg_x.as_str()).unwrap()
+    Some(Regex::new(reg_x.as_str()).unwrap())
   }
 
   /// Checks if any global rule has a hole
@@ -124,44 +675,28 @@ impl RuleStore {
   }
 
   /// Gets all the files from the code base that (i) have the language appropriate file extension, and (ii) contains the grep pattern.
-  /// Note that `WalkDir` traverses the directory with parallelism.
+  /// The files are read from the `codebase` (see `Codebase`), i.e. a directory, a single file or an archive.
   /// If all the global rules have no holes (i.e. we will have no grep patterns), we will try to find a match for each global rule in every file in the target.
-  pub(crate) fn get_relevant_files(
-    &self, path_to_codebase: &str, include: &Vec<Pattern>, exclude: &Vec<Pattern>,
-  ) -> HashMap<PathBuf, String> {
-    let _path_to_codebase = Path::new(path_to_codebase).to_path_buf();
+  /// The empty (or whitespace-only) files are never analyzed, since no rule can match them.
+  pub(crate) fn get_relevant_files(&self, codebase: &Codebase) -> HashMap<PathBuf, String> {
+    self.filter_relevant_files(codebase, self.get_files(codebase))
+  }
 
-    //If the path_to_codebase is a file, then execute piranha on it
-    if _path_to_codebase.is_file() {
-      return HashMap::from_iter([(
-        _path_to_codebase.clone(),
-        read_file(&_path_to_codebase).unwrap(),
-      )]);
+  /// Keeps the `files` (read from the `codebase` with `get_files`) that contain the grep pattern (see `get_relevant_files`).
+  pub(crate) fn filter_relevant_files(
+    &self, codebase: &Codebase, mut files: HashMap<PathBuf, String>,
+  ) -> HashMap<PathBuf, String> {
+    if codebase.is_file() {
+      return files;
     }
 
-    let mut files: HashMap<PathBuf, String> = WalkDir::new(path_to_codebase)
-      // walk over the entire code base
-      .into_iter()
-      // ignore errors
-      .filter_map(|e| e.ok())
-      // only retain the included paths (if any)
-      .filter(|f| include.is_empty() || include.iter().any(|p| p.matches_path(&f.path())))
-      // filter out all excluded paths (if any)
-      .filter(|f| exclude.is_empty() || exclude.iter().all(|p| !p.matches_path(&f.path())))
-      // filter files with the desired extension
-      .filter(|de| self.language().can_parse(de))
-      // read the file
-      .map(|f| (f.path(), read_file(&f.path()).unwrap()))
-      .collect();
-
-    if self.any_global_rules_has_holes() {
-      let pattern = self.get_grep_heuristics();
-      files = files
-        .iter()
-        // Filter the files containing the desired regex pattern
-        .filter(|x| pattern.is_match(x.1.as_str()))
-        .map(|(x, y)| (x.clone(), y.clone()))
-        .collect();
+    if let Some(pattern) = self
+      .any_global_rules_has_holes()
+      .then(|| self.get_grep_heuristics())
+      .flatten()
+    {
+      // Filter the files containing the desired regex pattern
+      files.retain(|_, content| pattern.is_match(content));
     }
     debug!(
       "{}",
@@ -169,4 +704,269 @@ impl RuleStore {
     );
     files
   }
+
+  /// Returns all the (non-blank) files of the code base in the languages of the rules, i.e. without the grep heuristics
+  /// of `get_relevant_files`. A single source file is analyzed regardless of its extension.
+  pub(crate) fn get_files(&self, codebase: &Codebase) -> HashMap<PathBuf, String> {
+    read_source_files(codebase, |path| {
+      self.language().can_parse_path(path)
+        || self.rule_languages().iter().any(|l| l.can_parse_path(path))
+    })
+    .unwrap_or_else(|e| panic!("{}", e.red()))
+  }
 }
+
+/// Reads the (non-blank) files of the code base that `can_parse`. A single source file is read regardless of
+/// its extension.
+pub(crate) fn read_source_files(
+  codebase: &Codebase, can_parse: impl Fn(&Path) -> bool,
+) -> Result<HashMap<PathBuf, String>, String> {
+  Ok(
+    codebase
+      .read_files(|path| codebase.is_file() || can_parse(path))?
+      .into_iter()
+      // filter out the empty (or whitespace-only) files
+      .filter(|(path, content)| !is_blank(path, content))
+      .collect(),
+  )
+}
+
+/// Checks if the file at `path` is empty (or only contains whitespace), in which case it is not analyzed.
+fn is_blank(path: &Path, content: &str) -> bool {
+  let is_blank = content.trim().is_empty();
+  if is_blank {
+    debug!("Skipping {:?} as it is empty", path);
+  }
+  is_blank
+}
+
+/// Reads a rule file (in the `rules.toml` format).
+/// Lists the rule files (i.e. the `*.toml` files) of the directory `path`, in the order of their names.
+fn list_rule_files(path: &Path) -> Vec<Result<PathBuf, String>> {
+  match fs::read_dir(path) {
+    Ok(entries) => entries
+      .filter_map(|e| e.ok())
+      .map(|e| e.path())
+      .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == "toml"))
+      .sorted()
+      .map(Ok)
+      .collect_vec(),
+    Err(e) => vec![Err(format!(
+      "Could not read the rules directory {path:?} - {e}"
+    ))],
+  }
+}
+
+fn read_rule_file(rule_file: &PathBuf) -> Result<Rules, String> {
+  read_file(rule_file)
+    .and_then(|content| toml::from_str::<Rules>(&content).map_err(|e| e.to_string()))
+    .map_err(|e| format!("Could not read the rule file {rule_file:?} - {e}"))
+}
+
+/// Checks that the `rule` is valid, and compiles its concrete syntax (if any) to its query for its language
+/// (the `language`, unless the rule targets another one).
+fn check_rule(mut rule: Rule, language: &PiranhaLanguage) -> Result<Rule, String> {
+  rule.validate()?;
+  rule.compile_query_cst(language)?;
+  Ok(rule)
+}
+
+/// Returns the built-in rules and edges of the run, i.e. of its language and of the other languages of the rules
+/// (see `PiranhaLanguage::prefixed_built_in_rules`).
+fn get_built_in_rule_set(args: &PiranhaArguments) -> RuleSet {
+  let mut rules = args.language().rules().clone().unwrap_or_default().rules;
+  let mut edges = args.language().edges().clone().unwrap_or_default().edges;
+  for language in args
+    .rule_graph()
+    .rule_languages(args.language().extension())
+  {
+    let (other_rules, other_edges) = language.prefixed_built_in_rules();
+    rules.extend(other_rules);
+    edges.extend(other_edges);
+  }
+  RuleSet { rules, edges }
+}
+
+/// Returns the rules and edges of the rule graph that are not built-in.
+fn get_user_defined_rule_set(args: &PiranhaArguments) -> RuleSet {
+  let RuleSet {
+    rules: built_in_rules,
+    edges: built_in_edges,
+  } = get_built_in_rule_set(args);
+  RuleSet {
+    rules: args
+      .rule_graph()
+      .rules()
+      .iter()
+      .filter(|r| !built_in_rules.contains(r))
+      .cloned()
+      .collect(),
+    edges: args
+      .rule_graph()
+      .edges()
+      .iter()
+      .filter(|e| !built_in_edges.contains(e))
+      .cloned()
+      .collect(),
+  }
+}
+
+/// Returns the captures of the query of the `rule` (i.e. the tags of the query that are not holes).
+fn get_captures(rule: &Rule) -> HashSet<String> {
+  get_tag_references(&rule.query_with_captures().pattern())
+    .into_iter()
+    .filter(|t| !rule.holes().contains(t))
+    .collect()
+}
+
+/// Returns the captures of the outermost nodes of the `query` (e.g. `@mi` in `(method_invocation ...) @mi`).
+/// These are replaced by default, and hence need not be referenced.
+fn get_outermost_captures(query: &str) -> HashSet<String> {
+  let mut depth = 0;
+  let mut in_string = false;
+  let mut outermost_query = String::new();
+  let mut chars = query.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' if in_string => {
+        chars.next();
+      }
+      '"' => in_string = !in_string,
+      '(' | '[' if !in_string => depth += 1,
+      ')' | ']' if !in_string => depth -= 1,
+      _ if !in_string && depth == 0 => outermost_query.push(c),
+      _ => {}
+    }
+  }
+  get_tag_references(&outermost_query).into_iter().collect()
+}
+
+/// Returns the tags available to each rule when it is instantiated, i.e. the input substitutions
+/// and the tags captured by the (transitively) preceding rules in the graph.
+fn get_available_tags(
+  rule_graph: &RuleGraph, input_substitutions: &HashMap<String, String>,
+) -> HashMap<String, HashSet<String>> {
+  let mut available_tags: HashMap<String, HashSet<String>> = rule_graph
+    .rules()
+    .iter()
+    .map(|r| {
+      (
+        r.name().to_string(),
+        input_substitutions.keys().cloned().collect(),
+      )
+    })
+    .collect();
+  // Propagate the tags along the edges until a fixed point is reached
+  let mut changed = true;
+  while changed {
+    changed = false;
+    for rule in rule_graph.rules() {
+      let mut produced_tags = available_tags[rule.name()].clone();
+      produced_tags.extend(get_captures(rule));
+      for (_, to_rule) in rule_graph.get_neighbors(rule.name()) {
+        if let Some(tags) = available_tags.get_mut(&to_rule) {
+          let number_of_tags = tags.len();
+          tags.extend(produced_tags.iter().cloned());
+          changed |= tags.len() != number_of_tags;
+        }
+      }
+    }
+  }
+  available_tags
+}
+
+/// Returns the tags known in the rule graph, i.e. the captures and holes of the rules, the input substitutions and
+/// the declared package (see `PACKAGE_TAG`).
+fn get_known_tags(args: &PiranhaArguments) -> HashSet<String> {
+  let mut known_tags: HashSet<String> = args.input_substitutions().into_keys().collect();
+  known_tags.insert(PACKAGE_TAG.to_string());
+  for rule in args.rule_graph().rules() {
+    known_tags.extend(get_captures(rule));
+    known_tags.extend(rule.holes().iter().cloned());
+  }
+  known_tags
+}
+
+/// Warns about the tags of the user defined rules that are likely mistakes, i.e.
+///   * the holes that are neither input substitutions nor captured by a preceding rule,
+///   * the tags referenced in `replace` that are input substitutions or captured by a preceding rule, but not holes
+///     of the rule (these are not substituted, and are emitted as is), and
+///   * the captures of the rewrite rules that are never used (e.g. a swapped argument missing from `replace`).
+/// The other references (e.g. a Java annotation `@Override`) are emitted as is, whereas the misspelled captures and
+/// holes are rejected when the rule is validated.
+fn warn_about_tags(args: &PiranhaArguments) {
+  let rule_graph = args.rule_graph();
+  let built_in_rule_set = get_built_in_rule_set(args);
+  let built_in_rules: HashSet<&String> = built_in_rule_set.rules.iter().map(|r| r.name()).collect();
+  let available_tags = get_available_tags(rule_graph, &args.input_substitutions());
+  let holes_in_graph: HashSet<&String> =
+    rule_graph.rules().iter().flat_map(|r| r.holes()).collect();
+  for rule in rule_graph
+    .rules()
+    .iter()
+    .filter(|r| !r.is_dummy_rule() && !built_in_rules.contains(r.name()))
+  {
+    let captures = get_captures(rule);
+    let available = &available_tags[rule.name()];
+    for hole in rule
+      .holes()
+      .iter()
+      .filter(|h| !available.contains(*h))
+      .sorted()
+    {
+      warn!(
+        "The hole `@{}` of the rule `{}` is neither an input substitution nor captured by a preceding rule.",
+        hole,
+        rule.name()
+      );
+    }
+
+    let references = get_tag_references(rule.replace());
+    for reference in references
+      .iter()
+      .filter(|r| !is_bound_by(r, |t| captures.contains(t) || rule.holes().contains(t)))
+      .sorted()
+      .dedup()
+    {
+      if is_bound_by(reference, |t| available.contains(t)) {
+        warn!(
+          "The replacement of the rule `{}` references the tag `@{}`, which is not one of its holes. It will not be substituted unless it is added to the holes.",
+          rule.name(),
+          reference
+        );
+      }
+    }
+
+    if rule.is_match_only_rule() {
+      continue;
+    }
+    let query_references = get_tag_references(&rule.query().pattern());
+    let outermost_captures = get_outermost_captures(&rule.query().pattern());
+    let filter_references: HashSet<String> = rule
+      .filters()
+      .iter()
+      .flat_map(|f| f.patterns())
+      .flat_map(|p| get_tag_references(&p.pattern()))
+      .collect();
+    for capture in captures.iter().sorted() {
+      let is_used = capture == rule.replace_node().trim_start_matches('@')
+        || outermost_captures.contains(capture)
+        // Used in a predicate of the query
+        || query_references.iter().filter(|t| *t == capture).count() > 1
+        || references.iter().any(|r| is_bound_by(r, |t| t == capture))
+        || filter_references.contains(capture)
+        || holes_in_graph.contains(capture);
+      if !is_used {
+        warn!(
+          "The capture `@{}` of the rule `{}` is never used.",
+          capture,
+          rule.name()
+        );
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+#[path = "unit_tests/rule_store_test.rs"]
+mod rule_store_test;
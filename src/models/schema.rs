@@ -0,0 +1,74 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+//! The JSON schemas of the configuration files and of the arguments (see `emit_schemas`), for the editors to validate
+//! and complete them. The configuration files are also rejected at runtime if they have unknown fields.
+
+use std::{
+  fs,
+  path::{Path, PathBuf},
+};
+
+use schemars::schema_for;
+use serde_json::{json, Value};
+
+use super::{
+  outgoing_edges::Edges, piranha_arguments::PiranhaArguments, rule::Rules, scopes::ScopeConfig,
+};
+
+/// The schemas of the configuration files, by name: `rules` (`rules.toml`), `edges` (`edges.toml`),
+/// `scope_config` (`scope_config.toml`), `input_substitutions` (the `input_substitutions_file`) and
+/// `piranha_arguments` (the arguments, by their names in the Python API).
+pub(crate) fn config_schemas() -> Vec<(&'static str, Value)> {
+  vec![
+    ("rules", json!(schema_for!(Rules))),
+    ("edges", json!(schema_for!(Edges))),
+    ("scope_config", json!(schema_for!(ScopeConfig))),
+    ("input_substitutions", input_substitutions_schema()),
+    ("piranha_arguments", json!(schema_for!(PiranhaArguments))),
+  ]
+}
+
+/// The schema of the `input_substitutions_file`, a flat table of strings, numbers, booleans or lists of them
+/// (see `PiranhaArguments::input_substitutions_from_file`).
+fn input_substitutions_schema() -> Value {
+  let scalar = json!({ "type": ["string", "number", "boolean"] });
+  json!({
+    "$schema": "http://json-schema.org/draft-07/schema#",
+    "title": "InputSubstitutions",
+    "type": "object",
+    "additionalProperties": {
+      "anyOf": [scalar, { "type": "array", "items": scalar }]
+    }
+  })
+}
+
+/// Writes the schema of each configuration file (see `config_schemas`) to `<name>.schema.json` in the directory `dir`
+/// (created if needed). Returns the paths of the written files.
+pub fn emit_schemas(dir: &Path) -> Result<Vec<PathBuf>, String> {
+  fs::create_dir_all(dir).map_err(|e| format!("Could not create the directory {dir:?} - {e}"))?;
+  config_schemas()
+    .into_iter()
+    .map(|(name, schema)| {
+      let path = dir.join(format!("{name}.schema.json"));
+      let content = serde_json::to_string_pretty(&schema).map_err(|e| e.to_string())?;
+      fs::write(&path, content)
+        .map_err(|e| format!("Could not write the schema {path:?} - {e}"))?;
+      Ok(path)
+    })
+    .collect()
+}
+
+#[cfg(test)]
+#[path = "unit_tests/schema_test.rs"]
+mod schema_test;
@@ -19,25 +19,54 @@ use crate::utilities::Instantiate;
 use derive_builder::Builder;
 use getset::Getters;
 use log::trace;
+use schemars::JsonSchema;
 use serde_derive::Deserialize;
 
 // Represents the content in the `scope_config.toml` file
-#[derive(Deserialize, Debug, Clone, Hash, PartialEq, Eq, Default, Getters)]
+#[derive(Deserialize, JsonSchema, Debug, Clone, Hash, PartialEq, Eq, Default, Getters)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct ScopeConfig {
   #[get = "pub"]
   scopes: Vec<ScopeGenerator>,
 }
 
 // Represents an entry in the `scope_config.toml` file
-#[derive(Deserialize, Debug, Clone, Hash, PartialEq, Eq, Default, Builder, Getters)]
+#[derive(Deserialize, JsonSchema, Debug, Clone, Hash, PartialEq, Eq, Default, Builder, Getters)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct ScopeGenerator {
   #[get = "pub"]
   name: String,
   #[get = "pub"]
   rules: Vec<ScopeQueryGenerator>,
+  // How the scope node is selected among the nodes matching the scope query (e.g. nested classes with the same name)
+  #[builder(default)]
+  #[serde(default)]
+  #[get = "pub"]
+  strategy: ScopeStrategy,
+  // The language of the files the scope applies to (e.g. `kt`), if it differs from the language of the scope
+  // configuration. It overrides the scope of the same name of that language (see `RuleStore::get_scope_generator`)
+  #[builder(default)]
+  #[serde(default)]
+  #[get = "pub"]
+  language: String,
+}
+
+/// How the scope of a rule triggered by an edit is selected among the nodes matching its scope query
+/// (e.g. for inner classes, or nested objects with the same name).
+#[derive(Deserialize, JsonSchema, Debug, Clone, Copy, Hash, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ScopeStrategy {
+  /// The first node matching the scope query of the innermost enclosing node (in the order of the code)
+  #[default]
+  FirstMatch,
+  /// The innermost node enclosing the edit
+  NearestEnclosing,
+  /// The outermost node enclosing the edit
+  OutermostEnclosing,
 }
 
-#[derive(Deserialize, Debug, Clone, Hash, PartialEq, Eq, Default, Getters, Builder)]
+#[derive(Deserialize, JsonSchema, Debug, Clone, Hash, PartialEq, Eq, Default, Getters, Builder)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct ScopeQueryGenerator {
   #[get = "pub"]
   enclosing_node: CGPattern, // a tree-sitter query matching some enclosing AST pattern (like method or class)
@@ -45,17 +74,34 @@ pub(crate) struct ScopeQueryGenerator {
   scope: CGPattern, // a tree-sitter query that will match the same node that matched `enclosing_node`
 }
 
+/// The scope query of a rule triggered by an edit, along with how the scope node is selected among its matches.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Getters)]
+pub(crate) struct ScopeQuery {
+  #[get = "pub"]
+  query: CGPattern,
+  #[get = "pub"]
+  strategy: ScopeStrategy,
+  // The byte range of the edit, along with the number of edits applied to the file at the time
+  // (the range is shifted by the subsequent edits, see `SourceCodeUnit::get_scope_node`)
+  #[get = "pub"]
+  origin: (usize, usize, usize),
+}
+
 // Implements instance methods related to getting the scope
 impl SourceCodeUnit {
   /// Generate a tree-sitter based query representing the scope of the previous edit.
-  /// We generate these scope queries by matching the rules provided in `<lang>_scopes.toml`.
+  /// We generate these scope queries by matching the rules provided in `<lang>_scopes.toml`
+  /// against the innermost node enclosing the edit (or the outermost one, see `ScopeStrategy`).
   pub(crate) fn get_scope_query(
     &self, scope_level: &str, start_byte: usize, end_byte: usize, rules_store: &mut RuleStore,
-  ) -> CGPattern {
+  ) -> ScopeQuery {
     let root_node = self.root_node();
     let mut changed_node = get_node_for_range(root_node, start_byte, end_byte);
     // Get the scope enclosing_nodes for `scope_level` from the `scope_config.toml`.
-    let scope_enclosing_nodes = rules_store.get_scope_query_generators(scope_level);
+    let scope_enclosing_nodes =
+      rules_store.get_scope_query_generators(scope_level, self.piranha_arguments().language());
+    let strategy = rules_store.get_scope_strategy(scope_level, self.piranha_arguments().language());
+    let mut scope_query = None;
 
     // Match the `scope_enclosing_node.enclosing_node` to the parent
     loop {
@@ -68,21 +114,33 @@ impl SourceCodeUnit {
         if let Some(p_match) = get_match_for_query(
           &changed_node,
           self.code(),
-          rules_store.query(m.enclosing_node()),
+          rules_store.query(m.enclosing_node(), self.piranha_arguments().language()),
           false,
         ) {
           // Generate the scope query for the specific context by substituting the
           // the tags with code snippets appropriately in the `generator` query.
-          return m.scope().instantiate(p_match.matches());
+          scope_query = Some(m.scope().instantiate(p_match.matches()));
+          break;
         }
       }
+      // The outermost enclosing node is looked up among all the ancestors
+      if scope_query.is_some() && strategy != ScopeStrategy::OutermostEnclosing {
+        break;
+      }
       if let Some(parent) = changed_node.parent() {
         changed_node = parent;
       } else {
         break;
       }
     }
-    panic!("Could not create scope query for {scope_level:?}");
+    let Some(query) = scope_query else {
+      panic!("Could not create scope query for {scope_level:?}");
+    };
+    ScopeQuery {
+      query,
+      strategy,
+      origin: (start_byte, end_byte, self.number_of_applied_edits()),
+    }
   }
 }
 
@@ -0,0 +1,102 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use std::fmt;
+
+use getset::{CopyGetters, Getters};
+use pyo3::{prelude::pyclass, pymethods};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::utilities::gen_py_str_methods;
+
+use super::matches::Range;
+
+/// The reasons for which Piranha intentionally skips a candidate match of a rule.
+/// This helps distinguish "skipped by design" from "never matched".
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+  /// The match does not satisfy the filters of the rule
+  FilterRejected,
+  /// An equivalent match was already recorded for the (match-only) rule at the same location
+  DuplicateMatch,
+  /// The replacement is equivalent to the matched code
+  NoOpEdit,
+  /// The rewrites of the file were vetoed by a plugin
+  VetoedByPlugin,
+  /// The replacement references a tag that is not bound by the match
+  UnboundTag,
+  /// The edit was skipped by the `EditInterceptor`
+  VetoedByInterceptor,
+  /// The match is in a region suppressed by a `piranha:disable` comment directive
+  SuppressedByComment,
+  /// The match starts outside the lines the rules are applied to (see `apply_rules_on_lines`)
+  OutsideLineRange,
+  /// The match is in the code rewritten by another rule of the same `exclusive_group`
+  ExclusiveGroup,
+}
+
+impl fmt::Display for SkipReason {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let reason = match self {
+      SkipReason::FilterRejected => "filter_rejected",
+      SkipReason::DuplicateMatch => "duplicate_match",
+      SkipReason::NoOpEdit => "no_op_edit",
+      SkipReason::VetoedByPlugin => "vetoed_by_plugin",
+      SkipReason::UnboundTag => "unbound_tag",
+      SkipReason::VetoedByInterceptor => "vetoed_by_interceptor",
+      SkipReason::SuppressedByComment => "suppressed_by_comment",
+      SkipReason::OutsideLineRange => "outside_line_range",
+      SkipReason::ExclusiveGroup => "exclusive_group",
+    };
+    write!(f, "{reason}")
+  }
+}
+
+/// A candidate match of a rule that was skipped
+#[derive(
+  Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Getters, CopyGetters,
+)]
+#[pyclass]
+pub struct SkippedMatch {
+  /// Name of the rule
+  #[pyo3(get)]
+  #[get = "pub"]
+  rule_name: String,
+  /// Why the candidate match was skipped
+  #[get_copy = "pub"]
+  reason: SkipReason,
+  /// Range of the candidate match
+  #[pyo3(get)]
+  range: Range,
+}
+
+impl SkippedMatch {
+  pub(crate) fn new(rule_name: &str, reason: SkipReason, range: tree_sitter::Range) -> Self {
+    Self {
+      rule_name: rule_name.to_string(),
+      reason,
+      range: Range::from(range),
+    }
+  }
+}
+
+#[pymethods]
+impl SkippedMatch {
+  #[getter(reason)]
+  fn py_reason(&self) -> String {
+    self.reason.to_string()
+  }
+
+  gen_py_str_methods!();
+}
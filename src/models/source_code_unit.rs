@@ -11,62 +11,378 @@ Copyright (c) 2023 Uber Technologies, Inc.
  limitations under the License.
 */
 use std::{
-  collections::{HashMap, VecDeque},
+  cell::{OnceCell, RefCell},
+  collections::{HashMap, HashSet, VecDeque},
+  fmt,
+  hash::{DefaultHasher, Hash, Hasher},
   path::{Path, PathBuf},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, RecvTimeoutError},
+    Arc,
+  },
+  thread,
+  time::{Duration, Instant},
 };
 
 use colored::Colorize;
 use itertools::Itertools;
-use log::{debug, error};
+use log::{debug, error, info, warn};
 
+use regex::Regex;
+use schemars::JsonSchema;
 use tree_sitter::{InputEdit, Node, Parser, Range, Tree};
+use tree_sitter_traversal::{traverse, Order};
 
 use crate::{
   models::capture_group_patterns::CGPattern,
   models::rule_graph::{GLOBAL, PARENT},
-  utilities::tree_sitter_utilities::{
-    get_match_for_query, get_node_for_range, get_replace_range, get_tree_sitter_edit,
-    number_of_errors,
+  piranha_rule,
+  utilities::{
+    exclude_split_line_ending, instantiate_query, is_protected_header_line,
+    tree_sitter_utilities::{
+      get_all_matches_for_query, get_changed_range, get_error_ranges, get_match_for_query,
+      get_node_for_range, get_range_for_offsets, get_replace_range, get_tree_sitter_edit,
+      number_of_errors, shift_range, LineMap, Overlap,
+    },
   },
 };
 
 use super::{
-  edit::Edit, matches::Match, piranha_arguments::PiranhaArguments, rule::InstantiatedRule,
+  edit::{Edit, EditConflict, TriggeredBy},
+  edit_interceptor::EditDecision,
+  matches::{self, Match},
+  piranha_arguments::PiranhaArguments,
+  piranha_output::RuleStat,
+  progress::ProgressEvent,
+  rule::InstantiatedRule,
   rule_store::RuleStore,
+  scopes::{ScopeQuery, ScopeStrategy},
+  skipped_match::{SkipReason, SkippedMatch},
+  source_code_view::SourceCodeView,
+  suppression::{get_suppressions, is_file_disabled, Suppression},
 };
 use getset::{CopyGetters, Getters, MutGetters, Setters};
+
+/// The pseudo-rule of the edits deleting the comments that match the `comment_deletion_patterns`
+pub(crate) static COMMENT_DELETION_RULE: &str = "Delete Matching Comment";
+
+/// The pseudo-rule of the rewrites of `SourceCodeUnit::replace_all_literals`
+pub(crate) static REPLACE_ALL_LITERALS_RULE: &str = "Replace All Literals";
+
+/// Determines how the input files that do not parse (i.e. whose AST contains error nodes) are handled
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, clap::ValueEnum, JsonSchema)]
+#[schemars(rename_all = "kebab-case")]
+pub enum OnParseError {
+  /// No rule is applied to the file. The file (and its error ranges) are reported in the output summary
+  #[default]
+  Skip,
+  /// The rules are applied to the file anyway
+  Proceed,
+  /// Piranha panics
+  Fail,
+}
+
+/// Determines the line endings of the rewritten files
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, clap::ValueEnum, JsonSchema)]
+#[schemars(rename_all = "kebab-case")]
+pub enum LineEndings {
+  /// The line endings of the file are kept. The new lines of the replacements use the dominant line ending of the file
+  #[default]
+  Preserve,
+  /// All the line endings of the rewritten file are converted to `\n`
+  Lf,
+  /// All the line endings of the rewritten file are converted to `\r\n`
+  Crlf,
+}
+
+/// The rules were not all applied within the time budget (see `SourceCodeUnit::apply_rules_with_timeout`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeoutError {
+  /// The time budget
+  pub timeout: Duration,
+  /// The rule that was being applied when the time budget expired
+  pub rule: String,
+}
+
+impl fmt::Display for TimeoutError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(
+      f,
+      "Timed out after {:?} while applying the rule `{}`",
+      self.timeout, self.rule
+    )
+  }
+}
+
+impl std::error::Error for TimeoutError {}
+
+/// Applying the rules transactionally failed (see `SourceCodeUnit::apply_rules_transactional`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PiranhaError {
+  /// An edit of the `rule` produced syntactically incorrect code in the file at `path`
+  SyntaxError { rule: String, path: PathBuf },
+}
+
+impl fmt::Display for PiranhaError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      PiranhaError::SyntaxError { rule, path } => write!(
+        f,
+        "The rule `{rule}` produced syntactically incorrect code in {path:?}"
+      ),
+    }
+  }
+}
+
+impl std::error::Error for PiranhaError {}
+
+impl LineEndings {
+  /// The line ending to use in the replacements for the `code`, i.e. its dominant line ending unless it is normalized.
+  fn line_ending_for(&self, code: &str) -> &'static str {
+    match self {
+      LineEndings::Lf => "\n",
+      LineEndings::Crlf => "\r\n",
+      LineEndings::Preserve => {
+        let new_lines = code.matches('\n').count();
+        let crlf_new_lines = code.matches("\r\n").count();
+        if crlf_new_lines > new_lines - crlf_new_lines {
+          "\r\n"
+        } else {
+          "\n"
+        }
+      }
+    }
+  }
+}
+
+/// Replaces all the new lines of the `code` (either `\n` or `\r\n`) with the `line_ending`.
+pub(crate) fn with_line_ending(code: &str, line_ending: &str) -> String {
+  let code = code.replace("\r\n", "\n");
+  if line_ending == "\n" {
+    code
+  } else {
+    code.replace('\n', line_ending)
+  }
+}
+
+/// The byte range deleted along with the comment between `start_byte` and `end_byte`: its whole line if the comment
+/// is alone on it, the comment and the whitespace around it if it trails some code, else the comment only.
+fn get_comment_deletion_range(code: &str, start_byte: usize, end_byte: usize) -> (usize, usize) {
+  let end_byte = exclude_split_line_ending(code, end_byte);
+  let is_blank = |c: char| c == ' ' || c == '\t';
+  let start = code[..start_byte].trim_end_matches(is_blank).len();
+  let end = code.len() - code[end_byte..].trim_start_matches(is_blank).len();
+  let starts_line = start == 0 || code[..start].ends_with('\n');
+  let rest = &code[end..];
+  let line_break = if rest.starts_with("\r\n") {
+    2
+  } else {
+    usize::from(rest.starts_with('\n'))
+  };
+  match (starts_line, rest.is_empty() || line_break > 0) {
+    (true, true) => (start, end + line_break),
+    (false, true) => (start, end),
+    _ => (start_byte, end_byte),
+  }
+}
+
 // Maintains the updated source code content and AST of the file
 #[derive(Clone, Getters, CopyGetters, MutGetters, Setters)]
 pub(crate) struct SourceCodeUnit {
   // The tree representing the file
+  #[get = "pub(crate)"]
   ast: Tree,
   // The original content of a file
-  #[get = "pub"]
+  #[get = "pub(crate)"]
   #[set = "pub(crate)"]
   original_content: String,
   // The content of a file
-  #[get = "pub"]
+  #[get = "pub(crate)"]
   #[set = "pub(crate)"]
   code: String,
   // The tag substitution cache.
   // This map is looked up to instantiate new rules.
-  #[get = "pub"]
+  #[get = "pub(crate)"]
   substitutions: HashMap<String, String>,
   // The path to the source code.
-  #[get = "pub"]
+  #[get = "pub(crate)"]
   path: PathBuf,
 
   // Rewrites applied to this source code unit
-  #[get = "pub"]
-  #[get_mut = "pub"]
+  #[get = "pub(crate)"]
+  #[get_mut = "pub(crate)"]
   rewrites: Vec<Edit>,
+  // The edits of the `dry` rules, recorded instead of being applied
+  #[get = "pub(crate)"]
+  suggested_rewrites: Vec<Edit>,
   // Matches for the read_only rules in this source code unit
-  #[get = "pub"]
-  #[get_mut = "pub"]
+  #[get = "pub(crate)"]
   matches: Vec<(String, Match)>,
+  // The byte range of each of the `matches` in the current code, shifted by the edits (None once its code is rewritten)
+  #[get = "pub(crate)"]
+  match_ranges: Vec<Option<(usize, usize)>>,
+  // The (tree-sitter) edits applied to this source code unit (in order), since the ones no longer referenced
+  // were pruned (see `prune_input_edits`)
+  input_edits: Vec<InputEdit>,
+  // The number of edits applied before the first of the `input_edits`
+  pruned_input_edits: usize,
   // Piranha Arguments passed by the user
-  #[get = "pub"]
+  #[get = "pub(crate)"]
   piranha_arguments: PiranhaArguments,
+  // Ranges of the syntax errors in the original content, when the file is skipped because of them
+  #[get = "pub(crate)"]
+  parse_errors: Vec<matches::Range>,
+  // The package (or module) declared in this source code unit, looked up once (for the package filters)
+  declared_package: OnceCell<Option<String>>,
+  // The regions suppressed by the comment directives (see `suppression`), looked up once per parse of the code
+  suppressions: OnceCell<Vec<Suppression>>,
+  // The byte ranges of the matches of each scope query (see `get_scope_node`), looked up once per parse of the code
+  scope_matches: RefCell<HashMap<CGPattern, Vec<(usize, usize)>>>,
+  // Whether the file is opted out of Piranha by a leading `piranha:disable` comment (see `suppression::is_file_disabled`)
+  disabled: bool,
+  // The statistics (matches, applications and time spent) of each rule applied to this source code unit
+  #[get = "pub(crate)"]
+  rule_statistics: HashMap<String, RuleStat>,
+  // The time after which no more rules are applied to this source code unit (see `remaining_time_budget`)
+  deadline: Option<Instant>,
+  // The rule that was being applied when the `deadline` expired (if it did)
+  #[get = "pub(crate)"]
+  timed_out_rule: Option<String>,
+  // Set (by another thread) once the rules should no longer be applied (see `apply_rules_with_timeout`)
+  cancelled: Option<Arc<AtomicBool>>,
+  // Notes for the reviewers of the rewrites (e.g. when a rule deleted one of several top-level types)
+  #[get = "pub(crate)"]
+  notes: Vec<String>,
+  // The chains of "Parent" cleanups (rule and range), each starting with the edit that triggered them (see `trace_propagation`)
+  #[get = "pub(crate)"]
+  propagation_traces: Vec<Vec<(String, matches::Range)>>,
+  // The contents that replaced the code other than through the rewrites (e.g. when deleting the consecutive new lines),
+  // along with the number of rewrites applied before (see `validate_edits`)
+  content_replacements: Vec<(usize, String)>,
+  // The line ending of the new lines in the replacements (see `LineEndings`), detected when the file is loaded
+  line_ending: &'static str,
+  // The rule and byte range of the edits skipped by the `edit_interceptor` (see `PiranhaArguments`)
+  vetoed_edits: HashSet<(String, usize, usize)>,
+  // The code rewritten by the rules of each `exclusive_group` (the group, the rule and the byte range), shifted by the edits
+  exclusive_regions: Vec<(String, String, usize, usize)>,
+  // Whether a syntactically incorrect edit is recorded in `failure` instead of panicking (see `apply_rules_transactional`)
+  transactional: bool,
+  // Why applying the rules transactionally failed (if it did)
+  failure: Option<PiranhaError>,
+  // The byte range of the lines the rules are restricted to (see `apply_rules_on_lines`), shifted by the edits
+  line_range: Option<(usize, usize)>,
+  // Whether the rules triggered by an edge may match outside the `line_range`
+  propagate_outside_range: bool,
+  // The number of nested propagations (see `propagate`) in progress
+  propagation_depth: usize,
+  // The rules scheduled (for a scope) in the current propagation episode, i.e. since the outermost propagation
+  // started, along with the number of edits applied when each of them was last applied (`None` while it is pending)
+  scheduled_rules: HashMap<ScheduledRule, Option<usize>>,
+}
+
+// Checks if the rules `a` and `b` are the same variant of a rule, i.e. they have the same name and substitutions
+fn is_same_variant(a: &InstantiatedRule, b: &InstantiatedRule) -> bool {
+  a.name() == b.name() && a.substitutions() == b.substitutions()
+}
+
+// The `parent_rules` triggered by the same rule as the `applied_rule` (except itself), i.e. the "Parent" rules that
+// are still re-evaluated after it was applied, although it does not trigger them
+fn get_sibling_rules(
+  parent_rules: &[InstantiatedRule], applied_rule: &InstantiatedRule,
+) -> Vec<InstantiatedRule> {
+  let trigger = |r: &InstantiatedRule| r.triggered_by().as_ref().map(|t| t.rule().to_string());
+  parent_rules
+    .iter()
+    .filter(|r| trigger(r) == trigger(applied_rule) && !is_same_variant(r, applied_rule))
+    .cloned()
+    .collect()
+}
+
+// A rule (i.e. its name and substitutions) scheduled for the byte range of its resolved scope node
+type ScheduledRule = (String, Vec<(String, String)>, std::ops::Range<usize>);
+
+// The state of a source code unit restored when applying rules transactionally fails (see `apply_rules_transactional`).
+// The collections that only grow while the rules are applied are truncated back to their length, rather than copied.
+struct Snapshot {
+  ast: Tree,
+  code: String,
+  substitutions: HashMap<String, String>,
+  rewrites: usize,
+  suggested_rewrites: usize,
+  matches: usize,
+  match_ranges: Vec<Option<(usize, usize)>>,
+  input_edits: usize,
+  notes: usize,
+  propagation_traces: usize,
+  content_replacements: usize,
+  rule_statistics: HashMap<String, RuleStat>,
+  timed_out_rule: Option<String>,
+  vetoed_edits: HashSet<(String, usize, usize)>,
+  exclusive_regions: Vec<(String, String, usize, usize)>,
+  line_range: Option<(usize, usize)>,
+}
+
+impl Snapshot {
+  fn new(source_code_unit: &SourceCodeUnit) -> Self {
+    Snapshot {
+      ast: source_code_unit.ast.clone(),
+      code: source_code_unit.code.clone(),
+      substitutions: source_code_unit.substitutions.clone(),
+      rewrites: source_code_unit.rewrites.len(),
+      suggested_rewrites: source_code_unit.suggested_rewrites.len(),
+      matches: source_code_unit.matches.len(),
+      match_ranges: source_code_unit.match_ranges.clone(),
+      input_edits: source_code_unit.number_of_applied_edits(),
+      notes: source_code_unit.notes.len(),
+      propagation_traces: source_code_unit.propagation_traces.len(),
+      content_replacements: source_code_unit.content_replacements.len(),
+      rule_statistics: source_code_unit.rule_statistics.clone(),
+      timed_out_rule: source_code_unit.timed_out_rule.clone(),
+      vetoed_edits: source_code_unit.vetoed_edits.clone(),
+      exclusive_regions: source_code_unit.exclusive_regions.clone(),
+      line_range: source_code_unit.line_range,
+    }
+  }
+
+  fn restore(self, source_code_unit: &mut SourceCodeUnit) {
+    source_code_unit.ast = self.ast;
+    source_code_unit.code = self.code;
+    source_code_unit.substitutions = self.substitutions;
+    source_code_unit.rewrites.truncate(self.rewrites);
+    source_code_unit
+      .suggested_rewrites
+      .truncate(self.suggested_rewrites);
+    source_code_unit.matches.truncate(self.matches);
+    source_code_unit.match_ranges = self.match_ranges;
+    // The edits recorded before the snapshot may have been pruned since
+    match self
+      .input_edits
+      .checked_sub(source_code_unit.pruned_input_edits)
+    {
+      Some(input_edits) => source_code_unit.input_edits.truncate(input_edits),
+      None => {
+        source_code_unit.input_edits.clear();
+        source_code_unit.pruned_input_edits = self.input_edits;
+      }
+    }
+    source_code_unit.notes.truncate(self.notes);
+    source_code_unit
+      .propagation_traces
+      .truncate(self.propagation_traces);
+    source_code_unit
+      .content_replacements
+      .truncate(self.content_replacements);
+    source_code_unit.rule_statistics = self.rule_statistics;
+    source_code_unit.timed_out_rule = self.timed_out_rule;
+    source_code_unit.vetoed_edits = self.vetoed_edits;
+    source_code_unit.exclusive_regions = self.exclusive_regions;
+    source_code_unit.line_range = self.line_range;
+    // The lookups on the (restored) code are done again
+    source_code_unit.declared_package = OnceCell::new();
+    source_code_unit.suppressions = OnceCell::new();
+    source_code_unit.scope_matches = RefCell::default();
+  }
 }
 
 impl SourceCodeUnit {
@@ -75,20 +391,71 @@ impl SourceCodeUnit {
     piranha_arguments: &PiranhaArguments,
   ) -> Self {
     let ast = parser.parse(&code, None).expect("Could not parse code");
-    let source_code_unit = Self {
+    let line_ending = piranha_arguments
+      .normalize_line_endings()
+      .line_ending_for(&code);
+    let mut source_code_unit = Self {
       ast,
       original_content: code.to_string(),
       code,
       substitutions: substitutions.clone(),
       path: path.to_path_buf(),
       rewrites: Vec::new(),
+      suggested_rewrites: Vec::new(),
       matches: Vec::new(),
+      match_ranges: Vec::new(),
+      input_edits: Vec::new(),
+      pruned_input_edits: 0,
       piranha_arguments: piranha_arguments.clone(),
+      parse_errors: Vec::new(),
+      declared_package: OnceCell::new(),
+      suppressions: OnceCell::new(),
+      scope_matches: RefCell::default(),
+      disabled: false,
+      rule_statistics: HashMap::new(),
+      deadline: None,
+      timed_out_rule: None,
+      cancelled: None,
+      notes: Vec::new(),
+      propagation_traces: Vec::new(),
+      content_replacements: Vec::new(),
+      line_ending,
+      vetoed_edits: HashSet::new(),
+      exclusive_regions: Vec::new(),
+      transactional: false,
+      failure: None,
+      line_range: None,
+      propagate_outside_range: false,
+      propagation_depth: 0,
+      scheduled_rules: HashMap::new(),
     };
-    // Panic if allow dirty ast is false and the tree is syntactically incorrect
-    if !piranha_arguments.allow_dirty_ast() && source_code_unit._number_of_errors() > 0 {
-      error!("{}: {}", "Syntax Error".red(), path.to_str().unwrap().red());
-      _ = &source_code_unit._panic_for_syntax_error();
+    // Handle the syntactically incorrect tree as per `on_parse_error` (unless allow dirty ast is true)
+    if !piranha_arguments.allow_dirty_ast() && source_code_unit.root_node().has_error() {
+      match piranha_arguments.on_parse_error() {
+        OnParseError::Skip => {
+          info!("Skipping {:?} as it contains syntax errors", path);
+          source_code_unit.parse_errors = get_error_ranges(&source_code_unit.root_node())
+            .into_iter()
+            .map(matches::Range::from)
+            .collect();
+        }
+        OnParseError::Proceed => {}
+        OnParseError::Fail => {
+          error!("{}: {}", "Syntax Error".red(), path.to_str().unwrap().red());
+          source_code_unit._panic_for_syntax_error();
+        }
+      }
+    }
+    source_code_unit.disabled = is_file_disabled(
+      source_code_unit.root_node(),
+      source_code_unit.code(),
+      piranha_arguments.language().comment_nodes(),
+    );
+    if source_code_unit.disabled {
+      info!(
+        "Skipping {:?} as it is disabled by a `piranha:disable` comment",
+        path
+      );
     }
 
     source_code_unit
@@ -98,15 +465,105 @@ impl SourceCodeUnit {
     self.ast.root_node()
   }
 
+  /// A read-only view of this source code unit (see `SourceCodeView`), e.g. to inspect its syntax tree.
+  pub(crate) fn view(&self) -> SourceCodeView<'_> {
+    SourceCodeView::new(self)
+  }
+
+  /// Returns the package (or module, or namespace) declared in this source code unit, if any.
+  /// It is looked up (with the language's `package_query`) only once.
+  pub(crate) fn declared_package(&self, rule_store: &mut RuleStore) -> Option<&str> {
+    self
+      .declared_package
+      .get_or_init(|| {
+        let package_query = self
+          .piranha_arguments()
+          .language()
+          .package_query()
+          .clone()?;
+        get_match_for_query(
+          &self.root_node(),
+          self.code(),
+          rule_store.query(&package_query, self.piranha_arguments().language()),
+          true,
+        )
+        .and_then(|m| m.matches().get("package").cloned())
+      })
+      .as_deref()
+  }
+
+  /// Checks if the matches of the rule `rule_name` at the `range` are suppressed by a comment directive
+  /// (like `// piranha:disable-next-line`, see `suppression`).
+  pub(crate) fn is_suppressed(&self, rule_name: &str, range: Range) -> bool {
+    self
+      .suppressions
+      .get_or_init(|| {
+        get_suppressions(
+          self.root_node(),
+          self.code(),
+          self.piranha_arguments().language().comment_nodes(),
+          self.path(),
+        )
+      })
+      .iter()
+      .any(|s| s.suppresses(rule_name, range.start_byte, range.end_byte))
+  }
+
+  /// Checks if the matches of the `rule` at the `range` are allowed by the lines the rules are restricted to
+  /// (see `apply_rules_on_lines`), i.e. the match starts within them, unless the rule was triggered by an edge
+  /// and `propagate_outside_range` is set.
+  pub(crate) fn is_within_line_range(&self, rule: &InstantiatedRule, range: Range) -> bool {
+    let Some((start_byte, end_byte)) = self.line_range else {
+      return true;
+    };
+    (self.propagate_outside_range && rule.triggered_by().is_some())
+      || (start_byte <= range.start_byte && range.start_byte < end_byte)
+  }
+
+  /// Checks if no rule should be applied to this file because it contains syntax errors (see `OnParseError::Skip`).
+  pub(crate) fn is_skipped(&self) -> bool {
+    !self.parse_errors.is_empty()
+  }
+
+  /// Checks if no rule should be applied to this file because it is opted out by a leading `// piranha:disable` comment.
+  pub(crate) fn is_disabled(&self) -> bool {
+    self.disabled
+  }
+
+  /// Checks if the time budget for applying the rules to this source code unit (see `apply_rules_with_timeout`)
+  /// has expired, recording the `rule_name` being applied when it first does.
+  /// No more rules are applied either once an edit failed (see `apply_rules_transactional`).
+  fn has_timed_out(&mut self, rule_name: &str) -> bool {
+    if self.failure.is_some() || self.timed_out_rule.is_some() {
+      return true;
+    }
+    if self
+      .cancelled
+      .as_ref()
+      .is_some_and(|cancelled| cancelled.load(Ordering::Relaxed))
+    {
+      warn!(
+        "Timed out while applying the rule `{}` to {:?}. The remaining rules are not applied to it.",
+        rule_name, self.path
+      );
+      self.timed_out_rule = Some(rule_name.to_string());
+      return true;
+    }
+    false
+  }
+
   /// Will apply the `rule` to all of its occurrences in the source code unit.
   fn apply_rule(
     &mut self, rule: InstantiatedRule, rules_store: &mut RuleStore, parser: &mut Parser,
-    scope_query: &Option<CGPattern>,
+    scope_query: &Option<ScopeQuery>,
   ) {
+    let mut edited_since = None;
     loop {
-      if !self._apply_rule(rule.clone(), rules_store, parser, scope_query) {
+      let number_of_input_edits = self.number_of_applied_edits();
+      if !self._apply_rule(rule.clone(), rules_store, parser, scope_query, edited_since) {
         break;
       }
+      edited_since = Some(number_of_input_edits);
     }
   }
 
@@ -115,6 +572,7 @@ impl SourceCodeUnit {
   /// Parameters:
   /// * `rule` : the rule to be applied
   /// * `rule_store`: contains the input rule graph.
+  /// * `edited_since`: the number of edits applied to the source code unit when this rule was last applied (if any).
   ///
   /// Algorithm:
   /// * check if the rule is match only
@@ -131,26 +589,52 @@ impl SourceCodeUnit {
   /// *** Propagate the change
   fn _apply_rule(
     &mut self, rule: InstantiatedRule, rule_store: &mut RuleStore, parser: &mut Parser,
-    scope_query: &Option<CGPattern>,
+    scope_query: &Option<ScopeQuery>, edited_since: Option<usize>,
   ) -> bool {
-    let scope_node = self.get_scope_node(scope_query, rule_store);
+    if self.has_timed_out(&rule.name()) {
+      return false;
+    }
+    let scope_node = self.get_scope_node(scope_query.as_ref(), rule_store);
 
     let mut query_again = false;
+    // Only the time spent matching is recorded, as the propagation is recorded for the next rules
+    let start = Instant::now();
 
     // When rule is a "rewrite" rule :
     // Update the first match of the rewrite rule
     // Add mappings to the substitution
     // Propagate each applied edit. The next rule will be applied relative to the application of this edit.
     if !rule.rule().is_match_only_rule() {
-      if let Some(edit) = self.get_edit(&rule, rule_store, scope_node, true) {
+      let mut next_edit = self
+        .get_next_edit(&rule, rule_store, scope_node, edited_since)
+        .map(|edit| self.with_line_ending(edit));
+      let mut is_vetoed = false;
+      if let Some(edit) = next_edit.take() {
+        next_edit = self
+          .exclude_edit(edit, &rule, rule_store)
+          .and_then(|edit| self.suggest_edit(edit, &rule))
+          .and_then(|edit| self.intercept_edit(edit, rule_store));
+        is_vetoed = next_edit.is_none();
+      }
+      let rule_stat = self.rule_statistics.entry(rule.name()).or_default();
+      rule_stat.record_time(start.elapsed());
+      // The skipped (or suggested) edit is not found again, hence the rule is queried again for its next match.
+      // It is counted as a match of the rule when it is reported (see `count_skipped_matches` and `suggest_edit`).
+      if is_vetoed {
+        query_again = true;
+      }
+      if let Some(edit) = next_edit {
+        rule_stat.record_match(true);
         self.rewrites_mut().push(edit.clone());
+        self.record_exclusive_region(&edit, &rule);
         query_again = true;
 
         // Add all the (code_snippet, tag) mapping to the substitution table.
         self.substitutions.extend(edit.p_match().matches().clone());
 
         // Apply edit_1
-        let applied_ts_edit = self.apply_edit(&edit, parser);
+        let applied_ts_edit =
+          self.apply_edit_and_delete_empty_enclosing(&edit, &rule, rule_store, parser);
 
         self.propagate(get_replace_range(applied_ts_edit), rule, rule_store, parser);
       }
@@ -161,8 +645,26 @@ impl SourceCodeUnit {
     // Propagate each match. Note that,  we pass a identity edit (where old range == new range) in to the propagate logic.
     // The next edit will be applied relative to the identity edit.
     else {
-      for m in self.get_matches(&rule, rule_store, scope_node, true) {
-        self.matches_mut().push((rule.name(), m.clone()));
+      let matches = self.get_matches(&rule, rule_store, scope_node, true);
+      self
+        .rule_statistics
+        .entry(rule.name())
+        .or_default()
+        .record_time(start.elapsed());
+      for m in matches {
+        if self.is_duplicate_match(&rule.name(), &m) {
+          rule_store.report_skipped_match(
+            self.path(),
+            SkippedMatch::new(&rule.name(), SkipReason::DuplicateMatch, m.range()),
+          );
+        } else {
+          self.record_match(rule.name(), m.clone());
+          self
+            .rule_statistics
+            .entry(rule.name())
+            .or_default()
+            .record_match(false);
+        }
 
         // In this scenario we pass the match and replace range as the range of the match `m`
         // This is equivalent to propagating an identity rule
@@ -199,18 +701,50 @@ impl SourceCodeUnit {
     parser: &mut Parser,
   ) {
     let mut current_replace_range = replace_range;
+    // The outermost propagation starts a new propagation episode (see `add_rules_to_stack`)
+    if self.propagation_depth == 0 {
+      self.scheduled_rules.clear();
+    }
+    self.propagation_depth += 1;
 
     let mut current_rule = rule.name();
-    let mut next_rules_stack: VecDeque<(CGPattern, InstantiatedRule)> = VecDeque::new();
+    // The values of the holes of the rules applied along the chain (e.g. a single value of a substitution with several
+    // values, see `InstantiatedRule::new_for_each_value`), which take precedence over the substitution table when
+    // instantiating the next rules
+    let mut rule_substitutions = rule.substitutions().clone();
+    let mut next_rules_stack: VecDeque<(ScheduledRule, ScopeQuery, InstantiatedRule)> =
+      VecDeque::new();
+    // The "Parent" rules triggered along with the last applied cleanup (by the same rule), which are re-evaluated
+    // against the context of the cleanup
+    let mut sibling_rules: Vec<InstantiatedRule> = Vec::new();
+    // The edit that triggered the propagation, followed by the "Parent" cleanups applied so far
+    let mut chain = vec![(current_rule.clone(), replace_range)];
     // Perform the parent edits, while queueing the Method and Class level edits.
     // let file_level_scope_names = [METHOD, CLASS];
     loop {
+      if self.has_timed_out(&current_rule) {
+        break;
+      }
       debug!("Current Rule: {current_rule}");
       // Get all the (next) rules that could be after applying the current rule (`rule`).
-      let next_rules_by_scope = self
+      // Each of them records the current rule, the scope and the range that triggered it.
+      let mut substitutions = self.substitutions.clone();
+      substitutions.extend(rule_substitutions.clone());
+      let next_rules_by_scope: HashMap<String, Vec<InstantiatedRule>> = self
         .piranha_arguments
         .rule_graph()
-        .get_next(&current_rule, self.substitutions());
+        .get_next(&current_rule, &substitutions)
+        .into_iter()
+        .map(|(scope, rules)| {
+          let triggered_by = TriggeredBy::new(&current_rule, &scope, current_replace_range);
+          let rules = rules
+            .into_iter()
+            .filter(|r| rules_store.has_required_features(r))
+            .map(|r| r.with_triggered_by(triggered_by.clone()))
+            .collect_vec();
+          (scope, rules)
+        })
+        .collect();
 
       debug!(
         "\n{}",
@@ -238,13 +772,58 @@ impl SourceCodeUnit {
 
       // Process the parent
       // Find the rules to be applied in the "Parent" scope that match any parent (context) of the changed node in the previous edit
-      if let Some(edit) = self.get_edit_for_context(
-        current_replace_range.start_byte,
-        current_replace_range.end_byte,
-        rules_store,
+      // (the parent rules are recorded in the statistics even if they do not match)
+      for r in &next_rules_by_scope[PARENT] {
+        self.rule_statistics.entry(r.name()).or_default();
+      }
+      let parent_rules = self.merge_parent_rules(
         &next_rules_by_scope[PARENT],
-      ) {
+        std::mem::take(&mut sibling_rules),
+      );
+      // The parent rules are looked up one at a time, to record the time spent matching each of them
+      let mut next_edit = None;
+      for parent_rule in &parent_rules {
+        let start = Instant::now();
+        next_edit = self.get_edit_for_context(
+          current_replace_range.start_byte,
+          current_replace_range.end_byte,
+          rules_store,
+          std::slice::from_ref(parent_rule),
+        );
+        self
+          .rule_statistics
+          .entry(parent_rule.name())
+          .or_default()
+          .record_time(start.elapsed());
+        if next_edit.is_some() {
+          break;
+        }
+      }
+      let next_edit = next_edit.map(|edit| self.with_line_ending(edit));
+      // The cleanups go through the same steps as the edits of the seed rules (exclusive groups, `dry` rules and
+      // the edit interceptor). A skipped (or suggested) cleanup ends the chain.
+      if let Some((edit, parent_rule)) = next_edit.and_then(|edit| {
+        let parent_rule = parent_rules
+          .iter()
+          .find(|r| r.name() == *edit.matched_rule())?
+          .clone();
+        self
+          .exclude_edit(edit, &parent_rule, rules_store)
+          .and_then(|edit| self.suggest_edit(edit, &parent_rule))
+          .and_then(|edit| self.intercept_edit(edit, rules_store))
+          .map(|edit| (edit, parent_rule))
+      }) {
+        if chain.len() > *self.piranha_arguments.max_propagation_depth() {
+          self.note_truncated_chain(&chain, &edit);
+          break;
+        }
         self.rewrites_mut().push(edit.clone());
+        self.record_exclusive_region(&edit, &parent_rule);
+        self
+          .rule_statistics
+          .entry(edit.matched_rule().to_string())
+          .or_default()
+          .record_match(true);
         debug!(
           "\n{}",
           format!(
@@ -254,9 +833,13 @@ impl SourceCodeUnit {
           .green()
         );
         // Apply the matched rule to the parent
-        let applied_edit = self.apply_edit(&edit, parser);
+        let applied_edit =
+          self.apply_edit_and_delete_empty_enclosing(&edit, &parent_rule, rules_store, parser);
         current_replace_range = get_replace_range(applied_edit);
         current_rule = edit.matched_rule().to_string();
+        rule_substitutions.extend(parent_rule.substitutions().clone());
+        sibling_rules = get_sibling_rules(&parent_rules, &parent_rule);
+        chain.push((current_rule.clone(), edit.p_match().range()));
         // Add the (tag, code_snippet) mapping to substitution table.
         self.substitutions.extend(edit.p_match().matches().clone());
       } else {
@@ -264,100 +847,917 @@ impl SourceCodeUnit {
         break;
       }
     }
+    if *self.piranha_arguments.trace_propagation() && chain.len() > 1 {
+      self.propagation_traces.push(
+        chain
+          .into_iter()
+          .map(|(rule, range)| (rule, matches::Range::from(range)))
+          .collect(),
+      );
+    }
 
     // Apply the next rules from the stack
-    for (sq, rle) in &next_rules_stack {
+    for (key, sq, rle) in &next_rules_stack {
       self.apply_rule(rle.clone(), rules_store, parser, &Some(sq.clone()));
+      // The range of the scope node may have changed (e.g. the rule deleted some of its code)
+      self.scheduled_rules.remove(key);
+      let key = self.scheduled_rule(rle, sq, rules_store);
+      let applied_edits = self.number_of_applied_edits();
+      self.scheduled_rules.insert(key, Some(applied_edits));
     }
+    self.propagation_depth -= 1;
+  }
+
+  /// Records (and logs) that the `chain` of "Parent" cleanups was stopped before applying the `next_edit`,
+  /// since it exceeded the `max_propagation_depth` (e.g. the rules rewrite each other back and forth).
+  fn note_truncated_chain(&mut self, chain: &[(String, Range)], next_edit: &Edit) {
+    let max_propagation_depth = *self.piranha_arguments.max_propagation_depth();
+    let trace = chain
+      .iter()
+      .chain(std::iter::once(&(
+        next_edit.matched_rule().to_string(),
+        next_edit.p_match().range(),
+      )))
+      .map(|(rule, range)| {
+        format!(
+          "`{rule}` ({}:{}-{}:{})",
+          range.start_point.row + 1,
+          range.start_point.column + 1,
+          range.end_point.row + 1,
+          range.end_point.column + 1
+        )
+      })
+      .join(" -> ");
+    warn!(
+      "Stopped the cleanup chain in {:?} after {max_propagation_depth} \"Parent\" cleanup(s): {trace}",
+      self.path
+    );
+    self.notes.push(format!(
+      "Stopped the cleanup chain triggered by the rule `{}` after {max_propagation_depth} \"Parent\" cleanup(s) (see `max_propagation_depth`): {trace}",
+      chain[0].0
+    ));
+  }
+
+  /// The "Parent" rules to match against the context of the last edit: the `next_rules` (triggered by the last
+  /// applied rule) along with the `sibling_rules` (triggered along with the last applied cleanup), ordered by their
+  /// declaration in the rule graph. Hence, a "Parent" rule is still applied after a sibling rule (triggered by the
+  /// same rule) changed the context, and the rules are tried in the same order regardless of which rule triggered them.
+  fn merge_parent_rules(
+    &self, next_rules: &[InstantiatedRule], sibling_rules: Vec<InstantiatedRule>,
+  ) -> Vec<InstantiatedRule> {
+    let mut parent_rules = next_rules.to_vec();
+    for rule in sibling_rules {
+      if !parent_rules.iter().any(|r| is_same_variant(r, &rule)) {
+        parent_rules.push(rule);
+      }
+    }
+    let names: HashSet<String> = parent_rules.iter().map(|r| r.name()).collect();
+    let mut positions: HashMap<&String, usize> = HashMap::new();
+    for (position, rule) in self
+      .piranha_arguments
+      .rule_graph()
+      .rules()
+      .iter()
+      .enumerate()
+    {
+      if names.contains(rule.name()) {
+        positions.entry(rule.name()).or_insert(position);
+      }
+    }
+    parent_rules.sort_by_key(|r| positions.get(&r.name()).copied());
+    parent_rules
   }
 
   /// Adds the "Method" and "Class" scoped next rules to the queue.
+  /// A rule is not added again for the same scope node within a propagation episode (e.g. when two branches of a
+  /// diamond-shaped rule graph lead to it) if it is still pending, or if it was applied and no edit was applied since.
+  /// Nor is it added for a scope node enclosed by another of its scope nodes (e.g. the `Class` and `File` scopes of
+  /// an edge). These suppressed duplicates are counted in the statistics of the rule.
   fn add_rules_to_stack(
     &mut self, next_rules_by_scope: &HashMap<String, Vec<InstantiatedRule>>,
     current_match_range: Range, rules_store: &mut RuleStore,
-    stack: &mut VecDeque<(CGPattern, InstantiatedRule)>,
+    stack: &mut VecDeque<(ScheduledRule, ScopeQuery, InstantiatedRule)>,
   ) {
+    // The rules of the scopes other than "Parent" and "Global", along with their scope query
+    let mut scheduled_rules = vec![];
     for (scope_level, rules) in next_rules_by_scope {
-      // Scope level is not "PArent" or "Global"
-      if ![PARENT, GLOBAL].contains(&scope_level.as_str()) {
-        for rule in rules {
-          let scope_query = self.get_scope_query(
-            scope_level,
-            current_match_range.start_byte,
-            current_match_range.end_byte,
-            rules_store,
-          );
-          // Add Method and Class scoped rules to the queue
-          stack.push_front((scope_query, rule.clone()));
-        }
+      if [PARENT, GLOBAL].contains(&scope_level.as_str()) {
+        continue;
+      }
+      for rule in rules {
+        let scope_query = self.get_scope_query(
+          scope_level,
+          current_match_range.start_byte,
+          current_match_range.end_byte,
+          rules_store,
+        );
+        let key = self.scheduled_rule(rule, &scope_query, rules_store);
+        scheduled_rules.push((key, scope_query, rule));
       }
     }
+    for (key, scope_query, rule) in &scheduled_rules {
+      // The scopes of an edge may overlap (e.g. the `Class` and the `File`). The rule is then only applied in the
+      // enclosing scope node, which contains the matches of the enclosed one (i.e. they are not matched twice).
+      let (name, substitutions, scope_range) = key;
+      let is_enclosed = scheduled_rules.iter().any(|((n, s, r), _, _)| {
+        n == name
+          && s == substitutions
+          && r != scope_range
+          && r.start <= scope_range.start
+          && scope_range.end <= r.end
+      });
+      // The rule is added only once per scope node (i.e. it is not applied twice to the same scope).
+      let is_duplicate = is_enclosed
+        || match self.scheduled_rules.get(key) {
+          Some(None) => true,
+          Some(Some(applied_edits)) => *applied_edits == self.number_of_applied_edits(),
+          None => false,
+        };
+      if is_duplicate {
+        self
+          .rule_statistics
+          .entry(rule.name())
+          .or_default()
+          .record_suppressed_duplicate();
+        continue;
+      }
+      self.scheduled_rules.insert(key.clone(), None);
+      // Add Method and Class scoped rules to the queue
+      stack.push_front((key.clone(), scope_query.clone(), (*rule).clone()));
+    }
   }
 
-  fn get_scope_node(&self, scope_query: &Option<CGPattern>, rules_store: &mut RuleStore) -> Node {
-    // Get scope node
-    // let mut scope_node = self.root_node();
-    if let Some(query_str) = scope_query {
-      // Apply the scope query in the source code and get the appropriate node
-      let tree_sitter_scope_query = rules_store.query(query_str);
-      if let Some(p_match) = get_match_for_query(
-        &self.root_node(),
-        self.code(),
-        tree_sitter_scope_query,
-        true,
-      ) {
-        return get_node_for_range(
-          self.root_node(),
-          p_match.range().start_byte,
-          p_match.range().end_byte,
-        );
+  /// The key of the `rule` scheduled for the `scope_query` in a propagation episode (see `add_rules_to_stack`),
+  /// i.e. the rule along with the current byte range of its scope node.
+  fn scheduled_rule(
+    &self, rule: &InstantiatedRule, scope_query: &ScopeQuery, rules_store: &mut RuleStore,
+  ) -> ScheduledRule {
+    let substitutions = rule
+      .substitutions()
+      .iter()
+      .map(|(k, v)| (k.to_string(), v.to_string()))
+      .sorted()
+      .collect_vec();
+    let scope_range = self
+      .get_scope_node(Some(scope_query), rules_store)
+      .byte_range();
+    (rule.name(), substitutions, scope_range)
+  }
+
+  /// Gets the next edit for the `rule` within the `scope_node`.
+  /// If the rule was previously applied (i.e. `edited_since` is provided), it first looks for the match
+  /// in the smallest node enclosing the code changed since then (and `rematch_slack` of its named ancestors).
+  /// It falls back to querying the entire `scope_node` if no match is found there.
+  fn get_next_edit(
+    &self, rule: &InstantiatedRule, rule_store: &mut RuleStore, scope_node: Node,
+    edited_since: Option<usize>,
+  ) -> Option<Edit> {
+    if let Some(edited_node) = edited_since.and_then(|idx| self.get_edited_node(idx, scope_node)) {
+      if let Some(edit) = self.get_edit(rule, rule_store, edited_node, true) {
+        return Some(edit);
+      }
+    }
+    self.get_edit(rule, rule_store, scope_node, true)
+  }
+
+  /// Returns the `rematch_slack`-th named ancestor of the smallest node enclosing the code changed by the
+  /// edits applied after the first `edited_since` edits.
+  /// Returns `None` if there is no such change (or the edits were pruned) or if the node is not strictly within the `scope_node`.
+  fn get_edited_node<'a>(&'a self, edited_since: usize, scope_node: Node<'a>) -> Option<Node<'a>> {
+    let (start_byte, end_byte) = get_changed_range(self.get_input_edits_since(edited_since)?)?;
+    let mut edited_node = get_node_for_range(self.root_node(), start_byte, end_byte);
+    for _ in 0..*self.piranha_arguments.rematch_slack() {
+      match edited_node.parent() {
+        Some(parent) => edited_node = parent,
+        None => break,
+      }
+    }
+    let is_within_scope = scope_node.start_byte() <= edited_node.start_byte()
+      && edited_node.end_byte() <= scope_node.end_byte()
+      && edited_node.byte_range() != scope_node.byte_range();
+    is_within_scope.then_some(edited_node)
+  }
+
+  /// Checks if an equivalent match (w.r.t. `whitespace_sensitivity`) was already recorded for the rule at the same location.
+  fn is_duplicate_match(&self, rule_name: &str, p_match: &Match) -> bool {
+    self.matches().iter().any(|(name, m)| {
+      name == rule_name
+        && m.range().start_byte == p_match.range().start_byte
+        && self.are_equivalent_snippets(m.matched_string(), p_match.matched_string())
+    })
+  }
+
+  /// Gets the scope node of the `scope_query` (or the root node if there is none), i.e. the node matching the scope
+  /// query selected per its `ScopeStrategy`: the first one, or the innermost (or outermost) one enclosing the edit
+  /// that triggered the rule. It falls back to the first match if none of the matches encloses the edit.
+  pub(crate) fn get_scope_node(
+    &self, scope_query: Option<&ScopeQuery>, rules_store: &mut RuleStore,
+  ) -> Node {
+    if let Some(scope_query) = scope_query {
+      // Apply the scope query in the source code (unless it was already applied since the last edit)
+      let mut scope_matches = self.scope_matches.borrow_mut();
+      let candidates = scope_matches
+        .entry(scope_query.query().clone())
+        .or_insert_with(|| {
+          let tree_sitter_scope_query =
+            rules_store.query(scope_query.query(), self.piranha_arguments().language());
+          get_all_matches_for_query(
+            &self.root_node(),
+            self.code().to_string(),
+            tree_sitter_scope_query,
+            true,
+            None,
+            None,
+          )
+          .iter()
+          .map(|m| (m.range().start_byte, m.range().end_byte))
+          .collect()
+        });
+      let (start_byte, end_byte) = self.shift_origin(*scope_query.origin());
+      let size = |(start, end): &&(usize, usize)| end - start;
+      let enclosing = candidates
+        .iter()
+        .filter(|(start, end)| *start <= start_byte && end_byte <= *end);
+      let selected = match scope_query.strategy() {
+        ScopeStrategy::FirstMatch => None,
+        ScopeStrategy::NearestEnclosing => enclosing.min_by_key(size),
+        ScopeStrategy::OutermostEnclosing => enclosing.max_by_key(size),
+      };
+      if let Some((start, end)) = selected.or(candidates.first()) {
+        return get_node_for_range(self.root_node(), *start, *end);
       }
     }
     self.root_node()
   }
 
-  /// Apply all `rules` sequentially.
+  /// The byte range of the edit that triggered a rule (see `ScopeQuery::origin`) following the edits applied since.
+  /// An edit overlapping the range extends it to the replacement.
+  /// The range is not shifted if the edits applied since were pruned.
+  fn shift_origin(
+    &self, (start_byte, end_byte, applied_edits): (usize, usize, usize),
+  ) -> (usize, usize) {
+    let Some(input_edits) = self.get_input_edits_since(applied_edits) else {
+      return (start_byte, end_byte);
+    };
+    input_edits
+      .iter()
+      .fold((start_byte, end_byte), |range, edit| {
+        shift_range(range, edit, Overlap::Extend).unwrap_or(range)
+      })
+  }
+
+  /// The number of edits applied to this source code unit so far.
+  pub(crate) fn number_of_applied_edits(&self) -> usize {
+    self.pruned_input_edits + self.input_edits.len()
+  }
+
+  /// The edits applied after the first `applied_edits` ones, or `None` if some of them were pruned.
+  fn get_input_edits_since(&self, applied_edits: usize) -> Option<&[InputEdit]> {
+    self
+      .input_edits
+      .get(applied_edits.checked_sub(self.pruned_input_edits)?..)
+  }
+
+  /// Drops the recorded edits. They are only referenced while applying a rule (and the rules it triggers),
+  /// so keeping them across the rules would grow the memory with the number of edits applied to the file.
+  fn prune_input_edits(&mut self) {
+    self.pruned_input_edits += self.input_edits.len();
+    self.input_edits.clear();
+  }
+
+  /// Apply all `rules` sequentially (unless the file is disabled, see `is_disabled`).
   pub(crate) fn apply_rules(
     &mut self, rules_store: &mut RuleStore, rules: &[InstantiatedRule], parser: &mut Parser,
-    scope_query: Option<CGPattern>,
+    scope_query: Option<ScopeQuery>,
   ) {
+    if self.disabled {
+      return;
+    }
     for rule in rules {
+      // The edits are still referenced by the origin of the `scope_query`, if any
+      if scope_query.is_none() {
+        self.prune_input_edits();
+      }
       self.apply_rule(rule.to_owned(), rules_store, parser, &scope_query)
     }
     self.perform_delete_consecutive_new_lines();
+    self.perform_normalize_line_endings(parser);
+  }
+
+  /// Applies the `rules` like `apply_rules`, but all or nothing. If any edit fails (i.e. produces syntactically
+  /// incorrect code), the code, AST, rewrites, matches and substitutions of this source code unit are restored
+  /// as they were before applying the rules, and the failure is returned.
+  /// The rules are applied within the remaining time budget of this source code unit (see `per_file_timeout_seconds`),
+  /// whose expiry is reported in its summary.
+  /// Note that the side effects on the `rules_store` (e.g. the collected global rules) are not rolled back.
+  pub(crate) fn apply_rules_transactional(
+    &mut self, rules_store: &mut RuleStore, rules: &[InstantiatedRule], parser: &mut Parser,
+  ) -> Result<(), PiranhaError> {
+    let snapshot = Snapshot::new(self);
+    self.transactional = true;
+    match self.remaining_time_budget() {
+      Some(timeout) => {
+        if let Err(e) = self.apply_rules_with_timeout(rules_store, rules, parser, timeout) {
+          self.timed_out_rule = Some(e.rule);
+        }
+      }
+      None => self.apply_rules(rules_store, rules, parser, None),
+    }
+    self.transactional = false;
+    if let Some(failure) = self.failure.take() {
+      snapshot.restore(self);
+      return Err(failure);
+    }
+    Ok(())
+  }
+
+  /// The time left for applying the rules to this source code unit, if it is limited (see `per_file_timeout_seconds`).
+  /// The time budget starts when the rules are first applied to this source code unit.
+  fn remaining_time_budget(&mut self) -> Option<Duration> {
+    let seconds = (*self.piranha_arguments.per_file_timeout_seconds())?;
+    let deadline = *self
+      .deadline
+      .get_or_insert_with(|| Instant::now() + Duration::from_secs(seconds));
+    Some(deadline.saturating_duration_since(Instant::now()))
+  }
+
+  /// Reports that applying the rules to this source code unit failed (and was rolled back) in its summary.
+  pub(crate) fn record_failure(&mut self, failure: &PiranhaError) {
+    self.notes.push(format!(
+      "{failure}: the rules applied to the file were rolled back"
+    ));
+  }
+
+  /// Applies the `rules` like `apply_rules`, but stops applying them once the `timeout` (wall-clock) elapsed, e.g. for
+  /// the pathological rules or the huge files. A background thread sets a cancellation flag after the `timeout`, which
+  /// is checked before each match (and within the propagation of the edits). The edits applied before are kept.
+  pub(crate) fn apply_rules_with_timeout(
+    &mut self, rules_store: &mut RuleStore, rules: &[InstantiatedRule], parser: &mut Parser,
+    timeout: Duration,
+  ) -> Result<(), TimeoutError> {
+    // An expired time budget cancels the rules upfront, rather than racing the watchdog
+    let cancelled = Arc::new(AtomicBool::new(timeout.is_zero()));
+    let (done, finished) = mpsc::channel::<()>();
+    let watchdog = {
+      let cancelled = cancelled.clone();
+      thread::spawn(move || {
+        // The rules were applied in time if the channel is closed before the timeout
+        if finished.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout) {
+          cancelled.store(true, Ordering::Relaxed);
+        }
+      })
+    };
+    let result = self.apply_rules_until_cancelled(rules_store, rules, parser, &cancelled);
+    drop(done);
+    let _ = watchdog.join();
+    result.map_err(|rule| TimeoutError { timeout, rule })
+  }
+
+  /// Applies the `rules` like `apply_rules`, until the `cancelled` flag is set (e.g. by the watchdog of
+  /// `apply_rules_with_timeout`). Returns the name of the rule being applied when it was cancelled (if it was).
+  fn apply_rules_until_cancelled(
+    &mut self, rules_store: &mut RuleStore, rules: &[InstantiatedRule], parser: &mut Parser,
+    cancelled: &Arc<AtomicBool>,
+  ) -> Result<(), String> {
+    let timed_out_before = self.timed_out_rule.is_some();
+    self.cancelled = Some(cancelled.clone());
+    self.apply_rules(rules_store, rules, parser, None);
+    self.cancelled = None;
+    // The cancellation is reported to the caller (rather than in the output summary), who may still apply other rules
+    if !timed_out_before && cancelled.load(Ordering::Relaxed) {
+      if let Some(rule) = self.timed_out_rule.take() {
+        return Err(rule);
+      }
+    }
+    Ok(())
+  }
+
+  /// Applies the `rules` like `apply_rules`, but only to the matches starting within the lines `start_line` to
+  /// `end_line` (inclusive, 0-based like the rows of tree-sitter), e.g. the lines changed in a pull request.
+  /// The matches of the rules triggered by an edge (e.g. the cleanup of a deleted flag check) are restricted too,
+  /// unless `propagate_outside_range` is set. The lines are tracked across the edits (e.g. a deleted line within
+  /// the range does not bring the next line into it). The other matches are skipped as `outside_line_range`.
+  pub(crate) fn apply_rules_on_lines(
+    &mut self, rules_store: &mut RuleStore, rules: &[InstantiatedRule], parser: &mut Parser,
+    start_line: usize, end_line: usize, propagate_outside_range: bool,
+  ) {
+    let line_map = LineMap::new(self.code());
+    let byte_offset = |line: usize| line_map.offset(line, 0).unwrap_or(self.code().len());
+    self.line_range = Some((byte_offset(start_line), byte_offset(end_line + 1)));
+    self.propagate_outside_range = propagate_outside_range;
+    self.apply_rules(rules_store, rules, parser, None);
+    self.line_range = None;
+    self.propagate_outside_range = false;
+  }
+
+  /// Renames the symbol `old_name` to `new_name`, i.e. replaces each identifier node whose text is `old_name`
+  /// (regardless of its declaration or scope), without writing a rule for it.
+  /// Returns the number of renamed identifiers.
+  pub(crate) fn refactor_rename_symbol(
+    &mut self, old_name: &str, new_name: &str, parser: &mut Parser, rule_store: &mut RuleStore,
+  ) -> usize {
+    let identifier_nodes = self.piranha_arguments.language().identifier_nodes();
+    if old_name == new_name || identifier_nodes.is_empty() {
+      return 0;
+    }
+    // The name is escaped in the string literal of the `#eq?` predicate
+    let query = instantiate_query(
+      &format!(
+        "([{}] @symbol (#eq? @symbol \"@old_name\"))",
+        identifier_nodes
+          .iter()
+          .map(|kind| format!("({kind})"))
+          .join(" ")
+      ),
+      &HashMap::from([("old_name".to_string(), old_name.to_string())]),
+    );
+    // A match-only rule: `new_name` is inserted verbatim (i.e. it is not instantiated like a `replace` template)
+    let rule_name = format!("Rename {old_name} to {new_name}");
+    let rule = piranha_rule! {
+      name = &rule_name,
+      query = &query
+    };
+    let matches = self.get_matches(
+      &InstantiatedRule::new(&rule, &HashMap::new()),
+      rule_store,
+      self.root_node(),
+      true,
+    );
+    // Rename from the last identifier to the first, so that the ranges of the pending matches stay valid
+    let matches = matches
+      .into_iter()
+      .sorted_by_key(|m| std::cmp::Reverse(m.range().start_byte))
+      .collect_vec();
+    for p_match in &matches {
+      let edit = Edit::new(
+        p_match.clone(),
+        new_name.to_string(),
+        rule_name.clone(),
+        self.code(),
+      );
+      self.rewrites_mut().push(edit.clone());
+      self.apply_rule_edit(&edit, parser);
+    }
+    matches.len()
   }
 
   /// Applies an edit to the source code unit
   /// # Arguments
-  /// * `replace_range` - the range of code to be replaced
-  /// * `replacement_str` - the replacement string
+  /// * `edit` - the range of code to be replaced, along with the replacement string
   /// * `parser`
   ///
   /// # Returns
-  /// The `edit:InputEdit` performed.
+  /// The `edit:InputEdit` performed, or an error if the `edit_interceptor` skipped the edit (which is then not
+  /// applied), or if the edit produced syntactically incorrect code (the edit is applied nonetheless).
+  /// The `edit_interceptor` may also replace the edit.
   ///
   /// Note - Causes side effect. - Updates `self.ast` and `self.code`
-  pub(crate) fn apply_edit(&mut self, edit: &Edit, parser: &mut Parser) -> InputEdit {
+  pub(crate) fn apply_edit(
+    &mut self, edit: &Edit, parser: &mut Parser,
+  ) -> Result<InputEdit, String> {
+    let Some(edit) = self.consult_edit_interceptor(edit.clone()) else {
+      return Err(format!(
+        "The edit was skipped by the edit interceptor: {edit}"
+      ));
+    };
+    let (ts_edit, has_new_errors) = self._apply_edit(&edit, parser);
+    if has_new_errors {
+      return Err(self._syntax_error_message());
+    }
+    Ok(ts_edit)
+  }
+
+  /// Applies the `edit` of a rule (see `apply_edit`).
+  /// Panics if the edit produced syntactically incorrect code, unless the rules are applied transactionally,
+  /// in which case the failure is recorded (and the source code unit is restored by `apply_rules_transactional`).
+  fn apply_rule_edit(&mut self, edit: &Edit, parser: &mut Parser) -> InputEdit {
+    let (ts_edit, has_new_errors) = self._apply_edit(edit, parser);
+    if has_new_errors {
+      if !self.transactional {
+        self._panic_for_syntax_error();
+      }
+      if self.failure.is_none() {
+        self.failure = Some(PiranhaError::SyntaxError {
+          rule: edit.matched_rule().to_string(),
+          path: self.path.clone(),
+        });
+      }
+    }
+    ts_edit
+  }
+
+  /// Applies the `edit`, and returns the `InputEdit` performed along with whether the number of syntax errors increased.
+  fn _apply_edit(&mut self, edit: &Edit, parser: &mut Parser) -> (InputEdit, bool) {
     // Get the tree_sitter's input edit representation
     let (new_source_code, ts_edit) = get_tree_sitter_edit(self.code.clone(), edit);
     // Apply edit to the tree
     let number_of_errors = self._number_of_errors();
     self.ast.edit(&ts_edit);
     self._replace_file_contents_and_re_parse(&new_source_code, parser, true);
+    self.record_input_edit(ts_edit);
+    (ts_edit, self._number_of_errors() > number_of_errors)
+  }
+
+  /// Records the (tree-sitter) `ts_edit` applied to the code, and shifts the ranges tracked across the edits.
+  fn record_input_edit(&mut self, ts_edit: InputEdit) {
+    self.input_edits.push(ts_edit);
+    self.update_vetoed_edits(&ts_edit);
+    self.update_exclusive_regions(&ts_edit);
+    self.update_line_range(&ts_edit);
+    self.update_suggested_rewrites(&ts_edit);
+    self.update_match_ranges(&ts_edit);
+  }
 
-    // Panic if the number of errors increased after the edit
-    if self._number_of_errors() > number_of_errors {
-      self._panic_for_syntax_error();
+  /// Replaces the code between the byte offsets `start_byte` and `end_byte` with the `replacement`.
+  /// Meant for the edits computed outside of Piranha (e.g. by an external analysis tool), which are not
+  /// driven by a rule. The edit is reported among the rewrites of this source code unit.
+  /// Returns an error if the offsets do not denote a valid range of the code, if the `edit_interceptor` skipped the
+  /// edit, or if the edit produced syntactically incorrect code, in which case the source code unit is left as it was.
+  pub(crate) fn apply_edit_at_offset(
+    &mut self, start_byte: usize, end_byte: usize, replacement: &str, parser: &mut Parser,
+  ) -> Result<InputEdit, String> {
+    if start_byte > end_byte || end_byte > self.code.len() {
+      return Err(format!(
+        "Invalid range {start_byte}..{end_byte} for code of {} bytes",
+        self.code.len()
+      ));
     }
-    ts_edit
+    if !self.code.is_char_boundary(start_byte) || !self.code.is_char_boundary(end_byte) {
+      return Err(format!(
+        "Invalid range {start_byte}..{end_byte} (not on a character boundary)"
+      ));
+    }
+    let edit = self.with_line_ending(Edit::replace_offsets(
+      &self.code,
+      start_byte,
+      end_byte,
+      replacement,
+    ));
+    // The edit (or its replacement by the `edit_interceptor`) is reported among the rewrites once applied
+    let Some(edit) = self.consult_edit_interceptor(edit) else {
+      return Err(format!(
+        "The edit of {start_byte}..{end_byte} was skipped by the edit interceptor"
+      ));
+    };
+    let snapshot = Snapshot::new(self);
+    let (ts_edit, has_new_errors) = self._apply_edit(&edit, parser);
+    if has_new_errors {
+      let error = self._syntax_error_message();
+      snapshot.restore(self);
+      return Err(error);
+    }
+    self.rewrites.push(edit);
+    Ok(ts_edit)
+  }
+
+  /// Deletes the comments matching one of the `patterns` (see `PiranhaArguments::comment_deletion_patterns`),
+  /// e.g. `// TODO: remove when STALE_FLAG launches`, along with their line if they are alone on it.
+  /// Each deletion (of the comment, along with the whitespace around it) is reported as a rewrite of the
+  /// `COMMENT_DELETION_RULE` pseudo-rule.
+  /// The consecutive new lines left behind are then deleted (see `delete_consecutive_new_lines`).
+  pub(crate) fn perform_comment_deletion(&mut self, patterns: &[Regex], parser: &mut Parser) {
+    let comment_nodes = self.piranha_arguments.language().comment_nodes();
+    let comment_ranges = traverse(self.root_node().walk(), Order::Pre)
+      .filter(|n| comment_nodes.iter().any(|kind| kind == n.kind()))
+      .filter(|n| {
+        let row = n.start_position().row;
+        let is_protected = row <= 1
+          && self
+            .code
+            .lines()
+            .nth(row)
+            .is_some_and(|line| is_protected_header_line(line, row));
+        !is_protected
+          && patterns
+            .iter()
+            .any(|p| p.is_match(&self.code[n.byte_range()]))
+      })
+      .map(|n| (n.start_byte(), n.end_byte()))
+      .collect_vec();
+    if comment_ranges.is_empty() {
+      return;
+    }
+    // Delete the comments from the last one, so that the offsets of the others remain valid.
+    // The whitespace around each comment is looked up in the current code, since it may have been deleted
+    // along with the next comment (e.g. `/* a */ /* b */`).
+    for (start_byte, end_byte) in comment_ranges.into_iter().rev() {
+      let (start, end) = get_comment_deletion_range(&self.code, start_byte, end_byte);
+      let edit = Edit::delete_offsets(&self.code, start, end, COMMENT_DELETION_RULE);
+      self
+        .rule_statistics
+        .entry(COMMENT_DELETION_RULE.to_string())
+        .or_default()
+        .record_match(true);
+      self
+        .piranha_arguments
+        .report_progress(ProgressEvent::RuleApplied {
+          rule: COMMENT_DELETION_RULE.to_string(),
+          path: self.path().clone(),
+        });
+      self.rewrites_mut().push(edit.clone());
+      self.apply_rule_edit(&edit, parser);
+    }
+    self.perform_delete_consecutive_new_lines();
+  }
+
+  /// Re-emits the new lines of the `edit`'s replacement with the line ending of this source code unit,
+  /// so that rewriting a file with `\r\n` line endings does not mix them with `\n` (and vice versa).
+  fn with_line_ending(&self, mut edit: Edit) -> Edit {
+    if edit.replacement_string().contains('\n') {
+      *edit.replacement_string_mut() =
+        with_line_ending(edit.replacement_string(), self.line_ending);
+    }
+    edit
+  }
+
+  /// Consults the `edit_interceptor` (if any) about the `edit` of a rule, and returns the edit to apply (if any).
+  /// The skipped edits are reported as skipped matches (see `consult_edit_interceptor`).
+  fn intercept_edit(&mut self, edit: Edit, rule_store: &mut RuleStore) -> Option<Edit> {
+    let (rule_name, range) = (edit.matched_rule().to_string(), edit.p_match().range());
+    let edit = self.consult_edit_interceptor(edit);
+    if edit.is_none() {
+      rule_store.report_skipped_match(
+        self.path(),
+        SkippedMatch::new(&rule_name, SkipReason::VetoedByInterceptor, range),
+      );
+    }
+    edit
+  }
+
+  /// Consults the `edit_interceptor` (if any) about the `edit`, and returns the edit to apply (if any).
+  /// The skipped edits are recorded, so that their rule is not applied again at the same range (see `is_vetoed_edit`).
+  fn consult_edit_interceptor(&mut self, edit: Edit) -> Option<Edit> {
+    let Some(interceptor) = self.piranha_arguments.edit_interceptor().clone() else {
+      return Some(edit);
+    };
+    match interceptor.on_edit(self.path(), &edit) {
+      EditDecision::Apply => Some(edit),
+      EditDecision::Replace(replacement) => Some(*replacement),
+      EditDecision::Skip => {
+        debug!("The edit was skipped by the interceptor {}", edit);
+        let range = edit.p_match().range();
+        self.vetoed_edits.insert((
+          edit.matched_rule().to_string(),
+          range.start_byte,
+          range.end_byte,
+        ));
+        None
+      }
+    }
+  }
+
+  /// Checks if an edit of the rule at the same range was skipped by the `edit_interceptor`.
+  pub(crate) fn is_vetoed_edit(&self, edit: &Edit) -> bool {
+    let range = edit.p_match().range();
+    !self.vetoed_edits.is_empty()
+      && self.vetoed_edits.contains(&(
+        edit.matched_rule().to_string(),
+        range.start_byte,
+        range.end_byte,
+      ))
+  }
+
+  /// Shifts the ranges of the skipped edits following the (tree-sitter) `edit`, and forgets the ones it overlaps
+  /// (their code changed).
+  fn update_vetoed_edits(&mut self, edit: &InputEdit) {
+    if self.vetoed_edits.is_empty() {
+      return;
+    }
+    self.vetoed_edits = self
+      .vetoed_edits
+      .drain()
+      .filter_map(|(rule, start_byte, end_byte)| {
+        let (start_byte, end_byte) = shift_range((start_byte, end_byte), edit, Overlap::Drop)?;
+        Some((rule, start_byte, end_byte))
+      })
+      .collect();
+  }
+
+  /// Skips the `edit` if its rule belongs to an `exclusive_group`, and another rule of the group already rewrote
+  /// the code it matched. The skipped attempt is reported in the notes (as it usually reveals overlapping rules),
+  /// and recorded like the edits vetoed by the `edit_interceptor`, so that the rule is not applied there again.
+  fn exclude_edit(
+    &mut self, edit: Edit, rule: &InstantiatedRule, rule_store: &mut RuleStore,
+  ) -> Option<Edit> {
+    let group = rule.rule().exclusive_group();
+    if group.is_empty() {
+      return Some(edit);
+    }
+    let range = edit.p_match().range();
+    let (start_byte, end_byte) = (range.start_byte, range.end_byte);
+    let Some((_, other_rule, _, _)) = self.exclusive_regions.iter().find(|(g, r, start, end)| {
+      let overlaps = if start_byte == end_byte || start == end {
+        start_byte <= *end && *start <= end_byte
+      } else {
+        start_byte < *end && *start < end_byte
+      };
+      g == group && *r != rule.name() && overlaps
+    }) else {
+      return Some(edit);
+    };
+    let note = format!(
+      "The rule `{}` was not applied at {}:{}, since the rule `{}` of the same exclusive group `{}` already rewrote this code.",
+      rule.name(),
+      range.start_point.row + 1,
+      range.start_point.column + 1,
+      other_rule,
+      group
+    );
+    warn!("{} ({:?})", note, self.path());
+    self.notes.push(note);
+    rule_store.report_skipped_match(
+      self.path(),
+      SkippedMatch::new(&rule.name(), SkipReason::ExclusiveGroup, range),
+    );
+    self
+      .vetoed_edits
+      .insert((rule.name(), start_byte, end_byte));
+    None
+  }
+
+  /// Records the `edit` of a `dry` rule among the suggested rewrites instead of applying it (hence it triggers no
+  /// propagation), and skips it like a vetoed edit, so that the rule moves on to its next match.
+  fn suggest_edit(&mut self, edit: Edit, rule: &InstantiatedRule) -> Option<Edit> {
+    if !*rule.rule().dry() {
+      return Some(edit);
+    }
+    debug!("Suggested rewrite (dry rule) : {}", edit);
+    self
+      .rule_statistics
+      .entry(rule.name())
+      .or_default()
+      .record_match(false);
+    let range = edit.p_match().range();
+    self
+      .vetoed_edits
+      .insert((rule.name(), range.start_byte, range.end_byte));
+    self.suggested_rewrites.push(edit);
+    None
+  }
+
+  /// Records the code rewritten by the `edit` if its `rule` belongs to an `exclusive_group` (see `exclude_edit`).
+  /// The byte range is the matched one, and follows the edit once it is applied (see `update_exclusive_regions`).
+  fn record_exclusive_region(&mut self, edit: &Edit, rule: &InstantiatedRule) {
+    let group = rule.rule().exclusive_group();
+    if group.is_empty() {
+      return;
+    }
+    let range = edit.p_match().range();
+    self.exclusive_regions.push((
+      group.to_string(),
+      rule.name(),
+      range.start_byte,
+      range.end_byte,
+    ));
+  }
+
+  /// Shifts the byte ranges of the code rewritten by the rules of an `exclusive_group` following the (tree-sitter)
+  /// `edit`. An edit within a range (e.g. the rewrite itself, or a cleanup of the rewritten code) grows or shrinks it.
+  fn update_exclusive_regions(&mut self, edit: &InputEdit) {
+    for (_, _, start_byte, end_byte) in self.exclusive_regions.iter_mut() {
+      (*start_byte, *end_byte) = shift_range((*start_byte, *end_byte), edit, Overlap::Clamp)
+        .unwrap_or((*start_byte, *end_byte));
+    }
+  }
+
+  /// Shifts the byte range of the lines the rules are restricted to (see `apply_rules_on_lines`) following the
+  /// (tree-sitter) `edit`. An edit within the range grows or shrinks it.
+  fn update_line_range(&mut self, edit: &InputEdit) {
+    self.line_range = self
+      .line_range
+      .and_then(|range| shift_range(range, edit, Overlap::Clamp));
+  }
+
+  /// Shifts the ranges of the matches (see `match_ranges`) following the (tree-sitter) `edit`.
+  fn update_match_ranges(&mut self, edit: &InputEdit) {
+    for match_range in self.match_ranges.iter_mut() {
+      *match_range = match_range.and_then(|range| shift_range(range, edit, Overlap::Drop));
+    }
+  }
+
+  /// Records the match `m` of the read-only rule `rule_name`
+  pub(crate) fn record_match(&mut self, rule_name: String, m: Match) {
+    let range = m.range();
+    self
+      .match_ranges
+      .push(Some((range.start_byte, range.end_byte)));
+    self.matches.push((rule_name, m));
+  }
+
+  /// Clears the matches of the read-only rules (e.g. once they are reported)
+  pub(crate) fn clear_matches(&mut self) {
+    self.matches.clear();
+    self.match_ranges.clear();
+  }
+
+  /// Shifts the ranges of the suggested rewrites (see `suggest_edit`) following the (tree-sitter) `edit`, so that
+  /// they denote the code of this source code unit (and its `code` at the end, in the output summary).
+  /// The suggested rewrites whose code is rewritten by the edit are dropped (and noted), since they no longer apply.
+  fn update_suggested_rewrites(&mut self, edit: &InputEdit) {
+    if self.suggested_rewrites.is_empty() {
+      return;
+    }
+    for mut suggested_rewrite in std::mem::take(&mut self.suggested_rewrites) {
+      let range = suggested_rewrite.p_match().range();
+      match shift_range((range.start_byte, range.end_byte), edit, Overlap::Drop) {
+        Some((start_byte, end_byte)) => {
+          if (start_byte, end_byte) != (range.start_byte, range.end_byte) {
+            *suggested_rewrite.p_match_mut().range_mut() =
+              matches::Range::from(get_range_for_offsets(&self.code, start_byte, end_byte));
+          }
+          self.suggested_rewrites.push(suggested_rewrite);
+        }
+        None => self.notes.push(format!(
+          "The rewrite suggested by the rule `{}` at {}:{} was dropped, since its code was rewritten by another rule.",
+          suggested_rewrite.matched_rule(),
+          range.start_point.row + 1,
+          range.start_point.column + 1
+        )),
+      }
+    }
+  }
+
+  /// Applies the `edit` and, if the `rule` enables `delete_empty_enclosing`, deletes the enclosing nodes
+  /// that became empty because of it (recursively).
+  /// Each applied edit is reported as a `ProgressEvent::RuleApplied` of the `rule`.
+  /// Returns the last `edit:InputEdit` performed.
+  fn apply_edit_and_delete_empty_enclosing(
+    &mut self, edit: &Edit, rule: &InstantiatedRule, rules_store: &mut RuleStore,
+    parser: &mut Parser,
+  ) -> InputEdit {
+    if edit.is_delete() {
+      self.note_deleted_type_declarations(edit);
+    }
+    self.report_rule_applied(rule);
+    let mut applied_ts_edit = self.apply_rule_edit(edit, parser);
+    if !*rule.rule().delete_empty_enclosing() || !edit.is_delete() {
+      return applied_ts_edit;
+    }
+    while let Some(edit) = self.get_edit_for_empty_enclosing_node(
+      get_replace_range(applied_ts_edit),
+      rule,
+      rules_store,
+      parser,
+    ) {
+      // A deletion skipped by the `edit_interceptor` ends the cleanup (like the "Parent" cleanups)
+      let Some(edit) = self.intercept_edit(edit, rules_store) else {
+        break;
+      };
+      debug!("Deleting the empty enclosing node {}", edit);
+      self.rewrites_mut().push(edit.clone());
+      self.report_rule_applied(rule);
+      applied_ts_edit = self.apply_rule_edit(&edit, parser);
+    }
+    applied_ts_edit
+  }
+
+  fn report_rule_applied(&self, rule: &InstantiatedRule) {
+    self
+      .piranha_arguments
+      .report_progress(ProgressEvent::RuleApplied {
+        rule: rule.name(),
+        path: self.path().clone(),
+      });
+  }
+
+  /// Records a note (and logs a warning) when the (deleting) `edit` removes some but not all of the top-level type
+  /// declarations of the file (see `PiranhaLanguage::type_declaration_nodes`), since the imports used only by
+  /// the deleted types are not cleaned up.
+  fn note_deleted_type_declarations(&mut self, edit: &Edit) {
+    let type_declaration_nodes = self.piranha_arguments.language().type_declaration_nodes();
+    let range = edit.p_match().range();
+    let root_node = self.root_node();
+    let mut cursor = root_node.walk();
+    let (deleted, remaining): (Vec<Node>, Vec<Node>) = root_node
+      .named_children(&mut cursor)
+      .filter(|n| type_declaration_nodes.contains(&n.kind().to_string()))
+      .partition(|n| range.start_byte <= n.start_byte() && n.end_byte() <= range.end_byte);
+    if deleted.is_empty() || remaining.is_empty() {
+      return;
+    }
+    let note = format!(
+      "The rule `{}` deleted {} of the {} top-level type declarations (at line(s) {}): check the imports used only by the deleted declaration(s)",
+      edit.matched_rule(),
+      deleted.len(),
+      deleted.len() + remaining.len(),
+      deleted
+        .iter()
+        .map(|n| n.start_position().row + 1)
+        .join(", ")
+    );
+    warn!("{:?}: {}", self.path, note);
+    self.notes.push(note);
   }
 
   fn _panic_for_syntax_error(&self) {
-    let msg = format!(
+    panic!("{}", self._syntax_error_message());
+  }
+
+  fn _syntax_error_message(&self) -> String {
+    format!(
       "Produced syntactically incorrect source code {}",
       self.code()
-    );
-    panic!("{}", msg);
+    )
   }
 
   /// Returns the number of errors in this source code unit
@@ -384,9 +1784,160 @@ impl SourceCodeUnit {
       .parse(replacement_content, prev_tree)
       .expect("Could not generate new tree!");
     self.ast = new_tree;
+    // The lookups on the (updated) code are done again, e.g. an edit may change the declared package
+    self.declared_package = OnceCell::new();
+    self.suppressions = OnceCell::new();
+    self.scope_matches = RefCell::default();
+    if !is_current_ast_edited {
+      self.record_content_replacement(replacement_content);
+    }
     self.code = replacement_content.to_string();
   }
 
+  /// Records that the code was replaced by the `content` other than through a rewrite, so that
+  /// `validate_edits` replays the subsequent rewrites on it.
+  pub(crate) fn record_content_replacement(&mut self, content: &str) {
+    if self.code != content {
+      self
+        .content_replacements
+        .push((self.rewrites.len(), content.to_string()));
+      // The ranges of the skipped edits are meaningless in the new content
+      self.vetoed_edits.clear();
+    }
+  }
+
+  /// Checks that the rewrites of this source code unit are consistent, before they are persisted.
+  /// The rewrites are replayed (in order) on the original content, checking that the range of each of them is
+  ///   * within the code it is applied to,
+  ///   * not partially overlapping the code produced by a previous rewrite (a rewrite can enclose the code
+  ///     produced by a previous one, like a cleanup, or be enclosed by it),
+  ///   * starting and ending at character boundaries (i.e. the rewrite does not produce invalid UTF-8).
+  ///
+  /// Returns all the conflicts found (rather than the first one). The offending rewrites are not replayed.
+  pub(crate) fn validate_edits(&self) -> Result<(), Vec<EditConflict>> {
+    let mut conflicts = Vec::new();
+    let mut code = self.original_content.clone();
+    let mut content_replacements = self.content_replacements.iter().peekable();
+    // The (index, start byte, end byte) of the code produced by the previous rewrites (in the current code)
+    let mut produced_ranges: Vec<(usize, usize, usize)> = Vec::new();
+    for (index, edit) in self.rewrites.iter().enumerate() {
+      // The code was replaced (e.g. its consecutive new lines deleted), hence the previous ranges are meaningless
+      while let Some((_, content)) = content_replacements.next_if(|(i, _)| *i == index) {
+        code = content.to_string();
+        produced_ranges.clear();
+      }
+      let range = edit.p_match().range();
+      let (start_byte, end_byte) = (range.start_byte, range.end_byte);
+      if start_byte > end_byte || end_byte > code.len() {
+        conflicts.push(EditConflict::OutOfBounds {
+          index,
+          start_byte,
+          end_byte,
+          code_length: code.len(),
+        });
+        continue;
+      }
+      if !code.is_char_boundary(start_byte) || !code.is_char_boundary(end_byte) {
+        conflicts.push(EditConflict::InvalidUtf8 {
+          index,
+          start_byte,
+          end_byte,
+        });
+        continue;
+      }
+      let replacement = edit.replacement_string();
+      let delta = replacement.len() as isize - (end_byte - start_byte) as isize;
+      let mut next_produced_ranges = Vec::new();
+      for &(previous_index, start, end) in &produced_ranges {
+        if end <= start_byte {
+          next_produced_ranges.push((previous_index, start, end));
+        } else if start >= end_byte {
+          let shift = |offset: usize| (offset as isize + delta) as usize;
+          next_produced_ranges.push((previous_index, shift(start), shift(end)));
+        } else if start <= start_byte && end_byte <= end {
+          next_produced_ranges.push((previous_index, start, (end as isize + delta) as usize));
+        } else if !(start_byte <= start && end <= end_byte) {
+          conflicts.push(EditConflict::Overlap {
+            index,
+            previous_index,
+          });
+        }
+      }
+      code.replace_range(start_byte..end_byte, replacement);
+      next_produced_ranges.push((index, start_byte, start_byte + replacement.len()));
+      produced_ranges = next_produced_ranges;
+    }
+    if conflicts.is_empty() {
+      Ok(())
+    } else {
+      Err(conflicts)
+    }
+  }
+
+  /// Replaces all the occurrences of the literal `old` in the code with `new` (e.g. to rename a constant), without
+  /// querying the AST. Each replacement is reported among the rewrites (see `REPLACE_ALL_LITERALS_RULE`), and the
+  /// code is parsed once all of them are applied.
+  /// Returns the number of replacements, or an error if they introduce syntax errors (unless `allow_dirty_ast` is
+  /// set), in which case the source code unit is left as it was.
+  pub(crate) fn replace_all_literals(
+    &mut self, old: &str, new: &str, parser: &mut Parser,
+  ) -> Result<usize, PiranhaError> {
+    if old.is_empty() || old == new {
+      return Ok(0);
+    }
+    let offsets = self
+      .code
+      .match_indices(old)
+      .map(|(start_byte, _)| start_byte)
+      .collect_vec();
+    if offsets.is_empty() {
+      return Ok(0);
+    }
+    let snapshot = Snapshot::new(self);
+    let number_of_errors = self._number_of_errors();
+    // Replace the occurrences from the last one, so that the offsets of the others remain valid.
+    // The tree is edited along, and re-parsed once at the end.
+    let mut code = self.code.clone();
+    for start_byte in offsets.iter().rev() {
+      let edit = self.with_line_ending(Edit::replace_offsets_for_rule(
+        &code,
+        *start_byte,
+        start_byte + old.len(),
+        new,
+        REPLACE_ALL_LITERALS_RULE,
+      ));
+      let (new_code, ts_edit) = get_tree_sitter_edit(code, &edit);
+      self.ast.edit(&ts_edit);
+      self.record_input_edit(ts_edit);
+      self.rewrites.push(edit);
+      code = new_code;
+    }
+    self._replace_file_contents_and_re_parse(&code, parser, true);
+    if self._number_of_errors() > number_of_errors && !*self.piranha_arguments.allow_dirty_ast() {
+      warn!(
+        "Could not replace `{}` with `{}` in {:?}: the updated code contains syntax errors",
+        old, new, self.path
+      );
+      snapshot.restore(self);
+      return Err(PiranhaError::SyntaxError {
+        rule: REPLACE_ALL_LITERALS_RULE.to_string(),
+        path: self.path.clone(),
+      });
+    }
+    Ok(offsets.len())
+  }
+
+  /// Returns a fingerprint of the current code and substitutions of this source code unit.
+  /// If the fingerprint is the same before and after applying the rules, the rules have reached a fixed point.
+  pub(crate) fn hash(&self) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    self.code.hash(&mut hasher);
+    for substitution in self.substitutions.iter().sorted() {
+      substitution.hash(&mut hasher);
+    }
+    hasher.finish()
+  }
+
   pub(crate) fn global_substitutions(&self) -> HashMap<String, String> {
     self
       .substitutions()
@@ -0,0 +1,199 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+//! A read-only view of a source code unit (see `SourceCodeUnit::view`), for the tools built on top of Piranha's
+//! parsing and matching, e.g. to inspect the syntax tree and the tag bindings of the files matched by the rules.
+//! The views are handed out by the `Analysis` returned by `analyze`, which applies the rules without writing anything.
+//! The source code units themselves stay crate-private: the code snippet of the Piranha arguments is rewritten by the
+//! functions of the crate root instead (`apply_edit_at_offset`, `refactor_rename_symbol`, `replace_all_literals` and
+//! `apply_rules_on_lines`), which return its `PiranhaOutputSummary`.
+//!
+//! For instance, to print the tag bindings of each match of the match-only rules of `configurations/rules.toml`
+//! (e.g. `query = "((method_invocation name: (_) @name) @call)"`) in the Java files of `src/`:
+//!
+//! ```no_run
+//! use polyglot_piranha::{
+//!   analyze,
+//!   models::{language::PiranhaLanguage, piranha_arguments::PiranhaArgumentsBuilder},
+//! };
+//!
+//! let piranha_arguments = PiranhaArgumentsBuilder::default()
+//!   .path_to_codebase("src/".to_string())
+//!   .path_to_configurations("configurations/".to_string())
+//!   .language(PiranhaLanguage::from("java"))
+//!   .build();
+//! for view in analyze(&piranha_arguments).unwrap().views() {
+//!   for (rule_name, m) in view.matches() {
+//!     println!("{:?} - {rule_name}: {:?}", view.path(), m.matches());
+//!   }
+//! }
+//! ```
+
+use std::{collections::HashMap, path::Path};
+
+use itertools::Itertools;
+use tree_sitter::{Node, Query, Range};
+use tree_sitter_traversal::{traverse, Order};
+
+use crate::utilities::tree_sitter_utilities::{get_non_overlapping_matches_for_query, LineMap};
+
+use super::{edit::Edit, matches::Match, source_code_unit::SourceCodeUnit};
+
+/// A read-only view of a `SourceCodeUnit`, i.e. its code, syntax tree, matches, substitutions and rewrites
+#[derive(Clone, Copy)]
+pub struct SourceCodeView<'a> {
+  source_code_unit: &'a SourceCodeUnit,
+}
+
+impl<'a> SourceCodeView<'a> {
+  pub(crate) fn new(source_code_unit: &'a SourceCodeUnit) -> Self {
+    SourceCodeView { source_code_unit }
+  }
+
+  /// The source code unit behind this view
+  pub(crate) fn source_code_unit(&self) -> &'a SourceCodeUnit {
+    self.source_code_unit
+  }
+
+  /// The path to the file
+  pub fn path(&self) -> &'a Path {
+    self.source_code_unit.path()
+  }
+
+  /// The content of the file (after the rewrites, if any)
+  pub fn code(&self) -> &'a str {
+    self.source_code_unit.code()
+  }
+
+  /// The content of the file before the rewrites
+  pub fn original_content(&self) -> &'a str {
+    self.source_code_unit.original_content()
+  }
+
+  /// The root node of the syntax tree of the `code`
+  pub fn root_node(&self) -> Node<'a> {
+    self.source_code_unit.root_node()
+  }
+
+  /// The nodes of the syntax tree (including the anonymous ones), in pre-order
+  pub fn nodes(&self) -> impl Iterator<Item = Node<'a>> {
+    traverse(self.root_node().walk(), Order::Pre)
+  }
+
+  /// The deepest node at the position (`line`, `column`), both 0-based with the column in bytes (e.g. the cursor
+  /// position in an editor), or `None` if the position is not in the code.
+  pub fn node_at_position(&self, line: usize, column: usize) -> Option<Node<'a>> {
+    let offset = LineMap::new(self.code()).offset(line, column)?;
+    self
+      .root_node()
+      .descendant_for_byte_range(offset, offset + 1)
+  }
+
+  /// The ranges of the code touched by at least one rule, i.e. the union of the ranges of the matches and of the
+  /// rewrites, where the overlapping (or adjacent) ranges are merged. The ranges are sorted.
+  /// Note that each range is the one of the code at the time of the match (or rewrite), hence a later rewrite may have shifted it.
+  pub fn coverage(&self) -> Vec<Range> {
+    let mut coverage: Vec<Range> = vec![];
+    for range in self
+      .matches()
+      .iter()
+      .map(|(_, m)| m.range())
+      .chain(self.rewrites().iter().map(|edit| edit.p_match().range()))
+      .sorted_by_key(|r| (r.start_byte, r.end_byte))
+    {
+      match coverage.last_mut() {
+        Some(last) if range.start_byte <= last.end_byte => {
+          if range.end_byte > last.end_byte {
+            last.end_byte = range.end_byte;
+            last.end_point = range.end_point;
+          }
+        }
+        _ => coverage.push(range),
+      }
+    }
+    coverage
+  }
+
+  /// The fraction (between 0 and 1) of the bytes of the code covered by the rules (see `coverage`)
+  pub fn coverage_ratio(&self) -> f64 {
+    if self.code().is_empty() {
+      return 0.0;
+    }
+    let covered_bytes: usize = self
+      .coverage()
+      .iter()
+      .map(|r| r.end_byte - r.start_byte)
+      .sum();
+    covered_bytes.min(self.code().len()) as f64 / self.code().len() as f64
+  }
+
+  /// Counts the occurrences of the tree-sitter `query` in the code, where an occurrence nested within another one
+  /// (e.g. `foo(foo())`) is not counted. Returns an error if the `query` is invalid for the language of the code.
+  pub fn count_matches(&self, query: &str) -> Result<usize, String> {
+    let language = self.source_code_unit.piranha_arguments().language();
+    let query = Query::new(*language.language(), query)
+      .map_err(|e| format!("Could not parse the query : {query:?} {e:?}"))?;
+    Ok(get_non_overlapping_matches_for_query(&self.root_node(), self.code(), &query, true).len())
+  }
+
+  /// The code of the `node` (of this syntax tree)
+  pub fn node_text(&self, node: Node) -> &'a str {
+    &self.code()[node.byte_range()]
+  }
+
+  /// The matches of the match-only rules, along with the name of the rule
+  pub fn matches(&self) -> &'a [(String, Match)] {
+    self.source_code_unit.matches()
+  }
+
+  /// The tag bindings (i.e. the substitutions) gathered while applying the rules
+  pub fn substitutions(&self) -> &'a HashMap<String, String> {
+    self.source_code_unit.substitutions()
+  }
+
+  /// The edits applied to the file
+  pub fn rewrites(&self) -> &'a [Edit] {
+    self.source_code_unit.rewrites()
+  }
+}
+
+/// The source code units of the files with matches or rewrites after applying the rules (see `analyze`),
+/// handed out as read-only views (sorted by path).
+pub struct Analysis {
+  source_code_units: Vec<SourceCodeUnit>,
+}
+
+impl Analysis {
+  pub(crate) fn new(source_code_units: Vec<SourceCodeUnit>) -> Self {
+    Analysis { source_code_units }
+  }
+
+  /// The views of the source code units, sorted by path
+  pub fn views(&self) -> impl Iterator<Item = SourceCodeView<'_>> {
+    self.source_code_units.iter().map(SourceCodeUnit::view)
+  }
+
+  /// The number of files with matches or rewrites
+  pub fn len(&self) -> usize {
+    self.source_code_units.len()
+  }
+
+  /// Whether no file has matches or rewrites
+  pub fn is_empty(&self) -> bool {
+    self.source_code_units.is_empty()
+  }
+}
+
+#[cfg(test)]
+#[path = "unit_tests/source_code_view_test.rs"]
+mod source_code_view_test;
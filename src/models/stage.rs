@@ -0,0 +1,147 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+//! The stages of a multi-step migration, e.g. `rewrite the call sites`, then `delete the declarations`.
+//! The stages are declared (in order) in `rules.toml`:
+//!
+//! ```toml
+//! [[stages]]
+//! name = "call_sites"
+//! rules = ["replace_is_enabled"]
+//!
+//! [[stages]]
+//! name = "declarations"
+//! rules = ["declarations_group"]
+//! continue_on = "never"
+//! ```
+//!
+//! Each stage only applies the seed rules it lists (by name or group), and starts from the result of the previous one.
+
+use getset::{CopyGetters, Getters};
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+
+use super::{piranha_output::PiranhaOutputSummary, rule_graph::RuleGraph};
+
+/// Determines whether the next stages are executed once a stage completed
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ContinueOn {
+  /// The next stages are executed unless the stage failed (i.e. a warning, like a skipped file, does not stop the migration)
+  #[default]
+  Warnings,
+  /// The next stages are only executed if the stage completed without any error or warning
+  Never,
+  /// The next stages are executed even if the stage failed
+  Always,
+}
+
+/// A named group of seed rules, applied after the previous stages
+#[derive(
+  Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq, Getters, CopyGetters,
+)]
+#[serde(deny_unknown_fields)]
+pub struct Stage {
+  /// The name of the stage (it is unique)
+  #[get = "pub"]
+  name: String,
+  /// The seed rules of the stage (by name or group)
+  #[get = "pub"]
+  rules: Vec<String>,
+  /// The gate between this stage and the next ones
+  #[serde(default)]
+  #[get_copy = "pub"]
+  continue_on: ContinueOn,
+}
+
+impl Stage {
+  pub fn new(name: &str, rules: &[&str], continue_on: ContinueOn) -> Self {
+    Self {
+      name: name.to_string(),
+      rules: rules.iter().map(|r| r.to_string()).collect(),
+      continue_on,
+    }
+  }
+
+  /// Checks if the next stages should be executed, given whether this stage failed or had warnings.
+  pub(crate) fn should_continue(&self, failed: bool, has_warnings: bool) -> bool {
+    match self.continue_on {
+      ContinueOn::Always => true,
+      ContinueOn::Warnings => !failed,
+      ContinueOn::Never => !failed && !has_warnings,
+    }
+  }
+
+  /// Checks that the stage is named, and only lists the seed rules (or groups) of the `rule_graph`.
+  pub(crate) fn validate(&self, rule_graph: &RuleGraph) -> Result<(), String> {
+    if self.name.is_empty() {
+      return Err("The name of a stage cannot be empty.".to_string());
+    }
+    if self.rules.is_empty() {
+      return Err(format!("The stage `{}` has no rule.", self.name));
+    }
+    for rule in &self.rules {
+      let seed_rules = rule_graph
+        .get_rules_for_group(rule)
+        .into_iter()
+        .filter_map(|name| rule_graph.get_rule_named(name))
+        .filter(|r| *r.is_seed_rule())
+        .count();
+      if seed_rules == 0 {
+        return Err(format!(
+          "The stage `{}` lists `{rule}`, which is neither a seed rule nor a group of seed rules.",
+          self.name
+        ));
+      }
+    }
+    Ok(())
+  }
+}
+
+/// The outcome of the stages of a run (a run without stages being a single stage), i.e. the summaries of the stages
+/// that completed and why the run stopped early (if it did)
+#[derive(Debug, Default, Getters)]
+pub struct StagesOutcome {
+  /// The summaries of the completed stages (attributed to their stage, see `PiranhaOutputSummary::stage`)
+  #[get = "pub"]
+  summaries: Vec<PiranhaOutputSummary>,
+  /// Why the run stopped early, e.g. a stage failed and the next stages were not executed
+  #[get = "pub"]
+  stop_reason: Option<String>,
+}
+
+impl StagesOutcome {
+  pub(crate) fn new(summaries: Vec<PiranhaOutputSummary>, stop_reason: Option<String>) -> Self {
+    Self {
+      summaries,
+      stop_reason,
+    }
+  }
+
+  /// The summaries, or the stop reason if the run stopped early.
+  pub fn into_result(self) -> Result<Vec<PiranhaOutputSummary>, String> {
+    match self.stop_reason {
+      Some(stop_reason) => Err(stop_reason),
+      None => Ok(self.summaries),
+    }
+  }
+
+  /// The summaries along with the stop reason.
+  pub fn into_parts(self) -> (Vec<PiranhaOutputSummary>, Option<String>) {
+    (self.summaries, self.stop_reason)
+  }
+}
+
+#[cfg(test)]
+#[path = "unit_tests/stage_test.rs"]
+mod stage_test;
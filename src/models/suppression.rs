@@ -0,0 +1,164 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+//! The comment directives opting code out of the edits (and matches) of Piranha:
+//! * `piranha:disable-next-line` suppresses the line following the comment
+//! * `piranha:disable` ... `piranha:enable` suppresses the code in between (until the end of the file if
+//!   the block is not closed)
+//!
+//! A directive can be restricted to some rules by listing their names after it (e.g. `// piranha:disable rule_a rule_b`).
+//! A `piranha:enable` closes the open blocks with the same rules, or all of them if it lists no rule.
+//! `all` stands for all the rules (e.g. `// piranha:disable-next-line all`).
+//!
+//! A file whose leading comments (i.e. before any code) contain a `piranha:disable` for all the rules, that is never
+//! closed, is opted out of Piranha altogether (see `is_file_disabled`).
+
+use std::path::Path;
+
+use log::warn;
+use regex::Regex;
+use tree_sitter::Node;
+use tree_sitter_traversal::{traverse, Order};
+
+// The marker of the directives, to skip the files without any
+const DIRECTIVE_MARKER: &str = "piranha:";
+
+/// A region of the code suppressed by a directive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Suppression {
+  start_byte: usize,
+  end_byte: usize,
+  // The names of the suppressed rules (all the rules, if empty)
+  rules: Vec<String>,
+}
+
+impl Suppression {
+  /// Checks if the matches of the rule `rule_name` within the byte range are suppressed, i.e. the range intersects this region.
+  pub(crate) fn suppresses(&self, rule_name: &str, start_byte: usize, end_byte: usize) -> bool {
+    start_byte < self.end_byte
+      && self.start_byte < end_byte.max(start_byte + 1)
+      && (self.rules.is_empty() || self.rules.iter().any(|r| r == rule_name))
+  }
+}
+
+/// Returns the regions suppressed by the directives in the comments (i.e. the nodes of the `comment_nodes` kinds)
+/// of the `code` (parsed as `root`).
+pub(crate) fn get_suppressions(
+  root: Node, code: &str, comment_nodes: &[String], path: &Path,
+) -> Vec<Suppression> {
+  if !code.contains(DIRECTIVE_MARKER) {
+    return vec![];
+  }
+  let directive = directive_regex();
+  let mut suppressions = vec![];
+  // The (start byte, rules) of the `piranha:disable` blocks not closed yet
+  let mut open_blocks: Vec<(usize, Vec<String>)> = vec![];
+  for comment in
+    traverse(root.walk(), Order::Pre).filter(|n| comment_nodes.iter().any(|kind| kind == n.kind()))
+  {
+    let Some((kind, rules)) = parse_directive(&directive, &code[comment.byte_range()]) else {
+      continue;
+    };
+    match kind.as_str() {
+      "disable-next-line" => {
+        let start_byte = code[comment.end_byte()..]
+          .find('\n')
+          .map_or(code.len(), |i| comment.end_byte() + i + 1);
+        let end_byte = code[start_byte..]
+          .find('\n')
+          .map_or(code.len(), |i| start_byte + i);
+        suppressions.push(Suppression {
+          start_byte,
+          end_byte,
+          rules,
+        });
+      }
+      "disable" => open_blocks.push((comment.end_byte(), rules)),
+      _ => open_blocks.retain(|(start_byte, block_rules)| {
+        let is_closed = rules.is_empty() || *block_rules == rules;
+        if is_closed {
+          suppressions.push(Suppression {
+            start_byte: *start_byte,
+            end_byte: comment.start_byte(),
+            rules: block_rules.clone(),
+          });
+        }
+        !is_closed
+      }),
+    }
+  }
+  for (start_byte, rules) in open_blocks {
+    warn!(
+      "The `piranha:disable` directive in {:?} (at byte {}) is not closed by a `piranha:enable`, the rest of the file is suppressed",
+      path, start_byte
+    );
+    suppressions.push(Suppression {
+      start_byte,
+      end_byte: code.len(),
+      rules,
+    });
+  }
+  suppressions
+}
+
+/// Checks if the whole file is opted out of Piranha, i.e. one of the comments preceding its first line of code
+/// is a `piranha:disable` directive for all the rules, and no `piranha:enable` closes it.
+pub(crate) fn is_file_disabled(root: Node, code: &str, comment_nodes: &[String]) -> bool {
+  if !code.contains(DIRECTIVE_MARKER) {
+    return false;
+  }
+  let directive = directive_regex();
+  let is_comment = |n: &Node| comment_nodes.iter().any(|kind| kind == n.kind());
+  // Checks if the `comment` is a directive of the `kind` for all the rules
+  let is_directive_for_all_rules = |comment: &Node, kind: &str| {
+    parse_directive(&directive, &code[comment.byte_range()])
+      .is_some_and(|(k, rules)| k == kind && rules.is_empty())
+  };
+  let mut cursor = root.walk();
+  let Some(disable) = root
+    .children(&mut cursor)
+    .take_while(is_comment)
+    .find(|comment| is_directive_for_all_rules(comment, "disable"))
+  else {
+    return false;
+  };
+  !traverse(root.walk(), Order::Pre)
+    .filter(|n| n.start_byte() > disable.start_byte() && is_comment(n))
+    .any(|comment| is_directive_for_all_rules(&comment, "enable"))
+}
+
+fn directive_regex() -> Regex {
+  Regex::new(r"piranha:(disable-next-line|disable|enable)((?:[ \t,]+[\w.\-]+)*)").unwrap()
+}
+
+/// Parses the directive (if any) in the `comment` into its kind (e.g. `disable`) and the names of the rules it
+/// applies to (all the rules, if empty).
+fn parse_directive(directive: &Regex, comment: &str) -> Option<(String, Vec<String>)> {
+  let captures = directive.captures(comment)?;
+  let rules = captures[2]
+    .split(|c: char| c.is_whitespace() || c == ',')
+    .filter(|r| !r.is_empty())
+    .map(String::from)
+    .collect::<Vec<_>>();
+  // `all` stands for all the rules
+  let rules = if rules.iter().any(|r| r == "all") {
+    vec![]
+  } else {
+    rules
+  };
+  Some((captures[1].to_string(), rules))
+}
+
+#[cfg(test)]
+#[path = "unit_tests/suppression_test.rs"]
+mod suppression_test;
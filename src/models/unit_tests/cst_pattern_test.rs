@@ -0,0 +1,107 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use tree_sitter::Query;
+
+use crate::{
+  models::{
+    default_configs::{JAVA, PYTHON},
+    language::PiranhaLanguage,
+  },
+  utilities::tree_sitter_utilities::get_match_for_query,
+};
+
+use super::{cst_to_query, get_cst_holes, CstPatternError};
+
+fn compile(snippet: &str, language: &str) -> Result<String, CstPatternError> {
+  cst_to_query(snippet, &PiranhaLanguage::from(language))
+}
+
+#[test]
+fn test_cst_method_invocation() {
+  // The expression is parsed as a statement (i.e. with the missing `;`)
+  assert_eq!(
+    compile("exp.isTreated(:[flag])", JAVA).unwrap(),
+    "(
+(method_invocation . object: (identifier) @cst_0 . \".\" . name: (identifier) @cst_1 . arguments: (argument_list \"(\" . (_) @flag . \")\") .) @cst
+(#eq? @cst_0 \"exp\")
+(#eq? @cst_1 \"isTreated\")
+)"
+  );
+}
+
+#[test]
+fn test_cst_query_compiles() {
+  for (snippet, language) in [
+    ("if (:[cond]) { :[body]; }", JAVA),
+    ("x = :[value] + 1;", JAVA),
+    ("foo(:[arg], \"bar\")", PYTHON),
+  ] {
+    let language = PiranhaLanguage::from(language);
+    let query = cst_to_query(snippet, &language).unwrap();
+    let query = Query::new(*language.language(), &query).unwrap();
+    assert!(query.capture_names().contains(&"cst".to_string()));
+  }
+}
+
+#[test]
+fn test_cst_statement_hole() {
+  // The hole stands for any statement, rather than an expression statement only
+  let query = compile("if (:[cond]) { :[body]; }", JAVA).unwrap();
+  assert!(query.contains("(block \"{\" . (_) @body . \"}\")"));
+
+  let language = PiranhaLanguage::from(JAVA);
+  let query = Query::new(*language.language(), &query).unwrap();
+  let code = "class A { void m() { if (x) { return y; } } }";
+  let tree = language.parser().parse(code, None).unwrap();
+  let m = get_match_for_query(&tree.root_node(), code, &query, true).unwrap();
+  assert_eq!(m.matches()["body"], "return y;");
+}
+
+#[test]
+fn test_cst_repeated_hole() {
+  let query = compile(":[x].equals(:[x])", JAVA).unwrap();
+  assert!(query.contains("(_) @x "));
+  assert!(query.contains("(_) @x_2"));
+  assert!(query.contains("(#eq? @x @x_2)"));
+  assert_eq!(get_cst_holes(":[x].equals(:[x], :[y])"), vec!["x", "y"]);
+}
+
+#[test]
+fn test_cst_unparsable() {
+  assert_eq!(
+    compile("exp.isTreated(:[flag]", JAVA).unwrap_err(),
+    CstPatternError::Unparsable {
+      snippet: "exp.isTreated(:[flag]".to_string(),
+      language: JAVA.to_string()
+    }
+  );
+}
+
+#[test]
+fn test_cst_not_a_single_node() {
+  let error = compile("a(); b();", JAVA).unwrap_err();
+  assert_eq!(
+    error,
+    CstPatternError::NotASingleNode {
+      snippet: "a(); b();".to_string(),
+      kinds: vec![
+        "expression_statement".to_string(),
+        "expression_statement".to_string()
+      ]
+    }
+  );
+  assert!(error
+    .to_string()
+    .contains("Wrap it in its enclosing construct"));
+}
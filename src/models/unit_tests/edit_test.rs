@@ -0,0 +1,76 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use serde_json::json;
+
+use super::Edit;
+
+#[test]
+fn test_to_lsp_workspace_edit() {
+  let code = "class A {\n  boolean b = isEnabled(FLAG);\n}\n";
+  let start_byte = code.find("isEnabled").unwrap();
+  let end_byte = code.find(";").unwrap();
+  let edit = Edit::replace_offsets(code, start_byte, end_byte, "true");
+  assert_eq!(
+    edit.to_lsp_workspace_edit(code, "file:///src/A.java"),
+    json!({
+      "changes": {
+        "file:///src/A.java": [{
+          "range": {
+            "start": { "line": 1, "character": 14 },
+            "end": { "line": 1, "character": 29 },
+          },
+          "newText": "true",
+        }]
+      }
+    })
+  );
+}
+
+#[test]
+fn test_to_lsp_workspace_edit_multi_line_delete() {
+  let code = "class A {\n  void m() {}\n  void n() {}\n}\n";
+  let start_byte = code.find("void m").unwrap();
+  let end_byte = code.find("  void n").unwrap();
+  let edit = Edit::replace_offsets(code, start_byte, end_byte, "");
+  let workspace_edit = edit.to_lsp_workspace_edit(code, "file:///src/A.java");
+  let text_edit = &workspace_edit["changes"]["file:///src/A.java"][0];
+  assert_eq!(
+    text_edit["range"]["start"],
+    json!({ "line": 1, "character": 2 })
+  );
+  assert_eq!(
+    text_edit["range"]["end"],
+    json!({ "line": 2, "character": 0 })
+  );
+  assert_eq!(text_edit["newText"], "");
+}
+
+#[test]
+fn test_to_lsp_workspace_edit_utf16_columns() {
+  // `é` is 2 bytes and 1 UTF-16 code unit, `𝔸` is 4 bytes and 2 UTF-16 code units
+  let code = "class A {\n  String s = \"é𝔸\" + isEnabled(FLAG);\n}\n";
+  let start_byte = code.find("isEnabled").unwrap();
+  let end_byte = code.find(";").unwrap();
+  let edit = Edit::replace_offsets(code, start_byte, end_byte, "true");
+  let workspace_edit = edit.to_lsp_workspace_edit(code, "file:///src/A.java");
+  let text_edit = &workspace_edit["changes"]["file:///src/A.java"][0];
+  assert_eq!(
+    text_edit["range"]["start"],
+    json!({ "line": 1, "character": 21 })
+  );
+  assert_eq!(
+    text_edit["range"]["end"],
+    json!({ "line": 1, "character": 36 })
+  );
+}
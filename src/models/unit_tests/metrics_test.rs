@@ -0,0 +1,78 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use serde_json::json;
+
+use super::{MetricsRecorder, RunMetrics, METRICS_SCHEMA_VERSION};
+use crate::models::{
+  default_configs::JAVA, language::PiranhaLanguage, piranha_arguments::PiranhaArgumentsBuilder,
+};
+
+/// The schema of `metrics.json` is consumed by dashboards, hence its fields must not change without
+/// incrementing `METRICS_SCHEMA_VERSION`.
+#[test]
+fn test_metrics_schema() {
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase("codebase".to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .substitutions(vec![(
+      "stale_flag_name".to_string(),
+      "STALE_FLAG".to_string(),
+    )])
+    .build();
+  let mut recorder = MetricsRecorder::new(&piranha_arguments);
+  recorder.record_failure();
+  let mut metrics = serde_json::to_value(recorder.metrics()).unwrap();
+  // The duration varies between the runs
+  assert!(metrics["duration_ms"].is_u64());
+  metrics["duration_ms"] = json!(0);
+
+  assert_eq!(
+    metrics,
+    json!({
+      "schema_version": METRICS_SCHEMA_VERSION,
+      "completed": false,
+      "duration_ms": 0,
+      "files_scanned": 0,
+      "files_edited": 0,
+      "files_deleted": 0,
+      "edits_by_rule": {},
+      "matches_by_rule": {},
+      "rule_statistics": {},
+      "truncation": {
+        "files_skipped": 0,
+        "files_timed_out": 0
+      },
+      "errors": {
+        "files_with_parse_errors": 0,
+        "failures": 1
+      },
+      "diagnostics": {
+        "unapplied_seed_rules": {}
+      },
+      "metadata": {
+        "piranha_version": env!("CARGO_PKG_VERSION"),
+        "language": "java",
+        "path_to_codebase": "codebase",
+        "dry_run": "false",
+        "substitutions.stale_flag_name": "STALE_FLAG"
+      }
+    })
+  );
+  // The document can be read back
+  let round_trip: RunMetrics = serde_json::from_value(metrics.clone()).unwrap();
+  assert_eq!(serde_json::to_value(round_trip).unwrap(), metrics);
+
+  recorder.record_completion();
+  assert!(recorder.metrics().completed());
+}
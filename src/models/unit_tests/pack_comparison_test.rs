@@ -0,0 +1,170 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use std::{
+  collections::BTreeMap,
+  fs,
+  path::{Path, PathBuf},
+};
+
+use tempdir::TempDir;
+
+use super::{compare_packs, BehavioralChange, FileComparison, PackComparison, PackEdit};
+use crate::models::default_configs::JAVA;
+
+fn edit(matched_string: &str, replacement: &str) -> PackEdit {
+  edit_at(1, 1, matched_string, replacement)
+}
+
+fn edit_at(line: usize, column: usize, matched_string: &str, replacement: &str) -> PackEdit {
+  PackEdit {
+    line,
+    column,
+    matched_string: matched_string.to_string(),
+    replacement: replacement.to_string(),
+  }
+}
+
+#[test]
+fn test_file_comparison() {
+  let foo_to_bar = edit("foo", "bar");
+  let baz_to_qux = edit("baz", "qux");
+
+  let unchanged = FileComparison::new(
+    "A.java",
+    &[foo_to_bar.clone(), baz_to_qux.clone()],
+    &[baz_to_qux.clone(), foo_to_bar.clone()],
+  );
+  assert_eq!(unchanged.change(), BehavioralChange::Unchanged);
+
+  // Applying an edit one more time is an addition
+  let additive = FileComparison::new(
+    "A.java",
+    &[edit("foo", "bar")],
+    &[foo_to_bar.clone(), foo_to_bar.clone(), baz_to_qux.clone()],
+  );
+  assert_eq!(additive.change(), BehavioralChange::Additive);
+  assert_eq!(
+    additive.added_edits(),
+    &vec![baz_to_qux.clone(), foo_to_bar.clone()]
+  );
+  assert!(additive.removed_edits().is_empty());
+
+  let modified = FileComparison::new("A.java", &[edit("foo", "bar")], &[edit("foo", "other")]);
+  assert_eq!(modified.change(), BehavioralChange::Modified);
+  assert_eq!(modified.added_edits(), &vec![edit("foo", "other")]);
+  assert_eq!(modified.removed_edits(), &vec![foo_to_bar.clone()]);
+
+  // The edits of the old version that are not applied anymore
+  let removed = FileComparison::new("A.java", &[foo_to_bar.clone()], &[]);
+  assert_eq!(removed.change(), BehavioralChange::Modified);
+
+  // The same edit applied at another site
+  let moved = FileComparison::new("A.java", &[foo_to_bar], &[edit_at(2, 1, "foo", "bar")]);
+  assert_eq!(moved.change(), BehavioralChange::Modified);
+  assert_eq!(moved.added_edits(), &vec![edit_at(2, 1, "foo", "bar")]);
+}
+
+#[test]
+fn test_pack_comparison() {
+  let edits_a = BTreeMap::from([
+    ("A.java".to_string(), vec![edit("foo", "bar")]),
+    ("B.java".to_string(), vec![edit("foo", "bar")]),
+  ]);
+  // A file only edited by the new version is an addition, whereas a file no longer edited is a modification
+  let edits_b = BTreeMap::from([
+    ("A.java".to_string(), vec![edit("foo", "bar")]),
+    ("C.java".to_string(), vec![edit("foo", "bar")]),
+  ]);
+  let comparison = PackComparison::new(&edits_a, &edits_b);
+  assert_eq!(comparison.change(), BehavioralChange::Modified);
+  assert_eq!(comparison.suggested_version_bump(), "major");
+  assert_eq!(
+    comparison
+      .files()
+      .iter()
+      .map(|file| (file.path().as_str(), file.change()))
+      .collect::<Vec<_>>(),
+    vec![
+      ("B.java", BehavioralChange::Modified),
+      ("C.java", BehavioralChange::Additive)
+    ]
+  );
+}
+
+#[test]
+fn test_compare_packs() {
+  let path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("compare_packs");
+  for (pack_b, change, exit_code) in [
+    // Renaming a rule does not change its behavior
+    ("v1_renamed_rule", BehavioralChange::Unchanged, 0),
+    ("v2_additive", BehavioralChange::Additive, 2),
+    ("v2_modified", BehavioralChange::Modified, 3),
+  ] {
+    let comparison = compare_packs(
+      &path.join("v1"),
+      &path.join(pack_b),
+      &path.join("corpus"),
+      JAVA,
+      None,
+    )
+    .unwrap();
+    assert_eq!(comparison.change(), change, "{pack_b}");
+    assert_eq!(comparison.change().exit_code(), exit_code);
+  }
+
+  let comparison = compare_packs(
+    &path.join("v1"),
+    &path.join("v2_additive"),
+    &path.join("corpus"),
+    JAVA,
+    None,
+  )
+  .unwrap();
+  assert_eq!(comparison.files().len(), 1);
+  assert_eq!(comparison.files()[0].path(), "A.java");
+  assert_eq!(
+    comparison.files()[0].added_edits(),
+    &vec![edit_at(19, 5, "baz", "qux")]
+  );
+}
+
+#[test]
+fn test_compare_packs_invalid() {
+  let path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("compare_packs");
+  let compare = |pack_b: &Path, language: &str| {
+    compare_packs(
+      &path.join("v1"),
+      pack_b,
+      &path.join("corpus"),
+      language,
+      None,
+    )
+  };
+
+  assert!(compare(&path.join("v2_additive"), "cobol").is_err());
+
+  // A rule pack with a misspelled field is reported, instead of panicking
+  let pack = TempDir::new_in(".", "invalid_pack").unwrap();
+  fs::write(
+    pack.path().join("rules.toml"),
+    "[[rules]]\nname = \"r\"\nqurey = \"(identifier) @id\"\n",
+  )
+  .unwrap();
+  let error = compare(pack.path(), JAVA).unwrap_err();
+  assert!(error.contains("qurey"), "{error}");
+}
@@ -0,0 +1,56 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use std::collections::HashMap;
+
+use crate::{models::Validator, utilities::Instantiate};
+
+use super::PackageFilter;
+
+fn package_filter(exact: &str, prefix: &str, regex: &str) -> PackageFilter {
+  PackageFilter {
+    exact: exact.to_string(),
+    prefix: prefix.to_string(),
+    regex: regex.to_string(),
+  }
+}
+
+#[test]
+fn test_is_satisfied_by() {
+  let filter = package_filter("", "com.uber", r"\.core$");
+  assert!(filter.is_satisfied_by(Some("com.uber.payments.core")));
+  assert!(!filter.is_satisfied_by(Some("com.uber.payments")));
+  assert!(!filter.is_satisfied_by(Some("org.core")));
+  assert!(!filter.is_satisfied_by(None));
+}
+
+/// The substituted values are matched literally by the regex, hence the instantiated regex stays valid.
+#[test]
+fn test_instantiate_escapes_the_regex() {
+  let filter = package_filter("", "@prefix", r"^@prefix\.[a-z]+$");
+  assert!(filter.validate().is_ok());
+  let substitutions = HashMap::from([("prefix".to_string(), "com.uber(".to_string())]);
+  let instantiated = filter.instantiate(&substitutions);
+  assert_eq!(instantiated.prefix(), "com.uber(");
+  assert_eq!(instantiated.regex(), r"^com\.uber\(\.[a-z]+$");
+  assert!(instantiated.is_satisfied_by(Some("com.uber(.payments")));
+  assert!(!instantiated.is_satisfied_by(Some("com.uberX.payments")));
+}
+
+/// An invalid regex is rejected when the rules are loaded, and never panics when matching.
+#[test]
+fn test_invalid_regex() {
+  let filter = package_filter("", "", "com.uber(");
+  assert!(filter.validate().is_err());
+  assert!(!filter.is_satisfied_by(Some("com.uber(")));
+}
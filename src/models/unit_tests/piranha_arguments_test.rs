@@ -11,12 +11,23 @@
  limitations under the License.
 */
 
+use std::{collections::HashMap, fs};
+
+use tempdir::TempDir;
+
 use crate::{
-  models::{default_configs::JAVA, language::PiranhaLanguage},
+  filter,
+  models::{
+    default_configs::{C_SHARP, GO, JAVA, KOTLIN, PROTO, PYTHON, SWIFT, THRIFT, TSX, TYPESCRIPT},
+    language::PiranhaLanguage,
+    source_code_unit::{LineEndings, OnParseError},
+  },
+  piranha_rule,
   tests::substitutions,
+  utilities::tree_sitter_utilities::WhitespaceSensitivity,
 };
 
-use super::PiranhaArgumentsBuilder;
+use super::{check_built_in_rules, parse_value_enum, PiranhaArgumentsBuilder, TEST_BUILT_IN_RULES};
 
 #[test]
 #[should_panic(expected = "Invalid Piranha Argument. Missing `path_to_codebase` or `code_snippet`")]
@@ -41,3 +52,251 @@ fn piranha_argument_invalid_both_codebase_and_snippet() {
     .substitutions(substitutions! {"super_interface_name" => "SomeInterface"})
     .build();
 }
+
+#[test]
+#[should_panic(expected = "The `scope_query_cache_size` should be positive.")]
+fn piranha_argument_invalid_scope_query_cache_size() {
+  let _ = PiranhaArgumentsBuilder::default()
+    .code_snippet("class A { }".to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .scope_query_cache_size(0)
+    .build();
+}
+
+#[test]
+fn piranha_argument_suspicious_substitutions() {
+  let args = PiranhaArgumentsBuilder::default()
+    .code_snippet("class A { }".to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .substitutions(substitutions! {
+      "flag_name" => "ENABLE_X\") (other_thing",
+      "treated" => "true"
+    })
+    .build();
+  // Only warned about
+  assert_eq!(
+    args.suspicious_substitutions(),
+    vec![("flag_name".to_string(), vec!['"', ')', '('])]
+  );
+}
+
+#[test]
+#[should_panic(
+  expected = "The substitution `flag_name` contains characters significant to the tree-sitter query syntax - `\"`, `)`, `(`"
+)]
+fn piranha_argument_strict_substitutions() {
+  let _ = PiranhaArgumentsBuilder::default()
+    .code_snippet("class A { }".to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .substitutions(substitutions! {"flag_name" => "ENABLE_X\") (other_thing"})
+    .strict_substitutions(true)
+    .build();
+}
+
+#[test]
+#[should_panic(expected = "Invalid comment deletion pattern `TODO(@stale_flag_name`")]
+fn piranha_argument_invalid_comment_deletion_pattern() {
+  let _ = PiranhaArgumentsBuilder::default()
+    .code_snippet("class A { }".to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .comment_deletion_patterns(vec!["TODO(@stale_flag_name".to_string()])
+    .build();
+}
+
+#[test]
+fn piranha_argument_input_substitutions_file() {
+  let temp_dir = TempDir::new_in(".", "substitutions").unwrap();
+  let json = temp_dir.path().join("substitutions.json");
+  fs::write(
+    &json,
+    r#"{"stale_flag_name": "FILE_FLAG", "treated": true, "namespace": ["A", "B"]}"#,
+  )
+  .unwrap();
+  let toml = temp_dir.path().join("substitutions.toml");
+  fs::write(
+    &toml,
+    "stale_flag_name = \"FILE_FLAG\"\ntreated = true\nnamespace = [\"A\", \"B\"]\n",
+  )
+  .unwrap();
+
+  let expected = HashMap::from([
+    ("stale_flag_name".to_string(), vec!["CLI_FLAG".to_string()]),
+    ("treated".to_string(), vec!["true".to_string()]),
+    (
+      "namespace".to_string(),
+      vec!["A".to_string(), "B".to_string()],
+    ),
+  ]);
+  for path in [json, toml] {
+    let args = PiranhaArgumentsBuilder::default()
+      .code_snippet("class A { }".to_string())
+      .language(PiranhaLanguage::from(JAVA))
+      .substitutions(substitutions! {"stale_flag_name" => "CLI_FLAG"})
+      .input_substitutions_file(Some(path))
+      .build();
+    // The substitutions passed explicitly take precedence over the ones of the file
+    assert_eq!(args.input_substitution_values(), expected);
+  }
+}
+
+#[test]
+#[should_panic(expected = "The value of the substitution `flags` in")]
+fn piranha_argument_invalid_input_substitutions_file() {
+  let temp_dir = TempDir::new_in(".", "substitutions").unwrap();
+  let path = temp_dir.path().join("substitutions.json");
+  fs::write(&path, r#"{"flags": {"nested": "FLAG"}}"#).unwrap();
+  let _ = PiranhaArgumentsBuilder::default()
+    .code_snippet("class A { }".to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .input_substitutions_file(Some(path))
+    .build();
+}
+
+#[test]
+#[should_panic(expected = "The `output_archive` requires the `path_to_codebase` to be an archive")]
+fn piranha_argument_output_archive_without_archive() {
+  let _ = PiranhaArgumentsBuilder::default()
+    .path_to_codebase("some/path".to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .output_archive(Some("output.zip".to_string()))
+    .build();
+}
+
+/// The built-in rules only reference the node kinds of the grammars they are shipped with.
+#[test]
+fn test_built_in_rules_match_grammars() {
+  for language in [
+    JAVA, KOTLIN, GO, SWIFT, C_SHARP, PYTHON, TSX, TYPESCRIPT, THRIFT, PROTO,
+  ] {
+    let language = PiranhaLanguage::from(language);
+    let rules = language.rules().clone().unwrap_or_default().rules;
+    let checked_rules = check_built_in_rules(rules.clone(), &language, true);
+    assert_eq!(checked_rules, Ok(rules), "{}", language.extension());
+  }
+}
+
+/// A built-in rule referencing a node kind unknown to the grammar is left out, or rejected with `strict_builtins`.
+#[test]
+fn test_built_in_rule_with_unknown_node_kind() {
+  let language = PiranhaLanguage::from(JAVA);
+  let bogus_rule = piranha_rule! {
+    name = "simplify_renamed_node",
+    query = "((renamed_expression (true) @t) @e)",
+    replace_node = "e",
+    replace = "true"
+  };
+  let rules = [
+    language.rules().clone().unwrap_or_default().rules,
+    vec![bogus_rule],
+  ]
+  .concat();
+
+  let checked_rules = check_built_in_rules(rules.clone(), &language, false).unwrap();
+  assert_eq!(checked_rules.len(), rules.len() - 1);
+  assert!(checked_rules
+    .iter()
+    .all(|r| r.name() != "simplify_renamed_node"));
+
+  assert_eq!(
+    check_built_in_rules(rules, &language, true).unwrap_err(),
+    "Invalid built-in rules. The built-in rule `simplify_renamed_node` references the node kind(s) `renamed_expression` unknown to the java grammar."
+  );
+}
+
+/// The patterns of the filters and the field names are checked too, since a renamed node kind in a `not_contains`
+/// would turn the guard of the rule into a no-op.
+#[test]
+fn test_built_in_rule_with_unknown_filter_node_kind_or_field() {
+  let language = PiranhaLanguage::from(JAVA);
+  let guarded_rule = piranha_rule! {
+    name = "delete_unguarded_class",
+    query = "((class_declaration) @c)",
+    replace_node = "c",
+    replace = "",
+    filters = [filter! {
+      , not_contains = ["((renamed_declaration) @d)",]
+    }]
+  };
+  assert_eq!(
+    check_built_in_rules(vec![guarded_rule], &language, true).unwrap_err(),
+    "Invalid built-in rules. The built-in rule `delete_unguarded_class` references the node kind(s) `renamed_declaration` unknown to the java grammar."
+  );
+
+  let renamed_field_rule = piranha_rule! {
+    name = "delete_renamed_field",
+    query = "((method_declaration renamed_name: (_) @n) @md)",
+    replace_node = "md",
+    replace = ""
+  };
+  assert_eq!(
+    check_built_in_rules(vec![renamed_field_rule], &language, true).unwrap_err(),
+    "Invalid built-in rules. The built-in rule `delete_renamed_field` references the field(s) `renamed_name` unknown to the java grammar."
+  );
+}
+
+/// The bogus rule is added to the built-in rules loaded when the arguments are built.
+#[test]
+fn test_built_in_rules_checked_when_building() {
+  TEST_BUILT_IN_RULES.with(|rules| {
+    rules.replace(vec![piranha_rule! {
+      name = "simplify_renamed_node",
+      query = "((renamed_expression (true) @t) @e)",
+      replace_node = "e",
+      replace = "true"
+    }])
+  });
+  let builder = |strict_builtins: bool| {
+    PiranhaArgumentsBuilder::default()
+      .code_snippet("class A {}".to_string())
+      .language(PiranhaLanguage::from(JAVA))
+      .strict_builtins(strict_builtins)
+      .try_build()
+  };
+  let args = builder(false).unwrap();
+  assert!(args
+    .rule_graph()
+    .rules()
+    .iter()
+    .all(|r| r.name() != "simplify_renamed_node"));
+  let error = builder(true).unwrap_err();
+  TEST_BUILT_IN_RULES.with(|rules| rules.borrow_mut().clear());
+  assert!(error.contains("simplify_renamed_node"), "{error}");
+}
+
+/// The values of the enum arguments passed from Python are validated, rather than panicking.
+#[test]
+fn test_parse_value_enum() {
+  let parse = |value: Option<&str>| {
+    parse_value_enum::<WhitespaceSensitivity>("whitespace_sensitivity", value.map(str::to_string))
+  };
+  assert_eq!(
+    parse(Some("insensitive")).unwrap(),
+    Some(WhitespaceSensitivity::Insensitive)
+  );
+  assert_eq!(
+    parse(Some("line-endings")).unwrap(),
+    Some(WhitespaceSensitivity::LineEndings)
+  );
+  assert_eq!(parse(None).unwrap(), None);
+  assert!(parse(Some("loose")).is_err());
+}
+
+#[test]
+fn test_parse_normalize_line_endings() {
+  assert_eq!(
+    parse_value_enum::<LineEndings>("normalize_line_endings", Some("crlf".to_string())).unwrap(),
+    Some(LineEndings::Crlf)
+  );
+  assert!(
+    parse_value_enum::<LineEndings>("normalize_line_endings", Some("cr".to_string())).is_err()
+  );
+}
+
+#[test]
+fn test_parse_on_parse_error() {
+  assert_eq!(
+    parse_value_enum::<OnParseError>("on_parse_error", Some("proceed".to_string())).unwrap(),
+    Some(OnParseError::Proceed)
+  );
+  assert!(parse_value_enum::<OnParseError>("on_parse_error", Some("ignore".to_string())).is_err());
+}
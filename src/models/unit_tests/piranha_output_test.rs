@@ -0,0 +1,193 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use colored::Colorize;
+use itertools::Itertools;
+
+use super::{PiranhaOutputSummary, RuleStat};
+use crate::{
+  models::{
+    default_configs::JAVA, edit::Edit, language::PiranhaLanguage, matches::Match,
+    piranha_arguments::PiranhaArgumentsBuilder, source_code_unit::SourceCodeUnit,
+  },
+  utilities::tree_sitter_utilities::get_range_for_offsets,
+};
+
+/// The summary of a shard that replaced `isEnabled()` with `true` in the file `path` for the rule `rule`.
+fn shard_summary(path: &str, rule: &str, matches: usize) -> PiranhaOutputSummary {
+  let original_content = "boolean b = isEnabled();".to_string();
+  let start_byte = original_content.find("isEnabled").unwrap();
+  let mut statistics = RuleStat::default();
+  for _ in 0..matches {
+    statistics.record_match(true);
+  }
+  statistics.record_time(Duration::from_millis(5));
+  PiranhaOutputSummary {
+    path: path.to_string(),
+    content: "boolean b = true;".to_string(),
+    rewrites: vec![Edit::replace_offsets(
+      &original_content,
+      start_byte,
+      original_content.len() - 1,
+      "true",
+    )],
+    rule_statistics: HashMap::from([(rule.to_string(), statistics)]),
+    original_content,
+    ..Default::default()
+  }
+}
+
+#[test]
+fn test_merge_summaries_of_different_files() {
+  let merged = PiranhaOutputSummary::merge(vec![
+    shard_summary("A.java", "replace_is_enabled", 1),
+    shard_summary("B.java", "replace_is_enabled", 2),
+    shard_summary("C.java", "delete_flag", 1),
+  ]);
+
+  assert_eq!(
+    merged.iter().map(|s| s.path().as_str()).collect_vec(),
+    vec!["A.java", "B.java", "C.java"]
+  );
+  assert!(merged.iter().all(|s| s.rewrites().len() == 1));
+  assert_eq!(
+    *merged[1].rule_statistics()["replace_is_enabled"].matches(),
+    2
+  );
+}
+
+/// The later summary of a file replaces the earlier ones, and its statistics are not counted twice.
+#[test]
+fn test_merge_summaries_of_the_same_file() {
+  let mut later = shard_summary("A.java", "replace_is_enabled", 1);
+  later.rewrites = vec![later.rewrites[0].with_replacement("false")];
+  later.content = "boolean b = false;".to_string();
+  later.original_content = "boolean b = true;".to_string();
+  later.notes = vec!["A note".to_string()];
+  let merged = PiranhaOutputSummary::merge(vec![
+    shard_summary("A.java", "replace_is_enabled", 1),
+    shard_summary("B.java", "replace_is_enabled", 1),
+    later,
+  ]);
+
+  assert_eq!(merged.len(), 2);
+  let a = &merged[0];
+  assert_eq!(a.path(), "A.java");
+  assert_eq!(a.original_content(), "boolean b = isEnabled();");
+  assert_eq!(a.content(), "boolean b = false;");
+  assert_eq!(
+    a.rewrites()
+      .iter()
+      .map(|edit| edit.replacement_string().to_string())
+      .collect_vec(),
+    vec!["false"]
+  );
+  assert_eq!(a.notes(), &vec!["A note".to_string()]);
+  let replace_is_enabled = &a.rule_statistics()["replace_is_enabled"];
+  assert_eq!(*replace_is_enabled.matches(), 1);
+  assert_eq!(*replace_is_enabled.time_ms(), 5);
+  assert_eq!(merged[1].path(), "B.java");
+}
+
+#[test]
+fn test_write_csv() {
+  let code = "class B {\n  void m() {\n    log(\"a, b\");\n  }\n}\n";
+  let start_byte = code.find("log").unwrap();
+  let end_byte = code.find(";\n  }").unwrap();
+  let summaries = vec![
+    shard_summary("A.java", "replace_is_enabled", 1),
+    PiranhaOutputSummary {
+      path: "B.java".to_string(),
+      rewrites: vec![
+        Edit::delete_offsets(code, start_byte, end_byte, "delete_log"),
+        Edit::delete_offsets(code, code.find("void").unwrap(), code.len() - 3, "delete_m"),
+      ],
+      ..Default::default()
+    },
+    // A file without rewrites has no row
+    PiranhaOutputSummary {
+      path: "C.java".to_string(),
+      ..Default::default()
+    },
+  ];
+
+  let mut output = vec![];
+  PiranhaOutputSummary::write_csv(&summaries, &mut output).unwrap();
+  assert_eq!(
+    String::from_utf8(output).unwrap(),
+    "file_path,rule_name,start_line,end_line,matched_text,replacement_text\n\
+     A.java,Edit At Offset,1,1,isEnabled(),true\n\
+     B.java,delete_log,3,3,\"log(\"\"a, b\"\")\",\n\
+     B.java,delete_m,2,4,\"void m() {\n    log(\"\"a, b\"\");\n  }\",\n"
+  );
+}
+
+#[test]
+fn test_highlight_matches() {
+  let source_code = "class A { int a = foo(); int b = foo(); int c = bar(); }";
+  let java = PiranhaLanguage::from(JAVA);
+  let mut parser = java.parser();
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase("some/test/path/".to_string())
+    .language(java)
+    .build();
+  let mut source_code_unit = SourceCodeUnit::new(
+    &mut parser,
+    source_code.to_string(),
+    &HashMap::new(),
+    PathBuf::new().as_path(),
+    &piranha_arguments,
+  );
+  for (rule_name, matched) in [
+    ("foo", "int a = foo()"),
+    ("foo", "foo(); int c"),
+    ("bar", "bar()"),
+  ] {
+    let start_byte = source_code.find(matched).unwrap();
+    let end_byte = start_byte + matched.len();
+    source_code_unit.record_match(
+      rule_name.to_string(),
+      Match::new(
+        matched.to_string(),
+        get_range_for_offsets(source_code, start_byte, end_byte),
+        HashMap::new(),
+      ),
+    );
+  }
+  // The later rewrites shift the matches (and drop the ones whose code is rewritten)
+  for (rewritten, replacement) in [("class A", "class Foo"), ("int c", "long c")] {
+    let code = source_code_unit.code().to_string();
+    let start_byte = code.find(rewritten).unwrap();
+    let edit = Edit::replace_offsets(&code, start_byte, start_byte + rewritten.len(), replacement);
+    source_code_unit.apply_edit(&edit, &mut parser).unwrap();
+  }
+
+  let summary = PiranhaOutputSummary::new(&source_code_unit);
+  assert_eq!(
+    summary.highlight_matches("foo"),
+    format!(
+      "class Foo {{ {}; int b = foo(); long c = bar(); }}",
+      "int a = foo()".yellow()
+    )
+  );
+  assert_eq!(
+    summary.highlight_matches("bar"),
+    format!(
+      "class Foo {{ int a = foo(); int b = foo(); long c = {}; }}",
+      "bar()".yellow()
+    )
+  );
+  assert_eq!(summary.highlight_matches("baz"), summary.content().as_str());
+}
@@ -0,0 +1,105 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use std::{
+  path::PathBuf,
+  time::{Duration, Instant},
+};
+
+use super::{ProgressEvent, ProgressState};
+
+#[test]
+fn test_progress_state() {
+  let mut state = ProgressState::default();
+  let now = Instant::now();
+  let mut line = String::new();
+  for event in [
+    ProgressEvent::GlobalPhaseStarted { number_of_files: 4 },
+    ProgressEvent::FileStarted {
+      path: PathBuf::from("A.java"),
+    },
+    ProgressEvent::RuleApplied {
+      rule: "r".to_string(),
+      path: PathBuf::from("A.java"),
+    },
+    ProgressEvent::FileStarted {
+      path: PathBuf::from("B.java"),
+    },
+  ] {
+    line = state.update(&event, now);
+  }
+  assert_eq!(
+    line,
+    "[=========>          ] 2/4 files | 1 edits | ETA 0s | B.java"
+  );
+
+  // A new phase restarts the count of the files, but not of the edits
+  state.update(
+    &ProgressEvent::GlobalPhaseStarted { number_of_files: 4 },
+    now,
+  );
+  assert_eq!(
+    state,
+    ProgressState {
+      total: 4,
+      processed: 0,
+      edits: 1,
+      current_file: Some(PathBuf::from("B.java")),
+      phase_started: Some(now),
+    }
+  );
+}
+
+#[test]
+fn test_render_progress_bar() {
+  let state = |total, processed| ProgressState {
+    total,
+    processed,
+    edits: 0,
+    current_file: None,
+    phase_started: None,
+  };
+  assert_eq!(
+    state(3, 0).render(Instant::now()),
+    "[                    ] 0/3 files | 0 edits"
+  );
+  assert_eq!(
+    state(3, 3).render(Instant::now()),
+    "[====================] 3/3 files | 0 edits"
+  );
+  assert_eq!(
+    state(0, 0).render(Instant::now()),
+    "[====================] 0/0 files | 0 edits"
+  );
+}
+
+#[test]
+fn test_progress_eta() {
+  let now = Instant::now();
+  let state = |processed| ProgressState {
+    total: 10,
+    processed,
+    edits: 0,
+    current_file: None,
+    phase_started: now.checked_sub(Duration::from_secs(26)),
+  };
+  // 26s for 2 files, hence 104s for the remaining 8 files
+  assert_eq!(
+    state(2).render(now),
+    "[===>                ] 2/10 files | 0 edits | ETA 1m44s"
+  );
+  assert_eq!(state(2).eta(now), Some(Duration::from_secs(104)));
+  // No estimate before the first file, nor once all the files are started
+  assert_eq!(state(0).eta(now), None);
+  assert_eq!(state(10).eta(now), None);
+}
@@ -0,0 +1,134 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use crate::models::edit::Edit;
+
+use super::{render_edit, RenderOptions};
+
+const CODE: &str = "class A {
+  void m() { foo(FLAG); }
+}
+";
+
+#[test]
+fn test_render_single_line_match() {
+  let edit = Edit::delete_offsets(CODE, 23, 32, "replace_foo").with_replacement("bar()");
+  assert_eq!(
+    render_edit(&edit, CODE, RenderOptions::default()),
+    "replace_foo (2:14)
+  |
+1 | class A {
+2 |   void m() { foo(FLAG); }
+  |              ^^^^^^^^^
+3 | }
+  = replace with: bar()"
+  );
+}
+
+#[test]
+fn test_render_truncated_lines() {
+  let edit = Edit::delete_offsets(CODE, 23, 32, "replace_foo").with_replacement("bar()");
+  assert_eq!(
+    render_edit(&edit, CODE, RenderOptions::new(0, 20)),
+    "replace_foo (2:14)
+  |
+2 |   void m() { foo(...
+  |              ^^^^^^^
+  = replace with: bar()"
+  );
+}
+
+#[test]
+fn test_render_multi_line_match() {
+  let code = "class A {
+  void m() {
+    if (FLAG) {
+      foo();
+    }
+  }
+}
+";
+  let edit = Edit::delete_offsets(code, 27, 57, "delete_if");
+  assert_eq!(
+    render_edit(&edit, code, RenderOptions::new(0, 100)),
+    "delete_if (3:5)
+  |
+3 |     if (FLAG) {
+  |     ^^^^^^^^^^^
+4 |       foo();
+  |       ^^^^^^
+5 |     }
+  |     ^
+  = delete"
+  );
+}
+
+#[test]
+fn test_render_multi_line_replacement() {
+  let edit =
+    Edit::delete_offsets(CODE, 23, 32, "replace_foo").with_replacement("if (x) {\n    bar();\n  }");
+  assert_eq!(
+    render_edit(&edit, CODE, RenderOptions::new(0, 100)),
+    "replace_foo (2:14)
+  |
+2 |   void m() { foo(FLAG); }
+  |              ^^^^^^^^^
+  = replace with:
+  | if (x) {
+  |     bar();
+  |   }"
+  );
+}
+
+#[test]
+fn test_render_end_of_file_match() {
+  let code = "class A {}\nint x = 1;";
+  let edit = Edit::delete_offsets(code, 11, 21, "delete_x");
+  assert_eq!(
+    render_edit(&edit, code, RenderOptions::default()),
+    "delete_x (2:1)
+  |
+1 | class A {}
+2 | int x = 1;
+  | ^^^^^^^^^^
+  = delete"
+  );
+
+  // An insertion at the end of the file (after its last new line)
+  let code = "class A {}\n";
+  let edit = Edit::replace_offsets(code, 11, 11, "class B {}\n");
+  assert_eq!(
+    render_edit(&edit, code, RenderOptions::new(1, 100)),
+    "Edit At Offset (2:1)
+  |
+1 | class A {}
+2 |
+  | ^
+  = replace with: class B {}"
+  );
+}
+
+#[test]
+fn test_render_edit_of_other_code() {
+  // The edit refers to another version of the code, where its offsets fall within the multi-byte characters
+  let edit = Edit::delete_offsets("let s = \"abcdef\";", 10, 14, "shorten");
+  let code = "let s = \"ééé\";";
+  assert_eq!(
+    render_edit(&edit, code, RenderOptions::default()),
+    "shorten (1:10)
+  |
+1 | let s = \"ééé\";
+  |          ^^
+  = delete"
+  );
+}
@@ -0,0 +1,257 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+
+use crate::models::outgoing_edges::Edges;
+use crate::models::rule::{Rule, TemplateInstance};
+use crate::models::rule_graph::{RuleGraph, RuleGraphBuilder};
+use crate::{edges, piranha_rule};
+
+fn sample_rule_graph() -> RuleGraph {
+  let rules = vec![
+    piranha_rule! {
+      name = "replace_flag",
+      query = "(method_invocation) @mi",
+      replace_node = "mi",
+      replace = "true"
+    },
+    piranha_rule! {
+      name = "find_flag",
+      query = "(method_invocation) @mi",
+      is_seed_rule = false
+    },
+    piranha_rule! {
+      name = "simplify_if",
+      query = "(if_statement) @if",
+      replace_node = "if",
+      replace = "",
+      is_seed_rule = false,
+      groups = ["boolean_cleanup"]
+    },
+    piranha_rule! {
+      name = "simplify_and",
+      query = "(binary_expression) @be",
+      replace_node = "be",
+      replace = "",
+      is_seed_rule = false,
+      groups = ["boolean_cleanup"]
+    },
+    piranha_rule! {
+      name = "delete_flag",
+      query = "(field_declaration) @fd",
+      replace_node = "fd",
+      replace = "",
+      is_seed_rule = false
+    },
+  ];
+  let edges = vec![
+    edges! {
+      from = "replace_flag",
+      to = ["boolean_cleanup"],
+      scope = "Parent"
+    },
+    edges! {
+      from = "replace_flag",
+      to = ["find_flag"],
+      scope = "Method"
+    },
+  ];
+  RuleGraphBuilder::default()
+    .rules(rules)
+    .edges(edges)
+    .build()
+}
+
+/// The seed rules of `sample_rule_graph` (`delete_flag` is not reachable from them)
+fn sample_seed_rules() -> HashSet<String> {
+  HashSet::from(["replace_flag".to_string()])
+}
+
+#[test]
+fn test_rule_graph_to_dot() {
+  let dot = sample_rule_graph().to_dot(&sample_seed_rules());
+  assert!(dot.starts_with("digraph RuleGraph {"));
+  // Styled by kind
+  assert!(
+    dot.contains("\"replace_flag\" [shape = ellipse, style = filled, fillcolor = lightblue];")
+  );
+  assert!(dot.contains("\"find_flag\" [shape = box];"));
+  // Groups are rendered as clusters
+  assert!(dot.contains(
+    "subgraph \"cluster_boolean_cleanup\" {\n    label = \"boolean_cleanup\";\n    \"simplify_if\" [shape = ellipse];\n    \"simplify_and\" [shape = ellipse];\n  }"
+  ));
+  // Edges are labelled with the scope (after expanding the groups)
+  assert!(dot.contains("\"replace_flag\" -> \"simplify_if\" [label = \"Parent\"];"));
+  assert!(dot.contains("\"replace_flag\" -> \"simplify_and\" [label = \"Parent\"];"));
+  assert!(dot.contains("\"replace_flag\" -> \"find_flag\" [label = \"Method\"];"));
+  // The rules that are not executed from the seed rules are not rendered
+  assert!(!dot.contains("delete_flag"));
+  // The rendered rules depend on the seed rules (e.g. only the cleanup rules are seeded in the `cleanup_only` mode)
+  let dot = sample_rule_graph().to_dot(&HashSet::from(["find_flag".to_string()]));
+  assert!(dot.contains("\"find_flag\" [shape = box, style = filled, fillcolor = lightblue];"));
+  assert!(!dot.contains("replace_flag"));
+}
+
+#[test]
+fn test_rule_graph_to_mermaid() {
+  let mermaid = sample_rule_graph().to_mermaid(&sample_seed_rules());
+  assert!(mermaid.starts_with("flowchart TD"));
+  assert!(mermaid.contains("r0([\"replace_flag\"])"));
+  assert!(mermaid.contains("r1[\"find_flag\"]"));
+  assert!(mermaid.contains("subgraph g1 [\"boolean_cleanup\"]"));
+  assert!(mermaid.contains("r0 -->|\"Parent\"| r2"));
+  assert!(mermaid.contains("r0 -->|\"Parent\"| r3"));
+  assert!(mermaid.contains("r0 -->|\"Method\"| r1"));
+  assert!(mermaid.contains("class r0 seed"));
+  assert!(!mermaid.contains("class r1 seed"));
+  assert!(!mermaid.contains("r4"));
+}
+
+#[test]
+fn test_edge_with_scope_list() {
+  let edges: Edges = toml::from_str(
+    r#"
+[[edges]]
+scope = ["Class", "File"]
+from = "replace_flag"
+to = ["find_flag"]
+
+[[edges]]
+scope = "Parent"
+from = "replace_flag"
+to = ["boolean_cleanup"]
+"#,
+  )
+  .unwrap();
+  assert_eq!(
+    edges.edges[0].get_scope(),
+    &vec!["Class".to_string(), "File".to_string()]
+  );
+  assert_eq!(edges.edges[1].get_scope(), &vec!["Parent".to_string()]);
+
+  let rule_graph = RuleGraphBuilder::default()
+    .rules(sample_rule_graph().rules().clone())
+    .edges(edges.edges)
+    .build();
+  let next_rules = rule_graph.get_next(&"replace_flag".to_string(), &HashMap::new());
+  // The overlapping scopes (`Class` and `File`) each get the next rule
+  for scope in ["Class", "File"] {
+    assert_eq!(
+      next_rules[scope].iter().map(|r| r.name()).collect_vec(),
+      vec!["find_flag"]
+    );
+  }
+  assert_eq!(next_rules["Parent"].len(), 2);
+}
+
+#[test]
+fn test_edge_with_disjoint_scope_list() {
+  let rule_graph = RuleGraphBuilder::default()
+    .rules(sample_rule_graph().rules().clone())
+    .edges(vec![edges! {
+      from = "replace_flag",
+      to = ["find_flag"],
+      scope = ["Parent" "Global"]
+    }])
+    .build();
+  assert_eq!(
+    rule_graph.get_neighbors(&"replace_flag".to_string()),
+    vec![
+      ("Parent".to_string(), "find_flag".to_string()),
+      ("Global".to_string(), "find_flag".to_string())
+    ]
+  );
+  let next_rules = rule_graph.get_next(&"replace_flag".to_string(), &HashMap::new());
+  assert_eq!(next_rules["Parent"].len(), 1);
+  assert_eq!(next_rules["Global"].len(), 1);
+}
+
+fn template_rules() -> Vec<Rule> {
+  vec![
+    piranha_rule! {
+      name = "delete",
+      query = "((method_invocation name: (_) @name) @mi (#eq? @name \"@api_method\"))",
+      replace_node = "mi",
+      replace = "",
+      template_params = ["api_method"]
+    },
+    piranha_rule! {
+      name = "cleanup",
+      query = "(if_statement) @if",
+      replace_node = "if",
+      replace = "",
+      is_seed_rule = false
+    },
+  ]
+}
+
+#[test]
+fn test_rule_templates() {
+  let rule_graph = RuleGraphBuilder::default()
+    .rules(template_rules())
+    .edges(vec![
+      edges! {
+        from = "delete",
+        to = ["cleanup"],
+        scope = "Parent"
+      },
+      edges! {
+        from = "cleanup",
+        to = ["delete_isTreated"],
+        scope = "Parent"
+      },
+    ])
+    .template_instances(vec![
+      TemplateInstance::new("delete", "", &[("api_method", "isTreated")]),
+      TemplateInstance::new(
+        "delete",
+        "delete_toggle",
+        &[("api_method", "isToggleEnabled")],
+      ),
+    ])
+    .build();
+
+  // The template is replaced by its instantiations
+  assert_eq!(
+    rule_graph
+      .rules()
+      .iter()
+      .map(|r| r.name())
+      .sorted()
+      .collect_vec(),
+    vec!["cleanup", "delete_isTreated", "delete_toggle"]
+  );
+  let delete_is_treated = rule_graph
+    .get_rule_named(&"delete_isTreated".to_string())
+    .unwrap();
+  assert!(delete_is_treated
+    .query()
+    .pattern()
+    .contains("\"isTreated\""));
+  assert!(delete_is_treated.template_params().is_empty());
+  // The edges from the template apply to all of its instantiations
+  for rule_name in ["delete_isTreated", "delete_toggle"] {
+    assert_eq!(
+      rule_graph.get_neighbors(&rule_name.to_string()),
+      vec![("Parent".to_string(), "cleanup".to_string())]
+    );
+  }
+  // The edges to an instantiation only apply to it
+  assert_eq!(
+    rule_graph.get_neighbors(&"cleanup".to_string()),
+    vec![("Parent".to_string(), "delete_isTreated".to_string())]
+  );
+}
@@ -12,7 +12,10 @@ Copyright (c) 2023 Uber Technologies, Inc.
 */
 
 use crate::models::{
-  capture_group_patterns::CGPattern, filter::FilterBuilder, rule_graph::RuleGraphBuilder,
+  capture_group_patterns::CGPattern,
+  filter::FilterBuilder,
+  rule::{Rule, RuleBuilder, TemplateInstance},
+  rule_graph::RuleGraphBuilder,
 };
 use crate::piranha_rule;
 
@@ -118,3 +121,152 @@ fn test_filter_bad_arg_contains_n_sibling() {
     .sibling_count(2)
     .build();
 }
+
+#[test]
+#[should_panic(expected = "`exactly_one_match` is only applicable to the `MultiFile` seed rules")]
+fn test_rule_graph_exactly_one_match_without_multi_file_scope() {
+  RuleGraphBuilder::default()
+    .rules(vec![RuleBuilder::default()
+      .name("Test rule".to_string())
+      .query(CGPattern::new("(if_statement) @if_stmt".to_string()))
+      .exactly_one_match(true)
+      .build()
+      .unwrap()])
+    .build();
+}
+
+#[test]
+#[should_panic(expected = "Invalid scope `Method` for the rule `Test rule`")]
+fn test_rule_graph_invalid_rule_scope() {
+  RuleGraphBuilder::default()
+    .rules(vec![RuleBuilder::default()
+      .name("Test rule".to_string())
+      .query(CGPattern::new("(if_statement) @if_stmt".to_string()))
+      .scope("Method".to_string())
+      .build()
+      .unwrap()])
+    .build();
+}
+
+#[test]
+#[should_panic(expected = "The tag `value` (`replace_with_capture`) is not captured by the query")]
+fn test_rule_graph_replace_with_unknown_capture() {
+  RuleGraphBuilder::default()
+    .rules(vec![piranha_rule! {
+      name = "Test rule",
+      query = "(parenthesized_expression (_) @expression) @parenthesized",
+      replace_node = "parenthesized",
+      replace_with_capture = "value"
+    }])
+    .build();
+}
+
+#[test]
+#[should_panic(expected = "The tag `value` (`replace_node`) is not captured by the query")]
+fn test_rule_graph_replace_unknown_capture_within_match() {
+  RuleGraphBuilder::default()
+    .rules(vec![piranha_rule! {
+      name = "Test rule",
+      query = "(parenthesized_expression (_) @expression) @parenthesized",
+      replace_node = "@value",
+      replace = ""
+    }])
+    .build();
+}
+
+#[test]
+#[should_panic(expected = "cannot have both a `replace` pattern and a `replace_with_capture` tag")]
+fn test_rule_graph_replace_and_replace_with_capture() {
+  RuleGraphBuilder::default()
+    .rules(vec![piranha_rule! {
+      name = "Test rule",
+      query = "(parenthesized_expression (_) @expression) @parenthesized",
+      replace_node = "parenthesized",
+      replace = "@expression",
+      replace_with_capture = "expression"
+    }])
+    .build();
+}
+
+#[test]
+#[should_panic(expected = "Invalid `directory_scope` glob `services/[` for the rule `Test rule`")]
+fn test_rule_graph_invalid_directory_scope() {
+  RuleGraphBuilder::default()
+    .rules(vec![piranha_rule! {
+      name = "Test rule",
+      query = "(parenthesized_expression (_) @expression) @parenthesized",
+      directory_scope = "services/["
+    }])
+    .build();
+}
+
+fn delete_template() -> Rule {
+  piranha_rule! {
+    name = "delete",
+    query = "((method_invocation name: (_) @name) @mi (#eq? @name \"@api_method\"))",
+    replace_node = "mi",
+    replace = "",
+    template_params = ["api_method"]
+  }
+}
+
+#[test]
+#[should_panic(expected = "The rule `delete_isTreated` is instantiated more than once")]
+fn test_rule_graph_template_instances_collision() {
+  RuleGraphBuilder::default()
+    .rules(vec![delete_template()])
+    .template_instances(vec![
+      TemplateInstance::new("delete", "", &[("api_method", "isTreated")]),
+      TemplateInstance::new("delete", "delete_isTreated", &[("api_method", "isEnabled")]),
+    ])
+    .build();
+}
+
+#[test]
+#[should_panic(
+  expected = "The instantiation `delete_isTreated` of the template `delete` has the same name as another rule"
+)]
+fn test_rule_graph_template_instance_collides_with_rule() {
+  RuleGraphBuilder::default()
+    .rules(vec![
+      delete_template(),
+      piranha_rule! {
+        name = "delete_isTreated",
+        query = "(method_invocation) @mi"
+      },
+    ])
+    .template_instances(vec![TemplateInstance::new(
+      "delete",
+      "",
+      &[("api_method", "isTreated")],
+    )])
+    .build();
+}
+
+#[test]
+#[should_panic(
+  expected = "The instantiation `delete_isTreated` of the template `delete` must provide exactly the parameters `api_method`"
+)]
+fn test_rule_graph_template_instance_missing_params() {
+  RuleGraphBuilder::default()
+    .rules(vec![delete_template()])
+    .template_instances(vec![TemplateInstance::new(
+      "delete",
+      "delete_isTreated",
+      &[("api", "isTreated")],
+    )])
+    .build();
+}
+
+#[test]
+#[should_panic(expected = "The template `remove` is not declared")]
+fn test_rule_graph_unknown_template() {
+  RuleGraphBuilder::default()
+    .rules(vec![delete_template()])
+    .template_instances(vec![TemplateInstance::new(
+      "remove",
+      "",
+      &[("api_method", "isTreated")],
+    )])
+    .build();
+}
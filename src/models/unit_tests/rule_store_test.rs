@@ -0,0 +1,262 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use std::{collections::HashMap, fs};
+
+use itertools::Itertools;
+use tempdir::TempDir;
+
+use super::{QueryCache, RuleGraphWarning, RuleStore};
+use crate::{
+  models::{
+    capture_group_patterns::CGPattern, default_configs::JAVA, language::PiranhaLanguage,
+    piranha_arguments::PiranhaArgumentsBuilder, rule::InstantiatedRule,
+    rule_graph::RuleGraphBuilder,
+  },
+  piranha_rule,
+  utilities::codebase::Codebase,
+};
+
+#[test]
+fn test_from_directory_iter() {
+  let rules_directory = TempDir::new_in(".", "rules").unwrap();
+  let write =
+    |name: &str, content: &str| fs::write(rules_directory.path().join(name), content).unwrap();
+  write(
+    "b_flags.toml",
+    r#"
+[[rules]]
+name = "replace_is_enabled"
+query = "((method_invocation name: (_) @name) @call (#eq? @name \"isEnabled\"))"
+replace_node = "call"
+replace = "true"
+
+[[rules]]
+name = "delete_flag"
+query = "((field_declaration) @f (#eq? @f \"@flag\"))"
+holes = ["flag"]
+"#,
+  );
+  write(
+    "a_invalid_rule.toml",
+    r#"
+[[rules]]
+name = "invalid_language"
+query = "(identifier) @n"
+language = "cobol"
+
+[[rules]]
+name = "invalid_query"
+query = "(unknown_node) @n"
+"#,
+  );
+  write("c_invalid_file.toml", "[[rules]]\nquery = 1");
+  write("README.md", "Not a rule file");
+
+  let rules = RuleStore::from_directory_iter(rules_directory.path(), &PiranhaLanguage::from(JAVA))
+    .collect::<Vec<_>>();
+  assert_eq!(rules.len(), 5);
+  // The files are read in the order of their names
+  assert!(rules[0]
+    .as_ref()
+    .unwrap_err()
+    .contains("Unsupported language `cobol` for the rule `invalid_language`"));
+  // The queries are only compiled when the rules are applied
+  assert_eq!(rules[1].as_ref().unwrap().name(), "invalid_query");
+  assert_eq!(rules[2].as_ref().unwrap().name(), "replace_is_enabled");
+  assert_eq!(rules[3].as_ref().unwrap().name(), "delete_flag");
+  assert!(rules[4]
+    .as_ref()
+    .unwrap_err()
+    .contains("c_invalid_file.toml"));
+}
+
+#[test]
+fn test_from_directory_iter_missing_directory() {
+  let rules = RuleStore::from_directory_iter(
+    &std::path::PathBuf::from("missing_rules_directory"),
+    &PiranhaLanguage::from(JAVA),
+  )
+  .collect::<Vec<_>>();
+  assert_eq!(rules.len(), 1);
+  assert!(rules[0]
+    .as_ref()
+    .unwrap_err()
+    .contains("Could not read the rules directory"));
+}
+
+/// Looks up 100 unique queries twice, with a cache of `capacity` queries.
+fn lookup_unique_queries(capacity: usize, reserved: usize) -> QueryCache {
+  let language = PiranhaLanguage::from(JAVA);
+  let queries = (0..100)
+    .map(|i| CGPattern::new(format!("((identifier) @id (#eq? @id \"name_{i}\"))")))
+    .collect::<Vec<_>>();
+  let mut rule_store = RuleStore {
+    rule_query_cache: QueryCache::new(capacity),
+    ..Default::default()
+  };
+  rule_store.rule_query_cache.reserve(reserved);
+  for _ in 0..2 {
+    for query in &queries {
+      rule_store.query(query, &language);
+    }
+  }
+  rule_store.rule_query_cache
+}
+
+#[test]
+fn test_query_cache() {
+  let cache = lookup_unique_queries(512, 0);
+  // The queries are only compiled in the first round
+  assert_eq!((cache.hits(), cache.misses()), (100, 100));
+  assert_eq!(cache.hit_rate(), 0.5);
+
+  // The least recently used queries are evicted, i.e. the queries of the first round are all evicted
+  // before they are looked up again
+  let cache = lookup_unique_queries(10, 0);
+  assert_eq!((cache.hits(), cache.misses()), (0, 200));
+  assert_eq!(cache.number_of_queries(), 10);
+
+  // Reserving room for the queries (e.g. of the seed rules) keeps them all in the cache
+  let cache = lookup_unique_queries(10, 100);
+  assert_eq!((cache.hits(), cache.misses()), (100, 100));
+  assert_eq!(cache.number_of_queries(), 100);
+}
+
+#[test]
+fn test_validate_no_captures() {
+  let rules = vec![
+    piranha_rule! {
+      name = "replace_true",
+      query = "(true)",
+      replace_node = "true",
+      replace = "false"
+    },
+    piranha_rule! {
+      name = "find_true",
+      query = "(true)"
+    },
+    piranha_rule! {
+      name = "replace_false",
+      query = "((false) @false)",
+      replace_node = "false",
+      replace = "true"
+    },
+  ];
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet("class A {}".to_string())
+    .rule_graph(RuleGraphBuilder::default().rules(rules).build())
+    .build();
+  let rule_store = RuleStore::new(&piranha_arguments);
+  assert_eq!(
+    rule_store.validate(),
+    vec![RuleGraphWarning::NoCaptures {
+      rule_name: "replace_true".to_string()
+    }]
+  );
+}
+
+#[test]
+fn test_add_to_global_rules_variants() {
+  let rule = piranha_rule! {
+    name = "replace_is_on",
+    query = "((method_invocation name: (_) @name arguments: (argument_list (_) @arg)) @mi (#eq? @arg \"@constant\"))",
+    replace_node = "mi",
+    replace = "true",
+    holes = ["constant"],
+    is_seed_rule = false
+  };
+  let other_rule = piranha_rule! {
+    name = "delete_constant",
+    query = "((field_declaration) @fd)",
+    replace_node = "fd",
+    replace = ""
+  };
+  let variant = |constant: &str| {
+    InstantiatedRule::new(
+      &rule,
+      &HashMap::from([("constant".to_string(), constant.to_string())]),
+    )
+  };
+  let global_rules = |variants: &[&str]| {
+    let mut rule_store = RuleStore::default();
+    rule_store.add_to_global_rules(&InstantiatedRule::new(&other_rule, &HashMap::new()));
+    for constant in variants {
+      rule_store.add_to_global_rules(&variant(constant));
+    }
+    rule_store
+      .global_rules()
+      .iter()
+      .map(|r| r.variant_key())
+      .collect_vec()
+  };
+
+  // The variants are kept (once each), in the same order whatever the order they were added in
+  let expected = global_rules(&["Experiment.ALPHA", "Experiment.BETA"]);
+  assert_eq!(expected.len(), 3);
+  assert_eq!(
+    global_rules(&["Experiment.BETA", "Experiment.ALPHA", "Experiment.BETA"]),
+    expected
+  );
+  assert_eq!(
+    expected[1],
+    (
+      "replace_is_on".to_string(),
+      vec![("constant".to_string(), "Experiment.ALPHA".to_string())]
+    )
+  );
+}
+
+#[test]
+fn test_get_relevant_files_prefilter() {
+  let temp_dir = TempDir::new_in(".", "prefilter").unwrap();
+  fs::write(
+    temp_dir.path().join("A.java"),
+    "class A { boolean b = isOn(Experiment.ALPHA); }",
+  )
+  .unwrap();
+  // Matched by `Experiment.ALPHA` as a regex, but it does not contain it literally
+  fs::write(
+    temp_dir.path().join("B.java"),
+    "class B { boolean b = isOn(ExperimentXALPHA); }",
+  )
+  .unwrap();
+  fs::write(temp_dir.path().join("C.java"), "class C {}").unwrap();
+  let relevant_files = |prefilter: bool| {
+    let rule = piranha_rule! {
+      name = "replace_is_on",
+      query = "((method_invocation arguments: (argument_list (_) @arg)) @mi (#eq? @arg \"@constant\"))",
+      replace_node = "mi",
+      replace = "true",
+      holes = ["constant"],
+      prefilter = prefilter
+    };
+    let mut rule_store = RuleStore::default();
+    rule_store.add_to_global_rules(&InstantiatedRule::new(
+      &rule,
+      &HashMap::from([("constant".to_string(), "Experiment.ALPHA".to_string())]),
+    ));
+    rule_store
+      .get_relevant_files(&Codebase::new(temp_dir.path(), &[], &[]))
+      .into_keys()
+      .map(|p| p.file_name().unwrap().to_str().unwrap().to_string())
+      .sorted()
+      .collect_vec()
+  };
+
+  // Only the files containing the substitutions (literally) are parsed
+  assert_eq!(relevant_files(true), vec!["A.java"]);
+  // Unless a rule opted out of the pre-filter
+  assert_eq!(relevant_files(false), vec!["A.java", "B.java", "C.java"]);
+}
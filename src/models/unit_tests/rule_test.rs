@@ -1,4 +1,4 @@
-use tree_sitter::Point;
+use tree_sitter::{Parser, Point};
 
 /*
 Copyright (c) 2023 Uber Technologies, Inc.
@@ -23,11 +23,12 @@ use crate::{
   utilities::eq_without_whitespace,
 };
 
-use super::InstantiatedRule;
+use super::{InstantiatedRule, ParseError, QueryFiles, Rule};
+use crate::models::Validator;
 use {
   crate::models::{rule_store::RuleStore, source_code_unit::SourceCodeUnit},
   std::collections::HashMap,
-  std::path::PathBuf,
+  std::path::{Path, PathBuf},
 };
 
 /// Tests whether a valid rule can be correctly instantiated given valid substitutions.
@@ -55,6 +56,112 @@ fn test_rule_try_instantiate_positive() {
   ))
 }
 
+/// Tests whether the `%{tag:regex}` placeholders of the replacement are substituted with the matched group.
+#[test]
+fn test_get_edit_substitute_tags_regex() {
+  let _rule = piranha_rule! {
+    name= "test",
+    query= "(
+    (method_invocation arguments: (argument_list (identifier) @flag)) @call
+    )",
+    replace_node = "call",
+    replace = "is%{flag:^FLAG_(\\w+)$}Enabled(%{flag})"
+  };
+  assert!(_rule.validate().is_ok());
+  let rule = InstantiatedRule::new(&_rule, &HashMap::new());
+  let source_code = "class Test {
+          public void foobar(){
+            boolean b = enabled(FLAG_STALE);
+          }
+        }";
+
+  let mut rule_store = RuleStore::default();
+  let args = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(UNUSED_CODE_PATH.to_string())
+    .build();
+  let mut parser = args.language().parser();
+  let source_code_unit = SourceCodeUnit::new(
+    &mut parser,
+    source_code.to_string(),
+    &HashMap::new(),
+    PathBuf::new().as_path(),
+    &args,
+  );
+  let edit = source_code_unit
+    .get_edit(&rule, &mut rule_store, source_code_unit.root_node(), true)
+    .unwrap();
+  assert_eq!(edit.replacement_string(), "isSTALEEnabled(FLAG_STALE)");
+
+  // The captured code is not substituted again, even if it looks like a placeholder
+  let _rule = piranha_rule! {
+    name= "test",
+    query= "(
+    (method_invocation arguments: (argument_list (string_literal) @s)) @call
+    )",
+    replace_node = "call",
+    replace = "wrap(@s, %{s})"
+  };
+  let rule = InstantiatedRule::new(&_rule, &HashMap::new());
+  let source_code_unit = SourceCodeUnit::new(
+    &mut parser,
+    source_code.replace("FLAG_STALE", "\"%{s} @s\""),
+    &HashMap::new(),
+    PathBuf::new().as_path(),
+    &args,
+  );
+  let edit = source_code_unit
+    .get_edit(&rule, &mut rule_store, source_code_unit.root_node(), true)
+    .unwrap();
+  assert_eq!(edit.replacement_string(), "wrap(\"%{s} @s\", \"%{s} @s\")");
+
+  // The matches for which a placeholder references an unbound tag are skipped
+  let _rule = piranha_rule! {
+    name= "test",
+    query= "((string_literal) @s)",
+    replace_node = "s",
+    replace = "%{unknown}"
+  };
+  let rule = InstantiatedRule::new(&_rule, &HashMap::new());
+  assert!(source_code_unit
+    .get_edit(&rule, &mut rule_store, source_code_unit.root_node(), true)
+    .is_none());
+
+  // The regexes of the placeholders are validated
+  let invalid_rule = piranha_rule! {
+    name= "test",
+    query= "((identifier) @flag)",
+    replace_node = "flag",
+    replace = "%{flag:(\\w+}"
+  };
+  assert!(invalid_rule.validate().is_err());
+}
+
+/// The replacements referencing a misspelled capture or hole are rejected, while the other references (e.g. an
+/// annotation) are emitted as is.
+#[test]
+fn test_validate_misspelled_tag_in_replacement() {
+  let rule = |replace: &str| {
+    piranha_rule! {
+      name = "swap_assert_equals",
+      query = "((method_invocation name: (_) @name arguments: (argument_list (_) @actual (_) @expected) @args) (#eq? @name \"assertEquals\"))",
+      replace_node = "args",
+      replace = replace,
+      holes = ["message"]
+    }
+  };
+  assert!(rule("(@expected, @actual)").validate().is_ok());
+  assert!(
+    rule("(@message, @expected, @actual) /* @Override @SuppressWarnings */")
+      .validate()
+      .is_ok()
+  );
+  assert_eq!(
+    rule("(@expected, @actaul)").validate().unwrap_err(),
+    "The replacement of the rule `swap_assert_equals` references the unknown tag `@actaul` (a misspelling of `@actual`?), which would be emitted as is."
+  );
+  assert!(rule("(@mesage, @expected, @actual)").validate().is_err());
+}
+
 /// Tests whether a valid rule is *not* instantiated given invalid substitutions.
 #[test]
 #[should_panic]
@@ -72,6 +179,71 @@ fn test_rule_try_instantiate_negative() {
   let _ = InstantiatedRule::new(&rule, &substitutions);
 }
 
+/// Tests whether the template of an instantiated rule is restored from its substitutions.
+#[test]
+fn test_instantiated_rule_to_rule() {
+  let rule = piranha_rule! {
+    name= "test",
+    query= "(
+    (method_invocation name: (_) @name arguments: (argument_list (identifier) @flag)) @call
+    (#eq? @name \"@api\")
+    (#eq? @flag \"@stale_flag\")
+    )",
+    replace_node = "call",
+    replace = "@treated",
+    holes = ["api" "stale_flag" "treated"]
+  };
+  let substitutions: HashMap<String, String> = HashMap::from([
+    (String::from("api"), String::from("isEnabled")),
+    // Contains the value of `treated`
+    (String::from("stale_flag"), String::from("FLAG_true")),
+    (String::from("treated"), String::from("true")),
+  ]);
+  let instantiated_rule = InstantiatedRule::new(&rule, &substitutions);
+  assert!(instantiated_rule
+    .query()
+    .pattern()
+    .contains("(#eq? @flag \"FLAG_true\")"));
+  assert_eq!(instantiated_rule.to_rule(), rule);
+}
+
+/// Tests whether the template of an instantiated rule is restored when a value also appears verbatim in the template.
+#[test]
+fn test_instantiated_rule_to_rule_value_in_template() {
+  let rule = piranha_rule! {
+    name= "test",
+    query= "(
+    (method_invocation name: (_) @name arguments: (argument_list (identifier) @flag)) @call
+    (#eq? @name \"isEnabled\")
+    (#eq? @flag \"@stale_flag\")
+    )",
+    replace_node = "call",
+    replace = "isEnabled(@stale_flag)",
+    holes = ["stale_flag"]
+  };
+  let substitutions: HashMap<String, String> =
+    HashMap::from([(String::from("stale_flag"), String::from("isEnabled"))]);
+  let instantiated_rule = InstantiatedRule::new(&rule, &substitutions);
+  assert_eq!(instantiated_rule.to_rule(), rule);
+}
+
+/// Tests whether the values substituted into the `directory_scope` of a rule are matched literally.
+#[test]
+fn test_instantiated_rule_applies_to() {
+  let rule = piranha_rule! {
+    name= "test",
+    query= "(identifier) @id",
+    directory_scope = "services/@service_name/**",
+    holes = ["service_name"]
+  };
+  let substitutions: HashMap<String, String> =
+    HashMap::from([(String::from("service_name"), String::from("[ab]*"))]);
+  let instantiated_rule = InstantiatedRule::new(&rule, &substitutions);
+  assert!(instantiated_rule.applies_to(Path::new("services/[ab]*/src/Main.java")));
+  assert!(!instantiated_rule.applies_to(Path::new("services/a/src/Main.java")));
+  assert!(!instantiated_rule.applies_to(Path::new("libs/[ab]*/src/Main.java")));
+}
+
 /// Positive tests for `rule.get_edit` method for given rule and input source code.
 #[test]
 fn test_get_edit_positive_recursive() {
@@ -347,3 +519,248 @@ fn test_satisfies_filter_not_enclosing_node_negative() {
     |result| result,
   );
 }
+
+#[test]
+fn test_rule_from_comment() {
+  let rule = Rule::from_comment(
+    r#"// @piranha-rule: name="rename_foo" query="((identifier) @id (#eq? @id \"foo\"))" replacement="bar""#,
+  )
+  .unwrap();
+  assert_eq!(rule.name(), "rename_foo");
+  assert_eq!(
+    rule.query().pattern(),
+    "((identifier) @id (#eq? @id \"foo\"))"
+  );
+  // The `replace_node` defaults to the only tag of the query
+  assert_eq!(rule.replace_node(), "id");
+  assert_eq!(rule.replace(), "bar");
+  assert!(rule.is_seed_rule());
+
+  // In a block comment, with an explicit `replace_node`
+  let rule = Rule::from_comment(
+    r#"/* @piranha-rule: query="(method_invocation name: (_) @n) @mi" replace_node="mi" replace="" groups="a, b" */"#,
+  )
+  .unwrap();
+  assert_eq!(rule.replace_node(), "mi");
+  assert_eq!(rule.replace(), "");
+  assert_eq!(rule.groups().len(), 2);
+  assert!(rule.name().starts_with("comment_rule_"));
+
+  // Without a replacement, the rule is match-only
+  let rule = Rule::from_comment(r#"# @piranha-rule: query="(identifier) @id""#).unwrap();
+  assert!(rule.is_match_only_rule());
+}
+
+#[test]
+fn test_rule_from_comment_errors() {
+  let error = |comment: &str| Rule::from_comment(comment).unwrap_err();
+  assert_eq!(
+    error(r#"// query="(identifier) @id""#),
+    ParseError::MissingAnnotation
+  );
+  assert_eq!(
+    error(r#"// @piranha-rule: name="r" replacement="x""#),
+    ParseError::MissingKey("query".to_string())
+  );
+  assert_eq!(
+    error(r#"// @piranha-rule: query="(call (identifier) @a (identifier) @b)" replacement="x""#),
+    ParseError::AmbiguousReplaceNode(vec!["a".to_string(), "b".to_string()])
+  );
+  assert_eq!(
+    error(r#"// @piranha-rule: query="(identifier) @id" replacement="x" scope="File""#),
+    ParseError::UnknownKey("scope".to_string())
+  );
+  assert_eq!(
+    error(r#"// @piranha-rule: query="(identifier) @id" query="(identifier) @i""#),
+    ParseError::DuplicateKey("query".to_string())
+  );
+  assert!(matches!(
+    error(r#"// @piranha-rule: query="(identifier) @id" language="cobol""#),
+    ParseError::InvalidRule(_)
+  ));
+}
+
+#[test]
+fn test_load_query_file() {
+  let configurations = tempdir::TempDir::new_in(".", "configurations").unwrap();
+  let queries = configurations.path().join("queries");
+  std::fs::create_dir(&queries).unwrap();
+  let query = "((identifier) @id (#eq? @id \"@flag\"))";
+  std::fs::write(queries.join("find_flag.scm"), query).unwrap();
+  let directories = [
+    PathBuf::from("missing"),
+    configurations.path().to_path_buf(),
+  ];
+  let mut query_files = QueryFiles::new();
+
+  let mut rule = piranha_rule! {
+    name = "find_flag",
+    holes = ["flag"],
+    query_file = "queries/find_flag.scm"
+  };
+  rule
+    .load_query_file(&directories, &mut query_files)
+    .unwrap();
+  assert_eq!(rule.query().pattern(), query);
+  // The path of the query file is kept as is, e.g. when the rule is exported
+  assert_eq!(rule.query_file(), "queries/find_flag.scm");
+  assert!(rule
+    .dot_style(true)
+    .contains("label = \"find_flag\\nqueries/find_flag.scm\""));
+  let exported = toml::to_string(&rule).unwrap();
+  assert!(exported.contains("query_file = \"queries/find_flag.scm\""));
+  assert!(!exported.contains("@flag"));
+  // Loading it again is a no-op
+  rule
+    .load_query_file(&directories, &mut query_files)
+    .unwrap();
+  assert_eq!(rule.query().pattern(), query);
+
+  // The file is read once
+  std::fs::write(queries.join("find_flag.scm"), "(identifier) @id").unwrap();
+  let mut other_rule = piranha_rule! {
+    name = "find_flag_again",
+    query_file = "queries/find_flag.scm"
+  };
+  other_rule
+    .load_query_file(&directories, &mut query_files)
+    .unwrap();
+  assert_eq!(other_rule.query().pattern(), query);
+
+  let mut missing_file_rule = piranha_rule! {
+    name = "missing_file",
+    query_file = "queries/missing.scm"
+  };
+  assert!(missing_file_rule
+    .load_query_file(&directories, &mut query_files)
+    .unwrap_err()
+    .contains("Could not find the query file `queries/missing.scm` of the rule `missing_file`"));
+
+  let mut query_and_file_rule = piranha_rule! {
+    name = "query_and_file",
+    query = "(identifier) @other",
+    query_file = "queries/find_flag.scm"
+  };
+  assert_eq!(
+    query_and_file_rule
+      .load_query_file(&directories, &mut query_files)
+      .unwrap_err(),
+    "The rule `query_and_file` cannot have both a `query` and a `query_file`."
+  );
+  // Even if the query is the content of the file
+  let mut same_query_and_file_rule = piranha_rule! {
+    name = "same_query_and_file",
+    query = query,
+    query_file = "queries/find_flag.scm"
+  };
+  assert!(same_query_and_file_rule
+    .load_query_file(&directories, &mut query_files)
+    .is_err());
+}
+
+#[test]
+fn test_capture_count() {
+  let rule = piranha_rule! {
+    name = "replace_is_enabled",
+    query = "(
+      (method_invocation name: (_) @name arguments: (_) @args) @call
+      (#eq? @name \"@flag_name\")
+    )",
+    replace_node = "call",
+    replace = "true",
+    holes = ["flag_name"]
+  };
+  // `@flag_name` (in the string) is a hole, and `@name` is counted once
+  assert_eq!(rule.capture_count(), 3);
+
+  let rule = piranha_rule! {
+    name = "replace_true",
+    query = "(true)",
+    replace_node = "true",
+    replace = "false"
+  };
+  assert_eq!(rule.capture_count(), 0);
+
+  let rule = piranha_rule! {
+    name = "find_blocks",
+    query = "(block (expression_statement))",
+    capture_all_groups = true
+  };
+  assert_eq!(rule.capture_count(), 2);
+}
+
+/// The nodes are captured once the holes are substituted, hence a hole never clobbers a generated capture, and
+/// the user defined tags cannot use the reserved prefix.
+#[test]
+fn test_capture_all_groups_instantiate() {
+  let rule = piranha_rule! {
+    name = "find_calls",
+    query = "((method_invocation name: (identifier) @name) (#eq? @name \"@identifier\"))",
+    holes = ["identifier"],
+    capture_all_groups = true
+  };
+  let substitutions = HashMap::from([("identifier".to_string(), "isEnabled".to_string())]);
+  assert_eq!(
+    rule.instantiate(&substitutions).query().pattern(),
+    "((method_invocation name: (identifier) @name) @__method_invocation (#eq? @name \"isEnabled\"))"
+  );
+
+  let invalid_rule = piranha_rule! {
+    name = "find_calls",
+    query = "((method_invocation) @__method_invocation)"
+  };
+  assert!(invalid_rule.validate().is_err());
+}
+
+#[test]
+fn test_preview() {
+  let rule = piranha_rule! {
+    name = "replace_is_treated",
+    query = "((method_invocation name: (_) @name arguments: (argument_list (_) @flag)) @call (#eq? @name \"isTreated\") (#eq? @flag \"@flag_name\"))",
+    replace_node = "call",
+    replace = "true",
+    holes = ["flag_name"]
+  };
+  let source_code = "class Test {
+  void m() {
+    boolean a = exp.isTreated(STALE_FLAG);
+    boolean b = exp.isTreated(STALE_FLAG);
+  }
+}";
+  let args = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(UNUSED_CODE_PATH.to_string())
+    .build();
+  let mut parser = args.language().parser();
+  let new_source_code_unit = |substitutions: &HashMap<String, String>, parser: &mut Parser| {
+    SourceCodeUnit::new(
+      parser,
+      source_code.to_string(),
+      substitutions,
+      PathBuf::new().as_path(),
+      &args,
+    )
+  };
+
+  let source_code_unit = new_source_code_unit(
+    &HashMap::from([("flag_name".to_string(), "STALE_FLAG".to_string())]),
+    &mut parser,
+  );
+  // Only the first match is rewritten, and the source code unit is unchanged
+  assert_eq!(
+    rule.preview(&source_code_unit.view()).unwrap(),
+    source_code.replacen("exp.isTreated(STALE_FLAG)", "true", 1)
+  );
+  assert_eq!(source_code_unit.code(), source_code);
+  assert!(source_code_unit.rewrites().is_empty());
+
+  // The hole has no substitution
+  let source_code_unit = new_source_code_unit(&HashMap::new(), &mut parser);
+  assert_eq!(rule.preview(&source_code_unit.view()), None);
+
+  // The rule does not match
+  let source_code_unit = new_source_code_unit(
+    &HashMap::from([("flag_name".to_string(), "OTHER_FLAG".to_string())]),
+    &mut parser,
+  );
+  assert_eq!(rule.preview(&source_code_unit.view()), None);
+}
@@ -0,0 +1,164 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use std::{collections::HashMap, path::PathBuf};
+
+use jsonschema::JSONSchema;
+use serde_json::{json, Value};
+use tempdir::TempDir;
+
+use super::{config_schemas, emit_schemas, Edges, Rules};
+
+fn compiled_schemas() -> HashMap<&'static str, JSONSchema> {
+  config_schemas()
+    .into_iter()
+    .map(|(name, schema)| (name, JSONSchema::compile(&schema).unwrap()))
+    .collect()
+}
+
+/// The errors of validating the `instance` against the schema `name`
+fn validation_errors(
+  schemas: &HashMap<&str, JSONSchema>, name: &str, instance: &Value,
+) -> Vec<String> {
+  schemas[name]
+    .validate(instance)
+    .err()
+    .map(|errors| {
+      errors
+        .map(|e| format!("{e} at {}", e.instance_path))
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+#[test]
+fn test_shipped_configurations_satisfy_schemas() {
+  let schemas = compiled_schemas();
+  let project_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+  let mut number_of_files = 0;
+  for directory in ["src/cleanup_rules/**", "demo/**"] {
+    let pattern = project_root.join(directory).join("*.toml");
+    for path in glob::glob(pattern.to_str().unwrap()).unwrap().flatten() {
+      let name = match path.file_name().and_then(|f| f.to_str()) {
+        Some("rules.toml") => "rules",
+        Some("edges.toml") => "edges",
+        Some("scope_config.toml") => "scope_config",
+        _ => continue,
+      };
+      let instance: Value = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+      let errors = validation_errors(&schemas, name, &instance);
+      assert!(errors.is_empty(), "{path:?} - {errors:?}");
+      number_of_files += 1;
+    }
+  }
+  assert!(number_of_files > 20);
+}
+
+#[test]
+fn test_schemas_reject_unknown_fields() {
+  let schemas = compiled_schemas();
+  let rules = json!({
+    "rules": [{ "name": "r", "query": "(identifier) @id", "replace_node": "id", "constraint": [] }]
+  });
+  assert_eq!(validation_errors(&schemas, "rules", &rules).len(), 1);
+  let rules = json!({
+    "rules": [{ "name": "r", "query": "(identifier) @id", "replace_node": "id", "filters": [{ "enclosing_node": "(block) @b" }] }]
+  });
+  assert!(validation_errors(&schemas, "rules", &rules).is_empty());
+
+  let edges = json!({ "edges": [{ "from": "a", "to": ["b"], "scope": "Parent" }] });
+  assert!(validation_errors(&schemas, "edges", &edges).is_empty());
+  let edges = json!({ "edges": [{ "from": "a", "to": "b", "scope": ["Parent", "Method"] }] });
+  assert_eq!(validation_errors(&schemas, "edges", &edges).len(), 1);
+
+  let substitutions = json!({ "stale_flag_name": "FLAG", "treated": true, "flags": ["A", "B"] });
+  assert!(validation_errors(&schemas, "input_substitutions", &substitutions).is_empty());
+  let substitutions = json!({ "nested": { "key": "value" } });
+  assert_eq!(
+    validation_errors(&schemas, "input_substitutions", &substitutions).len(),
+    1
+  );
+}
+
+#[test]
+fn test_piranha_arguments_schema() {
+  let schemas = compiled_schemas();
+  let arguments = json!({
+    "path_to_codebase": "src/",
+    "language": "java",
+    "substitutions": { "stale_flag_name": "FLAG" },
+    "include": ["*.java"],
+    "dry_run": true,
+    "on_parse_error": "proceed",
+    "whitespace_sensitivity": "line-endings"
+  });
+  assert!(validation_errors(&schemas, "piranha_arguments", &arguments).is_empty());
+  // The arguments only available via the Rust API are not part of the schema
+  let (_, schema) = config_schemas()
+    .into_iter()
+    .find(|(name, _)| *name == "piranha_arguments")
+    .unwrap();
+  assert!(schema["properties"].get("rule_graph").is_none());
+  assert!(schema["properties"].get("edit_interceptor").is_none());
+  assert!(schema["required"].is_null());
+  let arguments = json!({ "dry_run": "yes", "on_parse_error": "ignore" });
+  assert_eq!(
+    validation_errors(&schemas, "piranha_arguments", &arguments).len(),
+    2
+  );
+}
+
+#[test]
+fn test_configurations_reject_unknown_fields() {
+  // Like their schemas, the configuration files are rejected if they have unknown fields (e.g. a misspelled field)
+  let rules = toml::from_str::<Rules>(
+    r#"
+[[rules]]
+name = "r"
+query = "(identifier) @id"
+constraint = []
+"#,
+  );
+  assert!(rules.unwrap_err().to_string().contains("constraint"));
+  let edges = toml::from_str::<Edges>(
+    r#"
+[[edges]]
+from = "a"
+to = ["b"]
+scopes = "Parent"
+"#,
+  );
+  assert!(edges.is_err());
+}
+
+#[test]
+fn test_emit_schemas() {
+  let temp_dir = TempDir::new_in(".", "schemas").unwrap();
+  let paths = emit_schemas(&temp_dir.path().join("schemas")).unwrap();
+  assert_eq!(
+    paths
+      .iter()
+      .map(|p| p.file_name().unwrap().to_str().unwrap())
+      .collect::<Vec<_>>(),
+    [
+      "rules.schema.json",
+      "edges.schema.json",
+      "scope_config.schema.json",
+      "input_substitutions.schema.json",
+      "piranha_arguments.schema.json"
+    ]
+  );
+  let schema: Value = serde_json::from_str(&std::fs::read_to_string(&paths[0]).unwrap()).unwrap();
+  assert_eq!(schema["title"], "Rules");
+  temp_dir.close().unwrap();
+}
@@ -11,29 +11,21 @@
  limitations under the License.
 */
 
-use crate::models::{
-  capture_group_patterns::CGPattern,
-  default_configs::JAVA,
-  language::PiranhaLanguage,
-  piranha_arguments::{PiranhaArguments, PiranhaArgumentsBuilder},
-};
-
-/*
-Copyright (c) 2023 Uber Technologies, Inc.
-
- <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
- except in compliance with the License. You may obtain a copy of the License at
- <p>http://www.apache.org/licenses/LICENSE-2.0
-
- <p>Unless required by applicable law or agreed to in writing, software distributed under the
- License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
- express or implied. See the License for the specific language governing permissions and
- limitations under the License.
-*/
 use {
-  super::{ScopeGenerator, ScopeGeneratorBuilder, ScopeQueryGenerator, ScopeQueryGeneratorBuilder},
+  super::{
+    ScopeGenerator, ScopeGeneratorBuilder, ScopeQueryGenerator, ScopeQueryGeneratorBuilder,
+    ScopeStrategy,
+  },
   crate::{
-    models::{rule_store::RuleStore, source_code_unit::SourceCodeUnit},
+    models::{
+      capture_group_patterns::CGPattern,
+      default_configs::{JAVA, KOTLIN, SWIFT},
+      edit::Edit,
+      language::PiranhaLanguage,
+      piranha_arguments::{PiranhaArguments, PiranhaArgumentsBuilder},
+      rule_store::RuleStore,
+      source_code_unit::SourceCodeUnit,
+    },
     utilities::eq_without_whitespace,
   },
   std::{collections::HashMap, path::PathBuf},
@@ -135,9 +127,9 @@ fn test_get_scope_query_positive() {
   let mut rule_store = RuleStore::new(&piranha_args);
   let scope_query_method = source_code_unit.get_scope_query("Method", 133, 134, &mut rule_store);
 
-  println!("{}", scope_query_method.pattern().as_str());
+  println!("{}", scope_query_method.query().pattern().as_str());
   assert!(eq_without_whitespace(
-    scope_query_method.pattern().as_str(),
+    scope_query_method.query().pattern().as_str(),
     "(
       [(((method_declaration 
                 name : (_) @z
@@ -157,7 +149,7 @@ fn test_get_scope_query_positive() {
 
   let scope_query_class = source_code_unit.get_scope_query("Class", 133, 134, &mut rule_store);
   assert!(eq_without_whitespace(
-    scope_query_class.pattern().as_str(),
+    scope_query_class.query().pattern().as_str(),
     "(
         ((class_declaration name:(_) @z) @qc)
         (#eq? @z \"Test\")
@@ -191,3 +183,163 @@ fn test_get_scope_query_negative() {
   let mut rule_store = RuleStore::new(&piranha_args);
   let _ = source_code_unit.get_scope_query("Method", 9, 10, &mut rule_store);
 }
+
+/// The start byte of the `scope_level` scope node of the edit at `offset` in the `source_code`.
+fn _get_scope_node_start(
+  language: PiranhaLanguage, source_code: &str, scope_level: &str, offset: usize,
+) -> usize {
+  let mut parser = language.parser();
+  let piranha_args = PiranhaArgumentsBuilder::default()
+    .language(language)
+    .create()
+    .unwrap();
+  let source_code_unit = SourceCodeUnit::new(
+    &mut parser,
+    source_code.to_string(),
+    &HashMap::new(),
+    PathBuf::new().as_path(),
+    &piranha_args,
+  );
+  let mut rule_store = RuleStore::new(&piranha_args);
+  let scope_query =
+    source_code_unit.get_scope_query(scope_level, offset, offset + 1, &mut rule_store);
+  source_code_unit
+    .get_scope_node(Some(&scope_query), &mut rule_store)
+    .start_byte()
+}
+
+const JAVA_INNER_CLASSES: &str = "class A {
+      class Inner {
+        void foo() { int x = 1; }
+      }
+    }
+    class B {
+      class Inner {
+        void foo() { int y = 2; }
+      }
+    }";
+
+/// The inner classes have the same name, hence their scope query matches both of them.
+#[test]
+fn test_get_scope_node_java_inner_classes() {
+  let offset = JAVA_INNER_CLASSES.find("y = 2").unwrap();
+  let inner_class_of_a = JAVA_INNER_CLASSES.find("class Inner").unwrap();
+  let inner_class_of_b = JAVA_INNER_CLASSES.rfind("class Inner").unwrap();
+  let class_b = JAVA_INNER_CLASSES.find("class B").unwrap();
+  for (strategy, expected_start) in [
+    (ScopeStrategy::FirstMatch, inner_class_of_a),
+    (ScopeStrategy::NearestEnclosing, inner_class_of_b),
+    (ScopeStrategy::OutermostEnclosing, class_b),
+  ] {
+    let mut class_scope = _get_class_scope();
+    class_scope.strategy = strategy;
+    let mut language = PiranhaLanguage::from(JAVA);
+    language.set_scopes(vec![class_scope]);
+    assert_eq!(
+      _get_scope_node_start(language, JAVA_INNER_CLASSES, "Class", offset),
+      expected_start,
+      "{strategy:?}"
+    );
+  }
+  // The Java scopes select the nearest enclosing class
+  assert_eq!(
+    _get_scope_node_start(
+      PiranhaLanguage::from(JAVA),
+      JAVA_INNER_CLASSES,
+      "Class",
+      offset
+    ),
+    inner_class_of_b
+  );
+}
+
+#[test]
+fn test_get_scope_node_kotlin_nested_objects() {
+  let source_code = "class A {
+      object Flags {
+        fun check() = isEnabled(1)
+      }
+    }
+    class B {
+      object Flags {
+        fun check() = isEnabled(2)
+      }
+    }";
+  let offset = source_code.find("isEnabled(2)").unwrap();
+  assert_eq!(
+    _get_scope_node_start(PiranhaLanguage::from(KOTLIN), source_code, "Class", offset),
+    source_code.rfind("object Flags").unwrap()
+  );
+}
+
+/// The class and its extension have the same name, hence their `Class` scope query matches both of them.
+#[test]
+fn test_get_scope_node_swift_extension() {
+  let source_code = "class Sample {
+      func foo() { f(1) }
+    }
+    extension Sample {
+      func bar() { f(2) }
+    }";
+  let offset = source_code.find("f(2)").unwrap();
+  let extension = source_code.find("extension Sample").unwrap();
+  for scope_level in ["Class", "Extension"] {
+    assert_eq!(
+      _get_scope_node_start(
+        PiranhaLanguage::from(SWIFT),
+        source_code,
+        scope_level,
+        offset
+      ),
+      extension
+    );
+  }
+  assert_eq!(
+    _get_scope_node_start(
+      PiranhaLanguage::from(SWIFT),
+      source_code,
+      "Function",
+      offset
+    ),
+    source_code.find("func bar").unwrap()
+  );
+}
+
+/// The matches of the scope query are looked up once per edit, hence the scope node follows the edits.
+#[test]
+fn test_get_scope_node_after_edit() {
+  let language = PiranhaLanguage::from(JAVA);
+  let mut parser = language.parser();
+  let piranha_args = PiranhaArgumentsBuilder::default()
+    .language(language)
+    .create()
+    .unwrap();
+  let mut source_code_unit = SourceCodeUnit::new(
+    &mut parser,
+    JAVA_INNER_CLASSES.to_string(),
+    &HashMap::new(),
+    PathBuf::new().as_path(),
+    &piranha_args,
+  );
+  let mut rule_store = RuleStore::new(&piranha_args);
+  let offset = JAVA_INNER_CLASSES.find("y = 2").unwrap();
+  let scope_query = source_code_unit.get_scope_query("Class", offset, offset + 1, &mut rule_store);
+  let inner_class_of_b = JAVA_INNER_CLASSES.rfind("class Inner").unwrap();
+  assert_eq!(
+    source_code_unit
+      .get_scope_node(Some(&scope_query), &mut rule_store)
+      .start_byte(),
+    inner_class_of_b
+  );
+
+  // Delete the first class
+  let class_b = JAVA_INNER_CLASSES.find("class B").unwrap();
+  let edit = Edit::replace_offsets(JAVA_INNER_CLASSES, 0, class_b, "");
+  source_code_unit.apply_edit(&edit, &mut parser).unwrap();
+  assert_eq!(
+    source_code_unit
+      .get_scope_node(Some(&scope_query), &mut rule_store)
+      .start_byte(),
+    inner_class_of_b - class_b
+  );
+}
@@ -14,22 +14,35 @@ Copyright (c) 2023 Uber Technologies, Inc.
 use tree_sitter::{Parser, Point};
 
 use crate::{
-  filter,
+  edges, filter,
   models::{
-    default_configs::{JAVA, UNUSED_CODE_PATH},
-    filter::Filter,
+    capture_group_patterns::CGPattern,
+    default_configs::{C_SHARP, JAVA, UNUSED_CODE_PATH},
+    edit_interceptor::{EditDecision, EditInterceptor},
+    filter::{Filter, FilterBuilder},
     language::PiranhaLanguage,
+    matches::Match,
     piranha_arguments::PiranhaArgumentsBuilder,
     rule::InstantiatedRule,
+    rule_graph::RuleGraphBuilder,
     rule_store::RuleStore,
+    skipped_match::SkipReason,
   },
   piranha_rule,
   utilities::eq_without_whitespace,
 };
 use {
-  super::SourceCodeUnit,
-  crate::models::edit::Edit,
-  std::{collections::HashMap, path::PathBuf},
+  super::{PiranhaError, SourceCodeUnit, REPLACE_ALL_LITERALS_RULE},
+  crate::models::edit::{Edit, EditConflict},
+  std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+      atomic::{AtomicBool, Ordering},
+      Arc,
+    },
+    time::Duration,
+  },
   tree_sitter::Range,
 };
 
@@ -124,6 +137,119 @@ fn test_apply_edit_negative() {
   );
 }
 
+#[test]
+fn test_apply_edit_at_offset() {
+  let source_code = "class Test {
+      public void foobar(){
+        boolean isFlagTreated = true;
+      }
+    }";
+
+  let java = get_java_tree_sitter_language();
+  let mut parser = java.parser();
+  let mut source_code_unit =
+    SourceCodeUnit::default(source_code, &mut parser, java.extension().to_string());
+
+  let start_byte = source_code.find("true").unwrap();
+  let edit = source_code_unit
+    .apply_edit_at_offset(start_byte, start_byte + 4, "false", &mut parser)
+    .unwrap();
+  assert_eq!(edit.new_end_byte, start_byte + 5);
+  assert_eq!(edit.start_position.row, 2);
+  assert_eq!(
+    source_code_unit.code(),
+    &source_code.replace("true", "false")
+  );
+  assert_eq!(source_code_unit.rewrites().len(), 1);
+
+  // Invalid ranges are rejected without modifying the code
+  let code_length = source_code_unit.code().len();
+  assert!(source_code_unit
+    .apply_edit_at_offset(10, 5, "", &mut parser)
+    .is_err());
+  assert!(source_code_unit
+    .apply_edit_at_offset(code_length - 1, code_length + 1, "", &mut parser)
+    .is_err());
+  assert_eq!(
+    source_code_unit.code(),
+    &source_code.replace("true", "false")
+  );
+
+  // An edit producing syntactically incorrect code is rejected (rather than panicking), and undone
+  let start_byte = source_code_unit.code().find("boolean").unwrap();
+  assert!(source_code_unit
+    .apply_edit_at_offset(start_byte, start_byte, "(", &mut parser)
+    .is_err());
+  assert_eq!(
+    source_code_unit.code(),
+    &source_code.replace("true", "false")
+  );
+  assert_eq!(source_code_unit.rewrites().len(), 1);
+}
+
+/// Skips the edits replacing `true`, and replaces `false` by `!true` in the others.
+#[derive(Debug)]
+struct NegatingInterceptor;
+
+impl EditInterceptor for NegatingInterceptor {
+  fn on_edit(&self, _unit_path: &Path, edit: &Edit) -> EditDecision {
+    if edit.p_match().matched_string() == "true" {
+      return EditDecision::Skip;
+    }
+    EditDecision::Replace(Box::new(
+      edit.with_replacement(&edit.replacement_string().replace("false", "!true")),
+    ))
+  }
+}
+
+#[test]
+fn test_apply_edit_at_offset_with_edit_interceptor() {
+  let source_code = "class Test {
+      public void foobar(){
+        boolean a = true;
+        int b = 1;
+      }
+    }";
+
+  let java = get_java_tree_sitter_language();
+  let mut parser = java.parser();
+  let piranha_args = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(UNUSED_CODE_PATH.to_string())
+    .language(java)
+    .edit_interceptor(Some(Arc::new(NegatingInterceptor)))
+    .build();
+  let mut source_code_unit = SourceCodeUnit::new(
+    &mut parser,
+    source_code.to_string(),
+    &HashMap::new(),
+    PathBuf::new().as_path(),
+    &piranha_args,
+  );
+
+  // The skipped edit is not applied
+  let start_byte = source_code.find("true").unwrap();
+  assert!(source_code_unit
+    .apply_edit_at_offset(start_byte, start_byte + 4, "false", &mut parser)
+    .is_err());
+  assert_eq!(source_code_unit.code(), source_code);
+  assert!(source_code_unit.rewrites().is_empty());
+
+  // The replacement of the edit is applied (and reported) instead
+  let start_byte = source_code.find('1').unwrap();
+  source_code_unit
+    .apply_edit_at_offset(start_byte, start_byte + 1, "false ? 1 : 2", &mut parser)
+    .unwrap();
+  assert_eq!(
+    source_code_unit.code(),
+    &source_code.replace("= 1", "= !true ? 1 : 2")
+  );
+  assert_eq!(source_code_unit.rewrites().len(), 1);
+  assert_eq!(
+    source_code_unit.rewrites()[0].replacement_string(),
+    "!true ? 1 : 2"
+  );
+}
+
 /// Tests for contains, at_least, and at_most
 
 fn run_test_satisfies_filters(
@@ -276,6 +402,49 @@ fn test_satisfies_filters_at_most_0_negative() {
   assert_eq!(contains_0, not_contains);
 }
 
+#[test]
+fn test_satisfies_filters_negated() {
+  // The negation of `test_satisfies_filters_contains_positive`
+  run_test_satisfies_filters(
+    filter! {
+        enclosing_node= "(method_declaration) @md",
+        contains= "(
+                    ((method_invocation
+                        arguments: (argument_list (
+                            (identifier) @id))) @method)
+                    (#eq? @id \"@variable_name\")
+                )",
+        negated = true
+    },
+    |result| !result,
+  );
+  // The negation of `test_satisfies_filters_at_most_negative`
+  run_test_satisfies_filters(
+    FilterBuilder::default()
+      .enclosing_node(CGPattern::new("(method_declaration) @md".to_string()))
+      .contains(CGPattern::new("((method_invocation) @method)".to_string()))
+      .at_most(1)
+      .negate()
+      .build(),
+    |result| result,
+  );
+}
+
+#[test]
+fn test_negated_filter_from_toml() {
+  let filter: Filter = toml::from_str(
+    r#"
+    enclosing_node = "(method_declaration) @md"
+    not_contains = ["(method_invocation) @mi"]
+    negated = true
+    "#,
+  )
+  .unwrap();
+  assert!(*filter.negated());
+  let filter: Filter = toml::from_str(r#"enclosing_node = "(method_declaration) @md""#).unwrap();
+  assert!(!*filter.negated());
+}
+
 /// Tests for not contains
 #[test]
 fn test_satisfies_filters_not_contains_positive() {
@@ -548,6 +717,70 @@ fn test_satisfies_filters_sibling_count() {
   assert!(!source_code_unit.is_satisfied(*node, &rule_neg, &HashMap::new(), &mut rule_store,));
 }
 
+#[test]
+fn test_satisfies_filters_parent_kind_and_enclosing_node_distance() {
+  let rule = |filter: Filter| {
+    InstantiatedRule::new(
+      &piranha_rule! {
+        name= "test",
+        query= "((method_invocation) @mi)",
+        filters= [filter]
+      },
+      &HashMap::new(),
+    )
+  };
+  let in_expression_statement = rule(filter! {
+    , parent_kind = "expression_statement"
+  });
+  let close_to_statement = rule(filter! {
+    enclosing_node = "(expression_statement) @statement"
+    , max_enclosing_node_distance = 3
+  });
+  let close_to_method = rule(filter! {
+    enclosing_node = "(method_declaration) @md"
+    , max_enclosing_node_distance = 2
+  });
+  let invocation = rule(filter! {
+    enclosing_node = "(method_invocation) @mi"
+    , max_enclosing_node_distance = 0
+  });
+
+  let source_code = "class Test {
+      public void test(){
+        foo(bar());
+        if (isFlagTreated) {
+          foo(1);
+        }
+      }
+    }";
+
+  let mut rule_store = RuleStore::default();
+  let java = get_java_tree_sitter_language();
+  let mut parser = java.parser();
+  let source_code_unit =
+    SourceCodeUnit::default(source_code, &mut parser, java.extension().to_string());
+  let node_for = |snippet: &str| {
+    let start_byte = source_code.find(snippet).unwrap();
+    source_code_unit
+      .root_node()
+      .descendant_for_byte_range(start_byte, start_byte + snippet.len())
+      .unwrap()
+  };
+  let mut is_satisfied = |snippet: &str, rule: &InstantiatedRule| {
+    source_code_unit.is_satisfied(node_for(snippet), rule, &HashMap::new(), &mut rule_store)
+  };
+
+  // The parent of `bar()` is an argument list, while it is three named ancestors below the statement
+  // (the argument list, the invocation of `foo` and the statement)
+  assert!(!is_satisfied("bar()", &in_expression_statement));
+  assert!(is_satisfied("bar()", &close_to_statement));
+  // `foo(1)` is a statement, while it is nested in the `if` statement (five named ancestors below the method)
+  assert!(is_satisfied("foo(1)", &in_expression_statement));
+  assert!(!is_satisfied("foo(1)", &close_to_method));
+  // The enclosing node can be the matched node itself
+  assert!(is_satisfied("foo(1)", &invocation));
+}
+
 // Tests for contains without providing an enclosing node
 fn run_test_satisfies_filters_without_enclosing(
   filter: Filter, // Replace with the filter to test
@@ -699,3 +932,605 @@ fn test_satisfies_outermost_enclosing_node() {
 
   assert!(source_code_unit.is_satisfied(*node, &rule_positive, &HashMap::new(), &mut rule_store,));
 }
+
+#[test]
+fn test_hash() {
+  let java = get_java_tree_sitter_language();
+  let mut parser = java.parser();
+  let mut source_code_unit = |code: &str, substitutions: &[(&str, &str)]| {
+    let mut scu = SourceCodeUnit::default(code, &mut parser, java.extension().to_string());
+    scu.substitutions = substitutions
+      .iter()
+      .map(|(k, v)| (k.to_string(), v.to_string()))
+      .collect();
+    scu
+  };
+  let substitutions = [("a", "1"), ("b", "2"), ("c", "3")];
+  let hash = source_code_unit("class A { }", &substitutions).hash();
+  // The fingerprint does not depend on the order of the substitutions
+  let mut reversed = substitutions;
+  reversed.reverse();
+  assert_eq!(source_code_unit("class A { }", &reversed).hash(), hash);
+  assert_ne!(source_code_unit("class B { }", &substitutions).hash(), hash);
+  assert_ne!(
+    source_code_unit("class A { }", &substitutions[..2]).hash(),
+    hash
+  );
+}
+
+#[test]
+fn test_validate_edits() {
+  let java = get_java_tree_sitter_language();
+  let mut parser = java.parser();
+  // The ranges are in the code the edit is applied to (i.e. after applying the previous edits)
+  let edit = |start_byte: usize, end_byte: usize, replacement: &str| {
+    Edit::new(
+      Match::new(
+        String::new(),
+        range(start_byte, end_byte, 0, start_byte, 0, end_byte),
+        HashMap::new(),
+      ),
+      replacement.to_string(),
+      "rule".to_string(),
+      "",
+    )
+  };
+
+  let source_code = "class A { int a = foo(); }";
+  let mut source_code_unit =
+    SourceCodeUnit::default(source_code, &mut parser, java.extension().to_string());
+  // Replaces `foo()` by `true`, then the enclosing `int a = true;` by `boolean a = true;`
+  source_code_unit.rewrites_mut().push(edit(18, 23, "true"));
+  source_code_unit
+    .rewrites_mut()
+    .push(edit(10, 23, "boolean a = true;"));
+  assert_eq!(source_code_unit.validate_edits(), Ok(()));
+
+  let source_code = "class A { String a = \"é\"; int b = 1; }";
+  let mut source_code_unit =
+    SourceCodeUnit::default(source_code, &mut parser, java.extension().to_string());
+  // Replaces `1` by `10`
+  source_code_unit.rewrites_mut().push(edit(35, 36, "10"));
+  // Replaces `= 1` (partially overlapping the `10` produced by the previous edit)
+  source_code_unit.rewrites_mut().push(edit(33, 36, "= 2"));
+  // Ends beyond the end of the code
+  source_code_unit.rewrites_mut().push(edit(30, 100, "b"));
+  // Ends in the middle of `é`
+  source_code_unit.rewrites_mut().push(edit(22, 23, "e"));
+  assert_eq!(
+    source_code_unit.validate_edits(),
+    Err(vec![
+      EditConflict::Overlap {
+        index: 1,
+        previous_index: 0
+      },
+      EditConflict::OutOfBounds {
+        index: 2,
+        start_byte: 30,
+        end_byte: 100,
+        code_length: 40
+      },
+      EditConflict::InvalidUtf8 {
+        index: 3,
+        start_byte: 22,
+        end_byte: 23
+      },
+    ])
+  );
+}
+
+#[test]
+fn test_apply_rules_transactional() {
+  let rule = |name: &str, from: &str, to: &str| {
+    InstantiatedRule::new(
+      &piranha_rule! {
+        name = name,
+        query = &format!("((decimal_integer_literal) @value (#eq? @value \"{from}\"))"),
+        replace_node = "value",
+        replace = to
+      },
+      &HashMap::new(),
+    )
+  };
+  let replace_1_with_2 = rule("replace_1_with_2", "1", "2");
+  // Produces `int a = 2x;`, which is syntactically incorrect
+  let append_x = rule("append_x", "2", "@valuex");
+
+  let java = get_java_tree_sitter_language();
+  let mut parser = java.parser();
+  let mut rule_store = RuleStore::default();
+  let source_code = "class A { int a = 1; }";
+
+  let mut source_code_unit =
+    SourceCodeUnit::default(source_code, &mut parser, java.extension().to_string());
+  let result = source_code_unit.apply_rules_transactional(
+    &mut rule_store,
+    &[replace_1_with_2.clone(), append_x],
+    &mut parser,
+  );
+  assert_eq!(
+    result,
+    Err(PiranhaError::SyntaxError {
+      rule: "append_x".to_string(),
+      path: source_code_unit.path().clone(),
+    })
+  );
+  // The edit of the first rule is rolled back too
+  assert_eq!(source_code_unit.code(), source_code);
+  assert!(source_code_unit.rewrites().is_empty());
+  assert!(!source_code_unit.root_node().has_error());
+  // Including its (already pruned) input edit
+  assert_eq!(source_code_unit.number_of_applied_edits(), 0);
+
+  let result =
+    source_code_unit.apply_rules_transactional(&mut rule_store, &[replace_1_with_2], &mut parser);
+  assert_eq!(result, Ok(()));
+  assert_eq!(source_code_unit.code(), "class A { int a = 2; }");
+  assert_eq!(source_code_unit.rewrites().len(), 1);
+  assert_eq!(source_code_unit.number_of_applied_edits(), 1);
+}
+
+#[test]
+fn test_exclusive_group() {
+  let rules = |exclusive_group: &str| {
+    [
+      piranha_rule! {
+        name = "flag_treated",
+        query = "((method_invocation name: (_) @name) @mi (#eq? @name \"isTreated\"))",
+        replace_node = "mi",
+        replace = "true",
+        exclusive_group = exclusive_group
+      },
+      // Overlaps with the `flag_treated` rule (it should only match the `true` literals of the original code)
+      piranha_rule! {
+        name = "flag_control",
+        query = "((true) @value)",
+        replace_node = "value",
+        replace = "false",
+        exclusive_group = exclusive_group
+      },
+    ]
+    .map(|rule| InstantiatedRule::new(&rule, &HashMap::new()))
+  };
+  let java = get_java_tree_sitter_language();
+  let mut parser = java.parser();
+  let source_code = "class A { boolean a = exp.isTreated(); boolean b = true; }";
+
+  // Both rules are applied to the invocation
+  let mut rule_store = RuleStore::default();
+  let mut source_code_unit =
+    SourceCodeUnit::default(source_code, &mut parser, java.extension().to_string());
+  source_code_unit.apply_rules(&mut rule_store, &rules(""), &mut parser, None);
+  assert_eq!(
+    source_code_unit.code(),
+    "class A { boolean a = false; boolean b = false; }"
+  );
+
+  // Within a group, the second rule is not applied to the code rewritten by the first one
+  let mut rule_store = RuleStore::default();
+  let mut source_code_unit =
+    SourceCodeUnit::default(source_code, &mut parser, java.extension().to_string());
+  source_code_unit.apply_rules(
+    &mut rule_store,
+    &rules("treatment_handling"),
+    &mut parser,
+    None,
+  );
+  assert_eq!(
+    source_code_unit.code(),
+    "class A { boolean a = true; boolean b = false; }"
+  );
+  assert_eq!(
+    source_code_unit.notes(),
+    &vec!["The rule `flag_control` was not applied at 1:23, since the rule `flag_treated` of the same exclusive group `treatment_handling` already rewrote this code.".to_string()]
+  );
+  assert_eq!(
+    rule_store
+      .get_skip_counts()
+      .get(&("flag_control".to_string(), SkipReason::ExclusiveGroup)),
+    Some(&1)
+  );
+
+  // The guard holds in the later iterations
+  source_code_unit.apply_rules(
+    &mut rule_store,
+    &rules("treatment_handling"),
+    &mut parser,
+    None,
+  );
+  assert_eq!(
+    source_code_unit.code(),
+    "class A { boolean a = true; boolean b = false; }"
+  );
+}
+
+#[test]
+fn test_apply_rules_with_timeout() {
+  let [replace_1_with_2, replace_2_with_3] = [("1", "2"), ("2", "3")].map(|(from, to)| {
+    InstantiatedRule::new(
+      &piranha_rule! {
+        name = &format!("replace_{from}_with_{to}"),
+        query = &format!("((decimal_integer_literal) @value (#eq? @value \"{from}\"))"),
+        replace_node = "value",
+        replace = to
+      },
+      &HashMap::new(),
+    )
+  });
+  let java = get_java_tree_sitter_language();
+  let mut parser = java.parser();
+  let mut rule_store = RuleStore::default();
+  let fields = 10;
+  let source_code = format!(
+    "class A {{\n{}}}",
+    (0..fields)
+      .map(|i| format!("  int a{i} = 1;\n"))
+      .collect::<String>()
+  );
+  let mut source_code_unit =
+    SourceCodeUnit::default(&source_code, &mut parser, java.extension().to_string());
+
+  // The cancellation flag (set by the watchdog of `apply_rules_with_timeout`) is driven directly
+  let cancelled = Arc::new(AtomicBool::new(false));
+  let result = source_code_unit.apply_rules_until_cancelled(
+    &mut rule_store,
+    &[replace_1_with_2],
+    &mut parser,
+    &cancelled,
+  );
+  assert_eq!(result, Ok(()));
+  assert_eq!(source_code_unit.rewrites().len(), fields);
+
+  // No more rule is applied once cancelled, but the edits applied before are kept
+  cancelled.store(true, Ordering::Relaxed);
+  let result = source_code_unit.apply_rules_until_cancelled(
+    &mut rule_store,
+    &[replace_2_with_3.clone()],
+    &mut parser,
+    &cancelled,
+  );
+  assert_eq!(result, Err("replace_2_with_3".to_string()));
+  assert_eq!(source_code_unit.rewrites().len(), fields);
+  assert_eq!(source_code_unit.code().matches("= 2;").count(), fields);
+
+  // The rules are applied again with a generous timeout
+  let result = source_code_unit.apply_rules_with_timeout(
+    &mut rule_store,
+    &[replace_2_with_3],
+    &mut parser,
+    Duration::from_secs(600),
+  );
+  assert_eq!(result, Ok(()));
+  assert_eq!(source_code_unit.rewrites().len(), 2 * fields);
+  assert_eq!(source_code_unit.code().matches("= 3;").count(), fields);
+}
+
+/// Rules rewriting the `1` literals and deleting the `x` arguments (along with their trailing comma)
+fn get_rematch_rules() -> Vec<InstantiatedRule> {
+  [
+    piranha_rule! {
+      name = "replace_1_with_2",
+      query = "((decimal_integer_literal) @value (#eq? @value \"1\"))",
+      replace_node = "value",
+      replace = "2"
+    },
+    piranha_rule! {
+      name = "delete_x",
+      query = "((argument_list (identifier) @arg) (#eq? @arg \"x\"))",
+      replace_node = "arg",
+      replace = ""
+    },
+  ]
+  .iter()
+  .map(|rule| InstantiatedRule::new(rule, &HashMap::new()))
+  .collect()
+}
+
+/// A class with `lines` statements, every `step`-th of which is matched by the `get_rematch_rules`
+fn get_rematch_source_code(lines: usize, step: usize) -> String {
+  let statements = (0..lines)
+    .map(|i| {
+      if i % step == 0 {
+        format!("    int a{i} = 1; foo(x, a{i});\n")
+      } else {
+        format!("    int a{i} = 0; foo(y, a{i});\n")
+      }
+    })
+    .collect::<String>();
+  format!("class A {{\n  void m() {{\n{statements}  }}\n}}\n")
+}
+
+/// Applies the `get_rematch_rules` to the `source_code` with the given `rematch_slack`.
+fn apply_rematch_rules(source_code: &str, rematch_slack: u8) -> SourceCodeUnit {
+  let java = get_java_tree_sitter_language();
+  let mut parser = java.parser();
+  let mut source_code_unit = SourceCodeUnit::new(
+    &mut parser,
+    source_code.to_string(),
+    &HashMap::new(),
+    PathBuf::new().as_path(),
+    &PiranhaArgumentsBuilder::default()
+      .path_to_codebase("some/test/path/".to_string())
+      .language(java)
+      .rematch_slack(rematch_slack)
+      .build(),
+  );
+  source_code_unit.apply_rules(
+    &mut RuleStore::default(),
+    &get_rematch_rules(),
+    &mut parser,
+    None,
+  );
+  source_code_unit
+}
+
+#[test]
+fn test_rematch_within_edited_node() {
+  let (lines, step) = (2000, 20);
+  let source_code = get_rematch_source_code(lines, step);
+  let expected = source_code
+    .replace("= 1;", "= 2;")
+    .replace("foo(x, ", "foo( ");
+
+  let source_code_unit = apply_rematch_rules(&source_code, 2);
+  assert_eq!(source_code_unit.code(), &expected);
+  assert_eq!(source_code_unit.rewrites().len(), 2 * lines / step);
+
+  // The edited node of the root is the scope itself, hence every match is looked up in the entire file
+  let fully_rematched = apply_rematch_rules(&source_code, u8::MAX);
+  let rewritten_ranges = |source_code_unit: &SourceCodeUnit| {
+    source_code_unit
+      .rewrites()
+      .iter()
+      .map(|edit| edit.p_match().range())
+      .collect::<Vec<_>>()
+  };
+  assert_eq!(fully_rematched.code(), source_code_unit.code());
+  assert_eq!(
+    rewritten_ranges(&fully_rematched),
+    rewritten_ranges(&source_code_unit)
+  );
+
+  // Only the edits of the last rule are kept
+  assert_eq!(source_code_unit.number_of_applied_edits(), 2 * lines / step);
+  assert_eq!(source_code_unit.input_edits.len(), lines / step);
+}
+
+/// Compares the time taken to apply the `get_rematch_rules` to a 20k lines file with 500 matches per rule, when
+/// re-matching within the edited node and when re-matching against the entire file.
+/// Run with `cargo test --release -- --ignored bench_rematch_within_edited_node`.
+#[test]
+#[ignore]
+fn bench_rematch_within_edited_node() {
+  let source_code = get_rematch_source_code(20_000, 40);
+  let time = |rematch_slack: u8| {
+    let start = std::time::Instant::now();
+    let source_code_unit = apply_rematch_rules(&source_code, rematch_slack);
+    assert_eq!(source_code_unit.rewrites().len(), 1000);
+    start.elapsed()
+  };
+  let fully_rematched = time(u8::MAX);
+  let rematched_within_edited_node = time(2);
+  assert!(
+    rematched_within_edited_node * 2 < fully_rematched,
+    "{rematched_within_edited_node:?} (within the edited node) vs {fully_rematched:?} (entire file)"
+  );
+}
+
+#[test]
+fn test_apply_rules_on_lines() {
+  let replace_1_with_2 = piranha_rule! {
+    name = "replace_1_with_2",
+    query = "((decimal_integer_literal) @value (#eq? @value \"1\"))",
+    replace_node = "value",
+    replace = "2"
+  };
+  let rename_foo = piranha_rule! {
+    name = "rename_foo",
+    query = "((method_invocation name: (_) @name) (#eq? @name \"foo\"))",
+    replace_node = "name",
+    replace = "bar",
+    is_seed_rule = false
+  };
+  let java = get_java_tree_sitter_language();
+  let piranha_arguments = |propagate: bool| {
+    PiranhaArgumentsBuilder::default()
+      .path_to_codebase("some/test/path/".to_string())
+      .language(java.clone())
+      .rule_graph(
+        RuleGraphBuilder::default()
+          .rules(vec![replace_1_with_2.clone(), rename_foo.clone()])
+          .edges(if propagate {
+            vec![edges! {from = "replace_1_with_2", to = ["rename_foo"], scope = "Class"}]
+          } else {
+            vec![]
+          })
+          .build(),
+      )
+      .build()
+  };
+  let source_code = "class A {\n  int a = 1;\n  int b = 1;\n  int c = 1;\n  void m() { foo(); }\n}";
+  let mut parser = java.parser();
+  let rules = [InstantiatedRule::new(&replace_1_with_2, &HashMap::new())];
+
+  // Only the matches on the lines 1 and 2 are rewritten
+  let mut rule_store = RuleStore::default();
+  let mut source_code_unit = SourceCodeUnit::new(
+    &mut parser,
+    source_code.to_string(),
+    &HashMap::new(),
+    PathBuf::new().as_path(),
+    &piranha_arguments(false),
+  );
+  source_code_unit.apply_rules_on_lines(&mut rule_store, &rules, &mut parser, 1, 2, false);
+  assert_eq!(
+    source_code_unit.code(),
+    "class A {\n  int a = 2;\n  int b = 2;\n  int c = 1;\n  void m() { foo(); }\n}"
+  );
+  // The restriction is lifted afterwards
+  source_code_unit.apply_rules(&mut rule_store, &rules, &mut parser, None);
+  assert!(source_code_unit.code().contains("int c = 2;"));
+
+  // The rules triggered by an edge are restricted too, unless `propagate_outside_range` is set
+  for (propagate_outside_range, expected_invocation) in [(false, "foo();"), (true, "bar();")] {
+    let mut source_code_unit = SourceCodeUnit::new(
+      &mut parser,
+      source_code.to_string(),
+      &HashMap::new(),
+      PathBuf::new().as_path(),
+      &piranha_arguments(true),
+    );
+    source_code_unit.apply_rules_on_lines(
+      &mut rule_store,
+      &rules,
+      &mut parser,
+      1,
+      1,
+      propagate_outside_range,
+    );
+    assert!(source_code_unit.code().contains("int a = 2;\n  int b = 1;"));
+    assert!(source_code_unit.code().contains(expected_invocation));
+  }
+}
+
+#[test]
+fn test_update_line_range() {
+  let java = get_java_tree_sitter_language();
+  let mut parser = java.parser();
+  let source_code = "class A {\n  int a = 1;\n  int b = 1;\n}";
+  let mut source_code_unit =
+    SourceCodeUnit::default(source_code, &mut parser, java.extension().to_string());
+  // The line `  int a = 1;`
+  source_code_unit.line_range = Some((10, 23));
+
+  let mut apply_edit = |start_byte: usize, end_byte: usize, replacement: &str| {
+    let edit = Edit::replace_offsets(source_code_unit.code(), start_byte, end_byte, replacement);
+    source_code_unit.apply_edit(&edit, &mut parser).unwrap();
+    source_code_unit.line_range
+  };
+  // An edit before the range shifts it
+  assert_eq!(apply_edit(0, 0, "// A\n"), Some((15, 28)));
+  // An edit within the range grows (or shrinks) it
+  assert_eq!(apply_edit(25, 26, "100"), Some((15, 30)));
+  // An edit after the range does not change it
+  assert_eq!(apply_edit(40, 41, "100"), Some((15, 30)));
+}
+
+#[test]
+fn test_refactor_rename_symbol() {
+  let java = get_java_tree_sitter_language();
+  let mut parser = java.parser();
+  let mut rule_store = RuleStore::default();
+  let source_code =
+    "class A { Foo foo = new Foo(); int bar() { String s = \"foo\"; return foo.size() + foo.x; } }";
+
+  let mut source_code_unit =
+    SourceCodeUnit::default(source_code, &mut parser, java.extension().to_string());
+  let count = source_code_unit.refactor_rename_symbol("foo", "baz", &mut parser, &mut rule_store);
+  assert_eq!(count, 3);
+  // The string literal is not renamed
+  assert_eq!(
+    source_code_unit.code(),
+    "class A { Foo baz = new Foo(); int bar() { String s = \"foo\"; return baz.size() + baz.x; } }"
+  );
+
+  // The type identifiers are renamed too
+  let count = source_code_unit.refactor_rename_symbol("Foo", "Bar", &mut parser, &mut rule_store);
+  assert_eq!(count, 2);
+  assert!(source_code_unit
+    .code()
+    .starts_with("class A { Bar baz = new Bar();"));
+
+  assert_eq!(
+    source_code_unit.refactor_rename_symbol("missing", "other", &mut parser, &mut rule_store),
+    0
+  );
+  assert_eq!(source_code_unit.rewrites().len(), 5);
+}
+
+#[test]
+fn test_refactor_rename_symbol_verbatim() {
+  let c_sharp = PiranhaLanguage::from(C_SHARP);
+  let mut parser = c_sharp.parser();
+  let mut rule_store = RuleStore::default();
+  let source_code = "class A { int kind = 1; int Get() { return kind; } }";
+
+  let mut source_code_unit =
+    SourceCodeUnit::default(source_code, &mut parser, c_sharp.extension().to_string());
+  // The `@` of the verbatim identifier is not treated as a tag of a replacement template
+  let count =
+    source_code_unit.refactor_rename_symbol("kind", "@class", &mut parser, &mut rule_store);
+  assert_eq!(count, 2);
+  assert_eq!(
+    source_code_unit.code(),
+    "class A { int @class = 1; int Get() { return @class; } }"
+  );
+}
+
+#[test]
+fn test_replace_all_literals() {
+  let source_code = "class A { int a = OLD_NAME; int b = OLD_NAME + 1; String c = \"OLD_NAME\"; }";
+  let java = get_java_tree_sitter_language();
+  let mut parser = java.parser();
+  let mut source_code_unit =
+    SourceCodeUnit::default(source_code, &mut parser, java.extension().to_string());
+  assert_eq!(
+    source_code_unit.replace_all_literals("OLD_NAME", "NEW_NAME", &mut parser),
+    Ok(3)
+  );
+  let expected = "class A { int a = NEW_NAME; int b = NEW_NAME + 1; String c = \"NEW_NAME\"; }";
+  assert_eq!(source_code_unit.code(), expected);
+  assert!(!source_code_unit.root_node().has_error());
+  // Each replacement is reported, and the tree is the one of the updated code
+  assert_eq!(source_code_unit.rewrites().len(), 3);
+  assert!(source_code_unit
+    .rewrites()
+    .iter()
+    .all(|edit| edit.matched_rule() == REPLACE_ALL_LITERALS_RULE));
+  assert_eq!(source_code_unit.validate_edits(), Ok(()));
+  assert_eq!(
+    source_code_unit.root_node().to_sexp(),
+    parser.parse(expected, None).unwrap().root_node().to_sexp()
+  );
+
+  // The replacement would introduce a syntax error, hence it is rolled back
+  assert_eq!(
+    source_code_unit.replace_all_literals("NEW_NAME;", "NEW_NAME", &mut parser),
+    Err(PiranhaError::SyntaxError {
+      rule: REPLACE_ALL_LITERALS_RULE.to_string(),
+      path: source_code_unit.path().clone(),
+    })
+  );
+  assert_eq!(source_code_unit.code(), expected);
+  assert_eq!(source_code_unit.rewrites().len(), 3);
+  assert!(!source_code_unit.root_node().has_error());
+  assert_eq!(
+    source_code_unit.replace_all_literals("MISSING", "NAME", &mut parser),
+    Ok(0)
+  );
+}
+
+#[test]
+fn test_global_substitutions_with_custom_prefix() {
+  let args = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(UNUSED_CODE_PATH.to_string())
+    .global_tag_prefix("ORG_".to_string())
+    .build();
+  let mut parser = args.language().parser();
+  let substitutions = HashMap::from([
+    ("ORG_flag".to_string(), "STALE_FLAG".to_string()),
+    ("GLOBAL_TAG.flag".to_string(), "OTHER_FLAG".to_string()),
+    ("flag".to_string(), "LOCAL_FLAG".to_string()),
+  ]);
+  let source_code_unit = SourceCodeUnit::new(
+    &mut parser,
+    "class A {}".to_string(),
+    &substitutions,
+    PathBuf::new().as_path(),
+    &args,
+  );
+  assert_eq!(
+    source_code_unit.global_substitutions(),
+    HashMap::from([("ORG_flag".to_string(), "STALE_FLAG".to_string())])
+  );
+}
@@ -0,0 +1,151 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use std::{collections::HashMap, fs};
+
+use tempdir::TempDir;
+
+use crate::{
+  analyze,
+  models::{
+    default_configs::JAVA, edit::Edit, language::PiranhaLanguage, matches::Match,
+    piranha_arguments::PiranhaArgumentsBuilder, rule_graph::RuleGraphBuilder,
+    source_code_unit::SourceCodeUnit,
+  },
+  piranha_rule,
+  utilities::tree_sitter_utilities::get_range_for_offsets,
+};
+
+#[test]
+fn test_analyze_and_view() {
+  let temp_dir = TempDir::new_in(".", "source_code_view").unwrap();
+  let code = "class A {\n  void m() {\n    foo(FLAG);\n    bar();\n  }\n}\n";
+  fs::write(temp_dir.path().join("A.java"), code).unwrap();
+  fs::write(temp_dir.path().join("B.java"), "class B {}\n").unwrap();
+  let rules = vec![
+    piranha_rule! {
+      name = "find_calls",
+      query = "((method_invocation name: (_) @name) @call)"
+    },
+    piranha_rule! {
+      name = "replace_bar",
+      query = "((method_invocation name: (_) @name) @call (#eq? @name \"bar\"))",
+      replace_node = "call",
+      replace = "baz()"
+    },
+  ];
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rule_graph(RuleGraphBuilder::default().rules(rules).build())
+    .build();
+
+  let analysis = analyze(&piranha_arguments).unwrap();
+  // Only the file with matches (or rewrites) is returned, and nothing is written
+  assert_eq!(analysis.len(), 1);
+  assert_eq!(
+    fs::read_to_string(temp_dir.path().join("A.java")).unwrap(),
+    code
+  );
+
+  let view = analysis.views().next().unwrap();
+  assert_eq!(view.path(), temp_dir.path().join("A.java"));
+  assert_eq!(view.original_content(), code);
+  assert!(view.code().contains("baz();"));
+  assert_eq!(view.rewrites().len(), 1);
+  let names = view
+    .matches()
+    .iter()
+    .map(|(rule_name, m)| (rule_name.as_str(), m.matches()["name"].as_str()))
+    .collect::<Vec<_>>();
+  assert!(names.contains(&("find_calls", "foo")));
+  assert_eq!(view.root_node().kind(), "program");
+  let calls = view
+    .nodes()
+    .filter(|n| n.kind() == "method_invocation")
+    .map(|n| view.node_text(n))
+    .collect::<Vec<_>>();
+  assert_eq!(calls, vec!["foo(FLAG)", "baz()"]);
+}
+
+#[test]
+fn test_node_at_position() {
+  let code = "class Test {\n  void foobar() {}\n}\n";
+  let java = PiranhaLanguage::from(JAVA);
+  let mut parser = java.parser();
+  let source_code_unit = SourceCodeUnit::default(code, &mut parser, java.extension().to_string());
+  let view = source_code_unit.view();
+
+  let node = view.node_at_position(1, 8).unwrap();
+  assert_eq!(node.kind(), "identifier");
+  assert_eq!(view.node_text(node), "foobar");
+  assert_eq!(view.node_at_position(0, 0).unwrap().kind(), "class");
+  // The positions outside the code
+  assert!(view.node_at_position(1, 40).is_none());
+  assert!(view.node_at_position(10, 0).is_none());
+}
+
+#[test]
+fn test_coverage() {
+  let code = "class A { int a = foo(); int b = foo(); int c = bar(); }";
+  let java = PiranhaLanguage::from(JAVA);
+  let mut parser = java.parser();
+  let mut source_code_unit =
+    SourceCodeUnit::default(code, &mut parser, java.extension().to_string());
+  assert_eq!(source_code_unit.view().coverage(), vec![]);
+  assert_eq!(source_code_unit.view().coverage_ratio(), 0.0);
+
+  let p_match = |start_byte: usize, end_byte: usize| {
+    Match::new(
+      code[start_byte..end_byte].to_string(),
+      get_range_for_offsets(code, start_byte, end_byte),
+      HashMap::new(),
+    )
+  };
+  // The matches of `foo()` and `int b = foo();` overlap, and so do the rewrites of `bar()` and `int c = bar();`
+  for (start_byte, end_byte) in [(18, 23), (25, 39), (29, 38)] {
+    source_code_unit.record_match("rule".to_string(), p_match(start_byte, end_byte));
+  }
+  for (start_byte, end_byte) in [(48, 53), (40, 54)] {
+    source_code_unit.rewrites_mut().push(Edit::new(
+      p_match(start_byte, end_byte),
+      "0".to_string(),
+      "rule".to_string(),
+      code,
+    ));
+  }
+  let view = source_code_unit.view();
+  assert_eq!(
+    view.coverage(),
+    vec![
+      get_range_for_offsets(code, 18, 23),
+      get_range_for_offsets(code, 25, 39),
+      get_range_for_offsets(code, 40, 54),
+    ]
+  );
+  assert_eq!(view.coverage_ratio(), 33.0 / 56.0);
+}
+
+#[test]
+fn test_count_matches() {
+  let code = "class A { int a = foo(foo(1)); int b = foo(2); int c = bar(); }";
+  let java = PiranhaLanguage::from(JAVA);
+  let mut parser = java.parser();
+  let source_code_unit = SourceCodeUnit::default(code, &mut parser, java.extension().to_string());
+  let view = source_code_unit.view();
+  let query = "((method_invocation name: (_) @name) @invocation (#eq? @name \"foo\"))";
+  // The nested invocation is not counted
+  assert_eq!(view.count_matches(query), Ok(2));
+  assert_eq!(view.count_matches("(lambda_expression) @lambda"), Ok(0));
+  assert!(view.count_matches("(not_a_node_kind) @node").is_err());
+}
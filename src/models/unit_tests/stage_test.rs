@@ -0,0 +1,108 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use super::{ContinueOn, Stage};
+use crate::{
+  models::{rule::Rules, rule_graph::RuleGraphBuilder},
+  piranha_rule,
+};
+
+#[test]
+fn test_should_continue() {
+  let (failed, has_warnings) = (true, true);
+  let warnings = Stage::new("s", &["r"], ContinueOn::Warnings);
+  assert!(warnings.should_continue(!failed, has_warnings));
+  assert!(!warnings.should_continue(failed, !has_warnings));
+
+  let never = Stage::new("s", &["r"], ContinueOn::Never);
+  assert!(never.should_continue(!failed, !has_warnings));
+  assert!(!never.should_continue(!failed, has_warnings));
+  assert!(!never.should_continue(failed, !has_warnings));
+
+  let always = Stage::new("s", &["r"], ContinueOn::Always);
+  assert!(always.should_continue(failed, has_warnings));
+}
+
+#[test]
+fn test_stages_from_toml() {
+  let rules: Rules = toml::from_str(
+    r#"
+[[stages]]
+name = "first"
+rules = ["a"]
+
+[[stages]]
+name = "second"
+rules = ["b", "group"]
+continue_on = "never"
+
+[[rules]]
+name = "a"
+"#,
+  )
+  .unwrap();
+  assert_eq!(
+    rules.stages,
+    vec![
+      Stage::new("first", &["a"], ContinueOn::Warnings),
+      Stage::new("second", &["b", "group"], ContinueOn::Never),
+    ]
+  );
+}
+
+fn build_rule_graph_with_stages(stages: Vec<Stage>) {
+  RuleGraphBuilder::default()
+    .rules(vec![
+      piranha_rule! {
+        name = "seed",
+        query = "(method_invocation) @mi",
+        groups = ["seeds"]
+      },
+      piranha_rule! {
+        name = "not_seed",
+        query = "(method_invocation) @mi",
+        is_seed_rule = false
+      },
+    ])
+    .stages(stages)
+    .build();
+}
+
+#[test]
+fn test_valid_stages() {
+  build_rule_graph_with_stages(vec![
+    Stage::new("by_name", &["seed"], ContinueOn::Warnings),
+    Stage::new("by_group", &["seeds"], ContinueOn::Never),
+  ]);
+}
+
+#[test]
+#[should_panic(expected = "The stage `s` lists `not_seed`, which is neither a seed rule")]
+fn test_stage_with_non_seed_rule() {
+  build_rule_graph_with_stages(vec![Stage::new("s", &["not_seed"], ContinueOn::Warnings)]);
+}
+
+#[test]
+#[should_panic(expected = "The stage `s` lists `unknown`, which is neither a seed rule")]
+fn test_stage_with_unknown_rule() {
+  build_rule_graph_with_stages(vec![Stage::new("s", &["unknown"], ContinueOn::Warnings)]);
+}
+
+#[test]
+#[should_panic(expected = "The stage `s` is declared more than once")]
+fn test_duplicate_stages() {
+  build_rule_graph_with_stages(vec![
+    Stage::new("s", &["seed"], ContinueOn::Warnings),
+    Stage::new("s", &["seeds"], ContinueOn::Always),
+  ]);
+}
@@ -0,0 +1,120 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use std::path::Path;
+
+use super::{get_suppressions, is_file_disabled, Suppression};
+use crate::models::{default_configs::JAVA, language::PiranhaLanguage};
+
+fn suppressions_of(code: &str) -> Vec<Suppression> {
+  let language = PiranhaLanguage::from(JAVA);
+  let tree = language.parser().parse(code, None).unwrap();
+  get_suppressions(
+    tree.root_node(),
+    code,
+    language.comment_nodes(),
+    Path::new("A.java"),
+  )
+}
+
+/// The byte range of the (first) `snippet` in the `code`.
+fn range_of(code: &str, snippet: &str) -> (usize, usize) {
+  let start_byte = code.find(snippet).unwrap();
+  (start_byte, start_byte + snippet.len())
+}
+
+#[test]
+fn test_disable_next_line() {
+  let code = "class A {\n  // piranha:disable-next-line\n  int a = 1;\n  int b = 2;\n}\n";
+  let suppressions = suppressions_of(code);
+  assert_eq!(suppressions.len(), 1);
+  let (start_a, end_a) = range_of(code, "int a = 1;");
+  let (start_b, end_b) = range_of(code, "int b = 2;");
+  assert!(suppressions[0].suppresses("any_rule", start_a, end_a));
+  assert!(!suppressions[0].suppresses("any_rule", start_b, end_b));
+}
+
+#[test]
+fn test_disable_enable_block() {
+  let code = "class A {\n  int a = 1;\n  /* piranha:disable */\n  int b = 2;\n  int c = 3;\n  // piranha:enable\n  int d = 4;\n}\n";
+  let suppressions = suppressions_of(code);
+  assert_eq!(suppressions.len(), 1);
+  for (snippet, is_suppressed) in [
+    ("int a = 1;", false),
+    ("int b = 2;", true),
+    ("int c = 3;", true),
+    ("int d = 4;", false),
+  ] {
+    let (start_byte, end_byte) = range_of(code, snippet);
+    assert_eq!(
+      suppressions[0].suppresses("any_rule", start_byte, end_byte),
+      is_suppressed,
+      "{snippet}"
+    );
+  }
+}
+
+#[test]
+fn test_rule_scoped_directives() {
+  let code = "class A {\n  // piranha:disable rule_a, rule_b\n  // piranha:disable rule_c\n  int a = 1;\n  // piranha:enable rule_a, rule_b\n  int b = 2;\n}\n";
+  let suppressions = suppressions_of(code);
+  let is_suppressed = |rule_name: &str, snippet: &str| {
+    let (start_byte, end_byte) = range_of(code, snippet);
+    suppressions
+      .iter()
+      .any(|s| s.suppresses(rule_name, start_byte, end_byte))
+  };
+  assert!(is_suppressed("rule_a", "int a = 1;"));
+  assert!(is_suppressed("rule_b", "int a = 1;"));
+  assert!(is_suppressed("rule_c", "int a = 1;"));
+  assert!(!is_suppressed("rule_d", "int a = 1;"));
+  // Only the block of `rule_a` and `rule_b` is closed, the one of `rule_c` runs until the end of the file
+  assert!(!is_suppressed("rule_a", "int b = 2;"));
+  assert!(is_suppressed("rule_c", "int b = 2;"));
+}
+
+#[test]
+fn test_directives_outside_comments_ignored() {
+  let code = "class A {\n  String s = \"piranha:disable\";\n  int a = 1;\n}\n";
+  assert!(suppressions_of(code).is_empty());
+}
+
+#[test]
+fn test_all_rules_directive() {
+  let code = "class A {\n  // piranha:disable-next-line all\n  int a = 1;\n}\n";
+  let suppressions = suppressions_of(code);
+  let (start_byte, end_byte) = range_of(code, "int a = 1;");
+  assert!(suppressions[0].suppresses("any_rule", start_byte, end_byte));
+}
+
+#[test]
+fn test_is_file_disabled() {
+  let language = PiranhaLanguage::from(JAVA);
+  let is_disabled = |code: &str| {
+    let tree = language.parser().parse(code, None).unwrap();
+    is_file_disabled(tree.root_node(), code, language.comment_nodes())
+  };
+  assert!(is_disabled("// piranha:disable\nclass A {}\n"));
+  assert!(is_disabled(
+    "/* Copyright */\n// piranha:disable all\npackage a;\nclass A {}\n"
+  ));
+  // Not before the code
+  assert!(!is_disabled("package a;\n// piranha:disable\nclass A {}\n"));
+  // Restricted to some rules
+  assert!(!is_disabled("// piranha:disable rule_a\nclass A {}\n"));
+  // Closed later
+  assert!(!is_disabled(
+    "// piranha:disable\nclass A {}\n// piranha:enable\nclass B {}\n"
+  ));
+  assert!(!is_disabled("// piranha:disable-next-line\nclass A {}\n"));
+}
@@ -0,0 +1,138 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use crate::models::{default_configs::JAVA, filter::Filter, language::PiranhaLanguage};
+
+use super::{xpath_to_queries, xpath_to_query, XPathError};
+
+fn translate(xpath: &str) -> Result<String, XPathError> {
+  xpath_to_query(xpath, &PiranhaLanguage::from(JAVA))
+}
+
+#[test]
+fn test_xpath_attribute_predicate() {
+  assert_eq!(
+    translate("//method_declaration[@name='setUp']").unwrap(),
+    "(
+(method_declaration name: (_) @xpath_0_name) @xpath
+(#eq? @xpath_0_name \"setUp\")
+)"
+  );
+}
+
+#[test]
+fn test_xpath_child_axis() {
+  assert_eq!(
+    translate(
+      "/class_declaration[@name=\"FooTest\"]/class_body/method_declaration[@name='setUp' and @body]"
+    )
+    .unwrap(),
+    "(
+(class_declaration name: (_) @xpath_0_name (class_body (method_declaration name: (_) @xpath_2_name body: (_)))) @xpath
+(#eq? @xpath_0_name \"FooTest\")
+(#eq? @xpath_2_name \"setUp\")
+)"
+  );
+}
+
+#[test]
+fn test_xpath_wildcard() {
+  assert_eq!(
+    translate("//*[@name='foo']").unwrap(),
+    "(
+(_ name: (_) @xpath_0_name) @xpath
+(#eq? @xpath_0_name \"foo\")
+)"
+  );
+  assert_eq!(
+    translate("//method_invocation/*").unwrap(),
+    "(
+(method_invocation (_)) @xpath
+)"
+  );
+}
+
+#[test]
+fn test_xpath_descendant_axis_between_steps() {
+  assert_eq!(
+    xpath_to_queries(
+      "//class_declaration/class_body//method_declaration[@name='setUp']",
+      &PiranhaLanguage::from(JAVA)
+    )
+    .unwrap(),
+    vec![
+      "(
+(class_declaration (class_body)) @xpath
+)",
+      "(
+(method_declaration name: (_) @xpath_0_name) @xpath
+(#eq? @xpath_0_name \"setUp\")
+)"
+    ]
+  );
+}
+
+#[test]
+fn test_filter_from_xpath() {
+  let java = PiranhaLanguage::from(JAVA);
+  let filter = Filter::from_xpath("//class_declaration//method_declaration", &java).unwrap();
+  assert_eq!(
+    filter.enclosing_node().pattern(),
+    "(\n(class_declaration) @xpath\n)"
+  );
+  assert_eq!(
+    filter.contains().pattern(),
+    "(\n(method_declaration) @xpath\n)"
+  );
+  assert!(matches!(
+    Filter::from_xpath("//program//class_declaration//method_declaration", &java),
+    Err(XPathError::Unsupported(_))
+  ));
+}
+
+#[test]
+fn test_xpath_errors() {
+  assert_eq!(
+    translate("//class_declaration//method_declaration"),
+    Err(XPathError::Unsupported(
+      "the descendant axis (`//`) between two steps cannot be expressed as a single query"
+        .to_string()
+    ))
+  );
+  assert_eq!(
+    translate("//method_decl"),
+    Err(XPathError::UnknownNodeKind("method_decl".to_string()))
+  );
+  assert_eq!(
+    translate("//method_declaration[@title='setUp']"),
+    Err(XPathError::UnknownAttribute("title".to_string()))
+  );
+  assert_eq!(
+    translate("//method_declaration[@name='setUp'"),
+    Err(XPathError::Syntax {
+      position: 34,
+      message: "expected `and` or `]`".to_string()
+    })
+  );
+  assert_eq!(
+    translate("//method_declaration[@name=setUp]"),
+    Err(XPathError::Syntax {
+      position: 27,
+      message: "expected a quoted value".to_string()
+    })
+  );
+  assert!(matches!(
+    translate("//method_declaration[1]"),
+    Err(XPathError::Unsupported(_))
+  ));
+}
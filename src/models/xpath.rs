@@ -0,0 +1,289 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+//! Translates the XPath-like expressions (e.g. `//method_declaration[@name='setUp']`) to the equivalent tree-sitter
+//! queries, for the users familiar with XPath from the XML refactoring tools. The supported subset is:
+//! * the node tests, i.e. the kind of a (named) node, or `*` for any kind,
+//! * the child axis (`/`) between two steps, i.e. the node of the second step is a (named) child of the first one,
+//! * the descendant axis (`//`) before the first step, i.e. the path is matched anywhere,
+//! * the attribute predicates, where an attribute is a field of the node: `[@name]` (the field is present) and
+//!   `[@name='value']` (its code is equal to the value), possibly combined with `and`.
+//!
+//! Tree-sitter queries cannot express the descendant axis between two steps (a child pattern only matches
+//! the direct children), hence such a path is split into the queries of its parts (see `xpath_to_queries`),
+//! e.g. to be nested in a filter (see `Filter::from_xpath`).
+
+use std::fmt;
+
+use itertools::Itertools;
+
+use super::language::PiranhaLanguage;
+
+/// The tag of the node matched by the first step of the path, in the translated query
+pub const XPATH_TAG: &str = "xpath";
+
+/// Why an XPath-like expression could not be translated to a tree-sitter query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XPathError {
+  /// The expression is not well-formed, at the given (byte) position
+  Syntax { position: usize, message: String },
+  /// The node kind of a step is not defined by the grammar of the language
+  UnknownNodeKind(String),
+  /// The attribute (i.e. field) of a predicate is not defined by the grammar of the language
+  UnknownAttribute(String),
+  /// The expression is well-formed, but cannot be expressed as a tree-sitter query
+  Unsupported(String),
+}
+
+impl fmt::Display for XPathError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      XPathError::Syntax { position, message } => {
+        write!(f, "Invalid XPath at position {position} - {message}")
+      }
+      XPathError::UnknownNodeKind(kind) => write!(f, "Unknown node kind `{kind}`"),
+      XPathError::UnknownAttribute(attribute) => {
+        write!(
+          f,
+          "Unknown attribute `@{attribute}` (not a field of the grammar)"
+        )
+      }
+      XPathError::Unsupported(message) => write!(f, "Unsupported XPath - {message}"),
+    }
+  }
+}
+
+impl std::error::Error for XPathError {}
+
+// A step of the path, e.g. `method_declaration[@name='setUp']`
+#[derive(Debug)]
+struct Step {
+  // The node kind (`None` for `*`)
+  kind: Option<String>,
+  // The attributes (fields), along with the value of their code (if any)
+  predicates: Vec<(String, Option<String>)>,
+}
+
+/// Translates the XPath-like `xpath` (see above) to the equivalent tree-sitter query for the `language`.
+/// The node matched by the first step is tagged `@xpath` (see `XPATH_TAG`).
+/// The descendant axis (`//`) between two steps is rejected (see `xpath_to_queries`).
+pub fn xpath_to_query(xpath: &str, language: &PiranhaLanguage) -> Result<String, XPathError> {
+  let mut queries = xpath_to_queries(xpath, language)?;
+  if queries.len() > 1 {
+    return Err(XPathError::Unsupported(
+      "the descendant axis (`//`) between two steps cannot be expressed as a single query"
+        .to_string(),
+    ));
+  }
+  Ok(queries.remove(0))
+}
+
+/// Translates the XPath-like `xpath` (see above) to the tree-sitter queries of its parts separated by the descendant
+/// axis (`//`) between two steps, for the `language`. E.g. `//class_declaration//method_declaration` is translated
+/// to the query of `class_declaration` and the query of `method_declaration` (a descendant of the former).
+pub fn xpath_to_queries(
+  xpath: &str, language: &PiranhaLanguage,
+) -> Result<Vec<String>, XPathError> {
+  Parser::new(xpath)
+    .parse()?
+    .iter()
+    .map(|steps| steps_to_query(steps, language))
+    .collect()
+}
+
+// Translates the `steps` separated by the child axis (`/`) to the equivalent tree-sitter query
+fn steps_to_query(steps: &[Step], language: &PiranhaLanguage) -> Result<String, XPathError> {
+  let ts_language = language.language();
+  for step in steps {
+    if let Some(kind) = &step.kind {
+      if ts_language.id_for_node_kind(kind, true) == 0 {
+        return Err(XPathError::UnknownNodeKind(kind.to_string()));
+      }
+    }
+    for (attribute, _) in &step.predicates {
+      if ts_language.field_id_for_name(attribute).is_none() {
+        return Err(XPathError::UnknownAttribute(attribute.to_string()));
+      }
+    }
+  }
+
+  // The steps are nested (from the last one), the predicates on the codes of the fields are listed after the pattern
+  let mut pattern = String::new();
+  let mut eq_predicates = vec![];
+  for (i, step) in steps.iter().enumerate().rev() {
+    let fields = step
+      .predicates
+      .iter()
+      .map(|(attribute, value)| match value {
+        Some(value) => {
+          let tag = format!("{XPATH_TAG}_{i}_{attribute}");
+          eq_predicates.push(format!("(#eq? @{tag} \"{}\")", escape(value)));
+          format!("{attribute}: (_) @{tag}")
+        }
+        None => format!("{attribute}: (_)"),
+      })
+      .collect_vec();
+    pattern = format!(
+      "({}{}{})",
+      step.kind.as_deref().unwrap_or("_"),
+      fields.iter().map(|f| format!(" {f}")).join(""),
+      if pattern.is_empty() {
+        String::new()
+      } else {
+        format!(" {pattern}")
+      }
+    );
+  }
+  eq_predicates.reverse();
+  Ok(format!(
+    "(\n{}\n)",
+    std::iter::once(format!("{pattern} @{XPATH_TAG}"))
+      .chain(eq_predicates)
+      .join("\n")
+  ))
+}
+
+// Escapes the value of a predicate as a tree-sitter string
+pub(crate) fn escape(value: &str) -> String {
+  value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// A recursive descent parser of the supported subset of XPath
+struct Parser<'a> {
+  xpath: &'a str,
+  position: usize,
+}
+
+impl<'a> Parser<'a> {
+  fn new(xpath: &'a str) -> Self {
+    Parser { xpath, position: 0 }
+  }
+
+  // path := ('/' | '//')? step (('/' | '//') step)*
+  // The steps are grouped by the descendant axis (`//`) between them
+  fn parse(mut self) -> Result<Vec<Vec<Step>>, XPathError> {
+    self.skip_whitespace();
+    // The path is matched anywhere, hence the leading axis makes no difference
+    if !self.eat("//") {
+      self.eat("/");
+    }
+    let mut parts = vec![vec![self.step()?]];
+    loop {
+      self.skip_whitespace();
+      if self.position == self.xpath.len() {
+        return Ok(parts);
+      }
+      if self.eat("//") {
+        parts.push(vec![]);
+      } else if !self.eat("/") {
+        return Err(self.error("expected `/` or the end of the path"));
+      }
+      let step = self.step()?;
+      parts.last_mut().unwrap().push(step);
+    }
+  }
+
+  // step := (identifier | '*') ('[' predicate ('and' predicate)* ']')*
+  fn step(&mut self) -> Result<Step, XPathError> {
+    self.skip_whitespace();
+    let kind = if self.eat("*") {
+      None
+    } else {
+      Some(self.identifier("a node kind or `*`")?)
+    };
+    let mut predicates = vec![];
+    loop {
+      self.skip_whitespace();
+      if !self.eat("[") {
+        return Ok(Step { kind, predicates });
+      }
+      loop {
+        predicates.push(self.predicate()?);
+        self.skip_whitespace();
+        if self.eat("]") {
+          break;
+        }
+        if !self.eat("and") {
+          return Err(self.error("expected `and` or `]`"));
+        }
+      }
+    }
+  }
+
+  // predicate := '@' identifier ('=' literal)?
+  fn predicate(&mut self) -> Result<(String, Option<String>), XPathError> {
+    self.skip_whitespace();
+    if !self.eat("@") {
+      return Err(XPathError::Unsupported(format!(
+        "only the attribute predicates (e.g. `[@name='value']`) are supported, at position {}",
+        self.position
+      )));
+    }
+    let attribute = self.identifier("an attribute")?;
+    self.skip_whitespace();
+    if !self.eat("=") {
+      return Ok((attribute, None));
+    }
+    self.skip_whitespace();
+    Ok((attribute, Some(self.literal()?)))
+  }
+
+  // literal := "'" [^']* "'" | '"' [^"]* '"'
+  fn literal(&mut self) -> Result<String, XPathError> {
+    let rest = &self.xpath[self.position..];
+    let Some(quote) = rest.chars().next().filter(|c| *c == '\'' || *c == '"') else {
+      return Err(self.error("expected a quoted value"));
+    };
+    let Some(end) = rest[1..].find(quote) else {
+      return Err(self.error("unterminated value"));
+    };
+    self.position += end + 2;
+    Ok(rest[1..end + 1].to_string())
+  }
+
+  fn identifier(&mut self, expected: &str) -> Result<String, XPathError> {
+    let rest = &self.xpath[self.position..];
+    let length = rest
+      .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+      .unwrap_or(rest.len());
+    if length == 0 {
+      return Err(self.error(&format!("expected {expected}")));
+    }
+    self.position += length;
+    Ok(rest[..length].to_string())
+  }
+
+  fn eat(&mut self, token: &str) -> bool {
+    if self.xpath[self.position..].starts_with(token) {
+      self.position += token.len();
+      return true;
+    }
+    false
+  }
+
+  fn skip_whitespace(&mut self) {
+    let rest = &self.xpath[self.position..];
+    self.position += rest.len() - rest.trim_start().len();
+  }
+
+  fn error(&self, message: &str) -> XPathError {
+    XPathError::Syntax {
+      position: self.position,
+      message: message.to_string(),
+    }
+  }
+}
+
+#[cfg(test)]
+#[path = "unit_tests/xpath_test.rs"]
+mod xpath_test;
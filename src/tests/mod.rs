@@ -29,15 +29,19 @@ mod test_piranha_swift;
 
 mod test_piranha_python;
 
+mod test_piranha_cs;
 mod test_piranha_go;
 mod test_piranha_ts;
 mod test_piranha_tsx;
 
+mod test_piranha_proto;
 mod test_piranha_thrift;
 
 mod test_piranha_scm;
 mod test_piranha_strings;
 
+mod test_piranha_custom_language;
+
 use std::sync::Once;
 
 static INIT: Once = Once::new();
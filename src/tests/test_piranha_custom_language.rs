@@ -0,0 +1,224 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use std::{fs, path::Path, process::Command, str::FromStr};
+
+use tempdir::TempDir;
+
+use super::initialize;
+use crate::{
+  edges, execute_piranha,
+  models::{
+    language::{
+      register_language, register_language_from_library, LanguageDefinition, PiranhaLanguage,
+    },
+    piranha_arguments::PiranhaArgumentsBuilder,
+    rule_graph::RuleGraphBuilder,
+  },
+  piranha_rule,
+  utilities::read_file,
+};
+
+/// A language registered at runtime. Since no grammar other than the built-in ones is linked, it reuses
+/// the Python grammar, under another name and other extensions.
+struct CustomLanguage {
+  name: &'static str,
+  extensions: &'static [&'static str],
+  scope_config: &'static str,
+}
+
+impl LanguageDefinition for CustomLanguage {
+  fn name(&self) -> String {
+    self.name.to_string()
+  }
+
+  fn tree_sitter_language(&self) -> tree_sitter::Language {
+    tree_sitter_python::language()
+  }
+
+  fn extensions(&self) -> Vec<String> {
+    self.extensions.iter().map(|e| e.to_string()).collect()
+  }
+
+  fn comment_nodes(&self) -> Vec<String> {
+    vec!["comment".to_string()]
+  }
+
+  fn scope_config(&self) -> Option<String> {
+    Some(self.scope_config.to_string())
+  }
+}
+
+static FUNCTION_SCOPE_CONFIG: &str = r#"
+[[scopes]]
+name = "Function"
+[[scopes.rules]]
+enclosing_node = "((function_definition name: (_) @n) @xdn)"
+scope = """((function_definition name: (_) @z) @qdn (#eq? @z "@n"))"""
+"#;
+
+#[test]
+fn test_custom_language() {
+  initialize();
+  register_language(&CustomLanguage {
+    name: "custom_py",
+    extensions: &["cpy", "cpyi"],
+    scope_config: FUNCTION_SCOPE_CONFIG,
+  })
+  .unwrap();
+  assert_eq!(PiranhaLanguage::from("custom_py").scopes().len(), 1);
+
+  let temp_dir = TempDir::new_in(".", "tmp_test").unwrap();
+  let code = "def f():\n    if is_enabled():\n        log()\n\ndef g():\n    log()\n";
+  for file_name in ["a.cpy", "b.cpyi", "c.py"] {
+    fs::write(temp_dir.path().join(file_name), code).unwrap();
+  }
+
+  let rules = vec![
+    piranha_rule! {
+      name = "replace_is_enabled",
+      query = "((call function: (identifier) @f) @call (#eq? @f \"is_enabled\"))",
+      replace_node = "call",
+      replace = "True"
+    },
+    piranha_rule! {
+      name = "rename_log",
+      query = "((identifier) @id (#eq? @id \"log\"))",
+      replace_node = "id",
+      replace = "logger",
+      is_seed_rule = false
+    },
+  ];
+  // The `Function` scope is defined by the scope config of the custom language
+  let edges = vec![edges! {
+    from = "replace_is_enabled",
+    to = ["rename_log"],
+    scope = "Function"
+  }];
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from("custom_py"))
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(rules)
+        .edges(edges)
+        .build(),
+    )
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 2);
+  let expected = "def f():\n    if True:\n        logger()\n\ndef g():\n    log()\n";
+  for file_name in ["a.cpy", "b.cpyi"] {
+    assert_eq!(
+      read_file(&temp_dir.path().join(file_name)).unwrap(),
+      expected
+    );
+  }
+  // The files of the other languages are not analyzed
+  assert_eq!(read_file(&temp_dir.path().join("c.py")).unwrap(), code);
+}
+
+#[test]
+fn test_register_language_errors() {
+  // The built-in languages cannot be redefined
+  assert!(register_language(&CustomLanguage {
+    name: "java",
+    extensions: &["java"],
+    scope_config: FUNCTION_SCOPE_CONFIG,
+  })
+  .is_err());
+  // The configurations are validated
+  assert!(register_language(&CustomLanguage {
+    name: "invalid_custom_py",
+    extensions: &["icpy"],
+    scope_config: "[[scopes]]\nname = 1",
+  })
+  .is_err());
+  assert!(PiranhaLanguage::from_str("invalid_custom_py").is_err());
+  // The extensions of the other languages (built-in or registered) cannot be reused
+  assert!(register_language(&CustomLanguage {
+    name: "python_like",
+    extensions: &["python_like", "py"],
+    scope_config: FUNCTION_SCOPE_CONFIG,
+  })
+  .is_err());
+  register_language(&CustomLanguage {
+    name: "dsl_a",
+    extensions: &["dsl"],
+    scope_config: FUNCTION_SCOPE_CONFIG,
+  })
+  .unwrap();
+  assert!(register_language(&CustomLanguage {
+    name: "dsl_b",
+    extensions: &["dsl"],
+    scope_config: FUNCTION_SCOPE_CONFIG,
+  })
+  .is_err());
+  assert!(PiranhaLanguage::from_str("python_like").is_err());
+  assert!(PiranhaLanguage::from_str("dsl_b").is_err());
+}
+
+/// A real grammar (the JSON grammar of `tree-sitter-json`), compiled into a shared library, is registered from its path.
+#[cfg(unix)]
+#[test]
+fn test_register_language_from_library() {
+  initialize();
+  let grammar = Path::new("test-resources/custom_language/tree-sitter-json");
+  let temp_dir = TempDir::new_in(".", "tmp_test").unwrap();
+  let library_path = temp_dir.path().join("libtree-sitter-json.so");
+  let status = Command::new("cc")
+    .args(["-shared", "-fPIC", "-I"])
+    .arg(grammar)
+    .arg(grammar.join("parser.c"))
+    .arg("-o")
+    .arg(&library_path)
+    .status()
+    .unwrap();
+  assert!(status.success());
+  let register = |name: &str, library_path: &str| {
+    register_language_from_library(
+      name.to_string(),
+      library_path.to_string(),
+      None,
+      None,
+      None,
+      None,
+      None,
+    )
+  };
+  assert!(register("json", "missing/libtree-sitter-json.so").is_err());
+  register("json", library_path.to_str().unwrap()).unwrap();
+  assert!(register("json", library_path.to_str().unwrap()).is_err());
+
+  let code = "{\"enabled\": true, \"name\": \"piranha\"}\n";
+  fs::write(temp_dir.path().join("config.json"), code).unwrap();
+  let rule = piranha_rule! {
+    name = "disable",
+    query = "((pair key: (string) @key value: (true) @value) (#eq? @key \"\\\"enabled\\\"\"))",
+    replace_node = "value",
+    replace = "false"
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from("json"))
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert_eq!(
+    read_file(&temp_dir.path().join("config.json")).unwrap(),
+    "{\"enabled\": false, \"name\": \"piranha\"}\n"
+  );
+}
@@ -41,4 +41,14 @@ create_rewrite_tests! {
       "stale_flag_name" => "staleFlag",
       "treated" => "false"
     };
+  test_delete_empty_enclosing: "delete_empty_enclosing", 1;
+  test_exhaustive_parent_rules: "exhaustive_parent_rules", 1,
+    substitutions= substitutions! {
+      "stale_flag_name" => "STALE_FLAG"
+    };
+  test_comment_deletion_edited_files: "comment_deletion/edited_files", 1,
+    substitutions= substitutions! {
+      "stale_flag_name" => "STALE_FLAG"
+    },
+    comment_deletion_patterns = vec!["TODO.*@stale_flag_name".to_string()];
 }
@@ -18,15 +18,43 @@ use super::{
   execute_piranha_and_check_result, initialize, substitutions,
 };
 use crate::{
-  edges, execute_piranha, filter,
+  apply_edit_at_offset, apply_rules_on_lines, edges, execute_piranha, execute_piranha_with_plugins,
+  execute_stages_with_plugins, filter,
   models::{
-    default_configs::JAVA, language::PiranhaLanguage, piranha_arguments::PiranhaArgumentsBuilder,
-    rule_graph::RuleGraphBuilder,
+    capture_group_patterns::CGPattern,
+    default_configs::JAVA,
+    edit::Edit,
+    edit_interceptor::{EditDecision, EditInterceptor},
+    filter::{Filter, FilterBuilder},
+    language::PiranhaLanguage,
+    matches::Range,
+    metrics::{RunMetrics, UnappliedSeedRule},
+    package_filter::PackageFilter,
+    piranha_arguments::{PiranhaArguments, PiranhaArgumentsBuilder},
+    piranha_output::{MatchRecord, PiranhaOutputSummary},
+    piranha_plugin::PiranhaPlugin,
+    progress::{ProgressEvent, ProgressSink},
+    rule::Rule,
+    rule_graph::{read_user_config_files, RuleGraph, RuleGraphBuilder},
+    skipped_match::SkipReason,
+    source_code_unit::{LineEndings, OnParseError},
+    source_code_view::SourceCodeView,
+    stage::{ContinueOn, Stage},
+    xpath::xpath_to_query,
   },
-  piranha_rule,
-  utilities::eq_without_whitespace,
+  piranha_rule, refactor_rename_symbol, replace_all_literals,
+  utilities::{eq_without_whitespace, read_file, tree_sitter_utilities::get_range_for_offsets},
+  Piranha,
 };
-use std::{collections::HashMap, path::PathBuf};
+use itertools::Itertools;
+use std::{
+  collections::HashMap,
+  fs::{self, File},
+  io::Read,
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+};
+use tempdir::TempDir;
 
 create_rewrite_tests! {
   JAVA,
@@ -66,6 +94,72 @@ create_rewrite_tests! {
   test_new_line_character_used_in_string_literal:  "new_line_character_used_in_string_literal",   1;
   test_java_delete_method_invocation_argument: "delete_method_invocation_argument", 1;
   test_java_delete_method_invocation_argument_no_op: "delete_method_invocation_argument_no_op", 0;
+  test_multi_file_seed_rule: "multi_file_seed_rule/unique", 2;
+  test_long_lines_skipped: "long_lines/skipped", 2, max_line_length = 200, delete_consecutive_new_lines = true;
+  test_long_lines_processed: "long_lines/processed", 2, max_line_length = 200, process_long_lines = true, delete_consecutive_new_lines = true;
+  test_large_files_skipped: "long_lines/skipped", 2, max_file_size_bytes = Some(800), delete_consecutive_new_lines = true;
+  test_cleanup_only: "cleanup_only", 1, cleanup_only = true;
+  test_fold_literal_wrappers: "fold_literal_wrappers", 1, cleanup_only = true;
+  test_delete_empty_enclosing: "delete_empty_enclosing", 1;
+  test_diamond_propagation: "diamond_propagation", 1;
+  test_boolean_simplification: "boolean_simplification", 4,
+    substitutions = substitutions! {
+      "stale_flag_name" => "STALE_FLAG",
+      "treated" => "true"
+    };
+  test_exhaustive_parent_rules: "exhaustive_parent_rules", 1,
+    substitutions = substitutions! {
+      "stale_flag_name" => "STALE_FLAG"
+    };
+  test_query_file: "query_file", 1,
+    substitutions = substitutions! {
+      "stale_flag_name" => "STALE_FLAG",
+      "treated" => "true"
+    };
+  test_rule_templates: "rule_templates", 1,
+    substitutions = substitutions! {
+      "stale_flag_name" => "STALE_FLAG",
+      "treated" => "true"
+    };
+  test_comment_deletion_edited_files: "comment_deletion/edited_files", 1,
+    substitutions = substitutions! {
+      "stale_flag_name" => "STALE_FLAG",
+      "treated" => "true"
+    },
+    comment_deletion_patterns = vec!["TODO.*@stale_flag_name".to_string()];
+  test_comment_deletion_all_files: "comment_deletion/all_files", 2,
+    substitutions = substitutions! {
+      "stale_flag_name" => "STALE_FLAG",
+      "treated" => "true"
+    },
+    comment_deletion_patterns = vec!["TODO.*@stale_flag_name".to_string()],
+    comment_deletion_in_all_files = true;
+  test_package_filter: "package_filter", 1,
+    substitutions = substitutions! {
+      "package_prefix" => "com.uber.payments"
+    };
+  test_list_substitutions: "list_substitutions", 1,
+    substitutions = substitutions! {
+      "api" => "isEnabled",
+      "api" => "isOn",
+      "stale_flag" => "FLAG_A",
+      "stale_flag" => "FLAG_B"
+    };
+  test_requires_features: "requires_features", 1;
+  test_swap_arguments: "swap_arguments", 1;
+  test_edge_scope_list: "edge_scope_list", 1;
+  test_remove_annotation: "remove_annotation/delete", 1,
+    substitutions = substitutions! {
+      "annotation_name" => "ExperimentEnabled"
+    };
+  test_replace_annotation: "remove_annotation/replace", 1,
+    substitutions = substitutions! {
+      "annotation_name" => "ExperimentEnabled",
+      "replacement_annotation" => "@Experiment(@argument)"
+    };
+  test_empty_files: "empty_files", 1, delete_file_if_empty = true;
+  test_remove_dependency_injection_bindings: "remove_dependency_injection_bindings", 4,
+    substitutions = substitutions! {"flag_holder_class" => "StaleFlagHolder"};
 }
 
 create_match_tests! {
@@ -304,6 +398,499 @@ fn test_consecutive_scope_level_rules() {
   execute_piranha_and_check_result(&args, _path.join("expected").as_path(), 1, true)
 }
 
+/// The exported rules (with several filters, in a deterministic order) are read back as the same rule graph.
+#[test]
+fn test_export_rules() {
+  initialize();
+  let configurations = TempDir::new_in(".", "export_rules").unwrap();
+  let path_to_rules = configurations.path().join("rules.toml");
+  let code_snippet =
+    "class A { void foo() { if (isEnabled()) { log(); } } void bar() { isEnabled(); } }";
+  let rule_graph = RuleGraphBuilder::default()
+    .rules(vec![
+      piranha_rule! {
+        name = "replace_is_enabled",
+        query = "((method_invocation name: (_) @name) @call (#eq? @name \"isEnabled\"))",
+        replace_node = "call",
+        replace = "true",
+        filters = [
+          filter! {
+            enclosing_node = "(if_statement) @if_statement"
+          },
+          filter! {
+            enclosing_node = "(method_declaration) @md",
+            not_contains = ["((identifier) @id (#eq? @id \"skip\"))",]
+          },
+          filter! {
+            enclosing_node = "(class_declaration) @cd",
+            not_enclosing_node = "(lambda_expression) @lambda"
+          }
+        ]
+      },
+      piranha_rule! {
+        name = "find_log",
+        query = "((method_invocation name: (_) @name) @call (#eq? @name \"log\"))"
+      },
+    ])
+    .edges(vec![edges! {
+      from = "replace_is_enabled",
+      to = ["find_log"],
+      scope = ["Method" "Class"]
+    }])
+    .build();
+  let args = |rule_graph: RuleGraph| {
+    PiranhaArgumentsBuilder::default()
+      .code_snippet(code_snippet.to_string())
+      .language(PiranhaLanguage::from(JAVA))
+      .rule_graph(rule_graph)
+      .export_rules(Some(path_to_rules.to_str().unwrap().to_string()))
+      .build()
+  };
+  let output_summaries = execute_piranha(&args(rule_graph.clone()));
+  let exported_rules = fs::read_to_string(&path_to_rules).unwrap();
+
+  // The exported file is a valid `rules.toml` as well as `edges.toml`
+  fs::copy(&path_to_rules, configurations.path().join("edges.toml")).unwrap();
+  let path_to_configurations = configurations.path().to_str().unwrap().to_string();
+  let exported_rule_graph =
+    read_user_config_files(&path_to_configurations, &PiranhaLanguage::from(JAVA)).unwrap();
+  assert_eq!(exported_rule_graph.rules(), rule_graph.rules());
+  assert_eq!(exported_rule_graph.edges(), rule_graph.edges());
+
+  // Exporting the rules read back yields the same file
+  let _ = execute_piranha(&args(exported_rule_graph));
+  assert_eq!(fs::read_to_string(&path_to_rules).unwrap(), exported_rules);
+
+  // Replaying the exported rules yields the same rewrites
+  let replayed_summaries = execute_piranha(
+    &PiranhaArgumentsBuilder::default()
+      .code_snippet(code_snippet.to_string())
+      .language(PiranhaLanguage::from(JAVA))
+      .path_to_configurations(path_to_configurations)
+      .build(),
+  );
+  assert_eq!(
+    replayed_summaries[0].content(),
+    output_summaries[0].content()
+  );
+  configurations.close().unwrap();
+}
+
+#[test]
+fn test_crlf_line_endings() {
+  initialize();
+  // The line endings are `\r\n`, except for one
+  let code_snippet = "class A {\r\n  void m() {\r\n    foo();\r\n  }\n  void n() {}\r\n}\r\n";
+  let rule = piranha_rule! {
+    name = "split_foo",
+    query = "(
+  (expression_statement (method_invocation name: (_) @name)) @statement
+  (#eq? @name \"foo\")
+  )",
+    replace_node = "statement",
+    replace = "bar();\n    baz();"
+  };
+  let rewrite = |normalize_line_endings: LineEndings| {
+    let piranha_arguments = PiranhaArgumentsBuilder::default()
+      .language(PiranhaLanguage::from(JAVA))
+      .code_snippet(code_snippet.to_string())
+      .rule_graph(
+        RuleGraphBuilder::default()
+          .rules(vec![rule.clone()])
+          .build(),
+      )
+      .normalize_line_endings(normalize_line_endings)
+      .build();
+    let output_summaries = execute_piranha(&piranha_arguments);
+    assert_eq!(output_summaries.len(), 1);
+    output_summaries[0].content().to_string()
+  };
+
+  // Only the edited region changes, and the new line of the replacement is re-emitted as `\r\n`
+  assert_eq!(
+    rewrite(LineEndings::Preserve),
+    "class A {\r\n  void m() {\r\n    bar();\r\n    baz();\r\n  }\n  void n() {}\r\n}\r\n"
+  );
+  assert_eq!(
+    rewrite(LineEndings::Lf),
+    "class A {\n  void m() {\n    bar();\n    baz();\n  }\n  void n() {}\n}\n"
+  );
+  assert_eq!(
+    rewrite(LineEndings::Crlf),
+    "class A {\r\n  void m() {\r\n    bar();\r\n    baz();\r\n  }\r\n  void n() {}\r\n}\r\n"
+  );
+}
+
+/// The line comments include the `\r` of their `\r\n` line ending, which is kept when they are deleted
+/// (along with a deleted argument and its trailing comma, or as per the `comment_deletion_patterns`).
+#[test]
+fn test_crlf_line_endings_of_deleted_comments() {
+  initialize();
+  let code_snippet = "class A {\r\n  void m() {\r\n    foo(a, // first\r\n        b);\r\n    // TODO: stale\r\n    bar(); // TODO: stale\r\n  }\r\n}\r\n";
+  let rule = piranha_rule! {
+    name = "delete_argument",
+    query = "(argument_list ((identifier) @arg (#eq? @arg \"a\")))",
+    replace_node = "arg",
+    replace = ""
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet(code_snippet.to_string())
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .comment_deletion_patterns(vec!["TODO: stale".to_string()])
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert_eq!(
+    output_summaries[0].content(),
+    "class A {\r\n  void m() {\r\n    foo(\r\n        b);\r\n    bar();\r\n  }\r\n}\r\n"
+  );
+}
+
+/// The same flag is checked several times in one expression. Since the matches are applied in a fixed order of their
+/// ranges (from the bottom of the document to its top, the outermost match first among the matches starting at the
+/// same byte) rather than the order the query engine reports them in, every run produces the same output.
+#[test]
+fn test_same_flag_in_expression_deterministic() {
+  initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("same_flag_in_expression");
+  for _ in 0..5 {
+    let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+    let piranha_arguments = PiranhaArgumentsBuilder::default()
+      .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+      .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+      .language(PiranhaLanguage::from(JAVA))
+      .substitutions(substitutions! {
+        "stale_flag_name" => "STALE_FLAG",
+        "treated" => "true"
+      })
+      .build();
+    execute_piranha_and_check_result(&piranha_arguments, &_path.join("expected"), 1, false);
+    temp_dir.close().unwrap();
+  }
+}
+
+/// A substitution with (balanced) quotes is escaped where it is spliced into a string literal of the query,
+/// hence it matches the string literal of the flag, even with `strict_substitutions`.
+#[test]
+fn test_substitution_escaped_in_query_string() {
+  initialize();
+  let rule = piranha_rule! {
+    name = "replace_is_treated_literal",
+    query = "(
+  (method_invocation name: (_) @name arguments: (argument_list (string_literal) @flag)) @invocation
+  (#eq? @name \"isTreated\")
+  (#eq? @flag \"@flag_literal\")
+  )",
+    replace_node = "invocation",
+    replace = "true",
+    holes = ["flag_literal"]
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet(
+      "class A { void m() { if (exp.isTreated(\"STALE\") || exp.isTreated(\"OTHER\")) { a(); } } }"
+        .to_string(),
+    )
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .substitutions(substitutions! {"flag_literal" => "\"STALE\""})
+    .strict_substitutions(true)
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert!(eq_without_whitespace(
+    output_summaries[0].content(),
+    "class A { void m() { if (true || exp.isTreated(\"OTHER\")) { a(); } } }"
+  ));
+}
+
+/// The file is reached through both its path and a symlink. All its edits (by the seed rule and by the
+/// `Global` rule it triggers) should be applied to a single copy of it, reported once.
+#[cfg(unix)]
+#[test]
+fn test_single_source_code_unit_per_file() {
+  initialize();
+  let temp_dir = TempDir::new_in(".", "tmp_test").unwrap();
+  let file = temp_dir.path().join("A.java");
+  fs::write(
+    &file,
+    "class A {\n  static final boolean FLAG = true;\n  void m() {\n    if (FLAG) { foo(); }\n  }\n}\n",
+  )
+  .unwrap();
+  std::os::unix::fs::symlink(&file, temp_dir.path().join("B.java")).unwrap();
+
+  let rules = vec![
+    piranha_rule! {
+      name = "delete_flag_field",
+      query = "(
+  (field_declaration declarator: (variable_declarator name: (_) @flag_name)) @field
+  (#eq? @flag_name \"FLAG\")
+  )",
+      replace_node = "field",
+      replace = ""
+    },
+    piranha_rule! {
+      name = "replace_flag_usage",
+      query = "(
+  (identifier) @usage
+  (#eq? @usage \"@flag_name\")
+  )",
+      replace_node = "usage",
+      replace = "true",
+      holes = ["flag_name"],
+      is_seed_rule = false
+    },
+  ];
+  let edges = vec![edges! {
+    from = "delete_flag_field",
+    to = ["replace_flag_usage"],
+    scope = "Global"
+  }];
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(rules)
+        .edges(edges)
+        .build(),
+    )
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert_eq!(output_summaries[0].rewrites().len(), 2);
+  assert!(eq_without_whitespace(
+    &read_file(&file).unwrap(),
+    "class A {\n  void m() {\n    if (true) { foo(); }\n  }\n}\n"
+  ));
+  // The summary is for one of the paths of the file, which was written through it (keeping the symlink)
+  let summary_path = PathBuf::from(output_summaries[0].path());
+  assert!(summary_path == file || summary_path == temp_dir.path().join("B.java"));
+  assert!(fs::symlink_metadata(temp_dir.path().join("B.java"))
+    .unwrap()
+    .file_type()
+    .is_symlink());
+}
+
+/// The rules of the other languages are only applied to the files of their language (parsed with their grammar).
+/// Here a Java rule triggers a Python rule through a `Global` edge.
+#[test]
+fn test_rule_language() {
+  initialize();
+  let temp_dir = TempDir::new_in(".", "tmp_test").unwrap();
+  let java_file = temp_dir.path().join("A.java");
+  let python_file = temp_dir.path().join("a.py");
+  fs::write(
+    &java_file,
+    "class A {\n  static final boolean FLAG = true;\n  void m() {\n    FLAG = false;\n  }\n}\n",
+  )
+  .unwrap();
+  fs::write(&python_file, "if FLAG:\n    foo()\n").unwrap();
+
+  let rules = vec![
+    piranha_rule! {
+      name = "delete_flag_field",
+      query = "(
+  (field_declaration declarator: (variable_declarator name: (_) @flag_name)) @field
+  (#eq? @flag_name \"FLAG\")
+  )",
+      replace_node = "field",
+      replace = ""
+    },
+    piranha_rule! {
+      name = "replace_flag_usage",
+      query = "(
+  (identifier) @usage
+  (#eq? @usage \"@flag_name\")
+  )",
+      replace_node = "usage",
+      replace = "True",
+      holes = ["flag_name"],
+      is_seed_rule = false,
+      language = "py"
+    },
+  ];
+  let edges = vec![edges! {
+    from = "delete_flag_field",
+    to = ["replace_flag_usage"],
+    scope = "Global"
+  }];
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(rules)
+        .edges(edges)
+        .build(),
+    )
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 2);
+  // The Python rule is not applied to the usage of `FLAG` in the Java file
+  assert!(eq_without_whitespace(
+    &read_file(&java_file).unwrap(),
+    "class A {\n  void m() {\n    FLAG = false;\n  }\n}\n"
+  ));
+  assert!(eq_without_whitespace(
+    &read_file(&python_file).unwrap(),
+    "if True:\n    foo()\n"
+  ));
+}
+
+/// The rules of the other languages can trigger the built-in cleanup rules of their language, prefixed by it.
+#[test]
+fn test_rule_language_built_in_rules() {
+  initialize();
+  let temp_dir = TempDir::new_in(".", "tmp_test").unwrap();
+  let kotlin_file = temp_dir.path().join("a.kt");
+  fs::write(
+    &kotlin_file,
+    "fun f() {\n  if (isEnabled()) {\n    a()\n  } else {\n    b()\n  }\n}\n",
+  )
+  .unwrap();
+
+  let rules = vec![piranha_rule! {
+    name = "replace_is_enabled",
+    query = "(
+  (call_expression (simple_identifier) @name) @call
+  (#eq? @name \"isEnabled\")
+  )",
+    replace_node = "call",
+    replace = "true",
+    language = "kt"
+  }];
+  let edges = vec![edges! {
+    from = "replace_is_enabled",
+    to = ["kt/boolean_literal_cleanup"],
+    scope = "Parent"
+  }];
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(rules)
+        .edges(edges)
+        .build(),
+    )
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert!(eq_without_whitespace(
+    &read_file(&kotlin_file).unwrap(),
+    "fun f() {\n  a()\n}\n"
+  ));
+}
+
+/// The rules of different languages can only be connected by `Global` edges.
+#[test]
+#[should_panic(expected = "can only be connected by a `Global` edge")]
+fn test_rule_language_parent_edge_panic() {
+  initialize();
+  let rules = vec![
+    piranha_rule! {
+      name = "java_rule",
+      query = "(identifier) @id",
+      replace_node = "id",
+      replace = "x"
+    },
+    piranha_rule! {
+      name = "python_rule",
+      query = "(identifier) @id",
+      replace_node = "id",
+      replace = "y",
+      is_seed_rule = false,
+      language = "py"
+    },
+  ];
+  let edges = vec![edges! {
+    from = "java_rule",
+    to = ["python_rule"],
+    scope = "Parent"
+  }];
+  PiranhaArgumentsBuilder::default()
+    .code_snippet("class A {}".to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(rules)
+        .edges(edges)
+        .build(),
+    )
+    .build();
+}
+
+#[test]
+fn test_output_patch() {
+  initialize();
+  let temp_dir = TempDir::new_in(".", "tmp_test").unwrap();
+  let src = temp_dir.path().join("src");
+  fs::create_dir(&src).unwrap();
+  let code = "class A {\n  void m() {\n    foo();\n    bar();\n  }\n}\n";
+  fs::write(src.join("A.java"), code).unwrap();
+  fs::write(
+    src.join("B.java"),
+    "class B {\n  void m() {\n    bar();\n  }\n}\n",
+  )
+  .unwrap();
+  let patch_file = temp_dir.path().join("changes.patch");
+
+  let rule = piranha_rule! {
+    name = "replace_foo",
+    query = "(
+  (method_invocation name: (_) @name) @invocation
+  (#eq? @name \"foo\")
+  )",
+    replace_node = "invocation",
+    replace = "baz()"
+  };
+  let run = |dry_run: bool| {
+    let piranha_arguments = PiranhaArgumentsBuilder::default()
+      .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+      .language(PiranhaLanguage::from(JAVA))
+      .rule_graph(
+        RuleGraphBuilder::default()
+          .rules(vec![rule.clone()])
+          .build(),
+      )
+      .dry_run(dry_run)
+      .output_patch(Some(patch_file.to_str().unwrap().to_string()))
+      .build();
+    execute_piranha(&piranha_arguments);
+  };
+
+  // The paths are relative to the code base, and the unchanged `B.java` is not part of the patch
+  let expected_patch = "diff --git a/src/A.java b/src/A.java
+--- a/src/A.java
++++ b/src/A.java
+@@ -1,6 +1,6 @@
+ class A {
+   void m() {
+-    foo();
++    baz();
+     bar();
+   }
+ }
+";
+  run(true);
+  assert_eq!(fs::read_to_string(&patch_file).unwrap(), expected_patch);
+  assert_eq!(fs::read_to_string(src.join("A.java")).unwrap(), code);
+
+  run(false);
+  assert_eq!(fs::read_to_string(&patch_file).unwrap(), expected_patch);
+  assert_eq!(
+    fs::read_to_string(src.join("A.java")).unwrap(),
+    code.replace("foo()", "baz()")
+  );
+}
+
 /// This test is to check if Piranha is able to handle a syntactically incorrect tree.
 #[test]
 fn test_handle_syntactically_incorrect_tree() {
@@ -334,8 +921,8 @@ fn test_handle_syntactically_incorrect_tree() {
   temp_dir.close().unwrap();
 }
 
-/// This test is to check if Piranha panics when it encounters a syntactically incorrect tree and
-/// allow_dirty_ast is *not* set (to true).
+/// This test is to check if Piranha panics when it encounters a syntactically incorrect tree,
+/// allow_dirty_ast is *not* set (to true) and on_parse_error is `fail`.
 #[test]
 #[should_panic(expected = "Produced syntactically incorrect source code")]
 fn test_do_not_allow_syntactically_incorrect_tree() {
@@ -358,6 +945,7 @@ fn test_do_not_allow_syntactically_incorrect_tree() {
     .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
     .language(PiranhaLanguage::from(JAVA))
     .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .on_parse_error(OnParseError::Fail)
     .build();
 
   execute_piranha_and_check_result(&piranha_arguments, &_path.join("expected"), 1, true);
@@ -365,11 +953,55 @@ fn test_do_not_allow_syntactically_incorrect_tree() {
   temp_dir.close().unwrap();
 }
 
+/// This test is to check if Piranha skips (and reports) the files that contain syntax errors by default.
+#[test]
+fn test_skip_syntactically_incorrect_tree() {
+  initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("handle_syntactically_incorrect_tree");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+
+  let rule = piranha_rule! {
+    name = "Append l",
+    query = "(
+  (variable_declarator value: (decimal_integer_literal) @value)
+  (#not-match? @value \"l|L\")
+  )",
+    replace_node = "value",
+    replace = "@valuel"
+  };
+
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  let summary = &output_summaries[0];
+  assert!(summary.rewrites().is_empty());
+  assert!(!summary.parse_errors().is_empty());
+  assert_eq!(
+    summary.notes(),
+    &vec!["Skipped: contains 1 syntax error(s)".to_string()]
+  );
+  // The file is not rewritten
+  assert_eq!(
+    read_file(&temp_dir.path().join("Sample.java")).unwrap(),
+    read_file(&_path.join("input").join("Sample.java")).unwrap()
+  );
+  // Delete temp_dir
+  temp_dir.close().unwrap();
+}
+
 /// This test is to check if Piranha is able to handle a syntactically incorrect tree.
-/// We expect Piranha to panic in this case because the rule produces a "more" syntactically incorrect tree.
+/// The rule produces a "more" syntactically incorrect tree, hence its edits are rolled back and the failure
+/// is reported in the summary of the file (rather than failing the run).
 #[test]
-#[should_panic(expected = "Produced syntactically incorrect source code")]
-fn test_handle_syntactically_incorrect_tree_panic() {
+fn test_handle_syntactically_incorrect_tree_rolled_back() {
+  initialize();
   let _path = PathBuf::from("test-resources")
     .join(JAVA)
     .join("handle_syntactically_incorrect_tree");
@@ -390,9 +1022,21 @@ fn test_handle_syntactically_incorrect_tree_panic() {
     .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
     .language(PiranhaLanguage::from(JAVA))
     .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .on_parse_error(OnParseError::Proceed)
     .build();
 
-  execute_piranha_and_check_result(&piranha_arguments, &_path.join("expected"), 1, true);
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  let summary = &output_summaries[0];
+  assert!(summary.rewrites().is_empty());
+  assert_eq!(summary.notes().len(), 1);
+  assert!(summary.notes()[0]
+    .starts_with("The rule `Append x (wrong rule)` produced syntactically incorrect code"));
+  // The file is not rewritten
+  assert_eq!(
+    read_file(&temp_dir.path().join("Sample.java")).unwrap(),
+    read_file(&_path.join("input").join("Sample.java")).unwrap()
+  );
   // Delete temp_dir
   temp_dir.close().unwrap();
 }
@@ -414,3 +1058,2457 @@ fn test_incorrect_rule() {
 
   let _ = execute_piranha(&piranha_arguments);
 }
+
+struct PreProcessPlugin;
+
+impl PiranhaPlugin for PreProcessPlugin {
+  fn before_apply(&self, scu: &SourceCodeView) -> Option<String> {
+    Some(scu.code().replace("long sum = 0;", "long sum = 1;"))
+  }
+
+  fn after_apply(&self, _scu: &SourceCodeView) -> bool {
+    true
+  }
+}
+
+struct VetoPlugin;
+
+impl PiranhaPlugin for VetoPlugin {
+  fn before_apply(&self, _scu: &SourceCodeView) -> Option<String> {
+    None
+  }
+
+  fn after_apply(&self, _scu: &SourceCodeView) -> bool {
+    false
+  }
+}
+
+/// Records the files it pre-processes.
+#[derive(Default)]
+struct RecordingPlugin {
+  pre_processed_files: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl PiranhaPlugin for RecordingPlugin {
+  fn before_apply(&self, scu: &SourceCodeView) -> Option<String> {
+    self
+      .pre_processed_files
+      .lock()
+      .unwrap()
+      .push(scu.path().to_path_buf());
+    None
+  }
+
+  fn after_apply(&self, _scu: &SourceCodeView) -> bool {
+    true
+  }
+}
+
+/// The global rule found in the first file triggers another pass over the files, which are not pre-processed again.
+#[test]
+fn test_plugin_before_apply_once_per_file() {
+  initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("find_and_propagate");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .substitutions(substitutions! {"super_interface_name" => "SomeInterface"})
+    .dry_run(true)
+    .build();
+  let plugin = RecordingPlugin::default();
+  let pre_processed_files = plugin.pre_processed_files.clone();
+
+  execute_piranha_with_plugins(&piranha_arguments, vec![Box::new(plugin)]).unwrap();
+  let pre_processed_files = pre_processed_files.lock().unwrap();
+  assert_eq!(pre_processed_files.len(), 2);
+  assert!(pre_processed_files.iter().all_unique());
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_report_capture_ranges() {
+  initialize();
+  let code_snippet = "class A {
+    void foo() {
+      log(\"a\");
+      log(\"b\");
+    }
+  }";
+  let rule = piranha_rule! {
+    name = "find_foo",
+    query = "(
+  (method_declaration name: (identifier) @name body: (block (_)* @statements)) @method
+  (#eq? @name \"foo\")
+  )"
+  };
+  let find_foo = |report_capture_ranges: bool| {
+    let piranha_arguments = PiranhaArgumentsBuilder::default()
+      .language(PiranhaLanguage::from(JAVA))
+      .code_snippet(code_snippet.to_string())
+      .rule_graph(
+        RuleGraphBuilder::default()
+          .rules(vec![rule.clone()])
+          .build(),
+      )
+      .report_capture_ranges(report_capture_ranges)
+      .build();
+    let output_summaries = execute_piranha(&piranha_arguments);
+    assert_eq!(output_summaries.len(), 1);
+    assert_eq!(output_summaries[0].matches().len(), 1);
+    output_summaries[0].matches()[0].1.capture_ranges().clone()
+  };
+
+  let range_of = |snippet: &str| {
+    let start_byte = code_snippet.find(snippet).unwrap();
+    Range::from(get_range_for_offsets(
+      code_snippet,
+      start_byte,
+      start_byte + snippet.len(),
+    ))
+  };
+  let capture_ranges = find_foo(true);
+  assert_eq!(capture_ranges.len(), 3);
+  assert_eq!(capture_ranges["name"], vec![range_of("foo")]);
+  let method = &code_snippet[code_snippet.find("void").unwrap()..code_snippet.rfind('}').unwrap()];
+  assert_eq!(capture_ranges["method"], vec![range_of(method.trim_end())]);
+  // The quantified capture has a range for each statement
+  assert_eq!(
+    capture_ranges["statements"],
+    vec![range_of("log(\"a\");"), range_of("log(\"b\");")]
+  );
+
+  // The capture ranges are not reported by default
+  assert!(find_foo(false).is_empty());
+}
+
+/// Skips the edits of `compute(1)` and adds a tracking comment to the replacement of the other edits.
+#[derive(Debug)]
+struct TrackingInterceptor;
+
+impl EditInterceptor for TrackingInterceptor {
+  fn on_edit(&self, _unit_path: &Path, edit: &Edit) -> EditDecision {
+    if edit.p_match().matched_string() == "compute(1)" {
+      return EditDecision::Skip;
+    }
+    EditDecision::Replace(Box::new(
+      edit.with_replacement(&format!("{} /* piranha */", edit.replacement_string())),
+    ))
+  }
+}
+
+#[test]
+fn test_edit_interceptor() {
+  initialize();
+  let code_snippet = "class A {
+    void foo() {
+      int a = compute(1);
+      int b = compute(2);
+      int c = compute(1);
+    }
+  }";
+  let rule = piranha_rule! {
+    name = "replace_compute",
+    query = "(
+  (method_invocation name: (identifier) @name) @invocation
+  (#eq? @name \"compute\")
+  )",
+    replace_node = "invocation",
+    replace = "0"
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet(code_snippet.to_string())
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .edit_interceptor(Some(Arc::new(TrackingInterceptor)))
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert!(eq_without_whitespace(
+    output_summaries[0].content(),
+    "class A {
+    void foo() {
+      int a = compute(1);
+      int b = 0 /* piranha */;
+      int c = compute(1);
+    }
+  }"
+  ));
+  // The skipped edits are not found again
+  assert_eq!(
+    output_summaries[0].skip_counts()["replace_compute"]["vetoed_by_interceptor"],
+    2
+  );
+}
+
+/// Skips the deletions of the empty enclosing nodes (i.e. the "Parent" cleanups).
+#[derive(Debug)]
+struct KeepEnclosingInterceptor;
+
+impl EditInterceptor for KeepEnclosingInterceptor {
+  fn on_edit(&self, _unit_path: &Path, edit: &Edit) -> EditDecision {
+    if edit.triggered_by().is_some() {
+      return EditDecision::Skip;
+    }
+    EditDecision::Apply
+  }
+}
+
+#[test]
+fn test_edit_interceptor_on_empty_enclosing_node() {
+  initialize();
+  let code_snippet = "class A {
+    void foo(boolean enabled) {
+      if (enabled) {
+        tracker.track(\"if\");
+      }
+      bar();
+    }
+  }";
+  let rule = piranha_rule! {
+    name = "delete_tracker_calls",
+    query = "(
+  (expression_statement
+    (method_invocation object: (_) @obj name: (_) @name)) @stmt
+  (#eq? @obj \"tracker\")
+  (#eq? @name \"track\")
+  )",
+    replace_node = "stmt",
+    replace = "",
+    delete_empty_enclosing = true
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet(code_snippet.to_string())
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .edit_interceptor(Some(Arc::new(KeepEnclosingInterceptor)))
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  // The empty `if` statement is not deleted
+  assert!(eq_without_whitespace(
+    output_summaries[0].content(),
+    "class A {
+    void foo(boolean enabled) {
+      if (enabled) {
+      }
+      bar();
+    }
+  }"
+  ));
+  assert_eq!(output_summaries[0].rewrites().len(), 1);
+  assert_eq!(
+    output_summaries[0].skip_counts()["delete_tracker_calls"]["vetoed_by_interceptor"],
+    1
+  );
+}
+
+/// This test checks if the plugins can pre-process the source code and veto writing the updated source code.
+#[test]
+fn test_plugins_pre_process_and_veto() {
+  initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("handle_syntactically_incorrect_tree");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+
+  let rule = piranha_rule! {
+    name = "Append l",
+    query = "(
+  (variable_declarator value: (decimal_integer_literal) @value)
+  (#not-match? @value \"l|L\")
+  )",
+    replace_node = "value",
+    replace = "@valuel"
+  };
+
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .allow_dirty_ast(true)
+    .build();
+
+  let output_summaries = execute_piranha_with_plugins(
+    &piranha_arguments,
+    vec![Box::new(PreProcessPlugin), Box::new(VetoPlugin)],
+  )
+  .unwrap();
+
+  assert_eq!(output_summaries.len(), 1);
+  assert!(output_summaries[0].content().contains("long sum = 1l;"));
+  // The updated content should not be written to the disk
+  let path_to_input = temp_dir.path().join("Sample.java");
+  let content_on_disk = read_file(&path_to_input).unwrap();
+  let original_content = read_file(&_path.join("input").join("Sample.java")).unwrap();
+  assert_eq!(content_on_disk, original_content);
+  // Delete temp_dir
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_multi_file_seed_rule_condition_not_satisfied() {
+  initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("multi_file_seed_rule")
+    .join("not_unique");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .build();
+
+  let result = execute_piranha_with_plugins(&piranha_arguments, vec![]);
+  assert!(result
+    .unwrap_err()
+    .contains("`find_singleton_instance` is expected to match exactly once"));
+
+  // No file should be rewritten
+  for file_name in ["AnotherClient.java", "Client.java", "Singleton.java"] {
+    let path = temp_dir.path().join(file_name);
+    let expected_path = _path.join("input").join(file_name);
+    assert_eq!(
+      read_file(&path).unwrap(),
+      read_file(&expected_path).unwrap()
+    );
+  }
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_skipped_matches() {
+  initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("skipped_matches");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .explain(true)
+    .build();
+
+  let output_summaries =
+    execute_piranha_with_plugins(&piranha_arguments, vec![Box::new(VetoPlugin)]).unwrap();
+  assert_eq!(output_summaries.len(), 1);
+
+  let skipped_matches = output_summaries[0]
+    .skipped_matches()
+    .iter()
+    .map(|s| (s.rule_name().as_str(), s.reason()))
+    .collect_vec();
+  assert_eq!(
+    skipped_matches,
+    vec![
+      ("find_println", SkipReason::DuplicateMatch),
+      ("replace_compute", SkipReason::FilterRejected),
+      ("replace_compute", SkipReason::VetoedByPlugin),
+      ("replace_compute", SkipReason::VetoedByPlugin),
+      ("replace_literal", SkipReason::NoOpEdit),
+    ]
+  );
+
+  let skip_counts = output_summaries[0].skip_counts();
+  assert_eq!(skip_counts["replace_compute"]["vetoed_by_plugin"], 2);
+  assert_eq!(skip_counts["replace_compute"]["filter_rejected"], 1);
+  assert_eq!(skip_counts["find_println"]["duplicate_match"], 1);
+  assert_eq!(skip_counts["replace_literal"]["no_op_edit"], 1);
+  temp_dir.close().unwrap();
+
+  // Without `explain`, the skipped matches are only counted
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .build();
+  let output_summaries_without_explain =
+    execute_piranha_with_plugins(&piranha_arguments, vec![Box::new(VetoPlugin)]).unwrap();
+  assert_eq!(output_summaries_without_explain.len(), 1);
+  assert!(output_summaries_without_explain[0]
+    .skipped_matches()
+    .is_empty());
+  assert_eq!(
+    output_summaries_without_explain[0].skip_counts(),
+    output_summaries[0].skip_counts()
+  );
+  let matches = |summary: &PiranhaOutputSummary| {
+    summary
+      .rule_statistics()
+      .iter()
+      .map(|(rule, stat)| (rule.clone(), *stat.matches()))
+      .collect::<HashMap<_, _>>()
+  };
+  assert_eq!(
+    matches(&output_summaries_without_explain[0]),
+    matches(&output_summaries[0])
+  );
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_rule_statistics() {
+  initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("skipped_matches");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  // A file that is not updated (its only match is a no-op edit)
+  fs::write(
+    temp_dir.path().join("Other.java"),
+    "class Other {\n  int d = 42;\n}\n",
+  )
+  .unwrap();
+  let metrics_dir = TempDir::new_in(".", "metrics").unwrap();
+  let metrics_output = metrics_dir.path().join("metrics.json");
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .metrics_output(Some(metrics_output.to_str().unwrap().to_string()))
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+
+  let rule_statistics = output_summaries[0].rule_statistics();
+  // The invocation within `bar` matches, but is rejected by the filter
+  let replace_compute = &rule_statistics["replace_compute"];
+  assert_eq!(*replace_compute.matches(), 3);
+  assert_eq!(*replace_compute.applications(), 2);
+  // The second match of `find_println` is a duplicate
+  let find_println = &rule_statistics["find_println"];
+  assert_eq!(*find_println.matches(), 1);
+  assert_eq!(*find_println.applications(), 0);
+  // The rules that never apply an edit are reported too
+  let replace_literal = &rule_statistics["replace_literal"];
+  assert_eq!(*replace_literal.matches(), 1);
+  assert_eq!(*replace_literal.applications(), 0);
+
+  // The metrics cover the files that were not updated too
+  let metrics: RunMetrics = serde_json::from_str(&read_file(&metrics_output).unwrap()).unwrap();
+  let replace_literal = &metrics.rule_statistics()["replace_literal"];
+  assert_eq!(*replace_literal.matches(), 2);
+  assert_eq!(*replace_literal.applications(), 0);
+  assert_eq!(
+    *metrics.rule_statistics()["replace_compute"].applications(),
+    2
+  );
+  metrics_dir.close().unwrap();
+  temp_dir.close().unwrap();
+}
+
+/// The `Method` scope of the edge is enclosed by its `Class` scope, hence `find_log` only matches (once) in the class.
+#[test]
+fn test_edge_with_overlapping_scopes() {
+  initialize();
+  let rules = vec![
+    piranha_rule! {
+      name = "replace_is_stale",
+      query = "((method_invocation name: (_) @name) @call (#eq? @name \"isStale\"))",
+      replace_node = "call",
+      replace = "false"
+    },
+    piranha_rule! {
+      name = "find_log",
+      query = "((method_invocation name: (_) @name) @call (#eq? @name \"log\"))"
+    },
+  ];
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet(
+      "class A { void foo() { if (isStale()) { log(); } } void bar() { log(); } }".to_string(),
+    )
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(rules)
+        .edges(vec![edges! {
+          from = "replace_is_stale",
+          to = ["find_log"],
+          scope = ["Method" "Class"]
+        }])
+        .build(),
+    )
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert_eq!(output_summaries[0].matches().len(), 2);
+  assert_eq!(
+    *output_summaries[0].rule_statistics()["find_log"].suppressed_duplicates(),
+    1
+  );
+}
+
+/// Both branches of the diamond-shaped rule graph lead to `delete_unused_field` for the same class, which is applied
+/// only once per propagation (i.e. per match of `find_stale_flag`).
+#[test]
+fn test_diamond_propagation_suppressed_duplicates() {
+  initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("diamond_propagation");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+
+  let delete_unused_field = &output_summaries[0].rule_statistics()["delete_unused_field"];
+  assert_eq!(*delete_unused_field.applications(), 1);
+  assert_eq!(*delete_unused_field.suppressed_duplicates(), 2);
+  assert_eq!(
+    *output_summaries[0].rule_statistics()["find_log"].suppressed_duplicates(),
+    0
+  );
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_per_file_timeout() {
+  initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("swap_arguments");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .per_file_timeout_seconds(Some(0))
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  // The budget expires before the first rule is applied, hence the file is not rewritten
+  assert!(output_summaries[0].rewrites().is_empty());
+  let original_content = read_file(&_path.join("input").join("SwapArgumentsTest.java")).unwrap();
+  assert_eq!(output_summaries[0].content(), &original_content);
+  assert_eq!(output_summaries[0].notes().len(), 1);
+  assert!(output_summaries[0].notes()[0]
+    .starts_with("Timed out after 0 second(s) while applying the rule `swap_assert_equals"));
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_unbound_tag_skipped() {
+  initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("swap_arguments");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert!(!output_summaries[0].content().contains("@actual"));
+  let skip_counts = output_summaries[0].skip_counts();
+  assert_eq!(
+    skip_counts["swap_assert_equals_with_message"]["unbound_tag"],
+    1
+  );
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_edit_provenance() {
+  initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("feature_flag_system_2/treated");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .substitutions(substitutions! {
+      "stale_flag_name" => "STALE_FLAG",
+      "treated" => "true",
+      "treated_complement" => "false",
+      "namespace" => "some_long_name"
+    })
+    .cleanup_comments(true)
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  let rewrites = output_summaries
+    .iter()
+    .flat_map(|s| s.rewrites())
+    .collect_vec();
+  let triggers = |rule_name: &str| {
+    let edits = rewrites
+      .iter()
+      .filter(|e| e.matched_rule() == rule_name)
+      .collect_vec();
+    assert!(!edits.is_empty());
+    edits
+      .iter()
+      .map(|e| {
+        e.triggered_by()
+          .as_ref()
+          .map(|t| (t.rule().to_string(), t.scope().to_string()))
+      })
+      .unique()
+      .sorted()
+      .collect_vec()
+  };
+  let triggered_by =
+    |rule_name: &str, scope: &str| Some((rule_name.to_string(), scope.to_string()));
+
+  // Seed rules are not triggered by any other rule
+  assert_eq!(
+    triggers("delete_flag_method_declaration_inside_annotated_interface"),
+    vec![None]
+  );
+  // The global rule is triggered by the deletion of the flag method declaration (in any of the files)
+  assert!(triggers("replace_flag_method_with_boolean_literal")
+    .iter()
+    .all(|t| {
+      let (rule, scope) = t.as_ref().unwrap();
+      rule.starts_with("delete_flag_method_declaration") && scope == "Global"
+    }));
+  assert_eq!(
+    triggers("replace_identifier_with_value"),
+    vec![
+      triggered_by("delete_field_declaration", "Class"),
+      triggered_by("delete_variable_declaration", "Method")
+    ]
+  );
+  assert!(triggers("simplify_if_statement_true")
+    .iter()
+    .all(|t| t.as_ref().unwrap().1 == "Parent"));
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_matches_output() {
+  initialize();
+  let number_of_files = 100;
+  let matches_per_file = 50;
+  let temp_dir = TempDir::new_in(".", "tmp_test").unwrap();
+  let codebase = temp_dir.path().join("codebase");
+  fs::create_dir(&codebase).unwrap();
+  let statements = "    list.add(new ArrayList<>());\n".repeat(matches_per_file);
+  for i in 0..number_of_files {
+    let content = format!("class Foo{i} {{\n  void bar() {{\n{statements}  }}\n}}\n");
+    fs::write(codebase.join(format!("Foo{i}.java")), content).unwrap();
+  }
+
+  let rule = piranha_rule! {
+    name = "find_array_list_creation",
+    query = "((object_creation_expression) @oce)"
+  };
+  let args = |matches_output: Option<String>, omit_matches_from_summary: bool| {
+    PiranhaArgumentsBuilder::default()
+      .path_to_codebase(codebase.to_str().unwrap().to_string())
+      .language(PiranhaLanguage::from(JAVA))
+      .rule_graph(
+        RuleGraphBuilder::default()
+          .rules(vec![rule.clone()])
+          .build(),
+      )
+      .matches_output(matches_output)
+      .omit_matches_from_summary(omit_matches_from_summary)
+      .build()
+  };
+
+  let matches_output = temp_dir.path().join("matches.jsonl");
+  let matches_output_path = Some(matches_output.to_str().unwrap().to_string());
+  let output_summaries = execute_piranha(&args(matches_output_path.clone(), true));
+  // The matches are not reported in the output summary, when the user opts out
+  assert!(output_summaries.is_empty());
+
+  let records = read_file(&matches_output)
+    .unwrap()
+    .lines()
+    .map(|line| serde_json::from_str::<MatchRecord>(line).unwrap())
+    .collect_vec();
+  assert_eq!(records.len(), number_of_files * matches_per_file);
+  // The matches of a file are contiguous (each file is written as soon as it is processed)
+  let paths = records.iter().map(|r| r.path()).dedup().collect_vec();
+  assert_eq!(paths.len(), number_of_files);
+
+  // The same matches (in the same order) are reported in the output summary otherwise
+  let output_summaries = execute_piranha(&args(matches_output_path, false));
+  assert_eq!(output_summaries.len(), number_of_files);
+  for summary in output_summaries {
+    let expected = summary
+      .matches()
+      .iter()
+      .map(|(_, m)| m.range())
+      .collect_vec();
+    let actual = records
+      .iter()
+      .filter(|r| r.path() == summary.path())
+      .map(|r| r.p_match().range())
+      .collect_vec();
+    assert_eq!(actual, expected);
+  }
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_list_substitutions_edit_attribution() {
+  initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("list_substitutions");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .substitutions(substitutions! {
+      "api" => "isEnabled",
+      "api" => "isOn",
+      "stale_flag" => "FLAG_A",
+      "stale_flag" => "FLAG_B"
+    })
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  // Each edit is attributed to the values the rule was instantiated with
+  let bound_values = output_summaries
+    .iter()
+    .flat_map(|s| s.rewrites())
+    .filter(|e| e.matched_rule() == "replace_flag_check")
+    .map(|e| {
+      let substitutions = e.rule_substitutions();
+      (
+        substitutions["api"].as_str(),
+        substitutions["stale_flag"].as_str(),
+      )
+    })
+    .sorted()
+    .collect_vec();
+  assert_eq!(
+    bound_values,
+    vec![("isEnabled", "FLAG_A"), ("isOn", "FLAG_B")]
+  );
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_negative_cache() {
+  initialize();
+  let temp_dir = TempDir::new_in(".", "tmp_test").unwrap();
+  let codebase = temp_dir.path().join("codebase");
+  fs::create_dir(&codebase).unwrap();
+  fs::write(
+    codebase.join("Foo.java"),
+    "class Foo { List<String> names = Lists.newArrayList(); }",
+  )
+  .unwrap();
+  for name in ["Bar", "Baz"] {
+    fs::write(
+      codebase.join(format!("{name}.java")),
+      format!("class {name} {{ List<String> names = new ArrayList<>(); }}"),
+    )
+    .unwrap();
+  }
+
+  let args = |replace: &str| {
+    PiranhaArgumentsBuilder::default()
+      .path_to_codebase(codebase.to_str().unwrap().to_string())
+      .language(PiranhaLanguage::from(JAVA))
+      .rule_graph(
+        RuleGraphBuilder::default()
+          .rules(vec![piranha_rule! {
+            name = "replace_guava_new_array_list",
+            query = "((method_invocation object: (_) @obj name: (_) @name) @mi (#eq? @obj \"Lists\") (#eq? @name \"newArrayList\"))",
+            replace_node = "mi",
+            replace = replace
+          }])
+          .build(),
+      )
+      .dry_run(true)
+      .cache_dir(Some(
+        temp_dir.path().join("cache").to_str().unwrap().to_string(),
+      ))
+      .build()
+  };
+  // Returns the number of cache hits and the number of updated files
+  let run = |args: &PiranhaArguments| {
+    let mut piranha = Piranha::new(args, vec![]);
+    piranha.perform_cleanup().unwrap();
+    (
+      piranha.negative_cache.as_ref().unwrap().number_of_hits(),
+      piranha.get_updated_files().len(),
+    )
+  };
+
+  // The files without any match are recorded in the first run, and are not matched again in the second one
+  assert_eq!(run(&args("new ArrayList<>()")), (0, 1));
+  assert_eq!(run(&args("new ArrayList<>()")), (2, 1));
+
+  // Changing the rule invalidates the cache
+  assert_eq!(run(&args("new LinkedList<>()")), (0, 1));
+  assert_eq!(run(&args("new LinkedList<>()")), (2, 1));
+
+  // Changing a file invalidates its entry
+  fs::write(
+    codebase.join("Bar.java"),
+    "class Bar { List<String> names = new LinkedList<>(); }",
+  )
+  .unwrap();
+  assert_eq!(run(&args("new LinkedList<>()")), (1, 1));
+  temp_dir.close().unwrap();
+}
+
+/// The matches in the regions suppressed by the `piranha:disable` comment directives are not rewritten.
+#[test]
+fn test_suppression_directives() {
+  initialize();
+  let code_snippet = "class A {
+  void m() {
+    foo();
+    // piranha:disable-next-line
+    foo();
+    /* piranha:disable rename_foo */
+    foo();
+    bar();
+    /* piranha:enable */
+    foo();
+  }
+}
+";
+  let rules = vec![
+    piranha_rule! {
+      name = "rename_foo",
+      query = "((identifier) @id (#eq? @id \"foo\"))",
+      replace_node = "id",
+      replace = "qux"
+    },
+    piranha_rule! {
+      name = "rename_bar",
+      query = "((identifier) @id (#eq? @id \"bar\"))",
+      replace_node = "id",
+      replace = "baz"
+    },
+  ];
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet(code_snippet.to_string())
+    .rule_graph(RuleGraphBuilder::default().rules(rules).build())
+    .explain(true)
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert_eq!(
+    output_summaries[0].content(),
+    "class A {
+  void m() {
+    qux();
+    // piranha:disable-next-line
+    foo();
+    /* piranha:disable rename_foo */
+    foo();
+    baz();
+    /* piranha:enable */
+    qux();
+  }
+}
+"
+  );
+  assert_eq!(
+    output_summaries[0].skip_counts()["rename_foo"]["suppressed_by_comment"],
+    2
+  );
+  assert!(output_summaries[0]
+    .skipped_matches()
+    .iter()
+    .all(|s| s.reason() == SkipReason::SuppressedByComment));
+}
+
+/// With `capture_all_groups`, the replacement references the nodes of the query by their kind (prefixed with `__`).
+#[test]
+fn test_capture_all_groups() {
+  initialize();
+  let code_snippet = "class A {
+  boolean m(int a) {
+    return a == 1;
+  }
+}
+";
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet(code_snippet.to_string())
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(vec![piranha_rule! {
+          name = "yoda_condition",
+          query = "(binary_expression left: (identifier) operator: \"==\" right: (decimal_integer_literal))",
+          replace_node = "__binary_expression",
+          replace = "@__decimal_integer_literal == @__identifier",
+          capture_all_groups = true
+        }])
+        .build(),
+    )
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert_eq!(
+    output_summaries[0].content(),
+    "class A {
+  boolean m(int a) {
+    return 1 == a;
+  }
+}
+"
+  );
+}
+
+/// The files opted out by a leading `piranha:disable` comment are reported as skipped, without any rewrite.
+#[test]
+fn test_file_disabled_by_comment() {
+  initialize();
+  let code_snippet = "// piranha:disable
+class A {
+  void m() {
+    foo();
+  }
+}
+";
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet(code_snippet.to_string())
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(vec![piranha_rule! {
+          name = "rename_foo",
+          query = "((identifier) @id (#eq? @id \"foo\"))",
+          replace_node = "id",
+          replace = "qux"
+        }])
+        .build(),
+    )
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert_eq!(output_summaries[0].content(), code_snippet);
+  assert!(output_summaries[0].rewrites().is_empty());
+  assert_eq!(
+    output_summaries[0].notes(),
+    &vec!["Skipped: disabled by a `piranha:disable` comment".to_string()]
+  );
+}
+
+/// The files larger than `max_file_size_bytes` are reported in `skipped_files`, without their content.
+#[test]
+fn test_large_file_reported_as_skipped() {
+  initialize();
+  let code_snippet = "class A {
+  void m() {
+    foo();
+  }
+}
+";
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet(code_snippet.to_string())
+    .max_file_size_bytes(Some(10))
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(vec![piranha_rule! {
+          name = "rename_foo",
+          query = "((identifier) @id (#eq? @id \"foo\"))",
+          replace_node = "id",
+          replace = "qux"
+        }])
+        .build(),
+    )
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  let summary = &output_summaries[0];
+  assert!(summary.content().is_empty());
+  assert!(summary.rewrites().is_empty());
+  assert_eq!(summary.skipped_files().len(), 1);
+  let skipped_file = &summary.skipped_files()[0];
+  assert_eq!(skipped_file.path(), summary.path());
+  assert_eq!(*skipped_file.size_bytes(), code_snippet.len());
+  assert_eq!(skipped_file.reason(), "Skipped: larger than 10 bytes");
+  assert_eq!(summary.notes(), &vec![skipped_file.reason().to_string()]);
+}
+
+/// The rules annotated in the comments of any file are applied to the entire code base.
+#[test]
+fn test_rules_from_comments() {
+  initialize();
+  let temp_dir = TempDir::new_in(".", "tmp_test").unwrap();
+  fs::write(
+    temp_dir.path().join("A.java"),
+    "class A {\n  // @piranha-rule: name=\"rename_foo\" query=\"((method_invocation name: (_) @n) (#eq? @n \\\"foo\\\"))\" replacement=\"bar\"\n  void m() {\n    foo();\n  }\n}\n",
+  )
+  .unwrap();
+  fs::write(
+    temp_dir.path().join("B.java"),
+    "class B {\n  void m() {\n    foo();\n    baz();\n  }\n}\n",
+  )
+  .unwrap();
+
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rules_from_comments(true)
+    .build();
+  assert!(piranha_arguments
+    .rule_graph()
+    .get_rule_named(&"rename_foo".to_string())
+    .is_some());
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 2);
+  assert!(read_file(&temp_dir.path().join("A.java"))
+    .unwrap()
+    .contains("    bar();\n"));
+  assert_eq!(
+    read_file(&temp_dir.path().join("B.java")).unwrap(),
+    "class B {\n  void m() {\n    bar();\n    baz();\n  }\n}\n"
+  );
+
+  // The rules of the files skipped by the run (e.g. too large) are not read
+  fs::write(
+    temp_dir.path().join("C.java"),
+    "class C {\n  // @piranha-rule: name=\"rename_bar\" query=\"((method_invocation name: (_) @n) (#eq? @n \\\"bar\\\"))\" replacement=\"qux\"\n}\n",
+  )
+  .unwrap();
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rules_from_comments(true)
+    .max_file_size_bytes(Some(100))
+    .build();
+  assert!(piranha_arguments
+    .rule_graph()
+    .get_rule_named(&"rename_bar".to_string())
+    .is_none());
+  temp_dir.close().unwrap();
+}
+
+/// The commas deleted along with a node are the separators of the list (e.g. `argument_list`) containing it,
+/// never the text of a string literal next to it.
+#[test]
+fn test_trailing_comma_scoped_to_lists() {
+  initialize();
+  let code_snippet =
+    "class A {\n  void m() {\n    f(\"\\n,\", a);\n    g(a, \",\");\n    h(\", \", a);\n  }\n}\n";
+  let rules = vec![
+    piranha_rule! {
+      name = "delete_escape_sequence",
+      query = "(escape_sequence) @escape",
+      replace_node = "escape",
+      replace = ""
+    },
+    piranha_rule! {
+      name = "delete_argument",
+      query = "(argument_list ((identifier) @arg (#eq? @arg \"a\")))",
+      replace_node = "arg",
+      replace = ""
+    },
+  ];
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet(code_snippet.to_string())
+    .rule_graph(RuleGraphBuilder::default().rules(rules).build())
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert_eq!(
+    output_summaries[0].content(),
+    "class A {\n  void m() {\n    f(\",\");\n    g( \",\");\n    h(\", \");\n  }\n}\n"
+  );
+}
+
+/// Deleting a part of an element (e.g. the annotation of a parameter) keeps the commas separating the elements.
+#[test]
+fn test_comma_kept_when_deleting_part_of_element() {
+  initialize();
+  let code_snippet = "class A {\n  void m(@Nullable String a, int b) {}\n  void n(int a, @Nullable String b) {}\n}\n";
+  let rule = piranha_rule! {
+    name = "delete_annotation",
+    query = "(marker_annotation) @annotation",
+    replace_node = "annotation",
+    replace = ""
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet(code_snippet.to_string())
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert!(eq_without_whitespace(
+    output_summaries[0].content(),
+    "class A {\n  void m(String a, int b) {}\n  void n(int a, String b) {}\n}\n"
+  ));
+}
+
+/// Records the events of the progress of a run.
+#[derive(Debug, Default)]
+struct RecordingProgressSink {
+  events: Mutex<Vec<ProgressEvent>>,
+}
+
+impl ProgressSink for RecordingProgressSink {
+  fn on_event(&self, event: &ProgressEvent) {
+    self.events.lock().unwrap().push(event.clone());
+  }
+}
+
+#[test]
+fn test_progress_sink() {
+  initialize();
+  let temp_dir = TempDir::new_in(".", "tmp_test").unwrap();
+  let file_a = temp_dir.path().join("A.java");
+  let file_b = temp_dir.path().join("B.java");
+  fs::write(
+    &file_a,
+    "class A {\n  int a = foo();\n  int b = foo();\n}\n",
+  )
+  .unwrap();
+  fs::write(&file_b, "class B {\n  int a = bar();\n}\n").unwrap();
+  // Skipped, since it is larger than `max_file_size_bytes`
+  let file_c = temp_dir.path().join("C.java");
+  fs::write(
+    &file_c,
+    "class C {\n  int a = foo();\n  int b = foo();\n  int c = foo();\n}\n",
+  )
+  .unwrap();
+  let rule = piranha_rule! {
+    name = "replace_foo",
+    query = "((method_invocation name: (_) @name) @invocation (#eq? @name \"foo\"))",
+    replace_node = "invocation",
+    replace = "0"
+  };
+  let progress_sink = Arc::new(RecordingProgressSink::default());
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .max_file_size_bytes(Some(60))
+    .progress_sink(Some(progress_sink.clone()))
+    .build();
+  execute_piranha(&piranha_arguments);
+
+  let events = progress_sink.events.lock().unwrap().clone();
+  assert_eq!(
+    events.first(),
+    Some(&ProgressEvent::GlobalPhaseStarted { number_of_files: 3 })
+  );
+  // The code base is walked once, since no new global rule was added
+  assert_eq!(
+    events
+      .iter()
+      .filter(|e| matches!(e, ProgressEvent::GlobalPhaseStarted { .. }))
+      .count(),
+    1
+  );
+  let files_started = events
+    .iter()
+    .filter_map(|e| match e {
+      ProgressEvent::FileStarted { path } => Some(path.clone()),
+      _ => None,
+    })
+    .sorted()
+    .dedup()
+    .collect_vec();
+  assert_eq!(
+    files_started,
+    vec![file_a.clone(), file_b.clone(), file_c.clone()]
+  );
+  // Every started file is done, including the skipped one
+  assert_eq!(
+    events
+      .iter()
+      .filter(|e| matches!(e, ProgressEvent::FileStarted { .. }))
+      .count(),
+    events
+      .iter()
+      .filter(|e| matches!(e, ProgressEvent::FileDone { .. }))
+      .count()
+  );
+  let rules_applied = events
+    .iter()
+    .filter(|e| matches!(e, ProgressEvent::RuleApplied { rule, path } if rule == "replace_foo" && *path == file_a))
+    .count();
+  assert_eq!(rules_applied, 2);
+  let edits_per_file = events
+    .iter()
+    .filter_map(|e| match e {
+      ProgressEvent::FileDone { path, edits } => Some((path.clone(), *edits)),
+      _ => None,
+    })
+    .into_grouping_map()
+    .sum();
+  assert_eq!(edits_per_file.get(&file_a), Some(&2));
+  assert_eq!(edits_per_file.get(&file_b), Some(&0));
+  assert_eq!(edits_per_file.get(&file_c), Some(&0));
+  // Only the updated file is written, after all the edits
+  assert_eq!(
+    events.last(),
+    Some(&ProgressEvent::FileWritten { path: file_a })
+  );
+  temp_dir.close().unwrap();
+}
+
+/// The cleanups triggered through the `Parent` edges are reported as applied rules too.
+#[test]
+fn test_progress_sink_parent_cleanups() {
+  initialize();
+  let temp_dir = TempDir::new_in(".", "tmp_test").unwrap();
+  let file_a = temp_dir.path().join("A.java");
+  fs::write(
+    &file_a,
+    "class A {\n  void m() {\n    if (isEnabled()) {\n      a();\n    } else {\n      b();\n    }\n  }\n}\n",
+  )
+  .unwrap();
+  let rule = piranha_rule! {
+    name = "replace_is_enabled",
+    query = "((method_invocation name: (_) @name) @invocation (#eq? @name \"isEnabled\"))",
+    replace_node = "invocation",
+    replace = "true"
+  };
+  let edge = edges! {
+    from = "replace_is_enabled",
+    to = ["boolean_literal_cleanup"],
+    scope = "Parent"
+  };
+  let progress_sink = Arc::new(RecordingProgressSink::default());
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(vec![rule])
+        .edges(vec![edge])
+        .build(),
+    )
+    .progress_sink(Some(progress_sink.clone()))
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+
+  let events = progress_sink.events.lock().unwrap().clone();
+  let rules_applied = events
+    .iter()
+    .filter(|e| matches!(e, ProgressEvent::RuleApplied { .. }))
+    .count();
+  let edits = events
+    .iter()
+    .filter_map(|e| match e {
+      ProgressEvent::FileDone { edits, .. } => Some(*edits),
+      _ => None,
+    })
+    .sum::<usize>();
+  assert!(rules_applied > 1);
+  assert_eq!(rules_applied, edits);
+  assert_eq!(rules_applied, output_summaries[0].rewrites().len());
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_stages_gated_by_failing_stage() {
+  initialize();
+  let _path = PathBuf::from("test-resources").join(JAVA).join("stages");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .build();
+
+  let (summaries, stop_reason) =
+    execute_stages_with_plugins(&piranha_arguments, vec![]).into_parts();
+  let stop_reason = stop_reason.unwrap();
+  assert!(stop_reason.contains("The stage `check_single_legacy_call` failed"));
+  assert!(stop_reason.contains("the next stages (`delete_declarations`) were not executed"));
+  // The summaries of the completed stage are returned along with the stop reason
+  assert_eq!(summaries.len(), 1);
+  assert_eq!(summaries[0].stage().as_deref(), Some("rewrite_call_sites"));
+
+  // The first stage was applied, but not the third one
+  let sample = read_file(&temp_dir.path().join("Sample.java")).unwrap();
+  assert!(!sample.contains("isEnabled"));
+  assert!(sample.contains("private static final String STALE"));
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_metrics_output_on_failure() {
+  initialize();
+  let _path = PathBuf::from("test-resources").join(JAVA).join("stages");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  let metrics_dir = TempDir::new_in(".", "metrics").unwrap();
+  let metrics_output = metrics_dir.path().join("metrics.json");
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .metrics_output(Some(metrics_output.to_str().unwrap().to_string()))
+    .build();
+
+  assert!(execute_piranha_with_plugins(&piranha_arguments, vec![]).is_err());
+
+  // The metrics cover the first stage, completed before the second one failed
+  let metrics: RunMetrics = serde_json::from_str(&read_file(&metrics_output).unwrap()).unwrap();
+  assert!(!metrics.completed());
+  assert_eq!(metrics.errors().failures(), 1);
+  assert_eq!(metrics.files_edited(), 1);
+  assert_eq!(metrics.edits_by_rule().get("replace_is_enabled"), Some(&1));
+  assert!(!metrics
+    .edits_by_rule()
+    .contains_key("delete_flag_declaration"));
+  assert_eq!(metrics.metadata().get("language"), Some(&JAVA.to_string()));
+  temp_dir.close().unwrap();
+}
+
+/// Panics when it pre-processes the second file.
+#[derive(Default)]
+struct PanicOnSecondFilePlugin {
+  number_of_files: Mutex<usize>,
+}
+
+impl PiranhaPlugin for PanicOnSecondFilePlugin {
+  fn before_apply(&self, _scu: &SourceCodeView) -> Option<String> {
+    let mut number_of_files = self.number_of_files.lock().unwrap();
+    *number_of_files += 1;
+    assert!(*number_of_files < 2, "Unexpected second file");
+    None
+  }
+
+  fn after_apply(&self, _scu: &SourceCodeView) -> bool {
+    true
+  }
+}
+
+/// The metrics are written after each file, hence they cover the files processed before the run panicked.
+#[test]
+fn test_metrics_output_on_panic() {
+  initialize();
+  let temp_dir = TempDir::new_in(".", "tmp_test").unwrap();
+  for name in ["A", "B"] {
+    fs::write(
+      temp_dir.path().join(format!("{name}.java")),
+      format!("class {name} {{\n  int a = foo();\n}}\n"),
+    )
+    .unwrap();
+  }
+  let metrics_dir = TempDir::new_in(".", "metrics").unwrap();
+  let metrics_output = metrics_dir.path().join("metrics.json");
+  let rule = piranha_rule! {
+    name = "replace_foo",
+    query = "((method_invocation name: (_) @name) @invocation (#eq? @name \"foo\"))",
+    replace_node = "invocation",
+    replace = "0"
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .metrics_output(Some(metrics_output.to_str().unwrap().to_string()))
+    .build();
+
+  let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+    execute_piranha_with_plugins(
+      &piranha_arguments,
+      vec![Box::<PanicOnSecondFilePlugin>::default()],
+    )
+  }));
+  assert!(outcome.is_err());
+
+  let metrics: RunMetrics = serde_json::from_str(&read_file(&metrics_output).unwrap()).unwrap();
+  assert!(!metrics.completed());
+  assert_eq!(metrics.files_scanned(), 1);
+  assert_eq!(metrics.edits_by_rule().get("replace_foo"), Some(&1));
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_unapplied_seed_rules() {
+  initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("unapplied_seed_rules");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  let metrics_dir = TempDir::new_in(".", "metrics").unwrap();
+  let metrics_output = metrics_dir.path().join("metrics.json");
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .metrics_output(Some(metrics_output.to_str().unwrap().to_string()))
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+
+  let metrics: RunMetrics = serde_json::from_str(&read_file(&metrics_output).unwrap()).unwrap();
+  let unapplied_seed_rules = metrics.diagnostics().unapplied_seed_rules();
+  assert_eq!(
+    unapplied_seed_rules.iter().collect_vec(),
+    vec![
+      (
+        &"find_deprecated_method".to_string(),
+        &UnappliedSeedRule::NeverMatched
+      ),
+      (
+        &"find_never_called".to_string(),
+        &UnappliedSeedRule::NeverMatched
+      ),
+      (
+        &"replace_compute".to_string(),
+        &UnappliedSeedRule::FailedConstraints { count: 1 }
+      ),
+      (
+        &"replace_legacy".to_string(),
+        &UnappliedSeedRule::OutOfScope { count: 1 }
+      ),
+    ]
+  );
+  // The summary reports them too
+  assert_eq!(
+    output_summaries[0]
+      .unapplied_seed_rules()
+      .get("replace_compute"),
+    Some(&"all the matches (1) failed the filters".to_string())
+  );
+
+  // In the `cleanup_only` mode, the seed rules whose hole is not substituted are not seeded
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .cleanup_only(true)
+    .dry_run(true)
+    .metrics_output(Some(metrics_output.to_str().unwrap().to_string()))
+    .build();
+  execute_piranha(&piranha_arguments);
+  let metrics: RunMetrics = serde_json::from_str(&read_file(&metrics_output).unwrap()).unwrap();
+  assert_eq!(
+    metrics
+      .diagnostics()
+      .unapplied_seed_rules()
+      .get("delete_invocation"),
+    Some(&UnappliedSeedRule::UnsatisfiedHoles {
+      holes: vec!["method_name".to_string()]
+    })
+  );
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_archive_codebase() {
+  initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("archive_codebase");
+  let path_to_archive = _path.join("input").join("codebase.zip");
+  let output_dir = TempDir::new_in(".", "archive").unwrap();
+  let output_archive = output_dir.path().join("codebase.zip");
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(path_to_archive.to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .output_archive(Some(output_archive.to_str().unwrap().to_string()))
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert_eq!(
+    output_summaries[0].path(),
+    path_to_archive.join("src/Sample.java").to_str().unwrap()
+  );
+
+  let mut original = zip::ZipArchive::new(File::open(&path_to_archive).unwrap()).unwrap();
+  let mut updated = zip::ZipArchive::new(File::open(&output_archive).unwrap()).unwrap();
+  assert_eq!(
+    updated.file_names().sorted().collect_vec(),
+    original.file_names().sorted().collect_vec()
+  );
+  let mut sample = String::new();
+  updated
+    .by_name("src/Sample.java")
+    .unwrap()
+    .read_to_string(&mut sample)
+    .unwrap();
+  let expected = read_file(&_path.join("expected").join("Sample.java")).unwrap();
+  assert!(eq_without_whitespace(&sample, &expected));
+  // The untouched entries (and their metadata) are byte-identical
+  for name in ["src/", "src/Other.java", "README.md"] {
+    let read_raw = |archive: &mut zip::ZipArchive<File>| {
+      let index = (0..archive.len())
+        .find(|i| archive.by_index_raw(*i).unwrap().name() == name)
+        .unwrap();
+      let mut entry = archive.by_index_raw(index).unwrap();
+      let mut bytes = vec![];
+      entry.read_to_end(&mut bytes).unwrap();
+      let last_modified = entry.last_modified();
+      (
+        bytes,
+        (last_modified.datepart(), last_modified.timepart()),
+        entry.unix_mode(),
+        entry.crc32(),
+      )
+    };
+    assert_eq!(read_raw(&mut updated), read_raw(&mut original));
+  }
+  // The input archive is left as is
+  assert_eq!(original.len(), 4);
+  output_dir.close().unwrap();
+}
+
+#[test]
+fn test_stages_continue_on_failure() {
+  initialize();
+  let _path = PathBuf::from("test-resources").join(JAVA).join("stages");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  let rule_graph = read_user_config_files(
+    &_path.join("configurations").to_str().unwrap().to_string(),
+    &PiranhaLanguage::from(JAVA),
+  )
+  .unwrap();
+  let stages = rule_graph
+    .stages()
+    .iter()
+    .map(|s| {
+      let rules = s.rules().iter().map(String::as_str).collect_vec();
+      Stage::new(s.name(), &rules, ContinueOn::Always)
+    })
+    .collect_vec();
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(rule_graph.rules().clone())
+        .stages(stages)
+        .build(),
+    )
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  let stages_of_summaries = output_summaries
+    .iter()
+    .filter_map(|s| s.stage().clone())
+    .collect_vec();
+  assert_eq!(
+    stages_of_summaries,
+    vec!["rewrite_call_sites", "delete_declarations"]
+  );
+  // The third stage deleted the flag found (as a global tag) in the first stage
+  let sample = read_file(&temp_dir.path().join("Sample.java")).unwrap();
+  assert!(!sample.contains("isEnabled"));
+  assert!(!sample.contains("STALE"));
+  temp_dir.close().unwrap();
+}
+
+/// Tests whether the stages of a dry run start from the in-memory result of the previous stages, and whether the
+/// patch covers all the stages.
+#[test]
+fn test_stages_dry_run() {
+  initialize();
+  let _path = PathBuf::from("test-resources").join(JAVA).join("stages");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  let patch_file = temp_dir.path().join("changes.patch");
+  let rule_graph = read_user_config_files(
+    &_path.join("configurations").to_str().unwrap().to_string(),
+    &PiranhaLanguage::from(JAVA),
+  )
+  .unwrap();
+  let stages = rule_graph
+    .stages()
+    .iter()
+    .map(|s| {
+      let rules = s.rules().iter().map(String::as_str).collect_vec();
+      Stage::new(s.name(), &rules, ContinueOn::Always)
+    })
+    .collect_vec();
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(rule_graph.rules().clone())
+        .stages(stages)
+        .build(),
+    )
+    .dry_run(true)
+    .output_patch(Some(patch_file.to_str().unwrap().to_string()))
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  // The file is not rewritten
+  let sample = read_file(&temp_dir.path().join("Sample.java")).unwrap();
+  assert!(sample.contains("isEnabled"));
+  // The third stage started from the code rewritten by the first one
+  let last_summary = output_summaries
+    .iter()
+    .filter(|s| s.path().ends_with("Sample.java"))
+    .last()
+    .unwrap();
+  assert_eq!(last_summary.stage().as_deref(), Some("delete_declarations"));
+  assert!(!last_summary.content().contains("isEnabled"));
+  assert!(!last_summary.content().contains("STALE"));
+  // The patch covers the edits of both stages
+  let patch = read_file(&patch_file).unwrap();
+  assert!(patch.contains("-    if (isEnabled(STALE)) {"));
+  assert!(patch.contains("-  private static final String STALE = \"stale\";"));
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_replace_with_capture() {
+  initialize();
+  let code_snippet = r#"class A {
+  String s = unwrap("C:\\dir\\@name %{name} \u00e9 é");
+}
+"#;
+  let unwrap_rule = |replace: &str, replace_with_capture: &str| {
+    piranha_rule! {
+      name = "unwrap",
+      query = "((method_invocation name: (_) @name arguments: (argument_list (_) @arg)) @call (#eq? @name \"unwrap\"))",
+      replace_node = "call",
+      replace = replace,
+      replace_with_capture = replace_with_capture
+    }
+  };
+  let rewrite = |rule| {
+    let piranha_arguments = PiranhaArgumentsBuilder::default()
+      .language(PiranhaLanguage::from(JAVA))
+      .code_snippet(code_snippet.to_string())
+      .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+      .build();
+    execute_piranha(&piranha_arguments)[0].content().to_string()
+  };
+
+  // The captured code is copied byte for byte
+  let expected = r#"class A {
+  String s = "C:\\dir\\@name %{name} \u00e9 é";
+}
+"#;
+  assert_eq!(rewrite(unwrap_rule("", "arg")), expected);
+  // The template does not substitute the tags and placeholders found in the captured code either
+  assert_eq!(rewrite(unwrap_rule("@arg", "")), expected);
+}
+
+#[test]
+fn test_directory_scope() {
+  initialize();
+  let temp_dir = TempDir::new_in(".", "tmp_test").unwrap();
+  let write = |relative_path: &str, code: &str| {
+    let path = temp_dir.path().join(relative_path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, code).unwrap();
+  };
+  let usage = "boolean f() { return Payments.STALE_FLAG; }";
+  write(
+    "services/Payments/Payments.java",
+    "class Payments { static final boolean STALE_FLAG = false; }",
+  );
+  write(
+    "services/Payments/Checkout.java",
+    &format!("class Checkout {{ {usage} }}"),
+  );
+  write(
+    "services/Rides/Rides.java",
+    &format!("class Rides {{ {usage} }}"),
+  );
+
+  let rules = vec![
+    piranha_rule! {
+      name = "find_flag_owner",
+      query = "(
+        (class_declaration name: (_) @service_name
+          body: (class_body (field_declaration declarator: (variable_declarator name: (_) @f))))
+        (#eq? @f \"STALE_FLAG\"))"
+    },
+    piranha_rule! {
+      name = "replace_flag_usage",
+      query = "((field_access field: (_) @f) @access (#eq? @f \"STALE_FLAG\"))",
+      replace_node = "access",
+      replace = "true",
+      holes = ["service_name"],
+      is_seed_rule = false,
+      directory_scope = "services/@service_name/**"
+    },
+  ];
+  let edges = vec![edges! {
+    from = "find_flag_owner",
+    to = ["replace_flag_usage"],
+    scope = "Global"
+  }];
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(rules)
+        .edges(edges)
+        .build(),
+    )
+    .build();
+  execute_piranha(&piranha_arguments);
+
+  // The flag is only replaced within the directory of the service declaring it
+  let checkout = read_file(&temp_dir.path().join("services/Payments/Checkout.java")).unwrap();
+  assert!(checkout.contains("return true;"));
+  let rides = read_file(&temp_dir.path().join("services/Rides/Rides.java")).unwrap();
+  assert!(rides.contains("return Payments.STALE_FLAG;"));
+  temp_dir.close().unwrap();
+}
+
+/// The injection sites of the deleted class that are still used are reported (by the match-only rules of
+/// `remove_dependency_injection_bindings`), rather than deleted.
+#[test]
+fn test_report_used_dependency_injection_sites() {
+  initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("remove_dependency_injection_bindings");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .substitutions(substitutions! {"flag_holder_class" => "StaleFlagHolder"})
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+
+  let reported = output_summaries
+    .iter()
+    .flat_map(|summary| summary.matches())
+    .filter(|(rule_name, _)| rule_name.starts_with("report_used_injected"))
+    .map(|(rule_name, m)| (rule_name.as_str(), m.matched_string().as_str()))
+    .collect_vec();
+  assert_eq!(
+    reported,
+    vec![
+      (
+        "report_used_injected_field",
+        "@Inject StaleFlagHolder usedHolder;"
+      ),
+      (
+        "report_used_injected_constructor_parameter",
+        "@Inject\n  UsedConsumer(StaleFlagHolder holder) {\n    this.enabled = holder.isEnabled();\n  }"
+      ),
+    ]
+  );
+  temp_dir.close().unwrap();
+}
+
+/// The rule matches the whole method declaration (the filter only accepts the methods returning a value), but only
+/// deletes the `@FlagGated` annotations captured within the match - each of them, when the method has several.
+#[test]
+fn test_replace_capture_within_match() {
+  initialize();
+  let rule = piranha_rule! {
+    name = "delete_flag_gated_annotation",
+    query = "(
+  (method_declaration (modifiers (annotation name: (_) @name) @annotation)) @method
+  (#eq? @name \"FlagGated\")
+  )",
+    replace_node = "@annotation",
+    replace = "",
+    filters = [
+      FilterBuilder::default()
+        .contains(CGPattern::new("(return_statement) @return".to_string()))
+        .build()
+    ]
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet(
+      "class A {
+  @FlagGated(\"x\") @Override @FlagGated(\"y\")
+  public boolean isEnabled() { return true; }
+  @FlagGated(\"z\")
+  public void run() { }
+}"
+      .to_string(),
+    )
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert!(eq_without_whitespace(
+    output_summaries[0].content(),
+    "class A {
+  @Override
+  public boolean isEnabled() { return true; }
+  @FlagGated(\"z\")
+  public void run() { }
+}"
+  ));
+  // The edits replace the annotations, not the method
+  assert_eq!(
+    output_summaries[0]
+      .rewrites()
+      .iter()
+      .map(|edit| edit.p_match().matched_string().trim().to_string())
+      .collect_vec(),
+    vec!["@FlagGated(\"y\")", "@FlagGated(\"x\")"]
+  );
+}
+
+/// The trailing comma of the deleted capture (rather than of the whole match) is deleted too.
+#[test]
+fn test_delete_capture_within_match_with_comma() {
+  initialize();
+  let rule = piranha_rule! {
+    name = "delete_flag_argument",
+    query = "(
+  (method_invocation name: (_) @name arguments: (argument_list (identifier) @argument)) @invocation
+  (#eq? @name \"foo\")
+  (#eq? @argument \"FLAG\")
+  )",
+    replace_node = "@argument",
+    replace = ""
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet("class A { void m() { foo(a, FLAG, b); bar(a, FLAG); } }".to_string())
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert!(eq_without_whitespace(
+    output_summaries[0].content(),
+    "class A { void m() { foo(a, b); bar(a, FLAG); } }"
+  ));
+}
+
+/// The middle one of three top-level types is deleted: the rules triggered by an edit within another type are
+/// scoped to the type enclosing the edit, and the reviewers are notified to check the imports of the deleted type.
+#[test]
+fn test_multiple_top_level_classes() {
+  initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(JAVA)
+    .join("multiple_top_level_classes");
+  let temp_dir = copy_folder_to_temp_dir(&_path.join("input"));
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .path_to_configurations(_path.join("configurations").to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert!(eq_without_whitespace(
+    output_summaries[0].content(),
+    &read_file(&_path.join("expected").join("Sample.java")).unwrap()
+  ));
+  assert_eq!(
+    output_summaries[0].notes(),
+    &vec!["The rule `delete_middle_class` deleted 1 of the 3 top-level type declarations (at line(s) 22): check the imports used only by the deleted declaration(s)".to_string()]
+  );
+  temp_dir.close().unwrap();
+}
+
+/// The XPath-like constraints select the classes declaring a `setUp` method (`Filter::from_xpath`), and the
+/// statements within the `setUp` methods (the translated query as the `enclosing_node`).
+#[test]
+fn test_xpath_constraints() {
+  initialize();
+  let java = PiranhaLanguage::from(JAVA);
+  let find_test_classes = piranha_rule! {
+    name = "find_test_classes",
+    query = "((class_declaration name: (_) @name) @class)",
+    filters = [Filter::from_xpath("//method_declaration[@name='setUp']", &java).unwrap(),]
+  };
+  let delete_logs_in_set_up = piranha_rule! {
+    name = "delete_logs_in_set_up",
+    query = "(
+  (expression_statement (method_invocation name: (_) @name)) @statement
+  (#eq? @name \"log\")
+  )",
+    replace_node = "statement",
+    replace = "",
+    filters = [filter! {
+      enclosing_node = xpath_to_query("//method_declaration[@name='setUp']", &java).unwrap()
+    },]
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(java)
+    .code_snippet(
+      "class FooTest { void setUp() { log(); } void other() { log(); } }
+class BarTest { void other() { log(); } }"
+        .to_string(),
+    )
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(vec![find_test_classes, delete_logs_in_set_up])
+        .build(),
+    )
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert!(eq_without_whitespace(
+    output_summaries[0].content(),
+    "class FooTest { void setUp() { } void other() { log(); } }
+class BarTest { void other() { log(); } }"
+  ));
+  assert_eq!(
+    output_summaries[0]
+      .matches()
+      .iter()
+      .map(|(_, m)| m.matches()["name"].as_str())
+      .collect_vec(),
+    vec!["FooTest"]
+  );
+}
+
+/// The descendant axis between two steps nests the filter (`Filter::from_xpath`), i.e. the fields of the classes
+/// declaring a `setUp` method.
+#[test]
+fn test_xpath_nested_constraint() {
+  initialize();
+  let java = PiranhaLanguage::from(JAVA);
+  let find_test_fields = piranha_rule! {
+    name = "find_test_fields",
+    query = "((field_declaration declarator: (variable_declarator name: (_) @name)) @field)",
+    filters = [Filter::from_xpath("//class_declaration//method_declaration[@name='setUp']", &java).unwrap(),]
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(java)
+    .code_snippet(
+      "class FooTest { int a; void setUp() { } }
+class BarTest { int b; void other() { } }"
+        .to_string(),
+    )
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(vec![find_test_fields])
+        .build(),
+    )
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert_eq!(
+    output_summaries[0]
+      .matches()
+      .iter()
+      .map(|(_, m)| m.matches()["name"].as_str())
+      .collect_vec(),
+    vec!["a"]
+  );
+}
+
+/// The declared package is bound to `@__package__`, and looked up again once an edit changes it.
+#[test]
+fn test_package_tag_and_updated_package() {
+  initialize();
+  let package_filter =
+    |exact: &str| toml::from_str::<PackageFilter>(&format!("exact = \"{exact}\"")).unwrap();
+  let rename_package = piranha_rule! {
+    name = "rename_package",
+    query = "((package_declaration (scoped_identifier) @package) (#eq? @package \"com.uber.old\"))",
+    replace_node = "package",
+    replace = "com.uber.updated",
+    package_filter = package_filter("com.uber.old")
+  };
+  let log_package = piranha_rule! {
+    name = "log_package",
+    query = "((method_invocation name: (_) @name arguments: (argument_list) @args) (#eq? @name \"log\") (#eq? @args \"()\"))",
+    replace_node = "args",
+    replace = "(\"@__package__\")",
+    package_filter = package_filter("com.uber.updated")
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet("package com.uber.old;\nclass A { void f() { log(); } }".to_string())
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(vec![rename_package, log_package])
+        .build(),
+    )
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert!(eq_without_whitespace(
+    output_summaries[0].content(),
+    "package com.uber.updated;\nclass A { void f() { log(\"com.uber.updated\"); } }"
+  ));
+}
+
+/// The "Parent" rules rewriting each other back and forth are stopped after `max_propagation_depth` cleanups,
+/// and the chain of cleanups is noted (and traced with `trace_propagation`) in the output summary.
+#[test]
+fn test_max_propagation_depth() {
+  initialize();
+  let rename = |name: &str, from: &str, to: &str, is_seed_rule: bool| {
+    piranha_rule! {
+      name = name,
+      query = &format!("((method_invocation name: (_) @name) @call (#eq? @name \"{from}\"))"),
+      replace_node = "call",
+      replace = &format!("{to}()"),
+      is_seed_rule = is_seed_rule
+    }
+  };
+  let edges = vec![
+    edges! {
+      from = "x_to_a",
+      to = ["a_to_b"],
+      scope = "Parent"
+    },
+    edges! {
+      from = "a_to_b",
+      to = ["b_to_a"],
+      scope = "Parent"
+    },
+    edges! {
+      from = "b_to_a",
+      to = ["a_to_b"],
+      scope = "Parent"
+    },
+  ];
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet("class A { void m() { x(); } }".to_string())
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(vec![
+          rename("x_to_a", "x", "a", true),
+          rename("a_to_b", "a", "b", false),
+          rename("b_to_a", "b", "a", false),
+        ])
+        .edges(edges)
+        .build(),
+    )
+    .max_propagation_depth(3)
+    .trace_propagation(true)
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert!(eq_without_whitespace(
+    output_summaries[0].content(),
+    "class A { void m() { b(); } }"
+  ));
+  assert_eq!(output_summaries[0].rewrites().len(), 4);
+  assert_eq!(
+    output_summaries[0].notes(),
+    &vec!["Stopped the cleanup chain triggered by the rule `x_to_a` after 3 \"Parent\" cleanup(s) (see `max_propagation_depth`): `x_to_a` (1:22-1:25) -> `a_to_b` (1:22-1:25) -> `b_to_a` (1:22-1:25) -> `a_to_b` (1:22-1:25) -> `b_to_a` (1:22-1:25)".to_string()]
+  );
+  assert_eq!(
+    output_summaries[0]
+      .propagation_traces()
+      .iter()
+      .map(|trace| trace.iter().map(|(rule, _)| rule.as_str()).collect_vec())
+      .collect_vec(),
+    vec![vec!["x_to_a", "a_to_b", "b_to_a", "a_to_b"]]
+  );
+}
+
+/// The rules written in concrete syntax (`query_cst`) match the code with the same structure, where the holes match
+/// any single node (the same code for a repeated hole) and are captured for the replacement.
+#[test]
+fn test_query_cst() {
+  initialize();
+  let replace_is_treated = piranha_rule! {
+    name = "replace_is_treated",
+    replace_node = "cst",
+    replace = "FeatureFlags.isEnabled(@flag)",
+    query_cst = "exp.isTreated(:[flag])"
+  };
+  let simplify_self_equals = piranha_rule! {
+    name = "simplify_self_equals",
+    replace_node = "cst",
+    replace = "true",
+    query_cst = ":[x].equals(:[x])"
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet(
+      "class A {
+  void m() {
+    if (exp.isTreated(FLAG)) { a(); }
+    if (exp.isTreated(FLAG, 1)) { b(); }
+    if (other.isTreated(FLAG)) { c(); }
+    boolean s = a.equals(a);
+    boolean t = a.equals(b);
+  }
+}"
+      .to_string(),
+    )
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(vec![replace_is_treated, simplify_self_equals])
+        .build(),
+    )
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert!(eq_without_whitespace(
+    output_summaries[0].content(),
+    "class A {
+  void m() {
+    if (FeatureFlags.isEnabled(FLAG)) { a(); }
+    if (exp.isTreated(FLAG, 1)) { b(); }
+    if (other.isTreated(FLAG)) { c(); }
+    boolean s = true;
+    boolean t = a.equals(b);
+  }
+}"
+  ));
+}
+
+/// Tests whether the "Parent" cleanups go through the `dry` rules and exclusive groups, like the seed rules,
+/// and whether the ranges of the suggested rewrites follow the later edits.
+#[test]
+fn test_parent_cleanups_dry_and_exclusive() {
+  initialize();
+  let rules = |exclusive_group: &str, dry: bool| {
+    vec![
+      piranha_rule! {
+        name = "replace_is_treated",
+        replace_node = "cst",
+        replace = "true",
+        query_cst = "exp.isTreated(:[flag])",
+        exclusive_group = exclusive_group
+      },
+      piranha_rule! {
+        name = "rename_class",
+        query = "(class_declaration name: (_) @name)",
+        replace_node = "name",
+        replace = "Renamed"
+      },
+      piranha_rule! {
+        name = "replace_true",
+        query = "((true) @value)",
+        replace_node = "value",
+        replace = "false",
+        is_seed_rule = false,
+        exclusive_group = exclusive_group,
+        dry = dry
+      },
+    ]
+  };
+  let execute = |rules: Vec<Rule>| {
+    let piranha_arguments = PiranhaArgumentsBuilder::default()
+      .language(PiranhaLanguage::from(JAVA))
+      .code_snippet(
+        "class A {
+  void m() {
+    if (exp.isTreated(FLAG)) { a(); }
+  }
+}"
+        .to_string(),
+      )
+      .rule_graph(
+        RuleGraphBuilder::default()
+          .rules(rules)
+          .edges(vec![edges! {
+            from = "replace_is_treated",
+            to = ["replace_true"],
+            scope = "Parent"
+          }])
+          .build(),
+      )
+      .build();
+    execute_piranha(&piranha_arguments).remove(0)
+  };
+
+  // The cleanup of a `dry` rule is suggested, and its range denotes the final code
+  let summary = execute(rules("", true));
+  assert!(summary.content().contains("class Renamed"));
+  assert!(summary.content().contains("if (true)"));
+  let suggested_rewrites = summary.suggested_rewrites();
+  assert_eq!(suggested_rewrites.len(), 1);
+  let range = suggested_rewrites[0].p_match().range();
+  assert_eq!(&summary.content()[range.start_byte..range.end_byte], "true");
+
+  // The cleanup is not applied to the code rewritten by another rule of its exclusive group
+  let summary = execute(rules("treatment_handling", false));
+  assert!(summary.content().contains("if (true)"));
+  assert!(summary.suggested_rewrites().is_empty());
+  assert_eq!(summary.notes().len(), 1);
+  assert!(summary.notes()[0].contains("The rule `replace_true` was not applied"));
+}
+
+#[test]
+fn test_dry_rule() {
+  initialize();
+  let replace_is_treated = piranha_rule! {
+    name = "replace_is_treated",
+    replace_node = "cst",
+    replace = "true",
+    query_cst = "exp.isTreated(:[flag])"
+  };
+  let delete_log = piranha_rule! {
+    name = "delete_log",
+    replace_node = "cst",
+    replace = "",
+    query_cst = "log(:[message]);",
+    dry = true
+  };
+  let rename_method = piranha_rule! {
+    name = "rename_method",
+    query = "(method_declaration name: (_) @name) @md",
+    replace_node = "name",
+    replace = "renamed",
+    is_seed_rule = false
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(JAVA))
+    .code_snippet(
+      "class A {
+  void m() {
+    if (exp.isTreated(FLAG)) { a(); }
+    log(\"x\");
+    log(\"y\");
+  }
+}"
+      .to_string(),
+    )
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(vec![replace_is_treated, delete_log, rename_method])
+        .edges(vec![edges! {
+          from = "delete_log",
+          to = ["rename_method"],
+          scope = "Method"
+        }])
+        .build(),
+    )
+    .build();
+
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  let summary = &output_summaries[0];
+  // The edits of the dry rule are not applied, and do not trigger the next rules
+  assert!(eq_without_whitespace(
+    summary.content(),
+    "class A {
+  void m() {
+    if (true) { a(); }
+    log(\"x\");
+    log(\"y\");
+  }
+}"
+  ));
+  assert_eq!(summary.rewrites().len(), 1);
+  assert_eq!(
+    summary
+      .suggested_rewrites()
+      .iter()
+      .map(|edit| (
+        edit.matched_rule().to_string(),
+        edit.p_match().matched_string().to_string(),
+        edit.replacement_string().to_string()
+      ))
+      .collect::<Vec<_>>(),
+    vec![
+      (
+        "delete_log".to_string(),
+        "log(\"x\");".to_string(),
+        String::new()
+      ),
+      (
+        "delete_log".to_string(),
+        "log(\"y\");".to_string(),
+        String::new()
+      ),
+    ]
+  );
+}
+
+#[test]
+fn test_global_rule_variants() {
+  initialize();
+  let temp_dir = TempDir::new_in(".", "tmp_test").unwrap();
+  // The same flag is resolved to a different constant in each module
+  fs::write(
+    temp_dir.path().join("A.java"),
+    "class A {\n  static final Experiment STALE = Experiment.ALPHA;\n}\n",
+  )
+  .unwrap();
+  fs::write(
+    temp_dir.path().join("B.java"),
+    "class B {\n  static final Experiment STALE = Experiment.BETA;\n}\n",
+  )
+  .unwrap();
+  let file_c = temp_dir.path().join("C.java");
+  fs::write(
+    &file_c,
+    "class C {\n  boolean a = isOn(Experiment.ALPHA);\n  boolean b = isOn(Experiment.BETA);\n  boolean c = isOn(Experiment.GAMMA);\n}\n",
+  )
+  .unwrap();
+  let find_stale_constant = piranha_rule! {
+    name = "find_stale_constant",
+    query = "((variable_declarator name: (_) @name value: (_) @constant) @vd (#eq? @name \"STALE\"))"
+  };
+  let replace_is_on = piranha_rule! {
+    name = "replace_is_on",
+    query = "((method_invocation name: (_) @name arguments: (argument_list (_) @arg)) @mi (#eq? @name \"isOn\") (#eq? @arg \"@constant\"))",
+    replace_node = "mi",
+    replace = "true",
+    holes = ["constant"],
+    is_seed_rule = false
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(vec![find_stale_constant, replace_is_on])
+        .edges(vec![edges! {
+          from = "find_stale_constant",
+          to = ["replace_is_on"],
+          scope = "Global"
+        }])
+        .build(),
+    )
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+
+  // Both variants of the global rule are applied
+  let summary = output_summaries
+    .iter()
+    .find(|s| Path::new(s.path()) == file_c)
+    .unwrap();
+  assert!(eq_without_whitespace(
+    &read_file(&file_c).unwrap(),
+    "class C {\n  boolean a = true;\n  boolean b = true;\n  boolean c = isOn(Experiment.GAMMA);\n}\n"
+  ));
+  // Each edit is attributed to the variant that produced it
+  let rewrites_by_variant = summary
+    .rewrites_by_variant()
+    .iter()
+    .map(|(variant, indices)| {
+      let matched = indices
+        .iter()
+        .map(|i| {
+          summary.rewrites()[*i]
+            .p_match()
+            .matched_string()
+            .to_string()
+        })
+        .collect_vec();
+      (variant.to_string(), matched)
+    })
+    .collect_vec();
+  assert_eq!(
+    rewrites_by_variant,
+    vec![
+      (
+        "replace_is_on(constant=Experiment.ALPHA)".to_string(),
+        vec!["isOn(Experiment.ALPHA)".to_string()]
+      ),
+      (
+        "replace_is_on(constant=Experiment.BETA)".to_string(),
+        vec!["isOn(Experiment.BETA)".to_string()]
+      ),
+    ]
+  );
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_apply_edit_at_offset() {
+  initialize();
+  let code_snippet = "class A {\n  boolean b = isEnabled();\n}\n";
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .code_snippet(code_snippet.to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .build();
+  let start_byte = code_snippet.find("isEnabled()").unwrap();
+
+  let summary =
+    apply_edit_at_offset(&piranha_arguments, start_byte, start_byte + 11, "true").unwrap();
+  assert_eq!(summary.content(), "class A {\n  boolean b = true;\n}\n");
+  assert_eq!(summary.rewrites().len(), 1);
+  // The edit would introduce a syntax error
+  assert!(apply_edit_at_offset(&piranha_arguments, start_byte, start_byte, "(").is_err());
+  assert!(apply_edit_at_offset(&piranha_arguments, 10, 5, "").is_err());
+}
+
+#[test]
+fn test_refactor_rename_symbol() {
+  initialize();
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .code_snippet(
+      "class A {\n  Foo foo = new Foo();\n  int m() { return foo.get(); }\n}\n".to_string(),
+    )
+    .language(PiranhaLanguage::from(JAVA))
+    .build();
+
+  let summary = refactor_rename_symbol(&piranha_arguments, "foo", "bar").unwrap();
+  assert_eq!(
+    summary.content(),
+    "class A {\n  Foo bar = new Foo();\n  int m() { return bar.get(); }\n}\n"
+  );
+  assert_eq!(summary.rewrites().len(), 2);
+  // Nothing to rename
+  let summary = refactor_rename_symbol(&piranha_arguments, "missing", "other").unwrap();
+  assert!(summary.rewrites().is_empty());
+}
+
+#[test]
+fn test_apply_rules_on_lines() {
+  initialize();
+  let replace_1_with_2 = piranha_rule! {
+    name = "replace_1_with_2",
+    query = "((decimal_integer_literal) @value (#eq? @value \"1\"))",
+    replace_node = "value",
+    replace = "2"
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .code_snippet("class A {\n  int a = 1;\n  int b = 1;\n  int c = 1;\n}\n".to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(vec![replace_1_with_2])
+        .build(),
+    )
+    .build();
+
+  // Only the matches on the lines 1 and 2 are rewritten
+  let summary = apply_rules_on_lines(&piranha_arguments, 1, 2, false).unwrap();
+  assert_eq!(
+    summary.content(),
+    "class A {\n  int a = 2;\n  int b = 2;\n  int c = 1;\n}\n"
+  );
+  assert_eq!(summary.rewrites().len(), 2);
+}
+
+#[test]
+fn test_replace_all_literals() {
+  initialize();
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .code_snippet("class A {\n  int a = OLD_NAME;\n  String b = \"OLD_NAME\";\n}\n".to_string())
+    .language(PiranhaLanguage::from(JAVA))
+    .build();
+
+  // The occurrences within the string literals are replaced too
+  let summary = replace_all_literals(&piranha_arguments, "OLD_NAME", "NEW_NAME").unwrap();
+  assert_eq!(
+    summary.content(),
+    "class A {\n  int a = NEW_NAME;\n  String b = \"NEW_NAME\";\n}\n"
+  );
+  assert_eq!(summary.rewrites().len(), 2);
+  // The replacement would introduce a syntax error
+  assert!(replace_all_literals(&piranha_arguments, "OLD_NAME", "(").is_err());
+}
@@ -11,9 +11,17 @@ Copyright (c) 2023 Uber Technologies, Inc.
  limitations under the License.
 */
 
-use crate::models::default_configs::KOTLIN;
+use crate::{
+  execute_piranha,
+  models::{
+    default_configs::KOTLIN, language::PiranhaLanguage, piranha_arguments::PiranhaArgumentsBuilder,
+    rule_graph::RuleGraphBuilder,
+  },
+  piranha_rule,
+  utilities::eq_without_whitespace,
+};
 
-use super::{create_rewrite_tests, substitutions};
+use super::{create_rewrite_tests, initialize, substitutions};
 
 create_rewrite_tests! {
   KOTLIN,
@@ -45,4 +53,61 @@ create_rewrite_tests! {
         "namespace" => "some_long_name"
       }, cleanup_comments= true;
   test_file_scoped_chain_rules: "file_scoped_chain_rules",  1;
+  test_delete_empty_enclosing: "delete_empty_enclosing", 1;
+  test_remove_annotation: "remove_annotation/delete", 1,
+    substitutions = substitutions! {
+      "annotation_name" => "ExperimentEnabled"
+    };
+  test_replace_annotation: "remove_annotation/replace", 1,
+    substitutions = substitutions! {
+      "annotation_name" => "ExperimentEnabled",
+      "replacement_annotation" => "@Experiment(@argument)"
+    };
+  test_multiple_top_level_classes: "multiple_top_level_classes", 2;
+}
+
+/// Deleting the modifiers of a parameter keeps the comma preceding them.
+#[test]
+fn test_comma_kept_when_deleting_part_of_element() {
+  initialize();
+  let rule = piranha_rule! {
+    name = "delete_parameter_modifiers",
+    query = "(parameter_modifiers) @modifiers",
+    replace_node = "modifiers",
+    replace = ""
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(KOTLIN))
+    .code_snippet("fun m(a: String, @Nullable b: Int) {}\n".to_string())
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert!(eq_without_whitespace(
+    output_summaries[0].content(),
+    "fun m(a: String, b: Int) {}\n"
+  ));
+}
+
+/// The commas deleted along with an argument are the separators of the `value_arguments` containing it.
+#[test]
+fn test_trailing_comma_scoped_to_lists() {
+  initialize();
+  let rule = piranha_rule! {
+    name = "delete_argument",
+    query = "((value_argument (simple_identifier) @arg) @argument (#eq? @arg \"FLAG\"))",
+    replace_node = "argument",
+    replace = ""
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(KOTLIN))
+    .code_snippet("fun m() {\n  f(a, FLAG, b)\n  g(FLAG, \",\")\n}\n".to_string())
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert!(eq_without_whitespace(
+    output_summaries[0].content(),
+    "fun m() {\n  f(a, b)\n  g(\",\")\n}\n"
+  ));
 }
@@ -0,0 +1,26 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use crate::models::default_configs::PROTO;
+
+use super::{create_rewrite_tests, substitutions};
+
+create_rewrite_tests! {
+    PROTO,
+    test_delete_deprecated_field: "delete_deprecated_field", 1,
+      substitutions = substitutions! {
+        "deprecated_field_name" => "legacy_name",
+        "deprecated_field_name" => "nick_name",
+        "deprecated_field_name" => "legacy_attributes"
+      }, cleanup_comments = true, cleanup_only = true;
+}
@@ -16,10 +16,15 @@ use assert_cmd::prelude::{CommandCargoExt, OutputAssertExt};
 use std::{collections::HashMap, fs::File, path::Path, process::Command};
 use tempdir::TempDir;
 
-use super::create_match_tests;
+use super::{create_match_tests, create_rewrite_tests, initialize, substitutions};
 
 use crate::{
-  models::{default_configs::PYTHON, piranha_output::PiranhaOutputSummary},
+  execute_piranha,
+  models::{
+    default_configs::PYTHON, language::PiranhaLanguage, piranha_arguments::PiranhaArgumentsBuilder,
+    piranha_output::PiranhaOutputSummary, rule_graph::RuleGraphBuilder,
+  },
+  piranha_rule,
   utilities::{eq_without_whitespace, read_file},
 };
 
@@ -58,3 +63,163 @@ fn test_delete_modify_str_literal_from_list_via_cli() {
 }
 
 create_match_tests!(PYTHON, test_match_only: "structural_find", HashMap::from([("find_lists_with_str_literals", 3)]););
+
+create_rewrite_tests! {
+  PYTHON,
+  test_empty_files: "empty_files", 1, delete_file_if_empty = true;
+  test_cleanup_only: "cleanup_only", 1, cleanup_only = true;
+  test_exhaustive_parent_rules: "exhaustive_parent_rules", 1,
+    substitutions = substitutions! {
+      "stale_flag_name" => "STALE_FLAG"
+    };
+  test_comment_deletion_all_files: "comment_deletion/all_files", 2,
+    substitutions = substitutions! {
+      "stale_flag_name" => "STALE_FLAG"
+    },
+    comment_deletion_patterns = vec!["TODO.*@stale_flag_name".to_string()],
+    comment_deletion_in_all_files = true;
+}
+
+/// The shebang and the encoding declaration are not deleted along with the (comments of the) first function.
+#[test]
+fn test_shebang_and_encoding_declaration_preserved() {
+  initialize();
+  let code_snippet = "#!/usr/bin/env python\n# -*- coding: utf-8 -*-\ndef foo():\n    pass\n\n\ndef bar():\n    pass\n";
+  let rule = piranha_rule! {
+    name = "delete_foo",
+    query = "(
+  (function_definition name: (_) @name) @function
+  (#eq? @name \"foo\")
+  )",
+    replace_node = "function",
+    replace = ""
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(PYTHON))
+    .code_snippet(code_snippet.to_string())
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .cleanup_comments(true)
+    .delete_consecutive_new_lines(true)
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert_eq!(
+    output_summaries[0].content(),
+    "#!/usr/bin/env python\n# -*- coding: utf-8 -*-\n\ndef bar():\n    pass\n"
+  );
+}
+
+/// The matches in the regions suppressed by the `piranha:disable` comment directives are not rewritten,
+/// and the block that is not closed runs until the end of the file.
+#[test]
+fn test_suppression_directives() {
+  initialize();
+  let code_snippet = "foo()\n# piranha:disable-next-line\nfoo()\n# piranha:disable rename_bar\nfoo()\nbar()\n# piranha:enable\nbar()\n# piranha:disable\nfoo()\n";
+  let rules = vec![
+    piranha_rule! {
+      name = "rename_foo",
+      query = "((identifier) @id (#eq? @id \"foo\"))",
+      replace_node = "id",
+      replace = "qux"
+    },
+    piranha_rule! {
+      name = "rename_bar",
+      query = "((identifier) @id (#eq? @id \"bar\"))",
+      replace_node = "id",
+      replace = "baz"
+    },
+  ];
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(PYTHON))
+    .code_snippet(code_snippet.to_string())
+    .rule_graph(RuleGraphBuilder::default().rules(rules).build())
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert_eq!(
+    output_summaries[0].content(),
+    "qux()\n# piranha:disable-next-line\nfoo()\n# piranha:disable rename_bar\nqux()\nbar()\n# piranha:enable\nbaz()\n# piranha:disable\nfoo()\n"
+  );
+  let skip_counts = output_summaries[0].skip_counts();
+  assert_eq!(skip_counts["rename_foo"]["suppressed_by_comment"], 2);
+  assert_eq!(skip_counts["rename_bar"]["suppressed_by_comment"], 1);
+}
+
+/// The commas deleted along with a node are the separators of the list containing it, never the text of a string.
+#[test]
+fn test_trailing_comma_scoped_to_lists() {
+  initialize();
+  let code_snippet = "s = f\"{x},\"\nprint(x, \",\")\n";
+  let rules = vec![
+    piranha_rule! {
+      name = "delete_interpolation",
+      query = "(interpolation) @interpolation",
+      replace_node = "interpolation",
+      replace = ""
+    },
+    piranha_rule! {
+      name = "delete_argument",
+      query = "(argument_list ((identifier) @arg (#eq? @arg \"x\")))",
+      replace_node = "arg",
+      replace = ""
+    },
+  ];
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(PYTHON))
+    .code_snippet(code_snippet.to_string())
+    .rule_graph(RuleGraphBuilder::default().rules(rules).build())
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert_eq!(output_summaries[0].content(), "s = f\",\"\nprint( \",\")\n");
+}
+
+/// Deleting a part of an element (e.g. the arguments of a call) keeps the commas separating the elements.
+#[test]
+fn test_comma_kept_when_deleting_part_of_element() {
+  initialize();
+  let rule = piranha_rule! {
+    name = "delete_arguments",
+    query = "(call function: (identifier) @name arguments: (argument_list) @args (#eq? @name \"f\"))",
+    replace_node = "args",
+    replace = ""
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(PYTHON))
+    .code_snippet("x = [f(a), b]\n".to_string())
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert_eq!(output_summaries[0].content(), "x = [f, b]\n");
+}
+
+/// The comments of the deleted Python code are deleted along with it when `cleanup_comments` is enabled
+/// (the `comment` nodes are the comments of the Python grammar).
+#[test]
+fn test_cleanup_comments() {
+  initialize();
+  let code_snippet =
+    "def foo():\n    pass\n\n\n# Calls foo\nfoo()  # Not needed anymore\nbar(a, b)  # Calls bar\n";
+  let rule = piranha_rule! {
+    name = "delete_foo_call",
+    query = "(
+  (expression_statement (call function: (_) @name)) @statement
+  (#eq? @name \"foo\")
+  )",
+    replace_node = "statement",
+    replace = ""
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .language(PiranhaLanguage::from(PYTHON))
+    .code_snippet(code_snippet.to_string())
+    .rule_graph(RuleGraphBuilder::default().rules(vec![rule]).build())
+    .cleanup_comments(true)
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+  assert!(eq_without_whitespace(
+    output_summaries[0].content(),
+    "def foo():\n    pass\n\n\nbar(a, b)  # Calls bar\n"
+  ));
+}
@@ -15,8 +15,12 @@ use std::path::PathBuf;
 
 use super::{create_rewrite_tests, execute_piranha_and_check_result, substitutions};
 
-use crate::models::{
-  default_configs::SWIFT, language::PiranhaLanguage, piranha_arguments::PiranhaArgumentsBuilder,
+use crate::{
+  execute_piranha,
+  models::{
+    default_configs::SWIFT, language::PiranhaLanguage, piranha_arguments::PiranhaArgumentsBuilder,
+  },
+  utilities::read_file,
 };
 
 create_rewrite_tests! {
@@ -41,6 +45,12 @@ create_rewrite_tests! {
       "stale_flag" => "one"
     },
     cleanup_comments = true, delete_file_if_empty= false;
+  test_package_manifest_cleanup: "package_manifest_cleanup", 1,
+    substitutions = substitutions! {
+      "package_url" => "https://github.com/example/experimentation-sdk.git",
+      "package_name" => "experimentation-sdk"
+    },
+    delete_file_if_empty= false;
 }
 
 fn execute_piranha_with_default_swift_args(scenario: &str, substitutions: Vec<(String, String)>) {
@@ -106,3 +116,37 @@ fn test_delete_everything_after_return() {
   super::initialize();
   execute_piranha_with_default_swift_args("delete_statements_after_return", vec![]);
 }
+
+#[test]
+#[ignore] // Long running test
+fn test_guard_and_trailing_closures() {
+  super::initialize();
+  execute_piranha_with_default_swift_args("guard_and_trailing_closures", vec![]);
+}
+
+#[test]
+fn test_remove_pbxproj_references() {
+  super::initialize();
+  let _path = PathBuf::from("test-resources")
+    .join(SWIFT)
+    .join("remove_pbxproj_references");
+  let temp_dir = super::copy_folder_to_temp_dir(&_path.join("input"));
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .path_to_codebase(temp_dir.path().to_str().unwrap().to_string())
+    .language(PiranhaLanguage::from(SWIFT))
+    .pbxproj_references_to_remove(vec![
+      "Experimentation.framework".to_string(),
+      "experimentation-sdk".to_string(),
+    ])
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+  assert_eq!(output_summaries.len(), 1);
+
+  // The untouched content should be preserved byte for byte
+  let file_name = "project.pbxproj";
+  assert_eq!(
+    read_file(&temp_dir.path().join(file_name)).unwrap(),
+    read_file(&_path.join("expected").join(file_name)).unwrap()
+  );
+  temp_dir.close().unwrap();
+}
@@ -13,9 +13,14 @@ Copyright (c) 2023 Uber Technologies, Inc.
 
 use crate::models::default_configs::THRIFT;
 
-use super::create_rewrite_tests;
+use super::{create_rewrite_tests, substitutions};
 
 create_rewrite_tests! {
     THRIFT,
     test_feature_flag_system_1_treated: "match_exception_type", 1;
+    test_delete_deprecated_field: "delete_deprecated_field", 1,
+      substitutions = substitutions! {
+        "deprecated_field_name" => "legacyName",
+        "deprecated_field_name" => "nickName"
+      }, cleanup_comments = true, cleanup_only = true;
 }
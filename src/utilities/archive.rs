@@ -0,0 +1,294 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+//! Reads the code base from an archive (`.zip`, `.tar.gz` or `.tgz`) without extracting it to the file system,
+//! and writes the updated files to a new archive of the same format (see `output_archive`).
+//! The path of a file of the archive is the path of the archive joined with the name of its entry
+//! (e.g. `codebase.zip/src/Main.java`), hence the `include` and `exclude` patterns apply as for a directory.
+//! The archive is decompressed once per run: the entries of a `.zip` are decompressed on demand (and the untouched
+//! ones copied as is when writing), while a `.tar.gz` (that can only be read sequentially) is decompressed in memory.
+
+use std::{
+  collections::HashMap,
+  fmt,
+  fs::File,
+  io::{Read, Write},
+  path::{Path, PathBuf},
+  sync::{Mutex, OnceLock},
+};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use tar::EntryType;
+use zip::{write::FileOptions, ZipArchive, ZipWriter};
+
+/// The formats of the archives Piranha can read the code base from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ArchiveFormat {
+  Zip,
+  TarGz,
+}
+
+impl ArchiveFormat {
+  /// The format of the archive at `path` (by its extension), or `None` if it is not an archive.
+  pub(crate) fn of(path: &Path) -> Option<ArchiveFormat> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    if name.ends_with(".zip") {
+      Some(ArchiveFormat::Zip)
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+      Some(ArchiveFormat::TarGz)
+    } else {
+      None
+    }
+  }
+}
+
+/// An archive the code base is read from (see `Codebase`)
+pub(crate) struct Archive {
+  path: PathBuf,
+  format: ArchiveFormat,
+  // The decompressed `.tar` of a `.tar.gz`, on the first read
+  tar: OnceLock<Result<Vec<u8>, String>>,
+  // The content of the entries read so far by path (`None` if it is not valid UTF-8)
+  contents: Mutex<HashMap<PathBuf, Option<String>>>,
+}
+
+impl fmt::Debug for Archive {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Archive")
+      .field("path", &self.path)
+      .field("format", &self.format)
+      .finish()
+  }
+}
+
+impl Archive {
+  /// The archive at `path`, or `None` if it is not an archive (rather than a directory or a source file).
+  pub(crate) fn open(path: &Path) -> Option<Archive> {
+    let format = ArchiveFormat::of(path).filter(|_| path.is_file())?;
+    Some(Archive {
+      path: path.to_path_buf(),
+      format,
+      tar: OnceLock::new(),
+      contents: Mutex::new(HashMap::new()),
+    })
+  }
+
+  /// Reads the (regular) files of the archive whose path (see above) satisfies `keep`, in the order of the entries.
+  /// The other entries are not decompressed (for a `.zip`), and the files that are not valid UTF-8 are skipped
+  /// (they are copied as is to the output archive).
+  pub(crate) fn read_files(
+    &self, keep: impl Fn(&Path) -> bool,
+  ) -> Result<Vec<(PathBuf, String)>, String> {
+    let read_err = |e: String| format!("Could not read the archive {:?} - {e}", self.path);
+    let mut files = vec![];
+    let mut contents = self.contents.lock().unwrap();
+    match self.format {
+      ArchiveFormat::Zip => {
+        let file = File::open(&self.path).map_err(|e| read_err(e.to_string()))?;
+        let mut archive = ZipArchive::new(file).map_err(|e| read_err(e.to_string()))?;
+        for i in 0..archive.len() {
+          // The raw entry gives its name without decompressing it
+          let (name, is_dir) = {
+            let entry = archive
+              .by_index_raw(i)
+              .map_err(|e| read_err(e.to_string()))?;
+            (entry.name().to_string(), entry.is_dir())
+          };
+          let path = self.path.join(&name);
+          if is_dir || !keep(&path) {
+            continue;
+          }
+          if !contents.contains_key(&path) {
+            let mut entry = archive.by_index(i).map_err(|e| read_err(e.to_string()))?;
+            let mut bytes = vec![];
+            entry
+              .read_to_end(&mut bytes)
+              .map_err(|e| read_err(e.to_string()))?;
+            contents.insert(path.clone(), String::from_utf8(bytes).ok());
+          }
+          if let Some(Some(content)) = contents.get(&path) {
+            files.push((path, content.clone()));
+          }
+        }
+      }
+      ArchiveFormat::TarGz => {
+        let mut archive = tar::Archive::new(self.tar()?.as_slice());
+        for entry in archive.entries().map_err(|e| read_err(e.to_string()))? {
+          let mut entry = entry.map_err(|e| read_err(e.to_string()))?;
+          if !entry.header().entry_type().is_file() {
+            continue;
+          }
+          let name = entry.path().map_err(|e| read_err(e.to_string()))?;
+          let path = self.path.join(name);
+          if !keep(&path) {
+            continue;
+          }
+          if !contents.contains_key(&path) {
+            let mut bytes = vec![];
+            entry
+              .read_to_end(&mut bytes)
+              .map_err(|e| read_err(e.to_string()))?;
+            contents.insert(path.clone(), String::from_utf8(bytes).ok());
+          }
+          if let Some(Some(content)) = contents.get(&path) {
+            files.push((path, content.clone()));
+          }
+        }
+      }
+    }
+    Ok(files)
+  }
+
+  /// Writes a copy of the archive to `output`, where the content of the `updated_files` (by path, see above)
+  /// is replaced (or the entry is removed, for `None`). The other entries are copied as is, along with their metadata
+  /// (e.g. the modification time, the permissions and the PAX extended headers), which the updated entries also keep.
+  pub(crate) fn write(
+    &self, output: &Path, updated_files: &HashMap<PathBuf, Option<String>>,
+  ) -> Result<(), String> {
+    let write_err = |e: String| format!("Could not write the archive {output:?} - {e}");
+    let output_file = File::create(output).map_err(|e| write_err(e.to_string()))?;
+    match self.format {
+      ArchiveFormat::Zip => {
+        let input = File::open(&self.path).map_err(|e| write_err(e.to_string()))?;
+        let mut archive = ZipArchive::new(input).map_err(|e| write_err(e.to_string()))?;
+        let mut writer = ZipWriter::new(output_file);
+        for i in 0..archive.len() {
+          let entry = archive
+            .by_index_raw(i)
+            .map_err(|e| write_err(e.to_string()))?;
+          match updated_files.get(&self.path.join(entry.name())) {
+            None => writer
+              .raw_copy_file(entry)
+              .map_err(|e| write_err(e.to_string()))?,
+            Some(None) => {}
+            Some(Some(content)) => {
+              let mut options = FileOptions::default()
+                .compression_method(entry.compression())
+                .last_modified_time(entry.last_modified());
+              if let Some(mode) = entry.unix_mode() {
+                options = options.unix_permissions(mode);
+              }
+              writer
+                .start_file(entry.name(), options)
+                .map_err(|e| write_err(e.to_string()))?;
+              writer
+                .write_all(content.as_bytes())
+                .map_err(|e| write_err(e.to_string()))?;
+            }
+          }
+        }
+        writer.finish().map_err(|e| write_err(e.to_string()))?;
+      }
+      ArchiveFormat::TarGz => {
+        let mut archive = tar::Archive::new(self.tar()?.as_slice());
+        let mut builder = tar::Builder::new(GzEncoder::new(output_file, Compression::default()));
+        // The raw entries include the ones holding the metadata of the next entry (i.e. a PAX extended header or
+        // a GNU long name), which are copied (or removed) along with it
+        let mut metadata_entries = vec![];
+        let mut long_name = None;
+        let entries = archive
+          .entries()
+          .map_err(|e| write_err(e.to_string()))?
+          .raw(true);
+        for entry in entries {
+          let mut entry = entry.map_err(|e| write_err(e.to_string()))?;
+          let mut header = entry.header().clone();
+          let mut bytes = vec![];
+          entry
+            .read_to_end(&mut bytes)
+            .map_err(|e| write_err(e.to_string()))?;
+          match header.entry_type() {
+            EntryType::XHeader => {
+              long_name = get_pax_path(&bytes).or(long_name);
+              metadata_entries.push((header, bytes));
+              continue;
+            }
+            EntryType::GNULongName => {
+              long_name = Some(
+                String::from_utf8_lossy(&bytes)
+                  .trim_end_matches('\0')
+                  .to_string(),
+              );
+              metadata_entries.push((header, bytes));
+              continue;
+            }
+            EntryType::GNULongLink => {
+              metadata_entries.push((header, bytes));
+              continue;
+            }
+            _ => {}
+          }
+          let name = match long_name.take() {
+            Some(name) => PathBuf::from(name),
+            None => header
+              .path()
+              .map_err(|e| write_err(e.to_string()))?
+              .to_path_buf(),
+          };
+          let metadata_entries = std::mem::take(&mut metadata_entries);
+          let bytes = match updated_files.get(&self.path.join(&name)) {
+            None => bytes,
+            Some(None) => continue,
+            Some(Some(content)) => {
+              header.set_size(content.len() as u64);
+              header.set_cksum();
+              content.as_bytes().to_vec()
+            }
+          };
+          for (metadata_header, metadata) in &metadata_entries {
+            builder
+              .append(metadata_header, metadata.as_slice())
+              .map_err(|e| write_err(e.to_string()))?;
+          }
+          builder
+            .append(&header, bytes.as_slice())
+            .map_err(|e| write_err(e.to_string()))?;
+        }
+        builder
+          .into_inner()
+          .and_then(|encoder| encoder.finish())
+          .map_err(|e| write_err(e.to_string()))?;
+      }
+    }
+    Ok(())
+  }
+
+  /// The decompressed `.tar` of a `.tar.gz` archive (decompressed on the first call)
+  fn tar(&self) -> Result<&Vec<u8>, String> {
+    self
+      .tar
+      .get_or_init(|| {
+        let mut bytes = vec![];
+        File::open(&self.path)
+          .and_then(|file| GzDecoder::new(file).read_to_end(&mut bytes))
+          .map(|_| bytes)
+          .map_err(|e| format!("Could not read the archive {:?} - {e}", self.path))
+      })
+      .as_ref()
+      .map_err(|e| e.to_string())
+  }
+}
+
+/// The `path` record of a PAX extended header (i.e. records of the form `<length> <key>=<value>\n`), if any
+fn get_pax_path(bytes: &[u8]) -> Option<String> {
+  String::from_utf8_lossy(bytes)
+    .lines()
+    .filter_map(|record| record.split_once(' ')?.1.split_once('='))
+    .filter(|(key, _)| *key == "path")
+    .map(|(_, value)| value.to_string())
+    .last()
+}
+
+#[cfg(test)]
+#[path = "unit_tests/archive_test.rs"]
+mod archive_test;
@@ -0,0 +1,114 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+//! The code base Piranha reads the files from, i.e. a directory, a single source file or an archive (see `archive`).
+//! The files are selected by their path (`include`, `exclude` and the predicate of the caller) before being read.
+
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+};
+
+use glob::Pattern;
+use jwalk::WalkDir;
+
+use super::{archive::Archive, read_file};
+
+#[derive(Debug, Default)]
+enum Source {
+  #[default]
+  Directory,
+  File,
+  Archive(Archive),
+}
+
+/// The code base at `path_to_codebase`, along with the `include` and `exclude` patterns of the run
+#[derive(Debug, Default)]
+pub(crate) struct Codebase {
+  path: PathBuf,
+  source: Source,
+  include: Vec<Pattern>,
+  exclude: Vec<Pattern>,
+}
+
+impl Codebase {
+  pub(crate) fn new(path_to_codebase: &Path, include: &[Pattern], exclude: &[Pattern]) -> Self {
+    let source = match Archive::open(path_to_codebase) {
+      Some(archive) => Source::Archive(archive),
+      None if path_to_codebase.is_file() => Source::File,
+      None => Source::Directory,
+    };
+    Codebase {
+      path: path_to_codebase.to_path_buf(),
+      source,
+      include: include.to_vec(),
+      exclude: exclude.to_vec(),
+    }
+  }
+
+  pub(crate) fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Checks if the code base is an archive (rather than a directory or a source file)
+  pub(crate) fn is_archive(&self) -> bool {
+    matches!(self.source, Source::Archive(_))
+  }
+
+  /// Checks if the code base is a single source file
+  pub(crate) fn is_file(&self) -> bool {
+    matches!(self.source, Source::File)
+  }
+
+  /// Reads the files of the code base whose path satisfies `keep` (and the `include` and `exclude` patterns,
+  /// unless the code base is a single file), along with their content. The other files are not read.
+  pub(crate) fn read_files(
+    &self, keep: impl Fn(&Path) -> bool,
+  ) -> Result<Vec<(PathBuf, String)>, String> {
+    let is_included = |path: &Path| {
+      (self.include.is_empty() || self.include.iter().any(|p| p.matches_path(path)))
+        && self.exclude.iter().all(|p| !p.matches_path(path))
+    };
+    match &self.source {
+      Source::Archive(archive) => archive.read_files(|path| is_included(path) && keep(path)),
+      Source::File if keep(&self.path) => Ok(vec![(self.path.clone(), read_file(&self.path)?)]),
+      Source::File => Ok(vec![]),
+      Source::Directory => WalkDir::new(&self.path)
+        // walk over the entire code base
+        .into_iter()
+        // ignore errors
+        .filter_map(|e| e.ok())
+        .filter(|f| !f.file_type().is_dir())
+        // only retain the included (and not excluded) paths, satisfying `keep`
+        .filter(|f| is_included(&f.path()) && keep(&f.path()))
+        // read the file
+        .map(|f| {
+          let path = f.path();
+          read_file(&path)
+            .map(|content| (path.clone(), content))
+            .map_err(|e| format!("Could not read {path:?} - {e}"))
+        })
+        .collect(),
+    }
+  }
+
+  /// Writes a copy of the archive of the code base to `output`, with the `updated_files` (see `Archive::write`).
+  pub(crate) fn write_archive(
+    &self, output: &Path, updated_files: &HashMap<PathBuf, Option<String>>,
+  ) -> Result<(), String> {
+    match &self.source {
+      Source::Archive(archive) => archive.write(output, updated_files),
+      _ => Err(format!("The code base {:?} is not an archive", self.path)),
+    }
+  }
+}
@@ -11,15 +11,27 @@ Copyright (c) 2023 Uber Technologies, Inc.
  limitations under the License.
 */
 
+pub(crate) mod archive;
+pub(crate) mod codebase;
+pub(crate) mod patch;
+pub(crate) mod pbxproj;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 pub(crate) mod tree_sitter_utilities;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs::File;
 #[cfg(test)]
 use std::fs::{self, DirEntry};
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
 use std::io::{BufReader, Read};
 use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use itertools::Itertools;
+use regex::Regex;
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
 
 // Reads a file.
 pub(crate) fn read_file(file_path: &PathBuf) -> Result<String, String> {
@@ -32,24 +44,28 @@ pub(crate) fn read_file(file_path: &PathBuf) -> Result<String, String> {
     .map_err(|error| error.to_string())
 }
 
-// Reads a toml file. In case of error, it returns a default value (if return_default is true) else panics.
+// Reads a toml file. If the file does not exist, it returns a default value (if return_default is true) else panics.
+// Panics if the file is invalid.
 pub(crate) fn read_toml<T>(file_path: &PathBuf, return_default: bool) -> T
 where
   T: serde::de::DeserializeOwned + Default,
 {
-  match read_file(file_path)
-    .and_then(|content| toml::from_str::<T>(content.as_str()).map_err(|e| e.to_string()))
-  {
-    Ok(obj) => obj,
-    Err(err) => {
-      if return_default {
-        T::default()
-      } else {
-        #[rustfmt::skip]
-      panic!("Could not read file: {file_path:?} \n Error : \n {err:?}");
-      }
-    }
+  try_read_toml(file_path, return_default).unwrap_or_else(|err| panic!("{err}"))
+}
+
+// Reads a toml file. If the file does not exist, it returns a default value (if return_default is true) else an error.
+// Returns an error if the file is invalid.
+pub(crate) fn try_read_toml<T>(file_path: &PathBuf, return_default: bool) -> Result<T, String>
+where
+  T: serde::de::DeserializeOwned + Default,
+{
+  // A missing file is not an error (if return_default is true), whereas an invalid one always is
+  if return_default && !file_path.exists() {
+    return Ok(T::default());
   }
+  read_file(file_path)
+    .and_then(|content| toml::from_str::<T>(content.as_str()).map_err(|e| e.to_string()))
+    .map_err(|err| format!("Could not read file: {file_path:?} \n Error : \n {err:?}"))
 }
 
 pub(crate) fn parse_toml<T>(content: &str) -> T
@@ -79,6 +95,80 @@ pub(crate) fn eq_without_whitespace(s1: &str, s2: &str) -> bool {
     .eq(&s2.split_whitespace().collect::<String>())
 }
 
+/// Checks if `content` has a line longer than `max_line_length` bytes (like minified files)
+pub(crate) fn has_long_lines(content: &str, max_line_length: usize) -> bool {
+  content.split('\n').any(|line| line.len() > max_line_length)
+}
+
+/// Checks if the `line` (at the 0-based `row` of a file) is a shebang (`#!` on the first line) or an encoding
+/// declaration (PEP 263, on one of the first two lines), which are never deleted along with the code below them.
+pub(crate) fn is_protected_header_line(line: &str, row: usize) -> bool {
+  static ENCODING_DECLARATION_REGEX: OnceLock<Regex> = OnceLock::new();
+  (row == 0 && line.starts_with("#!"))
+    || (row <= 1
+      && ENCODING_DECLARATION_REGEX
+        .get_or_init(|| Regex::new(r"^[ \t\f]*#.*?coding[:=][ \t]*[-\w.]+").unwrap())
+        .is_match(line))
+}
+
+/// Replaces three (or more) consecutive new lines (possibly separated by whitespace) with two new lines.
+/// It is equivalent to replacing `\n(\s*\n)+(\s*\n)` with `\n${2}`, but scans `code` only once
+/// (the regex is re-scanned from each new line, which is quadratic for long runs of whitespace).
+/// The line endings are kept, i.e. `\r\n\r\n\r\n` is replaced with `\r\n\r\n`.
+pub(crate) fn delete_consecutive_new_lines(code: &str) -> String {
+  let mut output = String::with_capacity(code.len());
+  let mut rest = code;
+  while let Some(start) = rest.find(char::is_whitespace) {
+    output.push_str(&rest[..start]);
+    let whitespace_len = rest[start..]
+      .find(|c: char| !c.is_whitespace())
+      .unwrap_or(rest.len() - start);
+    let whitespace = &rest[start..start + whitespace_len];
+    let new_lines: Vec<usize> = whitespace.match_indices('\n').map(|(i, _)| i).collect();
+    if new_lines.len() < 3 {
+      output.push_str(whitespace);
+    } else {
+      // Keep the whitespace before the first and after the second last new line
+      output.push_str(&whitespace[..new_lines[0]]);
+      output.push('\n');
+      output.push_str(&whitespace[new_lines[new_lines.len() - 2] + 1..]);
+    }
+    rest = &rest[start + whitespace_len..];
+  }
+  output.push_str(rest);
+  output
+}
+
+/// The `end_byte` of a range of the `code`, moved before the `\r` of the `\r\n` line ending it would split otherwise
+/// (e.g. the line comments of most grammars include it), so that deleting the range does not leave a lone `\n`.
+pub(crate) fn exclude_split_line_ending(code: &str, end_byte: usize) -> usize {
+  if code[..end_byte].ends_with('\r') && code[end_byte..].starts_with('\n') {
+    end_byte - 1
+  } else {
+    end_byte
+  }
+}
+
+/// The maximum number of characters of a code snippet (or of a line) displayed, e.g. in the logs or the highlighted
+/// matches (minified files have huge snippets)
+pub(crate) const MAX_DISPLAYED_SNIPPET_LENGTH: usize = 1000;
+
+/// Truncates the `snippet` to (at most) `max_length` characters, for displaying it
+pub(crate) fn truncate_snippet(snippet: &str, max_length: usize) -> String {
+  match snippet.char_indices().nth(max_length) {
+    Some((end, _)) => format!("{}...", &snippet[..end]),
+    None => snippet.to_string(),
+  }
+}
+
+/// Truncates each line of the `text` to (at most) `max_length` characters, for displaying it
+pub(crate) fn truncate_lines(text: &str, max_length: usize) -> String {
+  text
+    .split('\n')
+    .map(|line| truncate_snippet(line, max_length))
+    .join("\n")
+}
+
 /// Checks if the given `dir_entry` is a file named `file_name`
 #[cfg(test)] // Rust analyzer FP
 pub(crate) fn has_name(dir_entry: &DirEntry, file_name: &str) -> bool {
@@ -159,17 +249,390 @@ pub(crate) trait Instantiate {
 }
 
 impl Instantiate for String {
+  /// The string is substituted in a single pass, so that the substituted values are never substituted again
+  /// (e.g. a value containing `@name` is kept as is, whatever the order of the `substitutions`).
+  /// When several tags match at the same position (e.g. `@a` and `@a.b`), the longest one is substituted.
   fn instantiate(&self, substitutions: &HashMap<String, String>) -> Self {
-    let mut output = self.to_string();
-    for (tag, substitute) in substitutions {
-      // Before replacing the key, it is transformed to a tree-sitter tag by adding `@` as prefix
-      let key = format!("@{tag}");
-      output = output.replace(&key, substitute);
+    if substitutions.is_empty() || !self.contains('@') {
+      return self.to_string();
     }
+    let mut output = String::with_capacity(self.len());
+    let mut rest = self.as_str();
+    while let Some(start) = rest.find('@') {
+      output.push_str(&rest[..start]);
+      let reference = &rest[start + 1..];
+      match substitutions
+        .iter()
+        .filter(|(tag, _)| !tag.is_empty() && reference.starts_with(tag.as_str()))
+        .max_by_key(|(tag, _)| tag.len())
+      {
+        Some((tag, substitute)) => {
+          output.push_str(substitute);
+          rest = &reference[tag.len()..];
+        }
+        None => {
+          output.push('@');
+          rest = reference;
+        }
+      }
+    }
+    output.push_str(rest);
     output
   }
 }
 
+/// Replaces the tags of the tree-sitter `query` with the corresponding `substitutions` (see `Instantiate`).
+/// The values spliced into a string literal of the query (e.g. `(#eq? @name "@stale_flag_name")`) are escaped,
+/// so that their quotes and backslashes are matched literally instead of ending the string.
+/// The new lines of the values are escaped everywhere.
+pub(crate) fn instantiate_query(query: &str, substitutions: &HashMap<String, String>) -> String {
+  let mut output = query.to_string();
+  for (tag, substitute) in substitutions {
+    let key = format!("@{tag}");
+    if !output.contains(&key) {
+      continue;
+    }
+    let escaped_substitute = substitute.replace('\\', "\\\\").replace('"', "\\\"");
+    let string_literal_ranges = get_string_literal_ranges(&output);
+    let mut instantiated = String::new();
+    let mut last_end = 0;
+    for (start, _) in output.match_indices(&key) {
+      instantiated.push_str(&output[last_end..start]);
+      if string_literal_ranges.iter().any(|r| r.contains(&start)) {
+        instantiated.push_str(&escaped_substitute.replace('\n', "\\n"));
+      } else {
+        instantiated.push_str(&substitute.replace('\n', "\\n"));
+      }
+      last_end = start + key.len();
+    }
+    instantiated.push_str(&output[last_end..]);
+    output = instantiated;
+  }
+  output
+}
+
+/// The byte ranges of the (double-quoted) string literals of the tree-sitter `query`, ignoring its `;` comments.
+fn get_string_literal_ranges(query: &str) -> Vec<std::ops::Range<usize>> {
+  let mut ranges = Vec::new();
+  let mut chars = query.char_indices();
+  while let Some((i, c)) = chars.next() {
+    match c {
+      ';' => {
+        chars.find(|(_, c)| *c == '\n');
+      }
+      '"' => {
+        let mut end = query.len();
+        while let Some((j, c)) = chars.next() {
+          match c {
+            '\\' => _ = chars.next(),
+            '"' => {
+              end = j;
+              break;
+            }
+            _ => {}
+          }
+        }
+        ranges.push(i..end);
+      }
+      _ => {}
+    }
+  }
+  ranges
+}
+
+/// The prefix of the tags generated by Piranha (e.g. by `capture_all_nodes`), which the user defined tags cannot use.
+pub(crate) const RESERVED_TAG_PREFIX: &str = "__";
+
+/// Captures each named node of the tree-sitter `query` that is not captured yet with its kind, prefixed with
+/// `RESERVED_TAG_PREFIX` (see `Rule::capture_all_groups`), e.g. `(return_statement (identifier) @id)` becomes
+/// `(return_statement (identifier) @id) @__return_statement`. The captures of the query are left as they are.
+/// When a kind appears more than once, the captures of the next nodes are suffixed with a number (e.g. `@__identifier`,
+/// `@__identifier_2`).
+pub(crate) fn capture_all_nodes(query: &str) -> String {
+  // The byte offsets of the matching parentheses, outside the string literals and the `;` comments
+  let mut parentheses = vec![];
+  let mut open_parentheses = vec![];
+  let mut chars = query.char_indices();
+  while let Some((i, c)) = chars.next() {
+    match c {
+      ';' => _ = chars.find(|(_, c)| *c == '\n'),
+      '"' => loop {
+        match chars.next() {
+          Some((_, '\\')) => _ = chars.next(),
+          Some((_, '"')) | None => break,
+          _ => {}
+        }
+      },
+      '(' => open_parentheses.push(i),
+      ')' => {
+        if let Some(open) = open_parentheses.pop() {
+          parentheses.push((open, i));
+        }
+      }
+      _ => {}
+    }
+  }
+  let mut occurrences: HashMap<String, usize> = HashMap::new();
+  let mut captures = vec![];
+  for (open, close) in parentheses.into_iter().sorted() {
+    // The groupings, predicates and wildcards have no kind
+    let kind: String = query[open + 1..]
+      .trim_start()
+      .chars()
+      .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+      .collect();
+    if kind.is_empty() || kind == "_" || kind.starts_with(|c: char| c.is_ascii_digit()) {
+      continue;
+    }
+    let mut end = close + 1;
+    if query[end..].starts_with(['*', '+', '?']) {
+      end += 1;
+    }
+    if query[end..].trim_start().starts_with('@') {
+      continue;
+    }
+    let occurrence = occurrences.entry(kind.clone()).or_insert(0);
+    *occurrence += 1;
+    let name = if *occurrence == 1 {
+      format!("{RESERVED_TAG_PREFIX}{kind}")
+    } else {
+      format!("{RESERVED_TAG_PREFIX}{kind}_{occurrence}")
+    };
+    captures.push((end, name));
+  }
+  let mut output = query.to_string();
+  for (end, name) in captures.into_iter().sorted().rev() {
+    output.insert_str(end, &format!(" @{name}"));
+  }
+  output
+}
+
+/// The characters of the substitution `value` significant to the tree-sitter query syntax, which may change what
+/// the rules match when it is spliced into their queries (e.g. `ENABLE_X") (other_thing`): the captures (`@`),
+/// the predicates (`#`), and the unbalanced quotes and parentheses.
+pub(crate) fn get_query_significant_characters(value: &str) -> Vec<char> {
+  let mut characters = value
+    .chars()
+    .filter(|c| ['@', '#'].contains(c))
+    .collect_vec();
+  if value.matches('"').count() % 2 == 1 {
+    characters.push('"');
+  }
+  let mut depth = 0;
+  for c in value.chars() {
+    match c {
+      '(' => depth += 1,
+      ')' if depth == 0 => characters.push(')'),
+      ')' => depth -= 1,
+      _ => {}
+    }
+  }
+  if depth > 0 {
+    characters.push('(');
+  }
+  characters.into_iter().unique().collect()
+}
+
+/// Matches the beginning of a `%{tag}` or `%{tag:regex}` placeholder (see `substitute_tags_regex`).
+fn placeholder_regex() -> &'static Regex {
+  static PLACEHOLDER_REGEX: OnceLock<Regex> = OnceLock::new();
+  PLACEHOLDER_REGEX.get_or_init(|| Regex::new(r"^%\{([A-Za-z_][\w.]*)(:|\})").unwrap())
+}
+
+/// Compiles the `pattern`, or returns the regex previously compiled for it.
+/// The regexes of the placeholders (or of the package filters) are the same for every match of a rule (or every
+/// file), so they are only compiled once.
+pub(crate) fn cached_regex(pattern: &str) -> Result<Regex, regex::Error> {
+  static REGEX_CACHE: OnceLock<Mutex<HashMap<String, Regex>>> = OnceLock::new();
+  let mut cache = REGEX_CACHE
+    .get_or_init(Default::default)
+    .lock()
+    .unwrap_or_else(|e| e.into_inner());
+  if let Some(regex) = cache.get(pattern) {
+    return Ok(regex.clone());
+  }
+  let regex = Regex::new(pattern)?;
+  cache.insert(pattern.to_string(), regex.clone());
+  Ok(regex)
+}
+
+/// A `Hasher` computing the sha256 of the hashed values. Unlike `DefaultHasher`, its output is stable across the
+/// versions of Rust, and wide enough for collisions to be ruled out (e.g. for the keys persisted on the disk).
+#[derive(Default)]
+pub(crate) struct Sha256Hasher(Sha256);
+
+impl Sha256Hasher {
+  /// The hex-encoded sha256 of the values hashed so far
+  pub(crate) fn digest(&self) -> String {
+    format!("{:x}", self.0.clone().finalize())
+  }
+}
+
+impl Hasher for Sha256Hasher {
+  fn write(&mut self, bytes: &[u8]) {
+    self.0.update(bytes);
+  }
+
+  fn finish(&self) -> u64 {
+    let digest = self.0.clone().finalize();
+    u64::from_be_bytes(digest[..8].try_into().unwrap())
+  }
+}
+
+/// Returns the tags referenced by the `%{tag}` and `%{tag:regex}` placeholders of the `template`.
+pub(crate) fn get_placeholder_tags(template: &str) -> Vec<String> {
+  template
+    .match_indices("%{")
+    .filter_map(|(i, _)| placeholder_regex().captures(&template[i..]))
+    .map(|c| c[1].to_string())
+    .collect()
+}
+
+/// Replaces the `%{tag:regex}` placeholders of the `template` with the first group of the `regex` matched against the value
+/// of the `tag` in `substitutions` (or with the whole match if the regex has no group), and the `%{tag}` placeholders with
+/// the value of the `tag` as is. Unlike the `@tag` references (see `Instantiate`), this allows transforming the captured
+/// code, e.g. `%{flag:^FLAG_(\w+)$}` is replaced with `STALE` when the `flag` is `FLAG_STALE`.
+///
+/// A placeholder is replaced with an empty string if its regex does not match, and kept as is if the tag has no value.
+/// The braces of the regex should be balanced (or escaped), e.g. `%{x:\d{2}}`.
+pub(crate) fn substitute_tags_regex(
+  template: &str, substitutions: &HashMap<String, String>,
+) -> Result<String, regex::Error> {
+  substitute_placeholders(template, substitutions, str::to_string)
+}
+
+/// Instantiates the replacement `template` of a rule with the `substitutions`, i.e. substitutes both its `%{tag}`
+/// placeholders (see `substitute_tags_regex`) and its `@tag` references (see `Instantiate`).
+/// The template is substituted in a single pass, so that the substituted code is never substituted again
+/// (e.g. a captured `%{x}` or `@x` is kept as is).
+pub(crate) fn instantiate_replacement(
+  template: &str, substitutions: &HashMap<String, String>,
+) -> Result<String, regex::Error> {
+  substitute_placeholders(template, substitutions, |text| {
+    text.to_string().instantiate(substitutions)
+  })
+}
+
+/// Substitutes the placeholders of the `template` (see `substitute_tags_regex`), while the text around them is
+/// transformed with `transform_text`.
+fn substitute_placeholders(
+  template: &str, substitutions: &HashMap<String, String>, transform_text: impl Fn(&str) -> String,
+) -> Result<String, regex::Error> {
+  let mut output = String::with_capacity(template.len());
+  let mut text = String::new();
+  let mut rest = template;
+  while let Some(start) = rest.find("%{") {
+    text.push_str(&rest[..start]);
+    rest = &rest[start..];
+    let Some(captures) = placeholder_regex().captures(rest) else {
+      text.push_str("%{");
+      rest = &rest[2..];
+      continue;
+    };
+    let placeholder_start = captures[0].len();
+    let placeholder_end = if &captures[2] == "}" {
+      Some(placeholder_start)
+    } else {
+      // Find the closing brace of the placeholder (i.e. not closing a brace of the regex, nor escaped)
+      let (mut depth, mut escaped, mut end) = (0, false, None);
+      for (i, c) in rest[placeholder_start..].char_indices() {
+        match c {
+          _ if escaped => escaped = false,
+          '\\' => escaped = true,
+          '{' => depth += 1,
+          '}' if depth == 0 => {
+            end = Some(placeholder_start + i);
+            break;
+          }
+          '}' => depth -= 1,
+          _ => {}
+        }
+      }
+      end
+    };
+    let Some(end) = placeholder_end else {
+      // Not a placeholder, since it is not closed
+      break;
+    };
+    let (regex, consumed) = if &captures[2] == "}" {
+      (None, end)
+    } else {
+      (Some(cached_regex(&rest[placeholder_start..end])?), end + 1)
+    };
+    let Some(value) = substitutions.get(&captures[1]) else {
+      // The placeholders of unbound tags are kept as is, rather than dropped
+      text.push_str(&rest[..consumed]);
+      rest = &rest[consumed..];
+      continue;
+    };
+    output.push_str(&transform_text(&text));
+    text.clear();
+    match regex {
+      None => output.push_str(value),
+      Some(regex) => {
+        if let Some(m) = regex.captures(value) {
+          output.push_str(m.get(1).or_else(|| m.get(0)).map_or("", |g| g.as_str()));
+        }
+      }
+    }
+    rest = &rest[consumed..];
+  }
+  text.push_str(rest);
+  output.push_str(&transform_text(&text));
+  Ok(output)
+}
+
+/// Serializes the `set` in sorted order, so that the output is deterministic.
+pub(crate) fn serialize_sorted<S: Serializer, T: Serialize + Ord>(
+  set: &HashSet<T>, serializer: S,
+) -> Result<S::Ok, S::Error> {
+  serializer.collect_seq(set.iter().sorted())
+}
+
+/// Returns the tags referenced (i.e. prefixed with `@`) in `input_string`, e.g. `x` and `y.z` for `foo(@x, @y.z)`.
+pub(crate) fn get_tag_references(input_string: &str) -> Vec<String> {
+  static TAG_REFERENCE_REGEX: OnceLock<Regex> = OnceLock::new();
+  TAG_REFERENCE_REGEX
+    .get_or_init(|| Regex::new(r"@([A-Za-z_][\w.]*)").unwrap())
+    .captures_iter(input_string)
+    .map(|c| c[1].trim_end_matches('.').to_string())
+    .collect()
+}
+
+/// Checks if the tag `reference` is likely a misspelling of the `tag`, i.e. they differ by at most one character
+/// (two for the longer tags), with the same case of the first character (e.g. `@Override` is an annotation rather
+/// than a misspelling of `override`). The short tags are not compared, since they are too similar to each other.
+pub(crate) fn is_likely_misspelling(reference: &str, tag: &str) -> bool {
+  let (reference, tag) = (reference.chars().collect_vec(), tag.chars().collect_vec());
+  if reference == tag
+    || tag.len() < 3
+    || reference.first().map(|c| c.is_uppercase()) != tag.first().map(|c| c.is_uppercase())
+  {
+    return false;
+  }
+  // The Levenshtein distance, computed row by row
+  let mut previous_row = (0..=tag.len()).collect_vec();
+  for (i, r) in reference.iter().enumerate() {
+    let mut row = vec![i + 1];
+    for (j, t) in tag.iter().enumerate() {
+      let substitution = previous_row[j] + usize::from(r != t);
+      row.push(substitution.min(previous_row[j + 1] + 1).min(row[j] + 1));
+    }
+    previous_row = row;
+  }
+  previous_row[tag.len()] <= if tag.len() > 5 { 2 } else { 1 }
+}
+
+/// Checks if the tag `reference` is bound by a tag satisfying `is_tag`.
+/// Since tags can contain dots, `@x.y` is bound by the tag `x.y`, but also by the tag `x` (followed by `.y`).
+pub(crate) fn is_bound_by(reference: &str, is_tag: impl Fn(&str) -> bool) -> bool {
+  reference
+    .match_indices('.')
+    .map(|(i, _)| &reference[..i])
+    .chain([reference])
+    .any(is_tag)
+}
+
 #[cfg(test)]
 #[path = "unit_tests/utilities_test.rs"]
 mod utilities_test;
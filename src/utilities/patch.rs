@@ -0,0 +1,329 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+//! Accumulates the changes to the files of the code base as a patch (see `output_patch`), i.e. a unified diff
+//! in the git format (with the `a/` and `b/` prefixes) that can be applied later with `git apply`.
+
+use std::{
+  collections::BTreeMap,
+  ops::Range,
+  path::{Component, Path, PathBuf},
+};
+
+use itertools::Itertools;
+
+use super::codebase::Codebase;
+
+// The number of unchanged lines around each change
+const CONTEXT_LINES: usize = 3;
+// The git modes of the regular and executable files
+const REGULAR_FILE_MODE: &str = "100644";
+const EXECUTABLE_FILE_MODE: &str = "100755";
+
+pub(crate) struct Patch {
+  // The root of the code base, the paths in the patch are relative to it
+  path_to_codebase: PathBuf,
+  // Whether the code base is a single source file (rather than a directory or an archive)
+  is_single_file: bool,
+  // The original and updated (`None` when deleted) content of each file, along with its git mode, by its (relative) path
+  file_contents: BTreeMap<String, (String, Option<String>, &'static str)>,
+}
+
+impl Patch {
+  pub(crate) fn new(codebase: &Codebase) -> Self {
+    Patch {
+      path_to_codebase: codebase.path().to_path_buf(),
+      is_single_file: codebase.is_file(),
+      file_contents: BTreeMap::new(),
+    }
+  }
+
+  /// Adds the changes from the `original` content of the file at `path` to the `updated` one (`None` when
+  /// the file is deleted). When the file was already added (e.g. by a previous stage), the changes are composed,
+  /// i.e. the patch goes from its first original content to its last updated one.
+  /// The file is expected to be on the disk (unless it is an entry of an archive), to read its mode.
+  pub(crate) fn add(&mut self, path: &Path, original: &str, updated: Option<&str>) {
+    let relative_path = self.relative_path(path);
+    let updated = updated.map(str::to_string);
+    self
+      .file_contents
+      .entry(relative_path)
+      .and_modify(|(_, u, _)| u.clone_from(&updated))
+      .or_insert_with(|| (original.to_string(), updated, file_mode(path)));
+  }
+
+  /// The path of the file relative to the root of the code base, with `/` separators.
+  /// If the code base is a single file, it is its name (or the name of its entry, if it is an archive).
+  fn relative_path(&self, path: &Path) -> String {
+    let relative_path = if self.is_single_file {
+      path.file_name().map(Path::new).unwrap_or(path)
+    } else {
+      path.strip_prefix(&self.path_to_codebase).unwrap_or(path)
+    };
+    relative_path
+      .components()
+      .filter_map(|c| match c {
+        Component::Normal(name) => Some(name.to_string_lossy()),
+        _ => None,
+      })
+      .join("/")
+  }
+}
+
+impl std::fmt::Display for Patch {
+  /// The diffs of the changed files, sorted by path. The files without any change are omitted.
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let diffs = self
+      .file_contents
+      .iter()
+      .filter_map(|(path, (original, updated, mode))| {
+        file_diff(path, original, updated.as_deref(), mode)
+      });
+    write!(f, "{}", diffs.format(""))
+  }
+}
+
+/// The git mode of the file at `path`, i.e. whether it is executable. A file that cannot be read from the disk
+/// (e.g. an entry of an archive) is a regular file.
+fn file_mode(path: &Path) -> &'static str {
+  if is_executable(path) {
+    EXECUTABLE_FILE_MODE
+  } else {
+    REGULAR_FILE_MODE
+  }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+  use std::os::unix::fs::PermissionsExt;
+  std::fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+  false
+}
+
+/// Returns the unified diff (in the git format) from the `original` content of the (regular) file at `path` to
+/// the `updated` one (`None` when the file is deleted), or `None` if there is no change (see `file_diff`).
+pub(crate) fn unified_diff(path: &str, original: &str, updated: Option<&str>) -> Option<String> {
+  file_diff(path, original, updated, REGULAR_FILE_MODE)
+}
+
+/// Returns the unified diff (in the git format) from the `original` content of the file at `path` (with the git `mode`)
+/// to the `updated` one (`None` when the file is deleted), or `None` if there is no change.
+/// The lines without a line ending (i.e. the last one) are marked with `\ No newline at end of file`.
+fn file_diff(path: &str, original: &str, updated: Option<&str>, mode: &str) -> Option<String> {
+  if updated == Some(original) {
+    return None;
+  }
+  let original_lines = original.split_inclusive('\n').collect_vec();
+  let updated_lines = updated
+    .map(|u| u.split_inclusive('\n').collect_vec())
+    .unwrap_or_default();
+
+  let mut diff = format!("diff --git a/{path} b/{path}\n");
+  if updated.is_none() {
+    diff.push_str(&format!("deleted file mode {mode}\n"));
+  }
+  diff.push_str(&format!("--- a/{path}\n"));
+  match updated {
+    Some(_) => diff.push_str(&format!("+++ b/{path}\n")),
+    None => diff.push_str("+++ /dev/null\n"),
+  }
+
+  for hunk in get_hunks(get_line_runs(&original_lines, &updated_lines)) {
+    let (first, last) = (hunk.first()?, hunk.last()?);
+    diff.push_str(&format!(
+      "@@ -{} +{} @@\n",
+      hunk_range(first.original.start, last.original.end),
+      hunk_range(first.updated.start, last.updated.end)
+    ));
+    for run in &hunk {
+      if run.is_equal {
+        push_lines(&mut diff, ' ', &original_lines[run.original.clone()]);
+        continue;
+      }
+      push_lines(&mut diff, '-', &original_lines[run.original.clone()]);
+      push_lines(&mut diff, '+', &updated_lines[run.updated.clone()]);
+    }
+  }
+  Some(diff)
+}
+
+/// A run of consecutive lines of the original content that are either equal to the lines `updated` of the updated
+/// content, or replaced by them (i.e. deleted, when `updated` is empty, or inserted, when `original` is empty).
+#[derive(Debug, Clone)]
+struct LineRun {
+  is_equal: bool,
+  original: Range<usize>,
+  updated: Range<usize>,
+}
+
+impl LineRun {
+  /// The first `length` lines of the (equal) run
+  fn head(&self, length: usize) -> LineRun {
+    LineRun {
+      is_equal: self.is_equal,
+      original: self.original.start..self.original.start + length,
+      updated: self.updated.start..self.updated.start + length,
+    }
+  }
+
+  /// The last `length` lines of the (equal) run
+  fn tail(&self, length: usize) -> LineRun {
+    LineRun {
+      is_equal: self.is_equal,
+      original: self.original.end - length..self.original.end,
+      updated: self.updated.end - length..self.updated.end,
+    }
+  }
+}
+
+/// Splits the `original` and `updated` lines into the runs of equal and replaced lines, in order.
+fn get_line_runs(original: &[&str], updated: &[&str]) -> Vec<LineRun> {
+  let mut runs: Vec<LineRun> = vec![];
+  let (mut i, mut j) = (0, 0);
+  for (x, y) in get_common_lines(original, updated) {
+    if x > i || y > j {
+      runs.push(LineRun {
+        is_equal: false,
+        original: i..x,
+        updated: j..y,
+      });
+    }
+    match runs.last_mut() {
+      Some(run) if run.is_equal => {
+        run.original.end += 1;
+        run.updated.end += 1;
+      }
+      _ => runs.push(LineRun {
+        is_equal: true,
+        original: x..x + 1,
+        updated: y..y + 1,
+      }),
+    }
+    (i, j) = (x + 1, y + 1);
+  }
+  if original.len() > i || updated.len() > j {
+    runs.push(LineRun {
+      is_equal: false,
+      original: i..original.len(),
+      updated: j..updated.len(),
+    });
+  }
+  runs
+}
+
+/// The indices of the lines of `original` and `updated` that are kept by a shortest edit script from the former to
+/// the latter (Myers' algorithm), in order. Takes O((N + M) * D) time and O(D^2) space, where D is the number of
+/// deleted and inserted lines.
+fn get_common_lines(original: &[&str], updated: &[&str]) -> Vec<(usize, usize)> {
+  let (n, m) = (original.len() as isize, updated.len() as isize);
+  // The furthest line `x` of `original` reached on each diagonal `k = x - y` (shifted by `offset`)
+  let offset = n + m + 1;
+  let mut furthest = vec![0; (2 * offset + 1) as usize];
+  // The diagonals `-d - 1..=d + 1` of `furthest` before each step `d`, to backtrack the edit script
+  let mut trace = vec![];
+  'search: for d in 0..=n + m {
+    trace.push(furthest[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+    for k in (-d..=d).step_by(2) {
+      let at = |k: isize| furthest[(k + offset) as usize];
+      let mut x = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+        at(k + 1)
+      } else {
+        at(k - 1) + 1
+      };
+      let mut y = x - k;
+      while x < n && y < m && original[x as usize] == updated[y as usize] {
+        (x, y) = (x + 1, y + 1);
+      }
+      furthest[(k + offset) as usize] = x;
+      if x >= n && y >= m {
+        break 'search;
+      }
+    }
+  }
+
+  let mut common_lines = vec![];
+  let (mut x, mut y) = (n, m);
+  for (d, diagonals) in trace.iter().enumerate().rev() {
+    let d = d as isize;
+    let at = |k: isize| diagonals[(k + d + 1) as usize];
+    let k = x - y;
+    let previous_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+      k + 1
+    } else {
+      k - 1
+    };
+    let (previous_x, previous_y) = (at(previous_k), at(previous_k) - previous_k);
+    while x > previous_x && y > previous_y {
+      (x, y) = (x - 1, y - 1);
+      common_lines.push((x as usize, y as usize));
+    }
+    (x, y) = (previous_x, previous_y);
+  }
+  common_lines.reverse();
+  common_lines
+}
+
+/// Groups the `runs` into hunks, i.e. the replaced runs along with up to `CONTEXT_LINES` equal lines around them
+/// (the hunks whose context would overlap are merged), as in `diff -u`.
+fn get_hunks(runs: Vec<LineRun>) -> Vec<Vec<LineRun>> {
+  let mut hunks = vec![];
+  let mut hunk: Vec<LineRun> = vec![];
+  let last = runs.len().saturating_sub(1);
+  for (i, run) in runs.into_iter().enumerate() {
+    let length = run.original.len();
+    if !run.is_equal {
+      hunk.push(run);
+    } else if hunk.is_empty() {
+      // The context before the first change
+      hunk.push(run.tail(length.min(CONTEXT_LINES)));
+    } else if i == last || length > 2 * CONTEXT_LINES {
+      // The context after the change ends the hunk, and the next hunk starts with the context before the next change
+      hunk.push(run.head(length.min(CONTEXT_LINES)));
+      hunks.push(std::mem::take(&mut hunk));
+      hunk.push(run.tail(length.min(CONTEXT_LINES)));
+    } else {
+      hunk.push(run);
+    }
+  }
+  if hunk.iter().any(|run| !run.is_equal) {
+    hunks.push(hunk);
+  }
+  hunks
+}
+
+/// The range of lines of a hunk (`start,length`, where `start` is 1-based), as in `diff -u`.
+fn hunk_range(start: usize, end: usize) -> String {
+  match end - start {
+    0 => format!("{start},0"),
+    1 => format!("{}", start + 1),
+    length => format!("{},{length}", start + 1),
+  }
+}
+
+fn push_lines(diff: &mut String, prefix: char, lines: &[&str]) {
+  for line in lines {
+    diff.push(prefix);
+    diff.push_str(line);
+    if !line.ends_with('\n') {
+      diff.push_str("\n\\ No newline at end of file\n");
+    }
+  }
+}
+
+#[cfg(test)]
+#[path = "unit_tests/patch_test.rs"]
+mod patch_test;
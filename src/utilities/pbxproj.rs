@@ -0,0 +1,148 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+//! A minimal, line based editor for Xcode project files (`project.pbxproj`).
+//!
+//! There is no tree-sitter grammar for this (plist like) format, hence it is handled as a special file.
+//! The editor only supports removing references (to frameworks, files and Swift packages) by name.
+//! Any content that is not removed is preserved byte for byte.
+
+use std::{collections::HashSet, sync::OnceLock};
+
+use regex::Regex;
+
+pub(crate) const PBXPROJ_FILE_NAME: &str = "project.pbxproj";
+
+/// An object definition in a pbxproj file, like
+/// `1A2B3C4D5E6F7A8B9C0D1E2F /* Foo.framework in Frameworks */ = {isa = PBXBuildFile; ... };`
+#[derive(Debug)]
+struct PbxObject {
+  id: String,
+  comment: String,
+  // Index of the first and last line (inclusive) of the definition
+  start_line: usize,
+  end_line: usize,
+}
+
+impl PbxObject {
+  /// Checks if the comment of this object denotes a reference to `name`.
+  /// Xcode annotates the objects as `name`, `name in <Build Phase>` or `XCRemoteSwiftPackageReference "name"`.
+  fn is_reference_to(&self, name: &str) -> bool {
+    self.comment == name
+      || self
+        .comment
+        .strip_prefix(name)
+        .is_some_and(|rest| rest.starts_with(" in "))
+      || self.comment == format!("XCRemoteSwiftPackageReference \"{name}\"")
+  }
+}
+
+/// Removes all the references to the frameworks, files or Swift packages named `names` from the `content` of a pbxproj file.
+///
+/// The objects annotated with any of the `names` are deleted along with the objects that refer to
+/// them (through `fileRef`, `productRef` or `package`), transitively.
+/// Finally, the entries pointing to any deleted object are deleted from all the lists (e.g. `files = ( ... );`).
+pub(crate) fn remove_references(content: &str, names: &[String]) -> String {
+  let lines: Vec<&str> = content.split_inclusive('\n').collect();
+  let objects = get_objects(&lines);
+
+  let mut removed_ids: HashSet<String> = objects
+    .iter()
+    .filter(|o| names.iter().any(|n| o.is_reference_to(n)))
+    .map(|o| o.id.to_string())
+    .collect();
+
+  static REFERENCE_REGEX: OnceLock<Regex> = OnceLock::new();
+  let reference = REFERENCE_REGEX
+    .get_or_init(|| Regex::new(r"\b(?:fileRef|productRef|package) = ([0-9A-F]{24})\b").unwrap());
+  loop {
+    let dependents: Vec<String> = objects
+      .iter()
+      .filter(|o| !removed_ids.contains(&o.id))
+      .filter(|o| {
+        lines[o.start_line..=o.end_line].iter().any(|l| {
+          reference
+            .captures_iter(l)
+            .any(|c| removed_ids.contains(&c[1]))
+        })
+      })
+      .map(|o| o.id.to_string())
+      .collect();
+    if dependents.is_empty() {
+      break;
+    }
+    removed_ids.extend(dependents);
+  }
+
+  if removed_ids.is_empty() {
+    return content.to_string();
+  }
+
+  let removed_lines: HashSet<usize> = objects
+    .iter()
+    .filter(|o| removed_ids.contains(&o.id))
+    .flat_map(|o| o.start_line..=o.end_line)
+    .collect();
+
+  static LIST_ENTRY_REGEX: OnceLock<Regex> = OnceLock::new();
+  let list_entry =
+    LIST_ENTRY_REGEX.get_or_init(|| Regex::new(r"^\s*([0-9A-F]{24})(?: /\*.*\*/)?,\s*$").unwrap());
+  lines
+    .iter()
+    .enumerate()
+    .filter(|(i, _)| !removed_lines.contains(i))
+    .filter(|(_, l)| {
+      !list_entry
+        .captures(l)
+        .is_some_and(|c| removed_ids.contains(&c[1]))
+    })
+    .map(|(_, l)| *l)
+    .collect()
+}
+
+/// Collects the object definitions (single line or multi line) in the given `lines`.
+fn get_objects(lines: &[&str]) -> Vec<PbxObject> {
+  static DEFINITION_REGEX: OnceLock<Regex> = OnceLock::new();
+  let definition =
+    DEFINITION_REGEX.get_or_init(|| Regex::new(r"^\s*([0-9A-F]{24}) /\* (.*?) \*/ = \{").unwrap());
+  let mut objects = vec![];
+  let mut i = 0;
+  while i < lines.len() {
+    if let Some(c) = definition.captures(lines[i]) {
+      let single_line = lines[i].trim_end().ends_with("};");
+      let end_line = if single_line {
+        Some(i)
+      } else {
+        // Nested dictionaries (like `buildSettings`) are closed at a deeper indentation
+        let indentation = &lines[i][..lines[i].len() - lines[i].trim_start().len()];
+        let closing = format!("{indentation}}};");
+        (i + 1..lines.len()).find(|j| lines[*j].trim_end() == closing)
+      };
+      if let Some(end_line) = end_line {
+        objects.push(PbxObject {
+          id: c[1].to_string(),
+          comment: c[2].to_string(),
+          start_line: i,
+          end_line,
+        });
+        i = end_line;
+      }
+    }
+    i += 1;
+  }
+  objects
+}
+
+#[cfg(test)]
+#[path = "unit_tests/pbxproj_test.rs"]
+mod pbxproj_test;
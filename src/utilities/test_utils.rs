@@ -0,0 +1,154 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+//! Assertions for testing rules on code snippets, without laying out the configurations and the code base
+//! on the disk (available with the `test-utils` feature). For instance:
+//!
+//! ```ignore
+//! assert_rewrite(
+//!   "java",
+//!   RULES_TOML,
+//!   "",
+//!   "class A { boolean b = isEnabled(STALE_FLAG); }",
+//!   "class A { boolean b = true; }",
+//!   &[("stale_flag_name", "STALE_FLAG")],
+//! );
+//! ```
+
+use colored::Colorize;
+
+use crate::{
+  execute_piranha,
+  models::{
+    language::PiranhaLanguage, outgoing_edges::Edges, piranha_arguments::PiranhaArgumentsBuilder,
+    rule::Rules, rule_graph::RuleGraphBuilder,
+  },
+};
+
+use super::patch::unified_diff;
+
+/// Applies the rules (`rules_toml` and `edges_toml`, in the format of `rules.toml` and `edges.toml`) instantiated
+/// with the `substitutions` to the `input_source` (in the `language`), and asserts that the result is the `expected_source`.
+/// On mismatch, panics with a (colored) diff from the expected to the actual result.
+pub fn assert_rewrite(
+  language: &str, rules_toml: &str, edges_toml: &str, input_source: &str, expected_source: &str,
+  substitutions: &[(&str, &str)],
+) {
+  let (actual_source, _) = run_rules(
+    language,
+    rules_toml,
+    edges_toml,
+    input_source,
+    substitutions,
+  );
+  if actual_source != expected_source {
+    panic!(
+      "The rewritten code differs from the expected code:\n{}",
+      colored_diff(expected_source, &actual_source)
+    );
+  }
+}
+
+/// Asserts that the rules (see `assert_rewrite`) do not change the `input_source`.
+pub fn assert_no_change(
+  language: &str, rules_toml: &str, edges_toml: &str, input_source: &str,
+  substitutions: &[(&str, &str)],
+) {
+  assert_rewrite(
+    language,
+    rules_toml,
+    edges_toml,
+    input_source,
+    input_source,
+    substitutions,
+  );
+}
+
+/// Asserts that the rules (see `assert_rewrite`) match the `input_source` exactly `expected_match_count` times
+/// (e.g. for the match-only rules).
+pub fn assert_matches(
+  language: &str, rules_toml: &str, edges_toml: &str, input_source: &str,
+  substitutions: &[(&str, &str)], expected_match_count: usize,
+) {
+  let (_, match_count) = run_rules(
+    language,
+    rules_toml,
+    edges_toml,
+    input_source,
+    substitutions,
+  );
+  assert_eq!(
+    match_count, expected_match_count,
+    "Expected {expected_match_count} match(es), but found {match_count}"
+  );
+}
+
+/// Applies the rules (see `assert_rewrite`) to the `input_source`, and returns the resulting code along with the number of matches.
+fn run_rules(
+  language: &str, rules_toml: &str, edges_toml: &str, input_source: &str,
+  substitutions: &[(&str, &str)],
+) -> (String, usize) {
+  let rules: Rules =
+    toml::from_str(rules_toml).unwrap_or_else(|e| panic!("Invalid rules.toml - {e}"));
+  let edges: Edges = if edges_toml.trim().is_empty() {
+    Edges::default()
+  } else {
+    toml::from_str(edges_toml).unwrap_or_else(|e| panic!("Invalid edges.toml - {e}"))
+  };
+  let piranha_arguments = PiranhaArgumentsBuilder::default()
+    .code_snippet(input_source.to_string())
+    .language(PiranhaLanguage::from(language))
+    .substitutions(
+      substitutions
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect(),
+    )
+    .rule_graph(
+      RuleGraphBuilder::default()
+        .rules(rules.rules)
+        .edges(edges.edges)
+        .build(),
+    )
+    .build();
+  let output_summaries = execute_piranha(&piranha_arguments);
+  let match_count = output_summaries.iter().map(|s| s.matches().len()).sum();
+  // The snippet is not reported if the rules neither matched nor rewrote it
+  let source = output_summaries
+    .first()
+    .map_or_else(|| input_source.to_string(), |s| s.content().to_string());
+  (source, match_count)
+}
+
+/// The diff from the `expected` code to the `actual` one, with the removed lines in red and the added ones in green.
+fn colored_diff(expected: &str, actual: &str) -> String {
+  let Some(diff) = unified_diff("snippet", expected, Some(actual)) else {
+    return String::new();
+  };
+  diff
+    .lines()
+    // Skip the header of the diff (i.e. the `diff`, `---` and `+++` lines)
+    .skip(3)
+    .map(|line| match line.chars().next() {
+      Some('-') => line.red().to_string(),
+      Some('+') => line.green().to_string(),
+      Some('@') => line.cyan().to_string(),
+      _ => line.to_string(),
+    })
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+#[cfg(test)]
+#[path = "unit_tests/test_utils_test.rs"]
+mod test_utils_test;
@@ -15,13 +15,17 @@ Copyright (c) 2023 Uber Technologies, Inc.
 
 use super::eq_without_whitespace;
 use crate::{
-  models::{edit::Edit, matches::Match},
+  models::{
+    edit::Edit,
+    matches::{self, Match},
+  },
   utilities::MapOfVec,
 };
 use itertools::Itertools;
 use log::debug;
+use schemars::JsonSchema;
 
-use std::collections::HashMap;
+use std::{cmp::Reverse, collections::HashMap};
 use tree_sitter::{InputEdit, Node, Parser, Point, Query, QueryCapture, QueryCursor, Range};
 use tree_sitter_traversal::{traverse, Order};
 
@@ -66,6 +70,11 @@ pub(crate) fn get_all_matches_for_query(
   // that use the quantifier operator (*/+)). Therefore for each query match, we have to group (join) the codes snippets
   // corresponding to the same tag.
   let mut output = vec![];
+  // The groups are visited in the order of their ranges (rather than the arbitrary order of the map),
+  // so that the matches starting at the same byte are in the same order in every run
+  let query_capture_groups = query_capture_groups
+    .into_iter()
+    .sorted_by_key(|(range, _)| (range.start_byte, range.end_byte));
   for (captured_node_range, query_matches) in query_capture_groups {
     // This ensures that each query pattern in rule.query matches the same node.
     if query_matches.len() != query.pattern_count() {
@@ -90,18 +99,52 @@ pub(crate) fn get_all_matches_for_query(
         }
       }
 
+      let capture_ranges = get_capture_ranges(query, &query_matches);
       let code_snippet_by_tag = accumulate_repeated_tags(query, query_matches, &source_code);
 
-      output.push(Match::new(
+      let mut p_match = Match::new(
         source_code[replace_node_range.start_byte..replace_node_range.end_byte].to_string(),
         replace_node_range,
         code_snippet_by_tag,
-      ));
+      );
+      *p_match.capture_ranges_mut() = capture_ranges;
+      output.push(p_match);
+    }
+  }
+  // This sorts the matches from bottom to top (the outermost first, among the matches starting at the same byte)
+  output.sort_by_key(|m| Reverse((m.range().start_byte, m.range().end_byte)));
+  output
+}
+
+/// Applies the query upon the given node, and gets all the matches that do not overlap each other
+/// (e.g. to count the occurrences of a pattern).
+/// # Arguments
+/// * `node` - the root node to apply the query upon
+/// * `source_code` - the corresponding source code string for the node.
+/// * `query` - the query to be applied
+/// * `recursive` - if `true` it matches the query to `self` and `self`'s sub-ASTs, else it matches the `query` only to `self`.
+///
+/// # Returns
+/// The matches in the order of the source code (unlike `get_all_matches_for_query`). A match overlapping a previous
+/// one (i.e. nested within it, as the matches are grouped by the range of their outermost tag) is skipped.
+pub(crate) fn get_non_overlapping_matches_for_query(
+  node: &Node, source_code: &str, query: &Query, recursive: bool,
+) -> Vec<Match> {
+  let mut output: Vec<Match> = vec![];
+  let all_matches =
+    get_all_matches_for_query(node, source_code.to_string(), query, recursive, None, None);
+  // The outermost match first, among the matches starting at the same byte
+  for p_match in all_matches
+    .into_iter()
+    .sorted_by_key(|m| (m.range().start_byte, Reverse(m.range().end_byte)))
+  {
+    if output
+      .last()
+      .is_none_or(|previous| previous.range().end_byte <= p_match.range().start_byte)
+    {
+      output.push(p_match);
     }
   }
-  // This sorts the matches from bottom to top
-  output.sort_by(|a, b| a.range().start_byte.cmp(&b.range().start_byte));
-  output.reverse();
   output
 }
 
@@ -170,6 +213,26 @@ fn accumulate_repeated_tags(
   code_snippet_by_tag
 }
 
+// Returns the mapping between each tag and the (sorted) ranges of the nodes it captured.
+// A tag bound to multiple nodes (by a quantified capture, e.g. `(_)* @args`) has multiple ranges,
+// while a tag that did not capture any node (e.g. an optional capture) has none.
+fn get_capture_ranges(
+  query: &Query, query_matches: &[Vec<tree_sitter::QueryCapture>],
+) -> HashMap<String, Vec<matches::Range>> {
+  let mut ranges_by_tag: HashMap<String, Vec<matches::Range>> = HashMap::new();
+  for capture in query_matches.iter().flatten() {
+    ranges_by_tag.collect(
+      query.capture_names()[capture.index as usize].clone(),
+      matches::Range::from(capture.node.range()),
+    );
+  }
+  for ranges in ranges_by_tag.values_mut() {
+    ranges.sort();
+    ranges.dedup();
+  }
+  ranges_by_tag
+}
+
 // In some queries, the `rule.query` matches a larger node, while the rewrite rule replaces the a sub-AST with a new pattern
 // For instance: cleanup_riles/java/rules:remove_unnecessary_nested_block (here the outermost tag is @block while the
 // replace_node is @nested.block)
@@ -248,7 +311,18 @@ pub(crate) fn get_tree_sitter_edit(code: String, edit: &Edit) -> (String, InputE
   )
 }
 
+/// Returns the range (including the positions) between the byte offsets `start_byte` and `end_byte` of the `code`.
+pub(crate) fn get_range_for_offsets(code: &str, start_byte: usize, end_byte: usize) -> Range {
+  Range {
+    start_byte,
+    end_byte,
+    start_point: position_for_offset(code.as_bytes(), start_byte),
+    end_point: position_for_offset(code.as_bytes(), end_byte),
+  }
+}
+
 // Finds the position (col and row number) for a given offset.
+// As in tree-sitter, only `\n` starts a new row, i.e. the `\r` of a `\r\n` line ending is the last column of its row.
 fn position_for_offset(input: &[u8], offset: usize) -> Point {
   let mut result = Point { row: 0, column: 0 };
   for c in &input[0..offset] {
@@ -262,6 +336,38 @@ fn position_for_offset(input: &[u8], offset: usize) -> Point {
   result
 }
 
+/// Maps the (line, column) positions of a code to byte offsets. As in tree-sitter's `Point`, both are 0-based
+/// and the column is in bytes.
+pub(crate) struct LineMap {
+  // The byte offset at which each line starts
+  line_starts: Vec<usize>,
+  // The length of the code (in bytes)
+  len: usize,
+}
+
+impl LineMap {
+  pub(crate) fn new(code: &str) -> Self {
+    let line_starts = std::iter::once(0)
+      .chain(code.match_indices('\n').map(|(i, _)| i + 1))
+      .collect();
+    LineMap {
+      line_starts,
+      len: code.len(),
+    }
+  }
+
+  /// Returns the byte offset of the position, or `None` if the line does not exist or the column is past its end.
+  pub(crate) fn offset(&self, line: usize, column: usize) -> Option<usize> {
+    let line_start = *self.line_starts.get(line)?;
+    // The line ends at its line break (or at the end of the code)
+    let line_end = self
+      .line_starts
+      .get(line + 1)
+      .map_or(self.len, |next_line_start| next_line_start - 1);
+    (line_start + column <= line_end).then_some(line_start + column)
+  }
+}
+
 // Creates the InputEdit as per the tree-sitter api documentation.
 fn _get_tree_sitter_edit(
   replace_range: Range, len_of_replacement: usize, old_source_code_bytes: &[u8],
@@ -322,6 +428,86 @@ pub(crate) fn get_replace_range(input_edit: InputEdit) -> Range {
   }
 }
 
+/// Determines how a byte range overlapping an edit follows it (see `shift_range`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Overlap {
+  /// The range is dropped, since its code changed
+  Drop,
+  /// The offsets within the replaced code are clamped to the replacement, i.e. the range grows or shrinks
+  Clamp,
+  /// The range is extended to the replacement
+  Extend,
+}
+
+/// Shifts the byte range `(start_byte, end_byte)` of the code following the (tree-sitter) `edit` applied to it.
+/// The ranges ending before the edit are unchanged, the ones starting after it are shifted, and the ones overlapping
+/// it are handled according to `overlap`. Returns `None` if the range is dropped.
+pub(crate) fn shift_range(
+  (start_byte, end_byte): (usize, usize), edit: &InputEdit, overlap: Overlap,
+) -> Option<(usize, usize)> {
+  let shift = |offset: usize| offset + edit.new_end_byte - edit.old_end_byte;
+  if end_byte <= edit.start_byte {
+    return Some((start_byte, end_byte));
+  }
+  if start_byte >= edit.old_end_byte {
+    return Some((shift(start_byte), shift(end_byte)));
+  }
+  let shifted_end = |clamped: usize| {
+    if end_byte >= edit.old_end_byte {
+      shift(end_byte)
+    } else {
+      clamped
+    }
+  };
+  match overlap {
+    Overlap::Drop => None,
+    Overlap::Clamp => {
+      let start_byte = if start_byte <= edit.start_byte {
+        start_byte
+      } else {
+        start_byte.min(edit.new_end_byte)
+      };
+      Some((
+        start_byte,
+        shifted_end(end_byte.min(edit.new_end_byte)).max(start_byte),
+      ))
+    }
+    Overlap::Extend => Some((
+      start_byte.min(edit.start_byte),
+      shifted_end(edit.new_end_byte),
+    )),
+  }
+}
+
+/// Returns the (byte) range that encompasses all the changes made by the given sequence of `edits`.
+/// The range is expressed w.r.t. the source code obtained after applying all the `edits` (in order).
+pub(crate) fn get_changed_range(edits: &[InputEdit]) -> Option<(usize, usize)> {
+  let mut changed_range: Option<(usize, usize)> = None;
+  for edit in edits {
+    let (edit_start, edit_end) = (edit.start_byte, edit.new_end_byte);
+    changed_range = Some(match changed_range {
+      None => (edit_start, edit_end),
+      // The previously changed range lies before this edit
+      Some((start, end)) if end <= edit.start_byte => (start, edit_end),
+      // The previously changed range lies after this edit, so it gets shifted
+      Some((start, end)) if start >= edit.old_end_byte => {
+        let shift = |x: usize| x + edit.new_end_byte - edit.old_end_byte;
+        (edit_start, shift(end))
+      }
+      // The previously changed range overlaps with this edit
+      Some((start, end)) => {
+        let end = if end > edit.old_end_byte {
+          end + edit.new_end_byte - edit.old_end_byte
+        } else {
+          edit_end
+        };
+        (start.min(edit_start), end.max(edit_end))
+      }
+    });
+  }
+  changed_range
+}
+
 /// Returns the (tree-sitter) parser for the tree-sitter query DSL
 pub(crate) fn get_ts_query_parser() -> Parser {
   let mut parser = Parser::new();
@@ -338,6 +524,117 @@ pub(crate) fn number_of_errors(node: &Node) -> usize {
     .count()
 }
 
+/// Returns the ranges of the error (and missing) nodes in the AST
+pub(crate) fn get_error_ranges(node: &Node) -> Vec<Range> {
+  traverse(node.walk(), Order::Pre)
+    .filter(|node| node.is_error() || node.is_missing())
+    .map(|node| node.range())
+    .collect()
+}
+
+/// Determines how strictly two code snippets are compared textually
+/// (e.g. when de-duplicating matches or detecting no-op edits).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, JsonSchema)]
+#[schemars(rename_all = "kebab-case")]
+pub enum WhitespaceSensitivity {
+  /// Snippets are compared byte by byte
+  #[default]
+  Exact,
+  /// `\r\n` and `\n` are considered equal
+  LineEndings,
+  /// Whitespace between tokens is ignored. Whitespace inside string literals and comments is preserved.
+  Insensitive,
+}
+
+/// Returns the normalized form (w.r.t. `sensitivity`) of the code snippet corresponding to `node`.
+///
+/// For `WhitespaceSensitivity::Insensitive`, the snippet is reduced to its token stream:
+/// the leaves of the AST are joined by a single space. String literals (i.e. nodes whose kind contains `string`)
+/// and comments (`comment_nodes`) are treated as single tokens, so that whitespace inside them is preserved.
+pub(crate) fn normalize_node_text(
+  node: &Node, source_code: &str, comment_nodes: &[String], sensitivity: WhitespaceSensitivity,
+) -> String {
+  let snippet = &source_code[node.start_byte()..node.end_byte()];
+  if sensitivity == WhitespaceSensitivity::Exact {
+    return snippet.to_string();
+  }
+  if sensitivity == WhitespaceSensitivity::LineEndings {
+    return snippet.replace("\r\n", "\n");
+  }
+
+  let mut tokens = vec![];
+  let mut last_end = node.start_byte();
+  for atom in get_atomic_nodes(*node, comment_nodes) {
+    // Text that is not covered by any node (other than whitespace) is retained as a token.
+    if atom.start_byte() > last_end {
+      let uncovered = source_code[last_end..atom.start_byte()].trim();
+      if !uncovered.is_empty() {
+        tokens.push(uncovered.to_string());
+      }
+    }
+    let text = &source_code[atom.start_byte()..atom.end_byte()];
+    if !text.is_empty() {
+      tokens.push(text.replace("\r\n", "\n"));
+    }
+    last_end = last_end.max(atom.end_byte());
+  }
+  tokens.join(" ")
+}
+
+/// Checks if the two snippets are equal once all their whitespace is removed, which is necessary for them to be
+/// equivalent (w.r.t. any `WhitespaceSensitivity`), and cheaper to check than normalizing them.
+pub(crate) fn differ_in_whitespace_only(snippet_1: &str, snippet_2: &str) -> bool {
+  snippet_1
+    .chars()
+    .filter(|c| !c.is_whitespace())
+    .eq(snippet_2.chars().filter(|c| !c.is_whitespace()))
+}
+
+/// Parses the `snippet` and returns its normalized form (w.r.t. `sensitivity`).
+/// Look at `normalize_node_text` for details.
+pub(crate) fn normalize_snippet(
+  snippet: &str, parser: &mut Parser, comment_nodes: &[String], sensitivity: WhitespaceSensitivity,
+) -> String {
+  if sensitivity != WhitespaceSensitivity::Insensitive {
+    return normalize_text(snippet, sensitivity);
+  }
+  match parser.parse(snippet, None) {
+    Some(tree) => normalize_node_text(&tree.root_node(), snippet, comment_nodes, sensitivity),
+    None => normalize_text(snippet, sensitivity),
+  }
+}
+
+// Normalizes the `snippet` without using its AST
+fn normalize_text(snippet: &str, sensitivity: WhitespaceSensitivity) -> String {
+  match sensitivity {
+    WhitespaceSensitivity::Exact => snippet.to_string(),
+    WhitespaceSensitivity::LineEndings => snippet.replace("\r\n", "\n"),
+    WhitespaceSensitivity::Insensitive => snippet.split_whitespace().join(" "),
+  }
+}
+
+/// Returns the nodes under `node` that are considered as a single token (in order).
+/// These are the leaves, the string literals and the comments.
+fn get_atomic_nodes<'a>(node: Node<'a>, comment_nodes: &[String]) -> Vec<Node<'a>> {
+  let mut atoms = vec![];
+  _collect_atomic_nodes(node, comment_nodes, &mut atoms);
+  atoms
+}
+
+fn _collect_atomic_nodes<'a>(node: Node<'a>, comment_nodes: &[String], atoms: &mut Vec<Node<'a>>) {
+  if node.child_count() == 0
+    || node.kind().contains("string")
+    || comment_nodes.contains(&node.kind().to_string())
+  {
+    atoms.push(node);
+    return;
+  }
+  let mut cursor = node.walk();
+  for child in node.children(&mut cursor) {
+    _collect_atomic_nodes(child, comment_nodes, atoms);
+  }
+}
+
 #[cfg(test)]
 #[path = "unit_tests/tree_sitter_utilities_test.rs"]
 mod tree_sitter_utilities_test;
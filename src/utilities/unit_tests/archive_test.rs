@@ -0,0 +1,216 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use std::{collections::HashMap, fs::File, io::Read, path::Path};
+
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use tempdir::TempDir;
+
+use super::{Archive, ArchiveFormat};
+
+#[test]
+fn test_archive_format() {
+  assert_eq!(
+    ArchiveFormat::of(Path::new("codebase.zip")),
+    Some(ArchiveFormat::Zip)
+  );
+  assert_eq!(
+    ArchiveFormat::of(Path::new("codebase.tar.gz")),
+    Some(ArchiveFormat::TarGz)
+  );
+  assert_eq!(
+    ArchiveFormat::of(Path::new("codebase.TGZ")),
+    Some(ArchiveFormat::TarGz)
+  );
+  assert_eq!(ArchiveFormat::of(Path::new("codebase.tar")), None);
+  assert_eq!(ArchiveFormat::of(Path::new("Sample.java")), None);
+}
+
+/// Writes a `.tar.gz` archive with the `files` (name, content and mode) at `path`
+fn write_tar_gz(path: &Path, files: &[(&str, &str, u32)]) {
+  let mut builder = tar::Builder::new(GzEncoder::new(
+    File::create(path).unwrap(),
+    Compression::default(),
+  ));
+  for (name, content, mode) in files {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(*mode);
+    header.set_mtime(1_672_628_646);
+    builder
+      .append_data(&mut header, name, content.as_bytes())
+      .unwrap();
+  }
+  builder.into_inner().unwrap().finish().unwrap();
+}
+
+/// Reads the entries (name, content and header) of the `.tar.gz` archive at `path`
+fn read_tar_gz(path: &Path) -> Vec<(String, String, tar::Header)> {
+  let mut archive = tar::Archive::new(GzDecoder::new(File::open(path).unwrap()));
+  archive
+    .entries()
+    .unwrap()
+    .map(|entry| {
+      let mut entry = entry.unwrap();
+      let name = entry.path().unwrap().to_string_lossy().to_string();
+      let mut content = String::new();
+      entry.read_to_string(&mut content).unwrap();
+      (name, content, entry.header().clone())
+    })
+    .collect()
+}
+
+#[test]
+fn test_tar_gz_round_trip() {
+  let temp_dir = TempDir::new_in(".", "archive").unwrap();
+  let input = temp_dir.path().join("codebase.tar.gz");
+  let output = temp_dir.path().join("output.tar.gz");
+  write_tar_gz(
+    &input,
+    &[
+      ("src/A.java", "class A {}\n", 0o644),
+      ("src/B.java", "class B {}\n", 0o600),
+      ("run.sh", "echo hello\n", 0o755),
+    ],
+  );
+
+  let archive = Archive::open(&input).unwrap();
+  let files = archive.read_files(|_| true).unwrap();
+  assert_eq!(
+    files,
+    vec![
+      (input.join("src/A.java"), "class A {}\n".to_string()),
+      (input.join("src/B.java"), "class B {}\n".to_string()),
+      (input.join("run.sh"), "echo hello\n".to_string()),
+    ]
+  );
+
+  let updated_files = HashMap::from([
+    (
+      input.join("src/A.java"),
+      Some("class A { int x; }\n".to_string()),
+    ),
+    (input.join("src/B.java"), None),
+  ]);
+  archive.write(&output, &updated_files).unwrap();
+
+  let original_entries = read_tar_gz(&input);
+  let entries = read_tar_gz(&output);
+  assert_eq!(
+    entries
+      .iter()
+      .map(|(name, content, _)| (name.as_str(), content.as_str()))
+      .collect::<Vec<_>>(),
+    vec![
+      ("src/A.java", "class A { int x; }\n"),
+      ("run.sh", "echo hello\n"),
+    ]
+  );
+  // The metadata of the entries is preserved, and the untouched entries are identical
+  assert_eq!(entries[0].2.mode().unwrap(), 0o644);
+  assert_eq!(entries[0].2.mtime().unwrap(), 1_672_628_646);
+  assert_eq!(entries[1].2.as_bytes(), original_entries[2].2.as_bytes());
+  temp_dir.close().unwrap();
+}
+
+#[test]
+fn test_read_files_filters_by_path() {
+  let temp_dir = TempDir::new_in(".", "archive").unwrap();
+  let input = temp_dir.path().join("codebase.tgz");
+  write_tar_gz(
+    &input,
+    &[
+      ("src/A.java", "class A {}\n", 0o644),
+      ("run.sh", "echo hello\n", 0o755),
+    ],
+  );
+  let archive = Archive::open(&input).unwrap();
+  let is_java = |path: &Path| path.extension().is_some_and(|e| e == "java");
+  assert_eq!(
+    archive.read_files(is_java).unwrap(),
+    vec![(input.join("src/A.java"), "class A {}\n".to_string())]
+  );
+  // The content of the entries is read once
+  assert_eq!(archive.contents.lock().unwrap().len(), 1);
+  assert_eq!(archive.read_files(|_| true).unwrap().len(), 2);
+  assert!(Archive::open(&temp_dir.path().join("missing.zip")).is_none());
+  temp_dir.close().unwrap();
+}
+
+/// A record of a PAX extended header, i.e. `<length> <key>=<value>\n` (where the length includes itself)
+fn pax_record(key: &str, value: &str) -> String {
+  let record = format!(" {key}={value}\n");
+  let mut length = record.len() + 1;
+  while format!("{length}").len() + record.len() != length {
+    length += 1;
+  }
+  format!("{length}{record}")
+}
+
+#[test]
+fn test_tar_gz_keeps_the_pax_extended_headers() {
+  let temp_dir = TempDir::new_in(".", "archive").unwrap();
+  let input = temp_dir.path().join("codebase.tar.gz");
+  let output = temp_dir.path().join("output.tar.gz");
+  let long_name = format!("src/{}/A.java", "nested".repeat(20));
+  let pax = format!(
+    "{}{}",
+    pax_record("path", &long_name),
+    pax_record("mtime", "1672628646.5")
+  );
+  let mut builder = tar::Builder::new(GzEncoder::new(
+    File::create(&input).unwrap(),
+    Compression::default(),
+  ));
+  let mut pax_header = tar::Header::new_ustar();
+  pax_header.set_entry_type(tar::EntryType::XHeader);
+  pax_header.set_path("PaxHeaders/A.java").unwrap();
+  pax_header.set_size(pax.len() as u64);
+  pax_header.set_cksum();
+  builder.append(&pax_header, pax.as_bytes()).unwrap();
+  let content = "class A {}\n";
+  let mut header = tar::Header::new_ustar();
+  header.set_path("A.java").unwrap();
+  header.set_size(content.len() as u64);
+  header.set_mode(0o644);
+  header.set_cksum();
+  builder.append(&header, content.as_bytes()).unwrap();
+  builder.into_inner().unwrap().finish().unwrap();
+
+  // The entry is named after the `path` of its PAX extended header
+  let archive = Archive::open(&input).unwrap();
+  let path = input.join(&long_name);
+  assert_eq!(
+    archive.read_files(|_| true).unwrap(),
+    vec![(path.clone(), content.to_string())]
+  );
+
+  let updated_files = HashMap::from([(path, Some("class A { int x; }\n".to_string()))]);
+  archive.write(&output, &updated_files).unwrap();
+  assert_eq!(
+    read_tar_gz(&output)
+      .iter()
+      .map(|(name, content, _)| (name.as_str(), content.as_str()))
+      .collect::<Vec<_>>(),
+    vec![(long_name.as_str(), "class A { int x; }\n")]
+  );
+  // The PAX extended header is copied as is
+  let mut output_archive = tar::Archive::new(GzDecoder::new(File::open(&output).unwrap()));
+  let mut entries = output_archive.entries().unwrap().raw(true);
+  let mut pax_entry = entries.next().unwrap().unwrap();
+  assert_eq!(pax_entry.header().entry_type(), tar::EntryType::XHeader);
+  let mut output_pax = String::new();
+  pax_entry.read_to_string(&mut output_pax).unwrap();
+  assert_eq!(output_pax, pax);
+  temp_dir.close().unwrap();
+}
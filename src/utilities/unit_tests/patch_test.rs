@@ -0,0 +1,184 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use std::path::Path;
+
+use super::{get_common_lines, unified_diff, Patch};
+use crate::utilities::codebase::Codebase;
+
+#[test]
+fn test_unified_diff() {
+  let original = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+  let updated = "a\nb\nc\nd\ne\nf\ng\nh\nI\nj\n";
+  assert_eq!(
+    unified_diff("src/A.java", original, Some(updated)).unwrap(),
+    "diff --git a/src/A.java b/src/A.java
+--- a/src/A.java
++++ b/src/A.java
+@@ -6,5 +6,5 @@
+ f
+ g
+ h
+-i
++I
+ j
+"
+  );
+
+  // The last line has no line ending
+  assert_eq!(
+    unified_diff("A.java", "a\nb", Some("a\nc")).unwrap(),
+    "diff --git a/A.java b/A.java
+--- a/A.java
++++ b/A.java
+@@ -1,2 +1,2 @@
+ a
+-b
+\\ No newline at end of file
++c
+\\ No newline at end of file
+"
+  );
+
+  assert_eq!(
+    unified_diff("A.java", "a\n", None).unwrap(),
+    "diff --git a/A.java b/A.java
+deleted file mode 100644
+--- a/A.java
++++ /dev/null
+@@ -1 +0,0 @@
+-a
+"
+  );
+
+  assert_eq!(unified_diff("A.java", "a\n", Some("a\n")), None);
+}
+
+#[test]
+fn test_unified_diff_hunks() {
+  let original = (1..=16).map(|i| format!("{i}\n")).collect::<String>();
+  // The changes more than 6 lines apart (i.e. twice the context) are in separate hunks
+  let updated = original.replace("2\n", "X\n").replace("14\n", "Y\n");
+  assert_eq!(
+    unified_diff("A.java", &original, Some(&updated)).unwrap(),
+    "diff --git a/A.java b/A.java
+--- a/A.java
++++ b/A.java
+@@ -1,5 +1,5 @@
+ 1
+-2
++X
+ 3
+ 4
+ 5
+@@ -11,6 +11,6 @@
+ 11
+ 12
+ 13
+-14
++Y
+ 15
+ 16
+"
+  );
+
+  // The lines inserted in an empty file
+  assert_eq!(
+    unified_diff("A.java", "", Some("a\nb\n")).unwrap(),
+    "diff --git a/A.java b/A.java
+--- a/A.java
++++ b/A.java
+@@ -0,0 +1,2 @@
++a
++b
+"
+  );
+}
+
+#[test]
+fn test_get_common_lines() {
+  let lines = |s: &'static str| s.split(' ').collect::<Vec<_>>();
+  assert_eq!(
+    get_common_lines(&lines("a b c a b b a"), &lines("c b a b a c")),
+    vec![(2, 0), (3, 2), (4, 3), (6, 4)]
+  );
+  assert_eq!(get_common_lines(&lines("a b"), &[]), vec![]);
+  assert_eq!(
+    get_common_lines(&lines("a b"), &lines("a b")),
+    vec![(0, 0), (1, 1)]
+  );
+}
+
+#[test]
+fn test_patch() {
+  let mut patch = Patch::new(&Codebase::new(Path::new("some/code/base"), &[], &[]));
+  patch.add(Path::new("some/code/base/src/B.java"), "b\n", Some("c\n"));
+  patch.add(Path::new("some/code/base/A.java"), "a\n", Some("a\n"));
+  patch.add(Path::new("some/code/base/src/A.java"), "a\n", None);
+  // The unchanged file is not in the patch, and the files are sorted by (relative) path
+  assert_eq!(
+    patch.to_string(),
+    "diff --git a/src/A.java b/src/A.java
+deleted file mode 100644
+--- a/src/A.java
++++ /dev/null
+@@ -1 +0,0 @@
+-a
+diff --git a/src/B.java b/src/B.java
+--- a/src/B.java
++++ b/src/B.java
+@@ -1 +1 @@
+-b
++c
+"
+  );
+}
+
+#[test]
+fn test_patch_composes_the_changes() {
+  let mut patch = Patch::new(&Codebase::new(Path::new("some/code/base"), &[], &[]));
+  patch.add(Path::new("some/code/base/A.java"), "a\n", Some("b\n"));
+  patch.add(Path::new("some/code/base/A.java"), "b\n", Some("c\n"));
+  patch.add(Path::new("some/code/base/B.java"), "a\n", Some("b\n"));
+  patch.add(Path::new("some/code/base/B.java"), "b\n", Some("a\n"));
+  // The patch goes from the first original content to the last updated one (B.java is back to its original content)
+  assert_eq!(
+    patch.to_string(),
+    "diff --git a/A.java b/A.java
+--- a/A.java
++++ b/A.java
+@@ -1 +1 @@
+-a
++c
+"
+  );
+}
+
+/// The deletion of an executable file is reported with its mode.
+#[cfg(unix)]
+#[test]
+fn test_patch_deleted_executable_file() {
+  use std::{fs, os::unix::fs::PermissionsExt};
+  use tempdir::TempDir;
+
+  let temp_dir = TempDir::new_in(".", "patch").unwrap();
+  let script = temp_dir.path().join("run.sh");
+  fs::write(&script, "echo a\n").unwrap();
+  fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+  let mut patch = Patch::new(&Codebase::new(temp_dir.path(), &[], &[]));
+  patch.add(&script, "echo a\n", None);
+  assert!(patch
+    .to_string()
+    .starts_with("diff --git a/run.sh b/run.sh\ndeleted file mode 100755\n"));
+}
@@ -0,0 +1,111 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use super::remove_references;
+
+const PROJECT: &str = "\
+/* Begin PBXBuildFile section */
+\t\tA10000000000000000000001 /* Foo.framework in Frameworks */ = {isa = PBXBuildFile; fileRef = A20000000000000000000001 /* Foo.framework */; };
+\t\tA10000000000000000000002 /* Bar.framework in Frameworks */ = {isa = PBXBuildFile; fileRef = A20000000000000000000002 /* Bar.framework */; };
+/* End PBXBuildFile section */
+
+/* Begin PBXFileReference section */
+\t\tA20000000000000000000001 /* Foo.framework */ = {isa = PBXFileReference; path = Foo.framework; sourceTree = \"<group>\"; };
+\t\tA20000000000000000000002 /* Bar.framework */ = {isa = PBXFileReference; path = Bar.framework; sourceTree = \"<group>\"; };
+/* End PBXFileReference section */
+
+/* Begin PBXFrameworksBuildPhase section */
+\t\tA30000000000000000000001 /* Frameworks */ = {
+\t\t\tisa = PBXFrameworksBuildPhase;
+\t\t\tfiles = (
+\t\t\t\tA10000000000000000000001 /* Foo.framework in Frameworks */,
+\t\t\t\tA10000000000000000000002 /* Bar.framework in Frameworks */,
+\t\t\t);
+\t\t};
+/* End PBXFrameworksBuildPhase section */
+";
+
+#[test]
+fn test_remove_framework_reference() {
+  let expected = "\
+/* Begin PBXBuildFile section */
+\t\tA10000000000000000000002 /* Bar.framework in Frameworks */ = {isa = PBXBuildFile; fileRef = A20000000000000000000002 /* Bar.framework */; };
+/* End PBXBuildFile section */
+
+/* Begin PBXFileReference section */
+\t\tA20000000000000000000002 /* Bar.framework */ = {isa = PBXFileReference; path = Bar.framework; sourceTree = \"<group>\"; };
+/* End PBXFileReference section */
+
+/* Begin PBXFrameworksBuildPhase section */
+\t\tA30000000000000000000001 /* Frameworks */ = {
+\t\t\tisa = PBXFrameworksBuildPhase;
+\t\t\tfiles = (
+\t\t\t\tA10000000000000000000002 /* Bar.framework in Frameworks */,
+\t\t\t);
+\t\t};
+/* End PBXFrameworksBuildPhase section */
+";
+  assert_eq!(
+    remove_references(PROJECT, &["Foo.framework".to_string()]),
+    expected
+  );
+}
+
+#[test]
+fn test_remove_unknown_reference() {
+  assert_eq!(
+    remove_references(PROJECT, &["Baz.framework".to_string()]),
+    PROJECT
+  );
+}
+
+#[test]
+fn test_remove_reference_preserves_line_endings() {
+  let project = PROJECT.replace('\n', "\r\n");
+  let result = remove_references(&project, &["Bar.framework".to_string()]);
+  assert!(!result.contains("Bar.framework"));
+  assert_eq!(result.matches("\r\n").count(), result.matches('\n').count());
+  assert_eq!(result.lines().count(), PROJECT.lines().count() - 3);
+}
+
+#[test]
+fn test_remove_swift_package_reference() {
+  let project = "\
+\t\tA10000000000000000000001 /* FooSDK in Frameworks */ = {isa = PBXBuildFile; productRef = A40000000000000000000001 /* FooSDK */; };
+\t\tA50000000000000000000001 /* XCRemoteSwiftPackageReference \"foo-sdk\" */ = {
+\t\t\tisa = XCRemoteSwiftPackageReference;
+\t\t\trequirement = {
+\t\t\t\tkind = upToNextMajorVersion;
+\t\t\t};
+\t\t};
+\t\tA40000000000000000000001 /* FooSDK */ = {
+\t\t\tisa = XCSwiftPackageProductDependency;
+\t\t\tpackage = A50000000000000000000001 /* XCRemoteSwiftPackageReference \"foo-sdk\" */;
+\t\t\tproductName = FooSDK;
+\t\t};
+\t\tA40000000000000000000002 /* Bar */ = {
+\t\t\tisa = XCSwiftPackageProductDependency;
+\t\t\tproductName = Bar;
+\t\t};
+";
+  let expected = "\
+\t\tA40000000000000000000002 /* Bar */ = {
+\t\t\tisa = XCSwiftPackageProductDependency;
+\t\t\tproductName = Bar;
+\t\t};
+";
+  assert_eq!(
+    remove_references(project, &["foo-sdk".to_string()]),
+    expected
+  );
+}
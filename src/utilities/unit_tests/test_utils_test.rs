@@ -0,0 +1,98 @@
+/*
+Copyright (c) 2023 Uber Technologies, Inc.
+
+ <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ except in compliance with the License. You may obtain a copy of the License at
+ <p>http://www.apache.org/licenses/LICENSE-2.0
+
+ <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ express or implied. See the License for the specific language governing permissions and
+ limitations under the License.
+*/
+
+use super::{assert_matches, assert_no_change, assert_rewrite, colored_diff};
+
+static RULES_TOML: &str = r#"
+[[rules]]
+name = "replace_is_enabled"
+query = """(
+(method_invocation name: (_) @name arguments: (argument_list (identifier) @flag)) @mi
+(#eq? @name "isEnabled")
+(#eq? @flag "@stale_flag_name")
+)"""
+replace_node = "mi"
+replace = "true"
+holes = ["stale_flag_name"]
+"#;
+
+static EDGES_TOML: &str = r#"
+[[edges]]
+scope = "Parent"
+from = "replace_is_enabled"
+to = ["boolean_literal_cleanup"]
+"#;
+
+#[test]
+fn test_assert_rewrite() {
+  assert_rewrite(
+    "java",
+    RULES_TOML,
+    EDGES_TOML,
+    "class A {\n  void m() {\n    if (isEnabled(STALE_FLAG)) {\n      foo();\n    }\n  }\n}\n",
+    "class A {\n  void m() {\n    foo();\n  }\n}\n",
+    &[("stale_flag_name", "STALE_FLAG")],
+  );
+}
+
+#[test]
+#[should_panic(expected = "The rewritten code differs from the expected code")]
+fn test_assert_rewrite_mismatch() {
+  assert_rewrite(
+    "java",
+    RULES_TOML,
+    "",
+    "class A {\n  boolean b = isEnabled(STALE_FLAG);\n}\n",
+    "class A {\n  boolean b = false;\n}\n",
+    &[("stale_flag_name", "STALE_FLAG")],
+  );
+}
+
+#[test]
+fn test_assert_no_change() {
+  assert_no_change(
+    "java",
+    RULES_TOML,
+    "",
+    "class A {\n  boolean b = isEnabled(OTHER_FLAG);\n}\n",
+    &[("stale_flag_name", "STALE_FLAG")],
+  );
+}
+
+#[test]
+fn test_assert_matches() {
+  let rules_toml = r#"
+[[rules]]
+name = "find_is_enabled"
+query = "((method_invocation name: (_) @name) @mi (#eq? @name \"isEnabled\"))"
+"#;
+  assert_matches(
+    "java",
+    rules_toml,
+    "",
+    "class A {\n  boolean a = isEnabled(X);\n  boolean b = isEnabled(Y);\n}\n",
+    &[],
+    2,
+  );
+  assert_matches("java", rules_toml, "", "class A {}\n", &[], 0);
+}
+
+#[test]
+fn test_colored_diff() {
+  let diff = colored_diff("a\nb\n", "a\nc\n");
+  // The header of the diff is skipped
+  assert!(!diff.contains("snippet"));
+  for line in ["@@ -1,2 +1,2 @@", " a", "-b", "+c"] {
+    assert!(diff.contains(line));
+  }
+}
@@ -12,11 +12,20 @@ Copyright (c) 2023 Uber Technologies, Inc.
 */
 use std::collections::HashMap;
 
-use tree_sitter::Query;
+use tree_sitter::{InputEdit, Point, Query};
 
 use crate::{
-  models::{capture_group_patterns::CGPattern, default_configs::JAVA, language::PiranhaLanguage},
-  utilities::{tree_sitter_utilities::get_all_matches_for_query, Instantiate},
+  models::{
+    capture_group_patterns::CGPattern, default_configs::JAVA, edit::Edit, language::PiranhaLanguage,
+  },
+  utilities::{
+    tree_sitter_utilities::{
+      differ_in_whitespace_only, get_all_matches_for_query, get_changed_range,
+      get_non_overlapping_matches_for_query, get_tree_sitter_edit, normalize_snippet, shift_range,
+      LineMap, Overlap, WhitespaceSensitivity,
+    },
+    Instantiate,
+  },
 };
 
 #[test]
@@ -125,6 +134,49 @@ fn test_get_all_matches_for_query_negative() {
   assert!(matches.is_empty());
 }
 
+/// The nested invocations (e.g. `foo(foo(1))`) overlap the outer ones, hence only the outer ones are returned.
+#[test]
+fn test_get_non_overlapping_matches_for_query() {
+  let source_code = "class Test {
+        void bar() {
+          foo(foo(1));
+          foo(2);
+        }
+      }";
+  let language = PiranhaLanguage::from(JAVA);
+  let query = Query::new(
+    *language.language(),
+    r#"(
+      (method_invocation name: (_) @name) @invocation
+      (#eq? @name "foo")
+      )"#,
+  )
+  .unwrap();
+  let mut parser = language.parser();
+  let ast = parser
+    .parse(source_code, None)
+    .expect("Could not parse code");
+
+  let matches = get_non_overlapping_matches_for_query(&ast.root_node(), source_code, &query, true);
+  assert_eq!(
+    matches
+      .iter()
+      .map(|m| m.matched_string().as_str())
+      .collect::<Vec<_>>(),
+    vec!["foo(foo(1))", "foo(2)"]
+  );
+  // All the matches (including the nested one) are returned from the bottom to the top
+  let all_matches = get_all_matches_for_query(
+    &ast.root_node(),
+    source_code.to_string(),
+    &query,
+    true,
+    None,
+    None,
+  );
+  assert_eq!(all_matches.len(), 3);
+}
+
 #[test]
 fn test_instantiate() {
   let substitutions = HashMap::from([
@@ -138,3 +190,177 @@ fn test_instantiate() {
     "isFlagTreated foo bar true"
   )
 }
+
+fn normalize_java(snippet: &str, sensitivity: WhitespaceSensitivity) -> String {
+  let language = PiranhaLanguage::from(JAVA);
+  let mut parser = language.parser();
+  normalize_snippet(snippet, &mut parser, language.comment_nodes(), sensitivity)
+}
+
+#[test]
+fn test_normalize_snippet_insensitive() {
+  let insensitive = WhitespaceSensitivity::Insensitive;
+  assert_eq!(
+    normalize_java("foo( \"a  b\" ,x);", insensitive),
+    normalize_java("foo(\"a  b\", x);", insensitive)
+  );
+  assert_eq!(
+    normalize_java("if (x) {\r\n  foo();\r\n}", insensitive),
+    normalize_java("if (x) { foo(); }", insensitive)
+  );
+  // Whitespace inside string literals is meaningful
+  assert_ne!(
+    normalize_java("foo(\"a b\", x);", insensitive),
+    normalize_java("foo(\"a  b\", x);", insensitive)
+  );
+  // Whitespace inside comments is retained
+  assert_ne!(
+    normalize_java("foo(); /* a b */", insensitive),
+    normalize_java("foo(); /* a  b */", insensitive)
+  );
+}
+
+#[test]
+fn test_normalize_snippet_line_endings() {
+  let line_endings = WhitespaceSensitivity::LineEndings;
+  assert_eq!(
+    normalize_java("if (x) {\r\n  foo();\r\n}", line_endings),
+    normalize_java("if (x) {\n  foo();\n}", line_endings)
+  );
+  assert_ne!(
+    normalize_java("if (x) { foo(); }", line_endings),
+    normalize_java("if (x) {\n  foo();\n}", line_endings)
+  );
+}
+
+#[test]
+fn test_normalize_snippet_exact() {
+  let exact = WhitespaceSensitivity::Exact;
+  assert_ne!(
+    normalize_java("foo(x);\r\n", exact),
+    normalize_java("foo(x);\n", exact)
+  );
+  assert_eq!(normalize_java("foo( x );", exact), "foo( x );");
+}
+
+#[test]
+fn test_differ_in_whitespace_only() {
+  assert!(differ_in_whitespace_only("foo( x );\r\n", "foo(x);"));
+  assert!(differ_in_whitespace_only("\"a b\"", "\"ab\""));
+  assert!(!differ_in_whitespace_only("foo(x);", "foo(y);"));
+  // Such snippets are never equivalent, w.r.t. any sensitivity
+  for sensitivity in [
+    WhitespaceSensitivity::Exact,
+    WhitespaceSensitivity::LineEndings,
+    WhitespaceSensitivity::Insensitive,
+  ] {
+    assert_ne!(
+      normalize_java("foo(x);", sensitivity),
+      normalize_java("foo(y);", sensitivity)
+    );
+  }
+}
+
+fn input_edit(start_byte: usize, old_end_byte: usize, new_end_byte: usize) -> InputEdit {
+  let point = Point::new(0, 0);
+  InputEdit {
+    start_byte,
+    old_end_byte,
+    new_end_byte,
+    start_position: point,
+    old_end_position: point,
+    new_end_position: point,
+  }
+}
+
+/// The positions of the edit of a file with `\r\n` line endings are the ones tree-sitter computes.
+#[test]
+fn test_get_tree_sitter_edit_with_crlf_line_endings() {
+  let code = "class A {\r\n  int a = 1;\r\n}\r\n";
+  let mut parser = PiranhaLanguage::from(JAVA).parser();
+  let mut ast = parser.parse(code, None).unwrap();
+  let start_byte = code.find('1').unwrap();
+  let edit = Edit::replace_offsets(code, start_byte, start_byte + 1, "2;\r\n  int b = 3");
+  let (new_code, ts_edit) = get_tree_sitter_edit(code.to_string(), &edit);
+  assert_eq!(
+    new_code,
+    "class A {\r\n  int a = 2;\r\n  int b = 3;\r\n}\r\n"
+  );
+  assert_eq!(ts_edit.start_position, Point { row: 1, column: 10 });
+  assert_eq!(ts_edit.old_end_position, Point { row: 1, column: 11 });
+  assert_eq!(ts_edit.new_end_position, Point { row: 2, column: 11 });
+
+  // The incrementally re-parsed tree is the one of the new code
+  ast.edit(&ts_edit);
+  let reparsed = parser.parse(&new_code, Some(&ast)).unwrap();
+  let parsed = parser.parse(&new_code, None).unwrap();
+  assert_eq!(reparsed.root_node().to_sexp(), parsed.root_node().to_sexp());
+  assert_eq!(
+    reparsed.root_node().end_position(),
+    parsed.root_node().end_position()
+  );
+}
+
+#[test]
+fn test_get_changed_range() {
+  assert_eq!(get_changed_range(&[]), None);
+  assert_eq!(get_changed_range(&[input_edit(10, 20, 15)]), Some((10, 15)));
+  // Second edit is after the first edit
+  assert_eq!(
+    get_changed_range(&[input_edit(10, 20, 15), input_edit(30, 40, 30)]),
+    Some((10, 30))
+  );
+  // Second edit is before the first edit (which gets shifted)
+  assert_eq!(
+    get_changed_range(&[input_edit(30, 40, 30), input_edit(10, 20, 15)]),
+    Some((10, 25))
+  );
+  // Second edit (e.g. deleting the trailing comma) overlaps with the first edit
+  assert_eq!(
+    get_changed_range(&[input_edit(10, 20, 10), input_edit(5, 11, 5)]),
+    Some((5, 5))
+  );
+  // Second edit (e.g. deleting the enclosing statement) encloses the first edit
+  assert_eq!(
+    get_changed_range(&[input_edit(10, 20, 12), input_edit(0, 50, 0)]),
+    Some((0, 0))
+  );
+}
+
+#[test]
+fn test_shift_range() {
+  let edit = input_edit(10, 20, 15);
+  for overlap in [Overlap::Drop, Overlap::Clamp, Overlap::Extend] {
+    // The ranges before the edit are unchanged, the ones after it are shifted
+    assert_eq!(shift_range((0, 10), &edit, overlap), Some((0, 10)));
+    assert_eq!(shift_range((20, 30), &edit, overlap), Some((15, 25)));
+  }
+  // The ranges overlapping the edit
+  assert_eq!(shift_range((5, 12), &edit, Overlap::Drop), None);
+  assert_eq!(shift_range((5, 25), &edit, Overlap::Clamp), Some((5, 20)));
+  assert_eq!(shift_range((12, 18), &edit, Overlap::Clamp), Some((12, 15)));
+  assert_eq!(shift_range((18, 25), &edit, Overlap::Clamp), Some((15, 20)));
+  assert_eq!(
+    shift_range((12, 18), &edit, Overlap::Extend),
+    Some((10, 15))
+  );
+  assert_eq!(
+    shift_range((12, 25), &edit, Overlap::Extend),
+    Some((10, 20))
+  );
+}
+
+#[test]
+fn test_line_map() {
+  let line_map = LineMap::new("ab\ncd\n\nef");
+  assert_eq!(line_map.offset(0, 0), Some(0));
+  assert_eq!(line_map.offset(0, 1), Some(1));
+  // The end of a line is its line break
+  assert_eq!(line_map.offset(0, 2), Some(2));
+  assert_eq!(line_map.offset(0, 3), None);
+  assert_eq!(line_map.offset(1, 1), Some(4));
+  assert_eq!(line_map.offset(2, 0), Some(6));
+  assert_eq!(line_map.offset(2, 1), None);
+  assert_eq!(line_map.offset(3, 2), Some(9));
+  assert_eq!(line_map.offset(4, 0), None);
+}
@@ -13,9 +13,15 @@ Copyright (c) 2023 Uber Technologies, Inc.
 
 use crate::utilities::find_file;
 use serde_derive::Deserialize;
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
+use tempdir::TempDir;
 
-use super::{read_file, read_toml};
+use super::{
+  capture_all_nodes, delete_consecutive_new_lines, exclude_split_line_ending, get_placeholder_tags,
+  get_query_significant_characters, get_tag_references, has_long_lines, instantiate_query,
+  instantiate_replacement, is_bound_by, is_likely_misspelling, is_protected_header_line, read_file,
+  read_toml, substitute_tags_regex, truncate_snippet, Instantiate,
+};
 
 #[derive(Deserialize, Default)]
 struct TestStruct {
@@ -50,6 +56,16 @@ fn test_read_toml_default() {
   assert!(result.name.eq(""));
 }
 
+#[test]
+#[should_panic(expected = "Could not read file")]
+fn test_read_toml_invalid() {
+  // Only a missing file defaults, an invalid one is not silently ignored
+  let temp_dir = TempDir::new_in(".", "read_toml").unwrap();
+  let path_to_test_file = temp_dir.path().join("invalid.toml");
+  std::fs::write(&path_to_test_file, "name = 42").unwrap();
+  let _: TestStruct = read_toml(&path_to_test_file, true);
+}
+
 #[test]
 fn test_find_file_positive() {
   let project_root =
@@ -66,3 +82,181 @@ fn test_find_file_negative() {
   let f = find_file(&project_root, "another_sample.toml.toml");
   assert!(f.is_file());
 }
+
+#[test]
+fn test_delete_consecutive_new_lines() {
+  let regex = regex::Regex::new(r"\n(\s*\n)+(\s*\n)").unwrap();
+  for code in [
+    "a\n\nb",
+    "a\n\n\nb",
+    "a  \n \n\t\n  \n    b\n",
+    "a\n\n\n\n\n",
+    "\n\n\na\r\n\r\n\r\nb",
+    "a \u{a0}\n \n\n b \n\n",
+  ] {
+    assert_eq!(
+      delete_consecutive_new_lines(code),
+      regex.replace_all(code, "\n${2}"),
+      "{code:?}"
+    );
+  }
+  // The `\r\n` line endings are kept
+  assert_eq!(
+    delete_consecutive_new_lines("a\r\n\r\n\r\n\r\nb\r\n"),
+    "a\r\n\r\nb\r\n"
+  );
+}
+
+#[test]
+fn test_exclude_split_line_ending() {
+  let code = "// a\r\nb\r";
+  assert_eq!(exclude_split_line_ending(code, 5), 4);
+  assert_eq!(exclude_split_line_ending(code, 4), 4);
+  assert_eq!(exclude_split_line_ending(code, 6), 6);
+  assert_eq!(exclude_split_line_ending(code, code.len()), code.len());
+}
+
+#[test]
+fn test_has_long_lines() {
+  assert!(has_long_lines("a\nbcd\ne", 2));
+  assert!(!has_long_lines("a\nbc\ne", 2));
+}
+
+#[test]
+fn test_is_protected_header_line() {
+  assert!(is_protected_header_line("#!/usr/bin/env python", 0));
+  assert!(!is_protected_header_line("#!/usr/bin/env python", 1));
+  assert!(is_protected_header_line("# -*- coding: utf-8 -*-", 0));
+  assert!(is_protected_header_line(
+    "# vim: set fileencoding=latin-1 :",
+    1
+  ));
+  assert!(!is_protected_header_line("# -*- coding: utf-8 -*-", 2));
+  assert!(!is_protected_header_line("# Some comment", 0));
+}
+
+#[test]
+fn test_truncate_snippet() {
+  assert_eq!(truncate_snippet("abcdef", 3), "abc...");
+  assert_eq!(truncate_snippet("abc", 3), "abc");
+  assert_eq!(truncate_snippet("äöüß", 2), "äö...");
+}
+
+#[test]
+fn test_get_tag_references() {
+  assert_eq!(
+    get_tag_references("assertEquals(@expected, @actual.get()); // foo@bar."),
+    vec!["expected", "actual.get", "bar"]
+  );
+}
+
+#[test]
+fn test_is_likely_misspelling() {
+  assert!(is_likely_misspelling("actaul", "actual"));
+  assert!(is_likely_misspelling("expeted", "expected"));
+  assert!(is_likely_misspelling("flg", "flag"));
+  assert!(!is_likely_misspelling("actual", "actual"));
+  assert!(!is_likely_misspelling("Override", "override"));
+  assert!(!is_likely_misspelling("b", "a"));
+  assert!(!is_likely_misspelling("message", "expected"));
+}
+
+#[test]
+fn test_substitute_tags_regex() {
+  let substitutions = HashMap::from([
+    ("flag".to_string(), "FLAG_STALE".to_string()),
+    ("n".to_string(), "2023".to_string()),
+  ]);
+  assert_eq!(
+    substitute_tags_regex(
+      r"%{flag:^FLAG_(\w+)$} %{flag} %{n:\d{2}$} %{flag:^X} %{unknown} @flag %{",
+      &substitutions
+    )
+    .unwrap(),
+    "STALE FLAG_STALE 23  %{unknown} @flag %{"
+  );
+  assert!(substitute_tags_regex("%{flag:(}", &substitutions).is_err());
+}
+
+#[test]
+fn test_instantiate_single_pass() {
+  let substitutions = HashMap::from([
+    ("a".to_string(), "@b".to_string()),
+    ("b".to_string(), "x".to_string()),
+    ("a.b".to_string(), "y".to_string()),
+  ]);
+  assert_eq!(
+    "@a @b @a.b @c".to_string().instantiate(&substitutions),
+    "@b x y @c"
+  );
+}
+
+#[test]
+fn test_instantiate_replacement() {
+  let substitutions = HashMap::from([
+    ("flag".to_string(), "FLAG_STALE".to_string()),
+    ("code".to_string(), "format(\"%{flag} @flag\")".to_string()),
+  ]);
+  // The substituted code is not substituted again
+  assert_eq!(
+    instantiate_replacement("%{flag:^FLAG_(\\w+)$}(@code, %{code})", &substitutions).unwrap(),
+    "STALE(format(\"%{flag} @flag\"), format(\"%{flag} @flag\"))"
+  );
+  assert_eq!(
+    get_placeholder_tags("%{flag:^FLAG_(\\w+)$} %{code} @other %{"),
+    vec!["flag", "code"]
+  );
+}
+
+#[test]
+fn test_get_query_significant_characters() {
+  assert!(get_query_significant_characters("STALE_FLAG").is_empty());
+  assert!(get_query_significant_characters("\"say (hi)\"").is_empty());
+  assert_eq!(
+    get_query_significant_characters("ENABLE_X\") (other_thing"),
+    vec!['"', ')', '(']
+  );
+  assert_eq!(get_query_significant_characters("@x #y @z"), vec!['@', '#']);
+}
+
+#[test]
+fn test_capture_all_nodes() {
+  assert_eq!(
+    capture_all_nodes(
+      "(return_statement (method_invocation name: (identifier) @name arguments: (argument_list (identifier) (_))))"
+    ),
+    "(return_statement (method_invocation name: (identifier) @name arguments: (argument_list (identifier) @__identifier (_)) @__argument_list) @__method_invocation) @__return_statement"
+  );
+  // The duplicate kinds are suffixed, and the captures of the query are left as they are
+  assert_eq!(
+    capture_all_nodes("((binary_expression (identifier) (identifier)+ \"(x)\") @identifier ; (comment)\n)"),
+    "((binary_expression (identifier) @__identifier (identifier)+ @__identifier_2 \"(x)\") @identifier ; (comment)\n)"
+  );
+  // The predicates are not captured
+  assert_eq!(
+    capture_all_nodes("((identifier) (#eq? @identifier \"x\"))"),
+    "((identifier) @__identifier (#eq? @identifier \"x\"))"
+  );
+}
+
+#[test]
+fn test_instantiate_query() {
+  let substitutions = HashMap::from([("flag".to_string(), "\"A\\B\"\n".to_string())]);
+  // The quotes and backslashes of the value are escaped within the string literals only (not within the comments)
+  assert_eq!(
+    instantiate_query(
+      r#"(#eq? @x "@flag") ; "@flag
+(#eq? @y @flag)"#,
+      &substitutions
+    ),
+    r#"(#eq? @x "\"A\\B\"\n") ; ""A\B"\n
+(#eq? @y "A\B"\n)"#
+  );
+}
+
+#[test]
+fn test_is_bound_by() {
+  assert!(is_bound_by("arg.isEmpty", |t| t == "arg"));
+  assert!(is_bound_by("nested.statements", |t| t == "nested.statements"));
+  assert!(!is_bound_by("arguments", |t| t == "arg"));
+}
@@ -0,0 +1,91 @@
+// Copyright (c) 2023 Uber Technologies, Inc.
+//
+// <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+// except in compliance with the License. You may obtain a copy of the License at
+// <p>http://www.apache.org/licenses/LICENSE-2.0
+//
+// <p>Unless required by applicable law or agreed to in writing, software distributed under the
+// License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+// express or implied. See the License for the specific language governing permissions and
+// limitations under the License.
+
+using System;
+
+namespace Uber.Piranha
+{
+    class XPFlagCleanerPositiveCases
+    {
+        enum TestExperimentName
+        {
+            SOME_FLAG
+        }
+
+        private XPTest experimentation;
+
+        private bool tBool = false;
+
+        public void ConditionalContainsStaleFlag()
+        {
+            Console.WriteLine("Hi World");
+        }
+
+        public void ConditionalWithElseContainsStaleFlag()
+        {
+            Console.WriteLine("Hi world 1");
+
+            Console.WriteLine("Hello World 2");
+        }
+
+        public void ComplexConditionalContainsStaleFlag(bool tBool)
+        {
+            Console.WriteLine("Hello World");
+        }
+
+        public void AssignmentsContainingStaleFlag()
+        {
+            tBool = false;
+
+            tBool = tBool;
+        }
+
+        public bool ReturnContainsStaleFlag()
+        {
+            return false;
+        }
+
+        public void CondexpContainsStaleFlag()
+        {
+            tBool = false;
+        }
+
+        public void MiscXpApisContainingStaleFlag()
+        {
+            if ((tBool || true)) {}
+        }
+
+        public int ReturnWithinIf(int x)
+        {
+            if (x == 0)
+            {
+                return 75;
+            }
+            return 100;
+        }
+
+        public void InlineField()
+        {
+        }
+
+        public int OrCompoundedWithNot(bool extraToggle)
+        {
+            if (extraToggle)
+            {
+                return 0;
+            }
+            else
+            {
+                return 1;
+            }
+        }
+    }
+}
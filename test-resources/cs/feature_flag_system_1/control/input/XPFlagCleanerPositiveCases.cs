@@ -0,0 +1,135 @@
+// Copyright (c) 2023 Uber Technologies, Inc.
+//
+// <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+// except in compliance with the License. You may obtain a copy of the License at
+// <p>http://www.apache.org/licenses/LICENSE-2.0
+//
+// <p>Unless required by applicable law or agreed to in writing, software distributed under the
+// License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+// express or implied. See the License for the specific language governing permissions and
+// limitations under the License.
+
+using System;
+
+namespace Uber.Piranha
+{
+    class XPFlagCleanerPositiveCases
+    {
+        enum TestExperimentName
+        {
+            SOME_FLAG,
+            // Some stale enum
+            STALE_FLAG
+        }
+
+        private XPTest experimentation;
+
+        private bool tBool = false;
+
+        private static readonly bool isStaleFlagEnabled = XPTest.Shared.IsToggleEnabled(TestExperimentName.STALE_FLAG);
+
+        public void ConditionalContainsStaleFlag()
+        {
+            if (experimentation.IsToggleEnabled(TestExperimentName.STALE_FLAG))
+            {
+                Console.WriteLine("Hello World");
+            }
+            if (experimentation.IsToggleEnabled(TestExperimentName.STALE_FLAG) == false)
+            {
+                Console.WriteLine("Hi World");
+            }
+        }
+
+        public void ConditionalWithElseContainsStaleFlag()
+        {
+            if (experimentation.IsToggleEnabled(TestExperimentName.STALE_FLAG) == true)
+            {
+                Console.WriteLine("Hello World 1");
+            }
+            else
+            {
+                Console.WriteLine("Hi world 1");
+            }
+
+            if ((experimentation.IsToggleEnabled(TestExperimentName.STALE_FLAG)) != true)
+            {
+                Console.WriteLine("Hello World 2");
+            }
+            else
+            {
+                Console.WriteLine("Hi world 2");
+            }
+        }
+
+        public void ComplexConditionalContainsStaleFlag(bool tBool)
+        {
+            if (tBool || (true || experimentation.IsToggleEnabled(TestExperimentName.STALE_FLAG)))
+            {
+                Console.WriteLine("Hello World");
+            }
+            else
+            {
+                Console.WriteLine("Hi world");
+            }
+        }
+
+        public void AssignmentsContainingStaleFlag()
+        {
+            tBool = experimentation.IsToggleEnabled(TestExperimentName.STALE_FLAG) && true;
+
+            tBool = experimentation.IsToggleEnabled(TestExperimentName.STALE_FLAG) || tBool;
+        }
+
+        public bool ReturnContainsStaleFlag()
+        {
+            return experimentation.IsToggleEnabled(TestExperimentName.STALE_FLAG);
+        }
+
+        public void CondexpContainsStaleFlag()
+        {
+            tBool = experimentation.IsToggleEnabled(TestExperimentName.STALE_FLAG) ? true : false;
+        }
+
+        public void MiscXpApisContainingStaleFlag()
+        {
+            experimentation.PutToggleEnabled(TestExperimentName.STALE_FLAG);
+
+            if (experimentation.IsToggleDisabled(TestExperimentName.STALE_FLAG) && (tBool || true)) {}
+        }
+
+        public int ReturnWithinIf(int x)
+        {
+            if (x == 0)
+            {
+                bool enabled = experimentation.IsToggleEnabled(TestExperimentName.STALE_FLAG);
+                if (enabled)
+                {
+                    Console.WriteLine();
+                    return 0;
+                }
+                return 75;
+            }
+            return 100;
+        }
+
+        public void InlineField()
+        {
+            if (isStaleFlagEnabled)
+            {
+                Console.WriteLine("Inlined field");
+            }
+        }
+
+        public int OrCompoundedWithNot(bool extraToggle)
+        {
+            if (extraToggle || !experimentation.IsToggleDisabled(TestExperimentName.STALE_FLAG))
+            {
+                return 0;
+            }
+            else
+            {
+                return 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+// Copyright (c) 2023 Uber Technologies, Inc.
+//
+// <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+// except in compliance with the License. You may obtain a copy of the License at
+// <p>http://www.apache.org/licenses/LICENSE-2.0
+//
+// <p>Unless required by applicable law or agreed to in writing, software distributed under the
+// License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+// express or implied. See the License for the specific language governing permissions and
+// limitations under the License.
+
+using System;
+
+namespace Uber.Piranha
+{
+    class XPFlagCleanerPositiveCases
+    {
+        enum TestExperimentName
+        {
+            SOME_FLAG,
+            // Some stale enum
+        }
+
+        private XPTest experimentation;
+
+        private bool tBool = false;
+
+        public void ConditionalContainsStaleFlag()
+        {
+            Console.WriteLine("Hello World");
+        }
+
+        public void ConditionalWithElseContainsStaleFlag()
+        {
+            Console.WriteLine("Hello World 1");
+
+            Console.WriteLine("Hi world 2");
+        }
+
+        public void ComplexConditionalContainsStaleFlag(bool tBool)
+        {
+            Console.WriteLine("Hello World");
+        }
+
+        public void AssignmentsContainingStaleFlag()
+        {
+            tBool = true;
+
+            tBool = true;
+        }
+
+        public bool ReturnContainsStaleFlag()
+        {
+            return true;
+        }
+
+        public void CondexpContainsStaleFlag()
+        {
+            tBool = true;
+        }
+
+        public void MiscXpApisContainingStaleFlag()
+        {
+        }
+
+        public int ReturnWithinIf(int x)
+        {
+            if (x == 0)
+            {
+                Console.WriteLine();
+                return 0;
+            }
+            return 100;
+        }
+
+        public void InlineField()
+        {
+            Console.WriteLine("Inlined field");
+        }
+
+        public int OrCompoundedWithNot(bool extraToggle)
+        {
+            return 0;
+        }
+    }
+}
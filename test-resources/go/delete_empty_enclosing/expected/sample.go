@@ -0,0 +1,32 @@
+package main
+
+import "fmt"
+
+func ifBody(enabled bool) {
+	
+	fmt.Println("after if")
+}
+
+func nestedBlocks(enabled bool) {
+	
+	fmt.Println("after for")
+}
+
+func ifElse(enabled bool) {
+	if enabled {
+		fmt.Println("enabled")
+	} else {
+		
+	}
+}
+
+func nonEmptyBlock(enabled bool) {
+	if enabled {
+		
+		fmt.Println("enabled")
+	}
+}
+
+func functionBody() {
+	
+}
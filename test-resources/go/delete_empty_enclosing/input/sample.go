@@ -0,0 +1,38 @@
+package main
+
+import "fmt"
+
+func ifBody(enabled bool) {
+	if enabled {
+		tracker.Track("if")
+	}
+	fmt.Println("after if")
+}
+
+func nestedBlocks(enabled bool) {
+	for enabled {
+		{
+			tracker.Track("nested")
+		}
+	}
+	fmt.Println("after for")
+}
+
+func ifElse(enabled bool) {
+	if enabled {
+		fmt.Println("enabled")
+	} else {
+		tracker.Track("else")
+	}
+}
+
+func nonEmptyBlock(enabled bool) {
+	if enabled {
+		tracker.Track("non empty")
+		fmt.Println("enabled")
+	}
+}
+
+func functionBody() {
+	tracker.Track("function")
+}
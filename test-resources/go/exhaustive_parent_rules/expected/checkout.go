@@ -0,0 +1,9 @@
+package checkout
+
+func isEnabled(isOn bool) bool {
+	return isOn
+}
+
+func isDisabled(isOff bool) bool {
+	return (isOff)
+}
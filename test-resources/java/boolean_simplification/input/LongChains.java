@@ -0,0 +1,76 @@
+/**
+ * Copyright (c) 2023 Uber Technologies, Inc.
+ *
+ * <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ * except in compliance with the License. You may obtain a copy of the License at
+ *
+ * <p>http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ * License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ * express or implied. See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+package com.uber.piranha;
+
+class LongChains {
+  boolean andChainFirst(boolean a, boolean b) {
+    return exp.isTreated(STALE_FLAG) && a && b;
+  }
+
+  boolean andChainMiddle(boolean a, boolean b, boolean c) {
+    return a && b && exp.isTreated(STALE_FLAG) && c;
+  }
+
+  boolean andChainLast(boolean a, boolean b) {
+    return a && b && exp.isTreated(STALE_FLAG);
+  }
+
+  void orChain(boolean a, boolean b) {
+    if (a || exp.isTreated(STALE_FLAG) || b) {
+      enabled();
+    } else {
+      disabled();
+    }
+  }
+
+  void orChainLast(boolean a, boolean b, boolean c) {
+    if (a || b || c || exp.isTreated(STALE_FLAG)) {
+      enabled();
+    }
+  }
+
+  boolean negatedAndChain(boolean a, boolean b) {
+    return a && !exp.isTreated(STALE_FLAG) && b;
+  }
+
+  boolean negatedAndChainLast(boolean a, boolean b, boolean c) {
+    return a && b && c && !exp.isTreated(STALE_FLAG);
+  }
+
+  void sideEffectsKept(boolean a) {
+    if (a && compute() || exp.isTreated(STALE_FLAG)) {
+      enabled();
+    }
+  }
+
+  void negatedOrChain(boolean a, boolean b, boolean c) {
+    if (a || !(exp.isTreated(STALE_FLAG)) || b || c) {
+      enabled();
+    }
+  }
+
+  // The casts and array accesses may throw, hence they are not dropped
+  void unsafeOperandsKept(Object o, boolean[] flags) {
+    if ((Boolean) o || exp.isTreated(STALE_FLAG)) {
+      enabled();
+    }
+    if (flags[0] || exp.isTreated(STALE_FLAG)) {
+      enabled();
+    }
+  }
+
+  boolean sideEffectsKeptInChain(boolean a) {
+    return compute() && a && !exp.isTreated(STALE_FLAG);
+  }
+}
@@ -0,0 +1,62 @@
+/*
+ * Copyright (c) 2023 Uber Technologies, Inc.
+ *
+ * <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ * except in compliance with the License. You may obtain a copy of the License at
+ *
+ * <p>http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ * License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ * express or implied. See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+package com.uber.piranha;
+
+class XPFlagCleanerPositiveCases {
+
+  // The calls to the flag API were manually replaced by `true`
+  public void conditional_contains_stale_flag() {
+    if (true) {
+      System.out.println("Hello World");
+    }
+  }
+
+  public void conditional_with_else_contains_stale_flag() {
+    if (true) {
+      System.out.println("Hello World");
+    } else {
+      System.out.println("Hi world");
+    }
+  }
+
+  public void complex_conditional_contains_stale_flag(boolean tBool) {
+    if (true || tBool) {
+      System.out.println("Hello World");
+    }
+  }
+
+  public void other_conditional_with_true_contains_stale_flag() {
+    if (!true) {
+      System.out.println("Hello World");
+    }
+    System.out.println("Hi world");
+  }
+
+  public int return_within_if_basic() {
+    if (true) {
+      return 20;
+    }
+    return 30;
+  }
+
+  public void condexp_contains_stale_flag() {
+    boolean x = true ? true : false;
+  }
+
+  public void unrelated_conditional(boolean tBool) {
+    if (tBool) {
+      System.out.println("Hello World");
+    }
+  }
+}
@@ -0,0 +1,33 @@
+package com.uber.piranha;
+
+class Sample {
+
+  void ifBody(boolean enabled) {
+    
+    System.out.println("after if");
+  }
+
+  void nestedBlocks(boolean enabled) {
+    
+    System.out.println("after while");
+  }
+
+  void ifElse(boolean enabled) {
+    if (enabled) {
+      System.out.println("enabled");
+    } else {
+      
+    }
+  }
+
+  void nonEmptyBlock(boolean enabled) {
+    if (enabled) {
+      
+      System.out.println("enabled");
+    }
+  }
+
+  void methodBody() {
+    
+  }
+}
@@ -0,0 +1,39 @@
+package com.uber.piranha;
+
+class Sample {
+
+  void ifBody(boolean enabled) {
+    if (enabled) {
+      tracker.track("if");
+    }
+    System.out.println("after if");
+  }
+
+  void nestedBlocks(boolean enabled) {
+    while (enabled) {
+      {
+        tracker.track("nested");
+      }
+    }
+    System.out.println("after while");
+  }
+
+  void ifElse(boolean enabled) {
+    if (enabled) {
+      System.out.println("enabled");
+    } else {
+      tracker.track("else");
+    }
+  }
+
+  void nonEmptyBlock(boolean enabled) {
+    if (enabled) {
+      tracker.track("non empty");
+      System.out.println("enabled");
+    }
+  }
+
+  void methodBody() {
+    tracker.track("method");
+  }
+}
@@ -0,0 +1,19 @@
+package com.uber.piranha;
+
+class Settings {
+  private int count;
+
+  Settings() {
+    this.count = 0;
+  }
+
+  void reset() {
+    count = 0;
+  }
+}
+
+class SettingsHelper {
+  static void configure(Settings settings) {
+    System.out.println("configured");
+  }
+}
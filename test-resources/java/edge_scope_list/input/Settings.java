@@ -0,0 +1,23 @@
+package com.uber.piranha;
+
+class Settings {
+  private boolean staleField = true;
+  private int count;
+
+  Settings() {
+    this.staleField = false;
+    this.count = 0;
+  }
+
+  void reset() {
+    staleField = true;
+    count = 0;
+  }
+}
+
+class SettingsHelper {
+  static void configure(Settings settings) {
+    settings.staleField = true;
+    System.out.println("configured");
+  }
+}
@@ -0,0 +1,50 @@
+/*
+ * Copyright (c) 2023 Uber Technologies, Inc.
+ *
+ * <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ * except in compliance with the License. You may obtain a copy of the License at
+ *
+ * <p>http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ * License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ * express or implied. See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+package com.uber.piranha;
+
+import java.util.Objects;
+import java.util.Optional;
+
+class Sample {
+
+  // The calls to the flag API were manually replaced by `true`
+  public void nested_wrappers() {
+    if (Optional.ofNullable(Boolean.valueOf(true)).orElse(false)) {
+      System.out.println("Hello World");
+    } else {
+      System.out.println("Hi world");
+    }
+  }
+
+  public void optional_of_get() {
+    if (!Optional.of(false).get()) {
+      System.out.println("Hello World");
+    }
+  }
+
+  public String require_non_null() {
+    return Objects.requireNonNull("value");
+  }
+
+  // The argument of `orElse` is evaluated, hence it is not dropped
+  public void side_effects() {
+    boolean x = Optional.ofNullable(true).orElse(compute());
+    boolean y = Boolean.valueOf(compute());
+  }
+
+  // A primitive literal has no members, hence the receivers are not folded
+  public boolean receivers(Object x) {
+    return Boolean.valueOf(true).equals(x) && Optional.of(1).get().hashCode() == 1;
+  }
+}
@@ -0,0 +1,9 @@
+class Sample {
+  void run(Experiments exp) {
+    foo();
+    bar();
+    if (isEnabled(FLAG_C)) {
+      baz();
+    }
+  }
+}
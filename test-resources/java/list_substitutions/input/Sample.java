@@ -0,0 +1,13 @@
+class Sample {
+  void run(Experiments exp) {
+    if (isEnabled(FLAG_A)) {
+      foo();
+    }
+    if (isOn(FLAG_B)) {
+      bar();
+    }
+    if (isEnabled(FLAG_C)) {
+      baz();
+    }
+  }
+}
@@ -0,0 +1,17 @@
+/**
+ * Copyright (c) 2023 Uber Technologies, Inc.
+ *
+ * <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ * except in compliance with the License. You may obtain a copy of the License at
+ *
+ * <p>http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ * License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ * express or implied. See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+class Minified { int m0() { return 0 + 0; } int m1() { return 0 + 1; } int m2() { return 0 + 2; } int m3() { return 0 + 3; } int m4() { return 0 + 4; } int m5() { return 0 + 5; } int m6() { return 0 + 6; } int m7() { return 0 + 7; } }
+
+
+
@@ -0,0 +1,6 @@
+class NoPackage {
+  void pay(Client client) {
+    client.legacyCharge(10);
+    client.legacyRefund(10);
+  }
+}
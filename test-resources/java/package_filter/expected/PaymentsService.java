@@ -0,0 +1,8 @@
+package com.uber.payments.core;
+
+class PaymentsService {
+  void pay(Client client) {
+    client.charge(10);
+    client.refund(10);
+  }
+}
@@ -0,0 +1,8 @@
+package com.uber.rides;
+
+class RidesService {
+  void pay(Client client) {
+    client.legacyCharge(10);
+    client.legacyRefund(10);
+  }
+}
@@ -0,0 +1,19 @@
+package com.uber.piranha;
+
+import java.util.List;
+
+class Sample {
+
+  private int count;
+
+  @Override
+  public String toString() {
+    return "Sample";
+  }
+
+  void bar(int a, List<String> names) {}
+
+  void qux(int a, int b) {}
+
+  void baz() {}
+}
@@ -0,0 +1,24 @@
+package com.uber.piranha;
+
+import com.uber.experiments.ExperimentEnabled;
+import java.util.List;
+
+@ExperimentEnabled("sample")
+class Sample {
+
+  @ExperimentEnabled
+  private int count;
+
+  @ExperimentEnabled(name = "foo", owner = "piranha")
+  @Override
+  public String toString() {
+    return "Sample";
+  }
+
+  void bar(@ExperimentEnabled int a, List<@ExperimentEnabled String> names) {}
+
+  void qux(int a, @ExperimentEnabled int b) {}
+
+  @com.uber.experiments.ExperimentEnabled("baz")
+  void baz() {}
+}
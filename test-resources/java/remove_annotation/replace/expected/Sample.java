@@ -0,0 +1,13 @@
+package com.uber.piranha;
+
+class Sample {
+
+  @Experiment("foo")
+  public void foo() {}
+
+  @Experiment(name = "bar")
+  public void bar() {}
+
+  @Experiment()
+  public void baz() {}
+}
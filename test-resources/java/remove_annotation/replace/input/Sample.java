@@ -0,0 +1,15 @@
+package com.uber.piranha;
+
+import com.uber.experiments.ExperimentEnabled;
+
+class Sample {
+
+  @ExperimentEnabled("foo")
+  public void foo() {}
+
+  @ExperimentEnabled(name = "bar")
+  public void bar() {}
+
+  @ExperimentEnabled
+  public void baz() {}
+}
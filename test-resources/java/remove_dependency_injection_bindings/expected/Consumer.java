@@ -0,0 +1,42 @@
+/**
+ * Copyright (c) 2023 Uber Technologies, Inc.
+ *
+ * <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ * except in compliance with the License. You may obtain a copy of the License at
+ *
+ * <p>http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ * License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ * express or implied. See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+package com.uber.piranha;
+
+import javax.inject.Inject;
+
+class Consumer {
+
+  @Inject StaleFlagHolder usedHolder;
+
+  private final OtherFlagHolder other;
+
+  @Inject
+  Consumer(OtherFlagHolder other) {
+    this.other = other;
+  }
+
+  boolean isEnabled() {
+    return usedHolder.isEnabled();
+  }
+}
+
+class UsedConsumer {
+
+  private final boolean enabled;
+
+  @Inject
+  UsedConsumer(StaleFlagHolder holder) {
+    this.enabled = holder.isEnabled();
+  }
+}
@@ -0,0 +1,39 @@
+/**
+ * Copyright (c) 2023 Uber Technologies, Inc.
+ *
+ * <p>Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file
+ * except in compliance with the License. You may obtain a copy of the License at
+ *
+ * <p>http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * <p>Unless required by applicable law or agreed to in writing, software distributed under the
+ * License is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either
+ * express or implied. See the License for the specific language governing permissions and
+ * limitations under the License.
+*/
+package com.uber.piranha;
+
+import dagger.Binds;
+import dagger.Module;
+import dagger.Provides;
+
+@Module(includes = {NetworkModule.class, StaleFlagHolder.class, StorageModule.class})
+abstract class FlagModule {
+
+  @Provides
+  @Singleton
+  static StaleFlagHolder provideStaleFlagHolder() {
+    return new StaleFlagHolder();
+  }
+
+  @Binds
+  abstract StaleFlagHolder bindStaleFlagHolder(StaleFlagHolderImpl impl);
+
+  @Provides
+  static OtherFlagHolder provideOtherFlagHolder() {
+    return new OtherFlagHolder();
+  }
+}
+
+@dagger.Module(subcomponents = StaleFlagHolder.class, includes = {NetworkModule.class})
+abstract class SubcomponentModule {}
@@ -0,0 +1,12 @@
+import com.google.common.collect.Lists;
+import com.example.StringUtils;
+
+class Sample {
+  List<String> names(String s) {
+    List<String> names = Lists.newArrayList();
+    if (StringUtils.isEmpty(s)) {
+      return names;
+    }
+    return names;
+  }
+}
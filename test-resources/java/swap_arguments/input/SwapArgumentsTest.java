@@ -0,0 +1,11 @@
+package com.uber.piranha;
+
+import static org.junit.Assert.assertEquals;
+
+class SwapArgumentsTest {
+
+  void testComputeValue() {
+    assertEquals(computeValue(), 42);
+    assertEquals("The computed value", computeValue(), 42);
+  }
+}
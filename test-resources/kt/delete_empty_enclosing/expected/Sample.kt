@@ -0,0 +1,28 @@
+package com.uber.piranha
+
+class Sample {
+
+    fun ifBody(enabled: Boolean) {
+        println("after if")
+    }
+
+    fun whileBody(enabled: Boolean) {
+        println("after while")
+    }
+
+    fun ifElse(enabled: Boolean) {
+        if (enabled) {
+            println("enabled")
+        } else {
+        }
+    }
+
+    fun nonEmptyBlock(enabled: Boolean) {
+        if (enabled) {
+            println("enabled")
+        }
+    }
+
+    fun functionBody() {
+    }
+}
@@ -0,0 +1,37 @@
+package com.uber.piranha
+
+class Sample {
+
+    fun ifBody(enabled: Boolean) {
+        if (enabled) {
+            tracker.track("if")
+        }
+        println("after if")
+    }
+
+    fun whileBody(enabled: Boolean) {
+        while (enabled) {
+            tracker.track("while")
+        }
+        println("after while")
+    }
+
+    fun ifElse(enabled: Boolean) {
+        if (enabled) {
+            println("enabled")
+        } else {
+            tracker.track("else")
+        }
+    }
+
+    fun nonEmptyBlock(enabled: Boolean) {
+        if (enabled) {
+            tracker.track("non empty")
+            println("enabled")
+        }
+    }
+
+    fun functionBody() {
+        tracker.track("function")
+    }
+}
@@ -0,0 +1,3 @@
+package com.uber.piranha
+
+typealias Callback = () -> Unit
@@ -0,0 +1,11 @@
+package com.uber.piranha
+
+import com.uber.piranha.middle.Helper
+
+class First {
+    val unused = 0
+}
+
+object Last {
+    fun foo() {}
+}
@@ -0,0 +1,5 @@
+package com.uber.piranha
+
+typealias Callback = () -> Unit
+
+class Middle
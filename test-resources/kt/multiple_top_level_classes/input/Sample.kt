@@ -0,0 +1,15 @@
+package com.uber.piranha
+
+import com.uber.piranha.middle.Helper
+
+class First {
+    val unused = 0
+}
+
+class Middle {
+    val helper = Helper()
+}
+
+object Last {
+    fun foo() {}
+}
@@ -0,0 +1,16 @@
+package com.uber.piranha
+
+import java.util.List
+
+class Sample {
+
+    private val count: Int = 0
+
+    override fun toString(): String {
+        return "Sample"
+    }
+
+    fun bar(a: Int, names: List<String>) {}
+
+    fun qux(a: Int, b: Int) {}
+}
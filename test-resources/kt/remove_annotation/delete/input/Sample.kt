@@ -0,0 +1,20 @@
+package com.uber.piranha
+
+import com.uber.experiments.ExperimentEnabled
+import java.util.List
+
+@ExperimentEnabled("sample")
+class Sample {
+
+    @ExperimentEnabled
+    private val count: Int = 0
+
+    @ExperimentEnabled(name = "foo")
+    override fun toString(): String {
+        return "Sample"
+    }
+
+    fun bar(@ExperimentEnabled a: Int, names: List<@ExperimentEnabled String>) {}
+
+    fun qux(a: Int, @ExperimentEnabled b: Int) {}
+}
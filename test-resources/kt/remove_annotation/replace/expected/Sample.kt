@@ -0,0 +1,13 @@
+package com.uber.piranha
+
+class Sample {
+
+    @Experiment("foo")
+    fun foo() {}
+
+    @Experiment(name = "bar")
+    fun bar() {}
+
+    @Experiment()
+    fun baz() {}
+}
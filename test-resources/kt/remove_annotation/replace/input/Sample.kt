@@ -0,0 +1,15 @@
+package com.uber.piranha
+
+import com.uber.experiments.ExperimentEnabled
+
+class Sample {
+
+    @ExperimentEnabled("foo")
+    fun foo() {}
+
+    @ExperimentEnabled(name = "bar")
+    fun bar() {}
+
+    @ExperimentEnabled
+    fun baz() {}
+}
@@ -0,0 +1,6 @@
+def foo():
+    if is_enabled():
+        do_something()
+    x = True
+    y = other()
+    return x, y
@@ -0,0 +1,6 @@
+def foo():
+    if not False and is_enabled():
+        do_something()
+    x = True or compute()
+    y = (not True) or other()
+    return x, y
@@ -0,0 +1,25 @@
+class SampleClass {
+
+    func checkNegatedGuard() {
+        return
+    }
+
+    func checkGuardInTrailingClosure(items: [Int]) {
+        items.forEach { item in
+            f1(item)
+        }
+    }
+
+    func checkIfInTrailingClosure() {
+        DispatchQueue.main.async {
+            f2()
+        }
+    }
+}
+
+extension SampleClass {
+
+    func checkTernary() -> Int {
+        return 1
+    }
+}
@@ -0,0 +1,36 @@
+class SampleClass {
+
+    func checkNegatedGuard() {
+        guard !placeholder_true else {
+            return
+        }
+        f1()
+        f2()
+    }
+
+    func checkGuardInTrailingClosure(items: [Int]) {
+        items.forEach { item in
+            guard placeholder_true else {
+                return
+            }
+            f1(item)
+        }
+    }
+
+    func checkIfInTrailingClosure() {
+        DispatchQueue.main.async {
+            if placeholder_false {
+                f1()
+            } else {
+                f2()
+            }
+        }
+    }
+}
+
+extension SampleClass {
+
+    func checkTernary() -> Int {
+        return placeholder_true ? 1 : 2
+    }
+}
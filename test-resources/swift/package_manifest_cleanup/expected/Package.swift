@@ -0,0 +1,23 @@
+// swift-tools-version:5.7
+import PackageDescription
+
+let package = Package(
+    name: "MyApp",
+    dependencies: [
+        .package(url: "https://github.com/example/networking.git", from: "2.0.0"),
+        .package(url: "https://github.com/example/logging.git", exact: "3.1.0")
+    ],
+    targets: [
+        .target(
+            name: "MyApp",
+            dependencies: [
+                .product(name: "Networking", package: "networking"),
+                .product(name: "Logging", package: "logging")
+            ]
+        ),
+        .testTarget(
+            name: "MyAppTests",
+            dependencies: ["MyApp"]
+        )
+    ]
+)
@@ -0,0 +1,25 @@
+// swift-tools-version:5.7
+import PackageDescription
+
+let package = Package(
+    name: "MyApp",
+    dependencies: [
+        .package(url: "https://github.com/example/networking.git", from: "2.0.0"),
+        .package(url: "https://github.com/example/experimentation-sdk.git", from: "1.4.0"),
+        .package(url: "https://github.com/example/logging.git", exact: "3.1.0")
+    ],
+    targets: [
+        .target(
+            name: "MyApp",
+            dependencies: [
+                .product(name: "Networking", package: "networking"),
+                .product(name: "Logging", package: "logging"),
+                .product(name: "ExperimentationSDK", package: "experimentation-sdk")
+            ]
+        ),
+        .testTarget(
+            name: "MyAppTests",
+            dependencies: ["MyApp", .product(name: "ExperimentationSDK", package: "experimentation-sdk")]
+        )
+    ]
+)
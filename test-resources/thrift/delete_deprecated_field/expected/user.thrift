@@ -0,0 +1,10 @@
+namespace java com.uber.user
+
+struct User {
+  1: required string uuid
+  3: optional string name
+}
+
+service UserService {
+  User getUser(1: string uuid)
+}
@@ -0,0 +1,13 @@
+namespace java com.uber.user
+
+struct User {
+  1: required string uuid
+  // The name shown before the name migration
+  2: optional string legacyName // Deprecated: use `name` instead
+  3: optional string name
+  4: optional string nickName /* Deprecated: not displayed anymore */
+}
+
+service UserService {
+  User getUser(1: string uuid)
+}
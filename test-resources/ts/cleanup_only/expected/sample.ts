@@ -0,0 +1,10 @@
+function foo(): number {
+  {
+    doSomething();
+  }
+  const x = isEnabled();
+  if (isDisabled()) {
+    return 1;
+  }
+  return 0;
+}
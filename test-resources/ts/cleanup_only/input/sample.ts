@@ -0,0 +1,12 @@
+function foo(): number {
+  if (!false) {
+    doSomething();
+  } else {
+    doSomethingElse();
+  }
+  const x = true && isEnabled();
+  if (false || isDisabled()) {
+    return 1;
+  }
+  return 0;
+}
@@ -194,6 +194,23 @@ def test_incorrect_import():
             edges = []
             )
 
+def test_execute_piranha_raises_the_error():
+    # The `MultiFile` rule matches every import, rather than exactly once
+    find_import = Rule(
+        name="find_import",
+        query="(import_declaration) @import",
+        scope="MultiFile",
+        exactly_one_match=True,
+    )
+    args = PiranhaArguments(
+        language="java",
+        path_to_codebase="test-resources/java/feature_flag_system_1/treated/input",
+        rule_graph=RuleGraph(rules=[find_import], edges=[]),
+        dry_run=True,
+    )
+    with pytest.raises(RuntimeError, match="The MultiFile rule `find_import` is expected to match exactly once"):
+        execute_piranha(args)
+
 def is_as_expected(path_to_scenario, output_summary):
     expected_output = join(path_to_scenario, "expected")
     input_dir = join(path_to_scenario, "input")